@@ -2,12 +2,16 @@ use anyhow::Context as _;
 use clap::{Parser, Subcommand};
 use tokio::io::{self, AsyncReadExt};
 
+use std::path::PathBuf;
+
 use zksync_config::{ContractsConfig, DBConfig, ETHClientConfig, ETHSenderConfig};
 use zksync_dal::{connection::DbVariant, ConnectionPool};
+use zksync_object_store::ObjectStoreFactory;
 use zksync_types::{L1BatchNumber, U256};
 
 use zksync_core::block_reverter::{
-    BlockReverter, BlockReverterEthConfig, BlockReverterFlags, L1ExecutedBatchesRevert,
+    ensure_no_violations, BlockReverter, BlockReverterEthConfig, BlockReverterFlags,
+    L1ExecutedBatchesRevert,
 };
 
 #[derive(Debug, Parser)]
@@ -57,11 +61,60 @@ enum Command {
         /// Flag that specifies if RocksDB with state keeper cache should be rolled back.
         #[arg(long)]
         rollback_sk_cache: bool,
+        /// Flag that specifies if Postgres DB and RocksDB with tree should be rolled back in a coordinated way,
+        /// with the tree root hash verified against Postgres before and after Postgres is rolled back.
+        /// Requires both `--rollback-postgres` and `--rollback-tree`.
+        #[arg(long)]
+        coordinated: bool,
+        /// Reports what a coordinated rollback would remove without modifying any data. Requires `--coordinated`.
+        #[arg(long)]
+        dry_run: bool,
+        /// Displays the dry-run report as a JSON object, so that it is machine-readable.
+        #[arg(long)]
+        json: bool,
+        /// If the target L1 batch is before the pruning horizon of the tree, removes the tree so that it's rebuilt
+        /// from Postgres by the metadata calculator. Without this flag, such a rollback fails. Requires `--coordinated`.
+        #[arg(long)]
+        allow_tree_rebuild: bool,
+        /// Directory to create a checkpoint of the tree in before rolling the tree back. The checkpoint allows
+        /// to undo the tree rollback using the `restore-tree` command. Defaults to the tree path with
+        /// the `_checkpoints` suffix. Requires `--coordinated`.
+        #[arg(long)]
+        checkpoint_dir: Option<PathBuf>,
+        /// Skips creating a checkpoint of the tree before rolling it back, e.g. on machines with limited disk space.
+        /// Requires `--coordinated`.
+        #[arg(long)]
+        no_backup: bool,
+        /// Removes witness inputs uploaded for the reverted L1 batches from the object store configured via env variables.
+        /// Without this flag, they are removed later by the house keeper. Requires `--rollback-postgres`.
+        #[arg(long)]
+        remove_witness_inputs: bool,
         /// Flag that allows to revert already executed blocks, it's ultra dangerous and required only for fixing external nodes
         #[arg(long)]
         allow_executed_block_reversion: bool,
     },
 
+    /// Verifies that the Merkle tree, Postgres and L1 are consistent after a rollback.
+    #[command(name = "verify-rollback")]
+    VerifyRollback {
+        /// L1 batch number the state was rolled back to.
+        #[arg(long)]
+        l1_batch_number: u32,
+        /// Displays violated invariants as a JSON array, so that they are machine-readable.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Restores the Merkle tree from a checkpoint created by a coordinated rollback. The node must be stopped.
+    /// The current tree is not removed, but rather moved next to the tree with the `.replaced` suffix.
+    /// Postgres is not restored; if it was rolled back, it must be restored separately (e.g., from a backup).
+    #[command(name = "restore-tree")]
+    RestoreTree {
+        /// Path to the tree checkpoint, as reported by the coordinated rollback.
+        #[arg(long)]
+        restore_from_checkpoint: PathBuf,
+    },
+
     /// Clears failed L1 transactions.
     #[command(name = "clear-failed-transactions")]
     ClearFailedL1Transactions,
@@ -97,6 +150,8 @@ async fn main() -> anyhow::Result<()> {
         .build()
         .await
         .context("failed to build a connection pool")?;
+    let default_checkpoint_dir =
+        PathBuf::from(format!("{}_checkpoints", db_config.merkle_tree.path));
     let mut block_reverter = BlockReverter::new(
         db_config.state_keeper_db_path,
         db_config.merkle_tree.path,
@@ -134,8 +189,52 @@ async fn main() -> anyhow::Result<()> {
             rollback_postgres,
             rollback_tree,
             rollback_sk_cache,
+            coordinated,
+            dry_run,
+            json,
+            allow_tree_rebuild,
+            checkpoint_dir,
+            no_backup,
+            remove_witness_inputs,
             allow_executed_block_reversion,
         } => {
+            anyhow::ensure!(
+                !coordinated || (rollback_postgres && rollback_tree),
+                "Coordinated rollback requires rolling back both Postgres and tree"
+            );
+            anyhow::ensure!(
+                !dry_run || coordinated,
+                "Dry run is only supported for coordinated rollback"
+            );
+            anyhow::ensure!(
+                !allow_tree_rebuild || coordinated,
+                "Allowing tree rebuild is only supported for coordinated rollback"
+            );
+            anyhow::ensure!(
+                (checkpoint_dir.is_none() && !no_backup) || coordinated,
+                "Tree checkpoints are only supported for coordinated rollback"
+            );
+            anyhow::ensure!(
+                checkpoint_dir.is_none() || !no_backup,
+                "`--checkpoint-dir` cannot be used together with `--no-backup`"
+            );
+            anyhow::ensure!(
+                !remove_witness_inputs || rollback_postgres,
+                "Witness inputs can only be removed when rolling back Postgres"
+            );
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            if dry_run {
+                let preview = block_reverter
+                    .preview_rollback_tree_and_postgres(l1_batch_number)
+                    .await?;
+                if json {
+                    println!("{}", serde_json::to_string(&preview).unwrap());
+                } else {
+                    print!("{preview}");
+                }
+                return Ok(());
+            }
+
             if !rollback_tree && rollback_postgres {
                 println!("You want to rollback Postgres DB without rolling back tree.");
                 println!(
@@ -166,6 +265,38 @@ async fn main() -> anyhow::Result<()> {
                 );
             }
 
+            if remove_witness_inputs {
+                let store_factory =
+                    ObjectStoreFactory::from_env().context("ObjectStoreFactory::from_env()")?;
+                block_reverter.set_object_store(store_factory.create_store().await);
+            }
+
+            if coordinated {
+                if rollback_sk_cache {
+                    // State keeper cache rollback relies on Postgres data, so it must precede the Postgres rollback.
+                    block_reverter
+                        .rollback_db(l1_batch_number, BlockReverterFlags::SK_CACHE)
+                        .await;
+                }
+                let checkpoint_dir =
+                    (!no_backup).then(|| checkpoint_dir.unwrap_or(default_checkpoint_dir));
+                let checkpoint = block_reverter
+                    .rollback_tree_and_postgres(
+                        l1_batch_number,
+                        allow_tree_rebuild,
+                        checkpoint_dir.as_deref(),
+                    )
+                    .await?;
+                if let Some(checkpoint) = checkpoint {
+                    println!(
+                        "Merkle tree state before the rollback (L1 batch #{}) is saved at `{}`",
+                        checkpoint.l1_batch,
+                        checkpoint.path.display()
+                    );
+                }
+                return Ok(());
+            }
+
             let mut flags = BlockReverterFlags::empty();
             if rollback_postgres {
                 flags |= BlockReverterFlags::POSTGRES;
@@ -176,9 +307,29 @@ async fn main() -> anyhow::Result<()> {
             if rollback_sk_cache {
                 flags |= BlockReverterFlags::SK_CACHE;
             }
-            block_reverter
-                .rollback_db(L1BatchNumber(l1_batch_number), flags)
-                .await
+            block_reverter.rollback_db(l1_batch_number, flags).await
+        }
+        Command::VerifyRollback {
+            l1_batch_number,
+            json,
+        } => {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            let violations = block_reverter.verify_rollback(l1_batch_number).await?;
+            if json {
+                println!("{}", serde_json::to_string(&violations).unwrap());
+            }
+            ensure_no_violations(l1_batch_number, &violations)?;
+            if !json {
+                println!("State is consistent with rollback to L1 batch #{l1_batch_number}");
+            }
+        }
+        Command::RestoreTree {
+            restore_from_checkpoint,
+        } => {
+            let l1_batch_number = block_reverter
+                .restore_tree_from_checkpoint(&restore_from_checkpoint)
+                .await?;
+            println!("Restored Merkle tree from checkpoint for L1 batch #{l1_batch_number}");
         }
         Command::ClearFailedL1Transactions => block_reverter.clear_failed_l1_transactions().await,
     }
@@ -190,6 +190,28 @@ pub struct OptionalENConfig {
     /// The default value is 128 MiB.
     #[serde(default = "OptionalENConfig::default_merkle_tree_block_cache_size_mb")]
     merkle_tree_block_cache_size_mb: usize,
+    /// Limit on the background I/O (flushes and compactions) of the Merkle tree RocksDB in bytes per second.
+    /// If not set, background I/O is not limited.
+    pub merkle_tree_rocksdb_rate_limit_bytes_per_sec: Option<usize>,
+    /// Interval between refreshing Merkle tree gauges (e.g., the tree lag) if the tree is idle, in milliseconds.
+    #[serde(default = "OptionalENConfig::default_merkle_tree_metrics_refresh_interval_ms")]
+    merkle_tree_metrics_refresh_interval_ms: u64,
+    /// Maximum number of touched storage slots in a single L1 batch that the Merkle tree is allowed to load into memory.
+    /// If an L1 batch touches more slots, the tree fails with an error instead of potentially running out of memory.
+    #[serde(default = "OptionalENConfig::default_merkle_tree_max_in_memory_slots")]
+    pub merkle_tree_max_in_memory_slots: usize,
+    /// Number of recent Merkle tree root hashes cached in memory. Set to 0 to disable caching.
+    #[serde(default = "OptionalENConfig::default_merkle_tree_root_hash_cache_size")]
+    pub merkle_tree_root_hash_cache_size: usize,
+    /// URL of the HTTP webhook notified about root hashes of L1 batches processed by the Merkle tree.
+    /// If not set, the webhook is disabled.
+    pub merkle_tree_root_webhook_url: Option<String>,
+    /// Lag of the Merkle tree (number of sealed L1 batches not yet processed by it) starting from which
+    /// the tree health is reported as affected. If not set, the lag doesn't affect the tree health.
+    pub merkle_tree_health_affected_lag: Option<u32>,
+    /// Lag of the Merkle tree starting from which the tree health is reported as not ready. If not set,
+    /// the lag doesn't affect the tree readiness.
+    pub merkle_tree_health_not_ready_lag: Option<u32>,
 
     // Other config settings
     /// Port on which the Prometheus exporter server is listening.
@@ -271,6 +293,18 @@ impl OptionalENConfig {
         128
     }
 
+    const fn default_merkle_tree_metrics_refresh_interval_ms() -> u64 {
+        10_000
+    }
+
+    const fn default_merkle_tree_max_in_memory_slots() -> usize {
+        50_000_000
+    }
+
+    const fn default_merkle_tree_root_hash_cache_size() -> usize {
+        16
+    }
+
     const fn default_fee_history_limit() -> u64 {
         1_024
     }
@@ -311,6 +345,10 @@ impl OptionalENConfig {
         self.merkle_tree_block_cache_size_mb * BYTES_IN_MEGABYTE
     }
 
+    pub fn merkle_tree_metrics_refresh_interval(&self) -> Duration {
+        Duration::from_millis(self.merkle_tree_metrics_refresh_interval_ms)
+    }
+
     pub fn api_namespaces(&self) -> Vec<Namespace> {
         self.api_namespaces
             .clone()
@@ -6,6 +6,9 @@ use std::{sync::Arc, time::Duration};
 
 use prometheus_exporter::PrometheusExporterConfig;
 use zksync_basic_types::{Address, L2ChainId};
+use zksync_config::configs::database::{
+    DalConsistencyPolicy, EnumerationIndexCheck, TreeHealthDetailsFormat,
+};
 use zksync_core::{
     api_server::{
         execution_sandbox::VmConcurrencyLimiter,
@@ -18,6 +21,7 @@ use zksync_core::{
     l1_gas_price::MainNodeGasPriceFetcher,
     metadata_calculator::{
         MetadataCalculator, MetadataCalculatorConfig, MetadataCalculatorModeConfig,
+        ObjectStoreUploadConfig, ProtectiveReadsThresholds, TreeHealthThresholds,
     },
     reorg_detector::ReorgDetector,
     setup_sigint_handler,
@@ -34,6 +38,7 @@ use zksync_dal::{connection::DbVariant, healthcheck::ConnectionPoolHealthCheck,
 use zksync_health_check::CheckHealth;
 use zksync_state::PostgresStorageCaches;
 use zksync_storage::RocksDB;
+use zksync_types::{L1BatchNumber, ProtocolVersionId};
 use zksync_utils::wait_for_tasks::wait_for_tasks;
 
 mod config;
@@ -99,6 +104,7 @@ async fn build_state_keeper(
 async fn init_tasks(
     config: ExternalNodeConfig,
     connection_pool: ConnectionPool,
+    reorg_receiver: watch::Receiver<Option<L1BatchNumber>>,
 ) -> anyhow::Result<(
     Vec<task::JoinHandle<anyhow::Result<()>>>,
     watch::Sender<bool>,
@@ -147,8 +153,63 @@ async fn init_tasks(
         max_l1_batches_per_iter: config.optional.max_l1_batches_per_tree_iter,
         multi_get_chunk_size: config.optional.merkle_tree_multi_get_chunk_size,
         block_cache_capacity: config.optional.merkle_tree_block_cache_size(),
+        rocksdb_rate_limit_bytes_per_sec: config
+            .optional
+            .merkle_tree_rocksdb_rate_limit_bytes_per_sec,
+        metrics_refresh_interval: config.optional.merkle_tree_metrics_refresh_interval(),
+        validate_initial_writes: false,
+        validate_repeated_writes: false,
+        auto_rebuild_on_corruption: false,
+        root_hash_cache_size: config.optional.merkle_tree_root_hash_cache_size,
+        root_webhook_url: config.optional.merkle_tree_root_webhook_url.as_deref(),
+        max_in_memory_witness_size: usize::MAX, // witnesses are not produced in the lightweight mode
+        max_in_memory_slots: config.optional.merkle_tree_max_in_memory_slots,
+        witness_compression_level: 0, // witnesses are not produced in the lightweight mode
+        witness_spill_compression_level: None,
+        health_thresholds: TreeHealthThresholds {
+            affected_lag: config.optional.merkle_tree_health_affected_lag,
+            not_ready_lag: config.optional.merkle_tree_health_not_ready_lag,
+        },
+        health_details_format: TreeHealthDetailsFormat::Json,
+        generate_witness_inputs: false, // witnesses are not produced in the lightweight mode
+        max_supported_protocol_version: ProtocolVersionId::next(),
+        witness_upload_queue_path: None, // witnesses are not produced in the lightweight mode
+        witness_upload_queue_max_size: 0,
+        hot_keys_report_top_n: None,
+        hot_keys_report_window: 1,
+        max_pending_witnesses: 1, // witnesses are not produced in the lightweight mode
+        witness_persistence_concurrency: 1,
+        witness_chunking_threshold: usize::MAX,
+        witness_chunk_size: 1,
+        deduplicate_witness_paths: false,
+        record_witness_protocol_version: false,
+        db_connection_max_attempts: 10,
+        db_connection_backoff: Duration::from_millis(500),
+        // The object store is not used since witnesses are not produced in the lightweight mode
+        object_store: ObjectStoreUploadConfig {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            request_timeout: Duration::from_secs(300),
+            failure_threshold: 3,
+        },
+        logs_verification_modulus: None,
+        logs_verification_cache_capacity: 0,
+        save_latency_slo: None,
+        enumeration_index_check: EnumerationIndexCheck::Error,
+        progress_check: EnumerationIndexCheck::Error,
+        dal_consistency_policy: DalConsistencyPolicy::FailFast,
+        empty_l1_batch_retries: None,
+        protective_reads_thresholds: ProtectiveReadsThresholds {
+            max_ratio: 100.0,
+            min_reads: 100,
+        },
+        stall_threshold: None,
+        l1_batch_notifications_capacity: 1_024,
+        watched_keys: &[],
     })
-    .await;
+    .await
+    // Halt the tree once a reorg is detected, so that it doesn't process L1 batches that will be reverted.
+    .with_reorg_receiver(reorg_receiver);
     healthchecks.push(Box::new(metadata_calculator.tree_health_check()));
 
     let consistency_checker = ConsistencyChecker::new(
@@ -396,12 +457,15 @@ async fn main() -> anyhow::Result<()> {
     .await
     .context("Performing genesis failed")?;
 
-    let (task_handles, stop_sender, health_check_handle) =
-        init_tasks(config.clone(), connection_pool.clone())
-            .await
-            .context("init_tasks")?;
-
     let reorg_detector = ReorgDetector::new(&main_node_url, connection_pool.clone());
+    let (task_handles, stop_sender, health_check_handle) = init_tasks(
+        config.clone(),
+        connection_pool.clone(),
+        reorg_detector.subscribe(),
+    )
+    .await
+    .context("init_tasks")?;
+
     let reorg_detector_handle = tokio::spawn(reorg_detector.run());
 
     let particular_crypto_alerts = None;
@@ -0,0 +1,192 @@
+use anyhow::Context as _;
+use clap::{Parser, Subcommand};
+
+use std::path::PathBuf;
+
+use zksync_config::{configs::chain::OperationsManagerConfig, DBConfig};
+use zksync_core::metadata_calculator::{
+    check_tree_versions, AuditedOperation, MetadataCalculator, MetadataCalculatorConfig,
+    MetadataCalculatorModeConfig, SnapshotExporter,
+};
+use zksync_dal::{connection::DbVariant, ConnectionPool};
+use zksync_types::L1BatchNumber;
+
+#[derive(Debug, Parser)]
+#[command(
+    author = "Matter Labs",
+    version,
+    about = "Maintenance utility for the Merkle tree of a stopped node",
+    long_about = "Prunes old Merkle tree versions, defragments the tree RocksDB, checks that the tree \
+                  has versions for all sealed L1 batches, exports tree snapshots and inspects the audit log \
+                  of destructive operations on the tree. The tree must not be used by other processes"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prunes Merkle tree versions before the specified L1 batch. Pruned versions can no longer
+    /// be used to serve proofs or regenerate witness inputs.
+    #[command(name = "prune")]
+    Prune {
+        /// First L1 batch to retain in the tree.
+        #[arg(long)]
+        first_l1_batch_to_keep: u32,
+    },
+    /// Defragments the Merkle tree RocksDB by copying the latest tree version to a fresh RocksDB instance
+    /// with nodes laid out in the key order. All previous tree versions are discarded. Requires free disk space
+    /// for a full copy of the latest tree version.
+    #[command(name = "defragment")]
+    Defragment,
+    /// Checks that the Merkle tree has stored versions for all L1 batches sealed in Postgres in the specified range.
+    /// Fails if any versions are missing.
+    #[command(name = "check-versions")]
+    CheckVersions {
+        /// First L1 batch to check. Defaults to the first L1 batch retained by the tree.
+        #[arg(long)]
+        from_l1_batch: Option<u32>,
+        /// Last L1 batch to check. Defaults to the last L1 batch sealed in Postgres.
+        #[arg(long)]
+        to_l1_batch: Option<u32>,
+    },
+    /// Exports Merkle tree entries for the specified L1 batch to an NDJSON snapshot, which can be compared
+    /// with other snapshots. Progress is periodically recorded to a checkpoint file next to the output.
+    #[command(name = "export-snapshot")]
+    ExportSnapshot {
+        /// L1 batch to export the tree state for.
+        #[arg(long)]
+        l1_batch_number: u32,
+        /// Path to the output file.
+        #[arg(long)]
+        output: PathBuf,
+        /// Resumes an interrupted export from the checkpoint file. If the checkpoint doesn't exist,
+        /// the export starts from scratch.
+        #[arg(long)]
+        resume: bool,
+        /// Compresses the output with zstd using the specified compression level.
+        #[arg(long)]
+        compression_level: Option<i32>,
+    },
+    /// Prints the audit log of destructive operations on the Merkle tree.
+    #[command(name = "audit-log")]
+    AuditLog,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    #[allow(deprecated)] // TODO (QIT-21): Use centralized configuration approach.
+    let log_format = vlog::log_format_from_env();
+    let _guard = vlog::ObservabilityBuilder::new()
+        .with_log_format(log_format)
+        .build();
+
+    let db_config = DBConfig::from_env().context("DBConfig::from_env()")?;
+    let operations_config =
+        OperationsManagerConfig::from_env().context("OperationsManagerConfig::from_env()")?;
+    // Maintenance operations don't produce witness inputs, so the tree is opened in the lightweight mode.
+    let mode = MetadataCalculatorModeConfig::Lightweight;
+    let config = MetadataCalculatorConfig::for_main_node(&db_config, &operations_config, mode);
+    let mut calculator = MetadataCalculator::new(&config).await;
+
+    match Cli::parse().command {
+        Command::Prune {
+            first_l1_batch_to_keep,
+        } => {
+            let first_l1_batch_to_keep = L1BatchNumber(first_l1_batch_to_keep);
+            let pruned_key_count = calculator.prune_tree(first_l1_batch_to_keep).await;
+            println!(
+                "Pruned {pruned_key_count} node keys from Merkle tree at `{}` up to L1 batch \
+                 #{first_l1_batch_to_keep}",
+                db_config.merkle_tree.path
+            );
+        }
+        Command::Defragment => {
+            let copied_node_count = calculator.defragment_tree().await?;
+            println!(
+                "Defragmented Merkle tree at `{}`; copied {copied_node_count} tree nodes",
+                db_config.merkle_tree.path
+            );
+        }
+        Command::CheckVersions {
+            from_l1_batch,
+            to_l1_batch,
+        } => {
+            let pool = ConnectionPool::builder(DbVariant::Master)
+                .build()
+                .await
+                .context("failed to build a connection pool")?;
+            let mut storage = pool.access_storage().await?;
+            let reader = calculator.tree_reader();
+            let from_l1_batch = match from_l1_batch {
+                Some(number) => L1BatchNumber(number),
+                None => reader.first_retained_l1_batch().unwrap_or(L1BatchNumber(0)),
+            };
+            let to_l1_batch = match to_l1_batch {
+                Some(number) => L1BatchNumber(number),
+                None => storage.blocks_dal().get_sealed_l1_batch_number().await?,
+            };
+            let report =
+                check_tree_versions(&mut storage, &reader, from_l1_batch..=to_l1_batch).await?;
+            println!(
+                "Checked {} sealed L1 batches in #{from_l1_batch}..=#{to_l1_batch}; \
+                 {} of them have no Merkle tree version",
+                report.sealed_count,
+                report.missing.len()
+            );
+            report.ensure_complete()?;
+        }
+        Command::ExportSnapshot {
+            l1_batch_number,
+            output,
+            resume,
+            compression_level,
+        } => {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            let reader = calculator.tree_reader();
+            let output_path = output.clone();
+            let export = tokio::task::spawn_blocking(move || {
+                let mut exporter = SnapshotExporter::new(&reader, l1_batch_number);
+                exporter.set_compression_level(compression_level);
+                exporter.export_entries(&output_path, resume)
+            })
+            .await
+            .context("snapshot export panicked")??;
+            println!(
+                "Exported {} Merkle tree entries for L1 batch #{l1_batch_number} to `{}` \
+                 ({} entries exported before resuming)",
+                export.entry_count,
+                output.display(),
+                export.resumed_entry_count
+            );
+        }
+        Command::AuditLog => {
+            let entries = calculator.tree_audit_log()?;
+            if entries.is_empty() {
+                println!("Audit log is empty");
+            }
+            for entry in entries {
+                let operation = match entry.operation {
+                    AuditedOperation::Revert {
+                        last_l1_batch_to_keep,
+                    } => format!("revert to L1 batch #{last_l1_batch_to_keep}"),
+                    AuditedOperation::Prune {
+                        first_l1_batch_to_keep,
+                        pruned_key_count,
+                    } => format!(
+                        "prune up to L1 batch #{first_l1_batch_to_keep} ({pruned_key_count} node keys)"
+                    ),
+                    AuditedOperation::Defragment { copied_node_count } => {
+                        format!("defragment ({copied_node_count} copied nodes)")
+                    }
+                };
+                println!(
+                    "{} ms: {operation}; root hash after operation: {:?}",
+                    entry.timestamp_ms, entry.root_hash
+                );
+            }
+        }
+    }
+    Ok(())
+}
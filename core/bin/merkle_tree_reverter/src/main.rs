@@ -0,0 +1,210 @@
+use anyhow::Context as _;
+use clap::Parser;
+
+use std::path::Path;
+
+use zksync_config::DBConfig;
+use zksync_dal::{connection::DbVariant, ConnectionPool};
+use zksync_merkle_tree::{domain::ZkSyncTree, MerkleTreeColumnFamily};
+use zksync_storage::RocksDB;
+use zksync_types::{L1BatchNumber, H256};
+
+#[derive(Debug, Parser)]
+#[command(
+    author = "Matter Labs",
+    version,
+    about = "Merkle tree revert utility for stopped nodes",
+    long_about = None
+)]
+struct Cli {
+    /// Number of the last L1 batch to keep in the tree.
+    #[arg(long = "last-batch")]
+    last_batch: u32,
+    /// Postgres URL to verify the reverted tree against. If specified, the reverted tree is only saved
+    /// if its root hash matches the root hash of the last kept L1 batch stored in Postgres.
+    #[arg(long = "postgres-url")]
+    postgres_url: Option<String>,
+}
+
+impl Cli {
+    async fn run(self, config: &DBConfig) -> anyhow::Result<()> {
+        let db_path = Path::new(&config.merkle_tree.path);
+        let mut tree = open_tree(db_path)?;
+        let next_l1_batch_number = tree.next_l1_batch_number();
+        println!(
+            "Merkle tree at `{}`: next L1 batch #{next_l1_batch_number}, root hash {:?}",
+            db_path.display(),
+            tree.root_hash()
+        );
+
+        let last_l1_batch_to_keep = L1BatchNumber(self.last_batch);
+        let root_hash = revert_tree(&mut tree, last_l1_batch_to_keep)?;
+        if let Some(postgres_url) = &self.postgres_url {
+            let expected_root_hash =
+                load_root_hash_from_postgres(postgres_url, last_l1_batch_to_keep).await?;
+            anyhow::ensure!(
+                root_hash == expected_root_hash,
+                "Root hash of the reverted Merkle tree ({root_hash:?}) differs from the root hash \
+                 of L1 batch #{last_l1_batch_to_keep} in Postgres ({expected_root_hash:?}); \
+                 the tree was not modified"
+            );
+            tracing::info!("Reverted Merkle tree root hash matches Postgres");
+        }
+
+        tree.save();
+        println!(
+            "Reverted Merkle tree to L1 batch #{last_l1_batch_to_keep}; root hash {root_hash:?}"
+        );
+        Ok(())
+    }
+}
+
+/// Opens the tree RocksDB for writing. Fails if RocksDB is locked, e.g. because the server is still running.
+fn open_tree(db_path: &Path) -> anyhow::Result<ZkSyncTree> {
+    anyhow::ensure!(
+        db_path.exists(),
+        "Merkle tree not found at `{}`",
+        db_path.display()
+    );
+    let db = RocksDB::<MerkleTreeColumnFamily>::try_with_cache(db_path, true, None);
+    let db = db.with_context(|| {
+        format!(
+            "failed opening Merkle tree at `{}`; make sure that the server using it is stopped",
+            db_path.display()
+        )
+    })?;
+    Ok(ZkSyncTree::new_lightweight(db))
+}
+
+/// Reverts the tree in memory so that `last_l1_batch_to_keep` is the last L1 batch in it.
+/// Returns the root hash of the reverted tree. Changes are not saved to RocksDB.
+fn revert_tree(
+    tree: &mut ZkSyncTree,
+    last_l1_batch_to_keep: L1BatchNumber,
+) -> anyhow::Result<H256> {
+    let next_l1_batch_number = tree.next_l1_batch_number();
+    anyhow::ensure!(
+        next_l1_batch_number > last_l1_batch_to_keep,
+        "Merkle tree (next L1 batch: #{next_l1_batch_number}) hasn't processed L1 batch \
+         #{last_l1_batch_to_keep} to revert to"
+    );
+    if let Some(first_retained_l1_batch) = tree.first_retained_l1_batch() {
+        anyhow::ensure!(
+            first_retained_l1_batch <= last_l1_batch_to_keep,
+            "Cannot revert Merkle tree to L1 batch #{last_l1_batch_to_keep}: the earliest L1 batch \
+             retained by the tree is #{first_retained_l1_batch}, and earlier tree versions were pruned"
+        );
+    }
+
+    if next_l1_batch_number == last_l1_batch_to_keep + 1 {
+        tracing::info!("Merkle tree is already at L1 batch #{last_l1_batch_to_keep}");
+    } else {
+        tracing::info!("Reverting Merkle tree to L1 batch #{last_l1_batch_to_keep}");
+        tree.revert_logs(last_l1_batch_to_keep);
+    }
+    Ok(tree.root_hash())
+}
+
+async fn load_root_hash_from_postgres(
+    postgres_url: &str,
+    l1_batch_number: L1BatchNumber,
+) -> anyhow::Result<H256> {
+    let pool = ConnectionPool::builder(DbVariant::Master)
+        .set_max_size(Some(1))
+        .build_inner(postgres_url)
+        .await;
+    let mut storage = pool.access_storage().await?;
+    storage
+        .blocks_dal()
+        .get_l1_batch_state_root(l1_batch_number)
+        .await?
+        .with_context(|| {
+            format!("no root hash is stored in Postgres for L1 batch #{l1_batch_number}")
+        })
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    #[allow(deprecated)] // TODO (QIT-21): Use centralized configuration approach.
+    let log_format = vlog::log_format_from_env();
+    #[allow(deprecated)] // TODO (QIT-21): Use centralized configuration approach.
+    let sentry_url = vlog::sentry_url_from_env();
+    #[allow(deprecated)] // TODO (QIT-21): Use centralized configuration approach.
+    let environment = vlog::environment_from_env();
+
+    let mut builder = vlog::ObservabilityBuilder::new().with_log_format(log_format);
+    if let Some(sentry_url) = sentry_url {
+        builder = builder
+            .with_sentry_url(&sentry_url)
+            .context("Invalid Sentry URL")?
+            .with_sentry_environment(environment);
+    }
+    let _guard = builder.build();
+
+    let db_config = DBConfig::from_env().context("DBConfig::from_env()")?;
+    Cli::parse().run(&db_config).await
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use zksync_types::{AccountTreeId, Address, StorageKey, StorageLog};
+
+    use super::*;
+
+    fn create_tree(db_path: &Path, l1_batch_count: u64) -> Vec<H256> {
+        let mut tree = open_tree(db_path).unwrap();
+        let root_hashes = (0..l1_batch_count).map(|number| {
+            let logs: Vec<_> = (0_u64..10)
+                .map(|i| {
+                    let key = H256::from_low_u64_be(number * 100 + i);
+                    let key = StorageKey::new(AccountTreeId::new(Address::repeat_byte(1)), key);
+                    StorageLog::new_write_log(key, H256::from_low_u64_be(i + 1))
+                })
+                .collect();
+            tree.process_l1_batch(&logs).root_hash
+        });
+        let root_hashes = root_hashes.collect();
+        tree.save();
+        root_hashes
+    }
+
+    #[test]
+    fn reverting_tree() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let root_hashes = create_tree(temp_dir.path(), 5);
+
+        let mut tree = open_tree(temp_dir.path()).unwrap();
+        let root_hash = revert_tree(&mut tree, L1BatchNumber(2)).unwrap();
+        assert_eq!(root_hash, root_hashes[2]);
+        tree.save();
+        drop(tree);
+
+        let mut tree = open_tree(temp_dir.path()).unwrap();
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(3));
+        assert_eq!(tree.root_hash(), root_hashes[2]);
+        // Reverting to the last L1 batch in the tree is a no-op.
+        let root_hash = revert_tree(&mut tree, L1BatchNumber(2)).unwrap();
+        assert_eq!(root_hash, root_hashes[2]);
+        let err = revert_tree(&mut tree, L1BatchNumber(3)).unwrap_err();
+        assert!(err.to_string().contains("hasn't processed"), "{err}");
+    }
+
+    #[test]
+    fn opening_locked_tree_fails() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        create_tree(temp_dir.path(), 1);
+
+        let _tree = open_tree(temp_dir.path()).unwrap();
+        let err = open_tree(temp_dir.path()).unwrap_err();
+        assert!(err.to_string().contains("server"), "{err}");
+    }
+
+    #[test]
+    fn opening_missing_tree_fails() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let err = open_tree(&temp_dir.path().join("missing")).unwrap_err();
+        assert!(err.to_string().contains("not found"), "{err}");
+    }
+}
@@ -0,0 +1,77 @@
+use clap::Parser;
+
+use zksync_dal::connection::DbVariant;
+use zksync_dal::ConnectionPool;
+use zksync_types::L1BatchNumber;
+
+/// Number of L1 batches checked in a single DAL call.
+const L1_BATCHES_CHUNK_SIZE: u32 = 100;
+
+#[derive(Debug, Parser)]
+#[command(
+    author = "Matter Labs",
+    about = "Consistency checker for the `initial_writes` table"
+)]
+struct Cli {
+    /// L1 batch number to start check from.
+    #[arg(long, default_value_t = 0)]
+    from_l1_batch: u32,
+    /// L1 batch number to check up to. If not specified, the check is performed up to the last sealed L1 batch.
+    #[arg(long)]
+    to_l1_batch: Option<u32>,
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Cli::parse();
+    let pool = ConnectionPool::singleton(DbVariant::Replica)
+        .build()
+        .await
+        .unwrap();
+    let mut connection = pool.access_storage().await.unwrap();
+
+    let to_l1_batch = match opt.to_l1_batch {
+        Some(number) => number,
+        None => {
+            connection
+                .blocks_dal()
+                .get_sealed_l1_batch_number()
+                .await
+                .unwrap()
+                .0
+        }
+    };
+    println!(
+        "Consistency check started for L1 batch range {}..={to_l1_batch}",
+        opt.from_l1_batch
+    );
+
+    let mut inconsistency_count = 0;
+    let mut chunk_start = opt.from_l1_batch;
+    while chunk_start <= to_l1_batch {
+        let chunk_end = to_l1_batch.min(chunk_start.saturating_add(L1_BATCHES_CHUNK_SIZE - 1));
+        let inconsistencies = connection
+            .storage_logs_dedup_dal()
+            .validate_initial_writes_consistency(
+                L1BatchNumber(chunk_start)..=L1BatchNumber(chunk_end),
+            )
+            .await
+            .unwrap();
+        for inconsistency in &inconsistencies {
+            println!("Found inconsistency: {inconsistency:?}");
+        }
+        inconsistency_count += inconsistencies.len();
+
+        println!("Processed L1 batches {chunk_start}..={chunk_end}");
+        if chunk_end == u32::MAX {
+            break;
+        }
+        chunk_start = chunk_end + 1;
+    }
+
+    if inconsistency_count > 0 {
+        eprintln!("Finished; found {inconsistency_count} inconsistencies");
+        std::process::exit(1);
+    }
+    println!("Finished; no inconsistencies found");
+}
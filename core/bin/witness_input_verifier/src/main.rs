@@ -0,0 +1,333 @@
+use anyhow::Context as _;
+use clap::{Parser, Subcommand};
+
+use std::path::PathBuf;
+
+use zksync_config::{configs::chain::OperationsManagerConfig, DBConfig};
+use zksync_core::metadata_calculator::{
+    export_witnesses, verify_witness_artifact, verify_witness_input, MetadataCalculator,
+    MetadataCalculatorConfig, MetadataCalculatorModeConfig, WitnessMismatch,
+};
+use zksync_dal::{connection::DbVariant, ConnectionPool};
+use zksync_object_store::ObjectStoreFactory;
+use zksync_types::L1BatchNumber;
+
+#[derive(Debug, Parser)]
+#[command(
+    author = "Matter Labs",
+    version,
+    about = "Verifier of Merkle tree witness inputs",
+    long_about = "Replays Merkle paths from witness inputs against root hashes of L1 batches stored in Postgres, \
+                  checks stored witness input objects against their recorded hashes, \
+                  regenerates lost or corrupted witness inputs, and exports witness inputs to local files"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Verifies witness inputs for a range of L1 batches.
+    #[command(name = "verify")]
+    Verify {
+        /// First L1 batch to verify (inclusive). Must be positive.
+        #[arg(long)]
+        from_l1_batch: u32,
+        /// Last L1 batch to verify (inclusive). If not specified, only `from_l1_batch` is verified.
+        #[arg(long)]
+        to_l1_batch: Option<u32>,
+    },
+    /// Verifies stored witness input objects for a range of L1 batches against the size and hash
+    /// recorded when the objects were uploaded.
+    #[command(name = "verify-artifacts")]
+    VerifyArtifacts {
+        /// First L1 batch to verify (inclusive).
+        #[arg(long)]
+        from_l1_batch: u32,
+        /// Last L1 batch to verify (inclusive). If not specified, only `from_l1_batch` is verified.
+        #[arg(long)]
+        to_l1_batch: Option<u32>,
+    },
+    /// Regenerates the witness input for an L1 batch using the Merkle tree and re-uploads it
+    /// to the object store. The Merkle tree must not be used by other processes.
+    #[command(name = "regenerate")]
+    Regenerate {
+        /// L1 batch to regenerate the witness input for. Must be positive.
+        #[arg(long)]
+        l1_batch: u32,
+        /// Path to a Merkle tree RocksDB (e.g., restored from a backup) used if the main tree
+        /// no longer retains the required tree version.
+        #[arg(long)]
+        checkpoint_path: Option<PathBuf>,
+    },
+    /// Exports witness inputs for a range of L1 batches from the object store to local files.
+    #[command(name = "export")]
+    Export {
+        /// First L1 batch to export (inclusive).
+        #[arg(long)]
+        from_l1_batch: u32,
+        /// Last L1 batch to export (inclusive). If not specified, only `from_l1_batch` is exported.
+        #[arg(long)]
+        to_l1_batch: Option<u32>,
+        /// Directory to export witness inputs to. Created if it doesn't exist.
+        #[arg(long)]
+        output_dir: PathBuf,
+        /// `zstd` compression level for exported witness inputs. If not specified, witness inputs
+        /// are not compressed.
+        #[arg(long)]
+        compression_level: Option<i32>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    #[allow(deprecated)] // TODO (QIT-21): Use centralized configuration approach.
+    let log_format = vlog::log_format_from_env();
+    #[allow(deprecated)] // TODO (QIT-21): Use centralized configuration approach.
+    let sentry_url = vlog::sentry_url_from_env();
+    #[allow(deprecated)] // TODO (QIT-21): Use centralized configuration approach.
+    let environment = vlog::environment_from_env();
+
+    let mut builder = vlog::ObservabilityBuilder::new().with_log_format(log_format);
+    if let Some(sentry_url) = sentry_url {
+        builder = builder
+            .with_sentry_url(&sentry_url)
+            .context("Invalid Sentry URL")?
+            .with_sentry_environment(environment);
+    }
+    let _guard = builder.build();
+
+    let store_factory = ObjectStoreFactory::from_env().context("ObjectStoreFactory::from_env()")?;
+    match Cli::parse().command {
+        Command::Verify {
+            from_l1_batch,
+            to_l1_batch,
+        } => verify(&store_factory, from_l1_batch, to_l1_batch).await,
+        Command::VerifyArtifacts {
+            from_l1_batch,
+            to_l1_batch,
+        } => verify_artifacts(&store_factory, from_l1_batch, to_l1_batch).await,
+        Command::Regenerate {
+            l1_batch,
+            checkpoint_path,
+        } => regenerate(&store_factory, L1BatchNumber(l1_batch), checkpoint_path).await,
+        Command::Export {
+            from_l1_batch,
+            to_l1_batch,
+            output_dir,
+            compression_level,
+        } => {
+            export(
+                &store_factory,
+                from_l1_batch,
+                to_l1_batch,
+                output_dir,
+                compression_level,
+            )
+            .await
+        }
+    }
+}
+
+async fn verify(
+    store_factory: &ObjectStoreFactory,
+    from_l1_batch: u32,
+    to_l1_batch: Option<u32>,
+) -> anyhow::Result<()> {
+    let to_l1_batch = to_l1_batch.unwrap_or(from_l1_batch);
+    anyhow::ensure!(
+        from_l1_batch <= to_l1_batch,
+        "invalid L1 batch range: {from_l1_batch}..={to_l1_batch}"
+    );
+
+    let pool = ConnectionPool::builder(DbVariant::Replica)
+        .build()
+        .await
+        .context("failed to build a connection pool")?;
+    let mut storage = pool.access_storage().await?;
+    let object_store = store_factory.create_store().await;
+
+    let mut failed_count = 0;
+    for l1_batch_number in from_l1_batch..=to_l1_batch {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let verification =
+            verify_witness_input(&mut storage, object_store.as_ref(), l1_batch_number)
+                .await
+                .with_context(|| format!("failed verifying L1 batch #{l1_batch_number}"))?;
+        match verification.mismatch {
+            None => println!(
+                "L1 batch #{l1_batch_number}: OK ({} Merkle paths)",
+                verification.verified_path_count
+            ),
+            Some(mismatch) => {
+                failed_count += 1;
+                println!(
+                    "L1 batch #{l1_batch_number}: FAILED; {}",
+                    describe(&mismatch)
+                );
+            }
+        }
+    }
+    anyhow::ensure!(
+        failed_count == 0,
+        "witness verification failed for {failed_count} L1 batch(es)"
+    );
+    Ok(())
+}
+
+async fn verify_artifacts(
+    store_factory: &ObjectStoreFactory,
+    from_l1_batch: u32,
+    to_l1_batch: Option<u32>,
+) -> anyhow::Result<()> {
+    let to_l1_batch = to_l1_batch.unwrap_or(from_l1_batch);
+    anyhow::ensure!(
+        from_l1_batch <= to_l1_batch,
+        "invalid L1 batch range: {from_l1_batch}..={to_l1_batch}"
+    );
+
+    let pool = ConnectionPool::builder(DbVariant::Replica)
+        .build()
+        .await
+        .context("failed to build a connection pool")?;
+    let mut storage = pool.access_storage().await?;
+    let object_store = store_factory.create_store().await;
+
+    let mut failed_count = 0;
+    for l1_batch_number in from_l1_batch..=to_l1_batch {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let verification =
+            verify_witness_artifact(&mut storage, object_store.as_ref(), l1_batch_number)
+                .await
+                .with_context(|| format!("failed verifying L1 batch #{l1_batch_number}"))?;
+        match verification {
+            None => println!("L1 batch #{l1_batch_number}: SKIPPED; no recorded witness artifact"),
+            Some(verification) if verification.is_ok() => println!(
+                "L1 batch #{l1_batch_number}: OK (`{}`, {}B)",
+                verification.object_key, verification.actual_size
+            ),
+            Some(verification) => {
+                failed_count += 1;
+                if verification.actual_size != verification.recorded_size
+                    || verification.actual_hash != verification.recorded_hash
+                {
+                    println!(
+                        "L1 batch #{l1_batch_number}: FAILED; object `{}` has size {}B and hash {:?}, \
+                         while {}B and {:?} were recorded on upload",
+                        verification.object_key,
+                        verification.actual_size,
+                        verification.actual_hash,
+                        verification.recorded_size,
+                        verification.recorded_hash
+                    );
+                } else {
+                    println!(
+                        "L1 batch #{l1_batch_number}: FAILED; chunks of `{}` don't match the manifest",
+                        verification.object_key
+                    );
+                }
+                for chunk_error in &verification.chunk_errors {
+                    println!("  {chunk_error}");
+                }
+            }
+        }
+    }
+    anyhow::ensure!(
+        failed_count == 0,
+        "witness artifact verification failed for {failed_count} L1 batch(es)"
+    );
+    Ok(())
+}
+
+async fn regenerate(
+    store_factory: &ObjectStoreFactory,
+    l1_batch_number: L1BatchNumber,
+    checkpoint_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let db_config = DBConfig::from_env().context("DBConfig::from_env()")?;
+    let operations_config =
+        OperationsManagerConfig::from_env().context("OperationsManagerConfig::from_env()")?;
+    let mode = MetadataCalculatorModeConfig::Full { store_factory };
+    let config = MetadataCalculatorConfig::for_main_node(&db_config, &operations_config, mode);
+    let mut calculator = MetadataCalculator::new(&config).await;
+
+    let pool = ConnectionPool::singleton(DbVariant::Master)
+        .build()
+        .await
+        .context("failed to build a connection pool")?;
+    let regenerated = calculator
+        .regenerate_witness(&pool, l1_batch_number, checkpoint_path.as_deref())
+        .await
+        .with_context(|| format!("failed regenerating witness for L1 batch #{l1_batch_number}"))?;
+    let source = if regenerated.used_checkpoint {
+        "checkpoint tree"
+    } else {
+        "main tree"
+    };
+    println!(
+        "L1 batch #{l1_batch_number}: regenerated witness input with {} Merkle paths using {source}; \
+         uploaded to `{}`",
+        regenerated.path_count, regenerated.object_key
+    );
+    Ok(())
+}
+
+async fn export(
+    store_factory: &ObjectStoreFactory,
+    from_l1_batch: u32,
+    to_l1_batch: Option<u32>,
+    output_dir: PathBuf,
+    compression_level: Option<i32>,
+) -> anyhow::Result<()> {
+    let to_l1_batch = to_l1_batch.unwrap_or(from_l1_batch);
+    anyhow::ensure!(
+        from_l1_batch <= to_l1_batch,
+        "invalid L1 batch range: {from_l1_batch}..={to_l1_batch}"
+    );
+
+    let object_store = store_factory.create_store().await;
+    let l1_batch_numbers = L1BatchNumber(from_l1_batch)..=L1BatchNumber(to_l1_batch);
+    let exported = export_witnesses(
+        object_store.as_ref(),
+        l1_batch_numbers,
+        &output_dir,
+        compression_level,
+    )
+    .await?;
+    for witness in exported {
+        println!(
+            "L1 batch #{}: exported to `{}` ({}B raw, {}B on disk)",
+            witness.l1_batch_number,
+            witness.path.display(),
+            witness.raw_size,
+            witness.file_size
+        );
+    }
+    Ok(())
+}
+
+fn describe(mismatch: &WitnessMismatch) -> String {
+    match mismatch {
+        WitnessMismatch::RootBeforeOperation {
+            path_index,
+            expected,
+            actual,
+        } => format!(
+            "Merkle path #{path_index} doesn't lead to the previous root hash \
+             (expected {expected:?}, got {actual:?})"
+        ),
+        WitnessMismatch::RootAfterOperation {
+            path_index,
+            expected,
+            actual,
+        } => format!(
+            "Merkle path #{path_index} doesn't lead to the recorded root hash after the operation \
+             (expected {expected:?}, got {actual:?})"
+        ),
+        WitnessMismatch::FinalRootHash { expected, actual } => format!(
+            "root hash after the last Merkle path differs from the L1 batch root hash \
+             (expected {expected:?}, got {actual:?})"
+        ),
+    }
+}
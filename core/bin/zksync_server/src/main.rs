@@ -1,13 +1,13 @@
 use anyhow::Context as _;
 use clap::Parser;
 
-use std::{str::FromStr, time::Duration};
-use zksync_config::configs::chain::NetworkConfig;
+use std::{path::PathBuf, str::FromStr, time::Duration};
+use zksync_config::configs::chain::{L1GenesisCheck, NetworkConfig};
 
-use zksync_config::{ContractsConfig, ETHSenderConfig};
+use zksync_config::{ContractsConfig, ETHClientConfig, ETHSenderConfig};
 use zksync_core::{
-    genesis_init, initialize_components, is_genesis_needed, setup_sigint_handler, Component,
-    Components,
+    check_genesis_on_l1, export_genesis_artifact, genesis_init, initialize_components,
+    is_genesis_needed, setup_sigint_handler, Component, Components,
 };
 use zksync_storage::RocksDB;
 use zksync_utils::wait_for_tasks::wait_for_tasks;
@@ -22,6 +22,10 @@ struct Cli {
     /// Generate genesis block for the first contract deployment using temporary DB.
     #[arg(long)]
     genesis: bool,
+    /// Compute the genesis state without accessing Postgres and write it as a JSON artifact
+    /// to the specified path.
+    #[arg(long, value_name = "PATH", conflicts_with = "genesis")]
+    export_genesis: Option<PathBuf>,
     /// Rebuild tree.
     #[arg(long)]
     rebuild_tree: bool,
@@ -76,6 +80,15 @@ async fn main() -> anyhow::Result<()> {
         tracing::info!("No sentry URL was provided");
     }
 
+    if let Some(path) = &opt.export_genesis {
+        let network = NetworkConfig::from_env().context("NetworkConfig")?;
+        let eth_sender = ETHSenderConfig::from_env().context("ETHSenderConfig")?;
+        let contracts = ContractsConfig::from_env().context("ContractsConfig")?;
+        export_genesis_artifact(&eth_sender, &network, &contracts, path)
+            .context("export_genesis_artifact")?;
+        return Ok(());
+    }
+
     if opt.genesis || is_genesis_needed().await {
         let network = NetworkConfig::from_env().context("NetworkConfig")?;
         let eth_sender = ETHSenderConfig::from_env().context("ETHSenderConfig")?;
@@ -88,6 +101,15 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    let network = NetworkConfig::from_env().context("NetworkConfig")?;
+    if network.l1_genesis_check != L1GenesisCheck::Disabled {
+        let eth_client = ETHClientConfig::from_env().context("ETHClientConfig")?;
+        let contracts = ContractsConfig::from_env().context("ContractsConfig")?;
+        check_genesis_on_l1(&network, &eth_client, &contracts)
+            .await
+            .context("check_genesis_on_l1")?;
+    }
+
     let components = if opt.rebuild_tree {
         vec![Component::Tree]
     } else {
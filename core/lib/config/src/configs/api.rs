@@ -278,6 +278,7 @@ mod tests {
                 listener_port: 3312,
                 pushgateway_url: "http://127.0.0.1:9091".into(),
                 push_interval_ms: Some(100),
+                merkle_tree_stage_latency_buckets: Some(vec![0.01, 1.0, 60.0]),
             },
             healthcheck: HealthCheckConfig { port: 8081 },
         }
@@ -320,6 +321,7 @@ mod tests {
             API_PROMETHEUS_LISTENER_PORT="3312"
             API_PROMETHEUS_PUSHGATEWAY_URL="http://127.0.0.1:9091"
             API_PROMETHEUS_PUSH_INTERVAL_MS=100
+            API_PROMETHEUS_MERKLE_TREE_STAGE_LATENCY_BUCKETS="0.01,1,60"
             API_HEALTHCHECK_PORT=8081
         "#;
         lock.set_env(config);
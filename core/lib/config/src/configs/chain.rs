@@ -2,7 +2,7 @@
 use anyhow::Context as _;
 use serde::Deserialize;
 /// Built-in uses
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 // Local uses
 use zksync_basic_types::network::Network;
 use zksync_basic_types::{Address, L2ChainId, H256};
@@ -48,12 +48,46 @@ pub struct NetworkConfig {
     /// ID of current zkSync network treated as ETH network ID.
     /// Used to distinguish zkSync from other Web3-capable networks.
     pub zksync_network_id: L2ChainId,
+    /// Path to a JSON file with genesis parameters (first validator, protocol version, verifier config
+    /// and base system contracts). Other formats (e.g., YAML) are not supported. If not set, genesis parameters
+    /// are derived from the other configs.
+    #[serde(default)]
+    pub genesis_file: Option<PathBuf>,
+    /// Whether to check on startup that the genesis L1 batch hash stored in the L1 diamond proxy
+    /// matches the genesis L1 batch in Postgres. Disabled by default (e.g., for L1-less dev environments).
+    #[serde(default)]
+    pub l1_genesis_check: L1GenesisCheck,
+    /// Timeout for fetching the genesis L1 batch hash from L1 during the startup check, in milliseconds.
+    #[serde(default = "NetworkConfig::default_l1_genesis_check_timeout_ms")]
+    pub l1_genesis_check_timeout_ms: u64,
 }
 
 impl NetworkConfig {
     pub fn from_env() -> anyhow::Result<Self> {
         envy_load("network", "CHAIN_ETH_")
     }
+
+    const fn default_l1_genesis_check_timeout_ms() -> u64 {
+        10_000
+    }
+
+    pub fn l1_genesis_check_timeout(&self) -> Duration {
+        Duration::from_millis(self.l1_genesis_check_timeout_ms)
+    }
+}
+
+/// Startup check of the genesis L1 batch against the L1 diamond proxy. A mismatch between hashes
+/// always aborts the server; the mode only determines handling of an unreachable L1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum L1GenesisCheck {
+    /// Do not perform the check.
+    #[default]
+    Disabled,
+    /// Abort if the genesis L1 batch hash cannot be fetched from L1.
+    Error,
+    /// Log a warning and continue if the genesis L1 batch hash cannot be fetched from L1.
+    Warn,
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq, Default)]
@@ -203,6 +237,9 @@ mod tests {
                 network: "localhost".parse().unwrap(),
                 zksync_network: "localhost".to_string(),
                 zksync_network_id: L2ChainId::from(270),
+                genesis_file: Some("etc/test_config/constant/genesis.json".into()),
+                l1_genesis_check: L1GenesisCheck::Warn,
+                l1_genesis_check_timeout_ms: 5_000,
             },
             state_keeper: StateKeeperConfig {
                 transaction_slots: 50,
@@ -254,6 +291,9 @@ mod tests {
             CHAIN_ETH_NETWORK="localhost"
             CHAIN_ETH_ZKSYNC_NETWORK="localhost"
             CHAIN_ETH_ZKSYNC_NETWORK_ID=270
+            CHAIN_ETH_GENESIS_FILE="etc/test_config/constant/genesis.json"
+            CHAIN_ETH_L1_GENESIS_CHECK="warn"
+            CHAIN_ETH_L1_GENESIS_CHECK_TIMEOUT_MS=5000
             CHAIN_STATE_KEEPER_TRANSACTION_SLOTS="50"
             CHAIN_STATE_KEEPER_FEE_ACCOUNT_ADDR="0xde03a0B5963f75f1C8485B355fF6D30f3093BDE7"
             CHAIN_STATE_KEEPER_MAX_SINGLE_TX_GAS="1000000"
@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 
 use std::time::Duration;
 
+use zksync_basic_types::H256;
+
 use super::envy_load;
 
 /// Mode of operation for the Merkle tree.
@@ -19,6 +21,55 @@ pub enum MerkleTreeMode {
     Lightweight,
 }
 
+/// Handling of mismatches between the Merkle tree and Postgres detected by a consistency check, e.g. between
+/// the next enumeration index produced by the tree for an L1 batch and the one derived from the `initial_writes` table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnumerationIndexCheck {
+    /// Halt the tree with an error.
+    #[default]
+    Error,
+    /// Log a warning and report the mismatch via metrics, but continue processing.
+    Warn,
+}
+
+/// Handling of inconsistencies in Postgres data detected by the Merkle tree, e.g. leaf indices of initial writes
+/// differing between the tree and the `initial_writes` table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DalConsistencyPolicy {
+    /// Halt the tree with an error on any detected inconsistency.
+    #[default]
+    FailFast,
+    /// Log a warning and report the inconsistency via metrics, but continue processing. Useful for archival
+    /// reprocessing, so that a single bad row doesn't halt the tree.
+    BestEffort,
+}
+
+/// Handling of L1 batches that have a header in Postgres, but neither storage logs nor protective reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyL1BatchPolicy {
+    /// Treat such an L1 batch as a valid empty L1 batch.
+    #[default]
+    Accept,
+    /// Treat such an L1 batch as potentially partially written, and retry loading its data
+    /// several times. If the data doesn't appear, halt the tree with an error.
+    Retry,
+}
+
+/// Format of the details reported in the Merkle tree health check.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeHealthDetailsFormat {
+    /// Structured JSON with nested objects (e.g., for object store health).
+    #[default]
+    Json,
+    /// Flat map with scalar values suitable for direct ingestion as metrics. Nested keys are joined with `_`,
+    /// boolean values are converted to 0 or 1, and null values are omitted.
+    Flat,
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct MerkleTreeConfig {
     /// Path to the RocksDB data directory for Merkle tree.
@@ -41,6 +92,213 @@ pub struct MerkleTreeConfig {
     /// Maximum number of L1 batches to be processed by the Merkle tree at a time.
     #[serde(default = "MerkleTreeConfig::default_max_l1_batches_per_iter")]
     pub max_l1_batches_per_iter: usize,
+    /// Interval between refreshing Merkle tree gauges (lag, disk usage, etc.) in milliseconds.
+    /// Gauges are refreshed regardless of whether the tree processes any L1 batches.
+    #[serde(default = "MerkleTreeConfig::default_metrics_refresh_interval_ms")]
+    pub metrics_refresh_interval_ms: u64,
+    /// Whether to validate consistency of the `initial_writes` table with storage logs for each processed
+    /// range of L1 batches. This check is slow and is intended for diagnostics only.
+    #[serde(default = "MerkleTreeConfig::default_validate_initial_writes")]
+    pub validate_initial_writes: bool,
+    /// Whether to wipe the Merkle tree RocksDB and rebuild the tree from Postgres if RocksDB is detected
+    /// to be corrupted on startup. Rebuilding may take a long time, but it doesn't require operator intervention.
+    #[serde(default = "MerkleTreeConfig::default_auto_rebuild_on_corruption")]
+    pub auto_rebuild_on_corruption: bool,
+    /// Number of recent tree root hashes cached in memory to speed up historical root hash queries.
+    /// If set to 0, root hashes are always loaded from RocksDB.
+    #[serde(default = "MerkleTreeConfig::default_root_hash_cache_size")]
+    pub root_hash_cache_size: usize,
+    /// URL of an optional HTTP webhook notified about the root hash of each L1 batch processed by the tree.
+    /// If not set (which is the default), the webhook is disabled. Requires the `webhook` feature of `zksync_core`.
+    pub root_webhook_url: Option<String>,
+    /// Maximum size of a witness input (in MB) kept in memory in the full tree mode. Larger witnesses
+    /// are spilled to a temporary file and read back when persisted to the object store.
+    #[serde(default = "MerkleTreeConfig::default_max_in_memory_witness_size_mb")]
+    pub max_in_memory_witness_size_mb: usize,
+    /// Maximum number of touched storage slots in a single L1 batch that the Merkle tree is allowed to load into memory.
+    /// If an L1 batch touches more slots, the tree fails with an error instead of potentially running out of memory.
+    #[serde(default = "MerkleTreeConfig::default_max_in_memory_slots")]
+    pub max_in_memory_slots: usize,
+    /// `zstd` compression level for witness inputs persisted to the object store. Higher levels produce smaller
+    /// blobs at the cost of more CPU time.
+    #[serde(default = "MerkleTreeConfig::default_witness_compression_level")]
+    pub witness_compression_level: i32,
+    /// `zstd` compression level for witness inputs spilled to disk (see `max_in_memory_witness_size_mb`).
+    /// If not set (which is the default), spilled witnesses are stored uncompressed.
+    pub witness_spill_compression_level: Option<i32>,
+    /// Lag of the Merkle tree (number of sealed L1 batches not yet processed by it) starting from which
+    /// the tree health is reported as affected. If not set, the lag doesn't affect the tree health.
+    pub health_affected_lag: Option<u32>,
+    /// Lag of the Merkle tree starting from which the tree health is reported as not ready. If not set,
+    /// the lag doesn't affect the tree readiness.
+    pub health_not_ready_lag: Option<u32>,
+    /// Whether to generate witness inputs and persist them to the object store in the full tree mode. Disabling this
+    /// keeps all other full-mode features (e.g., processing read logs) intact.
+    #[serde(default = "MerkleTreeConfig::default_generate_witness_inputs")]
+    pub generate_witness_inputs: bool,
+    /// Whether to cross-check leaf indices of repeated writes produced by the tree against the `initial_writes` table
+    /// for each processed L1 batch. Mismatches are logged and reported via metrics rather than halting the tree.
+    #[serde(default = "MerkleTreeConfig::default_validate_repeated_writes")]
+    pub validate_repeated_writes: bool,
+    /// Maximum protocol version of L1 batches that the Merkle tree is allowed to process. If the tree encounters
+    /// an L1 batch with a newer protocol version, it halts with an error instead of potentially computing wrong
+    /// root hashes. If not set, the newest protocol version known to the server is used.
+    pub max_supported_protocol_version: Option<u16>,
+    /// Path to a directory used as a durable queue of witness inputs awaiting upload to the object store
+    /// in the full tree mode. If set, witness inputs are uploaded by a background task with retries, so that
+    /// object store outages don't block tree processing. If not set (which is the default), witness inputs
+    /// are uploaded by the tree directly.
+    pub witness_upload_queue_path: Option<String>,
+    /// Maximum total size (in MB) of witness inputs in the upload queue. If the queue is full, the tree pauses
+    /// until queued witness inputs are uploaded.
+    #[serde(default = "MerkleTreeConfig::default_witness_upload_queue_max_size_mb")]
+    pub witness_upload_queue_max_size_mb: usize,
+    /// Number of the most frequently written storage keys periodically logged by the tree. If not set
+    /// (which is the default), write frequencies are not tracked.
+    pub hot_keys_report_top_n: Option<usize>,
+    /// Number of L1 batches in a window over which write frequencies of storage keys are tracked
+    /// (see `hot_keys_report_top_n`). A report is logged at the end of each window.
+    #[serde(default = "MerkleTreeConfig::default_hot_keys_report_window")]
+    pub hot_keys_report_window: u32,
+    /// Maximum number of L1 batches processed by the tree with witness inputs not yet persisted. If this number
+    /// is reached, the tree pauses until witness inputs are persisted.
+    #[serde(default = "MerkleTreeConfig::default_max_pending_witnesses")]
+    pub max_pending_witnesses: usize,
+    /// Number of witness inputs serialized and persisted concurrently.
+    #[serde(default = "MerkleTreeConfig::default_witness_persistence_concurrency")]
+    pub witness_persistence_concurrency: usize,
+    /// Limit on the background I/O (flushes and compactions) of the Merkle tree RocksDB in bytes per second.
+    /// Limiting smooths out I/O spikes affecting co-located services at the cost of slightly increased latency.
+    /// If not set (which is the default), background I/O is not limited.
+    pub rocksdb_rate_limit_bytes_per_sec: Option<usize>,
+    /// Serialized size of a witness input (in MB) starting from which the witness input is uploaded to the object store
+    /// in the chunked format, i.e., as a manifest and multiple chunks with a fixed number of Merkle paths each.
+    /// Smaller witness inputs are uploaded as a single object. Witness inputs put into the upload queue are always
+    /// uploaded as a single object.
+    #[serde(default = "MerkleTreeConfig::default_witness_chunking_threshold_mb")]
+    pub witness_chunking_threshold_mb: usize,
+    /// Number of Merkle paths in a single chunk of a witness input uploaded in the chunked format.
+    #[serde(default = "MerkleTreeConfig::default_witness_chunk_size")]
+    pub witness_chunk_size: usize,
+    /// Whether to persist witness inputs in the versioned format with deduplicated Merkle path hashes. This format
+    /// is substantially smaller, but can only be read by witness generators supporting it, so it's disabled by default.
+    #[serde(default = "MerkleTreeConfig::default_deduplicate_witness_paths")]
+    pub deduplicate_witness_paths: bool,
+    /// Whether to record the protocol version of the L1 batch in the header of persisted witness inputs. Witness inputs
+    /// with a recorded protocol version can only be read by witness generators supporting it, so it's disabled by default.
+    #[serde(default = "MerkleTreeConfig::default_record_witness_protocol_version")]
+    pub record_witness_protocol_version: bool,
+    /// Maximum number of attempts to acquire a Postgres connection in the tree update loop. Failed attempts are
+    /// assumed to be caused by transient issues (e.g., an exhausted pool or a restarting DB) and are retried
+    /// with exponential backoff; once all attempts fail, the tree is marked as not ready and stops with an error.
+    #[serde(default = "MerkleTreeConfig::default_db_connection_max_attempts")]
+    pub db_connection_max_attempts: usize,
+    /// Initial backoff between attempts to acquire a Postgres connection in the tree update loop in milliseconds.
+    /// The backoff is doubled after each failed attempt.
+    #[serde(default = "MerkleTreeConfig::default_db_connection_backoff_ms")]
+    pub db_connection_backoff_ms: u64,
+    /// Maximum number of attempts for each object store request made when persisting witness inputs.
+    #[serde(default = "MerkleTreeConfig::default_object_store_max_attempts")]
+    pub object_store_max_attempts: usize,
+    /// Initial backoff between attempts of an object store request in milliseconds. The backoff is doubled
+    /// after each failed attempt.
+    #[serde(default = "MerkleTreeConfig::default_object_store_backoff_ms")]
+    pub object_store_backoff_ms: u64,
+    /// Timeout for a single attempt of an object store request in milliseconds.
+    #[serde(default = "MerkleTreeConfig::default_object_store_request_timeout_ms")]
+    pub object_store_request_timeout_ms: u64,
+    /// Number of consecutive failed object store request attempts after which the tree is reported
+    /// as affected in its health check. The health is restored after a successful request.
+    #[serde(default = "MerkleTreeConfig::default_object_store_failure_threshold")]
+    pub object_store_failure_threshold: usize,
+    /// If set, storage logs loaded by the tree for L1 batches with numbers divisible by this value are cross-checked
+    /// against logs loaded using the slower reference method (e.g., 100 means that every 100th L1 batch is checked).
+    /// Divergences are logged and reported via metrics rather than halting the tree. If not set (which is the default),
+    /// loaded logs are not checked.
+    pub logs_verification_modulus: Option<u32>,
+    /// Maximum number of previous storage values cached between verified L1 batches. The cache saves Postgres
+    /// lookups for slots touched in adjacent verified L1 batches; set to 0 to disable caching.
+    #[serde(default = "MerkleTreeConfig::default_logs_verification_cache_capacity")]
+    pub logs_verification_cache_capacity: usize,
+    /// Target latency of saving processed L1 batches to RocksDB in milliseconds. If set, the fraction of recent saves
+    /// meeting the target is reported in the tree health details. If not set (which is the default), save latency
+    /// doesn't affect the tree health.
+    pub save_latency_slo_ms: Option<u64>,
+    /// Number of most recent tree saves used to compute compliance with `save_latency_slo_ms`.
+    #[serde(default = "MerkleTreeConfig::default_save_latency_slo_window")]
+    pub save_latency_slo_window: usize,
+    /// Minimum fraction of recent tree saves meeting `save_latency_slo_ms`. If the compliance drops below this value,
+    /// the tree is reported as affected in its health check.
+    #[serde(default = "MerkleTreeConfig::default_save_latency_slo_min_compliance")]
+    pub save_latency_slo_min_compliance: f64,
+    /// Whether to load L1 batch data (headers, storage logs and protective reads) processed by the tree
+    /// from the read replica rather than the primary Postgres. Tree outputs are still written to the primary.
+    /// L1 batches not yet replicated are treated as not yet sealed.
+    #[serde(default = "MerkleTreeConfig::default_load_from_replica")]
+    pub load_from_replica: bool,
+    /// Maximum replication lag in milliseconds tolerated for the read replica if `load_from_replica` is set.
+    /// If the replica lags behind the primary by more than this value, loading L1 batch data is retried
+    /// after the DB connection backoff. If not set (which is the default), the lag is not checked.
+    pub replica_max_replication_lag_ms: Option<u64>,
+    /// Postgres schema from which the tree loads L1 batch data and to which it persists tree outputs,
+    /// e.g. to run the tree for a shadow chain whose DAL tables live in a secondary schema of the same database.
+    /// Applies both to the primary Postgres and to the read replica if `load_from_replica` is set. Must be
+    /// a lowercase unquoted Postgres identifier. If not set (which is the default), the default search path is used.
+    pub postgres_schema: Option<String>,
+    /// Handling of mismatches between the next enumeration index produced by the tree for each L1 batch
+    /// and the one derived from the `initial_writes` table. Such a mismatch means that L1 batch commitments
+    /// and proofs will disagree. If not specified, mismatches halt the tree.
+    #[serde(default)]
+    pub enumeration_index_check: EnumerationIndexCheck,
+    /// Handling of the tree being ahead of the last sealed L1 batch in Postgres by more than one L1 batch, which is checked
+    /// on startup. Such a lead means that Postgres was reverted or restored without the tree. If not specified,
+    /// the lead halts the tree.
+    #[serde(default)]
+    pub progress_check: EnumerationIndexCheck,
+    /// Handling of inconsistencies in Postgres data detected by the tree: mismatches between initial writes produced
+    /// by the tree and the `initial_writes` table, inconsistencies found if `validate_initial_writes` is enabled,
+    /// and L1 batch headers that cannot be loaded. If not specified, inconsistencies halt the tree.
+    #[serde(default)]
+    pub dal_consistency_policy: DalConsistencyPolicy,
+    /// Handling of L1 batches with a header, but without storage logs or protective reads. Such an L1 batch
+    /// may be legitimately empty, or it may be partially written to Postgres. If not specified, such L1 batches
+    /// are processed as empty.
+    #[serde(default)]
+    pub empty_l1_batch_policy: EmptyL1BatchPolicy,
+    /// Maximum number of retries of loading data for an empty L1 batch if `empty_l1_batch_policy` is `retry`.
+    #[serde(default = "MerkleTreeConfig::default_empty_l1_batch_max_retries")]
+    pub empty_l1_batch_max_retries: usize,
+    /// Interval between retries of loading data for an empty L1 batch in milliseconds.
+    #[serde(default = "MerkleTreeConfig::default_empty_l1_batch_retry_interval_ms")]
+    pub empty_l1_batch_retry_interval_ms: u64,
+    /// Threshold in milliseconds after which the tree update loop is considered stalled if it doesn't complete
+    /// an iteration. A stalled tree is reported as not ready in its health check. Must exceed the sum of
+    /// the delay between polling Postgres and the expected time to process a single chunk of L1 batches.
+    /// If not set (which is the default), stalls are not detected.
+    pub stall_threshold_ms: Option<u64>,
+    /// Format of the details reported in the tree health check. If not specified, details are reported
+    /// as structured JSON.
+    #[serde(default)]
+    pub health_details_format: TreeHealthDetailsFormat,
+    /// Capacity of the channel notifying subscribers about L1 batches processed by the tree. If a subscriber
+    /// falls behind by more than this number of notifications, the oldest notifications are dropped for it,
+    /// and the subscriber is informed how many notifications it has missed.
+    #[serde(default = "MerkleTreeConfig::default_l1_batch_notifications_capacity")]
+    pub l1_batch_notifications_capacity: usize,
+    /// Hashed storage keys (as returned by `StorageKey::hashed_key()`) for which Merkle proofs are precomputed
+    /// and cached by the tree, so that they can be served without accessing RocksDB. A cached proof is recomputed
+    /// only when the corresponding key is written. If not specified, no proofs are cached.
+    #[serde(default)]
+    pub watched_keys: Vec<H256>,
+    /// Ratio of protective reads to writes in an L1 batch above which a warning is logged. Protective reads vastly
+    /// outnumbering writes may indicate misconfigured storage log deduplication in the state keeper. Only checked
+    /// in the full tree mode.
+    #[serde(default = "MerkleTreeConfig::default_excessive_protective_reads_ratio")]
+    pub excessive_protective_reads_ratio: f64,
+    /// Minimum number of protective reads in an L1 batch for it to be checked against `excessive_protective_reads_ratio`,
+    /// so that small L1 batches don't produce noise.
+    #[serde(default = "MerkleTreeConfig::default_min_checked_protective_reads")]
+    pub min_checked_protective_reads: usize,
 }
 
 impl Default for MerkleTreeConfig {
@@ -52,6 +310,57 @@ impl Default for MerkleTreeConfig {
             multi_get_chunk_size: Self::default_multi_get_chunk_size(),
             block_cache_size_mb: Self::default_block_cache_size_mb(),
             max_l1_batches_per_iter: Self::default_max_l1_batches_per_iter(),
+            metrics_refresh_interval_ms: Self::default_metrics_refresh_interval_ms(),
+            validate_initial_writes: Self::default_validate_initial_writes(),
+            auto_rebuild_on_corruption: Self::default_auto_rebuild_on_corruption(),
+            root_hash_cache_size: Self::default_root_hash_cache_size(),
+            root_webhook_url: None,
+            max_in_memory_witness_size_mb: Self::default_max_in_memory_witness_size_mb(),
+            max_in_memory_slots: Self::default_max_in_memory_slots(),
+            witness_compression_level: Self::default_witness_compression_level(),
+            witness_spill_compression_level: None,
+            health_affected_lag: None,
+            health_not_ready_lag: None,
+            generate_witness_inputs: Self::default_generate_witness_inputs(),
+            validate_repeated_writes: Self::default_validate_repeated_writes(),
+            max_supported_protocol_version: None,
+            witness_upload_queue_path: None,
+            witness_upload_queue_max_size_mb: Self::default_witness_upload_queue_max_size_mb(),
+            hot_keys_report_top_n: None,
+            hot_keys_report_window: Self::default_hot_keys_report_window(),
+            max_pending_witnesses: Self::default_max_pending_witnesses(),
+            witness_persistence_concurrency: Self::default_witness_persistence_concurrency(),
+            rocksdb_rate_limit_bytes_per_sec: None,
+            witness_chunking_threshold_mb: Self::default_witness_chunking_threshold_mb(),
+            witness_chunk_size: Self::default_witness_chunk_size(),
+            deduplicate_witness_paths: Self::default_deduplicate_witness_paths(),
+            record_witness_protocol_version: Self::default_record_witness_protocol_version(),
+            db_connection_max_attempts: Self::default_db_connection_max_attempts(),
+            db_connection_backoff_ms: Self::default_db_connection_backoff_ms(),
+            object_store_max_attempts: Self::default_object_store_max_attempts(),
+            object_store_backoff_ms: Self::default_object_store_backoff_ms(),
+            object_store_request_timeout_ms: Self::default_object_store_request_timeout_ms(),
+            object_store_failure_threshold: Self::default_object_store_failure_threshold(),
+            logs_verification_modulus: None,
+            logs_verification_cache_capacity: Self::default_logs_verification_cache_capacity(),
+            save_latency_slo_ms: None,
+            save_latency_slo_window: Self::default_save_latency_slo_window(),
+            save_latency_slo_min_compliance: Self::default_save_latency_slo_min_compliance(),
+            load_from_replica: Self::default_load_from_replica(),
+            replica_max_replication_lag_ms: None,
+            postgres_schema: None,
+            enumeration_index_check: EnumerationIndexCheck::default(),
+            progress_check: EnumerationIndexCheck::default(),
+            dal_consistency_policy: DalConsistencyPolicy::default(),
+            empty_l1_batch_policy: EmptyL1BatchPolicy::default(),
+            empty_l1_batch_max_retries: Self::default_empty_l1_batch_max_retries(),
+            empty_l1_batch_retry_interval_ms: Self::default_empty_l1_batch_retry_interval_ms(),
+            stall_threshold_ms: None,
+            health_details_format: TreeHealthDetailsFormat::default(),
+            l1_batch_notifications_capacity: Self::default_l1_batch_notifications_capacity(),
+            watched_keys: Vec::new(),
+            excessive_protective_reads_ratio: Self::default_excessive_protective_reads_ratio(),
+            min_checked_protective_reads: Self::default_min_checked_protective_reads(),
         }
     }
 }
@@ -77,10 +386,189 @@ impl MerkleTreeConfig {
         20
     }
 
+    const fn default_metrics_refresh_interval_ms() -> u64 {
+        10_000
+    }
+
+    const fn default_validate_initial_writes() -> bool {
+        false
+    }
+
+    const fn default_auto_rebuild_on_corruption() -> bool {
+        false
+    }
+
+    const fn default_root_hash_cache_size() -> usize {
+        16
+    }
+
+    const fn default_max_in_memory_witness_size_mb() -> usize {
+        512
+    }
+
+    const fn default_max_in_memory_slots() -> usize {
+        50_000_000
+    }
+
+    const fn default_witness_compression_level() -> i32 {
+        3
+    }
+
+    const fn default_generate_witness_inputs() -> bool {
+        true
+    }
+
+    const fn default_validate_repeated_writes() -> bool {
+        false
+    }
+
+    const fn default_witness_upload_queue_max_size_mb() -> usize {
+        4_096
+    }
+
+    const fn default_hot_keys_report_window() -> u32 {
+        100
+    }
+
+    const fn default_max_pending_witnesses() -> usize {
+        4
+    }
+
+    const fn default_witness_persistence_concurrency() -> usize {
+        2
+    }
+
+    const fn default_witness_chunking_threshold_mb() -> usize {
+        2_048
+    }
+
+    const fn default_witness_chunk_size() -> usize {
+        50_000
+    }
+
+    const fn default_deduplicate_witness_paths() -> bool {
+        false
+    }
+
+    const fn default_record_witness_protocol_version() -> bool {
+        false
+    }
+
+    const fn default_db_connection_max_attempts() -> usize {
+        10
+    }
+
+    const fn default_db_connection_backoff_ms() -> u64 {
+        500
+    }
+
+    const fn default_object_store_max_attempts() -> usize {
+        5
+    }
+
+    const fn default_object_store_backoff_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_object_store_request_timeout_ms() -> u64 {
+        300_000 // 5 minutes
+    }
+
+    const fn default_object_store_failure_threshold() -> usize {
+        3
+    }
+
+    const fn default_logs_verification_cache_capacity() -> usize {
+        100_000
+    }
+
+    const fn default_save_latency_slo_window() -> usize {
+        100
+    }
+
+    const fn default_save_latency_slo_min_compliance() -> f64 {
+        0.9
+    }
+
+    const fn default_load_from_replica() -> bool {
+        false
+    }
+
+    const fn default_empty_l1_batch_max_retries() -> usize {
+        5
+    }
+
+    const fn default_empty_l1_batch_retry_interval_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_l1_batch_notifications_capacity() -> usize {
+        1_024
+    }
+
+    const fn default_excessive_protective_reads_ratio() -> f64 {
+        100.0
+    }
+
+    const fn default_min_checked_protective_reads() -> usize {
+        100
+    }
+
     /// Returns the size of block cache size for Merkle tree in bytes.
     pub fn block_cache_size(&self) -> usize {
         self.block_cache_size_mb * super::BYTES_IN_MEGABYTE
     }
+
+    /// Returns the maximum size of a witness input kept in memory in bytes.
+    pub fn max_in_memory_witness_size(&self) -> usize {
+        self.max_in_memory_witness_size_mb * super::BYTES_IN_MEGABYTE
+    }
+
+    /// Returns the maximum total size of witness inputs in the upload queue in bytes.
+    pub fn witness_upload_queue_max_size(&self) -> usize {
+        self.witness_upload_queue_max_size_mb * super::BYTES_IN_MEGABYTE
+    }
+
+    /// Returns the serialized size of a witness input in bytes starting from which it's uploaded in the chunked format.
+    pub fn witness_chunking_threshold(&self) -> usize {
+        self.witness_chunking_threshold_mb * super::BYTES_IN_MEGABYTE
+    }
+
+    /// Returns the interval between refreshing Merkle tree gauges.
+    pub fn metrics_refresh_interval(&self) -> Duration {
+        Duration::from_millis(self.metrics_refresh_interval_ms)
+    }
+
+    /// Returns the initial backoff between attempts to acquire a Postgres connection in the tree update loop.
+    pub fn db_connection_backoff(&self) -> Duration {
+        Duration::from_millis(self.db_connection_backoff_ms)
+    }
+
+    /// Returns the initial backoff between attempts of an object store request.
+    pub fn object_store_backoff(&self) -> Duration {
+        Duration::from_millis(self.object_store_backoff_ms)
+    }
+
+    /// Returns the timeout for a single attempt of an object store request.
+    pub fn object_store_request_timeout(&self) -> Duration {
+        Duration::from_millis(self.object_store_request_timeout_ms)
+    }
+
+    /// Returns the interval between retries of loading data for an empty L1 batch.
+    pub fn empty_l1_batch_retry_interval(&self) -> Duration {
+        Duration::from_millis(self.empty_l1_batch_retry_interval_ms)
+    }
+
+    /// Returns the maximum replication lag tolerated for the read replica, if any.
+    pub fn replica_max_replication_lag(&self) -> Option<Duration> {
+        self.replica_max_replication_lag_ms
+            .map(Duration::from_millis)
+    }
+
+    /// Returns the threshold after which the tree update loop is considered stalled, if any.
+    pub fn stall_threshold(&self) -> Option<Duration> {
+        self.stall_threshold_ms.map(Duration::from_millis)
+    }
 }
 
 /// Database configuration.
@@ -152,6 +640,57 @@ mod tests {
             DATABASE_MERKLE_TREE_MODE=lightweight
             DATABASE_MERKLE_TREE_MULTI_GET_CHUNK_SIZE=250
             DATABASE_MERKLE_TREE_MAX_L1_BATCHES_PER_ITER=50
+            DATABASE_MERKLE_TREE_METRICS_REFRESH_INTERVAL_MS=5000
+            DATABASE_MERKLE_TREE_VALIDATE_INITIAL_WRITES=true
+            DATABASE_MERKLE_TREE_AUTO_REBUILD_ON_CORRUPTION=true
+            DATABASE_MERKLE_TREE_ROOT_HASH_CACHE_SIZE=32
+            DATABASE_MERKLE_TREE_ROOT_WEBHOOK_URL=http://127.0.0.1:3000/
+            DATABASE_MERKLE_TREE_MAX_IN_MEMORY_WITNESS_SIZE_MB=256
+            DATABASE_MERKLE_TREE_MAX_IN_MEMORY_SLOTS=1000000
+            DATABASE_MERKLE_TREE_WITNESS_COMPRESSION_LEVEL=9
+            DATABASE_MERKLE_TREE_HEALTH_AFFECTED_LAG=5
+            DATABASE_MERKLE_TREE_WITNESS_SPILL_COMPRESSION_LEVEL=1
+            DATABASE_MERKLE_TREE_HEALTH_NOT_READY_LAG=50
+            DATABASE_MERKLE_TREE_GENERATE_WITNESS_INPUTS=false
+            DATABASE_MERKLE_TREE_VALIDATE_REPEATED_WRITES=true
+            DATABASE_MERKLE_TREE_MAX_SUPPORTED_PROTOCOL_VERSION=15
+            DATABASE_MERKLE_TREE_WITNESS_UPLOAD_QUEUE_PATH=/db/witness_queue
+            DATABASE_MERKLE_TREE_WITNESS_UPLOAD_QUEUE_MAX_SIZE_MB=1024
+            DATABASE_MERKLE_TREE_HOT_KEYS_REPORT_TOP_N=20
+            DATABASE_MERKLE_TREE_HOT_KEYS_REPORT_WINDOW=50
+            DATABASE_MERKLE_TREE_MAX_PENDING_WITNESSES=8
+            DATABASE_MERKLE_TREE_WITNESS_PERSISTENCE_CONCURRENCY=3
+            DATABASE_MERKLE_TREE_ROCKSDB_RATE_LIMIT_BYTES_PER_SEC=104857600
+            DATABASE_MERKLE_TREE_WITNESS_CHUNKING_THRESHOLD_MB=512
+            DATABASE_MERKLE_TREE_WITNESS_CHUNK_SIZE=10000
+            DATABASE_MERKLE_TREE_DEDUPLICATE_WITNESS_PATHS=true
+            DATABASE_MERKLE_TREE_RECORD_WITNESS_PROTOCOL_VERSION=true
+            DATABASE_MERKLE_TREE_DB_CONNECTION_MAX_ATTEMPTS=3
+            DATABASE_MERKLE_TREE_DB_CONNECTION_BACKOFF_MS=2000
+            DATABASE_MERKLE_TREE_OBJECT_STORE_MAX_ATTEMPTS=3
+            DATABASE_MERKLE_TREE_OBJECT_STORE_BACKOFF_MS=500
+            DATABASE_MERKLE_TREE_OBJECT_STORE_REQUEST_TIMEOUT_MS=60000
+            DATABASE_MERKLE_TREE_OBJECT_STORE_FAILURE_THRESHOLD=2
+            DATABASE_MERKLE_TREE_LOGS_VERIFICATION_MODULUS=100
+            DATABASE_MERKLE_TREE_LOGS_VERIFICATION_CACHE_CAPACITY=5000
+            DATABASE_MERKLE_TREE_SAVE_LATENCY_SLO_MS=500
+            DATABASE_MERKLE_TREE_SAVE_LATENCY_SLO_WINDOW=50
+            DATABASE_MERKLE_TREE_SAVE_LATENCY_SLO_MIN_COMPLIANCE=0.95
+            DATABASE_MERKLE_TREE_LOAD_FROM_REPLICA=true
+            DATABASE_MERKLE_TREE_REPLICA_MAX_REPLICATION_LAG_MS=5000
+            DATABASE_MERKLE_TREE_POSTGRES_SCHEMA=shadow_chain
+            DATABASE_MERKLE_TREE_ENUMERATION_INDEX_CHECK=warn
+            DATABASE_MERKLE_TREE_PROGRESS_CHECK=warn
+            DATABASE_MERKLE_TREE_DAL_CONSISTENCY_POLICY=best_effort
+            DATABASE_MERKLE_TREE_EMPTY_L1_BATCH_POLICY=retry
+            DATABASE_MERKLE_TREE_EMPTY_L1_BATCH_MAX_RETRIES=3
+            DATABASE_MERKLE_TREE_EMPTY_L1_BATCH_RETRY_INTERVAL_MS=200
+            DATABASE_MERKLE_TREE_STALL_THRESHOLD_MS=60000
+            DATABASE_MERKLE_TREE_HEALTH_DETAILS_FORMAT=flat
+            DATABASE_MERKLE_TREE_L1_BATCH_NOTIFICATIONS_CAPACITY=64
+            DATABASE_MERKLE_TREE_WATCHED_KEYS="0x0000000000000000000000000000000000000000000000000000000000000001,0x0000000000000000000000000000000000000000000000000000000000000002"
+            DATABASE_MERKLE_TREE_EXCESSIVE_PROTECTIVE_READS_RATIO=50
+            DATABASE_MERKLE_TREE_MIN_CHECKED_PROTECTIVE_READS=1000
             DATABASE_BACKUP_COUNT=5
             DATABASE_BACKUP_INTERVAL_MS=60000
         "#;
@@ -164,6 +703,120 @@ mod tests {
         assert_eq!(db_config.merkle_tree.mode, MerkleTreeMode::Lightweight);
         assert_eq!(db_config.merkle_tree.multi_get_chunk_size, 250);
         assert_eq!(db_config.merkle_tree.max_l1_batches_per_iter, 50);
+        assert_eq!(
+            db_config.merkle_tree.metrics_refresh_interval(),
+            Duration::from_secs(5)
+        );
+        assert!(db_config.merkle_tree.validate_initial_writes);
+        assert!(db_config.merkle_tree.auto_rebuild_on_corruption);
+        assert_eq!(db_config.merkle_tree.root_hash_cache_size, 32);
+        assert_eq!(
+            db_config.merkle_tree.root_webhook_url,
+            Some("http://127.0.0.1:3000/".to_owned())
+        );
+        assert_eq!(db_config.merkle_tree.max_in_memory_witness_size_mb, 256);
+        assert_eq!(db_config.merkle_tree.max_in_memory_slots, 1_000_000);
+        assert_eq!(db_config.merkle_tree.witness_compression_level, 9);
+        assert_eq!(db_config.merkle_tree.health_affected_lag, Some(5));
+        assert_eq!(
+            db_config.merkle_tree.witness_spill_compression_level,
+            Some(1)
+        );
+        assert_eq!(db_config.merkle_tree.health_not_ready_lag, Some(50));
+        assert!(!db_config.merkle_tree.generate_witness_inputs);
+        assert!(db_config.merkle_tree.validate_repeated_writes);
+        assert_eq!(
+            db_config.merkle_tree.max_supported_protocol_version,
+            Some(15)
+        );
+        assert_eq!(
+            db_config.merkle_tree.witness_upload_queue_path.as_deref(),
+            Some("/db/witness_queue")
+        );
+        assert_eq!(
+            db_config.merkle_tree.witness_upload_queue_max_size_mb,
+            1_024
+        );
+        assert_eq!(db_config.merkle_tree.hot_keys_report_top_n, Some(20));
+        assert_eq!(db_config.merkle_tree.hot_keys_report_window, 50);
+        assert_eq!(db_config.merkle_tree.max_pending_witnesses, 8);
+        assert_eq!(db_config.merkle_tree.witness_persistence_concurrency, 3);
+        assert_eq!(
+            db_config.merkle_tree.rocksdb_rate_limit_bytes_per_sec,
+            Some(100 << 20)
+        );
+        assert_eq!(db_config.merkle_tree.witness_chunking_threshold_mb, 512);
+        assert_eq!(db_config.merkle_tree.witness_chunk_size, 10_000);
+        assert!(db_config.merkle_tree.deduplicate_witness_paths);
+        assert!(db_config.merkle_tree.record_witness_protocol_version);
+        assert_eq!(db_config.merkle_tree.db_connection_max_attempts, 3);
+        assert_eq!(
+            db_config.merkle_tree.db_connection_backoff(),
+            Duration::from_secs(2)
+        );
+        assert_eq!(db_config.merkle_tree.object_store_max_attempts, 3);
+        assert_eq!(
+            db_config.merkle_tree.object_store_backoff(),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            db_config.merkle_tree.object_store_request_timeout(),
+            Duration::from_secs(60)
+        );
+        assert_eq!(db_config.merkle_tree.object_store_failure_threshold, 2);
+        assert_eq!(db_config.merkle_tree.logs_verification_modulus, Some(100));
+        assert_eq!(
+            db_config.merkle_tree.logs_verification_cache_capacity,
+            5_000
+        );
+        assert_eq!(db_config.merkle_tree.save_latency_slo_ms, Some(500));
+        assert_eq!(db_config.merkle_tree.save_latency_slo_window, 50);
+        assert_eq!(db_config.merkle_tree.save_latency_slo_min_compliance, 0.95);
+        assert!(db_config.merkle_tree.load_from_replica);
+        assert_eq!(
+            db_config.merkle_tree.replica_max_replication_lag(),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            db_config.merkle_tree.postgres_schema.as_deref(),
+            Some("shadow_chain")
+        );
+        assert_eq!(
+            db_config.merkle_tree.enumeration_index_check,
+            EnumerationIndexCheck::Warn
+        );
+        assert_eq!(
+            db_config.merkle_tree.progress_check,
+            EnumerationIndexCheck::Warn
+        );
+        assert_eq!(
+            db_config.merkle_tree.dal_consistency_policy,
+            DalConsistencyPolicy::BestEffort
+        );
+        assert_eq!(
+            db_config.merkle_tree.empty_l1_batch_policy,
+            EmptyL1BatchPolicy::Retry
+        );
+        assert_eq!(db_config.merkle_tree.empty_l1_batch_max_retries, 3);
+        assert_eq!(
+            db_config.merkle_tree.empty_l1_batch_retry_interval(),
+            Duration::from_millis(200)
+        );
+        assert_eq!(
+            db_config.merkle_tree.stall_threshold(),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            db_config.merkle_tree.health_details_format,
+            TreeHealthDetailsFormat::Flat
+        );
+        assert_eq!(db_config.merkle_tree.l1_batch_notifications_capacity, 64);
+        assert_eq!(
+            db_config.merkle_tree.watched_keys,
+            [H256::from_low_u64_be(1), H256::from_low_u64_be(2)]
+        );
+        assert_eq!(db_config.merkle_tree.excessive_protective_reads_ratio, 50.0);
+        assert_eq!(db_config.merkle_tree.min_checked_protective_reads, 1_000);
         assert_eq!(db_config.backup_count, 5);
         assert_eq!(db_config.backup_interval().as_secs(), 60);
     }
@@ -179,6 +832,57 @@ mod tests {
             "DATABASE_MERKLE_TREE_MULTI_GET_CHUNK_SIZE",
             "DATABASE_MERKLE_TREE_BLOCK_CACHE_SIZE_MB",
             "DATABASE_MERKLE_TREE_MAX_L1_BATCHES_PER_ITER",
+            "DATABASE_MERKLE_TREE_METRICS_REFRESH_INTERVAL_MS",
+            "DATABASE_MERKLE_TREE_VALIDATE_INITIAL_WRITES",
+            "DATABASE_MERKLE_TREE_AUTO_REBUILD_ON_CORRUPTION",
+            "DATABASE_MERKLE_TREE_ROOT_HASH_CACHE_SIZE",
+            "DATABASE_MERKLE_TREE_ROOT_WEBHOOK_URL",
+            "DATABASE_MERKLE_TREE_MAX_IN_MEMORY_WITNESS_SIZE_MB",
+            "DATABASE_MERKLE_TREE_MAX_IN_MEMORY_SLOTS",
+            "DATABASE_MERKLE_TREE_WITNESS_COMPRESSION_LEVEL",
+            "DATABASE_MERKLE_TREE_HEALTH_AFFECTED_LAG",
+            "DATABASE_MERKLE_TREE_WITNESS_SPILL_COMPRESSION_LEVEL",
+            "DATABASE_MERKLE_TREE_HEALTH_NOT_READY_LAG",
+            "DATABASE_MERKLE_TREE_GENERATE_WITNESS_INPUTS",
+            "DATABASE_MERKLE_TREE_VALIDATE_REPEATED_WRITES",
+            "DATABASE_MERKLE_TREE_MAX_SUPPORTED_PROTOCOL_VERSION",
+            "DATABASE_MERKLE_TREE_WITNESS_UPLOAD_QUEUE_PATH",
+            "DATABASE_MERKLE_TREE_WITNESS_UPLOAD_QUEUE_MAX_SIZE_MB",
+            "DATABASE_MERKLE_TREE_HOT_KEYS_REPORT_TOP_N",
+            "DATABASE_MERKLE_TREE_HOT_KEYS_REPORT_WINDOW",
+            "DATABASE_MERKLE_TREE_MAX_PENDING_WITNESSES",
+            "DATABASE_MERKLE_TREE_WITNESS_PERSISTENCE_CONCURRENCY",
+            "DATABASE_MERKLE_TREE_ROCKSDB_RATE_LIMIT_BYTES_PER_SEC",
+            "DATABASE_MERKLE_TREE_WITNESS_CHUNKING_THRESHOLD_MB",
+            "DATABASE_MERKLE_TREE_WITNESS_CHUNK_SIZE",
+            "DATABASE_MERKLE_TREE_DEDUPLICATE_WITNESS_PATHS",
+            "DATABASE_MERKLE_TREE_RECORD_WITNESS_PROTOCOL_VERSION",
+            "DATABASE_MERKLE_TREE_DB_CONNECTION_MAX_ATTEMPTS",
+            "DATABASE_MERKLE_TREE_DB_CONNECTION_BACKOFF_MS",
+            "DATABASE_MERKLE_TREE_OBJECT_STORE_MAX_ATTEMPTS",
+            "DATABASE_MERKLE_TREE_OBJECT_STORE_BACKOFF_MS",
+            "DATABASE_MERKLE_TREE_OBJECT_STORE_REQUEST_TIMEOUT_MS",
+            "DATABASE_MERKLE_TREE_OBJECT_STORE_FAILURE_THRESHOLD",
+            "DATABASE_MERKLE_TREE_LOGS_VERIFICATION_MODULUS",
+            "DATABASE_MERKLE_TREE_LOGS_VERIFICATION_CACHE_CAPACITY",
+            "DATABASE_MERKLE_TREE_SAVE_LATENCY_SLO_MS",
+            "DATABASE_MERKLE_TREE_SAVE_LATENCY_SLO_WINDOW",
+            "DATABASE_MERKLE_TREE_SAVE_LATENCY_SLO_MIN_COMPLIANCE",
+            "DATABASE_MERKLE_TREE_LOAD_FROM_REPLICA",
+            "DATABASE_MERKLE_TREE_REPLICA_MAX_REPLICATION_LAG_MS",
+            "DATABASE_MERKLE_TREE_POSTGRES_SCHEMA",
+            "DATABASE_MERKLE_TREE_ENUMERATION_INDEX_CHECK",
+            "DATABASE_MERKLE_TREE_PROGRESS_CHECK",
+            "DATABASE_MERKLE_TREE_DAL_CONSISTENCY_POLICY",
+            "DATABASE_MERKLE_TREE_EMPTY_L1_BATCH_POLICY",
+            "DATABASE_MERKLE_TREE_EMPTY_L1_BATCH_MAX_RETRIES",
+            "DATABASE_MERKLE_TREE_EMPTY_L1_BATCH_RETRY_INTERVAL_MS",
+            "DATABASE_MERKLE_TREE_STALL_THRESHOLD_MS",
+            "DATABASE_MERKLE_TREE_HEALTH_DETAILS_FORMAT",
+            "DATABASE_MERKLE_TREE_L1_BATCH_NOTIFICATIONS_CAPACITY",
+            "DATABASE_MERKLE_TREE_WATCHED_KEYS",
+            "DATABASE_MERKLE_TREE_EXCESSIVE_PROTECTIVE_READS_RATIO",
+            "DATABASE_MERKLE_TREE_MIN_CHECKED_PROTECTIVE_READS",
             "DATABASE_BACKUP_COUNT",
             "DATABASE_BACKUP_INTERVAL_MS",
         ]);
@@ -191,6 +895,87 @@ mod tests {
         assert_eq!(db_config.merkle_tree.multi_get_chunk_size, 500);
         assert_eq!(db_config.merkle_tree.max_l1_batches_per_iter, 20);
         assert_eq!(db_config.merkle_tree.block_cache_size_mb, 128);
+        assert_eq!(db_config.merkle_tree.metrics_refresh_interval_ms, 10_000);
+        assert!(!db_config.merkle_tree.validate_initial_writes);
+        assert!(!db_config.merkle_tree.auto_rebuild_on_corruption);
+        assert_eq!(db_config.merkle_tree.root_hash_cache_size, 16);
+        assert_eq!(db_config.merkle_tree.root_webhook_url, None);
+        assert_eq!(db_config.merkle_tree.max_in_memory_witness_size_mb, 512);
+        assert_eq!(db_config.merkle_tree.max_in_memory_slots, 50_000_000);
+        assert_eq!(db_config.merkle_tree.witness_compression_level, 3);
+        assert_eq!(db_config.merkle_tree.health_affected_lag, None);
+        assert_eq!(db_config.merkle_tree.witness_spill_compression_level, None);
+        assert_eq!(db_config.merkle_tree.health_not_ready_lag, None);
+        assert!(db_config.merkle_tree.generate_witness_inputs);
+        assert!(!db_config.merkle_tree.validate_repeated_writes);
+        assert_eq!(db_config.merkle_tree.max_supported_protocol_version, None);
+        assert_eq!(db_config.merkle_tree.witness_upload_queue_path, None);
+        assert_eq!(
+            db_config.merkle_tree.witness_upload_queue_max_size_mb,
+            4_096
+        );
+        assert_eq!(db_config.merkle_tree.hot_keys_report_top_n, None);
+        assert_eq!(db_config.merkle_tree.hot_keys_report_window, 100);
+        assert_eq!(db_config.merkle_tree.max_pending_witnesses, 4);
+        assert_eq!(db_config.merkle_tree.witness_persistence_concurrency, 2);
+        assert_eq!(db_config.merkle_tree.rocksdb_rate_limit_bytes_per_sec, None);
+        assert_eq!(db_config.merkle_tree.witness_chunking_threshold_mb, 2_048);
+        assert_eq!(db_config.merkle_tree.witness_chunk_size, 50_000);
+        assert!(!db_config.merkle_tree.deduplicate_witness_paths);
+        assert!(!db_config.merkle_tree.record_witness_protocol_version);
+        assert_eq!(db_config.merkle_tree.db_connection_max_attempts, 10);
+        assert_eq!(db_config.merkle_tree.db_connection_backoff_ms, 500);
+        assert_eq!(db_config.merkle_tree.object_store_max_attempts, 5);
+        assert_eq!(db_config.merkle_tree.object_store_backoff_ms, 1_000);
+        assert_eq!(
+            db_config.merkle_tree.object_store_request_timeout_ms,
+            300_000
+        );
+        assert_eq!(db_config.merkle_tree.object_store_failure_threshold, 3);
+        assert_eq!(db_config.merkle_tree.logs_verification_modulus, None);
+        assert_eq!(
+            db_config.merkle_tree.logs_verification_cache_capacity,
+            100_000
+        );
+        assert_eq!(db_config.merkle_tree.save_latency_slo_ms, None);
+        assert_eq!(db_config.merkle_tree.save_latency_slo_window, 100);
+        assert_eq!(db_config.merkle_tree.save_latency_slo_min_compliance, 0.9);
+        assert!(!db_config.merkle_tree.load_from_replica);
+        assert_eq!(db_config.merkle_tree.replica_max_replication_lag_ms, None);
+        assert_eq!(db_config.merkle_tree.postgres_schema, None);
+        assert_eq!(
+            db_config.merkle_tree.enumeration_index_check,
+            EnumerationIndexCheck::Error
+        );
+        assert_eq!(
+            db_config.merkle_tree.progress_check,
+            EnumerationIndexCheck::Error
+        );
+        assert_eq!(
+            db_config.merkle_tree.dal_consistency_policy,
+            DalConsistencyPolicy::FailFast
+        );
+        assert_eq!(
+            db_config.merkle_tree.empty_l1_batch_policy,
+            EmptyL1BatchPolicy::Accept
+        );
+        assert_eq!(db_config.merkle_tree.empty_l1_batch_max_retries, 5);
+        assert_eq!(
+            db_config.merkle_tree.empty_l1_batch_retry_interval_ms,
+            1_000
+        );
+        assert_eq!(db_config.merkle_tree.stall_threshold_ms, None);
+        assert_eq!(
+            db_config.merkle_tree.health_details_format,
+            TreeHealthDetailsFormat::Json
+        );
+        assert_eq!(db_config.merkle_tree.l1_batch_notifications_capacity, 1_024);
+        assert!(db_config.merkle_tree.watched_keys.is_empty());
+        assert_eq!(
+            db_config.merkle_tree.excessive_protective_reads_ratio,
+            100.0
+        );
+        assert_eq!(db_config.merkle_tree.min_checked_protective_reads, 100);
         assert_eq!(db_config.backup_count, 5);
         assert_eq!(db_config.backup_interval().as_secs(), 60);
 
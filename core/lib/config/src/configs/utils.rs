@@ -12,6 +12,10 @@ pub struct PrometheusConfig {
     pub pushgateway_url: String,
     /// Push interval in ms.
     pub push_interval_ms: Option<u64>,
+    /// Overrides buckets (in seconds) for latency histograms of Merkle tree update stages.
+    /// If not set, built-in buckets spanning 1ms to 10min are used.
+    #[serde(default)]
+    pub merkle_tree_stage_latency_buckets: Option<Vec<f64>>,
 }
 
 impl PrometheusConfig {
@@ -0,0 +1,3 @@
+ALTER TABLE l1_batches ADD COLUMN IF NOT EXISTS tree_processed_at TIMESTAMP;
+-- Batches that already have tree data are considered processed.
+UPDATE l1_batches SET tree_processed_at = updated_at WHERE hash IS NOT NULL AND commitment IS NOT NULL;
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS witness_input_artifacts (
+    l1_batch_number BIGINT PRIMARY KEY REFERENCES l1_batches (number) ON DELETE CASCADE,
+    object_key TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    hash BYTEA NOT NULL,
+    uploaded_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Single-row table with the fingerprint of genesis parameters recorded at the first initialization.
+CREATE TABLE IF NOT EXISTS genesis_fingerprint (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    fingerprint BYTEA NOT NULL,
+    details JSONB NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE genesis_fingerprint DROP COLUMN IF EXISTS root_hash;
@@ -0,0 +1,3 @@
+-- Expected genesis root hash recorded at the first initialization. Nullable for fingerprints persisted
+-- before this column was added; it is backfilled on the next genesis check.
+ALTER TABLE genesis_fingerprint ADD COLUMN IF NOT EXISTS root_hash BYTEA;
@@ -0,0 +1,10 @@
+-- Witness input objects uploaded for L1 batches that were reverted. Unlike `witness_input_artifacts`, rows
+-- are not bound to L1 batches, so that they survive the revert; a row is removed once its object is removed
+-- from the object store.
+CREATE TABLE IF NOT EXISTS stale_witness_artifacts (
+    object_key TEXT PRIMARY KEY,
+    l1_batch_number BIGINT NOT NULL,
+    removal_attempts INT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
@@ -11,7 +11,7 @@ use sqlx::Row;
 use zksync_types::{
     aggregated_operations::AggregatedActionType,
     block::{BlockGasCount, L1BatchHeader, MiniblockHeader},
-    commitment::{L1BatchMetadata, L1BatchWithMetadata},
+    commitment::{L1BatchMetadata, L1BatchWithMetadata, TreeData},
     L1BatchNumber, MiniblockNumber, ProtocolVersionId, H256, MAX_GAS_PER_PUBDATA_BYTE, U256,
 };
 
@@ -21,6 +21,22 @@ use crate::{
     StorageProcessor,
 };
 
+/// Error returned when saving tree data for an L1 batch that already has different tree data persisted.
+/// This means that tree instances writing to the same Postgres have diverged.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Tree data for L1 batch #{l1_batch_number} diverges from the already saved data \
+     (stored: {stored:?}, new: {new:?})"
+)]
+pub struct TreeDataDivergence {
+    /// Number of the L1 batch.
+    pub l1_batch_number: L1BatchNumber,
+    /// Tree data stored in Postgres.
+    pub stored: TreeData,
+    /// Tree data that was attempted to be saved.
+    pub new: TreeData,
+}
+
 #[derive(Debug)]
 pub struct BlocksDal<'a, 'c> {
     pub(crate) storage: &'a mut StorageProcessor<'c>,
@@ -35,6 +51,49 @@ impl BlocksDal<'_, '_> {
         Ok(count == 0)
     }
 
+    /// Saves the fingerprint of genesis parameters together with its components and the expected
+    /// genesis root hash. Overwrites
+    /// the previously saved fingerprint, if any.
+    pub async fn save_genesis_fingerprint(
+        &mut self,
+        fingerprint: H256,
+        root_hash: H256,
+        details: &serde_json::Value,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO genesis_fingerprint (id, fingerprint, root_hash, details, created_at, updated_at) \
+             VALUES (TRUE, $1, $2, $3, now(), now()) \
+             ON CONFLICT (id) DO UPDATE SET fingerprint = $1, root_hash = $2, details = $3, updated_at = now()",
+            fingerprint.as_bytes(),
+            root_hash.as_bytes(),
+            details
+        )
+        .execute(self.storage.conn())
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the fingerprint of genesis parameters together with its components, or `None`
+    /// if the fingerprint was never saved.
+    pub async fn get_genesis_fingerprint(
+        &mut self,
+    ) -> sqlx::Result<Option<(H256, serde_json::Value)>> {
+        let row = sqlx::query!("SELECT fingerprint, details FROM genesis_fingerprint WHERE id")
+            .fetch_optional(self.storage.conn())
+            .await?;
+        Ok(row.map(|row| (H256::from_slice(&row.fingerprint), row.details)))
+    }
+
+    /// Returns the genesis root hash expected at the first initialization, or `None` if it was never saved.
+    pub async fn get_expected_genesis_root_hash(&mut self) -> sqlx::Result<Option<H256>> {
+        let row = sqlx::query!("SELECT root_hash FROM genesis_fingerprint WHERE id")
+            .fetch_optional(self.storage.conn())
+            .await?;
+        Ok(row
+            .and_then(|row| row.root_hash)
+            .map(|hash| H256::from_slice(&hash)))
+    }
+
     pub async fn get_miniblock_hashes_from_date(
         &mut self,
         timestamp: u64,
@@ -118,6 +177,29 @@ impl BlocksDal<'_, '_> {
         Ok(L1BatchNumber(number as u32))
     }
 
+    /// Returns numbers of sealed L1 batches in the specified range in the ascending order.
+    pub async fn get_sealed_l1_batch_numbers(
+        &mut self,
+        number_range: ops::RangeInclusive<L1BatchNumber>,
+    ) -> sqlx::Result<Vec<L1BatchNumber>> {
+        let rows = sqlx::query!(
+            "SELECT number FROM l1_batches \
+             WHERE number BETWEEN $1 AND $2 AND is_finished = TRUE \
+             ORDER BY number",
+            number_range.start().0 as i64,
+            number_range.end().0 as i64
+        )
+        .instrument("get_sealed_l1_batch_numbers")
+        .with_arg("number_range", &number_range)
+        .fetch_all(self.storage.conn())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| L1BatchNumber(row.number as u32))
+            .collect())
+    }
+
     pub async fn get_sealed_miniblock_number(&mut self) -> sqlx::Result<MiniblockNumber> {
         let number: i64 = sqlx::query!("SELECT MAX(number) as \"number\" FROM miniblocks")
             .instrument("get_sealed_miniblock_number")
@@ -143,6 +225,60 @@ impl BlocksDal<'_, '_> {
         Ok(L1BatchNumber(number as u32))
     }
 
+    /// Returns the number of the last L1 batch with tree data (i.e., the root hash) in Postgres,
+    /// together with its root hash and the enumeration index of the last leaf in the tree after
+    /// processing this L1 batch. Returns `None` if there are no L1 batches with tree data (e.g.,
+    /// before genesis). Since all values are obtained in a single query, they are consistent
+    /// with each other.
+    pub async fn get_last_l1_batch_with_tree_data(
+        &mut self,
+    ) -> sqlx::Result<Option<(L1BatchNumber, H256, u64)>> {
+        let row = sqlx::query!(
+            "SELECT number, hash AS \"hash!\", rollup_last_leaf_index AS \"rollup_last_leaf_index!\" \
+            FROM l1_batches \
+            WHERE hash IS NOT NULL AND rollup_last_leaf_index IS NOT NULL \
+            ORDER BY number DESC LIMIT 1"
+        )
+        .instrument("get_last_l1_batch_with_tree_data")
+        .report_latency()
+        .fetch_optional(self.storage.conn())
+        .await?;
+
+        Ok(row.map(|row| {
+            (
+                L1BatchNumber(row.number as u32),
+                H256::from_slice(&row.hash),
+                row.rollup_last_leaf_index as u64,
+            )
+        }))
+    }
+
+    /// Returns the root hash and the last leaf index stored for the specified L1 batch, or `None`
+    /// if the L1 batch is not yet processed by the tree.
+    pub async fn get_l1_batch_tree_data(
+        &mut self,
+        number: L1BatchNumber,
+    ) -> sqlx::Result<Option<(H256, u64)>> {
+        let row = sqlx::query!(
+            "SELECT hash AS \"hash!\", rollup_last_leaf_index AS \"rollup_last_leaf_index!\" \
+            FROM l1_batches \
+            WHERE number = $1 AND hash IS NOT NULL AND rollup_last_leaf_index IS NOT NULL",
+            number.0 as i64
+        )
+        .instrument("get_l1_batch_tree_data")
+        .with_arg("number", &number)
+        .report_latency()
+        .fetch_optional(self.storage.conn())
+        .await?;
+
+        Ok(row.map(|row| {
+            (
+                H256::from_slice(&row.hash),
+                row.rollup_last_leaf_index as u64,
+            )
+        }))
+    }
+
     pub async fn get_l1_batches_for_eth_tx_id(
         &mut self,
         eth_tx_id: u32,
@@ -215,6 +351,42 @@ impl BlocksDal<'_, '_> {
         .map(Into::into))
     }
 
+    /// Returns headers for the specified range of L1 batches ordered by L1 batch number.
+    /// The `i`th element of the returned vector corresponds to L1 batch `number_range.start() + i`;
+    /// `None` elements indicate gaps (L1 batches missing in the DB). L1 batches after the last L1 batch
+    /// present in the DB are not included, i.e., the returned vector may be shorter than the range.
+    pub async fn get_l1_batch_headers(
+        &mut self,
+        number_range: ops::RangeInclusive<L1BatchNumber>,
+    ) -> sqlx::Result<Vec<Option<L1BatchHeader>>> {
+        let rows = sqlx::query_as!(
+            StorageL1BatchHeader,
+            "SELECT number, l1_tx_count, l2_tx_count, \
+                timestamp, is_finished, fee_account_address, l2_to_l1_logs, l2_to_l1_messages, \
+                bloom, priority_ops_onchain_data, \
+                used_contract_hashes, base_fee_per_gas, l1_gas_price, \
+                l2_fair_gas_price, bootloader_code_hash, default_aa_code_hash, protocol_version \
+            FROM l1_batches \
+            WHERE number BETWEEN $1 AND $2 \
+            ORDER BY number",
+            number_range.start().0 as i64,
+            number_range.end().0 as i64
+        )
+        .instrument("get_l1_batch_headers")
+        .with_arg("number_range", &number_range)
+        .fetch_all(self.storage.conn())
+        .await?;
+
+        let mut headers = Vec::with_capacity(rows.len());
+        for row in rows {
+            let header: L1BatchHeader = row.into();
+            let idx = (header.number.0 - number_range.start().0) as usize;
+            headers.resize(idx, None);
+            headers.push(Some(header));
+        }
+        Ok(headers)
+    }
+
     /// Returns initial bootloader heap content for the specified L1 batch.
     pub async fn get_initial_bootloader_heap(
         &mut self,
@@ -479,7 +651,7 @@ impl BlocksDal<'_, '_> {
                 l2_l1_compressed_messages = $7, l2_l1_merkle_root = $8, \
                 zkporter_is_available = $9, bootloader_code_hash = $10, rollup_last_leaf_index = $11, \
                 aux_data_hash = $12, pass_through_data_hash = $13, meta_parameters_hash = $14, \
-                updated_at = now() \
+                tree_processed_at = now(), updated_at = now() \
             WHERE number = $15",
             metadata.root_hash.as_bytes(),
             metadata.merkle_root_hash.as_bytes(),
@@ -502,12 +674,22 @@ impl BlocksDal<'_, '_> {
         Ok(())
     }
 
-    pub async fn save_l1_batch_metadata(
+    /// Saves all data produced by the Merkle tree for the specified L1 batch and marks the batch
+    /// as processed by the tree. All tree-derived columns are written in a single statement, so other
+    /// components never observe partially saved tree data.
+    ///
+    /// This method is idempotent: if the L1 batch is already processed, it does not overwrite the saved data;
+    /// instead, it checks that the saved data is equal to the provided one. If the data differs,
+    /// returns a [`TreeDataDivergence`] error.
+    pub async fn save_l1_batch_tree_data(
         &mut self,
         number: L1BatchNumber,
-        metadata: &L1BatchMetadata,
-        previous_root_hash: H256,
+        tree_data: &TreeData,
     ) -> anyhow::Result<()> {
+        let TreeData {
+            metadata,
+            parent_hash: previous_root_hash,
+        } = tree_data;
         let update_result = sqlx::query!(
             "UPDATE l1_batches \
             SET hash = $1, merkle_root_hash = $2, commitment = $3, \
@@ -515,8 +697,8 @@ impl BlocksDal<'_, '_> {
                 l2_l1_compressed_messages = $6, l2_l1_merkle_root = $7, \
                 zkporter_is_available = $8, parent_hash = $9, rollup_last_leaf_index = $10, \
                 aux_data_hash = $11, pass_through_data_hash = $12, meta_parameters_hash = $13, \
-                updated_at = now() \
-            WHERE number = $14 AND hash IS NULL",
+                tree_processed_at = now(), updated_at = now() \
+            WHERE number = $14 AND tree_processed_at IS NULL",
             metadata.root_hash.as_bytes(),
             metadata.merkle_root_hash.as_bytes(),
             metadata.commitment.as_bytes(),
@@ -532,49 +714,42 @@ impl BlocksDal<'_, '_> {
             metadata.meta_parameters_hash.as_bytes(),
             number.0 as i64,
         )
-        .instrument("save_blocks_metadata")
+        .instrument("save_l1_batch_tree_data")
         .with_arg("number", &number)
         .report_latency()
         .execute(self.storage.conn())
         .await?;
 
         if update_result.rows_affected() == 0 {
+            // The L1 batch is either missing or already processed (e.g., if the tree has crashed after saving
+            // tree data, but before persisting its own state). In the latter case, the replay is benign
+            // if and only if it writes exactly the same data.
+            let stored_l1_batch = self
+                .get_storage_l1_batch(number)
+                .await
+                .context("get_storage_l1_batch()")?
+                .with_context(|| format!("L1 batch #{number} is not in the storage"))?;
+            let stored_parent_hash = stored_l1_batch.parent_hash.as_deref().map(H256::from_slice);
+            let stored_metadata: Result<L1BatchMetadata, _> = stored_l1_batch.try_into();
+            let (Some(parent_hash), Ok(metadata)) = (stored_parent_hash, stored_metadata) else {
+                anyhow::bail!(
+                    "Tree data for L1 batch #{number} is marked as saved, but is incomplete"
+                );
+            };
+            let stored = TreeData {
+                metadata,
+                parent_hash,
+            };
+            if stored != *tree_data {
+                return Err(TreeDataDivergence {
+                    l1_batch_number: number,
+                    stored,
+                    new: tree_data.clone(),
+                }
+                .into());
+            }
             tracing::debug!(
-                "L1 batch {} info wasn't updated. Details: root_hash: {:?}, merkle_root_hash: {:?}, \
-                 parent_hash: {:?}, commitment: {:?}, l2_l1_merkle_root: {:?}",
-                number.0 as i64,
-                metadata.root_hash,
-                metadata.merkle_root_hash,
-                previous_root_hash,
-                metadata.commitment,
-                metadata.l2_l1_merkle_root
-            );
-
-            // block was already processed. Verify that existing hashes match
-            let matched: i64 = sqlx::query!(
-                "SELECT COUNT(*) as \"count!\" \
-                FROM l1_batches \
-                WHERE number = $1 AND hash = $2 AND merkle_root_hash = $3 \
-                   AND parent_hash = $4 AND l2_l1_merkle_root = $5",
-                number.0 as i64,
-                metadata.root_hash.as_bytes(),
-                metadata.merkle_root_hash.as_bytes(),
-                previous_root_hash.as_bytes(),
-                metadata.l2_l1_merkle_root.as_bytes(),
-            )
-            .instrument("get_matching_blocks_metadata")
-            .with_arg("number", &number)
-            .report_latency()
-            .fetch_one(self.storage.conn())
-            .await?
-            .count;
-
-            anyhow::ensure!(matched == 1,
-                "Root hash verification failed. Hashes for L1 batch #{} do not match the expected values \
-                 (expected state hash: {:?}, L2 to L1 logs hash: {:?})",
-                number,
-                metadata.root_hash,
-                metadata.l2_l1_merkle_root
+                "Tree data for L1 batch #{number} is already saved and matches the provided data"
             );
         }
         Ok(())
@@ -912,7 +1087,7 @@ impl BlocksDal<'_, '_> {
             WHERE eth_commit_tx_id IS NULL \
                 AND number != 0 \
                 AND protocol_versions.bootloader_code_hash = $1 AND protocol_versions.default_account_code_hash = $2 \
-                AND commitment IS NOT NULL \
+                AND tree_processed_at IS NOT NULL \
                 AND (protocol_versions.id = $3 OR protocol_versions.upgrade_tx_hash IS NULL) \
             ORDER BY number LIMIT $4",
             bootloader_hash.as_bytes(),
@@ -1361,7 +1536,10 @@ impl BlocksDal<'_, '_> {
 mod tests {
     use db_test_macro::db_test;
     use zksync_contracts::BaseSystemContractsHashes;
-    use zksync_types::{l2_to_l1_log::L2ToL1Log, Address, ProtocolVersion, ProtocolVersionId};
+    use zksync_types::{
+        commitment::L1BatchMetaParameters, l2_to_l1_log::L2ToL1Log, Address, ProtocolVersion,
+        ProtocolVersionId,
+    };
 
     use super::*;
     use crate::ConnectionPool;
@@ -1426,6 +1604,85 @@ mod tests {
             .is_none());
     }
 
+    #[db_test(dal_crate)]
+    async fn loading_l1_batch_headers_range(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+
+        // Insert L1 batches #1, #2 and #4, leaving a gap at #3.
+        for number in [1, 2, 4] {
+            let header = L1BatchHeader::new(
+                L1BatchNumber(number),
+                100 + u64::from(number),
+                Address::default(),
+                BaseSystemContractsHashes::default(),
+                ProtocolVersionId::latest(),
+            );
+            conn.blocks_dal()
+                .insert_l1_batch(&header, &[], BlockGasCount::default())
+                .await
+                .unwrap();
+        }
+
+        let headers = conn
+            .blocks_dal()
+            .get_l1_batch_headers(L1BatchNumber(1)..=L1BatchNumber(4))
+            .await
+            .unwrap();
+        let numbers: Vec<_> = headers
+            .iter()
+            .map(|header| header.as_ref().map(|header| header.number))
+            .collect();
+        assert_eq!(
+            numbers,
+            [
+                Some(L1BatchNumber(1)),
+                Some(L1BatchNumber(2)),
+                None,
+                Some(L1BatchNumber(4))
+            ]
+        );
+        assert_eq!(headers[0].as_ref().unwrap().timestamp, 101);
+
+        // The range extends past the last L1 batch in the DB.
+        let headers = conn
+            .blocks_dal()
+            .get_l1_batch_headers(L1BatchNumber(2)..=L1BatchNumber(10))
+            .await
+            .unwrap();
+        let numbers: Vec<_> = headers
+            .iter()
+            .map(|header| header.as_ref().map(|header| header.number))
+            .collect();
+        assert_eq!(
+            numbers,
+            [Some(L1BatchNumber(2)), None, Some(L1BatchNumber(4))]
+        );
+
+        // The range lies completely past the last L1 batch.
+        let headers = conn
+            .blocks_dal()
+            .get_l1_batch_headers(L1BatchNumber(5)..=L1BatchNumber(10))
+            .await
+            .unwrap();
+        assert!(headers.is_empty());
+
+        // The range starts before the first L1 batch.
+        let headers = conn
+            .blocks_dal()
+            .get_l1_batch_headers(L1BatchNumber(0)..=L1BatchNumber(1))
+            .await
+            .unwrap();
+        assert!(headers[0].is_none());
+        assert_eq!(headers[1].as_ref().unwrap().number, L1BatchNumber(1));
+    }
+
     #[db_test(dal_crate)]
     async fn getting_predicted_gas(pool: ConnectionPool) {
         let mut conn = pool.access_storage().await.unwrap();
@@ -1489,4 +1746,240 @@ mod tests {
             assert_eq!(gas, 3 * expected_gas);
         }
     }
+
+    async fn is_tree_processed(conn: &mut StorageProcessor<'_>, number: L1BatchNumber) -> bool {
+        sqlx::query_scalar::<_, bool>(
+            "SELECT tree_processed_at IS NOT NULL FROM l1_batches WHERE number = $1",
+        )
+        .bind(number.0 as i64)
+        .fetch_one(conn.conn())
+        .await
+        .unwrap()
+    }
+
+    fn mock_tree_data() -> TreeData {
+        TreeData {
+            metadata: L1BatchMetadata {
+                root_hash: H256::repeat_byte(1),
+                rollup_last_leaf_index: 42,
+                merkle_root_hash: H256::repeat_byte(1),
+                initial_writes_compressed: vec![1; 10],
+                repeated_writes_compressed: vec![2; 5],
+                commitment: H256::repeat_byte(2),
+                l2_l1_messages_compressed: vec![],
+                l2_l1_merkle_root: H256::repeat_byte(3),
+                block_meta_params: L1BatchMetaParameters {
+                    zkporter_is_available: false,
+                    bootloader_code_hash: H256::zero(),
+                    default_aa_code_hash: H256::zero(),
+                },
+                aux_data_hash: H256::repeat_byte(4),
+                meta_parameters_hash: H256::repeat_byte(5),
+                pass_through_data_hash: H256::repeat_byte(6),
+            },
+            parent_hash: H256::repeat_byte(7),
+        }
+    }
+
+    #[db_test(dal_crate)]
+    async fn saving_l1_batch_tree_data(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+        let header = L1BatchHeader::new(
+            L1BatchNumber(1),
+            100,
+            Address::default(),
+            BaseSystemContractsHashes::default(),
+            ProtocolVersionId::default(),
+        );
+        conn.blocks_dal()
+            .insert_l1_batch(&header, &[], BlockGasCount::default())
+            .await
+            .unwrap();
+        assert!(!is_tree_processed(&mut conn, L1BatchNumber(1)).await);
+        assert!(conn
+            .blocks_dal()
+            .get_l1_batch_metadata(L1BatchNumber(1))
+            .await
+            .unwrap()
+            .is_none());
+
+        let mut tree_data = mock_tree_data();
+        conn.blocks_dal()
+            .save_l1_batch_tree_data(L1BatchNumber(1), &tree_data)
+            .await
+            .unwrap();
+        assert!(is_tree_processed(&mut conn, L1BatchNumber(1)).await);
+        let l1_batch = conn
+            .blocks_dal()
+            .get_l1_batch_metadata(L1BatchNumber(1))
+            .await
+            .unwrap()
+            .expect("no metadata for L1 batch #1");
+        assert_eq!(l1_batch.metadata, tree_data.metadata);
+
+        // Saving the same data again is a no-op.
+        conn.blocks_dal()
+            .save_l1_batch_tree_data(L1BatchNumber(1), &tree_data)
+            .await
+            .unwrap();
+        // Saving diverging data is an error, and it doesn't overwrite the saved data.
+        tree_data.metadata.root_hash = H256::repeat_byte(0xff);
+        conn.blocks_dal()
+            .save_l1_batch_tree_data(L1BatchNumber(1), &tree_data)
+            .await
+            .unwrap_err();
+        let l1_batch = conn
+            .blocks_dal()
+            .get_l1_batch_metadata(L1BatchNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(l1_batch.metadata.root_hash, H256::repeat_byte(1));
+    }
+
+    async fn insert_l1_batch_without_tree_data(conn: &mut StorageProcessor<'_>) {
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+        let header = L1BatchHeader::new(
+            L1BatchNumber(1),
+            100,
+            Address::default(),
+            BaseSystemContractsHashes::default(),
+            ProtocolVersionId::default(),
+        );
+        conn.blocks_dal()
+            .insert_l1_batch(&header, &[], BlockGasCount::default())
+            .await
+            .unwrap();
+    }
+
+    #[db_test(dal_crate)]
+    async fn replaying_identical_l1_batch_tree_data(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        insert_l1_batch_without_tree_data(&mut conn).await;
+        let tree_data = mock_tree_data();
+        for _ in 0..3 {
+            conn.blocks_dal()
+                .save_l1_batch_tree_data(L1BatchNumber(1), &tree_data)
+                .await
+                .unwrap();
+        }
+
+        let l1_batch = conn
+            .blocks_dal()
+            .get_l1_batch_metadata(L1BatchNumber(1))
+            .await
+            .unwrap()
+            .expect("no metadata for L1 batch #1");
+        assert_eq!(l1_batch.metadata, tree_data.metadata);
+    }
+
+    #[db_test(dal_crate)]
+    async fn replaying_conflicting_l1_batch_tree_data(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        insert_l1_batch_without_tree_data(&mut conn).await;
+        let tree_data = mock_tree_data();
+        conn.blocks_dal()
+            .save_l1_batch_tree_data(L1BatchNumber(1), &tree_data)
+            .await
+            .unwrap();
+
+        // Values not covered by the root hash must be checked as well.
+        let mut conflicting_tree_data = tree_data.clone();
+        conflicting_tree_data.metadata.initial_writes_compressed = vec![3; 10];
+        let mut conflicting_parent = tree_data.clone();
+        conflicting_parent.parent_hash = H256::repeat_byte(0xff);
+
+        for new_tree_data in [conflicting_tree_data, conflicting_parent] {
+            let err = conn
+                .blocks_dal()
+                .save_l1_batch_tree_data(L1BatchNumber(1), &new_tree_data)
+                .await
+                .unwrap_err();
+            let err = err
+                .downcast_ref::<TreeDataDivergence>()
+                .unwrap_or_else(|| panic!("unexpected error: {err:?}"));
+            assert_eq!(err.l1_batch_number, L1BatchNumber(1));
+            assert_eq!(err.stored, tree_data);
+            assert_eq!(err.new, new_tree_data);
+        }
+
+        let l1_batch = conn
+            .blocks_dal()
+            .get_l1_batch_metadata(L1BatchNumber(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(l1_batch.metadata, tree_data.metadata);
+    }
+
+    #[db_test(dal_crate)]
+    async fn getting_last_l1_batch_with_tree_data(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+        let last_l1_batch = conn
+            .blocks_dal()
+            .get_last_l1_batch_with_tree_data()
+            .await
+            .unwrap();
+        assert_eq!(last_l1_batch, None);
+
+        for number in [1, 2] {
+            let header = L1BatchHeader::new(
+                L1BatchNumber(number),
+                100,
+                Address::default(),
+                BaseSystemContractsHashes::default(),
+                ProtocolVersionId::default(),
+            );
+            conn.blocks_dal()
+                .insert_l1_batch(&header, &[], BlockGasCount::default())
+                .await
+                .unwrap();
+        }
+        let last_l1_batch = conn
+            .blocks_dal()
+            .get_last_l1_batch_with_tree_data()
+            .await
+            .unwrap();
+        assert_eq!(last_l1_batch, None);
+
+        // Only L1 batch #1 is processed by the tree.
+        let tree_data = mock_tree_data();
+        conn.blocks_dal()
+            .save_l1_batch_tree_data(L1BatchNumber(1), &tree_data)
+            .await
+            .unwrap();
+        let last_l1_batch = conn
+            .blocks_dal()
+            .get_last_l1_batch_with_tree_data()
+            .await
+            .unwrap();
+        assert_eq!(
+            last_l1_batch,
+            Some((
+                L1BatchNumber(1),
+                tree_data.metadata.root_hash,
+                tree_data.metadata.rollup_last_leaf_index
+            ))
+        );
+    }
 }
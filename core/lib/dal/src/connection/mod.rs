@@ -1,9 +1,12 @@
 use sqlx::{
     pool::PoolConnection,
     postgres::{PgConnectOptions, PgPool, PgPoolOptions, Postgres},
+    Connection as _,
 };
 
 use anyhow::Context as _;
+use thiserror::Error;
+
 use std::time::Duration;
 
 use zksync_utils::parse_env;
@@ -31,9 +34,15 @@ pub struct ConnectionPoolBuilder {
     db: DbVariant,
     max_size: Option<u32>,
     statement_timeout: Option<Duration>,
+    max_replication_lag: Option<Duration>,
+    statement_cache_capacity: Option<usize>,
+    schema: Option<String>,
 }
 
 impl ConnectionPoolBuilder {
+    /// Default capacity of the per-connection prepared statement cache (same as the `sqlx` default).
+    pub const DEFAULT_STATEMENT_CACHE_CAPACITY: usize = 100;
+
     /// Sets the maximum size of the created pool. If not specified, the max pool size will be
     /// taken from the `DATABASE_POOL_SIZE` env variable.
     pub fn set_max_size(&mut self, max_size: Option<u32>) -> &mut Self {
@@ -50,6 +59,43 @@ impl ConnectionPoolBuilder {
         self
     }
 
+    /// Sets the maximum replication lag for the pool. If specified, the replication lag is checked
+    /// each time a connection is acquired from the pool; if the lag exceeds the specified value,
+    /// a [`ReplicationLagError`] is returned instead of the connection. This only makes sense for pools
+    /// connected to a read replica; for the primary server, the lag is always zero.
+    pub fn set_max_replication_lag(&mut self, max_lag: Option<Duration>) -> &mut Self {
+        self.max_replication_lag = max_lag;
+        self
+    }
+
+    /// Sets the capacity of the prepared statement cache for each connection in the pool. Statements
+    /// executed via `sqlx` query macros are prepared once per connection and are cached (with LRU eviction)
+    /// afterwards, so the capacity should exceed the number of distinct hot queries executed on a single
+    /// connection. If not specified, [`Self::DEFAULT_STATEMENT_CACHE_CAPACITY`] is used.
+    ///
+    /// Setting the capacity to 0 disables caching, i.e., all statements are prepared each time they are executed.
+    /// This may be useful for short-lived connections (e.g., ones used for migrations) that modify the DB schema,
+    /// which may invalidate cached statements.
+    pub fn set_statement_cache_capacity(&mut self, capacity: Option<usize>) -> &mut Self {
+        self.statement_cache_capacity = capacity;
+        self
+    }
+
+    /// Sets the Postgres schema used by connections in the pool by setting their [`search_path`].
+    /// This allows to point a pool at a secondary set of DAL tables (e.g., ones for a shadow chain)
+    /// living in the same database; the schema must contain all tables used by the pool consumers.
+    /// The `public` schema is retained in the search path after `schema` since it contains custom types
+    /// used in DAL tables. If not specified, the default search path (i.e., normally the `public` schema) is used.
+    ///
+    /// The schema name must be a lowercase unquoted Postgres identifier (see [`schema_search_path()`]);
+    /// otherwise, building the pool panics.
+    ///
+    /// [`search_path`]: https://www.postgresql.org/docs/14/ddl-schemas.html#DDL-SCHEMAS-PATH
+    pub fn set_schema(&mut self, schema: Option<String>) -> &mut Self {
+        self.schema = schema;
+        self
+    }
+
     /// Builds a connection pool from this builder.
     pub async fn build(&self) -> anyhow::Result<ConnectionPool> {
         let database_url = match self.db {
@@ -73,6 +119,16 @@ impl ConnectionPoolBuilder {
             let timeout_string = format!("{}s", timeout.as_secs());
             connect_options = connect_options.options([("statement_timeout", timeout_string)]);
         }
+        if let Some(schema) = &self.schema {
+            let search_path = schema_search_path(schema).unwrap_or_else(|err| {
+                panic!("Invalid schema for {:?} database: {err}", self.db);
+            });
+            connect_options = connect_options.options([("search_path", search_path)]);
+        }
+        let statement_cache_capacity = self
+            .statement_cache_capacity
+            .unwrap_or(Self::DEFAULT_STATEMENT_CACHE_CAPACITY);
+        connect_options = connect_options.statement_cache_capacity(statement_cache_capacity);
         let pool = options
             .connect_with(connect_options)
             .await
@@ -80,18 +136,98 @@ impl ConnectionPoolBuilder {
                 panic!("Failed connecting to {:?} database: {}", self.db, err);
             });
         tracing::info!(
-            "Created pool for {db:?} database with {max_connections} max connections \
-             and {statement_timeout:?} statement timeout",
+            "Created pool for {db:?} database with {max_connections} max connections, \
+             {statement_timeout:?} statement timeout, {max_replication_lag:?} max replication lag, \
+             {statement_cache_capacity} cached statements per connection and {schema:?} schema",
             db = self.db,
-            statement_timeout = self.statement_timeout
+            statement_timeout = self.statement_timeout,
+            max_replication_lag = self.max_replication_lag,
+            schema = self.schema
         );
-        ConnectionPool::Real(pool)
+        match self.max_replication_lag {
+            Some(max_lag) => ConnectionPool::Replica(ReplicaPool { pool, max_lag }),
+            None => ConnectionPool::Real(pool),
+        }
+    }
+}
+
+/// Returns the Postgres `search_path` for connections using the specified `schema`, with `public` as a fallback.
+///
+/// # Errors
+///
+/// Returns an error if `schema` is not a valid lowercase unquoted Postgres identifier, i.e., doesn't consist
+/// of 1..=63 lowercase ASCII letters, digits and underscores, or starts with a digit. This ensures that
+/// the schema name can be safely interpolated into SQL statements and connection options.
+pub(crate) fn schema_search_path(schema: &str) -> anyhow::Result<String> {
+    let is_valid = (1..=63).contains(&schema.len())
+        && !schema.starts_with(|ch: char| ch.is_ascii_digit())
+        && schema
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_');
+    anyhow::ensure!(
+        is_valid,
+        "schema name `{schema}` is not a lowercase unquoted Postgres identifier"
+    );
+    // Connection options are whitespace-separated, so the search path must not contain whitespace.
+    Ok(format!("{schema},public"))
+}
+
+/// Error returned when acquiring a connection from a pool with replication lag checks if the replica
+/// lags behind the primary server too much. The error is transient; acquiring a connection may be retried
+/// after the replica catches up.
+#[derive(Debug, Clone, Copy, Error)]
+#[error("replication lag {lag:?} exceeds the configured maximum {max_lag:?}")]
+pub struct ReplicationLagError {
+    pub lag: Duration,
+    pub max_lag: Duration,
+}
+
+/// Connection pool for a read replica that checks replication lag before handing out connections.
+#[derive(Debug, Clone)]
+pub struct ReplicaPool {
+    pub(crate) pool: PgPool,
+    max_lag: Duration,
+}
+
+impl ReplicaPool {
+    async fn check_replication_lag(&self, conn: &mut StorageProcessor<'_>) -> anyhow::Result<()> {
+        let lag = replication_lag(conn)
+            .await
+            .context("failed getting replication lag")?;
+        CONNECTION_METRICS.replication_lag.set(lag);
+        check_replication_lag(lag, self.max_lag)?;
+        Ok(())
+    }
+}
+
+/// Returns the replication lag for the server `conn` is connected to. The lag is zero if the server
+/// is not a replica, or if the replica has replayed all WAL records received from the primary server.
+async fn replication_lag(conn: &mut StorageProcessor<'_>) -> sqlx::Result<Duration> {
+    let lag_in_seconds: f64 = sqlx::query_scalar(
+        "SELECT CASE \
+            WHEN NOT pg_is_in_recovery() THEN 0 \
+            WHEN pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0 \
+            ELSE COALESCE(EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()), 0) \
+        END::DOUBLE PRECISION",
+    )
+    .fetch_one(conn.conn())
+    .await?;
+    Ok(Duration::from_secs_f64(lag_in_seconds.max(0.0)))
+}
+
+fn check_replication_lag(lag: Duration, max_lag: Duration) -> Result<(), ReplicationLagError> {
+    if lag > max_lag {
+        CONNECTION_METRICS.replication_lag_exceeded.inc();
+        return Err(ReplicationLagError { lag, max_lag });
     }
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
 pub enum ConnectionPool {
     Real(PgPool),
+    /// Pool connected to a read replica with replication lag checks.
+    Replica(ReplicaPool),
     Test(TestPool),
 }
 
@@ -102,6 +238,9 @@ impl ConnectionPool {
             db,
             max_size: None,
             statement_timeout: None,
+            max_replication_lag: None,
+            statement_cache_capacity: None,
+            schema: None,
         }
     }
 
@@ -112,6 +251,9 @@ impl ConnectionPool {
             db,
             max_size: Some(1),
             statement_timeout: None,
+            max_replication_lag: None,
+            statement_cache_capacity: None,
+            schema: None,
         }
     }
 
@@ -123,6 +265,9 @@ impl ConnectionPool {
     ///
     /// This method is intended to be used in crucial contexts, where the
     /// database access is must-have (e.g. block committer).
+    ///
+    /// For pools with replication lag checks, returns a [`ReplicationLagError`] if the replica
+    /// lags behind too much.
     pub async fn access_storage(&self) -> anyhow::Result<StorageProcessor<'_>> {
         self.access_storage_inner(None).await
     }
@@ -145,20 +290,35 @@ impl ConnectionPool {
     ) -> anyhow::Result<StorageProcessor<'_>> {
         Ok(match self {
             ConnectionPool::Real(real_pool) => {
-                let acquire_latency = CONNECTION_METRICS.acquire.start();
-                let conn = Self::acquire_connection_retried(real_pool)
-                    .await
-                    .context("acquire_connection_retried()")?;
-                let elapsed = acquire_latency.observe();
-                if let Some(requester) = requester {
-                    CONNECTION_METRICS.acquire_tagged[&requester].observe(elapsed);
-                }
-                StorageProcessor::from_pool(conn)
+                Self::access_real_storage(real_pool, requester).await?
+            }
+            ConnectionPool::Replica(replica_pool) => {
+                let mut conn = Self::access_real_storage(&replica_pool.pool, requester).await?;
+                replica_pool.check_replication_lag(&mut conn).await?;
+                conn
             }
             ConnectionPool::Test(test) => test.access_storage().await,
         })
     }
 
+    async fn access_real_storage(
+        pool: &PgPool,
+        requester: Option<&'static str>,
+    ) -> anyhow::Result<StorageProcessor<'static>> {
+        let acquire_latency = CONNECTION_METRICS.acquire.start();
+        let conn = Self::acquire_connection_retried(pool)
+            .await
+            .context("acquire_connection_retried()")?;
+        let elapsed = acquire_latency.observe();
+        if let Some(requester) = requester {
+            CONNECTION_METRICS.acquire_tagged[&requester].observe(elapsed);
+        }
+        CONNECTION_METRICS
+            .cached_statements
+            .observe(conn.cached_statements_size());
+        Ok(StorageProcessor::from_pool(conn))
+    }
+
     async fn acquire_connection_retried(pool: &PgPool) -> anyhow::Result<PoolConnection<Postgres>> {
         const DB_CONNECTION_RETRIES: u32 = 3;
         const BACKOFF_INTERVAL: Duration = Duration::from_secs(1);
@@ -201,7 +361,7 @@ impl ConnectionPool {
     pub async fn access_test_storage(&self) -> StorageProcessor<'static> {
         match self {
             ConnectionPool::Test(test) => test.access_storage().await,
-            ConnectionPool::Real(_) => {
+            ConnectionPool::Real(_) | ConnectionPool::Replica(_) => {
                 panic!("Attempt to access test storage with the real pool");
             }
         }
@@ -212,6 +372,8 @@ impl ConnectionPool {
 mod tests {
     use assert_matches::assert_matches;
 
+    use zksync_types::{L1BatchNumber, H256};
+
     use super::*;
     use crate::get_test_database_url;
 
@@ -237,4 +399,127 @@ mod tests {
             sqlx::Error::Database(db_err) if db_err.message().contains("statement timeout")
         );
     }
+
+    /// Executes queries issued by the metadata calculator when loading an L1 batch. The queries
+    /// do not mutate the database.
+    async fn execute_hot_queries(conn: &mut StorageProcessor<'_>) {
+        let l1_batch_number = L1BatchNumber(0);
+        conn.blocks_dal()
+            .get_l1_batch_header(l1_batch_number)
+            .await
+            .unwrap();
+        conn.storage_logs_dedup_dal()
+            .get_protective_reads_for_l1_batch_range(l1_batch_number..=l1_batch_number)
+            .await;
+        conn.storage_logs_dal()
+            .get_touched_slots_for_l1_batch(l1_batch_number)
+            .await;
+        conn.storage_logs_dal()
+            .get_previous_storage_values(&[H256::zero()], l1_batch_number)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn hot_queries_are_executed_as_prepared_statements() {
+        const HOT_QUERY_COUNT: usize = 4;
+
+        let database_url = get_test_database_url().unwrap();
+        let pool = ConnectionPool::singleton(DbVariant::Master)
+            .build_inner(&database_url)
+            .await;
+        let mut conn = pool.access_storage().await.unwrap();
+        assert_eq!(conn.conn().cached_statements_size(), 0);
+
+        execute_hot_queries(&mut conn).await;
+        let cache_size = conn.conn().cached_statements_size();
+        assert!(cache_size >= HOT_QUERY_COUNT, "{cache_size}");
+
+        // Repeated queries must reuse the prepared statements.
+        for _ in 0..3 {
+            execute_hot_queries(&mut conn).await;
+            assert_eq!(conn.conn().cached_statements_size(), cache_size);
+        }
+    }
+
+    #[tokio::test]
+    async fn disabling_statement_cache() {
+        let database_url = get_test_database_url().unwrap();
+        let pool = ConnectionPool::singleton(DbVariant::Master)
+            .set_statement_cache_capacity(Some(0))
+            .build_inner(&database_url)
+            .await;
+        let mut conn = pool.access_storage().await.unwrap();
+        execute_hot_queries(&mut conn).await;
+        assert_eq!(conn.conn().cached_statements_size(), 0);
+    }
+
+    #[tokio::test]
+    async fn setting_schema() {
+        let database_url = get_test_database_url().unwrap();
+        let pool = ConnectionPool::singleton(DbVariant::Master)
+            .set_schema(Some("shadow_chain".to_owned()))
+            .build_inner(&database_url)
+            .await;
+
+        // NB. We must not mutate the database below! Doing so may break other tests.
+        let mut conn = pool.access_storage().await.unwrap();
+        let search_path: String = sqlx::query_scalar("SELECT current_setting('search_path')")
+            .fetch_one(conn.conn())
+            .await
+            .unwrap();
+        assert_eq!(search_path.replace(' ', ""), "shadow_chain,public");
+        // The schema doesn't exist in the test database, so queries fall back to the `public` schema.
+        execute_hot_queries(&mut conn).await;
+    }
+
+    #[test]
+    fn validating_schema_names() {
+        for schema in ["shadow_chain", "_shadow", "chain2"] {
+            let search_path = schema_search_path(schema).unwrap();
+            assert_eq!(search_path, format!("{schema},public"));
+        }
+        let long_schema = "a".repeat(64);
+        let invalid_schemas = [
+            "",
+            "2chain",
+            "Shadow",
+            "shadow chain",
+            "shadow,public",
+            "shadow\"; DROP SCHEMA public; --",
+            &long_schema,
+        ];
+        for schema in invalid_schemas {
+            let err = schema_search_path(schema).unwrap_err();
+            assert!(err.to_string().contains("schema name"), "{err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn replication_lag_checks_for_primary_server() {
+        let database_url = get_test_database_url().unwrap();
+        let pool = ConnectionPool::singleton(DbVariant::Replica)
+            .set_max_replication_lag(Some(Duration::from_secs(1)))
+            .build_inner(&database_url)
+            .await;
+        assert_matches!(pool, ConnectionPool::Replica(_));
+
+        // The test database is not a replica, so its replication lag is always zero.
+        let mut conn = pool.access_storage().await.unwrap();
+        let lag = replication_lag(&mut conn).await.unwrap();
+        assert_eq!(lag, Duration::ZERO);
+    }
+
+    #[test]
+    fn checking_replication_lag() {
+        let max_lag = Duration::from_secs(5);
+        check_replication_lag(Duration::ZERO, max_lag).unwrap();
+        check_replication_lag(max_lag, max_lag).unwrap();
+
+        let err = check_replication_lag(Duration::from_secs(6), max_lag).unwrap_err();
+        assert_eq!(err.lag, Duration::from_secs(6));
+        assert_eq!(err.max_lag, max_lag);
+        // The error must be recoverable from `anyhow::Error` returned by `access_storage()`.
+        let err = anyhow::Error::from(err);
+        assert!(err.downcast_ref::<ReplicationLagError>().is_some());
+    }
 }
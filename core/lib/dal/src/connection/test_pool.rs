@@ -35,7 +35,7 @@ impl fmt::Debug for TestPoolInner {
 }
 
 impl TestPoolInner {
-    async fn new() -> Self {
+    async fn new(schema: Option<&str>) -> Self {
         let database_url = crate::get_test_database_url().unwrap();
         let connection = PgConnection::connect(&database_url).await.unwrap();
         let mut connection = Box::pin(connection);
@@ -50,6 +50,9 @@ impl TestPoolInner {
             mem::transmute(transaction)
         };
         let mut transaction = Box::pin(transaction);
+        if let Some(schema) = schema {
+            Self::create_schema(&mut transaction, schema).await;
+        }
 
         let subtransaction = transaction.begin().await.unwrap();
         let subtransaction: Transaction<'static, Postgres> = unsafe {
@@ -64,6 +67,28 @@ impl TestPoolInner {
             _connection: connection,
         }
     }
+
+    /// Creates `schema` with empty copies of all tables from the `public` schema and sets it as the primary
+    /// search path. Both changes are local to the transaction and are thus discarded together with it.
+    async fn create_schema(transaction: &mut Transaction<'static, Postgres>, schema: &str) {
+        // Validating the schema name ensures that it can be safely interpolated into the statements below.
+        let search_path = super::schema_search_path(schema).unwrap();
+        let create_schema = format!("CREATE SCHEMA {schema}");
+        let create_tables = format!(
+            "DO $$ DECLARE t RECORD; BEGIN \
+             FOR t IN SELECT tablename FROM pg_tables WHERE schemaname = 'public' LOOP \
+             EXECUTE format('CREATE TABLE {schema}.%I (LIKE public.%I INCLUDING ALL)', t.tablename, t.tablename); \
+             END LOOP; END $$"
+        );
+        // Use the same search path as pools built with `ConnectionPoolBuilder::set_schema()`.
+        let set_search_path = format!("SET LOCAL search_path TO {search_path}");
+        for statement in [create_schema, create_tables, set_search_path] {
+            sqlx::query(&statement)
+                .execute(&mut *transaction)
+                .await
+                .unwrap();
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -107,7 +132,16 @@ impl TestPool {
     /// This method is unsafe, since internally it extends lifetime of the provided `Transaction`.
     pub async fn new() -> Self {
         Self {
-            inner: Arc::new(Mutex::new(TestPoolInner::new().await)),
+            inner: Arc::new(Mutex::new(TestPoolInner::new(None).await)),
+        }
+    }
+
+    /// Constructs a pool similar to [`Self::new()`], which operates on a separate Postgres `schema`
+    /// created within the test transaction. The schema initially contains empty copies of all tables
+    /// from the `public` schema, so the pool is isolated from data in other pools.
+    pub async fn with_schema(schema: &str) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(TestPoolInner::new(Some(schema)).await)),
         }
     }
 
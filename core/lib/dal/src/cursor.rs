@@ -0,0 +1,192 @@
+//! Server-side cursor support for iterating over large result sets.
+
+use futures::{stream, Stream};
+use sqlx::{
+    postgres::{PgArguments, PgRow},
+    FromRow,
+};
+
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::StorageProcessor;
+
+/// Counter used to generate unique cursor names within a connection.
+static CURSOR_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Internal state of a cursor stream.
+struct CursorState<'c, T> {
+    name: String,
+    fetch_sql: String,
+    batch_size: usize,
+    /// Transaction the cursor is declared in; `None` after the cursor is exhausted and closed.
+    transaction: Option<StorageProcessor<'c>>,
+    buffer: VecDeque<T>,
+}
+
+impl<'c, T> CursorState<'c, T>
+where
+    T: for<'r> FromRow<'r, PgRow> + Send + Unpin,
+{
+    async fn next(mut self) -> sqlx::Result<Option<(T, Self)>> {
+        loop {
+            if let Some(row) = self.buffer.pop_front() {
+                return Ok(Some((row, self)));
+            }
+            let Some(transaction) = &mut self.transaction else {
+                return Ok(None);
+            };
+
+            let rows: Vec<T> = sqlx::query_as(&self.fetch_sql)
+                .fetch_all(transaction.conn())
+                .await?;
+            let is_exhausted = rows.len() < self.batch_size;
+            self.buffer.extend(rows);
+            if is_exhausted {
+                self.close().await?;
+            }
+        }
+    }
+
+    async fn close(&mut self) -> sqlx::Result<()> {
+        if let Some(mut transaction) = self.transaction.take() {
+            // Closing the cursor explicitly is necessary if the transaction is nested;
+            // otherwise, the cursor would live until the end of the outer transaction.
+            sqlx::query(&format!("CLOSE {}", self.name))
+                .execute(transaction.conn())
+                .await?;
+            transaction.commit().await?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> StorageProcessor<'a> {
+    /// Streams rows returned by the specified SQL query using a server-side cursor. Rows are fetched
+    /// from the database in batches of `batch_size`, so the result set can be larger than RAM.
+    /// `args` are bound to the query placeholders (`$1`, `$2`, etc.).
+    ///
+    /// The cursor is declared inside a dedicated transaction (or a savepoint if this processor
+    /// is already in a transaction), which is committed once all rows are consumed. If the stream
+    /// is dropped early, the transaction is rolled back, which closes the cursor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is zero.
+    pub async fn stream_with_cursor<'c, T>(
+        &'c mut self,
+        sql: &str,
+        args: PgArguments,
+        batch_size: usize,
+    ) -> sqlx::Result<impl Stream<Item = sqlx::Result<T>> + Send + 'c>
+    where
+        T: for<'r> FromRow<'r, PgRow> + Send + Unpin + 'c,
+    {
+        assert!(batch_size > 0, "Cursor batch size must be positive");
+
+        let name = format!(
+            "dal_cursor_{}",
+            CURSOR_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut transaction = self.start_transaction().await?;
+        let declare_sql = format!("DECLARE {name} NO SCROLL CURSOR FOR {sql}");
+        sqlx::query_with(&declare_sql, args)
+            .execute(transaction.conn())
+            .await?;
+
+        let state = CursorState {
+            fetch_sql: format!("FETCH FORWARD {batch_size} FROM {name}"),
+            name,
+            batch_size,
+            transaction: Some(transaction),
+            buffer: VecDeque::with_capacity(batch_size),
+        };
+        Ok(stream::try_unfold(state, CursorState::next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use db_test_macro::db_test;
+    use futures::{StreamExt, TryStreamExt};
+
+    use super::*;
+    use crate::ConnectionPool;
+
+    async fn open_cursor_count(conn: &mut StorageProcessor<'_>) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM pg_cursors WHERE name LIKE 'dal_cursor_%'")
+            .fetch_one(conn.conn())
+            .await
+            .unwrap()
+    }
+
+    #[db_test(dal_crate)]
+    async fn streaming_rows_with_cursor(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        for batch_size in [1, 7, 100, 1_000] {
+            let rows: Vec<(i32,)> = conn
+                .stream_with_cursor(
+                    "SELECT generate_series(1, 100)",
+                    PgArguments::default(),
+                    batch_size,
+                )
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            let rows: Vec<_> = rows.into_iter().map(|(value,)| value).collect();
+            assert_eq!(rows, (1..=100).collect::<Vec<_>>());
+            assert_eq!(open_cursor_count(&mut conn).await, 0);
+        }
+    }
+
+    #[db_test(dal_crate)]
+    async fn streaming_rows_with_cursor_and_arguments(pool: ConnectionPool) {
+        use sqlx::Arguments as _;
+
+        let mut conn = pool.access_storage().await.unwrap();
+        let mut args = PgArguments::default();
+        args.add(10_i32);
+        args.add(20_i32);
+        let rows: Vec<(i32,)> = conn
+            .stream_with_cursor("SELECT generate_series($1, $2)", args, 3)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let rows: Vec<_> = rows.into_iter().map(|(value,)| value).collect();
+        assert_eq!(rows, (10..=20).collect::<Vec<_>>());
+    }
+
+    #[db_test(dal_crate)]
+    async fn dropping_cursor_stream_early(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        let mut rows = Box::pin(
+            conn.stream_with_cursor::<(i32,)>(
+                "SELECT generate_series(1, 100)",
+                PgArguments::default(),
+                10,
+            )
+            .await
+            .unwrap(),
+        );
+        let first_row = rows.next().await.unwrap().unwrap();
+        assert_eq!(first_row, (1,));
+        drop(rows);
+
+        // The connection must remain usable, and the cursor must be closed.
+        assert_eq!(open_cursor_count(&mut conn).await, 0);
+        let rows: Vec<(i32,)> = conn
+            .stream_with_cursor("SELECT generate_series(1, 5)", PgArguments::default(), 2)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 5);
+    }
+}
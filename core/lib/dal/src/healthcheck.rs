@@ -44,7 +44,12 @@ impl CheckHealth for ConnectionPoolHealthCheck {
         self.connection_pool.access_storage().await.unwrap();
 
         let mut health = Health::from(HealthStatus::Ready);
-        if let ConnectionPool::Real(pool) = &self.connection_pool {
+        let pool = match &self.connection_pool {
+            ConnectionPool::Real(pool) => Some(pool),
+            ConnectionPool::Replica(replica_pool) => Some(&replica_pool.pool),
+            ConnectionPool::Test(_) => None,
+        };
+        if let Some(pool) = pool {
             let details = ConnectionPoolHealthDetails::new(pool).await;
             health = health.with_details(details);
         }
@@ -41,6 +41,7 @@ use crate::tokens_dal::TokensDal;
 use crate::tokens_web3_dal::TokensWeb3Dal;
 use crate::transactions_dal::TransactionsDal;
 use crate::transactions_web3_dal::TransactionsWeb3Dal;
+use crate::witness_artifacts_dal::WitnessArtifactsDal;
 use crate::witness_generator_dal::WitnessGeneratorDal;
 
 #[macro_use]
@@ -50,6 +51,7 @@ pub mod blocks_dal;
 pub mod blocks_web3_dal;
 pub mod connection;
 pub mod contract_verification_dal;
+mod cursor;
 pub mod eth_sender_dal;
 pub mod events_dal;
 pub mod events_web3_dal;
@@ -79,6 +81,7 @@ pub mod tokens_dal;
 pub mod tokens_web3_dal;
 pub mod transactions_dal;
 pub mod transactions_web3_dal;
+pub mod witness_artifacts_dal;
 pub mod witness_generator_dal;
 
 #[cfg(test)]
@@ -307,4 +310,8 @@ impl<'a> StorageProcessor<'a> {
     pub fn system_dal(&mut self) -> SystemDal<'_, 'a> {
         SystemDal { storage: self }
     }
+
+    pub fn witness_artifacts_dal(&mut self) -> WitnessArtifactsDal<'_, 'a> {
+        WitnessArtifactsDal { storage: self }
+    }
 }
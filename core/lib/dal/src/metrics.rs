@@ -1,8 +1,8 @@
 //! Metrics for the data access layer.
 
 use vise::{
-    Buckets, Counter, EncodeLabelSet, EncodeLabelValue, Family, Histogram, LabeledFamily,
-    LatencyObserver, Metrics,
+    Buckets, Counter, EncodeLabelSet, EncodeLabelValue, Family, Gauge, Histogram, LabeledFamily,
+    LatencyObserver, Metrics, Unit,
 };
 
 use std::{thread, time::Duration};
@@ -70,6 +70,7 @@ impl From<&sqlx::Error> for ConnectionErrorKind {
 }
 
 const POOL_SIZE_BUCKETS: Buckets = Buckets::linear(0.0..=100.0, 10.0);
+const CACHED_STATEMENTS_BUCKETS: Buckets = Buckets::linear(0.0..=200.0, 20.0);
 
 /// Connection-related metrics.
 #[derive(Debug, Metrics)]
@@ -89,6 +90,17 @@ pub(crate) struct ConnectionMetrics {
     pub pool_idle: Histogram<usize>,
     /// Number of errors occurred when acquiring a DB connection.
     pub pool_acquire_error: Family<ConnectionErrorKind, Counter>,
+    /// Replication lag of the read replica, as observed when acquiring a connection from a pool
+    /// with replication lag checks.
+    #[metrics(unit = Unit::Seconds)]
+    pub replication_lag: Gauge<Duration>,
+    /// Number of connections refused because the replication lag exceeded the configured maximum.
+    pub replication_lag_exceeded: Counter,
+    /// Number of prepared statements cached by a DB connection, as observed when acquiring the connection.
+    /// If this number is close to the statement cache capacity, statements are likely evicted from the cache
+    /// and re-prepared.
+    #[metrics(buckets = CACHED_STATEMENTS_BUCKETS)]
+    pub cached_statements: Histogram<usize>,
 }
 
 #[vise::register]
@@ -3,7 +3,7 @@ use sqlx::Error;
 use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
-    ops::Range,
+    ops::{self, Range},
     time::Duration,
 };
 
@@ -306,6 +306,32 @@ impl ProverDal<'_, '_> {
         }
     }
 
+    /// Returns L1 batches in the specified range that have a successfully generated final (scheduler) proof,
+    /// either by the legacy or by the FRI prover.
+    pub async fn get_l1_batches_with_final_proofs(
+        &mut self,
+        l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+    ) -> Vec<L1BatchNumber> {
+        sqlx::query!(
+            "SELECT l1_batch_number AS \"l1_batch_number!\" FROM prover_jobs \
+             WHERE l1_batch_number >= $1 AND l1_batch_number <= $2 \
+             AND aggregation_round = 3 AND status = 'successful' \
+             UNION \
+             SELECT l1_batch_number FROM prover_jobs_fri \
+             WHERE l1_batch_number >= $1 AND l1_batch_number <= $2 \
+             AND aggregation_round = 3 AND status IN ('successful', 'sent_to_server') \
+             ORDER BY l1_batch_number",
+            l1_batch_numbers.start().0 as i64,
+            l1_batch_numbers.end().0 as i64
+        )
+        .fetch_all(self.storage.conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| L1BatchNumber(row.l1_batch_number as u32))
+        .collect()
+    }
+
     pub async fn get_prover_jobs_stats_per_circuit(
         &mut self,
     ) -> HashMap<String, JobCountStatistics> {
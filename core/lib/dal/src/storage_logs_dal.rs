@@ -1,7 +1,8 @@
+use futures::{Stream, TryStreamExt};
 use sqlx::types::chrono::Utc;
-use sqlx::Row;
+use sqlx::{postgres::PgArguments, Arguments, Row};
 
-use std::{collections::HashMap, time::Instant};
+use std::{collections::HashMap, ops, time::Instant};
 
 use crate::StorageProcessor;
 use zksync_types::{
@@ -14,7 +15,10 @@ pub struct StorageLogsDal<'a, 'c> {
     pub(crate) storage: &'a mut StorageProcessor<'c>,
 }
 
-impl StorageLogsDal<'_, '_> {
+impl<'a> StorageLogsDal<'a, '_> {
+    /// Maximum number of keys queried by [`Self::get_storage_values()`] and related methods in a single DB query.
+    const STORAGE_VALUES_CHUNK_SIZE: usize = 10_000;
+
     /// Inserts storage logs grouped by transaction for a miniblock. The ordering of transactions
     /// must be the same as their ordering in the miniblock.
     pub async fn insert_storage_logs(
@@ -239,6 +243,62 @@ impl StorageLogsDal<'_, '_> {
         touched_slots.collect()
     }
 
+    /// Returns the number of storage logs in the specified L1 batch. This is an upper bound
+    /// on the number of slots returned by [`Self::get_touched_slots_for_l1_batch()`] that is cheap to compute.
+    pub async fn estimate_touched_slots_count_for_l1_batch(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+    ) -> usize {
+        let count = sqlx::query!(
+            "SELECT COUNT(*) AS \"count!\" \
+            FROM storage_logs \
+            WHERE miniblock_number BETWEEN \
+                (SELECT MIN(number) FROM miniblocks WHERE l1_batch_number = $1) \
+                AND (SELECT MAX(number) FROM miniblocks WHERE l1_batch_number = $1)",
+            l1_batch_number.0 as i64
+        )
+        .fetch_one(self.storage.conn())
+        .await
+        .unwrap()
+        .count;
+        count as usize
+    }
+
+    /// Streams latest values for all [`StorageKey`]s written to in the specified range of L1 batches
+    /// judging by storage logs (i.e., not taking deduplication logic into account). Slots are ordered
+    /// by their hashed keys. Rows are loaded from the database using a server-side cursor in batches
+    /// of `batch_size`, so this method is suitable for ranges with a large number of touched slots.
+    pub async fn stream_touched_slots(
+        self,
+        l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+        batch_size: usize,
+    ) -> sqlx::Result<impl Stream<Item = sqlx::Result<(StorageKey, H256)>> + Send + 'a> {
+        let mut args = PgArguments::default();
+        args.add(l1_batch_numbers.start().0 as i64);
+        args.add(l1_batch_numbers.end().0 as i64);
+
+        let rows = self
+            .storage
+            .stream_with_cursor::<(Vec<u8>, Vec<u8>, Vec<u8>)>(
+                "SELECT DISTINCT ON (hashed_key) address, key, value \
+                FROM storage_logs \
+                WHERE miniblock_number BETWEEN \
+                    (SELECT MIN(number) FROM miniblocks WHERE l1_batch_number = $1) \
+                    AND (SELECT MAX(number) FROM miniblocks WHERE l1_batch_number = $2) \
+                ORDER BY hashed_key, miniblock_number DESC, operation_number DESC",
+                args,
+                batch_size,
+            )
+            .await?;
+        Ok(rows.map_ok(|(address, key, value)| {
+            let key = StorageKey::new(
+                AccountTreeId::new(Address::from_slice(&address)),
+                H256::from_slice(&key),
+            );
+            (key, H256::from_slice(&value))
+        }))
+    }
+
     /// Returns (hashed) storage keys and the corresponding values that need to be applied to a storage
     /// in order to revert it to the specified L1 batch. Deduplication is taken into account.
     pub async fn get_storage_logs_for_revert(
@@ -359,6 +419,19 @@ impl StorageLogsDal<'_, '_> {
         hashed_keys: &[H256],
         next_l1_batch: L1BatchNumber,
     ) -> HashMap<H256, Option<H256>> {
+        let values = self
+            .get_previous_storage_values_in_order(hashed_keys, next_l1_batch)
+            .await;
+        hashed_keys.iter().copied().zip(values).collect()
+    }
+
+    /// Same as [`Self::get_previous_storage_values()`], but returns values in the order of `hashed_keys`
+    /// (i.e., the `i`th returned value corresponds to `hashed_keys[i]`).
+    pub async fn get_previous_storage_values_in_order(
+        &mut self,
+        hashed_keys: &[H256],
+        next_l1_batch: L1BatchNumber,
+    ) -> Vec<Option<H256>> {
         let (miniblock_number, _) = self
             .storage
             .blocks_dal()
@@ -368,9 +441,9 @@ impl StorageLogsDal<'_, '_> {
             .unwrap();
 
         if miniblock_number == MiniblockNumber(0) {
-            hashed_keys.iter().copied().map(|key| (key, None)).collect()
+            vec![None; hashed_keys.len()]
         } else {
-            self.get_storage_values(hashed_keys, miniblock_number - 1)
+            self.get_storage_values_in_order(hashed_keys, miniblock_number - 1)
                 .await
         }
     }
@@ -381,14 +454,44 @@ impl StorageLogsDal<'_, '_> {
         hashed_keys: &[H256],
         miniblock_number: MiniblockNumber,
     ) -> HashMap<H256, Option<H256>> {
-        let hashed_keys: Vec<_> = hashed_keys.iter().map(H256::as_bytes).collect();
+        let values = self
+            .get_storage_values_in_order(hashed_keys, miniblock_number)
+            .await;
+        hashed_keys.iter().copied().zip(values).collect()
+    }
+
+    /// Same as [`Self::get_storage_values()`], but returns values in the order of `hashed_keys`.
+    /// Keys are queried in chunks of [`Self::STORAGE_VALUES_CHUNK_SIZE`] to keep individual queries reasonably small.
+    pub async fn get_storage_values_in_order(
+        &mut self,
+        hashed_keys: &[H256],
+        miniblock_number: MiniblockNumber,
+    ) -> Vec<Option<H256>> {
+        let mut values = Vec::with_capacity(hashed_keys.len());
+        for chunk in hashed_keys.chunks(Self::STORAGE_VALUES_CHUNK_SIZE) {
+            let chunk_values = self
+                .get_storage_values_for_chunk(chunk, miniblock_number)
+                .await;
+            values.extend(chunk_values);
+        }
+        values
+    }
 
+    async fn get_storage_values_for_chunk(
+        &mut self,
+        hashed_keys: &[H256],
+        miniblock_number: MiniblockNumber,
+    ) -> Vec<Option<H256>> {
+        let hashed_keys: Vec<_> = hashed_keys.iter().map(H256::as_bytes).collect();
         let rows = sqlx::query!(
-            "SELECT u.hashed_key as \"hashed_key!\", \
-                (SELECT value FROM storage_logs \
-                WHERE hashed_key = u.hashed_key AND miniblock_number <= $2 \
-                ORDER BY miniblock_number DESC, operation_number DESC LIMIT 1) as \"value?\" \
-            FROM UNNEST($1::bytea[]) AS u(hashed_key)",
+            "SELECT l.value as \"value?\" \
+            FROM UNNEST($1::bytea[]) WITH ORDINALITY AS u(hashed_key, ordinal) \
+            LEFT JOIN LATERAL ( \
+                SELECT value FROM storage_logs \
+                WHERE storage_logs.hashed_key = u.hashed_key AND miniblock_number <= $2 \
+                ORDER BY miniblock_number DESC, operation_number DESC LIMIT 1 \
+            ) l ON TRUE \
+            ORDER BY u.ordinal",
             &hashed_keys as &[&[u8]],
             miniblock_number.0 as i64
         )
@@ -396,12 +499,9 @@ impl StorageLogsDal<'_, '_> {
         .await
         .unwrap();
 
+        debug_assert_eq!(rows.len(), hashed_keys.len());
         rows.into_iter()
-            .map(|row| {
-                let key = H256::from_slice(&row.hashed_key);
-                let value = row.value.map(|value| H256::from_slice(&value));
-                (key, value)
-            })
+            .map(|row| row.value.map(|value| H256::from_slice(&value)))
             .collect()
     }
 
@@ -522,6 +622,7 @@ mod tests {
     use super::*;
     use crate::{tests::create_miniblock_header, ConnectionPool};
     use db_test_macro::db_test;
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
     use zksync_contracts::BaseSystemContractsHashes;
     use zksync_types::{
         block::{BlockGasCount, L1BatchHeader},
@@ -603,6 +704,17 @@ mod tests {
         assert_eq!(touched_slots.len(), 2);
         assert_eq!(touched_slots[&first_key], H256::repeat_byte(3));
         assert_eq!(touched_slots[&second_key], H256::repeat_byte(2));
+        // The estimate counts all logs, including ones overwriting the same slot.
+        let estimated_count = conn
+            .storage_logs_dal()
+            .estimate_touched_slots_count_for_l1_batch(L1BatchNumber(1))
+            .await;
+        assert_eq!(estimated_count, 3);
+        let estimated_count = conn
+            .storage_logs_dal()
+            .estimate_touched_slots_count_for_l1_batch(L1BatchNumber(2))
+            .await;
+        assert_eq!(estimated_count, 0);
 
         test_rollback(&mut conn, first_key, second_key).await;
     }
@@ -649,6 +761,78 @@ mod tests {
         assert!(value.is_none());
     }
 
+    #[db_test(dal_crate)]
+    async fn streaming_touched_slots(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+
+        conn.blocks_dal()
+            .delete_miniblocks(MiniblockNumber(0))
+            .await
+            .unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+
+        let account = AccountTreeId::new(Address::repeat_byte(1));
+        let logs: Vec<_> = (0_u64..10)
+            .map(|i| {
+                let key = StorageKey::new(account, H256::from_low_u64_be(i));
+                StorageLog::new_write_log(key, H256::from_low_u64_be(i + 1))
+            })
+            .collect();
+        insert_miniblock(&mut conn, 1, logs.clone()).await;
+        let new_logs: Vec<_> = (5_u64..15)
+            .map(|i| {
+                let key = StorageKey::new(account, H256::from_low_u64_be(i));
+                StorageLog::new_write_log(key, H256::from_low_u64_be(i + 100))
+            })
+            .collect();
+        insert_miniblock(&mut conn, 2, new_logs.clone()).await;
+
+        for batch_size in [1, 3, 100] {
+            let touched_slots: Vec<_> = conn
+                .storage_logs_dal()
+                .stream_touched_slots(L1BatchNumber(1)..=L1BatchNumber(1), batch_size)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            let expected_slots = conn
+                .storage_logs_dal()
+                .get_touched_slots_for_l1_batch(L1BatchNumber(1))
+                .await;
+            assert_eq!(touched_slots.len(), expected_slots.len());
+            for (key, value) in &touched_slots {
+                assert_eq!(expected_slots[key], *value);
+            }
+            let hashed_keys: Vec<_> = touched_slots
+                .iter()
+                .map(|(key, _)| key.hashed_key())
+                .collect();
+            let mut sorted_hashed_keys = hashed_keys.clone();
+            sorted_hashed_keys.sort_unstable();
+            assert_eq!(hashed_keys, sorted_hashed_keys);
+
+            let touched_slots: HashMap<_, _> = conn
+                .storage_logs_dal()
+                .stream_touched_slots(L1BatchNumber(1)..=L1BatchNumber(2), batch_size)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(touched_slots.len(), 15);
+            for log in logs[..5].iter().chain(&new_logs) {
+                assert_eq!(touched_slots[&log.key], log.value);
+            }
+        }
+    }
+
     #[db_test(dal_crate)]
     async fn getting_storage_logs_for_revert(pool: ConnectionPool) {
         let mut conn = pool.access_storage().await.unwrap();
@@ -776,4 +960,161 @@ mod tests {
             }
         }
     }
+
+    /// Reference implementation of [`StorageLogsDal::get_storage_values()`] querying all keys at once
+    /// and resolving each key with a correlated subquery.
+    async fn get_storage_values_naive(
+        conn: &mut StorageProcessor<'_>,
+        hashed_keys: &[H256],
+        miniblock_number: MiniblockNumber,
+    ) -> HashMap<H256, Option<H256>> {
+        let hashed_keys: Vec<_> = hashed_keys.iter().map(H256::as_bytes).collect();
+        let rows = sqlx::query!(
+            "SELECT u.hashed_key as \"hashed_key!\", \
+                (SELECT value FROM storage_logs \
+                WHERE hashed_key = u.hashed_key AND miniblock_number <= $2 \
+                ORDER BY miniblock_number DESC, operation_number DESC LIMIT 1) as \"value?\" \
+            FROM UNNEST($1::bytea[]) AS u(hashed_key)",
+            &hashed_keys as &[&[u8]],
+            miniblock_number.0 as i64
+        )
+        .fetch_all(conn.conn())
+        .await
+        .unwrap();
+
+        rows.into_iter()
+            .map(|row| {
+                let key = H256::from_slice(&row.hashed_key);
+                let value = row.value.map(|value| H256::from_slice(&value));
+                (key, value)
+            })
+            .collect()
+    }
+
+    #[db_test(dal_crate)]
+    async fn getting_storage_values_is_equivalent_to_naive_implementation(pool: ConnectionPool) {
+        const KEY_COUNT: u64 = 50;
+        const L1_BATCH_COUNT: u32 = 5;
+
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal()
+            .delete_miniblocks(MiniblockNumber(0))
+            .await
+            .unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let account = AccountTreeId::new(Address::repeat_byte(1));
+        let all_keys: Vec<_> = (0..KEY_COUNT)
+            .map(|i| StorageKey::new(account, H256::from_low_u64_be(i)))
+            .collect();
+        for number in 1..=L1_BATCH_COUNT {
+            let logs = all_keys
+                .iter()
+                .filter(|_| rng.gen_bool(0.3))
+                .map(|&key| StorageLog::new_write_log(key, H256::random_using(&mut rng)))
+                .collect();
+            insert_miniblock(&mut conn, number, logs).await;
+        }
+
+        for _ in 0..10 {
+            // Include keys without any logs and duplicate keys.
+            let mut hashed_keys: Vec<_> = all_keys
+                .iter()
+                .filter(|_| rng.gen_bool(0.5))
+                .map(StorageKey::hashed_key)
+                .collect();
+            hashed_keys.extend((0..5).map(|_| H256::random_using(&mut rng)));
+            hashed_keys.push(all_keys[0].hashed_key());
+            hashed_keys.push(all_keys[0].hashed_key());
+            hashed_keys.shuffle(&mut rng);
+
+            for number in 0..=L1_BATCH_COUNT {
+                let miniblock_number = MiniblockNumber(number);
+                let expected =
+                    get_storage_values_naive(&mut conn, &hashed_keys, miniblock_number).await;
+                let values = conn
+                    .storage_logs_dal()
+                    .get_storage_values(&hashed_keys, miniblock_number)
+                    .await;
+                assert_eq!(values, expected);
+
+                let values_in_order = conn
+                    .storage_logs_dal()
+                    .get_storage_values_in_order(&hashed_keys, miniblock_number)
+                    .await;
+                assert_eq!(values_in_order.len(), hashed_keys.len());
+                for (key, value) in hashed_keys.iter().zip(values_in_order) {
+                    assert_eq!(value, expected[key], "{key:?}");
+                }
+            }
+
+            let next_l1_batch = L1BatchNumber(rng.gen_range(1..=L1_BATCH_COUNT));
+            let expected = get_storage_values_naive(
+                &mut conn,
+                &hashed_keys,
+                MiniblockNumber(next_l1_batch.0 - 1),
+            )
+            .await;
+            let prev_values = conn
+                .storage_logs_dal()
+                .get_previous_storage_values_in_order(&hashed_keys, next_l1_batch)
+                .await;
+            let expected: Vec<_> = hashed_keys.iter().map(|key| expected[key]).collect();
+            assert_eq!(prev_values, expected);
+        }
+    }
+
+    #[db_test(dal_crate)]
+    async fn getting_storage_values_in_multiple_chunks(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal()
+            .delete_miniblocks(MiniblockNumber(0))
+            .await
+            .unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+
+        let mut rng = StdRng::seed_from_u64(123);
+        let account = AccountTreeId::new(Address::repeat_byte(1));
+        let logs: Vec<_> = (0_u64..100)
+            .map(|i| {
+                let key = StorageKey::new(account, H256::from_low_u64_be(i));
+                StorageLog::new_write_log(key, H256::random_using(&mut rng))
+            })
+            .collect();
+        insert_miniblock(&mut conn, 1, logs.clone()).await;
+
+        let key_count = StorageLogsDal::STORAGE_VALUES_CHUNK_SIZE * 2 + 1;
+        let mut hashed_keys: Vec<_> = (0..key_count)
+            .map(|_| H256::random_using(&mut rng))
+            .collect();
+        for (i, log) in logs.iter().enumerate() {
+            // Spread existing keys across all chunks.
+            hashed_keys[i * (key_count / logs.len())] = log.key.hashed_key();
+        }
+
+        let expected = get_storage_values_naive(&mut conn, &hashed_keys, MiniblockNumber(1)).await;
+        let values = conn
+            .storage_logs_dal()
+            .get_storage_values_in_order(&hashed_keys, MiniblockNumber(1))
+            .await;
+        assert_eq!(values.len(), key_count);
+        for (key, value) in hashed_keys.iter().zip(values) {
+            assert_eq!(value, expected[key], "{key:?}");
+        }
+        let non_empty_count = expected.values().filter(|value| value.is_some()).count();
+        assert_eq!(non_empty_count, logs.len());
+    }
 }
@@ -1,9 +1,30 @@
 use crate::StorageProcessor;
 use sqlx::types::chrono::Utc;
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    ops,
+};
 use zksync_types::{AccountTreeId, Address, L1BatchNumber, LogQuery, StorageKey, H256};
 use zksync_utils::u256_to_h256;
 
+/// Inconsistency between the `initial_writes` table and storage logs
+/// found by [`StorageLogsDedupDal::validate_initial_writes_consistency()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InitialWritesInconsistency {
+    /// Key has a non-zero value after the specified L1 batch according to storage logs,
+    /// but it has no initial write in this or earlier L1 batches.
+    MissingInitialWrite {
+        hashed_key: H256,
+        l1_batch_number: L1BatchNumber,
+    },
+    /// Initial write for the key refers to the specified L1 batch, but the batch has no non-zero writes
+    /// for the key.
+    UnmatchedInitialWrite {
+        hashed_key: H256,
+        l1_batch_number: L1BatchNumber,
+    },
+}
+
 #[derive(Debug)]
 pub struct StorageLogsDedupDal<'a, 'c> {
     pub(crate) storage: &'a mut StorageProcessor<'c>,
@@ -91,6 +112,59 @@ impl StorageLogsDedupDal<'_, '_> {
         .collect()
     }
 
+    /// Same as [`Self::get_protective_reads_for_l1_batch()`], but returns hashed keys instead
+    /// of full [`StorageKey`]s. The order of returned keys is not specified.
+    pub async fn get_protective_reads_hashed_keys_for_l1_batch(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+    ) -> Vec<H256> {
+        sqlx::query!(
+            "SELECT address, key FROM protective_reads WHERE l1_batch_number = $1",
+            l1_batch_number.0 as i64
+        )
+        .fetch_all(self.storage.conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| {
+            let address = Address::from_slice(&row.address);
+            StorageKey::raw_hashed_key(&address, &H256::from_slice(&row.key)).into()
+        })
+        .collect()
+    }
+
+    /// Returns protective reads for all L1 batches in the specified range, grouped by L1 batch.
+    /// L1 batches without protective reads are not present in the returned map.
+    pub async fn get_protective_reads_for_l1_batch_range(
+        &mut self,
+        l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+    ) -> HashMap<L1BatchNumber, Vec<StorageKey>> {
+        let rows = sqlx::query!(
+            "SELECT l1_batch_number, address, key FROM protective_reads \
+            WHERE l1_batch_number BETWEEN $1 AND $2 \
+            ORDER BY l1_batch_number",
+            l1_batch_numbers.start().0 as i64,
+            l1_batch_numbers.end().0 as i64
+        )
+        .fetch_all(self.storage.conn())
+        .await
+        .unwrap();
+
+        let mut protective_reads = HashMap::<_, Vec<_>>::new();
+        for row in rows {
+            let key = StorageKey::new(
+                AccountTreeId::new(Address::from_slice(&row.address)),
+                H256::from_slice(&row.key),
+            );
+            let l1_batch_number = L1BatchNumber(row.l1_batch_number as u32);
+            protective_reads
+                .entry(l1_batch_number)
+                .or_default()
+                .push(key);
+        }
+        protective_reads
+    }
+
     pub async fn max_enumeration_index(&mut self) -> Option<u64> {
         sqlx::query!("SELECT MAX(index) as \"max?\" FROM initial_writes",)
             .fetch_one(self.storage.conn())
@@ -100,6 +174,33 @@ impl StorageLogsDedupDal<'_, '_> {
             .map(|max| max as u64)
     }
 
+    /// Returns the number of the latest L1 batch with initial writes, or `None` if there are no initial writes.
+    pub async fn max_l1_batch_with_initial_writes(&mut self) -> Option<L1BatchNumber> {
+        sqlx::query!("SELECT MAX(l1_batch_number) as \"max?\" FROM initial_writes")
+            .fetch_one(self.storage.conn())
+            .await
+            .unwrap()
+            .max
+            .map(|max| L1BatchNumber(max as u32))
+    }
+
+    /// Returns the maximum enumeration index allocated in L1 batches up to and including the specified one,
+    /// or `None` if no indices are allocated by then.
+    pub async fn max_enumeration_index_for_l1_batch(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+    ) -> Option<u64> {
+        sqlx::query!(
+            "SELECT MAX(index) as \"max?\" FROM initial_writes WHERE l1_batch_number <= $1",
+            l1_batch_number.0 as i64
+        )
+        .fetch_one(self.storage.conn())
+        .await
+        .unwrap()
+        .max
+        .map(|max| max as u64)
+    }
+
     pub async fn initial_writes_for_batch(
         &mut self,
         l1_batch_number: L1BatchNumber,
@@ -118,6 +219,158 @@ impl StorageLogsDedupDal<'_, '_> {
         .collect()
     }
 
+    /// Returns hashed keys and L1 batch numbers of initial writes with the specified enumeration indices.
+    /// Indices not present in the `initial_writes` table are omitted from the returned map.
+    pub async fn initial_writes_for_indices(
+        &mut self,
+        indices: &[u64],
+    ) -> HashMap<u64, (H256, L1BatchNumber)> {
+        let indices: Vec<_> = indices.iter().map(|&index| index as i64).collect();
+        sqlx::query!(
+            "SELECT hashed_key, index, l1_batch_number FROM initial_writes \
+            WHERE index = ANY($1)",
+            &indices
+        )
+        .fetch_all(self.storage.conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| {
+            let hashed_key = H256::from_slice(&row.hashed_key);
+            let l1_batch_number = L1BatchNumber(row.l1_batch_number as u32);
+            (row.index as u64, (hashed_key, l1_batch_number))
+        })
+        .collect()
+    }
+
+    /// Returns up to `limit` initial writes with enumeration indices starting from `start_index`,
+    /// ordered by the index. Each write is returned as a `(hashed_key, index, l1_batch_number)` tuple.
+    pub async fn initial_writes_chunk(
+        &mut self,
+        start_index: u64,
+        limit: usize,
+    ) -> Vec<(H256, u64, L1BatchNumber)> {
+        sqlx::query!(
+            "SELECT hashed_key, index, l1_batch_number FROM initial_writes \
+            WHERE index >= $1 \
+            ORDER BY index LIMIT $2",
+            start_index as i64,
+            limit as i64
+        )
+        .fetch_all(self.storage.conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| {
+            let hashed_key = H256::from_slice(&row.hashed_key);
+            let l1_batch_number = L1BatchNumber(row.l1_batch_number as u32);
+            (hashed_key, row.index as u64, l1_batch_number)
+        })
+        .collect()
+    }
+
+    /// Overwrites enumeration indices of the specified initial writes, which are provided as
+    /// `(hashed_key, index)` tuples. Indices can be permuted among the updated writes; however, new indices
+    /// must not be used by initial writes not mentioned in `indices`.
+    pub async fn update_initial_writes_indices(&mut self, indices: &[(H256, u64)]) {
+        let (hashed_keys, indices): (Vec<_>, Vec<_>) = indices
+            .iter()
+            .map(|(hashed_key, index)| (hashed_key.as_bytes(), *index as i64))
+            .unzip();
+
+        let mut transaction = self.storage.start_transaction().await.unwrap();
+        // The uniqueness of indices is checked after each updated row, so we use negated indices
+        // as an intermediate step to be able to permute indices.
+        sqlx::query!(
+            "UPDATE initial_writes SET index = -u.index, updated_at = now() \
+            FROM UNNEST($1::bytea[], $2::bigint[]) AS u(hashed_key, index) \
+            WHERE initial_writes.hashed_key = u.hashed_key",
+            &hashed_keys as &[&[u8]],
+            &indices
+        )
+        .execute(transaction.conn())
+        .await
+        .unwrap();
+        sqlx::query!("UPDATE initial_writes SET index = -index WHERE index < 0")
+            .execute(transaction.conn())
+            .await
+            .unwrap();
+        transaction.commit().await.unwrap();
+    }
+
+    /// Checks consistency of the `initial_writes` table with storage logs for the specified range
+    /// of L1 batches. L1 batches are checked one by one, so that the memory consumption is bounded
+    /// by the number of storage logs in a single L1 batch (plus the number of found inconsistencies).
+    /// L1 batches without miniblocks are skipped.
+    ///
+    /// # Performance
+    ///
+    /// This method is slow and should only be used for diagnostics.
+    pub async fn validate_initial_writes_consistency(
+        &mut self,
+        l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+    ) -> sqlx::Result<Vec<InitialWritesInconsistency>> {
+        let mut inconsistencies = vec![];
+        for l1_batch_number in l1_batch_numbers.start().0..=l1_batch_numbers.end().0 {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            let miniblock_range = self
+                .storage
+                .blocks_dal()
+                .get_miniblock_range_of_l1_batch(l1_batch_number)
+                .await?;
+            let Some((first_miniblock, last_miniblock)) = miniblock_range else {
+                continue;
+            };
+
+            let missing_keys = sqlx::query!(
+                "SELECT last_writes.hashed_key as \"hashed_key!\" FROM ( \
+                    SELECT DISTINCT ON (hashed_key) hashed_key, value FROM storage_logs \
+                    WHERE miniblock_number BETWEEN $1 AND $2 \
+                    ORDER BY hashed_key, miniblock_number DESC, operation_number DESC \
+                ) AS last_writes \
+                WHERE last_writes.value != $3 AND NOT EXISTS ( \
+                    SELECT 1 FROM initial_writes \
+                    WHERE initial_writes.hashed_key = last_writes.hashed_key \
+                        AND initial_writes.l1_batch_number <= $4 \
+                )",
+                first_miniblock.0 as i64,
+                last_miniblock.0 as i64,
+                H256::zero().as_bytes(),
+                l1_batch_number.0 as i64
+            )
+            .fetch_all(self.storage.conn())
+            .await?;
+            inconsistencies.extend(missing_keys.into_iter().map(|row| {
+                InitialWritesInconsistency::MissingInitialWrite {
+                    hashed_key: H256::from_slice(&row.hashed_key),
+                    l1_batch_number,
+                }
+            }));
+
+            let unmatched_keys = sqlx::query!(
+                "SELECT hashed_key FROM initial_writes \
+                WHERE l1_batch_number = $1 AND NOT EXISTS ( \
+                    SELECT 1 FROM storage_logs \
+                    WHERE storage_logs.hashed_key = initial_writes.hashed_key \
+                        AND miniblock_number BETWEEN $2 AND $3 AND value != $4 \
+                )",
+                l1_batch_number.0 as i64,
+                first_miniblock.0 as i64,
+                last_miniblock.0 as i64,
+                H256::zero().as_bytes()
+            )
+            .fetch_all(self.storage.conn())
+            .await?;
+            inconsistencies.extend(unmatched_keys.into_iter().map(|row| {
+                InitialWritesInconsistency::UnmatchedInitialWrite {
+                    hashed_key: H256::from_slice(&row.hashed_key),
+                    l1_batch_number,
+                }
+            }));
+        }
+        Ok(inconsistencies)
+    }
+
     /// Returns `hashed_keys` that are both present in the input and in `initial_writes` table.
     pub async fn filter_written_slots(&mut self, hashed_keys: &[H256]) -> HashSet<H256> {
         let hashed_keys: Vec<_> = hashed_keys.iter().map(H256::as_bytes).collect();
@@ -134,3 +387,242 @@ impl StorageLogsDedupDal<'_, '_> {
         .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::create_miniblock_header, ConnectionPool};
+    use db_test_macro::db_test;
+    use zksync_contracts::BaseSystemContractsHashes;
+    use zksync_types::{
+        block::{BlockGasCount, L1BatchHeader},
+        MiniblockNumber, ProtocolVersion, ProtocolVersionId, StorageLog,
+    };
+
+    async fn insert_l1_batch(conn: &mut StorageProcessor<'_>, number: u32, logs: Vec<StorageLog>) {
+        let mut header = L1BatchHeader::new(
+            L1BatchNumber(number),
+            0,
+            Address::default(),
+            BaseSystemContractsHashes::default(),
+            ProtocolVersionId::default(),
+        );
+        header.is_finished = true;
+        conn.blocks_dal()
+            .insert_l1_batch(&header, &[], BlockGasCount::default())
+            .await
+            .unwrap();
+        conn.blocks_dal()
+            .insert_miniblock(&create_miniblock_header(number))
+            .await
+            .unwrap();
+        conn.storage_logs_dal()
+            .insert_storage_logs(MiniblockNumber(number), &[(H256::zero(), logs)])
+            .await;
+        conn.blocks_dal()
+            .mark_miniblocks_as_executed_in_l1_batch(L1BatchNumber(number))
+            .await
+            .unwrap();
+    }
+
+    #[db_test(dal_crate)]
+    async fn validating_initial_writes_consistency(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal()
+            .delete_miniblocks(MiniblockNumber(0))
+            .await
+            .unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+
+        let account = AccountTreeId::new(Address::repeat_byte(1));
+        let keys: Vec<_> = (0_u64..5)
+            .map(|i| StorageKey::new(account, H256::from_low_u64_be(i)))
+            .collect();
+        let logs = keys
+            .iter()
+            .map(|&key| StorageLog::new_write_log(key, H256::repeat_byte(1)))
+            .collect();
+        insert_l1_batch(&mut conn, 1, logs).await;
+        conn.storage_logs_dedup_dal()
+            .insert_initial_writes(L1BatchNumber(1), &keys)
+            .await;
+
+        // Repeated write and a write zeroing the key; neither requires an initial write.
+        let logs = vec![
+            StorageLog::new_write_log(keys[0], H256::repeat_byte(2)),
+            StorageLog::new_write_log(keys[1], H256::zero()),
+        ];
+        insert_l1_batch(&mut conn, 2, logs).await;
+
+        let inconsistencies = conn
+            .storage_logs_dedup_dal()
+            .validate_initial_writes_consistency(L1BatchNumber(1)..=L1BatchNumber(2))
+            .await
+            .unwrap();
+        assert!(inconsistencies.is_empty(), "{inconsistencies:?}");
+
+        // New key without an initial write, and an initial write for a key with zero value.
+        let new_key = StorageKey::new(account, H256::from_low_u64_be(100));
+        let zeroed_key = StorageKey::new(account, H256::from_low_u64_be(101));
+        let logs = vec![
+            StorageLog::new_write_log(new_key, H256::repeat_byte(3)),
+            StorageLog::new_write_log(zeroed_key, H256::zero()),
+        ];
+        insert_l1_batch(&mut conn, 3, logs).await;
+        conn.storage_logs_dedup_dal()
+            .insert_initial_writes(L1BatchNumber(3), &[zeroed_key])
+            .await;
+
+        let inconsistencies = conn
+            .storage_logs_dedup_dal()
+            .validate_initial_writes_consistency(L1BatchNumber(1)..=L1BatchNumber(3))
+            .await
+            .unwrap();
+        assert_eq!(
+            inconsistencies,
+            [
+                InitialWritesInconsistency::MissingInitialWrite {
+                    hashed_key: new_key.hashed_key(),
+                    l1_batch_number: L1BatchNumber(3),
+                },
+                InitialWritesInconsistency::UnmatchedInitialWrite {
+                    hashed_key: zeroed_key.hashed_key(),
+                    l1_batch_number: L1BatchNumber(3),
+                },
+            ]
+        );
+
+        // L1 batches outside the range must not be checked.
+        let inconsistencies = conn
+            .storage_logs_dedup_dal()
+            .validate_initial_writes_consistency(L1BatchNumber(1)..=L1BatchNumber(2))
+            .await
+            .unwrap();
+        assert!(inconsistencies.is_empty(), "{inconsistencies:?}");
+    }
+
+    #[db_test(dal_crate)]
+    async fn getting_protective_reads_hashed_keys(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal()
+            .delete_miniblocks(MiniblockNumber(0))
+            .await
+            .unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+        insert_l1_batch(&mut conn, 1, vec![]).await;
+
+        let read_logs: Vec<_> = (0_u64..10)
+            .map(|i| {
+                let address = Address::repeat_byte(i as u8);
+                let key = StorageKey::new(AccountTreeId::new(address), H256::from_low_u64_be(i));
+                StorageLog::new_read_log(key, H256::zero()).to_test_log_query()
+            })
+            .collect();
+        conn.storage_logs_dedup_dal()
+            .insert_protective_reads(L1BatchNumber(1), &read_logs)
+            .await;
+
+        let protective_reads = conn
+            .storage_logs_dedup_dal()
+            .get_protective_reads_for_l1_batch(L1BatchNumber(1))
+            .await;
+        assert_eq!(protective_reads.len(), 10);
+        let hashed_keys = conn
+            .storage_logs_dedup_dal()
+            .get_protective_reads_hashed_keys_for_l1_batch(L1BatchNumber(1))
+            .await;
+        let hashed_keys: HashSet<_> = hashed_keys.into_iter().collect();
+        let expected_hashed_keys: HashSet<_> = protective_reads
+            .iter()
+            .map(StorageKey::hashed_key)
+            .collect();
+        assert_eq!(hashed_keys, expected_hashed_keys);
+
+        let hashed_keys = conn
+            .storage_logs_dedup_dal()
+            .get_protective_reads_hashed_keys_for_l1_batch(L1BatchNumber(2))
+            .await;
+        assert!(hashed_keys.is_empty());
+    }
+
+    #[db_test(dal_crate)]
+    async fn getting_protective_reads_for_l1_batch_range(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal()
+            .delete_miniblocks(MiniblockNumber(0))
+            .await
+            .unwrap();
+        conn.blocks_dal()
+            .delete_l1_batches(L1BatchNumber(0))
+            .await
+            .unwrap();
+        conn.protocol_versions_dal()
+            .save_protocol_version_with_tx(ProtocolVersion::default())
+            .await;
+
+        let account = AccountTreeId::new(Address::repeat_byte(1));
+        let mut expected_reads = HashMap::new();
+        for number in 1..=4 {
+            insert_l1_batch(&mut conn, number, vec![]).await;
+            if number == 2 {
+                continue; // L1 batch #2 has no protective reads
+            }
+            let keys: HashSet<_> = (0..u64::from(number) * 3)
+                .map(|i| {
+                    StorageKey::new(account, H256::from_low_u64_be(u64::from(number) * 100 + i))
+                })
+                .collect();
+            let read_logs: Vec<_> = keys
+                .iter()
+                .map(|&key| StorageLog::new_read_log(key, H256::zero()).to_test_log_query())
+                .collect();
+            conn.storage_logs_dedup_dal()
+                .insert_protective_reads(L1BatchNumber(number), &read_logs)
+                .await;
+            expected_reads.insert(L1BatchNumber(number), keys);
+        }
+
+        for range in [1..=4, 1..=2, 2..=2, 3..=3, 3..=10, 5..=10] {
+            let l1_batch_numbers = L1BatchNumber(*range.start())..=L1BatchNumber(*range.end());
+            let protective_reads = conn
+                .storage_logs_dedup_dal()
+                .get_protective_reads_for_l1_batch_range(l1_batch_numbers.clone())
+                .await;
+            let protective_reads: HashMap<_, HashSet<_>> = protective_reads
+                .into_iter()
+                .map(|(number, keys)| (number, keys.into_iter().collect()))
+                .collect();
+            let expected_reads: HashMap<_, _> = expected_reads
+                .iter()
+                .filter(|(number, _)| l1_batch_numbers.contains(number))
+                .map(|(&number, keys)| (number, keys.clone()))
+                .collect();
+            assert_eq!(protective_reads, expected_reads, "{range:?}");
+
+            // Check consistency with the single-batch method.
+            for number in range.filter(|&number| number <= 4) {
+                let single_batch_reads = conn
+                    .storage_logs_dedup_dal()
+                    .get_protective_reads_for_l1_batch(L1BatchNumber(number))
+                    .await;
+                let range_reads = protective_reads
+                    .get(&L1BatchNumber(number))
+                    .cloned()
+                    .unwrap_or_default();
+                assert_eq!(single_batch_reads, range_reads);
+            }
+        }
+    }
+}
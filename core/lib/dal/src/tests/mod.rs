@@ -663,3 +663,100 @@ fn get_sample_proof() -> Vec<u8> {
     fs::read(format!("{}/etc/prover-test-data/proof.bin", zksync_home))
         .expect("Failed reading test proof file")
 }
+
+#[db_test(dal_crate)]
+async fn stale_witness_artifacts_workflow(connection_pool: ConnectionPool) {
+    let storage = &mut connection_pool.access_test_storage().await;
+    storage
+        .protocol_versions_dal()
+        .save_protocol_version_with_tx(Default::default())
+        .await;
+    let object_key = |number: u32| format!("merkel_tree_paths_{number}.bin");
+    for number in 1..=3 {
+        let header = L1BatchHeader::new(
+            L1BatchNumber(number),
+            0,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        );
+        storage
+            .blocks_dal()
+            .insert_l1_batch(&header, &[], Default::default())
+            .await
+            .unwrap();
+        storage
+            .witness_artifacts_dal()
+            .save_witness_artifact(
+                L1BatchNumber(number),
+                &object_key(number),
+                100,
+                H256::zero(),
+            )
+            .await;
+    }
+
+    let stale_count = storage
+        .witness_artifacts_dal()
+        .mark_witness_artifacts_as_stale(L1BatchNumber(1))
+        .await;
+    assert_eq!(stale_count, 2);
+    storage
+        .blocks_dal()
+        .delete_l1_batches(L1BatchNumber(1))
+        .await
+        .unwrap();
+    let stale_artifacts = storage
+        .witness_artifacts_dal()
+        .get_stale_witness_artifacts(10)
+        .await;
+    let stale_keys: Vec<_> = stale_artifacts.iter().map(|a| &a.object_key).collect();
+    assert_eq!(stale_keys, [&object_key(2), &object_key(3)]);
+
+    // Artifacts with failed removal attempts should be returned last.
+    storage
+        .witness_artifacts_dal()
+        .record_failed_stale_artifact_removals(&[object_key(2)])
+        .await;
+    let stale_artifacts = storage
+        .witness_artifacts_dal()
+        .get_stale_witness_artifacts(10)
+        .await;
+    assert_eq!(stale_artifacts[0].object_key, object_key(3));
+    assert_eq!(stale_artifacts[1].object_key, object_key(2));
+    assert_eq!(stale_artifacts[1].removal_attempts, 1);
+
+    // An object re-uploaded for a new L1 batch with the same number is no longer stale.
+    let header = L1BatchHeader::new(
+        L1BatchNumber(2),
+        1,
+        Default::default(),
+        Default::default(),
+        Default::default(),
+    );
+    storage
+        .blocks_dal()
+        .insert_l1_batch(&header, &[], Default::default())
+        .await
+        .unwrap();
+    storage
+        .witness_artifacts_dal()
+        .save_witness_artifact(L1BatchNumber(2), &object_key(2), 200, H256::zero())
+        .await;
+    let stale_artifacts = storage
+        .witness_artifacts_dal()
+        .get_stale_witness_artifacts(10)
+        .await;
+    let stale_keys: Vec<_> = stale_artifacts.iter().map(|a| &a.object_key).collect();
+    assert_eq!(stale_keys, [&object_key(3)]);
+
+    storage
+        .witness_artifacts_dal()
+        .remove_stale_witness_artifacts(&[object_key(3)])
+        .await;
+    let stale_artifacts = storage
+        .witness_artifacts_dal()
+        .get_stale_witness_artifacts(10)
+        .await;
+    assert!(stale_artifacts.is_empty());
+}
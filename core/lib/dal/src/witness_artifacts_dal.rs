@@ -0,0 +1,170 @@
+use sqlx::types::chrono::NaiveDateTime;
+
+use std::ops;
+
+use zksync_types::{L1BatchNumber, H256};
+
+use crate::StorageProcessor;
+
+/// Metadata of a witness input object uploaded to the object store for an L1 batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WitnessArtifact {
+    pub l1_batch_number: L1BatchNumber,
+    pub object_key: String,
+    /// Size of the stored object in bytes.
+    pub size: u64,
+    /// Blake2s-256 hash of the stored object.
+    pub hash: H256,
+    pub uploaded_at: NaiveDateTime,
+}
+
+/// Witness input object left in the object store after the L1 batch it was uploaded for was reverted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleWitnessArtifact {
+    pub l1_batch_number: L1BatchNumber,
+    pub object_key: String,
+    /// Number of failed attempts to remove the object from the object store.
+    pub removal_attempts: u32,
+}
+
+#[derive(Debug)]
+pub struct WitnessArtifactsDal<'a, 'c> {
+    pub(crate) storage: &'a mut StorageProcessor<'c>,
+}
+
+impl WitnessArtifactsDal<'_, '_> {
+    /// Records metadata of the witness input uploaded for an L1 batch. If the witness is re-uploaded,
+    /// the previously recorded metadata is overwritten. If the object was marked as stale (i.e., it was uploaded
+    /// for a reverted L1 batch with the same number), it is no longer considered stale.
+    pub async fn save_witness_artifact(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+        object_key: &str,
+        size: u64,
+        hash: H256,
+    ) {
+        sqlx::query!(
+            "INSERT INTO witness_input_artifacts \
+             (l1_batch_number, object_key, size, hash, uploaded_at, created_at, updated_at) \
+             VALUES ($1, $2, $3, $4, now(), now(), now()) \
+             ON CONFLICT (l1_batch_number) DO UPDATE \
+             SET object_key = $2, size = $3, hash = $4, uploaded_at = now(), updated_at = now()",
+            l1_batch_number.0 as i64,
+            object_key,
+            size as i64,
+            hash.as_bytes()
+        )
+        .execute(self.storage.conn())
+        .await
+        .unwrap();
+
+        sqlx::query!(
+            "DELETE FROM stale_witness_artifacts WHERE object_key = $1",
+            object_key
+        )
+        .execute(self.storage.conn())
+        .await
+        .unwrap();
+    }
+
+    /// Returns witness artifacts recorded for L1 batches in the specified range, ordered by L1 batch number.
+    pub async fn get_witness_artifacts(
+        &mut self,
+        l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+    ) -> Vec<WitnessArtifact> {
+        sqlx::query!(
+            "SELECT l1_batch_number, object_key, size, hash, uploaded_at \
+             FROM witness_input_artifacts \
+             WHERE l1_batch_number BETWEEN $1 AND $2 \
+             ORDER BY l1_batch_number",
+            l1_batch_numbers.start().0 as i64,
+            l1_batch_numbers.end().0 as i64
+        )
+        .fetch_all(self.storage.conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| WitnessArtifact {
+            l1_batch_number: L1BatchNumber(row.l1_batch_number as u32),
+            object_key: row.object_key,
+            size: row.size as u64,
+            hash: H256::from_slice(&row.hash),
+            uploaded_at: row.uploaded_at,
+        })
+        .collect()
+    }
+    /// Marks witness artifacts recorded for L1 batches after `last_l1_batch_to_keep` as stale, so that
+    /// the corresponding objects can be removed from the object store. Must be called before these L1 batches
+    /// are removed from Postgres since artifact metadata is removed together with L1 batches.
+    /// Returns the number of marked artifacts.
+    pub async fn mark_witness_artifacts_as_stale(
+        &mut self,
+        last_l1_batch_to_keep: L1BatchNumber,
+    ) -> usize {
+        let result = sqlx::query!(
+            "INSERT INTO stale_witness_artifacts \
+             (object_key, l1_batch_number, removal_attempts, created_at, updated_at) \
+             SELECT object_key, l1_batch_number, 0, now(), now() \
+             FROM witness_input_artifacts WHERE l1_batch_number > $1 \
+             ON CONFLICT (object_key) DO UPDATE \
+             SET l1_batch_number = excluded.l1_batch_number, updated_at = now()",
+            last_l1_batch_to_keep.0 as i64
+        )
+        .execute(self.storage.conn())
+        .await
+        .unwrap();
+        result.rows_affected() as usize
+    }
+
+    /// Returns up to `limit` stale witness artifacts, starting from ones with the fewest failed removal attempts.
+    /// Artifacts with an object key re-recorded for an existing L1 batch are skipped, so that the re-uploaded
+    /// object is not removed.
+    pub async fn get_stale_witness_artifacts(&mut self, limit: usize) -> Vec<StaleWitnessArtifact> {
+        sqlx::query!(
+            "SELECT l1_batch_number, object_key, removal_attempts \
+             FROM stale_witness_artifacts \
+             WHERE NOT EXISTS ( \
+                 SELECT 1 FROM witness_input_artifacts \
+                 WHERE witness_input_artifacts.object_key = stale_witness_artifacts.object_key \
+             ) \
+             ORDER BY removal_attempts, l1_batch_number \
+             LIMIT $1",
+            limit as i64
+        )
+        .fetch_all(self.storage.conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| StaleWitnessArtifact {
+            l1_batch_number: L1BatchNumber(row.l1_batch_number as u32),
+            object_key: row.object_key,
+            removal_attempts: row.removal_attempts as u32,
+        })
+        .collect()
+    }
+
+    /// Removes stale witness artifacts with the specified object keys after the objects were removed
+    /// from the object store.
+    pub async fn remove_stale_witness_artifacts(&mut self, object_keys: &[String]) {
+        sqlx::query!(
+            "DELETE FROM stale_witness_artifacts WHERE object_key = ANY($1)",
+            object_keys
+        )
+        .execute(self.storage.conn())
+        .await
+        .unwrap();
+    }
+
+    /// Records a failed attempt to remove objects for the specified stale witness artifacts.
+    pub async fn record_failed_stale_artifact_removals(&mut self, object_keys: &[String]) {
+        sqlx::query!(
+            "UPDATE stale_witness_artifacts \
+             SET removal_attempts = removal_attempts + 1, updated_at = now() \
+             WHERE object_key = ANY($1)",
+            object_keys
+        )
+        .execute(self.storage.conn())
+        .await
+        .unwrap();
+    }
+}
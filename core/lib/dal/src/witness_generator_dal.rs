@@ -1,7 +1,11 @@
 use itertools::Itertools;
 use sqlx::Row;
 
-use std::{collections::HashMap, ops::Range, time::Duration};
+use std::{
+    collections::HashMap,
+    ops::{Range, RangeInclusive},
+    time::Duration,
+};
 
 use zksync_types::proofs::{
     AggregationRound, JobCountStatistics, WitnessGeneratorJobMetadata, WitnessJobInfo,
@@ -742,6 +746,25 @@ impl WitnessGeneratorDal<'_, '_> {
         }
     }
 
+    /// Returns L1 batches in the specified range that have witness inputs saved (regardless
+    /// of the witness job status).
+    pub async fn get_l1_batches_with_witness_inputs(
+        &mut self,
+        l1_batch_numbers: RangeInclusive<L1BatchNumber>,
+    ) -> Vec<L1BatchNumber> {
+        sqlx::query!(
+            "SELECT l1_batch_number FROM witness_inputs WHERE l1_batch_number >= $1 AND l1_batch_number <= $2 ORDER BY l1_batch_number",
+            l1_batch_numbers.start().0 as i64,
+            l1_batch_numbers.end().0 as i64
+        )
+        .fetch_all(self.storage.conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| L1BatchNumber(row.l1_batch_number as u32))
+        .collect()
+    }
+
     pub async fn get_basic_circuit_and_circuit_inputs_blob_urls_to_be_cleaned(
         &mut self,
         limit: u8,
@@ -16,6 +16,8 @@ pub enum HealthStatus {
     NotReady,
     /// Component is ready for operations.
     Ready,
+    /// Component is operational, but is affected by a non-fatal issue (e.g., lags behind).
+    Affected,
     /// Component is shut down.
     ShutDown,
     /// Component has been abnormally interrupted by a panic.
@@ -25,15 +27,16 @@ pub enum HealthStatus {
 impl HealthStatus {
     /// Checks whether a component is ready according to this status.
     pub fn is_ready(self) -> bool {
-        matches!(self, Self::Ready)
+        matches!(self, Self::Ready | Self::Affected)
     }
 
     fn priority_for_aggregation(self) -> usize {
         match self {
             Self::Ready => 0,
-            Self::ShutDown => 1,
-            Self::NotReady => 2,
-            Self::Panicked => 3,
+            Self::Affected => 1,
+            Self::ShutDown => 2,
+            Self::NotReady => 3,
+            Self::Panicked => 4,
         }
     }
 }
@@ -257,4 +260,24 @@ mod tests {
         let updated = health_updater.update(health);
         assert!(updated);
     }
+
+    #[tokio::test]
+    async fn aggregating_affected_health_status() {
+        let (ready_check, ready_updater) = ReactiveHealthCheck::new("ready");
+        ready_updater.update(HealthStatus::Ready.into());
+        let (affected_check, affected_updater) = ReactiveHealthCheck::new("affected");
+        affected_updater.update(HealthStatus::Affected.into());
+
+        let mut checks: Vec<Box<dyn CheckHealth>> =
+            vec![Box::new(ready_check), Box::new(affected_check)];
+        let app_health = AppHealth::new(&checks).await;
+        assert!(app_health.is_ready());
+        assert_matches!(app_health.inner.status(), HealthStatus::Affected);
+
+        let (not_ready_check, _not_ready_updater) = ReactiveHealthCheck::new("not_ready");
+        checks.push(Box::new(not_ready_check));
+        let app_health = AppHealth::new(&checks).await;
+        assert!(!app_health.is_ready());
+        assert_matches!(app_health.inner.status(), HealthStatus::NotReady);
+    }
 }
@@ -0,0 +1,197 @@
+//! Copying the latest tree version to another database, which is used to defragment the tree.
+
+use std::{collections::HashMap, mem};
+
+use crate::{
+    storage::PatchSet,
+    types::{InternalNode, Manifest, Nibbles, Node, NodeKey, Root, TreeTags},
+    Database, MerkleTree,
+};
+
+/// Sink for copied tree nodes that flushes them to the target database in chunks.
+struct CopiedNodes<'a, T> {
+    target: &'a mut T,
+    manifest: Manifest,
+    version: u64,
+    chunk_size: usize,
+    chunk: HashMap<NodeKey, Node>,
+    copied_count: u64,
+}
+
+impl<T: Database> CopiedNodes<'_, T> {
+    fn push(&mut self, key: NodeKey, node: Node) {
+        self.chunk.insert(key, node);
+        self.copied_count += 1;
+        if self.chunk.len() >= self.chunk_size {
+            self.flush(None);
+        }
+    }
+
+    fn flush(&mut self, root: Option<Root>) {
+        let nodes = mem::take(&mut self.chunk);
+        let patch = PatchSet::for_copied_nodes(self.manifest.clone(), self.version, root, nodes);
+        self.target.apply_patch(patch);
+    }
+}
+
+impl<DB: Database> MerkleTree<'_, DB> {
+    /// Copies the latest version of this tree to the `target` database, which should be empty.
+    ///
+    /// All nodes reachable from the latest root are re-keyed to the latest version, so that
+    /// in the target database they are laid out in the key order, and stale nodes are not copied.
+    /// As a consequence, previous tree versions are inaccessible in the target database, similar to
+    /// a tree pruned up to the latest version. Since node versions are not hashed, the root hash
+    /// of the copied version is the same as in this tree.
+    ///
+    /// Nodes are written to `target` in chunks of `chunk_size` nodes, which bounds RAM usage
+    /// for large trees. Returns the number of copied nodes (excluding the root node).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero, or if a tree node reachable from the latest root is missing.
+    pub fn copy_latest_version<T: Database>(&self, target: &mut T, chunk_size: usize) -> u64 {
+        assert!(chunk_size > 0, "Chunk size must be positive");
+        let Some(version) = self.latest_version() else {
+            return 0; // The tree has no versions; nothing to copy
+        };
+        let mut manifest = self.db.manifest().unwrap_or_default();
+        if manifest.tags.is_none() {
+            manifest.tags = Some(TreeTags::new(self.hasher));
+        }
+        let mut root = self
+            .root(version)
+            .unwrap_or_else(|| panic!("missing root for the latest tree version {version}"));
+
+        let mut nodes = CopiedNodes {
+            target,
+            manifest,
+            version,
+            chunk_size,
+            chunk: HashMap::new(),
+            copied_count: 0,
+        };
+        if let Root::Filled {
+            node: Node::Internal(node),
+            ..
+        } = &mut root
+        {
+            self.copy_children(node, Nibbles::EMPTY, &mut nodes);
+        }
+        let copied_count = nodes.copied_count;
+        // The root is written last, so that the target tree doesn't reference missing nodes
+        // if copying is interrupted.
+        nodes.flush(Some(root));
+        copied_count
+    }
+
+    fn copy_children<T: Database>(
+        &self,
+        node: &mut InternalNode,
+        nibbles: Nibbles,
+        nodes: &mut CopiedNodes<'_, T>,
+    ) {
+        // Children are iterated in the ascending nibble order, so nodes are copied
+        // in the ascending key order.
+        for nibble in 0..InternalNode::CHILD_COUNT {
+            let Some(child_ref) = node.child_ref_mut(nibble) else {
+                continue;
+            };
+            let child_nibbles = nibbles
+                .push(nibble)
+                .unwrap_or_else(|| panic!("internal node at terminal tree level {nibbles}"));
+            let child_key = child_nibbles.with_version(child_ref.version);
+            let mut child = self
+                .db
+                .tree_node(&child_key, child_ref.is_leaf)
+                .unwrap_or_else(|| panic!("missing tree node at {child_key}"));
+            if let Node::Internal(child) = &mut child {
+                self.copy_children(child, child_nibbles, nodes);
+            }
+            child_ref.version = nodes.version;
+            nodes.push(child_nibbles.with_version(nodes.version), child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, ValueHash};
+
+    fn create_tree_with_versions(version_count: u64) -> MerkleTree<'static, PatchSet> {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        for version in 0..version_count {
+            let kvs = (0..50).map(|i| {
+                let key = Key::from(i * 1_000_003 + version * 7);
+                (key, ValueHash::from_low_u64_be(i + version))
+            });
+            tree.extend(kvs.collect());
+        }
+        tree
+    }
+
+    #[test]
+    fn copying_empty_tree() {
+        let tree = MerkleTree::new(PatchSet::default());
+        let mut target = PatchSet::default();
+        assert_eq!(tree.copy_latest_version(&mut target, 10), 0);
+        assert!(target.roots_mut().is_empty());
+    }
+
+    #[test]
+    fn copying_latest_version() {
+        let tree = create_tree_with_versions(10);
+        let latest_version = tree.latest_version().unwrap();
+        let keys: Vec<_> = (0..100).map(|i| Key::from(i * 1_000_003)).collect();
+        let expected_entries: Vec<_> = tree
+            .entries(latest_version, &keys)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.value_hash, entry.leaf_index))
+            .collect();
+        assert!(expected_entries
+            .iter()
+            .any(|&(_, leaf_index)| leaf_index > 0));
+
+        for chunk_size in [1, 7, 100, usize::MAX] {
+            let mut target = PatchSet::default();
+            let copied_count = tree.copy_latest_version(&mut target, chunk_size);
+            assert!(copied_count > 0);
+            assert_eq!(target.roots_mut().len(), 1);
+            // All nodes must be stored under the latest version.
+            let copied_keys: Vec<_> = target.nodes_mut().map(|(key, _)| *key).collect();
+            assert_eq!(copied_keys.len() as u64, copied_count);
+            assert!(copied_keys.iter().all(|key| key.version == latest_version));
+
+            let copied_tree = MerkleTree::new(target);
+            assert_eq!(copied_tree.latest_version(), Some(latest_version));
+            assert_eq!(copied_tree.latest_root_hash(), tree.latest_root_hash());
+            assert_eq!(copied_tree.root_hash(latest_version - 1), None);
+            copied_tree.verify_consistency(latest_version).unwrap();
+            let entries = copied_tree.entries(latest_version, &keys).unwrap();
+            let entries: Vec<_> = entries
+                .into_iter()
+                .map(|entry| (entry.value_hash, entry.leaf_index))
+                .collect();
+            assert_eq!(entries, expected_entries);
+        }
+    }
+
+    #[test]
+    fn copied_tree_can_be_extended() {
+        let mut tree = create_tree_with_versions(5);
+        let mut target = PatchSet::default();
+        tree.copy_latest_version(&mut target, 16);
+        let mut copied_tree = MerkleTree::new(target);
+
+        let kvs = vec![
+            (Key::from(1), ValueHash::repeat_byte(1)),
+            (Key::from(1_000_003), ValueHash::repeat_byte(2)),
+        ];
+        let output = tree.extend(kvs.clone());
+        let copied_output = copied_tree.extend(kvs);
+        assert_eq!(copied_output.root_hash, output.root_hash);
+        assert_eq!(copied_output.leaf_count, output.leaf_count);
+        copied_tree.verify_consistency(5).unwrap();
+    }
+}
@@ -2,10 +2,15 @@
 
 use rayon::{ThreadPool, ThreadPoolBuilder};
 
+use std::{collections::HashSet, ops};
+
 use crate::{
     storage::{MerkleTreeColumnFamily, PatchSet, Patched, RocksDBWrapper},
-    types::{Key, Root, TreeInstruction, TreeLogEntry, ValueHash, TREE_DEPTH},
-    BlockOutput, HashTree, MerkleTree,
+    types::{
+        Key, Root, TreeEntry, TreeEntryWithProof, TreeInstruction, TreeLogEntry,
+        TreeLogEntryWithProof, ValueHash, TREE_DEPTH,
+    },
+    BlockOutput, Database, HashTree, MerkleTree, MerkleTreePruner, NoVersionError,
 };
 use zksync_crypto::hasher::blake2::Blake2Hasher;
 use zksync_storage::RocksDB;
@@ -31,6 +36,28 @@ pub struct TreeMetadata {
     pub witness: Option<PrepareBasicCircuitsJob>,
 }
 
+/// Receiver of witness inputs produced by [`ZkSyncTree`] in the full processing mode. Allows to consume
+/// Merkle paths as they are produced (e.g., to write them to disk) rather than accumulating
+/// a [`PrepareBasicCircuitsJob`] in memory.
+pub trait WitnessSink: Send {
+    /// Called once before any Merkle paths are pushed to the sink.
+    fn start(&mut self, next_enumeration_index: u64, merkle_path_count: usize);
+
+    /// Pushes the next Merkle path. Paths always have the full length (i.e., [`TREE_DEPTH`] hashes).
+    fn push_merkle_path(&mut self, path: StorageLogMetadata);
+}
+
+impl WitnessSink for PrepareBasicCircuitsJob {
+    fn start(&mut self, next_enumeration_index: u64, merkle_path_count: usize) {
+        *self = Self::new(next_enumeration_index);
+        self.reserve(merkle_path_count);
+    }
+
+    fn push_merkle_path(&mut self, path: StorageLogMetadata) {
+        Self::push_merkle_path(self, path);
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum TreeMode {
     Lightweight,
@@ -48,6 +75,7 @@ pub struct ZkSyncTree {
     tree: MerkleTree<'static, Patched<RocksDBWrapper>>,
     thread_pool: Option<ThreadPool>,
     mode: TreeMode,
+    generate_witness: bool,
 }
 
 impl ZkSyncTree {
@@ -95,6 +123,7 @@ impl ZkSyncTree {
             tree: MerkleTree::new(Patched::new(wrapper)),
             thread_pool: None,
             mode,
+            generate_witness: true,
         }
     }
 
@@ -122,11 +151,93 @@ impl ZkSyncTree {
         self.thread_pool = Some(Self::create_thread_pool(thread_count));
     }
 
+    /// Enables or disables generating witness inputs ([`TreeMetadata::witness`]) when processing
+    /// L1 batches. Witness generation is enabled by default; it only has effect in the full processing mode.
+    pub fn set_witness_generation(&mut self, enabled: bool) {
+        self.generate_witness = enabled;
+    }
+
     /// Returns the current root hash of this tree.
     pub fn root_hash(&self) -> ValueHash {
         self.tree.latest_root_hash()
     }
 
+    /// Returns the root hash of the tree after processing the specified L1 batch, or `None` if
+    /// the L1 batch is not processed by the tree yet.
+    pub fn root_hash_at(&self, l1_batch_number: L1BatchNumber) -> Option<ValueHash> {
+        self.tree.root_hash(u64::from(l1_batch_number.0))
+    }
+
+    /// Reads entries for the specified keys from the tree version corresponding to the specified
+    /// L1 batch. Entries are returned in the same order as `keys`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn entries(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        keys: &[Key],
+    ) -> Result<Vec<TreeEntry>, NoVersionError> {
+        self.tree.entries(u64::from(l1_batch_number.0), keys)
+    }
+
+    /// Reads entries together with Merkle proofs for the specified keys from the tree version
+    /// corresponding to the specified L1 batch. Entries are returned in the same order as `keys`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn entries_with_proofs(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        keys: &[Key],
+    ) -> Result<Vec<TreeEntryWithProof>, NoVersionError> {
+        self.tree
+            .entries_with_proofs(u64::from(l1_batch_number.0), keys)
+    }
+
+    /// Estimates the number of new leaves (i.e., initial writes) that processing the provided storage logs
+    /// would insert into the tree at its latest version. The tree is not modified. Read logs and repeated
+    /// writes to the same key within `storage_logs` are not counted.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn estimate_new_leaves(&self, storage_logs: &[StorageLog]) -> u64 {
+        let written_keys: HashSet<_> = storage_logs
+            .iter()
+            .filter(|log| log.kind == StorageLogKind::Write)
+            .map(|log| log.key.hashed_key_u256())
+            .collect();
+        let Some(version) = self.tree.latest_version() else {
+            return written_keys.len() as u64;
+        };
+
+        let written_keys: Vec<_> = written_keys.into_iter().collect();
+        let entries = self
+            .tree
+            .entries(version, &written_keys)
+            .expect("latest tree version is missing");
+        entries.iter().filter(|entry| entry.is_empty()).count() as u64
+    }
+
+    /// Computes a fingerprint of the latest tree state, which captures all tree entries together
+    /// with their leaf indices. Unlike the root hash, the fingerprint doesn't depend on the tree hashing logic.
+    /// This method visits all tree nodes, so it is slow for large trees.
+    pub fn state_fingerprint(&self) -> ValueHash {
+        self.tree.latest_state_fingerprint()
+    }
+
+    /// Returns hashed keys and enumeration indices of all keys in the latest tree state, ordered
+    /// by the enumeration index. This method visits all tree nodes and keeps all keys in RAM,
+    /// so it should only be used for diagnostics and repairs.
+    pub fn leaves(&self) -> Vec<(Key, u64)> {
+        let Some(version) = self.tree.latest_version() else {
+            return vec![];
+        };
+        self.tree
+            .leaves(version)
+            .expect("latest tree version is missing")
+    }
+
     /// Checks whether this tree is empty.
     pub fn is_empty(&self) -> bool {
         let Some(version) = self.tree.latest_version() else {
@@ -146,6 +257,16 @@ impl ZkSyncTree {
         L1BatchNumber(number)
     }
 
+    /// Returns the earliest L1 batch for which the tree state is retained, or `None` if the tree is empty.
+    /// Tree versions for earlier L1 batches were pruned (or not copied during defragmentation), so the tree
+    /// cannot be queried at or reverted to these L1 batches.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn first_retained_l1_batch(&self) -> Option<L1BatchNumber> {
+        let version = self.tree.first_retained_version()?;
+        let number = u32::try_from(version).expect("integer overflow for L1 batch number");
+        Some(L1BatchNumber(number))
+    }
+
     /// Verifies tree consistency. `l1_batch_number` specifies the version of the tree
     /// to be checked, expressed as the number of latest L1 batch applied to the tree.
     ///
@@ -167,7 +288,84 @@ impl ZkSyncTree {
         }
     }
 
+    /// Processes storage logs for a single L1 batch split into `groups` (e.g., by L2 blocks). Returns metadata
+    /// for the entire L1 batch together with tree root hashes after applying each group. All groups
+    /// are committed as a single tree version, and the returned metadata is the same as if the concatenated
+    /// logs were processed via [`Self::process_l1_batch()`]; in particular, leaf indices are assigned
+    /// in the order of concatenated logs.
+    pub fn process_l1_batch_with_subroots(
+        &mut self,
+        groups: &[Vec<StorageLog>],
+    ) -> (TreeMetadata, Vec<ValueHash>) {
+        let storage_logs: Vec<_> = groups.iter().flatten().copied().collect();
+        let mut root_hash = self.tree.latest_root_hash();
+        // Per-log root hashes are only available with proofs, so the full processing mode is used
+        // regardless of the tree mode. Witness inputs are still only generated in the full mode.
+        let generate_witness = self.mode == TreeMode::Full && self.generate_witness;
+        let (metadata, log_root_hashes) =
+            self.process_l1_batch_with_proofs(&storage_logs, generate_witness);
+
+        let mut log_root_hashes = log_root_hashes.into_iter();
+        let subroots = groups
+            .iter()
+            .map(|group| {
+                // An empty group doesn't change the root hash.
+                if let Some(group_root_hash) = log_root_hashes.by_ref().take(group.len()).last() {
+                    root_hash = group_root_hash;
+                }
+                root_hash
+            })
+            .collect();
+        (metadata, subroots)
+    }
+
     fn process_l1_batch_full(&mut self, storage_logs: &[StorageLog]) -> TreeMetadata {
+        self.process_l1_batch_with_proofs(storage_logs, self.generate_witness)
+            .0
+    }
+
+    /// Processes storage logs comprising a single L1 batch, pushing the produced witness input
+    /// to `witness_sink` rather than returning it in [`TreeMetadata::witness`] (which is always `None`).
+    /// This allows to bound memory usage for L1 batches with large witnesses.
+    ///
+    /// In the lightweight processing mode, or if witness generation is disabled, `witness_sink` is not used.
+    pub fn process_l1_batch_with_witness(
+        &mut self,
+        storage_logs: &[StorageLog],
+        witness_sink: &mut dyn WitnessSink,
+    ) -> TreeMetadata {
+        match self.mode {
+            TreeMode::Full if self.generate_witness => {
+                self.process_l1_batch_with_proofs_inner(storage_logs, Some(witness_sink))
+                    .0
+            }
+            TreeMode::Full => self.process_l1_batch_full(storage_logs),
+            TreeMode::Lightweight => self.process_l1_batch_lightweight(storage_logs),
+        }
+    }
+
+    /// Processes storage logs in the full mode, returning tree root hashes after applying each log.
+    fn process_l1_batch_with_proofs(
+        &mut self,
+        storage_logs: &[StorageLog],
+        generate_witness: bool,
+    ) -> (TreeMetadata, Vec<ValueHash>) {
+        if generate_witness {
+            let mut witness = PrepareBasicCircuitsJob::new(0);
+            let (mut metadata, log_root_hashes) =
+                self.process_l1_batch_with_proofs_inner(storage_logs, Some(&mut witness));
+            metadata.witness = Some(witness);
+            (metadata, log_root_hashes)
+        } else {
+            self.process_l1_batch_with_proofs_inner(storage_logs, None)
+        }
+    }
+
+    fn process_l1_batch_with_proofs_inner(
+        &mut self,
+        storage_logs: &[StorageLog],
+        witness_sink: Option<&mut dyn WitnessSink>,
+    ) -> (TreeMetadata, Vec<ValueHash>) {
         let l1_batch_number = self.next_l1_batch_number();
         let instructions = Self::transform_logs(storage_logs);
         let starting_leaf_count = self.tree.latest_root().leaf_count();
@@ -184,9 +382,75 @@ impl ZkSyncTree {
             self.tree.extend_with_proofs(instructions.clone())
         };
 
-        let mut witness = PrepareBasicCircuitsJob::new(starting_leaf_count + 1);
-        witness.reserve(output.logs.len());
-        for (log, (key, instruction)) in output.logs.iter().zip(&instructions) {
+        if let Some(witness_sink) = witness_sink {
+            Self::write_witness(
+                starting_leaf_count,
+                &output.logs,
+                &instructions,
+                witness_sink,
+            );
+        }
+
+        let root_hash = output.root_hash().unwrap_or(starting_root_hash);
+        let log_root_hashes = output.logs.iter().map(|log| log.root_hash).collect();
+        let logs = output
+            .logs
+            .into_iter()
+            .filter_map(|log| (!log.base.is_read()).then_some(log.base));
+        let kvs = instructions.into_iter().filter_map(|(key, instruction)| {
+            let TreeInstruction::Write(value) = instruction else {
+                return None;
+            };
+            Some((key, value))
+        });
+        let (initial_writes, repeated_writes) = Self::extract_writes(logs, kvs);
+
+        tracing::info!(
+            "Processed batch #{l1_batch_number}; root hash is {root_hash}, \
+             {leaf_count} leaves in total, \
+             {initial_writes} initial writes, {repeated_writes} repeated writes",
+            leaf_count = output.leaf_count,
+            initial_writes = initial_writes.len(),
+            repeated_writes = repeated_writes.len()
+        );
+
+        let metadata = TreeMetadata {
+            root_hash,
+            rollup_last_leaf_index: output.leaf_count + 1,
+            initial_writes,
+            repeated_writes,
+            witness: None,
+        };
+        (metadata, log_root_hashes)
+    }
+
+    /// Checks whether the log is a no-op update, which must be omitted from the produced witness.
+    fn is_no_op_update(log: &TreeLogEntryWithProof, instruction: &TreeInstruction) -> bool {
+        match (log.base, instruction) {
+            (TreeLogEntry::Updated { previous_value, .. }, TreeInstruction::Write(value)) => {
+                previous_value == *value
+            }
+            _ => false,
+        }
+    }
+
+    fn write_witness(
+        starting_leaf_count: u64,
+        logs: &[TreeLogEntryWithProof],
+        instructions: &[(Key, TreeInstruction)],
+        witness: &mut dyn WitnessSink,
+    ) {
+        let merkle_path_count = logs
+            .iter()
+            .zip(instructions)
+            .filter(|(log, (_, instruction))| !Self::is_no_op_update(log, instruction))
+            .count();
+        witness.start(starting_leaf_count + 1, merkle_path_count);
+
+        for (log, (key, instruction)) in logs.iter().zip(instructions) {
+            if Self::is_no_op_update(log, instruction) {
+                continue;
+            }
             let empty_levels_end = TREE_DEPTH - log.merkle_path.len();
             let empty_subtree_hashes =
                 (0..empty_levels_end).map(|i| Blake2Hasher.empty_subtree_hash(i));
@@ -214,49 +478,13 @@ impl ZkSyncTree {
                 },
                 value_written,
                 value_read: match log.base {
-                    TreeLogEntry::Updated { previous_value, .. } => {
-                        if previous_value.0 == value_written {
-                            // A no-op update that must be omitted from the produced `witness`.
-                            continue;
-                        }
-                        previous_value.0
-                    }
+                    TreeLogEntry::Updated { previous_value, .. } => previous_value.0,
                     TreeLogEntry::Read { value, .. } => value.0,
                     TreeLogEntry::Inserted { .. } | TreeLogEntry::ReadMissingKey => [0_u8; 32],
                 },
             };
             witness.push_merkle_path(log);
         }
-
-        let root_hash = output.root_hash().unwrap_or(starting_root_hash);
-        let logs = output
-            .logs
-            .into_iter()
-            .filter_map(|log| (!log.base.is_read()).then_some(log.base));
-        let kvs = instructions.into_iter().filter_map(|(key, instruction)| {
-            let TreeInstruction::Write(value) = instruction else {
-                return None;
-            };
-            Some((key, value))
-        });
-        let (initial_writes, repeated_writes) = Self::extract_writes(logs, kvs);
-
-        tracing::info!(
-            "Processed batch #{l1_batch_number}; root hash is {root_hash}, \
-             {leaf_count} leaves in total, \
-             {initial_writes} initial writes, {repeated_writes} repeated writes",
-            leaf_count = output.leaf_count,
-            initial_writes = initial_writes.len(),
-            repeated_writes = repeated_writes.len()
-        );
-
-        TreeMetadata {
-            root_hash,
-            rollup_last_leaf_index: output.leaf_count + 1,
-            initial_writes,
-            repeated_writes,
-            witness: Some(witness),
-        }
     }
 
     fn transform_logs(storage_logs: &[StorageLog]) -> Vec<(Key, TreeInstruction)> {
@@ -351,15 +579,96 @@ impl ZkSyncTree {
         kvs.collect()
     }
 
+    /// Processes storage logs on top of the tree state after `base_l1_batch` rather than the latest
+    /// tree state. The changes are not retained: after this method returns, the tree has the same state
+    /// as the latest state in the database.
+    ///
+    /// This method will overwrite all unsaved changes in the tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to `base_l1_batch` is missing,
+    /// i.e., it was not created yet or was pruned.
+    pub fn process_l1_batch_on_base(
+        &mut self,
+        base_l1_batch: L1BatchNumber,
+        storage_logs: &[StorageLog],
+    ) -> Result<TreeMetadata, NoVersionError> {
+        self.tree.db.reset();
+        let base_version = u64::from(base_l1_batch.0);
+        let version_count = self.tree.latest_version().map_or(0, |version| version + 1);
+        if base_version >= version_count || self.tree.root(base_version).is_none() {
+            // The base version either doesn't exist yet or was pruned.
+            return Err(NoVersionError {
+                missing_version: base_version,
+                version_count,
+            });
+        }
+
+        self.tree.truncate_recent_versions(base_version + 1);
+        let metadata = self.process_l1_batch(storage_logs);
+        self.tree.db.reset();
+        Ok(metadata)
+    }
+
     /// Reverts the tree to a previous state.
     ///
     /// This method will overwrite all unsaved changes in the tree.
     pub fn revert_logs(&mut self, last_l1_batch_to_keep: L1BatchNumber) {
         self.tree.db.reset();
         let retained_version_count = u64::from(last_l1_batch_to_keep.0 + 1);
+        let version_count = self.tree.latest_version().map_or(0, |version| version + 1);
+        let removed_version_count = version_count.saturating_sub(retained_version_count);
+        tracing::info!(
+            "Reverting Merkle tree to L1 batch #{last_l1_batch_to_keep}; \
+             {removed_version_count} most recent versions will be removed on save"
+        );
         self.tree.truncate_recent_versions(retained_version_count);
     }
 
+    /// Prunes stale tree nodes persisted in RocksDB, so that only tree versions starting from
+    /// the specified L1 batch remain accessible. If `first_l1_batch_to_keep` exceeds the latest
+    /// persisted L1 batch, only the latest version is retained. Changes not yet saved to RocksDB
+    /// are not affected.
+    ///
+    /// Returns the number of pruned node keys.
+    pub fn prune_up_to(&mut self, first_l1_batch_to_keep: L1BatchNumber) -> usize {
+        let db = self.tree.db.inner_mut();
+        let Some(latest_version) = db
+            .manifest()
+            .and_then(|manifest| manifest.version_count.checked_sub(1))
+        else {
+            return 0; // The tree is empty; nothing to prune
+        };
+        let past_versions_to_keep =
+            latest_version.saturating_sub(u64::from(first_l1_batch_to_keep.0));
+        tracing::info!(
+            "Pruning Merkle tree up to L1 batch #{first_l1_batch_to_keep} \
+             (latest persisted L1 batch: #{latest_version})"
+        );
+        let (mut pruner, _handle) = MerkleTreePruner::new(db, past_versions_to_keep);
+        pruner.run_to_completion()
+    }
+
+    /// Copies the latest persisted version of this tree to `target_db`, which should be empty,
+    /// so that tree nodes are laid out in the key order and stale nodes are not copied.
+    /// The root hash of the copied tree is the same, but previous tree versions are inaccessible in it,
+    /// as if the tree was pruned up to the latest version. Consumer cursors are copied as well.
+    /// Changes not yet saved to RocksDB are not copied.
+    ///
+    /// Returns the number of copied tree nodes.
+    pub fn copy_defragmented(&self, target_db: RocksDB<MerkleTreeColumnFamily>) -> u64 {
+        /// Number of nodes written to the target RocksDB in a single write batch.
+        const CHUNK_SIZE: usize = 100_000;
+
+        let source = self.tree.db.inner();
+        let mut target = RocksDBWrapper::from(target_db);
+        let copied_node_count =
+            MerkleTree::new(source.clone()).copy_latest_version(&mut target, CHUNK_SIZE);
+        source.copy_cursors(&target);
+        copied_node_count
+    }
+
     /// Saves the accumulated changes in the tree to RocksDB.
     pub fn save(&mut self) {
         let mut l1_batch_numbers = self.tree.db.patched_versions();
@@ -368,8 +677,178 @@ impl ZkSyncTree {
         self.tree.db.flush();
     }
 
+    /// Saves the accumulated changes in the tree to RocksDB together with a cursor of a tree consumer.
+    /// The changes and the cursor are written in a single RocksDB write batch, so they are persisted
+    /// atomically; i.e., after a crash, the tree and the cursor cannot diverge. The cursor is written
+    /// even if the tree has no changes to save.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cursor_cf` contains tree data (see [`MerkleTreeColumnFamily::contains_tree_data()`]).
+    pub fn save_with_cursor(
+        &mut self,
+        cursor_cf: MerkleTreeColumnFamily,
+        cursor_key: &[u8],
+        cursor_value: &[u8],
+    ) {
+        assert!(
+            !cursor_cf.contains_tree_data(),
+            "Cannot write cursor to column family {cursor_cf:?} containing tree data"
+        );
+        let mut l1_batch_numbers = self.tree.db.patched_versions();
+        l1_batch_numbers.sort_unstable();
+        tracing::info!("Flushing L1 batches #{l1_batch_numbers:?} to RocksDB with consumer cursor");
+        self.tree.db.flush_with(|db, patch| {
+            db.apply_patch_with_cursor(patch, cursor_cf, cursor_key, cursor_value);
+        });
+    }
+
+    /// Fsyncs the RocksDB write-ahead log, so that changes saved to RocksDB survive a crash even if
+    /// sync writes are off. This is cheaper than flushing RocksDB memtables. Changes accumulated in RAM
+    /// are not affected; they must be saved first.
+    pub fn sync_wal(&self) {
+        self.tree.db.inner().sync_wal();
+    }
+
+    /// Reads a consumer cursor persisted via [`Self::save_with_cursor()`]. Changes accumulated
+    /// in RAM are not taken into account.
+    pub fn cursor(&self, cursor_cf: MerkleTreeColumnFamily, cursor_key: &[u8]) -> Option<Vec<u8>> {
+        self.tree.db.inner().cursor(cursor_cf, cursor_key)
+    }
+
     /// Resets the tree to the latest database state.
     pub fn reset(&mut self) {
         self.tree.db.reset();
     }
+
+    /// Creates a read-only handle to this tree sharing its RocksDB instance. The reader only observes
+    /// changes saved to RocksDB.
+    pub fn reader(&self) -> ZkSyncTreeReader {
+        ZkSyncTreeReader(MerkleTree::new(self.tree.db.inner().clone()))
+    }
+
+    /// Returns the number of live readers created via [`Self::reader()`], including their clones.
+    pub fn reader_count(&self) -> usize {
+        self.tree.db.inner().handle_count() - 1
+    }
+}
+
+/// Read-only handle to a [`ZkSyncTree`] obtained via [`ZkSyncTree::reader()`]. Can be used concurrently
+/// with the tree; only tree versions saved to RocksDB are visible to the reader.
+#[derive(Debug)]
+pub struct ZkSyncTreeReader(MerkleTree<'static, RocksDBWrapper>);
+
+impl Clone for ZkSyncTreeReader {
+    fn clone(&self) -> Self {
+        Self(MerkleTree::new(self.0.db.clone()))
+    }
+}
+
+impl ZkSyncTreeReader {
+    /// Creates a reader for the tree persisted in `db`. Unlike [`ZkSyncTree`], the reader never writes
+    /// to `db`, so it can be used with a DB opened via [`RocksDB::open_read_only()`].
+    pub fn new(db: RocksDB<MerkleTreeColumnFamily>) -> Self {
+        Self(MerkleTree::new(RocksDBWrapper::from(db)))
+    }
+
+    /// Returns the next L1 batch number that should be processed by the tree, based on the saved tree state.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn next_l1_batch_number(&self) -> L1BatchNumber {
+        let number = self.0.latest_version().map_or(0, |version| {
+            u32::try_from(version + 1).expect("integer overflow for L1 batch number")
+        });
+        L1BatchNumber(number)
+    }
+
+    /// Returns the root hash of the tree after processing the specified L1 batch, or `None` if
+    /// the L1 batch is not saved yet.
+    pub fn root_hash_at(&self, l1_batch_number: L1BatchNumber) -> Option<ValueHash> {
+        self.0.root_hash(u64::from(l1_batch_number.0))
+    }
+
+    /// Returns the earliest L1 batch for which the tree state is retained, or `None` if the tree is empty.
+    /// See [`ZkSyncTree::first_retained_l1_batch()`] for details.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn first_retained_l1_batch(&self) -> Option<L1BatchNumber> {
+        let version = self.0.first_retained_version()?;
+        let number = u32::try_from(version).expect("integer overflow for L1 batch number");
+        Some(L1BatchNumber(number))
+    }
+
+    /// Returns L1 batches from the specified range that have a tree version saved in RocksDB,
+    /// in the ascending order. L1 batches not processed by the tree yet or pruned are not included.
+    pub fn stored_versions(
+        &self,
+        l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+    ) -> Vec<L1BatchNumber> {
+        let Some(latest_version) = self.0.latest_version() else {
+            return vec![];
+        };
+        let start = u64::from(l1_batch_numbers.start().0);
+        let end = u64::from(l1_batch_numbers.end().0).min(latest_version);
+        (start..=end)
+            .filter(|&version| self.0.root(version).is_some())
+            .map(|version| L1BatchNumber(version as u32))
+            .collect()
+    }
+
+    /// Returns hashed keys and enumeration indices of all keys in the tree version corresponding
+    /// to the specified L1 batch, ordered by the enumeration index. This method visits all tree nodes
+    /// for the version and keeps all keys in RAM.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn leaves(
+        &self,
+        l1_batch_number: L1BatchNumber,
+    ) -> Result<Vec<(Key, u64)>, NoVersionError> {
+        self.0.leaves(u64::from(l1_batch_number.0))
+    }
+
+    /// Returns up to `limit` entries with keys exceeding `start_after` from the tree version corresponding
+    /// to the specified L1 batch, in the ascending key order. Can be used to iterate over all tree entries
+    /// in chunks without keeping all keys in RAM; see [`MerkleTree::entries_in_key_order()`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn entries_in_key_order(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        start_after: Option<Key>,
+        limit: usize,
+    ) -> Result<Vec<(Key, TreeEntry)>, NoVersionError> {
+        self.0
+            .entries_in_key_order(u64::from(l1_batch_number.0), start_after, limit)
+    }
+
+    /// Reads entries for the specified keys from the tree version corresponding to the specified
+    /// L1 batch. Entries are returned in the same order as `keys`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn entries(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        keys: &[Key],
+    ) -> Result<Vec<TreeEntry>, NoVersionError> {
+        self.0.entries(u64::from(l1_batch_number.0), keys)
+    }
+
+    /// Reads entries together with Merkle proofs for the specified keys from the tree version
+    /// corresponding to the specified L1 batch. Entries are returned in the same order as `keys`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn entries_with_proofs(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        keys: &[Key],
+    ) -> Result<Vec<TreeEntryWithProof>, NoVersionError> {
+        self.0
+            .entries_with_proofs(u64::from(l1_batch_number.0), keys)
+    }
 }
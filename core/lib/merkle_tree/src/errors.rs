@@ -2,7 +2,7 @@
 
 use std::{error, fmt, str::Utf8Error};
 
-use crate::types::NodeKey;
+use crate::types::{NodeKey, ValueHash};
 
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -164,6 +164,18 @@ impl fmt::Display for NoVersionError {
 
 impl error::Error for NoVersionError {}
 
+/// Error returned by [`TreeEntryWithProof::verify_continuity()`](crate::TreeEntryWithProof::verify_continuity())
+/// if continuity doesn't hold for a pair of proofs.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ContinuityError {
+    /// Leaf index of the key differs between the proofs.
+    #[error("Leaf index mismatch: {prev} (previous proof) vs {next} (next proof)")]
+    LeafIndexMismatch { prev: u64, next: u64 },
+    /// Value hash of the key differs between the proofs.
+    #[error("Value hash mismatch: {prev:?} (previous proof) vs {next:?} (next proof)")]
+    ValueHashMismatch { prev: ValueHash, next: ValueHash },
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
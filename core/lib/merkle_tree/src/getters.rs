@@ -1,9 +1,11 @@
 //! Getters for the Merkle tree.
 
+use blake2::{Blake2s256, Digest};
+
 use crate::{
     hasher::HasherWithStats,
     storage::{LoadAncestorsResult, SortedKeys, WorkingPatchSet},
-    types::{Nibbles, Node, TreeEntry, TreeEntryWithProof},
+    types::{Nibbles, Node, Root, TreeEntry, TreeEntryWithProof},
     Database, Key, MerkleTree, NoVersionError, ValueHash,
 };
 
@@ -41,13 +43,7 @@ where
         leaf_keys: &[Key],
         mut transform: impl FnMut(&mut WorkingPatchSet, &Key, &Nibbles) -> T,
     ) -> Result<Vec<T>, NoVersionError> {
-        let root = self.db.root(version).ok_or_else(|| {
-            let manifest = self.db.manifest().unwrap_or_default();
-            NoVersionError {
-                missing_version: version,
-                version_count: manifest.version_count,
-            }
-        })?;
+        let root = self.root_or_error(version)?;
         let sorted_keys = SortedKeys::new(leaf_keys.iter().copied());
         let mut patch_set = WorkingPatchSet::new(version, root);
         let LoadAncestorsResult {
@@ -61,6 +57,185 @@ where
             .collect())
     }
 
+    fn root_or_error(&self, version: u64) -> Result<Root, NoVersionError> {
+        self.db.root(version).ok_or_else(|| {
+            let manifest = self.db.manifest().unwrap_or_default();
+            NoVersionError {
+                missing_version: version,
+                version_count: manifest.version_count,
+            }
+        })
+    }
+
+    /// Computes a fingerprint of the full tree state at the specified version. The fingerprint
+    /// is a Blake2s-256 digest of all `(key, value_hash, leaf_index)` tuples in the tree, ordered by key.
+    /// Unlike the root hash, the fingerprint doesn't depend on the tree hashing logic, so it can be used
+    /// to compare tree states if the hashing logic is suspected to be faulty.
+    ///
+    /// This method visits all tree nodes for the version, so it is slow for large trees.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    pub fn state_fingerprint(&self, version: u64) -> Result<ValueHash, NoVersionError> {
+        let root = self.root_or_error(version)?;
+        Ok(self.fingerprint(root))
+    }
+
+    /// Computes a fingerprint of the latest tree state; see [`Self::state_fingerprint()`] for details.
+    /// If the tree has no versions, returns the fingerprint of an empty tree.
+    pub fn latest_state_fingerprint(&self) -> ValueHash {
+        self.fingerprint(self.latest_root())
+    }
+
+    fn fingerprint(&self, root: Root) -> ValueHash {
+        let mut digest = Blake2s256::new();
+        if let Root::Filled { node, .. } = root {
+            self.update_fingerprint(&mut digest, &node, Nibbles::EMPTY);
+        }
+        ValueHash::from_slice(&digest.finalize())
+    }
+
+    fn update_fingerprint(&self, digest: &mut Blake2s256, node: &Node, nibbles: Nibbles) {
+        match node {
+            Node::Leaf(leaf) => {
+                let mut key_bytes = [0_u8; 32];
+                leaf.full_key.to_big_endian(&mut key_bytes);
+                digest.update(key_bytes);
+                digest.update(leaf.value_hash.as_bytes());
+                digest.update(leaf.leaf_index.to_be_bytes());
+            }
+            Node::Internal(node) => {
+                // Children are iterated in the ascending nibble order, so leaves are visited
+                // in the ascending key order.
+                for (nibble, child_ref) in node.children() {
+                    let child_nibbles = nibbles.push(nibble).unwrap_or_else(|| {
+                        panic!("internal node at terminal tree level {nibbles}")
+                    });
+                    let child_key = child_nibbles.with_version(child_ref.version);
+                    let child = self
+                        .db
+                        .tree_node(&child_key, child_ref.is_leaf)
+                        .unwrap_or_else(|| panic!("missing tree node at {child_key}"));
+                    self.update_fingerprint(digest, &child, child_nibbles);
+                }
+            }
+        }
+    }
+
+    /// Returns keys and leaf indices of all leaves in the tree at the specified version, ordered
+    /// by the leaf index (i.e., in the order the keys were inserted into the tree).
+    ///
+    /// This method visits all tree nodes for the version and keeps all leaves in RAM,
+    /// so it should only be used for diagnostics and repairs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    pub fn leaves(&self, version: u64) -> Result<Vec<(Key, u64)>, NoVersionError> {
+        let root = self.root_or_error(version)?;
+        let mut leaves = Vec::with_capacity(usize::try_from(root.leaf_count()).unwrap_or(0));
+        if let Root::Filled { node, .. } = root {
+            self.collect_leaves(&node, Nibbles::EMPTY, &mut leaves);
+        }
+        leaves.sort_unstable_by_key(|&(_, leaf_index)| leaf_index);
+        Ok(leaves)
+    }
+
+    fn collect_leaves(&self, node: &Node, nibbles: Nibbles, leaves: &mut Vec<(Key, u64)>) {
+        match node {
+            Node::Leaf(leaf) => leaves.push((leaf.full_key, leaf.leaf_index)),
+            Node::Internal(node) => {
+                for (nibble, child_ref) in node.children() {
+                    let child_nibbles = nibbles.push(nibble).unwrap_or_else(|| {
+                        panic!("internal node at terminal tree level {nibbles}")
+                    });
+                    let child_key = child_nibbles.with_version(child_ref.version);
+                    let child = self
+                        .db
+                        .tree_node(&child_key, child_ref.is_leaf)
+                        .unwrap_or_else(|| panic!("missing tree node at {child_key}"));
+                    self.collect_leaves(&child, child_nibbles, leaves);
+                }
+            }
+        }
+    }
+
+    /// Returns up to `limit` entries of the tree at the specified version with keys exceeding `start_after`
+    /// (or starting from the least key if `start_after` is `None`) in the ascending key order.
+    ///
+    /// Tree nodes are traversed in the ascending nibble order, skipping subtrees with keys not exceeding
+    /// `start_after`, so only nodes on the paths to the returned leaves are loaded. Thus, the tree can be iterated
+    /// over in chunks (using the last returned key as `start_after` for the next chunk) without keeping
+    /// all its keys in RAM.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    pub fn entries_in_key_order(
+        &self,
+        version: u64,
+        start_after: Option<Key>,
+        limit: usize,
+    ) -> Result<Vec<(Key, TreeEntry)>, NoVersionError> {
+        let root = self.root_or_error(version)?;
+        let mut entries = Vec::with_capacity(limit.min(1_024));
+        if let Root::Filled { node, .. } = root {
+            self.collect_entries_after(&node, Nibbles::EMPTY, start_after, limit, &mut entries);
+        }
+        Ok(entries)
+    }
+
+    /// Collects leaves in the subtree rooted at `node`. If `start_after` is specified, `nibbles` must be
+    /// its prefix.
+    fn collect_entries_after(
+        &self,
+        node: &Node,
+        nibbles: Nibbles,
+        start_after: Option<Key>,
+        limit: usize,
+        entries: &mut Vec<(Key, TreeEntry)>,
+    ) {
+        match node {
+            Node::Leaf(leaf) => {
+                if start_after.map_or(true, |key| leaf.full_key > key) {
+                    entries.push((leaf.full_key, (*leaf).into()));
+                }
+            }
+            Node::Internal(node) => {
+                let start_nibble =
+                    start_after.map(|key| Nibbles::nibble(&key, nibbles.nibble_count()));
+                for (nibble, child_ref) in node.children() {
+                    if entries.len() >= limit {
+                        return;
+                    }
+                    let child_start_after = match start_nibble {
+                        // All keys in the child subtree are less than `start_after`.
+                        Some(start_nibble) if nibble < start_nibble => continue,
+                        Some(start_nibble) if nibble == start_nibble => start_after,
+                        // All keys in the child subtree exceed `start_after`.
+                        _ => None,
+                    };
+                    let child_nibbles = nibbles.push(nibble).unwrap_or_else(|| {
+                        panic!("internal node at terminal tree level {nibbles}")
+                    });
+                    let child_key = child_nibbles.with_version(child_ref.version);
+                    let child = self
+                        .db
+                        .tree_node(&child_key, child_ref.is_leaf)
+                        .unwrap_or_else(|| panic!("missing tree node at {child_key}"));
+                    self.collect_entries_after(
+                        &child,
+                        child_nibbles,
+                        child_start_after,
+                        limit,
+                        entries,
+                    );
+                }
+            }
+        }
+    }
+
     /// Reads entries together with Merkle proofs with the specified keys from the tree. The entries are returned
     /// in the same order as requested.
     ///
@@ -132,4 +307,131 @@ mod tests {
         assert!(entries[1].base.is_empty());
         entries[1].verify(tree.hasher, missing_key, output.root_hash);
     }
+
+    #[test]
+    fn state_fingerprint_basics() {
+        let kvs: Vec<_> = (0_u64..100)
+            .map(|i| {
+                (
+                    Key::from(i * 0x1_0000_0001),
+                    ValueHash::from_low_u64_be(i + 1),
+                )
+            })
+            .collect();
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+        let empty_fingerprint = tree.state_fingerprint(0).unwrap();
+        tree.extend(kvs.clone());
+        let fingerprint = tree.state_fingerprint(1).unwrap();
+        assert_ne!(fingerprint, empty_fingerprint);
+        assert_eq!(tree.state_fingerprint(0).unwrap(), empty_fingerprint);
+        tree.state_fingerprint(2).unwrap_err();
+        assert_eq!(tree.latest_state_fingerprint(), fingerprint);
+        let new_tree = MerkleTree::new(PatchSet::default());
+        assert_eq!(new_tree.latest_state_fingerprint(), empty_fingerprint);
+
+        let mut same_tree = MerkleTree::new(PatchSet::default());
+        same_tree.extend(kvs.clone());
+        assert_eq!(same_tree.state_fingerprint(0).unwrap(), fingerprint);
+
+        let mut other_kvs = kvs;
+        other_kvs[42].1 = ValueHash::repeat_byte(0xff);
+        let mut other_tree = MerkleTree::new(PatchSet::default());
+        other_tree.extend(other_kvs);
+        assert_ne!(other_tree.state_fingerprint(0).unwrap(), fingerprint);
+    }
+
+    #[test]
+    fn state_fingerprint_depends_on_leaf_indices() {
+        let kvs = vec![
+            (Key::from(1), ValueHash::repeat_byte(1)),
+            (Key::from(2), ValueHash::repeat_byte(2)),
+        ];
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(kvs.clone());
+        let mut reordered_tree = MerkleTree::new(PatchSet::default());
+        reordered_tree.extend(kvs.into_iter().rev().collect());
+
+        assert_ne!(
+            tree.state_fingerprint(0).unwrap(),
+            reordered_tree.state_fingerprint(0).unwrap()
+        );
+    }
+
+    #[test]
+    fn iterating_over_entries_in_key_order() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+        assert!(tree.entries_in_key_order(0, None, 10).unwrap().is_empty());
+
+        let keys: Vec<_> = (0_u64..200)
+            .map(|i| Key::from((200 - i) * 0x1_0000_0001) << (i % 4 * 60))
+            .collect();
+        let kvs = keys
+            .iter()
+            .zip(1_u64..)
+            .map(|(&key, i)| (key, ValueHash::from_low_u64_be(i)));
+        tree.extend(kvs.collect());
+        let mut expected_entries: Vec<_> = keys
+            .iter()
+            .zip(1_u64..)
+            .map(|(&key, i)| {
+                let entry = TreeEntry {
+                    value_hash: ValueHash::from_low_u64_be(i),
+                    leaf_index: i,
+                };
+                (key, entry)
+            })
+            .collect();
+        expected_entries.sort_unstable_by_key(|&(key, _)| key);
+
+        assert_eq!(
+            tree.entries_in_key_order(1, None, usize::MAX).unwrap(),
+            expected_entries
+        );
+        for chunk_size in [1, 7, 64] {
+            let mut entries = vec![];
+            let mut start_after = None;
+            loop {
+                let chunk = tree
+                    .entries_in_key_order(1, start_after, chunk_size)
+                    .unwrap();
+                assert!(chunk.len() <= chunk_size);
+                let Some(&(last_key, _)) = chunk.last() else {
+                    break;
+                };
+                start_after = Some(last_key);
+                entries.extend(chunk);
+            }
+            assert_eq!(entries, expected_entries);
+        }
+
+        // `start_after` doesn't need to be present in the tree.
+        let start_after = expected_entries[100].0 + 1;
+        let entries = tree.entries_in_key_order(1, Some(start_after), 5).unwrap();
+        assert_eq!(entries, expected_entries[101..106]);
+        let entries = tree.entries_in_key_order(1, Some(Key::MAX), 5).unwrap();
+        assert!(entries.is_empty());
+        tree.entries_in_key_order(2, None, 5).unwrap_err();
+    }
+
+    #[test]
+    fn leaves_are_ordered_by_leaf_index() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+        assert!(tree.leaves(0).unwrap().is_empty());
+
+        let keys: Vec<_> = (0_u64..50)
+            .map(|i| Key::from((50 - i) * 0x1_0000_0001))
+            .collect();
+        let kvs = keys.iter().map(|&key| (key, ValueHash::repeat_byte(1)));
+        tree.extend(kvs.collect());
+        // Updating existing keys must not change leaf indices.
+        tree.extend(vec![(keys[10], ValueHash::repeat_byte(2))]);
+
+        let expected_leaves: Vec<_> = keys.iter().copied().zip(1..).collect();
+        assert_eq!(tree.leaves(1).unwrap(), expected_leaves);
+        assert_eq!(tree.leaves(2).unwrap(), expected_leaves);
+        tree.leaves(3).unwrap_err();
+    }
 }
@@ -3,6 +3,7 @@
 use std::mem;
 
 use crate::{
+    errors::ContinuityError,
     hasher::{HashTree, HasherWithStats},
     types::{
         BlockOutputWithProofs, Key, LeafNode, TreeEntry, TreeEntryWithProof, TreeInstruction,
@@ -80,6 +81,32 @@ impl TreeEntryWithProof {
         );
         assert_eq!(root_hash, trusted_root_hash, "Root hash mismatch");
     }
+
+    /// Verifies continuity of this proof and `next_proof`, a proof for the same key obtained
+    /// at a later tree version. Continuity holds iff the key was not written between the versions,
+    /// i.e., its value and leaf index are unchanged. (Merkle paths may differ since other keys
+    /// could be written between the versions.)
+    ///
+    /// This method does not verify the proofs themselves; use [`Self::verify()`] for this.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if continuity doesn't hold.
+    pub fn verify_continuity(&self, next_proof: &Self) -> Result<(), ContinuityError> {
+        if self.base.leaf_index != next_proof.base.leaf_index {
+            return Err(ContinuityError::LeafIndexMismatch {
+                prev: self.base.leaf_index,
+                next: next_proof.base.leaf_index,
+            });
+        }
+        if self.base.value_hash != next_proof.base.value_hash {
+            return Err(ContinuityError::ValueHashMismatch {
+                prev: self.base.value_hash,
+                next: next_proof.base.value_hash,
+            });
+        }
+        Ok(())
+    }
 }
 
 /// Range digest in a Merkle tree allowing to compute its root hash based on the provided entries.
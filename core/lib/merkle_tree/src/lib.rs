@@ -42,6 +42,7 @@
 )]
 
 mod consistency;
+mod defragmentation;
 pub mod domain;
 mod errors;
 mod getters;
@@ -64,7 +65,7 @@ pub mod unstable {
 }
 
 pub use crate::{
-    errors::NoVersionError,
+    errors::{ContinuityError, NoVersionError},
     hasher::{HashTree, TreeRangeDigest},
     pruning::{MerkleTreePruner, MerkleTreePrunerHandle},
     storage::{
@@ -77,7 +78,7 @@ pub use crate::{
     },
 };
 
-use crate::{storage::Storage, types::Root};
+use crate::{metrics::TRUNCATION_METRICS, storage::Storage, types::Root};
 use zksync_crypto::hasher::blake2::Blake2Hasher;
 
 /// Binary Merkle tree implemented using AR16MT from Diem [Jellyfish Merkle tree] white paper.
@@ -174,6 +175,27 @@ impl<'a, DB: Database> MerkleTree<'a, DB> {
         self.db.manifest()?.version_count.checked_sub(1)
     }
 
+    /// Returns the earliest version of the tree retained in the database, or `None` if no versions
+    /// are present yet. Earlier versions were pruned (or not copied during defragmentation).
+    pub(crate) fn first_retained_version(&self) -> Option<u64> {
+        let latest_version = self.latest_version()?;
+        // Retained versions form a contiguous range ending at the latest version, so we can use binary search.
+        let (mut left, mut right) = (0, latest_version);
+        if self.root(left).is_some() {
+            right = left;
+        }
+        // Invariant: the version `left` is not retained, and the version `right` is retained.
+        while left + 1 < right {
+            let middle = (left + right) / 2;
+            if self.root(middle).is_some() {
+                right = middle;
+            } else {
+                left = middle;
+            }
+        }
+        Some(right)
+    }
+
     /// Returns the root hash for the latest version of the tree.
     pub fn latest_root_hash(&self) -> ValueHash {
         let root_hash = self
@@ -190,13 +212,17 @@ impl<'a, DB: Database> MerkleTree<'a, DB> {
 
     /// Removes the most recent versions from the database.
     ///
-    /// The current implementation does not actually remove node data for the removed versions
-    /// since it's likely to be reused in the future (especially upper-level internal nodes).
+    /// Nodes and stale keys for the removed versions are removed as well. For RocksDB, this is performed
+    /// using range deletions, so the cost of truncation doesn't depend on the number of removed versions.
     pub fn truncate_recent_versions(&mut self, retained_version_count: u64) {
         let mut manifest = self.db.manifest().unwrap_or_default();
         if manifest.version_count > retained_version_count {
+            let removed_version_count = manifest.version_count - retained_version_count;
+            TRUNCATION_METRICS
+                .removed_versions
+                .set(removed_version_count);
             manifest.version_count = retained_version_count;
-            let patch = PatchSet::from_manifest(manifest);
+            let patch = PatchSet::for_truncation(manifest);
             self.db.apply_patch(patch);
         }
     }
@@ -3,7 +3,7 @@
 use std::{
     fmt, ops,
     sync::atomic::{AtomicU64, Ordering},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::types::Nibbles;
@@ -233,6 +233,8 @@ struct PatchNodeStats {
 pub(crate) struct ApplyPatchStats {
     node_stats_by_nibble_count: [PatchNodeStats; MAX_TRACKED_NIBBLE_COUNT + 1],
     copied_hashes: u64,
+    /// First truncated version and the start of the truncation, if the patch truncates the tree.
+    truncation: Option<(u64, Instant)>,
 }
 
 impl ApplyPatchStats {
@@ -240,9 +242,18 @@ impl ApplyPatchStats {
         Self {
             node_stats_by_nibble_count: [PatchNodeStats::default(); MAX_TRACKED_NIBBLE_COUNT + 1],
             copied_hashes,
+            truncation: None,
         }
     }
 
+    pub fn start_truncation(&mut self, truncated_from_version: u64) {
+        tracing::info!(
+            "Removing nodes and stale keys for Merkle tree versions {truncated_from_version}.. \
+             using range deletions"
+        );
+        self.truncation = Some((truncated_from_version, Instant::now()));
+    }
+
     pub fn update_node_bytes(&mut self, key_nibbles: &Nibbles, node_bytes: &[u8]) {
         let nibble_count = key_nibbles.nibble_count();
         let idx = nibble_count.min(MAX_TRACKED_NIBBLE_COUNT);
@@ -268,9 +279,32 @@ impl ApplyPatchStats {
         }
 
         metrics.copied_hashes.observe(self.copied_hashes);
+
+        if let Some((truncated_from_version, started_at)) = self.truncation {
+            let elapsed = started_at.elapsed();
+            TRUNCATION_METRICS.latency.observe(elapsed);
+            tracing::info!(
+                "Removed nodes and stale keys for Merkle tree versions {truncated_from_version}.. \
+                 in {elapsed:?}"
+            );
+        }
     }
 }
 
+#[derive(Debug, Metrics)]
+#[metrics(prefix = "merkle_tree_truncation")]
+pub(crate) struct TruncationMetrics {
+    /// Number of versions removed by the latest tree truncation (e.g., on a revert).
+    pub removed_versions: Gauge<u64>,
+    /// Time spent persisting a tree truncation to RocksDB, including range deletions of data
+    /// for the removed versions.
+    #[metrics(buckets = Buckets::LATENCIES)]
+    pub latency: Histogram<Duration>,
+}
+
+#[vise::register]
+pub(crate) static TRUNCATION_METRICS: Global<TruncationMetrics> = Global::new();
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue, EncodeLabelSet)]
 #[metrics(label = "bound", rename_all = "snake_case")]
 enum Bound {
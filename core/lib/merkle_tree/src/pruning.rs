@@ -141,6 +141,21 @@ impl<DB: PruneDatabase> MerkleTreePruner<DB> {
         Some(stats)
     }
 
+    /// Prunes all stale keys up to the target retained version on the current thread.
+    /// Returns the total number of pruned keys.
+    pub(crate) fn run_to_completion(&mut self) -> usize {
+        let mut pruned_key_count = 0;
+        while let Some(stats) = self.run_once() {
+            pruned_key_count += stats.pruned_key_count;
+            let has_more_work = stats.has_more_work();
+            stats.report();
+            if !has_more_work {
+                break;
+            }
+        }
+        pruned_key_count
+    }
+
     /// Runs this pruner indefinitely until it is aborted by dropping its handle.
     pub fn run(mut self) {
         tracing::info!("Started Merkle tree pruner {self:?}");
@@ -144,9 +144,21 @@ impl Database for PatchSet {
             self.stale_keys_by_version
                 .retain(|&version, _| version < new_version_count);
         }
+        self.truncated_from_version =
+            match (self.truncated_from_version, other.truncated_from_version) {
+                (Some(this), Some(other)) => Some(this.min(other)),
+                (this, other) => this.or(other),
+            };
         self.manifest = other.manifest;
         self.roots.extend(other.roots);
-        self.nodes_by_version.extend(other.nodes_by_version);
+        // Nodes for the same version may be split among several patches (e.g., when copying a tree),
+        // so they are merged rather than replaced.
+        for (version, nodes) in other.nodes_by_version {
+            self.nodes_by_version
+                .entry(version)
+                .or_default()
+                .extend(nodes);
+        }
         self.stale_keys_by_version
             .extend(other.stale_keys_by_version);
     }
@@ -175,6 +187,11 @@ impl<DB: Database> Patched<DB> {
             .map_or_else(Vec::new, |patch| patch.roots.keys().copied().collect())
     }
 
+    /// Provides access to the wrapped DB, ignoring changes held in RAM.
+    pub(crate) fn inner(&self) -> &DB {
+        &self.inner
+    }
+
     /// Provides access to the wrapped DB. Should not be used to mutate DB data.
     pub(crate) fn inner_mut(&mut self) -> &mut DB {
         &mut self.inner
@@ -187,6 +204,13 @@ impl<DB: Database> Patched<DB> {
         }
     }
 
+    /// Flushes changes from RAM using the provided closure, which receives the wrapped database
+    /// and the pending changes (if any).
+    pub(crate) fn flush_with(&mut self, flush: impl FnOnce(&mut DB, Option<PatchSet>)) {
+        let patch = self.patch.take();
+        flush(&mut self.inner, patch);
+    }
+
     /// Forgets about changes held in RAM.
     pub fn reset(&mut self) {
         self.patch = None;
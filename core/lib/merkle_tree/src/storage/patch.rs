@@ -26,6 +26,9 @@ pub struct PatchSet {
     //   - Full upper levels (i.e., `Vec<Option<Node>>`)
     pub(super) nodes_by_version: HashMap<u64, HashMap<NodeKey, Node>>,
     pub(super) stale_keys_by_version: HashMap<u64, Vec<NodeKey>>,
+    /// If set, all persisted nodes and stale keys for versions starting from this one
+    /// must be removed when applying the patch.
+    pub(super) truncated_from_version: Option<u64>,
 }
 
 impl PatchSet {
@@ -35,6 +38,17 @@ impl PatchSet {
             roots: HashMap::new(),
             nodes_by_version: HashMap::new(),
             stale_keys_by_version: HashMap::new(),
+            truncated_from_version: None,
+        }
+    }
+
+    /// Creates a patch truncating the tree to `manifest.version_count` versions. Besides updating
+    /// the manifest, applying the patch removes all data for the truncated versions.
+    pub(crate) fn for_truncation(manifest: Manifest) -> Self {
+        let truncated_from_version = Some(manifest.version_count);
+        Self {
+            truncated_from_version,
+            ..Self::from_manifest(manifest)
         }
     }
 
@@ -63,6 +77,28 @@ impl PatchSet {
             roots: HashMap::from_iter([(version, root)]),
             nodes_by_version: HashMap::from_iter([(version, nodes)]),
             stale_keys_by_version: HashMap::from_iter([(version, stale_keys)]),
+            truncated_from_version: None,
+        }
+    }
+
+    /// Creates a patch with `nodes` copied from another tree and re-keyed to the specified `version`.
+    /// The patch may contain the root for this version; the root should be supplied in the last patch
+    /// for the version, after all nodes referenced by it are written.
+    pub(crate) fn for_copied_nodes(
+        manifest: Manifest,
+        version: u64,
+        root: Option<Root>,
+        nodes: HashMap<NodeKey, Node>,
+    ) -> Self {
+        debug_assert_eq!(manifest.version_count, version + 1);
+        debug_assert!(nodes.keys().all(|key| key.version == version));
+
+        Self {
+            manifest,
+            roots: root.map(|root| (version, root)).into_iter().collect(),
+            nodes_by_version: HashMap::from_iter([(version, nodes)]),
+            stale_keys_by_version: HashMap::new(),
+            truncated_from_version: None,
         }
     }
 
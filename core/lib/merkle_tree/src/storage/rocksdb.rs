@@ -13,7 +13,11 @@ use crate::{
     },
     types::{InternalNode, LeafNode, Manifest, Nibbles, Node, NodeKey, Root, StaleNodeKey},
 };
-use zksync_storage::{db::NamedColumnFamily, rocksdb::DBPinnableSlice, RocksDB};
+use zksync_storage::{
+    db::{NamedColumnFamily, WriteBatch},
+    rocksdb::DBPinnableSlice,
+    RocksDB,
+};
 
 /// RocksDB column families used by the tree.
 #[derive(Debug, Clone, Copy)]
@@ -23,16 +27,28 @@ pub enum MerkleTreeColumnFamily {
     Tree,
     /// Column family containing stale node keys that are eventually removed by the pruning logic.
     StaleKeys,
+    /// Column family containing cursors of tree consumers, which are persisted atomically
+    /// with tree changes. Not used by the tree itself.
+    Cursors,
+}
+
+impl MerkleTreeColumnFamily {
+    /// Checks whether this column family contains tree data and thus must not be written
+    /// by tree consumers.
+    pub fn contains_tree_data(self) -> bool {
+        matches!(self, Self::Tree | Self::StaleKeys)
+    }
 }
 
 impl NamedColumnFamily for MerkleTreeColumnFamily {
     const DB_NAME: &'static str = "merkle_tree";
-    const ALL: &'static [Self] = &[Self::Tree, Self::StaleKeys];
+    const ALL: &'static [Self] = &[Self::Tree, Self::StaleKeys, Self::Cursors];
 
     fn name(&self) -> &'static str {
         match self {
             Self::Tree => "default",
             Self::StaleKeys => "stale_keys",
+            Self::Cursors => "cursors",
         }
     }
 }
@@ -126,6 +142,114 @@ impl RocksDBWrapper {
         })
     }
 
+    /// Reads a consumer cursor from the specified column family.
+    pub(crate) fn cursor(&self, cf: MerkleTreeColumnFamily, key: &[u8]) -> Option<Vec<u8>> {
+        self.db
+            .get_cf(cf, key)
+            .expect("Failed reading from RocksDB")
+    }
+
+    /// Fsyncs the RocksDB write-ahead log.
+    pub(crate) fn sync_wal(&self) {
+        self.db.sync_wal().expect("Failed syncing RocksDB WAL");
+    }
+
+    /// Copies all consumer cursors to the `target` database.
+    pub(crate) fn copy_cursors(&self, target: &Self) {
+        let cursors_cf = MerkleTreeColumnFamily::Cursors;
+        let mut write_batch = target.db.new_write_batch();
+        for (key, value) in self.db.prefix_iterator_cf(cursors_cf, &[]) {
+            write_batch.put_cf(cursors_cf, &key, &value);
+        }
+        target
+            .db
+            .write(write_batch)
+            .expect("Failed writing a batch to RocksDB");
+    }
+
+    /// Applies the provided patch (if any) together with the consumer cursor in a single write batch,
+    /// so that they are persisted atomically.
+    pub(crate) fn apply_patch_with_cursor(
+        &mut self,
+        patch: Option<PatchSet>,
+        cursor_cf: MerkleTreeColumnFamily,
+        cursor_key: &[u8],
+        cursor_value: &[u8],
+    ) {
+        let mut write_batch = self.db.new_write_batch();
+        let metrics = patch.map(|patch| Self::fill_write_batch(&mut write_batch, patch));
+        write_batch.put_cf(cursor_cf, cursor_key, cursor_value);
+        self.db
+            .write(write_batch)
+            .expect("Failed writing a batch to RocksDB");
+        if let Some(metrics) = metrics {
+            metrics.report();
+        }
+    }
+
+    fn fill_write_batch(
+        write_batch: &mut WriteBatch<'_, MerkleTreeColumnFamily>,
+        patch: PatchSet,
+    ) -> ApplyPatchStats {
+        let tree_cf = MerkleTreeColumnFamily::Tree;
+        let mut node_bytes = Vec::with_capacity(128);
+        // ^ 128 looks somewhat reasonable as node capacity
+
+        let mut metrics = ApplyPatchStats::new(patch.copied_hashes_count());
+
+        patch.manifest.serialize(&mut node_bytes);
+        write_batch.put_cf(tree_cf, Self::MANIFEST_KEY, &node_bytes);
+
+        if let Some(truncated_version) = patch.truncated_from_version {
+            metrics.start_truncation(truncated_version);
+            // Remove all nodes and stale keys for truncated versions using range deletions,
+            // so that the cost of truncation doesn't depend on the number of removed versions.
+            // Since node keys and stale keys start with the big-endian version, these ranges
+            // cover exactly the truncated versions; the manifest key is not affected.
+            let start_key = NodeKey::empty(truncated_version).to_db_key();
+            let end_key = [u8::MAX; 9];
+            write_batch.delete_range_cf(tree_cf, &*start_key..&end_key[..]);
+            let stale_keys_cf = MerkleTreeColumnFamily::StaleKeys;
+            let start_key = truncated_version.to_be_bytes();
+            write_batch.delete_range_cf(stale_keys_cf, &start_key[..]..&end_key[..]);
+        }
+
+        for (root_version, root) in patch.roots {
+            node_bytes.clear();
+            let root_key = NodeKey::empty(root_version);
+            // Delete the key range corresponding to the entire new version. This removes
+            // potential garbage left after reverting the tree to a previous version.
+            let next_root_key = NodeKey::empty(root_version + 1);
+            let keys_to_delete = &*root_key.to_db_key()..&*next_root_key.to_db_key();
+            write_batch.delete_range_cf(tree_cf, keys_to_delete);
+
+            root.serialize(&mut node_bytes);
+            metrics.update_node_bytes(&Nibbles::EMPTY, &node_bytes);
+            write_batch.put_cf(tree_cf, &root_key.to_db_key(), &node_bytes);
+        }
+
+        let all_nodes = patch.nodes_by_version.into_values().flatten();
+        for (node_key, node) in all_nodes {
+            node_bytes.clear();
+            node.serialize(&mut node_bytes);
+            metrics.update_node_bytes(&node_key.nibbles, &node_bytes);
+            write_batch.put_cf(tree_cf, &node_key.to_db_key(), &node_bytes);
+        }
+
+        let stale_keys_cf = MerkleTreeColumnFamily::StaleKeys;
+        let all_stale_keys = patch
+            .stale_keys_by_version
+            .into_iter()
+            .flat_map(|(version, keys)| {
+                keys.into_iter()
+                    .map(move |key| StaleNodeKey::new(key, version))
+            });
+        for replaced_key in all_stale_keys {
+            write_batch.put_cf(stale_keys_cf, &replaced_key.to_db_key(), &[]);
+        }
+        metrics
+    }
+
     /// Returns the wrapped RocksDB instance.
     pub fn into_inner(self) -> RocksDB<MerkleTreeColumnFamily> {
         self.db
@@ -185,50 +309,8 @@ impl Database for RocksDBWrapper {
     }
 
     fn apply_patch(&mut self, patch: PatchSet) {
-        let tree_cf = MerkleTreeColumnFamily::Tree;
         let mut write_batch = self.db.new_write_batch();
-        let mut node_bytes = Vec::with_capacity(128);
-        // ^ 128 looks somewhat reasonable as node capacity
-
-        let mut metrics = ApplyPatchStats::new(patch.copied_hashes_count());
-
-        patch.manifest.serialize(&mut node_bytes);
-        write_batch.put_cf(tree_cf, Self::MANIFEST_KEY, &node_bytes);
-
-        for (root_version, root) in patch.roots {
-            node_bytes.clear();
-            let root_key = NodeKey::empty(root_version);
-            // Delete the key range corresponding to the entire new version. This removes
-            // potential garbage left after reverting the tree to a previous version.
-            let next_root_key = NodeKey::empty(root_version + 1);
-            let keys_to_delete = &*root_key.to_db_key()..&*next_root_key.to_db_key();
-            write_batch.delete_range_cf(tree_cf, keys_to_delete);
-
-            root.serialize(&mut node_bytes);
-            metrics.update_node_bytes(&Nibbles::EMPTY, &node_bytes);
-            write_batch.put_cf(tree_cf, &root_key.to_db_key(), &node_bytes);
-        }
-
-        let all_nodes = patch.nodes_by_version.into_values().flatten();
-        for (node_key, node) in all_nodes {
-            node_bytes.clear();
-            node.serialize(&mut node_bytes);
-            metrics.update_node_bytes(&node_key.nibbles, &node_bytes);
-            write_batch.put_cf(tree_cf, &node_key.to_db_key(), &node_bytes);
-        }
-
-        let stale_keys_cf = MerkleTreeColumnFamily::StaleKeys;
-        let all_stale_keys = patch
-            .stale_keys_by_version
-            .into_iter()
-            .flat_map(|(version, keys)| {
-                keys.into_iter()
-                    .map(move |key| StaleNodeKey::new(key, version))
-            });
-        for replaced_key in all_stale_keys {
-            write_batch.put_cf(stale_keys_cf, &replaced_key.to_db_key(), &[]);
-        }
-
+        let metrics = Self::fill_write_batch(&mut write_batch, patch);
         self.db
             .write(write_batch)
             .expect("Failed writing a batch to RocksDB");
@@ -17,7 +17,7 @@ pub enum TreeInstruction {
 }
 
 /// Entry in a Merkle tree associated with a key.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TreeEntry {
     /// Value associated with the key.
     pub value_hash: ValueHash,
@@ -4,11 +4,14 @@ use serde::{Deserialize, Serialize};
 use serde_with::{hex::Hex, serde_as};
 use tempfile::TempDir;
 
-use std::slice;
+use std::{fs, mem, slice, time::Instant};
 
 use zksync_config::constants::ACCOUNT_CODE_STORAGE_ADDRESS;
 use zksync_crypto::hasher::blake2::Blake2Hasher;
-use zksync_merkle_tree::{domain::ZkSyncTree, HashTree};
+use zksync_merkle_tree::{
+    domain::{WitnessSink, ZkSyncTree},
+    HashTree, MerkleTreeColumnFamily,
+};
 use zksync_storage::RocksDB;
 use zksync_types::{
     proofs::StorageLogMetadata, AccountTreeId, Address, L1BatchNumber, StorageKey, StorageLog, H256,
@@ -104,6 +107,54 @@ fn basic_workflow_multiblock() {
     assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(12));
 }
 
+#[test]
+fn computing_subroots_within_l1_batch() {
+    let logs = gen_storage_logs();
+    let (first_group, second_group) = logs.split_at(logs.len() / 3);
+    let groups = [first_group.to_vec(), vec![], second_group.to_vec()];
+
+    for lightweight in [false, true] {
+        let create_tree = |temp_dir: &TempDir| {
+            let db = RocksDB::new(temp_dir.as_ref(), false);
+            if lightweight {
+                ZkSyncTree::new_lightweight(db)
+            } else {
+                ZkSyncTree::new(db)
+            }
+        };
+
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let mut tree = create_tree(&temp_dir);
+        let expected_first_subroot = tree.process_l1_batch(first_group).root_hash;
+        tree.reset();
+        let expected_metadata = tree.process_l1_batch(&logs);
+        tree.reset();
+
+        let (metadata, subroots) = tree.process_l1_batch_with_subroots(&groups);
+        assert_eq!(metadata.root_hash, expected_metadata.root_hash);
+        assert_eq!(
+            metadata.rollup_last_leaf_index,
+            expected_metadata.rollup_last_leaf_index
+        );
+        assert_eq!(metadata.initial_writes, expected_metadata.initial_writes);
+        assert_eq!(metadata.witness.is_some(), !lightweight);
+        assert_eq!(
+            subroots,
+            [
+                expected_first_subroot,
+                expected_first_subroot,
+                expected_metadata.root_hash
+            ]
+        );
+
+        // All groups are committed as a single tree version.
+        tree.save();
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(1));
+        assert_eq!(tree.root_hash(), expected_metadata.root_hash);
+        tree.verify_consistency(L1BatchNumber(0));
+    }
+}
+
 #[test]
 fn filtering_out_no_op_writes() {
     let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
@@ -444,6 +495,55 @@ fn witness_workflow() {
     insta::assert_yaml_snapshot!("log-metadata-list-short", witnesses);
 }
 
+/// Witness sink collecting Merkle paths in memory without compacting them.
+#[derive(Debug, Default)]
+struct CollectingWitnessSink {
+    next_enumeration_index: Option<u64>,
+    expected_path_count: usize,
+    merkle_paths: Vec<StorageLogMetadata>,
+}
+
+impl WitnessSink for CollectingWitnessSink {
+    fn start(&mut self, next_enumeration_index: u64, merkle_path_count: usize) {
+        assert!(self.next_enumeration_index.is_none());
+        self.next_enumeration_index = Some(next_enumeration_index);
+        self.expected_path_count = merkle_path_count;
+    }
+
+    fn push_merkle_path(&mut self, path: StorageLogMetadata) {
+        assert_eq!(path.merkle_paths.len(), 256);
+        self.merkle_paths.push(path);
+    }
+}
+
+#[test]
+fn streaming_witnesses_to_sink() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let other_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let mut logs = gen_storage_logs();
+    // Add a no-op update, which must be omitted from witnesses.
+    logs.push(logs[0]);
+
+    let mut tree = ZkSyncTree::new(RocksDB::new(temp_dir.as_ref(), false));
+    let mut streaming_tree = ZkSyncTree::new(RocksDB::new(other_dir.as_ref(), false));
+    for block in logs.chunks(30) {
+        let metadata = tree.process_l1_batch(block);
+        let mut sink = CollectingWitnessSink::default();
+        let streamed_metadata = streaming_tree.process_l1_batch_with_witness(block, &mut sink);
+        assert!(streamed_metadata.witness.is_none());
+        assert_eq!(streamed_metadata.root_hash, metadata.root_hash);
+
+        let job = metadata.witness.unwrap();
+        assert_eq!(
+            sink.next_enumeration_index,
+            Some(job.next_enumeration_index())
+        );
+        assert_eq!(sink.expected_path_count, job.merkle_path_count());
+        let merkle_paths: Vec<_> = job.into_merkle_paths().collect();
+        assert_eq!(sink.merkle_paths, merkle_paths);
+    }
+}
+
 #[test]
 fn witnesses_with_multiple_blocks() {
     let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
@@ -485,3 +585,380 @@ fn witnesses_with_multiple_blocks() {
         "{non_empty_levels_by_block:?}"
     );
 }
+
+#[test]
+fn disabling_witness_generation() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let logs = gen_storage_logs();
+    let read_logs: Vec<_> = logs
+        .iter()
+        .map(|log| StorageLog::new_read_log(log.key, log.value))
+        .collect();
+
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new(db);
+    tree.set_witness_generation(false);
+    let metadata = tree.process_l1_batch(&logs);
+    assert!(metadata.witness.is_none());
+    let read_metadata = tree.process_l1_batch(&read_logs);
+    assert!(read_metadata.witness.is_none());
+    assert_eq!(read_metadata.root_hash, metadata.root_hash);
+
+    let other_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(other_dir.as_ref(), false);
+    let mut reference_tree = ZkSyncTree::new(db);
+    let reference_metadata = reference_tree.process_l1_batch(&logs);
+    assert!(reference_metadata.witness.is_some());
+    assert_eq!(reference_metadata.root_hash, metadata.root_hash);
+    assert_eq!(
+        reference_metadata.initial_writes.len(),
+        metadata.initial_writes.len()
+    );
+}
+
+#[test]
+fn saving_cursor_without_tree_changes() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    tree.process_l1_batch(&gen_storage_logs());
+    tree.save_with_cursor(MerkleTreeColumnFamily::Cursors, b"cursor", &[0]);
+    let root_hash = tree.root_hash();
+
+    // Saving a cursor without tree changes must not affect the tree.
+    tree.save_with_cursor(MerkleTreeColumnFamily::Cursors, b"cursor", &[1]);
+    drop(tree);
+
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let tree = ZkSyncTree::new_lightweight(db);
+    tree.verify_consistency(L1BatchNumber(0));
+    assert_eq!(tree.root_hash(), root_hash);
+    assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(1));
+    let cursor = tree.cursor(MerkleTreeColumnFamily::Cursors, b"cursor");
+    assert_eq!(cursor, Some(vec![1]));
+}
+
+/// Checks that the tree state is recovered from the synced WAL after a simulated crash. The crash is simulated
+/// by leaking the tree, so that RocksDB doesn't flush memtables on close, and opening a copy of its directory.
+/// The test cannot check durability on power loss (i.e., that the WAL is actually fsynced); it only checks
+/// that the synced state is recoverable from the WAL alone.
+#[test]
+fn synced_wal_survives_tree_crash() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    tree.process_l1_batch(&gen_storage_logs());
+    tree.save_with_cursor(MerkleTreeColumnFamily::Cursors, b"cursor", &[1]);
+    tree.sync_wal();
+    let root_hash = tree.root_hash();
+
+    // The original directory stays locked by the leaked RocksDB instance, so we open its copy.
+    let crashed_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let mut has_sst_files = false;
+    for entry in fs::read_dir(temp_dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        has_sst_files |= path.extension().map_or(false, |ext| ext == "sst");
+        fs::copy(&path, crashed_dir.path().join(path.file_name().unwrap())).unwrap();
+    }
+    mem::forget(tree);
+    assert!(!has_sst_files, "tree data was flushed to SST files");
+
+    let db = RocksDB::new(crashed_dir.as_ref(), false);
+    let tree = ZkSyncTree::new_lightweight(db);
+    tree.verify_consistency(L1BatchNumber(0));
+    assert_eq!(tree.root_hash(), root_hash);
+    assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(1));
+    let cursor = tree.cursor(MerkleTreeColumnFamily::Cursors, b"cursor");
+    assert_eq!(cursor, Some(vec![1]));
+}
+
+#[test]
+#[should_panic(expected = "containing tree data")]
+fn saving_cursor_to_tree_column_family() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    tree.save_with_cursor(MerkleTreeColumnFamily::Tree, b"cursor", &[0]);
+}
+
+#[test]
+fn pruning_old_tree_versions() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    assert_eq!(tree.prune_up_to(L1BatchNumber(0)), 0);
+    assert_eq!(tree.first_retained_l1_batch(), None);
+
+    let logs = gen_storage_logs();
+    let root_hashes: Vec<_> = logs
+        .chunks(20)
+        .map(|chunk| {
+            tree.process_l1_batch(chunk);
+            tree.root_hash()
+        })
+        .collect();
+    tree.save();
+    assert_eq!(tree.first_retained_l1_batch(), Some(L1BatchNumber(0)));
+
+    let pruned_key_count = tree.prune_up_to(L1BatchNumber(3));
+    assert!(pruned_key_count > 0);
+    // Pruning must be idempotent.
+    assert_eq!(tree.prune_up_to(L1BatchNumber(3)), 0);
+    assert_eq!(tree.first_retained_l1_batch(), Some(L1BatchNumber(3)));
+    assert_eq!(tree.root_hash_at(L1BatchNumber(2)), None);
+
+    tree.verify_consistency(L1BatchNumber(4));
+    assert_eq!(tree.root_hash_at(L1BatchNumber(3)), Some(root_hashes[3]));
+    assert_eq!(tree.root_hash_at(L1BatchNumber(4)), Some(root_hashes[4]));
+    assert_eq!(tree.root_hash(), root_hashes[4]);
+}
+
+fn deep_revert_batch_logs(
+    logs: &[StorageLog],
+    l1_batch_number: u32,
+    value_offset: u64,
+) -> Vec<StorageLog> {
+    let chunk_start = (l1_batch_number as usize * 3) % logs.len();
+    let value = H256::from_low_u64_be(u64::from(l1_batch_number) + value_offset);
+    logs[chunk_start..]
+        .iter()
+        .take(3)
+        .map(|log| StorageLog::new_write_log(log.key, value))
+        .collect()
+}
+
+fn assert_no_data_for_versions(db: &RocksDB<MerkleTreeColumnFamily>, first_removed_version: u64) {
+    for cf in [
+        MerkleTreeColumnFamily::Tree,
+        MerkleTreeColumnFamily::StaleKeys,
+    ] {
+        let mut keys = db.prefix_iterator_cf(cf, &[]).map(|(key, _)| key);
+        let removed_key = keys.find(|key| {
+            key.len() >= 8
+                && u64::from_be_bytes(key[..8].try_into().unwrap()) >= first_removed_version
+        });
+        assert!(removed_key.is_none(), "{cf:?}: {removed_key:?}");
+    }
+}
+
+#[test]
+fn deep_revert_removes_data_for_reverted_versions() {
+    const L1_BATCH_COUNT: u32 = 1_000;
+    const RETAINED_L1_BATCH_COUNT: u32 = 10;
+    const NEW_L1_BATCH_COUNT: u32 = 5;
+
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let logs = gen_storage_logs();
+    let batch_logs = |l1_batch_number, value_offset| {
+        deep_revert_batch_logs(&logs, l1_batch_number, value_offset)
+    };
+
+    {
+        let db = RocksDB::new(temp_dir.as_ref(), false);
+        let mut tree = ZkSyncTree::new_lightweight(db);
+        for l1_batch_number in 0..L1_BATCH_COUNT {
+            tree.process_l1_batch(&batch_logs(l1_batch_number, 0));
+        }
+        tree.save();
+
+        tree.revert_logs(L1BatchNumber(RETAINED_L1_BATCH_COUNT - 1));
+        tree.save();
+        assert_eq!(
+            tree.next_l1_batch_number(),
+            L1BatchNumber(RETAINED_L1_BATCH_COUNT)
+        );
+        // Use other values, so that the new tree versions differ from the reverted ones.
+        let new_l1_batches = RETAINED_L1_BATCH_COUNT..RETAINED_L1_BATCH_COUNT + NEW_L1_BATCH_COUNT;
+        for l1_batch_number in new_l1_batches {
+            tree.process_l1_batch(&batch_logs(l1_batch_number, 1 << 32));
+        }
+        tree.save();
+    }
+
+    // Check that no nodes or stale keys are retained for the reverted versions.
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let first_removed_version = u64::from(RETAINED_L1_BATCH_COUNT + NEW_L1_BATCH_COUNT);
+    assert_no_data_for_versions(&db, first_removed_version);
+
+    // Pruning must not remove nodes that are still live; this could happen if stale keys
+    // for the reverted versions were retained.
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    let latest_l1_batch = L1BatchNumber(RETAINED_L1_BATCH_COUNT + NEW_L1_BATCH_COUNT - 1);
+    let root_hash = tree.root_hash();
+    assert!(tree.prune_up_to(latest_l1_batch) > 0);
+    tree.verify_consistency(latest_l1_batch);
+    assert_eq!(tree.root_hash(), root_hash);
+}
+
+/// Benchmarks reverting the tree by ~50k versions. With range deletions, the revert should take roughly
+/// the same time as a shallow one. Ignored by default since building the tree takes a while;
+/// run with `cargo test -p zksync_merkle_tree --release deep_revert_benchmark -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn deep_revert_benchmark() {
+    const L1_BATCH_COUNT: u32 = 50_000;
+    const SAVE_INTERVAL: u32 = 1_000;
+    const RETAINED_L1_BATCH_COUNT: u32 = 10;
+
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let logs = gen_storage_logs();
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    let started_at = Instant::now();
+    for l1_batch_number in 0..L1_BATCH_COUNT {
+        tree.process_l1_batch(&deep_revert_batch_logs(&logs, l1_batch_number, 0));
+        if (l1_batch_number + 1) % SAVE_INTERVAL == 0 {
+            tree.save();
+        }
+    }
+    tree.save();
+    println!(
+        "Created tree with {L1_BATCH_COUNT} versions in {:?}",
+        started_at.elapsed()
+    );
+
+    let started_at = Instant::now();
+    tree.revert_logs(L1BatchNumber(RETAINED_L1_BATCH_COUNT - 1));
+    tree.save();
+    let revert_latency = started_at.elapsed();
+    println!(
+        "Reverted tree by {} versions in {revert_latency:?}",
+        L1_BATCH_COUNT - RETAINED_L1_BATCH_COUNT
+    );
+    assert_eq!(
+        tree.next_l1_batch_number(),
+        L1BatchNumber(RETAINED_L1_BATCH_COUNT)
+    );
+    tree.verify_consistency(L1BatchNumber(RETAINED_L1_BATCH_COUNT - 1));
+    drop(tree);
+
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    assert_no_data_for_versions(&db, u64::from(RETAINED_L1_BATCH_COUNT));
+}
+
+#[test]
+fn copying_defragmented_tree() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    let logs = gen_storage_logs();
+    for chunk in logs.chunks(20) {
+        tree.process_l1_batch(chunk);
+    }
+    tree.save_with_cursor(MerkleTreeColumnFamily::Cursors, b"cursor", &[4]);
+    let root_hash = tree.root_hash();
+    let fingerprint = tree.state_fingerprint();
+
+    let other_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(other_dir.as_ref(), false);
+    let copied_node_count = tree.copy_defragmented(db);
+    assert!(copied_node_count > 0);
+    drop(tree);
+
+    let db = RocksDB::new(other_dir.as_ref(), false);
+    let mut copied_tree = ZkSyncTree::new_lightweight(db);
+    copied_tree.verify_consistency(L1BatchNumber(4));
+    assert_eq!(copied_tree.root_hash(), root_hash);
+    assert_eq!(copied_tree.state_fingerprint(), fingerprint);
+    assert_eq!(copied_tree.next_l1_batch_number(), L1BatchNumber(5));
+    // Previous tree versions are not copied.
+    assert_eq!(copied_tree.root_hash_at(L1BatchNumber(3)), None);
+    let cursor = copied_tree.cursor(MerkleTreeColumnFamily::Cursors, b"cursor");
+    assert_eq!(cursor, Some(vec![4]));
+
+    // The copied tree must be usable for further updates.
+    let new_logs: Vec<_> = logs
+        .iter()
+        .take(10)
+        .map(|log| StorageLog::new_write_log(log.key, H256::repeat_byte(0xaa)))
+        .collect();
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    let expected_metadata = tree.process_l1_batch(&new_logs);
+    let metadata = copied_tree.process_l1_batch(&new_logs);
+    assert_eq!(metadata.root_hash, expected_metadata.root_hash);
+    copied_tree.save();
+    copied_tree.verify_consistency(L1BatchNumber(5));
+}
+
+#[test]
+fn listing_leaves_by_enumeration_index() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    assert!(tree.leaves().is_empty());
+
+    let logs = gen_storage_logs();
+    let mut expected_leaves = vec![];
+    for chunk in logs.chunks(30) {
+        let metadata = tree.process_l1_batch(chunk);
+        expected_leaves.extend(
+            metadata
+                .initial_writes
+                .iter()
+                .map(|write| (write.key, write.index)),
+        );
+    }
+    // Repeated writes must not influence enumeration indices.
+    tree.process_l1_batch(&logs[..10]);
+    tree.save();
+
+    let leaves = tree.leaves();
+    assert_eq!(leaves.len(), logs.len());
+    assert_eq!(leaves, expected_leaves);
+    let indices: Vec<_> = leaves.iter().map(|&(_, index)| index).collect();
+    assert_eq!(indices, (1..=logs.len() as u64).collect::<Vec<_>>());
+}
+
+#[test]
+fn listing_stored_versions() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    let reader = tree.reader();
+    let all_l1_batches = L1BatchNumber(0)..=L1BatchNumber(10);
+    assert!(reader.stored_versions(all_l1_batches.clone()).is_empty());
+
+    let logs = gen_storage_logs();
+    for chunk in logs.chunks(20) {
+        tree.process_l1_batch(chunk);
+    }
+    tree.save();
+    let stored_versions = reader.stored_versions(all_l1_batches.clone());
+    let expected_versions: Vec<_> = (0..5).map(L1BatchNumber).collect();
+    assert_eq!(stored_versions, expected_versions);
+    let stored_versions = reader.stored_versions(L1BatchNumber(2)..=L1BatchNumber(3));
+    assert_eq!(stored_versions, [L1BatchNumber(2), L1BatchNumber(3)]);
+
+    // Pruned versions must not be listed.
+    tree.prune_up_to(L1BatchNumber(3));
+    let stored_versions = reader.stored_versions(all_l1_batches);
+    assert_eq!(stored_versions, [L1BatchNumber(3), L1BatchNumber(4)]);
+}
+
+#[test]
+fn reader_only_observes_saved_changes() {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let db = RocksDB::new(temp_dir.as_ref(), false);
+    let mut tree = ZkSyncTree::new_lightweight(db);
+    let reader = tree.reader();
+    assert_eq!(reader.next_l1_batch_number(), L1BatchNumber(0));
+
+    let logs = gen_storage_logs();
+    let keys: Vec<_> = logs.iter().map(|log| log.key.hashed_key_u256()).collect();
+    tree.process_l1_batch(&logs[..50]);
+    assert_eq!(reader.next_l1_batch_number(), L1BatchNumber(0));
+    assert_eq!(reader.root_hash_at(L1BatchNumber(0)), None);
+
+    tree.save();
+    assert_eq!(reader.next_l1_batch_number(), L1BatchNumber(1));
+    let root_hash = tree.root_hash();
+    assert_eq!(reader.root_hash_at(L1BatchNumber(0)), Some(root_hash));
+
+    let entries = reader.entries_with_proofs(L1BatchNumber(0), &keys).unwrap();
+    for (i, (entry, key)) in entries.iter().zip(&keys).enumerate() {
+        assert_eq!(entry.base.is_empty(), i >= 50);
+        entry.verify(&Blake2Hasher, *key, root_hash);
+    }
+    assert!(reader.entries(L1BatchNumber(1), &keys).is_err());
+}
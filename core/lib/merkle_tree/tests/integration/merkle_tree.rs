@@ -7,8 +7,8 @@ use std::{cmp, mem};
 
 use zksync_crypto::hasher::{blake2::Blake2Hasher, Hasher};
 use zksync_merkle_tree::{
-    Database, HashTree, MerkleTree, PatchSet, Patched, TreeInstruction, TreeLogEntry,
-    TreeRangeDigest,
+    ContinuityError, Database, HashTree, MerkleTree, PatchSet, Patched, TreeEntryWithProof,
+    TreeInstruction, TreeLogEntry, TreeRangeDigest,
 };
 use zksync_types::{AccountTreeId, Address, StorageKey, H256, U256};
 
@@ -304,6 +304,43 @@ fn entry_proofs_are_computed_correctly_with_intermediate_commits() {
     }
 }
 
+fn prove_key_at_two_versions(updated_key_index: usize) -> (TreeEntryWithProof, TreeEntryWithProof) {
+    let kvs = generate_key_value_pairs(0..20);
+    let key = kvs[0].0;
+    let mut tree = MerkleTree::new(PatchSet::default());
+    let old_root_hash = tree.extend(kvs[..10].to_vec()).root_hash;
+
+    let mut new_kvs = kvs[10..].to_vec();
+    new_kvs.push((kvs[updated_key_index].0, H256::repeat_byte(0xff)));
+    let new_root_hash = tree.extend(new_kvs).root_hash;
+
+    let old_proof = tree.entries_with_proofs(0, &[key]).unwrap().pop().unwrap();
+    old_proof.verify(&Blake2Hasher, key, old_root_hash);
+    let new_proof = tree.entries_with_proofs(1, &[key]).unwrap().pop().unwrap();
+    new_proof.verify(&Blake2Hasher, key, new_root_hash);
+    assert_ne!(old_proof.merkle_path, new_proof.merkle_path);
+    (old_proof, new_proof)
+}
+
+#[test]
+fn proof_continuity_holds_for_unchanged_key() {
+    let (old_proof, new_proof) = prove_key_at_two_versions(1);
+    old_proof.verify_continuity(&new_proof).unwrap();
+}
+
+#[test]
+fn proof_continuity_does_not_hold_for_updated_key() {
+    let (old_proof, new_proof) = prove_key_at_two_versions(0);
+    let err = old_proof.verify_continuity(&new_proof).unwrap_err();
+    assert_eq!(
+        err,
+        ContinuityError::ValueHashMismatch {
+            prev: old_proof.base.value_hash,
+            next: H256::repeat_byte(0xff),
+        }
+    );
+}
+
 fn test_accumulated_commits<DB: Database>(db: DB, chunk_size: usize) -> DB {
     let (kvs, expected_hash) = &*KVS_AND_HASH;
     let mut db = Patched::new(db);
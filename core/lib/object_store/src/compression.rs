@@ -0,0 +1,263 @@
+//! Optional `zstd` compression of stored blobs.
+//!
+//! Compressed blobs start with [`COMPRESSED_BLOB_PREFIX`] followed by a `zstd` frame. Objects supporting
+//! compression (currently, only `PrepareBasicCircuitsJob`) detect the prefix on deserialization
+//! and decompress blobs transparently; blobs without the prefix are deserialized as is.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+/// Prefix of compressed blobs. The prefix cannot occur at the start of legacy `bincode`-serialized
+/// `PrepareBasicCircuitsJob`s: those start with the number of Merkle paths encoded as a little-endian `u64`,
+/// so the last prefix byte would need to be zero for any realistic number of paths.
+pub const COMPRESSED_BLOB_PREFIX: [u8; 8] = *b"zkzstd01";
+
+/// Checks whether the provided blob is compressed.
+pub fn is_compressed(blob: &[u8]) -> bool {
+    blob.starts_with(&COMPRESSED_BLOB_PREFIX)
+}
+
+/// Compresses the raw blob read from `reader` using the specified `zstd` compression level.
+///
+/// # Errors
+///
+/// Proxies I/O errors from `reader` and the compressor.
+pub fn compress(mut reader: impl Read, level: i32) -> io::Result<Vec<u8>> {
+    compress_into(vec![], level, |encoder| {
+        io::copy(&mut reader, encoder).map(drop)
+    })
+}
+
+/// Compresses the raw blob produced by `write_fn` using the specified `zstd` compression level,
+/// and writes the compressed blob (including [`COMPRESSED_BLOB_PREFIX`]) to `writer`. Returns `writer`.
+/// Unlike [`compress()`], this doesn't require the raw blob to be available as a whole.
+///
+/// # Errors
+///
+/// Proxies I/O errors from `write_fn`, `writer` and the compressor.
+pub fn compress_into<W: Write>(
+    writer: W,
+    level: i32,
+    write_fn: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<W> {
+    let mut encoder = CompressingWriter::new(writer, level)?;
+    write_fn(&mut encoder)?;
+    encoder.finish()
+}
+
+/// Writer compressing the raw blob written to it using `zstd` and writing the compressed blob
+/// (including [`COMPRESSED_BLOB_PREFIX`]) to the wrapped writer. Unlike [`compress_into()`], this allows
+/// producing the raw blob incrementally, e.g. across several calls. The compressed blob is complete only
+/// after [`Self::finish()`] is called.
+pub struct CompressingWriter<W: Write> {
+    encoder: zstd::Encoder<'static, W>,
+}
+
+impl<W: Write> fmt::Debug for CompressingWriter<W> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("CompressingWriter")
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W: Write> CompressingWriter<W> {
+    /// Creates a writer with the specified `zstd` compression level.
+    ///
+    /// # Errors
+    ///
+    /// Proxies I/O errors from `writer` when writing the blob prefix, and returns an error
+    /// if the compressor cannot be initialized.
+    pub fn new(mut writer: W, level: i32) -> io::Result<Self> {
+        writer.write_all(&COMPRESSED_BLOB_PREFIX)?;
+        Self::continuation(writer, level)
+    }
+
+    /// Creates a writer appending a new `zstd` frame to a compressed blob started with [`Self::new()`]
+    /// and written to `writer` (i.e., without writing [`COMPRESSED_BLOB_PREFIX`]). Since decompression
+    /// handles concatenated frames, the blob can be extended this way, e.g., to resume an interrupted write
+    /// at a frame boundary.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the compressor cannot be initialized.
+    pub fn continuation(writer: W, level: i32) -> io::Result<Self> {
+        Ok(Self {
+            encoder: zstd::Encoder::new(writer, level)?,
+        })
+    }
+
+    /// Finalizes the compressed blob and returns the wrapped writer.
+    ///
+    /// # Errors
+    ///
+    /// Proxies I/O errors from the wrapped writer and the compressor.
+    pub fn finish(self) -> io::Result<W> {
+        self.encoder.finish()
+    }
+}
+
+impl<W: Write> Write for CompressingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+/// Decompresses the blob if it's compressed; otherwise, returns it unchanged.
+///
+/// # Errors
+///
+/// Returns an error if the blob has the compression prefix, but cannot be decompressed.
+pub fn decompress_if_needed(blob: Vec<u8>) -> io::Result<Vec<u8>> {
+    if !is_compressed(&blob) {
+        return Ok(blob);
+    }
+    let mut decompressed = vec![];
+    decompressing_reader(&blob)?.read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Returns a reader streaming the decompressed blob contents, or the blob itself if it's not compressed.
+/// Unlike [`decompress_if_needed()`], this doesn't require holding the entire decompressed blob in memory.
+///
+/// # Errors
+///
+/// Returns an error if the blob has the compression prefix, but the decompressor cannot be initialized.
+pub fn decompressing_reader(blob: &[u8]) -> io::Result<Box<dyn Read + '_>> {
+    Ok(
+        if let Some(compressed) = blob.strip_prefix(&COMPRESSED_BLOB_PREFIX) {
+            Box::new(zstd::Decoder::new(compressed)?)
+        } else {
+            Box::new(blob)
+        },
+    )
+}
+
+/// Returns a reader streaming the decompressed contents of the blob read from `reader`, or the blob itself
+/// if it's not compressed. Unlike [`decompressing_reader()`], this doesn't require the blob to be loaded
+/// into memory (e.g., it can be streamed from a file).
+///
+/// # Errors
+///
+/// Proxies I/O errors from `reader` when reading the blob prefix, and returns an error if the decompressor
+/// cannot be initialized.
+pub fn decompressing_stream<R: Read>(mut reader: R) -> io::Result<DecompressingStream<R>> {
+    let mut prefix = Vec::with_capacity(COMPRESSED_BLOB_PREFIX.len());
+    (&mut reader)
+        .take(COMPRESSED_BLOB_PREFIX.len() as u64)
+        .read_to_end(&mut prefix)?;
+    let inner = if prefix == COMPRESSED_BLOB_PREFIX {
+        StreamInner::Compressed(zstd::Decoder::new(reader)?)
+    } else {
+        StreamInner::Raw(io::Cursor::new(prefix).chain(reader))
+    };
+    Ok(DecompressingStream { inner })
+}
+
+/// Reader returned by [`decompressing_stream()`].
+pub struct DecompressingStream<R: Read> {
+    inner: StreamInner<R>,
+}
+
+enum StreamInner<R: Read> {
+    Compressed(zstd::Decoder<'static, io::BufReader<R>>),
+    Raw(io::Chain<io::Cursor<Vec<u8>>, R>),
+}
+
+impl<R: Read> fmt::Debug for DecompressingStream<R> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let is_compressed = matches!(self.inner, StreamInner::Compressed(_));
+        formatter
+            .debug_struct("DecompressingStream")
+            .field("is_compressed", &is_compressed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read> Read for DecompressingStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            StreamInner::Compressed(decoder) => decoder.read(buf),
+            StreamInner::Raw(reader) => reader.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_roundtrip() {
+        for raw in [
+            vec![],
+            vec![0_u8; 1_024],
+            (0..=255).cycle().take(10_000).collect(),
+        ] {
+            let compressed = compress(raw.as_slice(), 3).unwrap();
+            assert!(is_compressed(&compressed));
+            assert_eq!(decompress_if_needed(compressed).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn streaming_decompression() {
+        for raw in [
+            vec![],
+            b"short".to_vec(),
+            (0..=255).cycle().take(10_000).collect(),
+        ] {
+            let compressed = compress(raw.as_slice(), 3).unwrap();
+            for blob in [&compressed, &raw] {
+                let mut decompressed = vec![];
+                decompressing_stream(blob.as_slice())
+                    .unwrap()
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+                assert_eq!(decompressed, raw);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_compression() {
+        let raw: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let mut writer = CompressingWriter::new(vec![], 3).unwrap();
+        for part in raw.chunks(1_000) {
+            writer.write_all(part).unwrap();
+        }
+        let compressed = writer.finish().unwrap();
+        assert!(is_compressed(&compressed));
+        assert_eq!(decompress_if_needed(compressed).unwrap(), raw);
+    }
+
+    #[test]
+    fn decompressing_concatenated_frames() {
+        let mut compressed = CompressingWriter::new(vec![], 3).unwrap().finish().unwrap();
+        for part in [b"first".as_slice(), b"", b"second"] {
+            let mut writer = CompressingWriter::continuation(&mut compressed, 3).unwrap();
+            writer.write_all(part).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let mut decompressed = vec![];
+        decompressing_stream(compressed.as_slice())
+            .unwrap()
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, b"firstsecond");
+    }
+
+    #[test]
+    fn uncompressed_blobs_are_returned_as_is() {
+        let raw = b"legacy blob".to_vec();
+        assert!(!is_compressed(&raw));
+        assert_eq!(decompress_if_needed(raw.clone()).unwrap(), raw);
+    }
+}
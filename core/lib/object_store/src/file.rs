@@ -1,9 +1,9 @@
 use async_trait::async_trait;
-use tokio::{fs, io};
+use tokio::{fs, io, io::AsyncWriteExt};
 
 use std::fmt::Debug;
 
-use crate::raw::{Bucket, ObjectStore, ObjectStoreError};
+use crate::raw::{Bucket, MultipartUpload, ObjectStore, ObjectStoreError};
 
 impl From<io::Error> for ObjectStoreError {
     fn from(err: io::Error) -> Self {
@@ -69,6 +69,51 @@ impl ObjectStore for FileBackedObjectStore {
         let filename = self.filename(bucket, key);
         fs::remove_file(filename).await.map_err(From::from)
     }
+
+    async fn start_multipart_upload(
+        &self,
+        bucket: Bucket,
+        key: &str,
+    ) -> Result<Box<dyn MultipartUpload + '_>, ObjectStoreError> {
+        let filename = self.filename(bucket, key);
+        let partial_filename = format!("{filename}.partial");
+        let file = fs::File::create(&partial_filename).await?;
+        Ok(Box::new(FileMultipartUpload {
+            filename,
+            partial_filename,
+            file,
+        }))
+    }
+}
+
+/// Multipart upload appending parts to a temporary file, which is renamed on completion.
+#[derive(Debug)]
+struct FileMultipartUpload {
+    filename: String,
+    partial_filename: String,
+    file: fs::File,
+}
+
+#[async_trait]
+impl MultipartUpload for FileMultipartUpload {
+    async fn upload_part(&mut self, part: Vec<u8>) -> Result<(), ObjectStoreError> {
+        self.file.write_all(&part).await.map_err(From::from)
+    }
+
+    async fn complete(mut self: Box<Self>) -> Result<(), ObjectStoreError> {
+        self.file.flush().await?;
+        drop(self.file);
+        fs::rename(&self.partial_filename, &self.filename)
+            .await
+            .map_err(From::from)
+    }
+
+    async fn abort(self: Box<Self>) -> Result<(), ObjectStoreError> {
+        drop(self.file);
+        fs::remove_file(&self.partial_filename)
+            .await
+            .map_err(From::from)
+    }
 }
 
 #[cfg(test)]
@@ -106,6 +151,43 @@ mod test {
         assert!(result.is_ok(), "result must be OK");
     }
 
+    #[tokio::test]
+    async fn test_multipart_upload() {
+        let dir = TempDir::new("test-data").unwrap();
+        let path = dir.into_path().into_os_string().into_string().unwrap();
+        let object_store = FileBackedObjectStore::new(path).await;
+        let mut upload = object_store
+            .start_multipart_upload(Bucket::ProverJobs, "test-key.bin")
+            .await
+            .unwrap();
+        upload.upload_part(vec![1, 2, 3]).await.unwrap();
+        upload.upload_part(vec![4, 5]).await.unwrap();
+        let err = object_store
+            .get_raw(Bucket::ProverJobs, "test-key.bin")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+
+        upload.complete().await.unwrap();
+        let bytes = object_store
+            .get_raw(Bucket::ProverJobs, "test-key.bin")
+            .await
+            .unwrap();
+        assert_eq!(bytes, [1, 2, 3, 4, 5]);
+
+        let mut upload = object_store
+            .start_multipart_upload(Bucket::ProverJobs, "aborted-key.bin")
+            .await
+            .unwrap();
+        upload.upload_part(vec![1, 2, 3]).await.unwrap();
+        upload.abort().await.unwrap();
+        let err = object_store
+            .get_raw(Bucket::ProverJobs, "aborted-key.bin")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+    }
+
     #[tokio::test]
     async fn test_remove() {
         let dir = TempDir::new("test-data").unwrap();
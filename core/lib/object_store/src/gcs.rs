@@ -10,15 +10,18 @@ use google_cloud_storage::{
         get::GetObjectRequest,
         upload::{Media, UploadObjectRequest, UploadType},
     },
+    http::resumable_upload_client::{ChunkSize, ResumableUploadClient, UploadStatus},
     http::Error as HttpError,
 };
 use http::StatusCode;
 
-use std::{fmt, future::Future, time::Duration};
+use std::{error, fmt, future::Future, time::Duration};
 
 use crate::{
     metrics::GCS_METRICS,
-    raw::{Bucket, ObjectStore, ObjectStoreError},
+    raw::{
+        Bucket, MultipartUpload, ObjectStore, ObjectStoreError, MULTIPART_UPLOAD_PART_ALIGNMENT,
+    },
 };
 
 async fn retry<T, E, Fut, F>(max_retries: u16, mut f: F) -> Result<T, E>
@@ -143,6 +146,28 @@ impl From<HttpError> for ObjectStoreError {
     }
 }
 
+/// Checks whether `err` is an HTTP error that won't be fixed by retrying the request.
+pub(crate) fn is_permanent_error(err: &(dyn error::Error + Send + Sync + 'static)) -> bool {
+    let Some(err) = err.downcast_ref::<HttpError>() else {
+        return false;
+    };
+    let status = match err {
+        HttpError::HttpClient(err) => err.status(),
+        HttpError::Response(response) => StatusCode::from_u16(response.code).ok(),
+        HttpError::TokenSource(_) => None,
+    };
+    status.map_or(false, is_permanent_status)
+}
+
+/// Client-side errors are permanent, except for ones signalling that the request should be retried later.
+fn is_permanent_status(status: StatusCode) -> bool {
+    status.is_client_error()
+        && !matches!(
+            status,
+            StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS
+        )
+}
+
 #[async_trait]
 impl ObjectStore for GoogleCloudStorage {
     async fn get_raw(&self, bucket: Bucket, key: &str) -> Result<Vec<u8>, ObjectStoreError> {
@@ -205,6 +230,131 @@ impl ObjectStore for GoogleCloudStorage {
     async fn remove_raw(&self, bucket: Bucket, key: &str) -> Result<(), ObjectStoreError> {
         self.remove_inner(bucket.as_str(), key).await
     }
+
+    async fn start_multipart_upload(
+        &self,
+        bucket: Bucket,
+        key: &str,
+    ) -> Result<Box<dyn MultipartUpload + '_>, ObjectStoreError> {
+        let filename = Self::filename(bucket.as_str(), key);
+        tracing::trace!(
+            "Starting resumable upload to GCS for key {filename} from bucket {}",
+            self.bucket_prefix
+        );
+
+        let upload_type = UploadType::Simple(Media::new(filename));
+        let request = UploadObjectRequest {
+            bucket: self.bucket_prefix.clone(),
+            ..Default::default()
+        };
+        let client = retry(self.max_retries, || {
+            self.client.prepare_resumable_upload(&request, &upload_type)
+        })
+        .await?;
+        Ok(Box::new(GcsMultipartUpload {
+            max_retries: self.max_retries,
+            bucket,
+            key: key.to_owned(),
+            client,
+            uploaded_len: 0,
+            pending_part: None,
+        }))
+    }
+}
+
+/// Multipart upload to GCS based on a resumable upload session. Since the last chunk of a resumable upload
+/// must specify the total object size, the last received part is held back until the upload is completed.
+struct GcsMultipartUpload {
+    max_retries: u16,
+    bucket: Bucket,
+    key: String,
+    client: ResumableUploadClient,
+    uploaded_len: u64,
+    pending_part: Option<Vec<u8>>,
+}
+
+impl fmt::Debug for GcsMultipartUpload {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("GcsMultipartUpload")
+            .field("bucket", &self.bucket)
+            .field("key", &self.key)
+            .field("uploaded_len", &self.uploaded_len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GcsMultipartUpload {
+    /// Uploads a chunk to GCS, retrying on errors. Since the chunk range is fixed, re-uploading a chunk
+    /// after a transient error is idempotent.
+    async fn upload_chunk(
+        &mut self,
+        chunk: Vec<u8>,
+        total_size: Option<u64>,
+    ) -> Result<UploadStatus, ObjectStoreError> {
+        let chunk_len = chunk.len() as u64;
+        let size = ChunkSize::new(
+            self.uploaded_len,
+            self.uploaded_len + chunk_len - 1,
+            total_size,
+        );
+        let status = retry(self.max_retries, || {
+            self.client.upload_multiple_chunk(chunk.clone(), &size)
+        })
+        .await?;
+        self.uploaded_len += chunk_len;
+        Ok(status)
+    }
+}
+
+#[async_trait]
+impl MultipartUpload for GcsMultipartUpload {
+    async fn upload_part(&mut self, part: Vec<u8>) -> Result<(), ObjectStoreError> {
+        if part.is_empty() {
+            return Ok(());
+        }
+        if let Some(previous_part) = self.pending_part.replace(part) {
+            if previous_part.len() % MULTIPART_UPLOAD_PART_ALIGNMENT != 0 {
+                let err = format!(
+                    "non-final part size {} is not divisible by {MULTIPART_UPLOAD_PART_ALIGNMENT}",
+                    previous_part.len()
+                );
+                return Err(ObjectStoreError::Other(err.into()));
+            }
+            self.upload_chunk(previous_part, None).await?;
+        }
+        Ok(())
+    }
+
+    async fn complete(mut self: Box<Self>) -> Result<(), ObjectStoreError> {
+        let store_latency = GCS_METRICS.start_store(self.bucket);
+        // Resumable uploads cannot finish with an empty chunk, so empty objects aren't supported.
+        let Some(last_part) = self.pending_part.take() else {
+            let err = format!("no data uploaded for key {}", self.key);
+            return Err(ObjectStoreError::Other(err.into()));
+        };
+        let total_size = self.uploaded_len + last_part.len() as u64;
+        let status = self.upload_chunk(last_part, Some(total_size)).await?;
+        if !matches!(status, UploadStatus::Ok(_)) {
+            let err = format!(
+                "resumable upload for key {} is incomplete after uploading the last chunk",
+                self.key
+            );
+            return Err(ObjectStoreError::Other(err.into()));
+        }
+
+        let elapsed = store_latency.observe();
+        tracing::trace!(
+            "Stored data to GCS for key {} from bucket {} in {total_size} bytes and it took: {elapsed:?}",
+            self.key,
+            self.bucket
+        );
+        Ok(())
+    }
+
+    async fn abort(self: Box<Self>) -> Result<(), ObjectStoreError> {
+        self.client.cancel().await.map_err(ObjectStoreError::from)
+    }
 }
 
 #[cfg(test)]
@@ -245,4 +395,25 @@ mod test {
         let result = retry(2, || retry_success_after_n_retries(2)).await;
         assert_eq!(result, Ok(42));
     }
+
+    #[test]
+    fn classifying_http_statuses() {
+        let permanent_statuses = [
+            StatusCode::BAD_REQUEST,
+            StatusCode::FORBIDDEN,
+            StatusCode::NOT_FOUND,
+        ];
+        for status in permanent_statuses {
+            assert!(is_permanent_status(status), "{status}");
+        }
+        let transient_statuses = [
+            StatusCode::REQUEST_TIMEOUT,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ];
+        for status in transient_statuses {
+            assert!(!is_permanent_status(status), "{status}");
+        }
+    }
 }
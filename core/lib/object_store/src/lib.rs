@@ -23,12 +23,14 @@
     clippy::doc_markdown
 )]
 
+pub mod compression;
 mod file;
 mod gcs;
 mod metrics;
 mod mock;
 mod objects;
 mod raw;
+pub mod streaming;
 
 // Re-export `bincode` crate so that client binaries can conveniently use it.
 pub use bincode;
@@ -39,6 +41,12 @@ pub mod _reexports {
 }
 
 pub use self::{
-    objects::{AggregationsKey, CircuitKey, ClosedFormInputKey, FriCircuitKey, StoredObject},
-    raw::{Bucket, ObjectStore, ObjectStoreError, ObjectStoreFactory},
+    objects::{
+        witness_input_chunk_key, AggregationsKey, CircuitKey, ClosedFormInputKey, FriCircuitKey,
+        StoredObject, WitnessInputFormat, WitnessInputHeader, VERSIONED_WITNESS_INPUT_PREFIX,
+    },
+    raw::{
+        Bucket, MultipartUpload, ObjectStore, ObjectStoreError, ObjectStoreFactory,
+        MULTIPART_UPLOAD_PART_ALIGNMENT,
+    },
 };
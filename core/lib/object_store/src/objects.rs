@@ -2,7 +2,10 @@
 
 use zksync_types::aggregated_operations::L1BatchProofForL1;
 use zksync_types::{
-    proofs::{AggregationRound, PrepareBasicCircuitsJob},
+    proofs::{
+        AggregationRound, ChunkedJobReader, DeduplicatedBasicCircuitsJob, PrepareBasicCircuitsJob,
+        PrepareBasicCircuitsJobManifest,
+    },
     storage::witness_block_state::WitnessBlockState,
     zkevm_test_harness::{
         abstract_zksync_circuit::concrete_circuits::ZkSyncCircuit,
@@ -13,10 +16,13 @@ use zksync_types::{
         LeafAggregationOutputDataWitness, NodeAggregationOutputDataWitness,
         SchedulerCircuitInstanceWitness,
     },
-    L1BatchNumber,
+    L1BatchNumber, ProtocolVersionId,
 };
 
-use crate::raw::{BoxedError, Bucket, ObjectStore, ObjectStoreError};
+use crate::{
+    compression,
+    raw::{BoxedError, Bucket, ObjectStore, ObjectStoreError},
+};
 
 /// Object that can be stored in an [`ObjectStore`].
 pub trait StoredObject: Sized {
@@ -82,9 +88,207 @@ impl StoredObject for PrepareBasicCircuitsJob {
         format!("merkel_tree_paths_{key}.bin")
     }
 
+    fn serialize(&self) -> Result<Vec<u8>, BoxedError> {
+        bincode::serialize(self).map_err(From::from)
+    }
+
+    /// Deserializes a job, transparently decompressing it if it was [compressed](crate::compression)
+    /// by the producer, and decoding it if it's stored in a [versioned format](WitnessInputHeader).
+    fn deserialize(bytes: Vec<u8>) -> Result<Self, BoxedError> {
+        let bytes = compression::decompress_if_needed(bytes)?;
+        let header = WitnessInputHeader::parse(&bytes)?;
+        decode_witness_input_payload(header, &bytes[header.encoded_len()..])
+    }
+}
+
+/// Decodes the payload of a `PrepareBasicCircuitsJob` serialization with the specified header.
+fn decode_witness_input_payload(
+    header: WitnessInputHeader,
+    payload: &[u8],
+) -> Result<PrepareBasicCircuitsJob, BoxedError> {
+    match WitnessPayloadLayout::for_protocol_version(header.protocol_version)? {
+        WitnessPayloadLayout::Initial => match header.format {
+            WitnessInputFormat::Legacy => bincode::deserialize(payload).map_err(From::from),
+            WitnessInputFormat::Deduplicated => {
+                let job: DeduplicatedBasicCircuitsJob = bincode::deserialize(payload)?;
+                job.into_job().map_err(From::from)
+            }
+        },
+    }
+}
+
+/// Layout of the `PrepareBasicCircuitsJob` payload, which depends on the protocol version
+/// of the L1 batch the job was produced for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WitnessPayloadLayout {
+    /// Layout used by L1 batches predating protocol versioning and by protocol versions
+    /// up to and including [`ProtocolVersionId::next()`].
+    Initial,
+}
+
+impl WitnessPayloadLayout {
+    /// Selects the payload layout for the specified protocol version. A protocol upgrade changing
+    /// the layout must introduce a new variant; until then, jobs for newer protocol versions are rejected
+    /// rather than misdecoded.
+    fn for_protocol_version(
+        protocol_version: Option<ProtocolVersionId>,
+    ) -> Result<Self, BoxedError> {
+        match protocol_version {
+            None => Ok(Self::Initial),
+            Some(version) if version <= ProtocolVersionId::next() => Ok(Self::Initial),
+            Some(version) => Err(format!(
+                "witness input payload layout for protocol version {version:?} is not known"
+            )
+            .into()),
+        }
+    }
+}
+
+/// Prefix of `PrepareBasicCircuitsJob` serializations in a versioned format. The prefix is followed
+/// by a single format version byte (see [`WitnessInputHeader`]) and the format-specific payload.
+/// Like [`COMPRESSED_BLOB_PREFIX`](compression::COMPRESSED_BLOB_PREFIX), the prefix cannot occur
+/// at the start of legacy serializations. Versioned serializations may be compressed; in this case,
+/// the prefix is a part of the compressed data.
+pub const VERSIONED_WITNESS_INPUT_PREFIX: [u8; 7] = *b"zkwitin";
+
+/// Payload format of a `PrepareBasicCircuitsJob` serialization.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WitnessInputFormat {
+    /// `bincode` serialization of the job in the compact form.
+    #[default]
+    Legacy,
+    /// `bincode` serialization of the [`DeduplicatedBasicCircuitsJob`].
+    Deduplicated,
+}
+
+/// Header of a `PrepareBasicCircuitsJob` serialization.
+///
+/// The header consists of [`VERSIONED_WITNESS_INPUT_PREFIX`] and a format version byte:
+///
+/// - Version 1: [deduplicated](WitnessInputFormat::Deduplicated) payload.
+/// - Version 2: [compact](WitnessInputFormat::Legacy) payload with the protocol version.
+/// - Version 3: [deduplicated](WitnessInputFormat::Deduplicated) payload with the protocol version.
+///
+/// Versions 2 and 3 are produced for L1 batches with a known protocol version if the producer is configured
+/// to record it; the version is recorded as a little-endian `u16` after the format version byte. Decoders
+/// select the payload layout based on the protocol version, so that a job produced for an unsupported protocol version
+/// is rejected rather than misdecoded. Legacy serializations have an empty header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WitnessInputHeader {
+    /// Format of the payload following the header.
+    pub format: WitnessInputFormat,
+    /// Protocol version of the L1 batch the job was produced for.
+    pub protocol_version: Option<ProtocolVersionId>,
+}
+
+impl WitnessInputHeader {
+    /// Maximum length of the header. Can be used to read a sufficiently long serialization start
+    /// for [`Self::parse()`].
+    pub const MAX_LEN: usize = VERSIONED_WITNESS_INPUT_PREFIX.len() + 3;
+
+    const DEDUPLICATED_VERSION: u8 = 1;
+    const VERSIONED_COMPACT_VERSION: u8 = 2;
+    const VERSIONED_DEDUPLICATED_VERSION: u8 = 3;
+
+    /// Creates a header for the specified payload format and protocol version.
+    pub fn new(format: WitnessInputFormat, protocol_version: Option<ProtocolVersionId>) -> Self {
+        Self {
+            format,
+            protocol_version,
+        }
+    }
+
+    /// Returns the format version byte, or `None` for legacy unversioned serializations.
+    fn format_version(self) -> Option<u8> {
+        match (self.format, self.protocol_version) {
+            (WitnessInputFormat::Legacy, None) => None,
+            (WitnessInputFormat::Deduplicated, None) => Some(Self::DEDUPLICATED_VERSION),
+            (WitnessInputFormat::Legacy, Some(_)) => Some(Self::VERSIONED_COMPACT_VERSION),
+            (WitnessInputFormat::Deduplicated, Some(_)) => {
+                Some(Self::VERSIONED_DEDUPLICATED_VERSION)
+            }
+        }
+    }
+
+    /// Returns the header to be written before the `bincode` serialization of the payload.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let Some(format_version) = self.format_version() else {
+            return vec![];
+        };
+        let mut header = VERSIONED_WITNESS_INPUT_PREFIX.to_vec();
+        header.push(format_version);
+        if let Some(protocol_version) = self.protocol_version {
+            header.extend_from_slice(&(protocol_version as u16).to_le_bytes());
+        }
+        header
+    }
+
+    /// Returns the length of the encoded header in bytes.
+    pub fn encoded_len(self) -> usize {
+        match self.format_version() {
+            None => 0,
+            Some(_) if self.protocol_version.is_none() => VERSIONED_WITNESS_INPUT_PREFIX.len() + 1,
+            Some(_) => Self::MAX_LEN,
+        }
+    }
+
+    /// Parses the header of an uncompressed serialization from its start (at least [`Self::MAX_LEN`]
+    /// bytes if the serialization is long enough).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the serialization has an unsupported format version, or is produced
+    /// for an unknown protocol version.
+    pub fn parse(serialization_start: &[u8]) -> Result<Self, BoxedError> {
+        let Some(rest) = serialization_start.strip_prefix(&VERSIONED_WITNESS_INPUT_PREFIX) else {
+            return Ok(Self::default());
+        };
+        let (format, has_protocol_version) = match rest.first() {
+            Some(&Self::DEDUPLICATED_VERSION) => (WitnessInputFormat::Deduplicated, false),
+            Some(&Self::VERSIONED_COMPACT_VERSION) => (WitnessInputFormat::Legacy, true),
+            Some(&Self::VERSIONED_DEDUPLICATED_VERSION) => (WitnessInputFormat::Deduplicated, true),
+            Some(version) => {
+                return Err(format!("unsupported witness input format version: {version}").into());
+            }
+            None => return Err("witness input format version is missing".into()),
+        };
+        if !has_protocol_version {
+            return Ok(Self::new(format, None));
+        }
+
+        let Some(&[lo, hi]) = rest.get(1..3) else {
+            return Err("witness input protocol version is truncated".into());
+        };
+        let raw_version = u16::from_le_bytes([lo, hi]);
+        let protocol_version = ProtocolVersionId::try_from(raw_version).map_err(|_| {
+            format!(
+                "witness input was produced for unknown protocol version {raw_version}; \
+                 it cannot be decoded by this version of the software"
+            )
+        })?;
+        Ok(Self::new(format, Some(protocol_version)))
+    }
+}
+
+/// Manifest of a `PrepareBasicCircuitsJob` stored in the chunked format. Chunks are stored
+/// in the same bucket under keys returned by [`witness_input_chunk_key()`].
+impl StoredObject for PrepareBasicCircuitsJobManifest {
+    const BUCKET: Bucket = Bucket::WitnessInput;
+    type Key<'a> = L1BatchNumber;
+
+    fn encode_key(key: Self::Key<'_>) -> String {
+        format!("witness_inputs_{key}_manifest.bin")
+    }
+
     serialize_using_bincode!();
 }
 
+/// Returns the key of a chunk of a `PrepareBasicCircuitsJob` stored in the chunked format. Chunks have
+/// the same format as `PrepareBasicCircuitsJob`s stored as a single object (i.e., may be compressed).
+pub fn witness_input_chunk_key(l1_batch_number: L1BatchNumber, chunk_index: usize) -> String {
+    format!("witness_inputs_{l1_batch_number}_{chunk_index}.bin")
+}
+
 impl StoredObject for BlockBasicCircuits<Bn256> {
     const BUCKET: Bucket = Bucket::LeafAggregationWitnessJobs;
     type Key<'a> = L1BatchNumber;
@@ -242,4 +446,76 @@ impl dyn ObjectStore + '_ {
         self.put_raw(V::BUCKET, &key, bytes).await?;
         Ok(key)
     }
+
+    /// Fetches the `PrepareBasicCircuitsJob` for the specified L1 batch. If the job is stored
+    /// in the chunked format (i.e., has a [manifest](PrepareBasicCircuitsJobManifest)), chunks are fetched
+    /// one by one, checked against the manifest and reassembled into the job; otherwise, the job
+    /// is fetched as a single object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job or any of its chunks does not exist, cannot be accessed,
+    /// cannot be deserialized, or is inconsistent with the manifest.
+    pub async fn get_witness_input(
+        &self,
+        l1_batch_number: L1BatchNumber,
+    ) -> Result<PrepareBasicCircuitsJob, ObjectStoreError> {
+        let manifest = match self
+            .get::<PrepareBasicCircuitsJobManifest>(l1_batch_number)
+            .await
+        {
+            Ok(manifest) => manifest,
+            Err(ObjectStoreError::KeyNotFound(_)) => return self.get(l1_batch_number).await,
+            Err(err) => return Err(err),
+        };
+
+        let mut reader = ChunkedJobReader::new(manifest);
+        while let Some(chunk_index) = reader.next_chunk_index() {
+            let key = witness_input_chunk_key(l1_batch_number, chunk_index);
+            let blob = self.get_raw(PrepareBasicCircuitsJob::BUCKET, &key).await?;
+            reader
+                .manifest()
+                .verify_chunk(chunk_index, &blob)
+                .map_err(|err| ObjectStoreError::Serialization(err.into()))?;
+            let chunk = PrepareBasicCircuitsJob::deserialize(blob)
+                .map_err(ObjectStoreError::Serialization)?;
+            reader
+                .push_chunk(chunk)
+                .map_err(|err| ObjectStoreError::Serialization(err.into()))?;
+        }
+        reader
+            .finish()
+            .map_err(|err| ObjectStoreError::Serialization(err.into()))
+    }
+
+    /// Removes the witness input for the specified L1 batch stored under `object_key`. If the key
+    /// refers to a [manifest](PrepareBasicCircuitsJobManifest), all chunks listed in it are removed
+    /// before the manifest itself, so that an interrupted removal can be retried. Chunks that are
+    /// already missing are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object under `object_key` does not exist or cannot be removed,
+    /// if the manifest cannot be deserialized, or if any of the chunks cannot be removed.
+    pub async fn remove_witness_input(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        object_key: &str,
+    ) -> Result<(), ObjectStoreError> {
+        let manifest_key = PrepareBasicCircuitsJobManifest::encode_key(l1_batch_number);
+        if object_key == manifest_key {
+            let manifest = self
+                .get::<PrepareBasicCircuitsJobManifest>(l1_batch_number)
+                .await?;
+            for chunk_index in 0..manifest.chunks.len() {
+                let key = witness_input_chunk_key(l1_batch_number, chunk_index);
+                match self.remove_raw(PrepareBasicCircuitsJob::BUCKET, &key).await {
+                    Ok(()) | Err(ObjectStoreError::KeyNotFound(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        self.remove_raw(PrepareBasicCircuitsJob::BUCKET, object_key)
+            .await
+    }
 }
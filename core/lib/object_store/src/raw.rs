@@ -3,7 +3,11 @@ use async_trait::async_trait;
 
 use std::{error, fmt, sync::Arc};
 
-use crate::{file::FileBackedObjectStore, gcs::GoogleCloudStorage, mock::MockStore};
+use crate::{
+    file::FileBackedObjectStore,
+    gcs::{self, GoogleCloudStorage},
+    mock::MockStore,
+};
 use zksync_config::configs::object_store::ObjectStoreMode;
 use zksync_config::ObjectStoreConfig;
 
@@ -60,6 +64,18 @@ pub enum ObjectStoreError {
     Other(BoxedError),
 }
 
+impl ObjectStoreError {
+    /// Checks whether this error is transient, i.e., whether the failed operation may succeed
+    /// if retried. Missing keys and (de)serialization errors are permanent. Other errors are transient
+    /// unless they are client-side HTTP errors (e.g., 403 Forbidden).
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::KeyNotFound(_) | Self::Serialization(_) => false,
+            Self::Other(err) => !gcs::is_permanent_error(err.as_ref()),
+        }
+    }
+}
+
 impl fmt::Display for ObjectStoreError {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -115,6 +131,86 @@ pub trait ObjectStore: fmt::Debug + Send + Sync {
     ///
     /// Returns an error if removal fails.
     async fn remove_raw(&self, bucket: Bucket, key: &str) -> Result<(), ObjectStoreError>;
+
+    /// Starts a multipart upload of the value with the given key into the given bucket. The value
+    /// is only stored once the returned upload is [completed](MultipartUpload::complete()).
+    ///
+    /// The default implementation buffers all parts in memory and stores the value
+    /// using [`Self::put_raw()`] on completion.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the upload cannot be started.
+    async fn start_multipart_upload(
+        &self,
+        bucket: Bucket,
+        key: &str,
+    ) -> Result<Box<dyn MultipartUpload + '_>, ObjectStoreError> {
+        Ok(Box::new(BufferedUpload {
+            store: self,
+            bucket,
+            key: key.to_owned(),
+            buffer: vec![],
+        }))
+    }
+}
+
+/// Alignment of parts in a [`MultipartUpload`]. All parts except for the last one must have size
+/// divisible by this value.
+pub const MULTIPART_UPLOAD_PART_ALIGNMENT: usize = 256 << 10;
+
+/// Multipart upload started with [`ObjectStore::start_multipart_upload()`].
+#[async_trait]
+pub trait MultipartUpload: fmt::Debug + Send {
+    /// Uploads the next part of the value. All parts except for the last one must have size divisible
+    /// by [`MULTIPART_UPLOAD_PART_ALIGNMENT`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the part cannot be uploaded. Implementations should retry transient errors
+    /// themselves; an error returned from this method is considered fatal for the upload.
+    async fn upload_part(&mut self, part: Vec<u8>) -> Result<(), ObjectStoreError>;
+
+    /// Completes the upload, making the value available in the store.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the upload cannot be completed.
+    async fn complete(self: Box<Self>) -> Result<(), ObjectStoreError>;
+
+    /// Aborts the upload, discarding all uploaded parts.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the upload cannot be aborted.
+    async fn abort(self: Box<Self>) -> Result<(), ObjectStoreError>;
+}
+
+/// Fallback [`MultipartUpload`] implementation buffering the entire value in memory.
+#[derive(Debug)]
+struct BufferedUpload<'a, S: ?Sized> {
+    store: &'a S,
+    bucket: Bucket,
+    key: String,
+    buffer: Vec<u8>,
+}
+
+#[async_trait]
+impl<S: ObjectStore + ?Sized> MultipartUpload for BufferedUpload<'_, S> {
+    async fn upload_part(&mut self, part: Vec<u8>) -> Result<(), ObjectStoreError> {
+        self.buffer.extend_from_slice(&part);
+        Ok(())
+    }
+
+    async fn complete(self: Box<Self>) -> Result<(), ObjectStoreError> {
+        self.store
+            .put_raw(self.bucket, &self.key, self.buffer)
+            .await
+    }
+
+    async fn abort(self: Box<Self>) -> Result<(), ObjectStoreError> {
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -135,6 +231,14 @@ impl<T: ObjectStore + ?Sized> ObjectStore for Arc<T> {
     async fn remove_raw(&self, bucket: Bucket, key: &str) -> Result<(), ObjectStoreError> {
         (**self).remove_raw(bucket, key).await
     }
+
+    async fn start_multipart_upload(
+        &self,
+        bucket: Bucket,
+        key: &str,
+    ) -> Result<Box<dyn MultipartUpload + '_>, ObjectStoreError> {
+        (**self).start_multipart_upload(bucket, key).await
+    }
 }
 
 #[derive(Debug)]
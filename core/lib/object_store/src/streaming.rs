@@ -0,0 +1,262 @@
+//! Streaming uploads of values produced by synchronous serializers.
+//!
+//! A value is serialized on a blocking thread into fixed-size parts, which are uploaded
+//! via [`MultipartUpload`] as soon as they are filled. The serializer is blocked while the upload
+//! lags behind, so that only a bounded number of parts is held in memory at any time.
+
+use tokio::sync::mpsc;
+
+use std::{io, mem};
+
+use crate::raw::{
+    Bucket, MultipartUpload, ObjectStore, ObjectStoreError, MULTIPART_UPLOAD_PART_ALIGNMENT,
+};
+
+/// Number of filled parts that may wait for upload. Together with the part being filled
+/// by the serializer and the part being uploaded, this bounds the memory used by the upload.
+const MAX_QUEUED_PARTS: usize = 1;
+
+/// Writer splitting the written data into parts and sending them for upload.
+#[derive(Debug)]
+struct PartWriter {
+    part_size: usize,
+    buffer: Vec<u8>,
+    parts_sender: mpsc::Sender<Vec<u8>>,
+    written_len: u64,
+}
+
+impl PartWriter {
+    fn new(part_size: usize, parts_sender: mpsc::Sender<Vec<u8>>) -> Self {
+        Self {
+            part_size,
+            buffer: Vec::with_capacity(part_size),
+            parts_sender,
+            written_len: 0,
+        }
+    }
+
+    fn send_buffer(&mut self) -> io::Result<()> {
+        let part = mem::replace(&mut self.buffer, Vec::with_capacity(self.part_size));
+        self.parts_sender
+            .blocking_send(part)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "upload was terminated"))
+    }
+
+    /// Sends the last (potentially incomplete) part and returns the total number of written bytes.
+    fn finish(mut self) -> io::Result<u64> {
+        if !self.buffer.is_empty() {
+            self.send_buffer()?;
+        }
+        Ok(self.written_len)
+    }
+}
+
+impl io::Write for PartWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.part_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        self.written_len += len as u64;
+        if self.buffer.len() == self.part_size {
+            self.send_buffer()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Parts are only sent once they are full, since all parts except for the last one
+        // must have the same aligned size.
+        Ok(())
+    }
+}
+
+/// Uploads the value written by `write_fn` into the given bucket under the specified key using
+/// a [`MultipartUpload`]. `write_fn` is executed on a blocking thread; the value is split into parts
+/// of `part_size` bytes which are uploaded concurrently with the serialization.
+///
+/// Returns the output of `write_fn` together with the size of the uploaded value.
+///
+/// # Errors
+///
+/// Returns an error if `write_fn` or the upload fails. In both cases, the upload is aborted.
+///
+/// # Panics
+///
+/// Panics if `part_size` is zero or is not divisible by [`MULTIPART_UPLOAD_PART_ALIGNMENT`].
+pub async fn upload_streaming<T, F>(
+    store: &dyn ObjectStore,
+    bucket: Bucket,
+    key: &str,
+    part_size: usize,
+    write_fn: F,
+) -> Result<(T, u64), ObjectStoreError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn io::Write) -> anyhow::Result<T> + Send + 'static,
+{
+    assert!(
+        part_size > 0 && part_size % MULTIPART_UPLOAD_PART_ALIGNMENT == 0,
+        "part size {part_size} is not a positive multiple of {MULTIPART_UPLOAD_PART_ALIGNMENT}"
+    );
+
+    let mut upload = store.start_multipart_upload(bucket, key).await?;
+    let (parts_sender, mut parts_receiver) = mpsc::channel(MAX_QUEUED_PARTS);
+    let producer = tokio::task::spawn_blocking(move || {
+        let mut writer = PartWriter::new(part_size, parts_sender);
+        let output = write_fn(&mut writer)?;
+        let len = writer.finish()?;
+        anyhow::Ok((output, len))
+    });
+
+    let mut upload_result = Ok(());
+    while let Some(part) = parts_receiver.recv().await {
+        upload_result = upload.upload_part(part).await;
+        if upload_result.is_err() {
+            break;
+        }
+    }
+    // Unblocks the producer if the upload has failed.
+    drop(parts_receiver);
+    let producer_result = match producer.await {
+        Ok(result) => result.map_err(|err| ObjectStoreError::Serialization(err.into())),
+        Err(err) => Err(ObjectStoreError::Other(err.into())),
+    };
+
+    // Upload errors take precedence since they are the root cause of producer errors in this case.
+    match upload_result.and(producer_result) {
+        Ok(output) => {
+            upload.complete().await?;
+            Ok(output)
+        }
+        Err(err) => {
+            if let Err(abort_err) = upload.abort().await {
+                tracing::warn!(
+                    "Failed aborting upload for key {key} in bucket {bucket}: {abort_err}"
+                );
+            }
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use std::io::Write;
+
+    use super::*;
+    use crate::mock::MockStore;
+
+    const PART_SIZE: usize = MULTIPART_UPLOAD_PART_ALIGNMENT;
+
+    #[tokio::test]
+    async fn uploading_value_in_multiple_parts() {
+        let store = MockStore::default();
+        let value: Vec<u8> = (0..=255).cycle().take(PART_SIZE * 5 / 2).collect();
+        let value_copy = value.clone();
+        let write_fn = move |writer: &mut dyn io::Write| -> anyhow::Result<i32> {
+            for chunk in value_copy.chunks(1_000) {
+                writer.write_all(chunk)?;
+            }
+            Ok(42)
+        };
+        let (output, len) =
+            upload_streaming(&store, Bucket::WitnessInput, "test", PART_SIZE, write_fn)
+                .await
+                .unwrap();
+
+        assert_eq!(output, 42);
+        assert_eq!(len, value.len() as u64);
+        let stored = store.get_raw(Bucket::WitnessInput, "test").await.unwrap();
+        assert_eq!(stored, value);
+    }
+
+    #[tokio::test]
+    async fn serialization_error_aborts_upload() {
+        let store = MockStore::default();
+        let write_fn = |writer: &mut dyn io::Write| -> anyhow::Result<()> {
+            writer.write_all(&vec![1; PART_SIZE * 2])?;
+            anyhow::bail!("oops")
+        };
+        let err = upload_streaming(&store, Bucket::WitnessInput, "test", PART_SIZE, write_fn)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, ObjectStoreError::Serialization(_)), "{err}");
+        let err = store
+            .get_raw(Bucket::WitnessInput, "test")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+    }
+
+    #[derive(Debug)]
+    struct FailingStore;
+
+    #[derive(Debug)]
+    struct FailingUpload;
+
+    #[async_trait]
+    impl ObjectStore for FailingStore {
+        async fn get_raw(&self, _bucket: Bucket, _key: &str) -> Result<Vec<u8>, ObjectStoreError> {
+            unreachable!()
+        }
+
+        async fn put_raw(
+            &self,
+            _bucket: Bucket,
+            _key: &str,
+            _value: Vec<u8>,
+        ) -> Result<(), ObjectStoreError> {
+            unreachable!()
+        }
+
+        async fn remove_raw(&self, _bucket: Bucket, _key: &str) -> Result<(), ObjectStoreError> {
+            unreachable!()
+        }
+
+        async fn start_multipart_upload(
+            &self,
+            _bucket: Bucket,
+            _key: &str,
+        ) -> Result<Box<dyn MultipartUpload + '_>, ObjectStoreError> {
+            Ok(Box::new(FailingUpload))
+        }
+    }
+
+    #[async_trait]
+    impl MultipartUpload for FailingUpload {
+        async fn upload_part(&mut self, _part: Vec<u8>) -> Result<(), ObjectStoreError> {
+            Err(ObjectStoreError::Other("network error".into()))
+        }
+
+        async fn complete(self: Box<Self>) -> Result<(), ObjectStoreError> {
+            unreachable!("upload must not be completed")
+        }
+
+        async fn abort(self: Box<Self>) -> Result<(), ObjectStoreError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn upload_error_terminates_serialization() {
+        let write_fn = |writer: &mut dyn io::Write| -> anyhow::Result<()> {
+            // Without the producer being terminated, this would block indefinitely.
+            loop {
+                writer.write_all(&[0; 1_024])?;
+            }
+        };
+        let err = upload_streaming(
+            &FailingStore,
+            Bucket::WitnessInput,
+            "test",
+            PART_SIZE,
+            write_fn,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(err, ObjectStoreError::Other(_)), "{err}");
+    }
+}
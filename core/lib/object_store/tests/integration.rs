@@ -2,10 +2,16 @@
 
 use tokio::fs;
 
-use zksync_object_store::{Bucket, ObjectStoreFactory};
+use zksync_object_store::{
+    compression, witness_input_chunk_key, Bucket, ObjectStoreError, ObjectStoreFactory,
+    StoredObject, WitnessInputFormat, WitnessInputHeader,
+};
 use zksync_types::{
-    proofs::{PrepareBasicCircuitsJob, StorageLogMetadata},
-    L1BatchNumber,
+    proofs::{
+        DeduplicatedBasicCircuitsJob, PrepareBasicCircuitsJob, PrepareBasicCircuitsJobManifest,
+        StorageLogMetadata, WitnessChunkMetadata,
+    },
+    L1BatchNumber, ProtocolVersionId,
 };
 
 /// Tests compatibility of the `PrepareBasicCircuitsJob` serialization to the previously used
@@ -65,3 +71,229 @@ async fn prepare_basic_circuits_job_compatibility() {
 
     assert_job_integrity(job_tuple.1, job_tuple.0);
 }
+
+#[tokio::test]
+async fn getting_chunked_witness_input() {
+    let snapshot = fs::read("./tests/snapshots/prepare-basic-circuits-job-full.bin")
+        .await
+        .unwrap();
+    let job: PrepareBasicCircuitsJob = bincode::deserialize(&snapshot).unwrap();
+    let store = ObjectStoreFactory::mock().create_store().await;
+    let l1_batch_number = L1BatchNumber(1);
+
+    let mut manifest = PrepareBasicCircuitsJobManifest::new(job.next_enumeration_index());
+    for (chunk_index, chunk) in job.clone().into_chunks(2).enumerate() {
+        let blob = chunk.serialize().unwrap();
+        let metadata = WitnessChunkMetadata::new(chunk.merkle_path_count(), &blob);
+        manifest.chunks.push(metadata);
+        let key = witness_input_chunk_key(l1_batch_number, chunk_index);
+        store
+            .put_raw(Bucket::WitnessInput, &key, blob)
+            .await
+            .unwrap();
+    }
+    assert_eq!(manifest.chunks.len(), 2);
+    store.put(l1_batch_number, &manifest).await.unwrap();
+
+    let reassembled_job = store.get_witness_input(l1_batch_number).await.unwrap();
+    assert_eq!(
+        reassembled_job.next_enumeration_index(),
+        job.next_enumeration_index()
+    );
+    let merkle_paths: Vec<_> = reassembled_job.into_merkle_paths().collect();
+    assert_eq!(
+        merkle_paths,
+        job.clone().into_merkle_paths().collect::<Vec<_>>()
+    );
+
+    // Jobs stored as a single object must be read as well.
+    store.put(L1BatchNumber(2), &job).await.unwrap();
+    let single_object_job = store.get_witness_input(L1BatchNumber(2)).await.unwrap();
+    assert_job_integrity(
+        single_object_job.next_enumeration_index(),
+        single_object_job.into_merkle_paths().collect(),
+    );
+
+    // Tampered chunks must be detected.
+    let key = witness_input_chunk_key(l1_batch_number, 1);
+    let mut blob = store.get_raw(Bucket::WitnessInput, &key).await.unwrap();
+    *blob.last_mut().unwrap() ^= 1;
+    store
+        .put_raw(Bucket::WitnessInput, &key, blob)
+        .await
+        .unwrap();
+    let err = store.get_witness_input(l1_batch_number).await.unwrap_err();
+    assert!(matches!(err, ObjectStoreError::Serialization(_)), "{err}");
+}
+
+#[tokio::test]
+async fn removing_chunked_witness_input() {
+    let snapshot = fs::read("./tests/snapshots/prepare-basic-circuits-job-full.bin")
+        .await
+        .unwrap();
+    let job: PrepareBasicCircuitsJob = bincode::deserialize(&snapshot).unwrap();
+    let store = ObjectStoreFactory::mock().create_store().await;
+    let l1_batch_number = L1BatchNumber(1);
+
+    let mut manifest = PrepareBasicCircuitsJobManifest::new(job.next_enumeration_index());
+    for (chunk_index, chunk) in job.clone().into_chunks(2).enumerate() {
+        let blob = chunk.serialize().unwrap();
+        manifest
+            .chunks
+            .push(WitnessChunkMetadata::new(chunk.merkle_path_count(), &blob));
+        let key = witness_input_chunk_key(l1_batch_number, chunk_index);
+        store
+            .put_raw(Bucket::WitnessInput, &key, blob)
+            .await
+            .unwrap();
+    }
+    let manifest_key = store.put(l1_batch_number, &manifest).await.unwrap();
+    let single_object_key = store.put(L1BatchNumber(2), &job).await.unwrap();
+
+    // Emulate an interrupted removal, after which one of the chunks is already missing.
+    let removed_chunk_key = witness_input_chunk_key(l1_batch_number, 0);
+    store
+        .remove_raw(Bucket::WitnessInput, &removed_chunk_key)
+        .await
+        .unwrap();
+    store
+        .remove_witness_input(l1_batch_number, &manifest_key)
+        .await
+        .unwrap();
+    for chunk_index in 0..manifest.chunks.len() {
+        let key = witness_input_chunk_key(l1_batch_number, chunk_index);
+        let err = store.get_raw(Bucket::WitnessInput, &key).await.unwrap_err();
+        assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+    }
+    let err = store
+        .get_raw(Bucket::WitnessInput, &manifest_key)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+
+    let err = store
+        .remove_witness_input(l1_batch_number, &manifest_key)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+
+    store
+        .remove_witness_input(L1BatchNumber(2), &single_object_key)
+        .await
+        .unwrap();
+    let err = store.get_witness_input(L1BatchNumber(2)).await.unwrap_err();
+    assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+}
+
+#[tokio::test]
+async fn getting_deduplicated_witness_input() {
+    let snapshot = fs::read("./tests/snapshots/prepare-basic-circuits-job-full.bin")
+        .await
+        .unwrap();
+    let job: PrepareBasicCircuitsJob = bincode::deserialize(&snapshot).unwrap();
+    let deduplicated = DeduplicatedBasicCircuitsJob::from(job);
+    let header = WitnessInputHeader::new(WitnessInputFormat::Deduplicated, None);
+    let mut serialized = header.to_bytes();
+    bincode::serialize_into(&mut serialized, &deduplicated).unwrap();
+    assert_eq!(WitnessInputHeader::parse(&serialized).unwrap(), header);
+    assert_eq!(
+        WitnessInputHeader::parse(&snapshot).unwrap(),
+        WitnessInputHeader::default()
+    );
+
+    let store = ObjectStoreFactory::mock().create_store().await;
+    let compressed = compression::compress(serialized.as_slice(), 3).unwrap();
+    for (l1_batch_number, blob) in [(1, serialized.clone()), (2, compressed)] {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let key = PrepareBasicCircuitsJob::encode_key(l1_batch_number);
+        store
+            .put_raw(Bucket::WitnessInput, &key, blob)
+            .await
+            .unwrap();
+        let job: PrepareBasicCircuitsJob = store.get(l1_batch_number).await.unwrap();
+        assert_job_integrity(
+            job.next_enumeration_index(),
+            job.into_merkle_paths().collect(),
+        );
+    }
+
+    // Unknown format versions must be rejected.
+    let version_idx = header.encoded_len() - 1;
+    serialized[version_idx] = 0xff;
+    let key = PrepareBasicCircuitsJob::encode_key(L1BatchNumber(3));
+    store
+        .put_raw(Bucket::WitnessInput, &key, serialized)
+        .await
+        .unwrap();
+    let err = store
+        .get::<PrepareBasicCircuitsJob>(L1BatchNumber(3))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ObjectStoreError::Serialization(_)), "{err}");
+}
+
+#[tokio::test]
+async fn witness_input_with_protocol_version() {
+    let snapshot = fs::read("./tests/snapshots/prepare-basic-circuits-job-full.bin")
+        .await
+        .unwrap();
+    let job: PrepareBasicCircuitsJob = bincode::deserialize(&snapshot).unwrap();
+    let deduplicated = DeduplicatedBasicCircuitsJob::from(job);
+    let store = ObjectStoreFactory::mock().create_store().await;
+
+    let protocol_versions = [ProtocolVersionId::latest(), ProtocolVersionId::next()];
+    let mut serialized_blobs = vec![];
+    for protocol_version in protocol_versions {
+        let compact_header =
+            WitnessInputHeader::new(WitnessInputFormat::Legacy, Some(protocol_version));
+        let mut compact = compact_header.to_bytes();
+        compact.extend_from_slice(&snapshot);
+        let deduplicated_header =
+            WitnessInputHeader::new(WitnessInputFormat::Deduplicated, Some(protocol_version));
+        let mut serialized = deduplicated_header.to_bytes();
+        bincode::serialize_into(&mut serialized, &deduplicated).unwrap();
+
+        for (header, blob) in [(compact_header, compact), (deduplicated_header, serialized)] {
+            assert_eq!(header.encoded_len(), WitnessInputHeader::MAX_LEN);
+            assert_eq!(WitnessInputHeader::parse(&blob).unwrap(), header);
+            serialized_blobs.push(blob);
+        }
+    }
+
+    for (i, blob) in serialized_blobs.iter().enumerate() {
+        let l1_batch_number = L1BatchNumber(i as u32 + 1);
+        let key = PrepareBasicCircuitsJob::encode_key(l1_batch_number);
+        let blob = compression::compress(blob.as_slice(), 3).unwrap();
+        store
+            .put_raw(Bucket::WitnessInput, &key, blob)
+            .await
+            .unwrap();
+        let job: PrepareBasicCircuitsJob = store.get(l1_batch_number).await.unwrap();
+        assert_job_integrity(
+            job.next_enumeration_index(),
+            job.into_merkle_paths().collect(),
+        );
+    }
+
+    // Jobs produced for an unknown protocol version must be rejected rather than misdecoded.
+    let mut serialized = serialized_blobs.pop().unwrap();
+    let protocol_version_idx = WitnessInputHeader::MAX_LEN - 2;
+    serialized[protocol_version_idx..WitnessInputHeader::MAX_LEN]
+        .copy_from_slice(&u16::MAX.to_le_bytes());
+    let err = WitnessInputHeader::parse(&serialized).unwrap_err();
+    assert!(
+        err.to_string().contains("unknown protocol version"),
+        "{err}"
+    );
+
+    let key = PrepareBasicCircuitsJob::encode_key(L1BatchNumber(100));
+    store
+        .put_raw(Bucket::WitnessInput, &key, serialized)
+        .await
+        .unwrap();
+    let err = store
+        .get::<PrepareBasicCircuitsJob>(L1BatchNumber(100))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ObjectStoreError::Serialization(_)), "{err}");
+}
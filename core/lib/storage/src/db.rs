@@ -1,6 +1,7 @@
 use rocksdb::{
-    properties, BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBPinnableSlice,
-    IteratorMode, Options, PrefixRange, ReadOptions, WriteOptions, DB,
+    checkpoint::Checkpoint, properties, BlockBasedOptions, Cache, ColumnFamily,
+    ColumnFamilyDescriptor, DBPinnableSlice, IteratorMode, Options, PrefixRange, ReadOptions,
+    WriteOptions, DB,
 };
 
 use std::ffi::CStr;
@@ -139,6 +140,17 @@ impl RocksDBInner {
     }
 }
 
+/// Additional options for opening a [`RocksDB`] instance.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RocksDBOptions {
+    /// Capacity of the LRU block cache shared among all column families in bytes. If not set,
+    /// RocksDB uses its default block cache for each column family.
+    pub block_cache_capacity: Option<usize>,
+    /// Limit on the throughput of background I/O (flushes and compactions) in bytes per second.
+    /// If not set, background I/O is not limited.
+    pub rate_limit_bytes_per_sec: Option<usize>,
+}
+
 /// Thin wrapper around a RocksDB instance.
 ///
 /// The wrapper is cheaply cloneable (internally, it wraps a DB instance in an [`Arc`]).
@@ -150,6 +162,12 @@ pub struct RocksDB<CF> {
 }
 
 impl<CF: NamedColumnFamily> RocksDB<CF> {
+    /// Refill period of the background I/O rate limiter (the default value recommended by RocksDB).
+    const RATE_LIMITER_REFILL_PERIOD_US: i64 = 100_000;
+    /// Fairness of the background I/O rate limiter between low- and high-priority requests
+    /// (the default value recommended by RocksDB).
+    const RATE_LIMITER_FAIRNESS: i32 = 10;
+
     pub fn new(path: &Path, tune_options: bool) -> Self {
         Self::with_cache(path, tune_options, None)
     }
@@ -159,8 +177,44 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
         tune_options: bool,
         block_cache_capacity: Option<usize>,
     ) -> Self {
-        let caches = RocksDBCaches::new(block_cache_capacity);
-        let options = Self::rocksdb_options(tune_options, None);
+        Self::try_with_cache(path, tune_options, block_cache_capacity)
+            .expect("failed to init rocksdb")
+    }
+
+    /// Same as [`Self::with_cache()`], but returns an error if RocksDB cannot be opened
+    /// (e.g., because it is corrupted or locked by another process).
+    pub fn try_with_cache(
+        path: &Path,
+        tune_options: bool,
+        block_cache_capacity: Option<usize>,
+    ) -> Result<Self, rocksdb::Error> {
+        let options = RocksDBOptions {
+            block_cache_capacity,
+            ..RocksDBOptions::default()
+        };
+        Self::try_with_options(path, tune_options, options)
+    }
+
+    pub fn with_options(path: &Path, tune_options: bool, options: RocksDBOptions) -> Self {
+        Self::try_with_options(path, tune_options, options).expect("failed to init rocksdb")
+    }
+
+    /// Opens RocksDB with the specified additional `options`. Returns an error if RocksDB cannot be opened.
+    pub fn try_with_options(
+        path: &Path,
+        tune_options: bool,
+        db_options: RocksDBOptions,
+    ) -> Result<Self, rocksdb::Error> {
+        let caches = RocksDBCaches::new(db_options.block_cache_capacity);
+        let mut options = Self::rocksdb_options(tune_options, None);
+        if let Some(rate_limit) = db_options.rate_limit_bytes_per_sec {
+            // The rate limiter is shared among all column families and only applies to flushes and compactions.
+            options.set_ratelimiter(
+                rate_limit.try_into().unwrap_or(i64::MAX),
+                Self::RATE_LIMITER_REFILL_PERIOD_US,
+                Self::RATE_LIMITER_FAIRNESS,
+            );
+        }
         let existing_cfs = DB::list_cf(&options, path).unwrap_or_else(|err| {
             tracing::warn!(
                 "Failed getting column families for RocksDB `{}` at `{}`, assuming CFs are empty; {err}",
@@ -205,7 +259,7 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
             ColumnFamilyDescriptor::new(cf_name, cf_options)
         });
 
-        let db = DB::open_cf_descriptors(&options, path, cfs).expect("failed to init rocksdb");
+        let db = DB::open_cf_descriptors(&options, path, cfs)?;
         let inner = Arc::new(RocksDBInner {
             db,
             db_name: CF::DB_NAME,
@@ -215,11 +269,42 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
         });
         RocksdbSizeMetrics::register(CF::DB_NAME, Arc::downgrade(&inner));
 
-        Self {
+        Ok(Self {
             inner,
             sync_writes: false,
             _cf: PhantomData,
-        }
+        })
+    }
+
+    /// Opens RocksDB at `path` in the read-only mode. Unlike [`Self::try_with_options()`], this doesn't lock the DB,
+    /// so it can be used while the DB is opened by another process (e.g., a running server); the returned instance
+    /// observes the DB state at the moment of opening. Column families missing in the DB are not opened.
+    /// Writes to the returned instance fail.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the DB doesn't exist or cannot be opened.
+    pub fn open_read_only(path: &Path) -> Result<Self, rocksdb::Error> {
+        let options = Options::default();
+        let existing_cfs = DB::list_cf(&options, path)?;
+        let cf_names: HashSet<_> = CF::ALL
+            .iter()
+            .map(|cf| cf.name())
+            .filter(|&cf_name| existing_cfs.iter().any(|name| name == cf_name))
+            .collect();
+        let db = DB::open_cf_for_read_only(&options, path, cf_names.iter().copied(), false)?;
+        let inner = Arc::new(RocksDBInner {
+            db,
+            db_name: CF::DB_NAME,
+            cf_names,
+            _registry_entry: RegistryEntry::new(),
+            _caches: RocksDBCaches::new(None),
+        });
+        Ok(Self {
+            inner,
+            sync_writes: false,
+            _cf: PhantomData,
+        })
     }
 
     /// Switches on sync writes in [`Self::write()`] and [`Self::put()`]. This has a performance
@@ -230,6 +315,12 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
         self
     }
 
+    /// Returns the number of live handles to this RocksDB instance (including this one). Handles are created
+    /// by cloning; the instance is closed once all handles are dropped.
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
     fn rocksdb_options(
         tune_options: bool,
         block_based_options: Option<BlockBasedOptions>,
@@ -297,6 +388,12 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
         Ok(())
     }
 
+    /// Flushes the write-ahead log (WAL) buffer and fsyncs the WAL, so that all writes made before the call
+    /// are durable even if sync writes are off. Unlike flushing memtables, this doesn't produce SST files.
+    pub fn sync_wal(&self) -> Result<(), rocksdb::Error> {
+        self.inner.db.flush_wal(true)
+    }
+
     fn column_family(&self, cf: CF) -> &ColumnFamily {
         self.inner
             .db
@@ -309,6 +406,14 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
         self.inner.db.get_cf(cf, key)
     }
 
+    /// Creates a consistent checkpoint of this database at `path`, which must not exist.
+    /// If `path` is on the same filesystem as the database, checkpoint files are hard links to the DB files,
+    /// so creating a checkpoint is cheap. The checkpoint can be opened as a regular database.
+    pub fn create_checkpoint(&self, path: &Path) -> Result<(), rocksdb::Error> {
+        let checkpoint = Checkpoint::new(&self.inner.db)?;
+        checkpoint.create_checkpoint(path)
+    }
+
     /// Iterates over key-value pairs in the specified column family `cf` in the lexical
     /// key order. The keys are filtered so that they start from the specified `prefix`.
     pub fn prefix_iterator_cf(
@@ -459,4 +564,55 @@ mod tests {
         let value = db.get_cf(JunkColumnFamily, b"test").unwrap();
         assert_eq!(value.unwrap(), b"value");
     }
+
+    #[test]
+    fn opening_db_in_read_only_mode() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDB::<OldColumnFamilies>::new(temp_dir.path(), true).with_sync_writes();
+        let mut batch = db.new_write_batch();
+        batch.put_cf(OldColumnFamilies::Junk, b"test", b"value");
+        db.write(batch).unwrap();
+
+        // The DB is still open for writing; read-only mode doesn't require the lock.
+        let read_only_db = RocksDB::<OldColumnFamilies>::open_read_only(temp_dir.path()).unwrap();
+        let value = read_only_db
+            .get_cf(OldColumnFamilies::Junk, b"test")
+            .unwrap();
+        assert_eq!(value.unwrap(), b"value");
+        let mut batch = read_only_db.new_write_batch();
+        batch.put_cf(OldColumnFamilies::Junk, b"test", b"new_value");
+        read_only_db.write(batch).unwrap_err();
+        drop(read_only_db);
+        drop(db);
+
+        // Column families missing in the DB are skipped.
+        let read_only_db = RocksDB::<NewColumnFamilies>::open_read_only(temp_dir.path()).unwrap();
+        assert_eq!(read_only_db.inner.cf_names, HashSet::from(["default"]));
+        drop(read_only_db);
+
+        RocksDB::<OldColumnFamilies>::open_read_only(&temp_dir.path().join("missing")).unwrap_err();
+    }
+
+    #[test]
+    fn creating_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let db = RocksDB::<OldColumnFamilies>::new(&db_path, true).with_sync_writes();
+        let mut batch = db.new_write_batch();
+        batch.put_cf(OldColumnFamilies::Junk, b"test", b"value");
+        db.write(batch).unwrap();
+
+        let checkpoint_path = temp_dir.path().join("checkpoint");
+        db.create_checkpoint(&checkpoint_path).unwrap();
+        // The checkpoint must not be affected by subsequent changes.
+        let mut batch = db.new_write_batch();
+        batch.put_cf(OldColumnFamilies::Junk, b"test", b"new_value");
+        db.write(batch).unwrap();
+        // Creating a checkpoint at an existing path must fail.
+        db.create_checkpoint(&checkpoint_path).unwrap_err();
+
+        let checkpoint = RocksDB::<OldColumnFamilies>::new(&checkpoint_path, true);
+        let value = checkpoint.get_cf(OldColumnFamilies::Junk, b"test").unwrap();
+        assert_eq!(value.unwrap(), b"value");
+    }
 }
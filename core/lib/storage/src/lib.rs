@@ -1,5 +1,5 @@
 pub mod db;
 mod metrics;
 
-pub use db::RocksDB;
+pub use db::{RocksDB, RocksDBOptions};
 pub use rocksdb;
@@ -63,6 +63,15 @@ pub struct L1BatchMetadata {
     pub pass_through_data_hash: H256,
 }
 
+/// Data produced by the Merkle tree (and the commitment derived from it) for a single L1 batch.
+/// This data is persisted atomically, so it's either fully present for an L1 batch or absent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeData {
+    pub metadata: L1BatchMetadata,
+    /// Root hash of the previous L1 batch.
+    pub parent_hash: H256,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct L1BatchWithMetadata {
     pub header: L1BatchHeader,
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::fmt::Debug;
+use std::iter;
 use std::net::IpAddr;
 use std::ops::Add;
 use std::str::FromStr;
@@ -21,7 +23,7 @@ use zkevm_test_harness::{
 };
 
 use crate::witness_block_state::WitnessHashBlockState;
-use zksync_basic_types::{L1BatchNumber, H256, U256};
+use zksync_basic_types::{web3::signing::keccak256, L1BatchNumber, H256, U256};
 
 const HASH_LEN: usize = H256::len_bytes();
 
@@ -59,6 +61,31 @@ impl StorageLogMetadata {
         })
     }
 
+    /// Compacts the Merkle path by removing starting hashes coinciding with the corresponding hashes
+    /// in `first_path`. This is the compaction used by [`PrepareBasicCircuitsJob`] for all paths
+    /// except for the first one.
+    pub fn compact_merkle_path(&mut self, first_path: &[[u8; HASH_LEN]]) {
+        assert_eq!(first_path.len(), self.merkle_paths.len());
+
+        let mut hash_pairs = self.merkle_paths.iter().zip(first_path);
+        let first_unique_idx =
+            hash_pairs.position(|(hash, first_path_hash)| hash != first_path_hash);
+        let first_unique_idx = first_unique_idx.unwrap_or(self.merkle_paths.len());
+        self.merkle_paths = self.merkle_paths.split_off(first_unique_idx);
+    }
+
+    /// Restores the full Merkle path compacted using [`Self::compact_merkle_path()`].
+    pub fn restore_merkle_path(&mut self, first_path: &[[u8; HASH_LEN]]) {
+        assert!(
+            self.merkle_paths.len() <= first_path.len(),
+            "Merkle paths in `PrepareBasicCircuitsJob` are malformed; the first path is not \
+             the longest one"
+        );
+        let spliced_len = first_path.len() - self.merkle_paths.len();
+        self.merkle_paths
+            .splice(0..0, first_path[..spliced_len].iter().cloned());
+    }
+
     fn first_time_touching_empty_storage(&self) -> bool {
         self.value_written == self.value_read
             && H256::from_slice(&self.value_read) == H256::default()
@@ -176,6 +203,19 @@ impl PrepareBasicCircuitsJob {
         self.next_enumeration_index
     }
 
+    /// Returns the number of Merkle paths (i.e., read / write operations) in this job.
+    pub fn merkle_path_count(&self) -> usize {
+        self.merkle_paths.len()
+    }
+
+    /// Returns the number of write operations among Merkle paths in this job.
+    pub fn write_count(&self) -> usize {
+        self.merkle_paths
+            .iter()
+            .filter(|path| path.is_write)
+            .count()
+    }
+
     /// Reserves additional capacity for Merkle paths.
     pub fn reserve(&mut self, additional_capacity: usize) {
         self.merkle_paths.reserve(additional_capacity);
@@ -183,35 +223,23 @@ impl PrepareBasicCircuitsJob {
 
     /// Pushes an additional Merkle path.
     pub fn push_merkle_path(&mut self, mut path: StorageLogMetadata) {
-        let Some(first_path) = self.merkle_paths.first() else {
-            self.merkle_paths.push(path);
-            return;
-        };
-        assert_eq!(first_path.merkle_paths.len(), path.merkle_paths.len());
-
-        let mut hash_pairs = path.merkle_paths.iter().zip(&first_path.merkle_paths);
-        let first_unique_idx =
-            hash_pairs.position(|(hash, first_path_hash)| hash != first_path_hash);
-        let first_unique_idx = first_unique_idx.unwrap_or(path.merkle_paths.len());
-        path.merkle_paths = path.merkle_paths.split_off(first_unique_idx);
+        if let Some(first_path) = self.merkle_paths.first() {
+            path.compact_merkle_path(&first_path.merkle_paths);
+        }
         self.merkle_paths.push(path);
     }
 
+    /// Returns the last pushed Merkle path in the compact form.
+    pub fn last_merkle_path(&self) -> Option<&StorageLogMetadata> {
+        self.merkle_paths.last()
+    }
+
     /// Converts this job into an iterator over the contained Merkle paths.
     pub fn into_merkle_paths(self) -> impl ExactSizeIterator<Item = StorageLogMetadata> {
         let mut merkle_paths = self.merkle_paths;
         if let [first, rest @ ..] = merkle_paths.as_mut_slice() {
             for path in rest {
-                assert!(
-                    path.merkle_paths.len() <= first.merkle_paths.len(),
-                    "Merkle paths in `PrepareBasicCircuitsJob` are malformed; the first path is not \
-                     the longest one"
-                );
-                let spliced_len = first.merkle_paths.len() - path.merkle_paths.len();
-                let spliced_hashes = &first.merkle_paths[0..spliced_len];
-                path.merkle_paths
-                    .splice(0..0, spliced_hashes.iter().cloned());
-                debug_assert_eq!(path.merkle_paths.len(), first.merkle_paths.len());
+                path.restore_merkle_path(&first.merkle_paths);
             }
         }
         merkle_paths.into_iter()
@@ -235,6 +263,366 @@ impl PrepareBasicCircuitsJob {
         }
         block_state
     }
+
+    /// Splits this job into jobs with up to `chunk_size` Merkle paths each, preserving the order of paths.
+    /// Each chunk is a standalone job in the compact form (i.e., its first path is full). This is used
+    /// to store large jobs in the [chunked format](PrepareBasicCircuitsJobManifest).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn into_chunks(self, chunk_size: usize) -> impl Iterator<Item = Self> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+
+        let next_enumeration_index = self.next_enumeration_index;
+        let first_path = self
+            .merkle_paths
+            .first()
+            .map(|path| path.merkle_paths.clone())
+            .unwrap_or_default();
+        let mut merkle_paths = self.merkle_paths.into_iter().peekable();
+        iter::from_fn(move || {
+            merkle_paths.peek()?;
+            let mut chunk = Self::new(next_enumeration_index);
+            chunk.reserve(chunk_size.min(merkle_paths.len()));
+            for mut path in merkle_paths.by_ref().take(chunk_size) {
+                // Restore the full path in the same way as `into_merkle_paths()` does; it will be
+                // compacted relative to the first path in the chunk.
+                path.restore_merkle_path(&first_path);
+                chunk.push_merkle_path(path);
+            }
+            Some(chunk)
+        })
+    }
+}
+
+/// Metadata of a single chunk of a [`PrepareBasicCircuitsJob`] stored in the chunked format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WitnessChunkMetadata {
+    /// Number of Merkle paths in the chunk.
+    pub merkle_path_count: usize,
+    /// Size of the stored chunk blob in bytes.
+    pub size: u64,
+    /// Keccak-256 hash of the stored chunk blob.
+    pub hash: H256,
+}
+
+impl WitnessChunkMetadata {
+    /// Creates metadata for the chunk stored as `blob`.
+    pub fn new(merkle_path_count: usize, blob: &[u8]) -> Self {
+        Self {
+            merkle_path_count,
+            size: blob.len() as u64,
+            hash: H256(keccak256(blob)),
+        }
+    }
+}
+
+/// Manifest of a [`PrepareBasicCircuitsJob`] stored in the chunked format.
+///
+/// Jobs with a large serialization are split by the producer into chunks with a fixed number
+/// of Merkle paths (see [`PrepareBasicCircuitsJob::into_chunks()`]), which are stored as separate objects
+/// together with the manifest. Chunks can be fetched and checked one by one, and are reassembled
+/// into the job using [`ChunkedJobReader`].
+///
+/// # Stability
+///
+/// Like `PrepareBasicCircuitsJob`, the manifest is serialized using `bincode`, so changes
+/// in its `serde` serialization must be backwards-compatible.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrepareBasicCircuitsJobManifest {
+    pub next_enumeration_index: u64,
+    pub chunks: Vec<WitnessChunkMetadata>,
+}
+
+impl PrepareBasicCircuitsJobManifest {
+    /// Creates a manifest without chunks.
+    pub fn new(next_enumeration_index: u64) -> Self {
+        Self {
+            next_enumeration_index,
+            chunks: vec![],
+        }
+    }
+
+    /// Returns the total number of Merkle paths in the job.
+    pub fn merkle_path_count(&self) -> usize {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.merkle_path_count)
+            .sum()
+    }
+
+    /// Checks the stored blob for the chunk with the specified index against its size and hash
+    /// recorded in the manifest.
+    pub fn verify_chunk(&self, chunk_index: usize, blob: &[u8]) -> Result<(), ChunkedJobError> {
+        let chunk = self
+            .chunks
+            .get(chunk_index)
+            .ok_or(ChunkedJobError::UnknownChunk(chunk_index))?;
+        let actual_size = blob.len() as u64;
+        if actual_size != chunk.size {
+            return Err(ChunkedJobError::SizeMismatch {
+                chunk_index,
+                expected: chunk.size,
+                actual: actual_size,
+            });
+        }
+        let actual_hash = H256(keccak256(blob));
+        if actual_hash != chunk.hash {
+            return Err(ChunkedJobError::HashMismatch {
+                chunk_index,
+                expected: chunk.hash,
+                actual: actual_hash,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Errors that can occur when reassembling a [`PrepareBasicCircuitsJob`] from chunks.
+#[derive(Debug, thiserror::Error)]
+pub enum ChunkedJobError {
+    #[error("chunk #{0} is not present in the manifest")]
+    UnknownChunk(usize),
+    #[error("chunk #{chunk_index} has unexpected size: expected {expected}B, got {actual}B")]
+    SizeMismatch {
+        chunk_index: usize,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("chunk #{chunk_index} has unexpected hash: expected {expected:?}, got {actual:?}")]
+    HashMismatch {
+        chunk_index: usize,
+        expected: H256,
+        actual: H256,
+    },
+    #[error(
+        "chunk #{chunk_index} has unexpected number of Merkle paths: expected {expected}, got {actual}"
+    )]
+    MerklePathCountMismatch {
+        chunk_index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error(
+        "chunk #{chunk_index} has unexpected next enumeration index: expected {expected}, got {actual}"
+    )]
+    EnumerationIndexMismatch {
+        chunk_index: usize,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("job is incomplete: {pushed} out of {total} chunks are provided")]
+    Incomplete { pushed: usize, total: usize },
+}
+
+/// Reassembles a [`PrepareBasicCircuitsJob`] stored in the chunked format from its chunks.
+/// Chunks must be pushed in the order specified by the manifest.
+#[derive(Debug)]
+pub struct ChunkedJobReader {
+    manifest: PrepareBasicCircuitsJobManifest,
+    job: PrepareBasicCircuitsJob,
+    pushed_chunks: usize,
+}
+
+impl ChunkedJobReader {
+    pub fn new(manifest: PrepareBasicCircuitsJobManifest) -> Self {
+        let mut job = PrepareBasicCircuitsJob::new(manifest.next_enumeration_index);
+        job.reserve(manifest.merkle_path_count());
+        Self {
+            manifest,
+            job,
+            pushed_chunks: 0,
+        }
+    }
+
+    pub fn manifest(&self) -> &PrepareBasicCircuitsJobManifest {
+        &self.manifest
+    }
+
+    /// Returns the index of the next chunk to be pushed, or `None` if all chunks are pushed.
+    pub fn next_chunk_index(&self) -> Option<usize> {
+        (self.pushed_chunks < self.manifest.chunks.len()).then_some(self.pushed_chunks)
+    }
+
+    /// Appends Merkle paths from the next chunk to the reassembled job.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunk is inconsistent with the manifest.
+    pub fn push_chunk(&mut self, chunk: PrepareBasicCircuitsJob) -> Result<(), ChunkedJobError> {
+        let chunk_index = self.pushed_chunks;
+        let metadata = self
+            .manifest
+            .chunks
+            .get(chunk_index)
+            .ok_or(ChunkedJobError::UnknownChunk(chunk_index))?;
+        if chunk.merkle_path_count() != metadata.merkle_path_count {
+            return Err(ChunkedJobError::MerklePathCountMismatch {
+                chunk_index,
+                expected: metadata.merkle_path_count,
+                actual: chunk.merkle_path_count(),
+            });
+        }
+        if chunk.next_enumeration_index != self.manifest.next_enumeration_index {
+            return Err(ChunkedJobError::EnumerationIndexMismatch {
+                chunk_index,
+                expected: self.manifest.next_enumeration_index,
+                actual: chunk.next_enumeration_index,
+            });
+        }
+
+        for path in chunk.into_merkle_paths() {
+            self.job.push_merkle_path(path);
+        }
+        self.pushed_chunks += 1;
+        Ok(())
+    }
+
+    /// Returns the reassembled job.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if not all chunks were pushed.
+    pub fn finish(self) -> Result<PrepareBasicCircuitsJob, ChunkedJobError> {
+        let total = self.manifest.chunks.len();
+        if self.pushed_chunks < total {
+            return Err(ChunkedJobError::Incomplete {
+                pushed: self.pushed_chunks,
+                total,
+            });
+        }
+        Ok(self.job)
+    }
+}
+
+/// [`PrepareBasicCircuitsJob`] with deduplicated Merkle path hashes.
+///
+/// Paths for keys in the same subtree share many sibling hashes, and so do paths for consecutive operations
+/// on the same key. In this encoding, all distinct hashes from the compact form of the job are stored once
+/// in a dictionary, and each Merkle path references them by index. The job is decoded back
+/// to the compact form using [`Self::into_job()`].
+///
+/// # Stability
+///
+/// This type is serialized using `bincode` and is stored in a versioned format; changes in its `serde`
+/// serialization require a new format version.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeduplicatedBasicCircuitsJob {
+    #[serde_as(as = "Vec<Bytes>")]
+    hashes: Vec<[u8; HASH_LEN]>,
+    merkle_paths: Vec<DeduplicatedStorageLogMetadata>,
+    next_enumeration_index: u64,
+}
+
+/// [`StorageLogMetadata`] with Merkle path hashes replaced by references to the hash dictionary.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct DeduplicatedStorageLogMetadata {
+    #[serde_as(as = "Bytes")]
+    root_hash: [u8; HASH_LEN],
+    is_write: bool,
+    first_write: bool,
+    merkle_path_refs: Vec<u32>,
+    leaf_hashed_key: U256,
+    leaf_enumeration_index: u64,
+    #[serde_as(as = "Bytes")]
+    value_written: [u8; HASH_LEN],
+    #[serde_as(as = "Bytes")]
+    value_read: [u8; HASH_LEN],
+}
+
+impl From<PrepareBasicCircuitsJob> for DeduplicatedBasicCircuitsJob {
+    fn from(job: PrepareBasicCircuitsJob) -> Self {
+        let mut hashes = vec![];
+        let mut hash_refs = HashMap::new();
+        let merkle_paths = job.merkle_paths.into_iter().map(|path| {
+            let merkle_path_refs = path.merkle_paths.iter().map(|hash| {
+                *hash_refs.entry(*hash).or_insert_with(|| {
+                    let hash_ref = u32::try_from(hashes.len())
+                        .expect("too many distinct hashes in Merkle paths");
+                    hashes.push(*hash);
+                    hash_ref
+                })
+            });
+            DeduplicatedStorageLogMetadata {
+                root_hash: path.root_hash,
+                is_write: path.is_write,
+                first_write: path.first_write,
+                merkle_path_refs: merkle_path_refs.collect(),
+                leaf_hashed_key: path.leaf_hashed_key,
+                leaf_enumeration_index: path.leaf_enumeration_index,
+                value_written: path.value_written,
+                value_read: path.value_read,
+            }
+        });
+        let merkle_paths = merkle_paths.collect();
+
+        Self {
+            hashes,
+            merkle_paths,
+            next_enumeration_index: job.next_enumeration_index,
+        }
+    }
+}
+
+impl DeduplicatedBasicCircuitsJob {
+    /// Returns the number of distinct hashes in Merkle paths.
+    pub fn distinct_hash_count(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Decodes this job into the compact form.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a Merkle path references a hash missing from the dictionary.
+    pub fn into_job(self) -> Result<PrepareBasicCircuitsJob, InvalidHashReference> {
+        let hashes = self.hashes;
+        let merkle_paths = self
+            .merkle_paths
+            .into_iter()
+            .enumerate()
+            .map(|(path_index, path)| {
+                let merkle_paths = path.merkle_path_refs.into_iter().map(|hash_ref| {
+                    hashes
+                        .get(hash_ref as usize)
+                        .copied()
+                        .ok_or(InvalidHashReference {
+                            path_index,
+                            hash_ref,
+                            hash_count: hashes.len(),
+                        })
+                });
+                Ok(StorageLogMetadata {
+                    root_hash: path.root_hash,
+                    is_write: path.is_write,
+                    first_write: path.first_write,
+                    merkle_paths: merkle_paths.collect::<Result<_, _>>()?,
+                    leaf_hashed_key: path.leaf_hashed_key,
+                    leaf_enumeration_index: path.leaf_enumeration_index,
+                    value_written: path.value_written,
+                    value_read: path.value_read,
+                })
+            });
+
+        Ok(PrepareBasicCircuitsJob {
+            merkle_paths: merkle_paths.collect::<Result<_, _>>()?,
+            next_enumeration_index: self.next_enumeration_index,
+        })
+    }
+}
+
+/// Error returned when decoding a malformed [`DeduplicatedBasicCircuitsJob`].
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Merkle path #{path_index} references hash #{hash_ref}, while there are only {hash_count} hashes"
+)]
+pub struct InvalidHashReference {
+    path_index: usize,
+    hash_ref: u32,
+    hash_count: usize,
 }
 
 /// Enriched `PrepareBasicCircuitsJob`. All the other fields are taken from the `l1_batches` table.
@@ -501,6 +889,9 @@ mod tests {
             job.push_merkle_path(log.clone());
         }
 
+        assert_eq!(job.merkle_path_count(), 10);
+        assert_eq!(job.write_count(), 5);
+
         // Check that Merkle paths are compacted.
         for (i, log) in job.merkle_paths.iter().enumerate() {
             let expected_merkle_path_len = if i == 0 { 256 } else { 1 };
@@ -511,6 +902,124 @@ mod tests {
         assert_eq!(logs_from_job, logs);
     }
 
+    fn create_job(path_count: u8) -> (PrepareBasicCircuitsJob, Vec<StorageLogMetadata>) {
+        let logs = (0..path_count).map(|i| {
+            let mut merkle_paths = vec![[0_u8; 32]; 254];
+            merkle_paths.push([i / 4; 32]);
+            merkle_paths.push([i; 32]);
+            StorageLogMetadata {
+                root_hash: [i; 32],
+                is_write: i % 2 == 0,
+                first_write: i % 3 == 0,
+                merkle_paths,
+                leaf_hashed_key: U256::from(i),
+                leaf_enumeration_index: u64::from(i) + 1,
+                value_written: [i; 32],
+                value_read: [0; 32],
+            }
+        });
+        let logs: Vec<_> = logs.collect();
+
+        let mut job = PrepareBasicCircuitsJob::new(4);
+        for log in &logs {
+            job.push_merkle_path(log.clone());
+        }
+        (job, logs)
+    }
+
+    /// Stand-in for the stored chunk blob; the reader doesn't depend on the blob format.
+    fn mock_blob(chunk: &PrepareBasicCircuitsJob) -> Vec<u8> {
+        format!("{chunk:?}").into_bytes()
+    }
+
+    #[test]
+    fn chunked_job_roundtrip() {
+        let (job, logs) = create_job(10);
+        let chunks: Vec<_> = job.into_chunks(4).collect();
+        let chunk_lengths: Vec<_> = chunks
+            .iter()
+            .map(|chunk| chunk.merkle_path_count())
+            .collect();
+        assert_eq!(chunk_lengths, [4, 4, 2]);
+        for chunk in &chunks {
+            assert_eq!(chunk.next_enumeration_index(), 4);
+            // Each chunk must be a standalone compacted job.
+            assert_eq!(chunk.merkle_paths[0].merkle_paths.len(), 256);
+            assert!(chunk.merkle_paths[1..]
+                .iter()
+                .all(|path| path.merkle_paths.len() <= 2));
+        }
+
+        let mut manifest = PrepareBasicCircuitsJobManifest::new(4);
+        for chunk in &chunks {
+            let metadata = WitnessChunkMetadata::new(chunk.merkle_path_count(), &mock_blob(chunk));
+            manifest.chunks.push(metadata);
+        }
+        assert_eq!(manifest.merkle_path_count(), 10);
+
+        let mut reader = ChunkedJobReader::new(manifest);
+        for (chunk_index, chunk) in chunks.into_iter().enumerate() {
+            assert_eq!(reader.next_chunk_index(), Some(chunk_index));
+            reader
+                .manifest()
+                .verify_chunk(chunk_index, &mock_blob(&chunk))
+                .unwrap();
+            reader.push_chunk(chunk).unwrap();
+        }
+        assert_eq!(reader.next_chunk_index(), None);
+        let job = reader.finish().unwrap();
+        assert_eq!(job.next_enumeration_index(), 4);
+        let logs_from_job: Vec<_> = job.into_merkle_paths().collect();
+        assert_eq!(logs_from_job, logs);
+    }
+
+    #[test]
+    fn chunked_job_reader_detects_inconsistencies() {
+        let (job, _) = create_job(6);
+        let chunks: Vec<_> = job.into_chunks(3).collect();
+        let mut manifest = PrepareBasicCircuitsJobManifest::new(4);
+        for chunk in &chunks {
+            let blob = mock_blob(chunk);
+            manifest
+                .chunks
+                .push(WitnessChunkMetadata::new(chunk.merkle_path_count(), &blob));
+        }
+
+        let tampered_blob = mock_blob(&chunks[1]);
+        let err = manifest.verify_chunk(0, &tampered_blob).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ChunkedJobError::SizeMismatch { chunk_index: 0, .. }
+                    | ChunkedJobError::HashMismatch { chunk_index: 0, .. }
+            ),
+            "{err}"
+        );
+        let err = manifest.verify_chunk(2, &tampered_blob).unwrap_err();
+        assert!(matches!(err, ChunkedJobError::UnknownChunk(2)), "{err}");
+
+        let mut reader = ChunkedJobReader::new(manifest);
+        let err = reader
+            .push_chunk(PrepareBasicCircuitsJob::new(4))
+            .unwrap_err();
+        assert!(
+            matches!(err, ChunkedJobError::MerklePathCountMismatch { .. }),
+            "{err}"
+        );
+        reader.push_chunk(chunks[0].clone()).unwrap();
+        let err = reader.finish().unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ChunkedJobError::Incomplete {
+                    pushed: 1,
+                    total: 2
+                }
+            ),
+            "{err}"
+        );
+    }
+
     fn get_arr_with_last_byte(val: u8) -> [u8; 32] {
         let mut arr = [0; 32];
         arr[31] = val;
@@ -592,4 +1101,31 @@ mod tests {
             witness_hash_block_state
         );
     }
+
+    #[test]
+    fn deduplicated_job_roundtrip() {
+        let (job, logs) = create_job(10);
+        let deduplicated = DeduplicatedBasicCircuitsJob::from(job.clone());
+        // All hashes in paths are `[i; 32]` for `i` in `0..10`.
+        assert_eq!(deduplicated.distinct_hash_count(), 10);
+        assert_eq!(deduplicated.merkle_paths[0].merkle_path_refs.len(), 256);
+
+        let restored = deduplicated.into_job().unwrap();
+        assert_eq!(restored.next_enumeration_index(), 4);
+        for (restored_path, path) in restored.merkle_paths.iter().zip(&job.merkle_paths) {
+            assert_eq!(restored_path, path);
+        }
+        let logs_from_job: Vec<_> = restored.into_merkle_paths().collect();
+        assert_eq!(logs_from_job, logs);
+    }
+
+    #[test]
+    fn deduplicated_job_with_invalid_hash_reference() {
+        let (job, _) = create_job(3);
+        let mut deduplicated = DeduplicatedBasicCircuitsJob::from(job);
+        deduplicated.merkle_paths[2].merkle_path_refs[0] = 100;
+        let err = deduplicated.into_job().unwrap_err();
+        assert_eq!(err.path_index, 2);
+        assert_eq!(err.hash_ref, 100);
+    }
 }
@@ -0,0 +1,10 @@
+//! Compiles gRPC definitions if the `grpc` feature is enabled.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/tree.proto");
+        tonic_build::compile_protos("proto/tree.proto")?;
+    }
+    Ok(())
+}
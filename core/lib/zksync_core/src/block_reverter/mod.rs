@@ -1,14 +1,21 @@
+use anyhow::Context as _;
 use bitflags::bitflags;
 use serde::Serialize;
 use tokio::time::sleep;
 
-use std::path::Path;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use zksync_config::{ContractsConfig, ETHSenderConfig};
 use zksync_contracts::zksync_contract;
 use zksync_dal::ConnectionPool;
-use zksync_merkle_tree::domain::ZkSyncTree;
+use zksync_merkle_tree::{
+    domain::{ZkSyncTree, ZkSyncTreeReader},
+    MerkleTreeColumnFamily,
+};
+use zksync_object_store::ObjectStore;
 use zksync_state::RocksdbStorage;
 use zksync_storage::RocksDB;
 use zksync_types::aggregated_operations::AggregatedActionType;
@@ -23,6 +30,16 @@ use zksync_types::{L1BatchNumber, PackedEthSignature, H160, H256, U256};
 
 use zksync_eth_signer::{EthereumSigner, PrivateKeySigner, TransactionParameters};
 
+use crate::house_keeper::stale_witness_artifacts_cleaner::{
+    remove_stale_witness_artifacts, StaleArtifactsRemoval,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Number of stale witness inputs removed from the object store in a single batch.
+const STALE_ARTIFACTS_BATCH_SIZE: usize = 100;
+
 bitflags! {
     pub struct BlockReverterFlags: u32 {
         const POSTGRES = 0b_0001;
@@ -93,6 +110,7 @@ pub struct BlockReverter {
     eth_config: Option<BlockReverterEthConfig>,
     connection_pool: ConnectionPool,
     executed_batches_revert_mode: L1ExecutedBatchesRevert,
+    object_store: Option<Box<dyn ObjectStore>>,
 }
 
 impl BlockReverter {
@@ -109,9 +127,18 @@ impl BlockReverter {
             eth_config,
             connection_pool,
             executed_batches_revert_mode,
+            object_store: None,
         }
     }
 
+    /// Sets the object store used to remove witness inputs (including chunks of witness inputs stored
+    /// in the chunked format) uploaded for the reverted L1 batches once Postgres is rolled back, either by
+    /// [`Self::rollback_tree_and_postgres()`] or by [`Self::rollback_db()`]. If the object store is not set,
+    /// witness inputs are removed by the house keeper.
+    pub fn set_object_store(&mut self, object_store: Box<dyn ObjectStore>) {
+        self.object_store = Some(object_store);
+    }
+
     /// Rolls back DBs (Postgres + RocksDB) to a previous state.
     pub async fn rollback_db(
         &self,
@@ -122,29 +149,601 @@ impl BlockReverter {
         let rollback_postgres = flags.contains(BlockReverterFlags::POSTGRES);
         let rollback_sk_cache = flags.contains(BlockReverterFlags::SK_CACHE);
 
+        self.check_executed_batches_revert(last_l1_batch_to_keep)
+            .await
+            .unwrap();
+
+        // Tree needs to be reverted first to keep state recoverable
+        self.rollback_rocks_dbs(last_l1_batch_to_keep, rollback_tree, rollback_sk_cache)
+            .await;
+        if rollback_postgres {
+            self.rollback_postgres(last_l1_batch_to_keep).await;
+            self.remove_stale_witness_artifacts().await;
+        }
+    }
+
+    async fn check_executed_batches_revert(
+        &self,
+        last_l1_batch_to_keep: L1BatchNumber,
+    ) -> anyhow::Result<()> {
         if matches!(
             self.executed_batches_revert_mode,
             L1ExecutedBatchesRevert::Disallowed
         ) {
-            let mut storage = self.connection_pool.access_storage().await.unwrap();
+            let mut storage = self.connection_pool.access_storage().await?;
             let last_executed_l1_batch = storage
                 .blocks_dal()
                 .get_number_of_last_l1_batch_executed_on_eth()
-                .await
-                .unwrap()
-                .expect("failed to get last executed L1 batch");
-            assert!(
+                .await?
+                .context("failed to get last executed L1 batch")?;
+            anyhow::ensure!(
                 last_l1_batch_to_keep >= last_executed_l1_batch,
                 "Attempt to revert already executed L1 batches"
             );
         }
+        Ok(())
+    }
 
-        // Tree needs to be reverted first to keep state recoverable
-        self.rollback_rocks_dbs(last_l1_batch_to_keep, rollback_tree, rollback_sk_cache)
+    /// Rolls back the Merkle tree and Postgres to a previous state in a coordinated way, so that they
+    /// cannot end up at different L1 batches:
+    ///
+    /// 1. The tree is reverted first since it can be recomputed from Postgres. Its root hash
+    ///    is checked against the root hash stored in Postgres for `last_l1_batch_to_keep`,
+    ///    and the tree is only saved if the hashes match.
+    /// 2. Postgres data is truncated in a single transaction.
+    /// 3. Witness inputs uploaded for the reverted L1 batches are removed from the object store (if it's set).
+    ///    Removal failures are logged, but do not fail the rollback; failed removals are retried
+    ///    by the house keeper.
+    /// 4. The resulting state of the tree, Postgres and L1 is verified with [`Self::verify_rollback()`].
+    ///
+    /// Each step is idempotent, so if the rollback fails midway, re-running it converges to the target state.
+    /// The state keeper cache is not rolled back; if necessary, it must be rolled back before this method
+    /// is called since its rollback relies on Postgres data.
+    ///
+    /// If `last_l1_batch_to_keep` is before the earliest L1 batch retained by the (pruned) tree, the tree
+    /// cannot be reverted. In this case, the method fails without modifying any data unless `allow_tree_rebuild`
+    /// is set; if it is set, the tree is removed, so that the metadata calculator rebuilds it from Postgres.
+    ///
+    /// Before the tree is modified, its checkpoint is created in `checkpoint_dir` (if specified). The checkpoint
+    /// is returned and can be restored with [`Self::restore_tree_from_checkpoint()`].
+    pub async fn rollback_tree_and_postgres(
+        &self,
+        last_l1_batch_to_keep: L1BatchNumber,
+        allow_tree_rebuild: bool,
+        checkpoint_dir: Option<&Path>,
+    ) -> anyhow::Result<Option<TreeCheckpoint>> {
+        self.check_executed_batches_revert(last_l1_batch_to_keep)
+            .await?;
+
+        let mut storage = self.connection_pool.access_storage().await?;
+        let sealed_l1_batch_number = storage.blocks_dal().get_sealed_l1_batch_number().await?;
+        anyhow::ensure!(
+            last_l1_batch_to_keep <= sealed_l1_batch_number,
+            "Cannot roll back to L1 batch #{last_l1_batch_to_keep}, which is not sealed \
+             (last sealed L1 batch: #{sealed_l1_batch_number})"
+        );
+        let target_root_hash = storage
+            .blocks_dal()
+            .get_l1_batch_state_root(last_l1_batch_to_keep)
+            .await?
+            .with_context(|| {
+                format!(
+                    "No root hash is stored in Postgres for L1 batch #{last_l1_batch_to_keep}; \
+                     the tree must process it before rolling back"
+                )
+            })?;
+        drop(storage);
+
+        let merkle_tree_path = Path::new(&self.merkle_tree_path);
+        anyhow::ensure!(
+            merkle_tree_path.exists(),
+            "Merkle tree not found at `{}`",
+            merkle_tree_path.display()
+        );
+        // `revert_logs()` cannot restore pruned tree versions, so this case is checked before modifying any data.
+        let first_retained_l1_batch = Self::first_retained_tree_l1_batch(merkle_tree_path)?
+            .filter(|&first_retained| first_retained > last_l1_batch_to_keep);
+        if let Some(first_retained_l1_batch) = first_retained_l1_batch {
+            anyhow::ensure!(
+                    allow_tree_rebuild,
+                    "Cannot roll back Merkle tree to L1 batch #{last_l1_batch_to_keep}: the earliest L1 batch \
+                     retained by the tree is #{first_retained_l1_batch}, and earlier tree versions were pruned. \
+                     To proceed, either restore a tree checkpoint for an L1 batch not after #{last_l1_batch_to_keep} \
+                     and re-run the rollback, or allow rebuilding the tree (`--allow-tree-rebuild`), in which case \
+                     the tree will be removed and rebuilt from Postgres by the metadata calculator after \
+                     the Postgres rollback. Rebuilding may take a long time. No data was modified"
+            );
+        }
+
+        let checkpoint = if let Some(checkpoint_dir) = checkpoint_dir {
+            Self::checkpoint_tree(merkle_tree_path, checkpoint_dir, last_l1_batch_to_keep)?
+        } else {
+            tracing::warn!("Skipping creating a checkpoint of Merkle tree before rolling it back");
+            None
+        };
+        let is_tree_removed = match first_retained_l1_batch {
+            Some(first_retained_l1_batch) => {
+                tracing::warn!(
+                    "L1 batch #{last_l1_batch_to_keep} is before the pruning horizon of Merkle tree \
+                     (earliest retained L1 batch: #{first_retained_l1_batch}); removing the tree at `{}`, \
+                     so that it's rebuilt from Postgres",
+                    merkle_tree_path.display()
+                );
+                fs::remove_dir_all(merkle_tree_path).with_context(|| {
+                    format!(
+                        "failed removing Merkle tree at `{}`",
+                        merkle_tree_path.display()
+                    )
+                })?;
+                true
+            }
+            None => {
+                tracing::info!("Rolling back Merkle tree to L1 batch #{last_l1_batch_to_keep}...");
+                Self::rollback_tree(last_l1_batch_to_keep, merkle_tree_path, target_root_hash)?;
+                false
+            }
+        };
+
+        self.rollback_postgres(last_l1_batch_to_keep).await;
+        self.remove_stale_witness_artifacts().await;
+
+        tracing::info!("Verifying rolled back state...");
+        let mut violations = self.verify_rollback(last_l1_batch_to_keep).await?;
+        if is_tree_removed {
+            // The tree is expected to be missing; it will be rebuilt by the metadata calculator.
+            violations.retain(|violation| {
+                !matches!(violation, RollbackInvariantViolation::TreeMissing { .. })
+            });
+        }
+        ensure_no_violations(last_l1_batch_to_keep, &violations)?;
+        tracing::info!("Rolled back Merkle tree and Postgres to L1 batch #{last_l1_batch_to_keep}");
+        if let Some(checkpoint) = &checkpoint {
+            tracing::info!(
+                "Merkle tree state before the rollback (L1 batch #{}) is saved at `{}`; it can be restored \
+                 with `block_reverter restore-tree --restore-from-checkpoint {}`",
+                checkpoint.l1_batch,
+                checkpoint.path.display(),
+                checkpoint.path.display()
+            );
+        }
+        Ok(checkpoint)
+    }
+
+    /// Creates a checkpoint of the Merkle tree at `tree_path` in `checkpoint_dir` before the tree is rolled back
+    /// to `last_l1_batch_to_keep`. Returns `None` if the rollback doesn't modify the tree.
+    ///
+    /// If a checkpoint for the same L1 batch already exists (e.g., because a previous rollback attempt failed),
+    /// it is reused provided that its root hash matches the tree.
+    fn checkpoint_tree(
+        tree_path: &Path,
+        checkpoint_dir: &Path,
+        last_l1_batch_to_keep: L1BatchNumber,
+    ) -> anyhow::Result<Option<TreeCheckpoint>> {
+        let db = RocksDB::<MerkleTreeColumnFamily>::new(tree_path, true);
+        let tree = ZkSyncTree::new_lightweight(db.clone());
+        let next_l1_batch = tree.next_l1_batch_number();
+        if next_l1_batch <= last_l1_batch_to_keep + 1 {
+            return Ok(None);
+        }
+        let l1_batch = L1BatchNumber(next_l1_batch.0 - 1);
+        let root_hash = tree.root_hash();
+
+        let path = checkpoint_dir.join(format!("l1_batch_{l1_batch}"));
+        if path.exists() {
+            let checkpoint_root_hash = Self::open_tree_reader(&path)?.root_hash_at(l1_batch);
+            anyhow::ensure!(
+                checkpoint_root_hash == Some(root_hash),
+                "Merkle tree checkpoint at `{}` doesn't match the tree (root hash for L1 batch #{l1_batch}: \
+                 {checkpoint_root_hash:?}, expected {root_hash:?}). Remove the checkpoint or specify another \
+                 checkpoint directory. No data was modified",
+                path.display()
+            );
+            tracing::info!(
+                "Reusing Merkle tree checkpoint for L1 batch #{l1_batch} at `{}`",
+                path.display()
+            );
+        } else {
+            fs::create_dir_all(checkpoint_dir).with_context(|| {
+                format!(
+                    "failed creating directory for Merkle tree checkpoints at `{}`",
+                    checkpoint_dir.display()
+                )
+            })?;
+            db.create_checkpoint(&path).with_context(|| {
+                format!(
+                    "failed creating Merkle tree checkpoint at `{}`",
+                    path.display()
+                )
+            })?;
+            tracing::info!(
+                "Created Merkle tree checkpoint for L1 batch #{l1_batch} at `{}`",
+                path.display()
+            );
+        }
+        Ok(Some(TreeCheckpoint { path, l1_batch }))
+    }
+
+    /// Opens a Merkle tree RocksDB instance. Unlike `RocksDB::new()`, returns an error (rather than panicking)
+    /// if the DB cannot be opened, e.g. because it is locked by a running server.
+    fn try_open_tree_db(path: &Path) -> anyhow::Result<RocksDB<MerkleTreeColumnFamily>> {
+        let db = RocksDB::try_with_cache(path, true, None);
+        db.with_context(|| {
+            format!(
+                "failed opening Merkle tree at `{}`; make sure that the server using it is stopped",
+                path.display()
+            )
+        })
+    }
+
+    /// Opens a read-only reader for the Merkle tree at `path`. The tree is not locked, so it can be read
+    /// even if it's in use by a running server.
+    fn open_tree_reader(path: &Path) -> anyhow::Result<ZkSyncTreeReader> {
+        let db = RocksDB::open_read_only(path).with_context(|| {
+            format!(
+                "failed opening Merkle tree at `{}` in read-only mode",
+                path.display()
+            )
+        })?;
+        Ok(ZkSyncTreeReader::new(db))
+    }
+
+    /// Restores the Merkle tree from a checkpoint created by [`Self::rollback_tree_and_postgres()`], e.g. to undo
+    /// a mistaken rollback. The checkpoint is moved to the tree location, and the current tree is moved
+    /// next to it (with the `.replaced` suffix) rather than removed. Thus, the checkpoint must be located
+    /// on the same filesystem as the tree. Returns the last L1 batch processed by the restored tree.
+    ///
+    /// The node must be stopped. Postgres is not modified; if it was rolled back as well, it must be restored
+    /// separately (e.g., from a backup). Otherwise, the metadata calculator will truncate the restored tree
+    /// to match Postgres.
+    pub async fn restore_tree_from_checkpoint(
+        &self,
+        checkpoint_path: &Path,
+    ) -> anyhow::Result<L1BatchNumber> {
+        anyhow::ensure!(
+            checkpoint_path.exists(),
+            "Merkle tree checkpoint not found at `{}`",
+            checkpoint_path.display()
+        );
+        let next_l1_batch = Self::open_tree_reader(checkpoint_path)?.next_l1_batch_number();
+        anyhow::ensure!(
+            next_l1_batch > L1BatchNumber(0),
+            "Merkle tree checkpoint at `{}` is empty",
+            checkpoint_path.display()
+        );
+        let l1_batch = L1BatchNumber(next_l1_batch.0 - 1);
+
+        let merkle_tree_path = Path::new(&self.merkle_tree_path);
+        if merkle_tree_path.exists() {
+            // Fails if the tree is in use, e.g. because the server is still running.
+            Self::try_open_tree_db(merkle_tree_path)?;
+            let mut replaced_path = merkle_tree_path.as_os_str().to_owned();
+            replaced_path.push(".replaced");
+            let replaced_path = PathBuf::from(replaced_path);
+            anyhow::ensure!(
+                !replaced_path.exists(),
+                "Cannot move the current Merkle tree to `{}` since this path already exists",
+                replaced_path.display()
+            );
+            fs::rename(merkle_tree_path, &replaced_path).with_context(|| {
+                format!(
+                    "failed moving Merkle tree from `{}` to `{}`",
+                    merkle_tree_path.display(),
+                    replaced_path.display()
+                )
+            })?;
+            tracing::info!(
+                "Moved the current Merkle tree to `{}`; it can be removed after the restored tree is checked",
+                replaced_path.display()
+            );
+        }
+        fs::rename(checkpoint_path, merkle_tree_path).with_context(|| {
+            format!(
+                "failed moving Merkle tree checkpoint from `{}` to `{}`",
+                checkpoint_path.display(),
+                merkle_tree_path.display()
+            )
+        })?;
+        tracing::info!(
+            "Restored Merkle tree at `{}` from checkpoint for L1 batch #{l1_batch}",
+            merkle_tree_path.display()
+        );
+
+        let mut storage = self.connection_pool.access_storage().await?;
+        let sealed_l1_batch_number = storage.blocks_dal().get_sealed_l1_batch_number().await?;
+        if sealed_l1_batch_number < l1_batch {
+            tracing::warn!(
+                "Restored Merkle tree (last L1 batch: #{l1_batch}) is ahead of Postgres (last sealed L1 batch: \
+                 #{sealed_l1_batch_number}). Restore Postgres as well; otherwise, the tree will be truncated \
+                 by the metadata calculator"
+            );
+        }
+        Ok(l1_batch)
+    }
+
+    /// Removes witness inputs for the reverted L1 batches from the object store, if it's set. Errors are logged,
+    /// but do not fail the rollback.
+    async fn remove_stale_witness_artifacts(&self) {
+        let Some(object_store) = &self.object_store else {
+            tracing::info!(
+                "Object store is not set; witness inputs for reverted L1 batches will be removed \
+                 by the house keeper"
+            );
+            return;
+        };
+        if let Err(err) = self
+            .try_remove_stale_witness_artifacts(object_store.as_ref())
+            .await
+        {
+            tracing::warn!(
+                "Failed removing witness inputs for reverted L1 batches: {err:#}; \
+                 the removal will be retried by the house keeper"
+            );
+        }
+    }
+
+    async fn try_remove_stale_witness_artifacts(
+        &self,
+        object_store: &dyn ObjectStore,
+    ) -> anyhow::Result<()> {
+        tracing::info!("Removing witness inputs for reverted L1 batches...");
+        let mut storage = self.connection_pool.access_storage().await?;
+        let mut total_removal = StaleArtifactsRemoval::default();
+        loop {
+            let removal = remove_stale_witness_artifacts(
+                &mut storage,
+                object_store,
+                STALE_ARTIFACTS_BATCH_SIZE,
+            )
             .await;
-        if rollback_postgres {
-            self.rollback_postgres(last_l1_batch_to_keep).await;
+            total_removal.removed += removal.removed;
+            total_removal.failed += removal.failed;
+            // Artifacts that failed to be removed are returned last, so the loop stops on the first batch
+            // without progress. This also prevents hammering an unavailable object store.
+            let is_last_batch = removal.removed + removal.failed < STALE_ARTIFACTS_BATCH_SIZE;
+            if removal.removed == 0 || is_last_batch {
+                break;
+            }
         }
+
+        if total_removal.failed > 0 {
+            tracing::warn!(
+                "Removed {} witness inputs for reverted L1 batches; {} inputs failed to be removed \
+                 and will be retried by the house keeper",
+                total_removal.removed,
+                total_removal.failed
+            );
+        } else {
+            tracing::info!(
+                "Removed {} witness inputs for reverted L1 batches",
+                total_removal.removed
+            );
+        }
+        Ok(())
+    }
+
+    /// Verifies that the Merkle tree, Postgres and (if an L1 client is configured) L1 are consistent
+    /// with a rollback to `last_l1_batch_to_keep`:
+    ///
+    /// - The last L1 batch in Postgres is `last_l1_batch_to_keep`, and no tree-related tables
+    ///   contain data for later L1 batches.
+    /// - The last L1 batch processed by the tree is `last_l1_batch_to_keep`, and the tree root hash
+    ///   for it matches the one stored in Postgres.
+    /// - The last L1 batch committed on L1 is not after `last_l1_batch_to_keep`.
+    ///
+    /// This check is run automatically at the end of [`Self::rollback_tree_and_postgres()`].
+    /// Returns all violated invariants; an empty list means that the state is consistent.
+    pub async fn verify_rollback(
+        &self,
+        last_l1_batch_to_keep: L1BatchNumber,
+    ) -> anyhow::Result<Vec<RollbackInvariantViolation>> {
+        let mut violations = vec![];
+        let mut storage = self.connection_pool.access_storage().await?;
+        let postgres_l1_batch = storage.blocks_dal().get_sealed_l1_batch_number().await?;
+        if postgres_l1_batch != last_l1_batch_to_keep {
+            violations.push(RollbackInvariantViolation::PostgresL1BatchMismatch {
+                postgres_l1_batch,
+                expected: last_l1_batch_to_keep,
+            });
+        }
+
+        let last_l1_batch_with_initial_writes = storage
+            .storage_logs_dedup_dal()
+            .max_l1_batch_with_initial_writes()
+            .await;
+        let last_l1_batch_with_witness_inputs = storage
+            .blocks_dal()
+            .get_last_l1_batch_number_with_witness_inputs()
+            .await?;
+        let tree_tables = [
+            ("initial_writes", last_l1_batch_with_initial_writes),
+            ("witness_inputs", Some(last_l1_batch_with_witness_inputs)),
+        ];
+        for (table, last_l1_batch) in tree_tables {
+            if let Some(last_l1_batch) = last_l1_batch {
+                if last_l1_batch > last_l1_batch_to_keep {
+                    violations.push(RollbackInvariantViolation::L1BatchesBeyondTarget {
+                        table,
+                        last_l1_batch,
+                        expected: last_l1_batch_to_keep,
+                    });
+                }
+            }
+        }
+        let postgres_root_hash = storage
+            .blocks_dal()
+            .get_l1_batch_state_root(last_l1_batch_to_keep)
+            .await?;
+        drop(storage);
+
+        let merkle_tree_path = Path::new(&self.merkle_tree_path);
+        if merkle_tree_path.exists() {
+            let tree = Self::open_tree_reader(merkle_tree_path)?;
+            let tree_l1_batch = tree
+                .next_l1_batch_number()
+                .0
+                .checked_sub(1)
+                .map(L1BatchNumber);
+            if tree_l1_batch != Some(last_l1_batch_to_keep) {
+                violations.push(RollbackInvariantViolation::TreeL1BatchMismatch {
+                    tree_l1_batch,
+                    expected: last_l1_batch_to_keep,
+                });
+            }
+            let tree_root_hash = tree.root_hash_at(last_l1_batch_to_keep);
+            if tree_root_hash.is_none() || tree_root_hash != postgres_root_hash {
+                violations.push(RollbackInvariantViolation::RootHashMismatch {
+                    l1_batch: last_l1_batch_to_keep,
+                    tree_root_hash,
+                    postgres_root_hash,
+                });
+            }
+        } else {
+            violations.push(RollbackInvariantViolation::TreeMissing {
+                path: self.merkle_tree_path.clone(),
+            });
+        }
+
+        if self.eth_config.is_some() {
+            let last_committed_l1_batch = self
+                .get_l1_batch_number_from_contract(AggregatedActionType::Commit)
+                .await;
+            if last_committed_l1_batch > last_l1_batch_to_keep {
+                violations.push(RollbackInvariantViolation::CommittedOnL1BeyondTarget {
+                    last_committed_l1_batch,
+                    expected: last_l1_batch_to_keep,
+                });
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Previews [`Self::rollback_tree_and_postgres()`] without modifying any data.
+    pub async fn preview_rollback_tree_and_postgres(
+        &self,
+        last_l1_batch_to_keep: L1BatchNumber,
+    ) -> anyhow::Result<RollbackPreview> {
+        let mut storage = self.connection_pool.access_storage().await?;
+        let sealed_l1_batch_number = storage.blocks_dal().get_sealed_l1_batch_number().await?;
+        anyhow::ensure!(
+            last_l1_batch_to_keep <= sealed_l1_batch_number,
+            "Cannot roll back to L1 batch #{last_l1_batch_to_keep}, which is not sealed \
+             (last sealed L1 batch: #{sealed_l1_batch_number})"
+        );
+        let sealed_miniblock_number = storage.blocks_dal().get_sealed_miniblock_number().await?;
+        let (_, last_miniblock_to_keep) = storage
+            .blocks_dal()
+            .get_miniblock_range_of_l1_batch(last_l1_batch_to_keep)
+            .await?
+            .with_context(|| format!("L1 batch #{last_l1_batch_to_keep} has no miniblocks"))?;
+        let postgres_root_hash = storage
+            .blocks_dal()
+            .get_l1_batch_state_root(last_l1_batch_to_keep)
+            .await?;
+
+        let last_committed_l1_batch = storage
+            .blocks_dal()
+            .get_number_of_last_l1_batch_committed_on_eth()
+            .await?;
+        let last_proven_l1_batch = storage
+            .blocks_dal()
+            .get_number_of_last_l1_batch_proven_on_eth()
+            .await?;
+        let last_executed_l1_batch = storage
+            .blocks_dal()
+            .get_number_of_last_l1_batch_executed_on_eth()
+            .await?;
+        drop(storage);
+
+        let merkle_tree_path = Path::new(&self.merkle_tree_path);
+        let tree = if merkle_tree_path.exists() {
+            Some(Self::preview_tree(last_l1_batch_to_keep, merkle_tree_path)?)
+        } else {
+            None
+        };
+        let is_removed = |l1_batch: Option<L1BatchNumber>| {
+            l1_batch.map_or(false, |l1_batch| l1_batch > last_l1_batch_to_keep)
+        };
+
+        Ok(RollbackPreview {
+            last_l1_batch_to_keep,
+            l1_batches_to_remove: sealed_l1_batch_number.0 - last_l1_batch_to_keep.0,
+            miniblocks_to_remove: sealed_miniblock_number
+                .0
+                .saturating_sub(last_miniblock_to_keep.0),
+            postgres_root_hash,
+            tree,
+            last_committed_l1_batch,
+            last_proven_l1_batch,
+            last_executed_l1_batch,
+            removes_committed_l1_batches: is_removed(last_committed_l1_batch),
+            removes_proven_l1_batches: is_removed(last_proven_l1_batch),
+            removes_executed_l1_batches: is_removed(last_executed_l1_batch),
+        })
+    }
+
+    fn preview_tree(
+        last_l1_batch_to_keep: L1BatchNumber,
+        path: &Path,
+    ) -> anyhow::Result<TreeRollbackPreview> {
+        let tree = Self::open_tree_reader(path)?;
+        let next_l1_batch = tree.next_l1_batch_number();
+        let first_retained_l1_batch = tree.first_retained_l1_batch();
+        if next_l1_batch <= last_l1_batch_to_keep + 1 {
+            let last_l1_batch = next_l1_batch.0.checked_sub(1).map(L1BatchNumber);
+            return Ok(TreeRollbackPreview {
+                next_l1_batch,
+                versions_to_truncate: 0,
+                root_hash_after_rollback: last_l1_batch
+                    .and_then(|l1_batch| tree.root_hash_at(l1_batch)),
+                first_retained_l1_batch,
+                crosses_pruning_horizon: false,
+            });
+        }
+
+        // If the target tree version is missing, it was pruned.
+        let root_hash_after_rollback = tree.root_hash_at(last_l1_batch_to_keep);
+        Ok(TreeRollbackPreview {
+            next_l1_batch,
+            versions_to_truncate: next_l1_batch.0 - last_l1_batch_to_keep.0 - 1,
+            root_hash_after_rollback,
+            first_retained_l1_batch,
+            crosses_pruning_horizon: root_hash_after_rollback.is_none(),
+        })
+    }
+
+    fn first_retained_tree_l1_batch(path: &Path) -> anyhow::Result<Option<L1BatchNumber>> {
+        Ok(Self::open_tree_reader(path)?.first_retained_l1_batch())
+    }
+
+    /// Reverts the tree to `last_l1_batch_to_keep` and checks that its root hash matches `expected_root_hash`.
+    /// The reverted tree is only saved if the check succeeds. The tree must have processed `last_l1_batch_to_keep`;
+    /// otherwise, it couldn't be consistent with Postgres after the rollback.
+    fn rollback_tree(
+        last_l1_batch_to_keep: L1BatchNumber,
+        path: &Path,
+        expected_root_hash: H256,
+    ) -> anyhow::Result<()> {
+        let db = RocksDB::new(path, true);
+        let mut tree = ZkSyncTree::new_lightweight(db);
+        let next_l1_batch_number = tree.next_l1_batch_number();
+        anyhow::ensure!(
+            next_l1_batch_number > last_l1_batch_to_keep,
+            "Merkle tree (next L1 batch: #{next_l1_batch_number}) hasn't processed L1 batch \
+             #{last_l1_batch_to_keep} to roll back to"
+        );
+
+        if next_l1_batch_number > last_l1_batch_to_keep + 1 {
+            tree.revert_logs(last_l1_batch_to_keep);
+        }
+        let root_hash = tree.root_hash();
+        anyhow::ensure!(
+            root_hash == expected_root_hash,
+            "Merkle tree root hash for L1 batch #{last_l1_batch_to_keep} ({root_hash:?}) doesn't match \
+             the root hash stored in Postgres ({expected_root_hash:?})"
+        );
+        tracing::info!("Saving tree changes to disk...");
+        tree.save();
+        Ok(())
     }
 
     async fn rollback_rocks_dbs(
@@ -267,6 +866,13 @@ impl BlockReverter {
             .storage_logs_dal()
             .rollback_storage_logs(last_miniblock_to_keep)
             .await;
+        // Witness artifact metadata is removed together with L1 batches, so it must be processed beforehand.
+        tracing::info!("marking witness artifacts for reverted l1 batches as stale...");
+        let stale_artifacts_count = transaction
+            .witness_artifacts_dal()
+            .mark_witness_artifacts_as_stale(last_l1_batch_to_keep)
+            .await;
+        tracing::info!("marked {stale_artifacts_count} witness artifacts as stale");
         tracing::info!("rolling back l1 batches...");
         transaction
             .blocks_dal()
@@ -429,6 +1035,202 @@ impl BlockReverter {
     }
 }
 
+/// Returns an error listing all `violations` if there are any.
+pub fn ensure_no_violations(
+    last_l1_batch_to_keep: L1BatchNumber,
+    violations: &[RollbackInvariantViolation],
+) -> anyhow::Result<()> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let violations: Vec<_> = violations
+        .iter()
+        .map(|violation| format!("- {violation}"))
+        .collect();
+    anyhow::bail!(
+        "State after rollback to L1 batch #{last_l1_batch_to_keep} is inconsistent; violated invariants:\n{}",
+        violations.join("\n")
+    )
+}
+
+/// Invariant violated after a rollback, as reported by [`BlockReverter::verify_rollback()`].
+#[derive(Debug, Clone, PartialEq, Serialize, thiserror::Error)]
+#[serde(tag = "invariant", rename_all = "snake_case")]
+pub enum RollbackInvariantViolation {
+    #[error("last L1 batch in Postgres is #{postgres_l1_batch}, expected #{expected}")]
+    PostgresL1BatchMismatch {
+        postgres_l1_batch: L1BatchNumber,
+        expected: L1BatchNumber,
+    },
+    #[error("table `{table}` contains data for L1 batches up to #{last_l1_batch}, expected no later than #{expected}")]
+    L1BatchesBeyondTarget {
+        table: &'static str,
+        last_l1_batch: L1BatchNumber,
+        expected: L1BatchNumber,
+    },
+    #[error("Merkle tree is not found at `{path}`")]
+    TreeMissing { path: String },
+    #[error("last L1 batch processed by Merkle tree is {tree_l1_batch:?}, expected #{expected}")]
+    TreeL1BatchMismatch {
+        tree_l1_batch: Option<L1BatchNumber>,
+        expected: L1BatchNumber,
+    },
+    #[error(
+        "Merkle tree root hash for L1 batch #{l1_batch} ({tree_root_hash:?}) doesn't match \
+         the root hash stored in Postgres ({postgres_root_hash:?})"
+    )]
+    RootHashMismatch {
+        l1_batch: L1BatchNumber,
+        tree_root_hash: Option<H256>,
+        postgres_root_hash: Option<H256>,
+    },
+    #[error("last L1 batch committed on L1 is #{last_committed_l1_batch}, expected no later than #{expected}")]
+    CommittedOnL1BeyondTarget {
+        last_committed_l1_batch: L1BatchNumber,
+        expected: L1BatchNumber,
+    },
+}
+
+/// Preview of a coordinated rollback of the Merkle tree and Postgres returned by
+/// [`BlockReverter::preview_rollback_tree_and_postgres()`].
+#[derive(Debug, Serialize)]
+pub struct RollbackPreview {
+    pub last_l1_batch_to_keep: L1BatchNumber,
+    /// Number of L1 batches removed from Postgres.
+    pub l1_batches_to_remove: u32,
+    /// Number of miniblocks removed from Postgres.
+    pub miniblocks_to_remove: u32,
+    /// Root hash stored in Postgres for `last_l1_batch_to_keep`; `None` if the tree hasn't processed
+    /// this L1 batch yet (in which case the rollback will fail).
+    pub postgres_root_hash: Option<H256>,
+    /// Changes to the Merkle tree; `None` if the tree is not found.
+    pub tree: Option<TreeRollbackPreview>,
+    /// Last L1 batch with a confirmed commit transaction on L1.
+    pub last_committed_l1_batch: Option<L1BatchNumber>,
+    /// Last L1 batch with a confirmed prove transaction on L1.
+    pub last_proven_l1_batch: Option<L1BatchNumber>,
+    /// Last L1 batch with a confirmed execute transaction on L1.
+    pub last_executed_l1_batch: Option<L1BatchNumber>,
+    pub removes_committed_l1_batches: bool,
+    pub removes_proven_l1_batches: bool,
+    pub removes_executed_l1_batches: bool,
+}
+
+/// Human-readable summary of the preview, e.g. for the block reverter CLI.
+impl fmt::Display for RollbackPreview {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn l1_batch(number: Option<L1BatchNumber>) -> String {
+            number.map_or_else(|| "none".to_owned(), |number| format!("#{number}"))
+        }
+        fn hash(hash: Option<H256>) -> String {
+            hash.map_or_else(|| "unknown".to_owned(), |hash| format!("{hash:?}"))
+        }
+
+        let target = self.last_l1_batch_to_keep;
+        writeln!(
+            formatter,
+            "Rollback to L1 batch #{target} (dry run; no data was modified)"
+        )?;
+        writeln!(formatter, "Postgres:")?;
+        let l1_batches_to_remove = self.l1_batches_to_remove;
+        writeln!(formatter, "  L1 batches to remove: {l1_batches_to_remove}")?;
+        writeln!(
+            formatter,
+            "  Miniblocks to remove: {}",
+            self.miniblocks_to_remove
+        )?;
+        let root_hash = hash(self.postgres_root_hash);
+        writeln!(formatter, "  Root hash for L1 batch #{target}: {root_hash}")?;
+
+        writeln!(formatter, "Merkle tree:")?;
+        if let Some(tree) = &self.tree {
+            writeln!(formatter, "  Next L1 batch: #{}", tree.next_l1_batch)?;
+            writeln!(
+                formatter,
+                "  Versions to truncate: {}",
+                tree.versions_to_truncate
+            )?;
+            let root_hash = hash(tree.root_hash_after_rollback);
+            writeln!(formatter, "  Root hash after rollback: {root_hash}")?;
+            let first_retained = l1_batch(tree.first_retained_l1_batch);
+            writeln!(formatter, "  Earliest retained L1 batch: {first_retained}")?;
+        } else {
+            writeln!(formatter, "  not found")?;
+        }
+
+        writeln!(formatter, "L1:")?;
+        let l1_batches = [
+            (
+                "committed",
+                self.last_committed_l1_batch,
+                self.removes_committed_l1_batches,
+            ),
+            (
+                "proven",
+                self.last_proven_l1_batch,
+                self.removes_proven_l1_batches,
+            ),
+            (
+                "executed",
+                self.last_executed_l1_batch,
+                self.removes_executed_l1_batches,
+            ),
+        ];
+        for (action, number, is_removed) in l1_batches {
+            let removed = if is_removed { " (removed)" } else { "" };
+            let number = l1_batch(number);
+            writeln!(formatter, "  Last {action} L1 batch: {number}{removed}")?;
+        }
+
+        let mut warnings = vec![];
+        if self.postgres_root_hash.is_none() {
+            warnings.push("target L1 batch has no root hash in Postgres; the rollback will fail");
+        }
+        if self
+            .tree
+            .as_ref()
+            .map_or(false, |tree| tree.crosses_pruning_horizon)
+        {
+            warnings.push("target L1 batch is pruned from the tree; use `--allow-tree-rebuild`");
+        }
+        if self.removes_executed_l1_batches {
+            warnings.push("the rollback removes L1 batches executed on L1");
+        }
+        if !warnings.is_empty() {
+            writeln!(formatter, "Warnings:")?;
+            for warning in warnings {
+                writeln!(formatter, "  - {warning}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checkpoint of the Merkle tree created by [`BlockReverter::rollback_tree_and_postgres()`]
+/// before the tree is rolled back.
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeCheckpoint {
+    /// Path to the checkpoint.
+    pub path: PathBuf,
+    /// Last L1 batch processed by the tree in the checkpoint.
+    pub l1_batch: L1BatchNumber,
+}
+
+/// Part of [`RollbackPreview`] related to the Merkle tree.
+#[derive(Debug, Serialize)]
+pub struct TreeRollbackPreview {
+    /// Next L1 batch to be processed by the tree before the rollback.
+    pub next_l1_batch: L1BatchNumber,
+    /// Number of tree versions (i.e., processed L1 batches) truncated from the tree.
+    pub versions_to_truncate: u32,
+    /// Root hash of the tree after the rollback; `None` if the target tree version was pruned or the tree is empty.
+    pub root_hash_after_rollback: Option<H256>,
+    /// Earliest L1 batch retained by the tree; `None` if the tree is empty.
+    pub first_retained_l1_batch: Option<L1BatchNumber>,
+    /// Whether `last_l1_batch_to_keep` is before the tree pruning horizon, i.e. the tree cannot be rolled back to it.
+    pub crosses_pruning_horizon: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct SuggestedRollbackValues {
     pub last_executed_l1_batch_number: L1BatchNumber,
@@ -0,0 +1,463 @@
+//! Tests for the coordinated rollback of the Merkle tree and Postgres.
+
+use assert_matches::assert_matches;
+use async_trait::async_trait;
+use db_test_macro::db_test;
+use tempfile::TempDir;
+
+use zksync_object_store::{witness_input_chunk_key, Bucket, ObjectStoreError, ObjectStoreFactory};
+
+use super::*;
+use crate::metadata_calculator::tests::{
+    create_mock_witness, reset_db_state, run_calculator, setup_lightweight_calculator,
+    store_chunked_witness,
+};
+
+const LAST_L1_BATCH: L1BatchNumber = L1BatchNumber(5);
+const L1_BATCH_TO_KEEP: L1BatchNumber = L1BatchNumber(3);
+
+/// Seals L1 batches #1..=5 in Postgres and processes them with the Merkle tree. Returns the path to the tree.
+async fn prepare_tree_and_postgres(
+    pool: &ConnectionPool,
+    prover_pool: &ConnectionPool,
+    temp_dir: &TempDir,
+) -> PathBuf {
+    let calculator = setup_lightweight_calculator(temp_dir.path(), pool).await;
+    reset_db_state(pool, LAST_L1_BATCH.0 as usize).await;
+    run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+    // Tree location used by `setup_lightweight_calculator()`.
+    temp_dir.path().join("new")
+}
+
+fn create_reverter(pool: &ConnectionPool, merkle_tree_path: &Path) -> BlockReverter {
+    BlockReverter::new(
+        String::new(), // the state keeper cache is not rolled back in tests
+        merkle_tree_path.to_str().unwrap().to_owned(),
+        None,
+        pool.clone(),
+        L1ExecutedBatchesRevert::Allowed,
+    )
+}
+
+/// Returns the next L1 batch and root hash of the tree at `path`.
+fn tree_state(path: &Path) -> (L1BatchNumber, H256) {
+    let tree = ZkSyncTree::new_lightweight(RocksDB::new(path, true));
+    (tree.next_l1_batch_number(), tree.root_hash())
+}
+
+async fn postgres_root_hash(pool: &ConnectionPool, l1_batch_number: L1BatchNumber) -> Option<H256> {
+    let mut storage = pool.access_storage().await.unwrap();
+    storage
+        .blocks_dal()
+        .get_l1_batch_state_root(l1_batch_number)
+        .await
+        .unwrap()
+}
+
+async fn sealed_l1_batch(pool: &ConnectionPool) -> L1BatchNumber {
+    let mut storage = pool.access_storage().await.unwrap();
+    storage
+        .blocks_dal()
+        .get_sealed_l1_batch_number()
+        .await
+        .unwrap()
+}
+
+async fn assert_rolled_back(pool: &ConnectionPool, reverter: &BlockReverter, tree_path: &Path) {
+    let root_hash = postgres_root_hash(pool, L1_BATCH_TO_KEEP).await.unwrap();
+    assert_eq!(sealed_l1_batch(pool).await, L1_BATCH_TO_KEEP);
+    assert_eq!(postgres_root_hash(pool, L1_BATCH_TO_KEEP + 1).await, None);
+    assert_eq!(tree_state(tree_path), (L1_BATCH_TO_KEEP + 1, root_hash));
+
+    let violations = reverter.verify_rollback(L1_BATCH_TO_KEEP).await.unwrap();
+    assert!(violations.is_empty(), "{violations:?}");
+}
+
+#[db_test]
+async fn rolling_back_tree_and_postgres(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let reverter = create_reverter(&pool, &tree_path);
+
+    let checkpoint = reverter
+        .rollback_tree_and_postgres(L1_BATCH_TO_KEEP, false, None)
+        .await
+        .unwrap();
+    assert!(checkpoint.is_none());
+    assert_rolled_back(&pool, &reverter, &tree_path).await;
+}
+
+#[db_test]
+async fn rollback_converges_after_partial_failure(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let reverter = create_reverter(&pool, &tree_path);
+
+    // Emulate a rollback failing after the tree is rolled back, but before Postgres is.
+    let root_hash = postgres_root_hash(&pool, L1_BATCH_TO_KEEP).await.unwrap();
+    BlockReverter::rollback_tree(L1_BATCH_TO_KEEP, &tree_path, root_hash).unwrap();
+    let violations = reverter.verify_rollback(L1_BATCH_TO_KEEP).await.unwrap();
+    assert!(
+        violations.contains(&RollbackInvariantViolation::PostgresL1BatchMismatch {
+            postgres_l1_batch: LAST_L1_BATCH,
+            expected: L1_BATCH_TO_KEEP,
+        }),
+        "{violations:?}"
+    );
+
+    reverter
+        .rollback_tree_and_postgres(L1_BATCH_TO_KEEP, false, None)
+        .await
+        .unwrap();
+    assert_rolled_back(&pool, &reverter, &tree_path).await;
+
+    // Re-running a completed rollback is a no-op.
+    reverter
+        .rollback_tree_and_postgres(L1_BATCH_TO_KEEP, false, None)
+        .await
+        .unwrap();
+    assert_rolled_back(&pool, &reverter, &tree_path).await;
+}
+
+#[db_test]
+async fn previewing_rollback(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let reverter = create_reverter(&pool, &tree_path);
+    let tree_state_before_preview = tree_state(&tree_path);
+
+    // The tree is opened in the read-only mode, so the preview works even if the tree is in use.
+    let tree_in_use = RocksDB::<MerkleTreeColumnFamily>::new(&tree_path, true);
+    let preview = reverter
+        .preview_rollback_tree_and_postgres(L1_BATCH_TO_KEEP)
+        .await
+        .unwrap();
+    drop(tree_in_use);
+    let root_hash = postgres_root_hash(&pool, L1_BATCH_TO_KEEP).await;
+    assert_eq!(preview.last_l1_batch_to_keep, L1_BATCH_TO_KEEP);
+    assert_eq!(preview.l1_batches_to_remove, 2);
+    // Each L1 batch consists of a single miniblock.
+    assert_eq!(preview.miniblocks_to_remove, 2);
+    assert_eq!(preview.postgres_root_hash, root_hash);
+    assert_eq!(preview.last_committed_l1_batch, None);
+    assert!(!preview.removes_committed_l1_batches);
+    assert!(!preview.removes_executed_l1_batches);
+
+    let tree_preview = preview.tree.unwrap();
+    assert_eq!(tree_preview.next_l1_batch, LAST_L1_BATCH + 1);
+    assert_eq!(tree_preview.versions_to_truncate, 2);
+    assert_eq!(tree_preview.root_hash_after_rollback, root_hash);
+    assert_eq!(tree_preview.first_retained_l1_batch, Some(L1BatchNumber(0)));
+    assert!(!tree_preview.crosses_pruning_horizon);
+
+    let summary = preview.to_string();
+    assert!(summary.contains("L1 batches to remove: 2"), "{summary}");
+    assert!(summary.contains("Versions to truncate: 2"), "{summary}");
+    assert!(
+        summary.contains("Last executed L1 batch: none"),
+        "{summary}"
+    );
+    assert!(!summary.contains("Warnings"), "{summary}");
+
+    // The preview must not modify data.
+    assert_eq!(sealed_l1_batch(&pool).await, LAST_L1_BATCH);
+    assert_eq!(tree_state(&tree_path), tree_state_before_preview);
+
+    let err = reverter
+        .preview_rollback_tree_and_postgres(LAST_L1_BATCH + 1)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not sealed"), "{err}");
+}
+
+#[db_test]
+async fn verifying_rollback(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let reverter = create_reverter(&pool, &tree_path);
+
+    let violations = reverter.verify_rollback(LAST_L1_BATCH).await.unwrap();
+    assert!(violations.is_empty(), "{violations:?}");
+
+    let violations = reverter.verify_rollback(L1_BATCH_TO_KEEP).await.unwrap();
+    assert!(
+        violations.contains(&RollbackInvariantViolation::PostgresL1BatchMismatch {
+            postgres_l1_batch: LAST_L1_BATCH,
+            expected: L1_BATCH_TO_KEEP,
+        }),
+        "{violations:?}"
+    );
+    assert!(
+        violations.contains(&RollbackInvariantViolation::TreeL1BatchMismatch {
+            tree_l1_batch: Some(LAST_L1_BATCH),
+            expected: L1_BATCH_TO_KEEP,
+        }),
+        "{violations:?}"
+    );
+    assert!(
+        violations.iter().any(|violation| matches!(
+            violation,
+            RollbackInvariantViolation::L1BatchesBeyondTarget {
+                table: "initial_writes",
+                ..
+            }
+        )),
+        "{violations:?}"
+    );
+    let err = ensure_no_violations(L1_BATCH_TO_KEEP, &violations).unwrap_err();
+    let err = err.to_string();
+    assert!(err.contains("last L1 batch in Postgres is #5"), "{err}");
+
+    fs::remove_dir_all(&tree_path).unwrap();
+    let violations = reverter.verify_rollback(LAST_L1_BATCH).await.unwrap();
+    assert_matches!(
+        violations.as_slice(),
+        [RollbackInvariantViolation::TreeMissing { .. }]
+    );
+}
+
+#[db_test]
+async fn restoring_tree_from_checkpoint(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let reverter = create_reverter(&pool, &tree_path);
+    let tree_state_before_rollback = tree_state(&tree_path);
+
+    let checkpoint_dir = temp_dir.path().join("checkpoints");
+    let checkpoint = reverter
+        .rollback_tree_and_postgres(L1_BATCH_TO_KEEP, false, Some(&checkpoint_dir))
+        .await
+        .unwrap()
+        .expect("no checkpoint created");
+    assert_eq!(checkpoint.l1_batch, LAST_L1_BATCH);
+    assert!(checkpoint.path.starts_with(&checkpoint_dir));
+    assert_rolled_back(&pool, &reverter, &tree_path).await;
+    let rolled_back_tree_state = tree_state(&tree_path);
+
+    let restored_l1_batch = reverter
+        .restore_tree_from_checkpoint(&checkpoint.path)
+        .await
+        .unwrap();
+    assert_eq!(restored_l1_batch, LAST_L1_BATCH);
+    assert_eq!(tree_state(&tree_path), tree_state_before_rollback);
+    assert!(!checkpoint.path.exists());
+    // The rolled back tree is moved aside rather than removed.
+    let replaced_path = temp_dir.path().join("new.replaced");
+    assert_eq!(tree_state(&replaced_path), rolled_back_tree_state);
+
+    let err = reverter
+        .restore_tree_from_checkpoint(&checkpoint.path)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not found"), "{err}");
+}
+
+#[db_test]
+async fn rolling_back_across_tree_pruning_horizon(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let reverter = create_reverter(&pool, &tree_path);
+    let mut tree = ZkSyncTree::new_lightweight(RocksDB::new(&tree_path, true));
+    tree.prune_up_to(L1_BATCH_TO_KEEP + 1);
+    drop(tree);
+    let tree_state_before_rollback = tree_state(&tree_path);
+
+    let preview = reverter
+        .preview_rollback_tree_and_postgres(L1_BATCH_TO_KEEP)
+        .await
+        .unwrap();
+    let tree_preview = preview.tree.unwrap();
+    assert_eq!(
+        tree_preview.first_retained_l1_batch,
+        Some(L1_BATCH_TO_KEEP + 1)
+    );
+    assert_eq!(tree_preview.root_hash_after_rollback, None);
+    assert!(tree_preview.crosses_pruning_horizon);
+    let summary = preview.to_string();
+    assert!(summary.contains("`--allow-tree-rebuild`"), "{summary}");
+
+    let err = reverter
+        .rollback_tree_and_postgres(L1_BATCH_TO_KEEP, false, None)
+        .await
+        .unwrap_err();
+    let err = err.to_string();
+    assert!(
+        err.contains("earliest L1 batch retained by the tree is #4"),
+        "{err}"
+    );
+    assert!(err.contains("No data was modified"), "{err}");
+    assert_eq!(sealed_l1_batch(&pool).await, LAST_L1_BATCH);
+    assert_eq!(tree_state(&tree_path), tree_state_before_rollback);
+
+    // If rebuilding the tree is allowed, the tree is removed, and Postgres is rolled back.
+    reverter
+        .rollback_tree_and_postgres(L1_BATCH_TO_KEEP, true, None)
+        .await
+        .unwrap();
+    assert!(!tree_path.exists());
+    assert_eq!(sealed_l1_batch(&pool).await, L1_BATCH_TO_KEEP);
+}
+
+/// Uploads witness inputs for all sealed L1 batches and records their metadata in Postgres. The witness input
+/// for the last L1 batch is stored in the chunked format. Returns object keys of the uploaded witness inputs.
+async fn upload_witness_inputs(
+    pool: &ConnectionPool,
+    object_store: &dyn ObjectStore,
+) -> Vec<String> {
+    let mut storage = pool.access_storage().await.unwrap();
+    let job = create_mock_witness(5);
+    let mut object_keys = vec![];
+    for number in 1..=LAST_L1_BATCH.0 {
+        let l1_batch_number = L1BatchNumber(number);
+        let object_key = if l1_batch_number == LAST_L1_BATCH {
+            store_chunked_witness(object_store, l1_batch_number, &job, 2).await
+        } else {
+            object_store.put(l1_batch_number, &job).await.unwrap()
+        };
+        storage
+            .witness_artifacts_dal()
+            .save_witness_artifact(l1_batch_number, &object_key, 100, H256::zero())
+            .await;
+        object_keys.push(object_key);
+    }
+    object_keys
+}
+
+async fn object_exists(object_store: &dyn ObjectStore, key: &str) -> bool {
+    match object_store.get_raw(Bucket::WitnessInput, key).await {
+        Ok(_) => true,
+        Err(ObjectStoreError::KeyNotFound(_)) => false,
+        Err(err) => panic!("unexpected object store error: {err}"),
+    }
+}
+
+async fn assert_witness_inputs_removed(object_store: &dyn ObjectStore, object_keys: &[String]) {
+    for (i, object_key) in object_keys.iter().enumerate() {
+        let is_reverted = i as u32 + 1 > L1_BATCH_TO_KEEP.0;
+        assert_eq!(
+            object_exists(object_store, object_key).await,
+            !is_reverted,
+            "{object_key}"
+        );
+    }
+    // Chunks of the witness input stored in the chunked format must be removed as well.
+    for chunk_index in 0..3 {
+        let chunk_key = witness_input_chunk_key(LAST_L1_BATCH, chunk_index);
+        assert!(
+            !object_exists(object_store, &chunk_key).await,
+            "{chunk_key}"
+        );
+    }
+}
+
+#[db_test]
+async fn removing_witness_inputs_on_rollback(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let store_factory = ObjectStoreFactory::mock();
+    let object_store = store_factory.create_store().await;
+    let object_keys = upload_witness_inputs(&pool, object_store.as_ref()).await;
+    let mut reverter = create_reverter(&pool, &tree_path);
+    reverter.set_object_store(store_factory.create_store().await);
+
+    reverter
+        .rollback_tree_and_postgres(L1_BATCH_TO_KEEP, false, None)
+        .await
+        .unwrap();
+    assert_rolled_back(&pool, &reverter, &tree_path).await;
+    assert_witness_inputs_removed(object_store.as_ref(), &object_keys).await;
+    let mut storage = pool.access_storage().await.unwrap();
+    let stale_artifacts = storage
+        .witness_artifacts_dal()
+        .get_stale_witness_artifacts(10)
+        .await;
+    assert!(stale_artifacts.is_empty(), "{stale_artifacts:?}");
+}
+
+#[db_test]
+async fn removing_witness_inputs_on_non_coordinated_rollback(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let store_factory = ObjectStoreFactory::mock();
+    let object_store = store_factory.create_store().await;
+    let object_keys = upload_witness_inputs(&pool, object_store.as_ref()).await;
+    let mut reverter = create_reverter(&pool, &tree_path);
+    reverter.set_object_store(store_factory.create_store().await);
+
+    let flags = BlockReverterFlags::POSTGRES | BlockReverterFlags::TREE;
+    reverter.rollback_db(L1_BATCH_TO_KEEP, flags).await;
+    assert_rolled_back(&pool, &reverter, &tree_path).await;
+    assert_witness_inputs_removed(object_store.as_ref(), &object_keys).await;
+}
+
+/// Object store failing all [`ObjectStore::remove_raw()`] calls.
+#[derive(Debug)]
+struct NonRemovableObjectStore(Box<dyn ObjectStore>);
+
+#[async_trait]
+impl ObjectStore for NonRemovableObjectStore {
+    async fn get_raw(&self, bucket: Bucket, key: &str) -> Result<Vec<u8>, ObjectStoreError> {
+        self.0.get_raw(bucket, key).await
+    }
+
+    async fn put_raw(
+        &self,
+        bucket: Bucket,
+        key: &str,
+        value: Vec<u8>,
+    ) -> Result<(), ObjectStoreError> {
+        self.0.put_raw(bucket, key, value).await
+    }
+
+    async fn remove_raw(&self, _bucket: Bucket, _key: &str) -> Result<(), ObjectStoreError> {
+        Err(ObjectStoreError::Other("emulated failure".into()))
+    }
+}
+
+#[db_test]
+async fn failed_witness_input_removal_does_not_block_rollback(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let tree_path = prepare_tree_and_postgres(&pool, &prover_pool, &temp_dir).await;
+    let store_factory = ObjectStoreFactory::mock();
+    let object_store = store_factory.create_store().await;
+    let object_keys = upload_witness_inputs(&pool, object_store.as_ref()).await;
+    let mut reverter = create_reverter(&pool, &tree_path);
+    let failing_store = NonRemovableObjectStore(store_factory.create_store().await);
+    reverter.set_object_store(Box::new(failing_store));
+
+    reverter
+        .rollback_tree_and_postgres(L1_BATCH_TO_KEEP, false, None)
+        .await
+        .unwrap();
+    assert_rolled_back(&pool, &reverter, &tree_path).await;
+
+    // Failed removals are recorded, so that they can be retried by the house keeper.
+    let mut storage = pool.access_storage().await.unwrap();
+    let stale_artifacts = storage
+        .witness_artifacts_dal()
+        .get_stale_witness_artifacts(10)
+        .await;
+    let stale_keys: Vec<_> = stale_artifacts
+        .iter()
+        .map(|artifact| artifact.object_key.clone())
+        .collect();
+    assert_eq!(stale_keys, object_keys[L1_BATCH_TO_KEEP.0 as usize..]);
+    assert!(stale_artifacts
+        .iter()
+        .all(|artifact| artifact.removal_attempts == 1));
+    for object_key in &object_keys {
+        assert!(object_exists(object_store.as_ref(), object_key).await);
+    }
+}
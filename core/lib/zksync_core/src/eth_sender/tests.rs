@@ -15,7 +15,7 @@ use zksync_types::{
         AggregatedOperation, L1BatchCommitOperation, L1BatchExecuteOperation, L1BatchProofOperation,
     },
     block::L1BatchHeader,
-    commitment::{L1BatchMetaParameters, L1BatchMetadata, L1BatchWithMetadata},
+    commitment::{L1BatchMetaParameters, L1BatchMetadata, L1BatchWithMetadata, TreeData},
     ethabi::Token,
     helpers::unix_timestamp_ms,
     web3::contract::Error,
@@ -831,10 +831,12 @@ async fn insert_l1_batch(tester: &mut EthSenderTester, number: L1BatchNumber) ->
         .storage()
         .await
         .blocks_dal()
-        .save_l1_batch_metadata(
+        .save_l1_batch_tree_data(
             header.number,
-            &default_l1_batch_metadata(),
-            Default::default(),
+            &TreeData {
+                metadata: default_l1_batch_metadata(),
+                parent_hash: H256::zero(),
+            },
         )
         .await
         .unwrap();
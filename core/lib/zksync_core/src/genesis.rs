@@ -3,10 +3,23 @@
 //! setups the required databases, and outputs the data required to initialize a smart contract.
 
 use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
 
-use zksync_contracts::BaseSystemContracts;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt, fs,
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use zksync_config::configs::{
+    chain::L1GenesisCheck,
+    database::{DalConsistencyPolicy, MerkleTreeMode},
+};
+use zksync_contracts::{BaseSystemContracts, SystemContractCode};
 use zksync_dal::StorageProcessor;
-use zksync_merkle_tree::domain::ZkSyncTree;
+use zksync_merkle_tree::{domain::ZkSyncTree, TreeLogEntry};
 
 use zksync_types::{
     block::DeployedContract,
@@ -14,13 +27,18 @@ use zksync_types::{
     commitment::{L1BatchCommitment, L1BatchMetadata},
     get_code_key, get_system_context_init_logs,
     protocol_version::{L1VerifierConfig, ProtocolVersion},
+    system_contracts::get_system_smart_contracts,
     tokens::{TokenInfo, TokenMetadata, ETHEREUM_ADDRESS},
+    web3::{ethabi, signing::keccak256},
     zkevm_test_harness::witness::sort_storage_access::sort_storage_access_queries,
     AccountTreeId, Address, L1BatchNumber, L2ChainId, LogQuery, MiniblockNumber, ProtocolVersionId,
     StorageKey, StorageLog, StorageLogKind, Timestamp, H256,
 };
-use zksync_utils::{be_words_to_bytes, h256_to_u256};
-use zksync_utils::{bytecode::hash_bytecode, u256_to_h256};
+use zksync_utils::{be_words_to_bytes, bytes_to_be_words, h256_to_u256};
+use zksync_utils::{
+    bytecode::{hash_bytecode, validate_bytecode},
+    u256_to_h256,
+};
 
 use crate::metadata_calculator::L1BatchWithLogs;
 
@@ -32,6 +50,315 @@ pub struct GenesisParams {
     pub system_contracts: Vec<DeployedContract>,
     pub first_verifier_address: Address,
     pub first_l1_verifier_config: L1VerifierConfig,
+    /// Custom storage writes (e.g., pre-funded account balances or pre-deployed contracts) applied
+    /// after the standard genesis logs. Must not overlap with slots written by the standard genesis.
+    pub additional_storage_logs: Vec<StorageLog>,
+    /// Bytecodes of contracts pre-deployed via `additional_storage_logs`.
+    pub additional_factory_deps: Vec<Vec<u8>>,
+}
+
+impl GenesisParams {
+    /// Loads genesis parameters from a JSON file following the [`GenesisParamsFile`] schema.
+    /// System contracts are always loaded from disk.
+    ///
+    /// Only JSON is supported; YAML genesis files (`.yaml` / `.yml`) are rejected up front
+    /// and should be converted to JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or if it contains invalid values.
+    /// Errors name the offending field.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if let Some(extension @ ("yaml" | "yml")) = extension {
+            anyhow::bail!(
+                "genesis file `{}` has unsupported format `{extension}`; only JSON genesis files are supported",
+                path.display()
+            );
+        }
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("cannot read genesis file `{}`", path.display()))?;
+        let file: GenesisParamsFile = serde_json::from_str(&contents)
+            .with_context(|| format!("cannot parse genesis file `{}`", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        file.resolve(base_dir)
+            .with_context(|| format!("invalid genesis file `{}`", path.display()))
+    }
+
+    /// Checks that additional storage logs are non-zero writes to distinct slots that are not written
+    /// by the standard genesis, and that additional factory deps are valid bytecodes.
+    fn validate_additional_state(&self, chain_id: L2ChainId) -> anyhow::Result<()> {
+        let standard_keys: HashSet<_> = genesis_write_logs(&self.system_contracts, chain_id, &[])
+            .into_iter()
+            .map(|log| log.key)
+            .collect();
+        let mut additional_keys = HashSet::with_capacity(self.additional_storage_logs.len());
+        for (i, log) in self.additional_storage_logs.iter().enumerate() {
+            anyhow::ensure!(
+                log.kind == StorageLogKind::Write,
+                "`additional_storage_logs[{i}]`: only write logs are allowed"
+            );
+            // Zero writes to new slots are no-op per deduplication rules, so they wouldn't be persisted.
+            anyhow::ensure!(
+                !log.value.is_zero(),
+                "`additional_storage_logs[{i}]`: written value must be non-zero"
+            );
+            anyhow::ensure!(
+                !standard_keys.contains(&log.key),
+                "`additional_storage_logs[{i}]`: slot {:?} collides with a slot written by the standard genesis",
+                log.key
+            );
+            anyhow::ensure!(
+                additional_keys.insert(log.key),
+                "`additional_storage_logs[{i}]`: slot {:?} is written multiple times",
+                log.key
+            );
+        }
+        for (i, bytecode) in self.additional_factory_deps.iter().enumerate() {
+            validate_bytecode(bytecode)
+                .with_context(|| format!("`additional_factory_deps[{i}]`: invalid bytecode"))?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the base system contracts are consistent with the protocol version. For protocol versions
+    /// with a frozen bootloader (see [`base_system_contracts_for_version()`]), the bootloader must match it.
+    fn validate_base_system_contracts(&self) -> anyhow::Result<()> {
+        let Some(expected_contracts) = frozen_base_system_contracts(self.protocol_version) else {
+            return Ok(());
+        };
+        let expected_hash = expected_contracts.bootloader.hash;
+        let actual_hash = self.base_system_contracts.bootloader.hash;
+        anyhow::ensure!(
+            actual_hash == expected_hash,
+            "`base_system_contracts.bootloader`: bootloader with hash {actual_hash:?} is inconsistent \
+             with protocol version {:?}, which uses bootloader with hash {expected_hash:?}",
+            self.protocol_version
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl GenesisParams {
+    /// Creates genesis params with the latest protocol version and system contracts loaded from disk.
+    pub(crate) fn mock() -> Self {
+        Self {
+            first_validator: Address::repeat_byte(0x01),
+            protocol_version: ProtocolVersionId::latest(),
+            base_system_contracts: BaseSystemContracts::load_from_disk(),
+            system_contracts: get_system_smart_contracts(),
+            first_l1_verifier_config: L1VerifierConfig {
+                params: zksync_types::protocol_version::VerifierParams {
+                    recursion_node_level_vk_hash: H256::repeat_byte(0x02),
+                    recursion_leaf_level_vk_hash: H256::repeat_byte(0x03),
+                    recursion_circuits_set_vks_hash: H256::repeat_byte(0x04),
+                },
+                recursion_scheduler_level_vk_hash: H256::repeat_byte(0x05),
+            },
+            first_verifier_address: Address::repeat_byte(0x06),
+            additional_storage_logs: vec![],
+            additional_factory_deps: vec![],
+        }
+    }
+}
+
+/// Loads base system contracts used at genesis for the specified protocol version. Bootloaders for protocol versions
+/// older than [`ProtocolVersionId::latest()`] are loaded from `etc/multivm_bootloaders`; for other versions,
+/// contracts are loaded in the same way as [`BaseSystemContracts::load_from_disk()`].
+pub fn base_system_contracts_for_version(
+    protocol_version: ProtocolVersionId,
+) -> BaseSystemContracts {
+    frozen_base_system_contracts(protocol_version)
+        .unwrap_or_else(BaseSystemContracts::load_from_disk)
+}
+
+fn frozen_base_system_contracts(
+    protocol_version: ProtocolVersionId,
+) -> Option<BaseSystemContracts> {
+    Some(match protocol_version {
+        ProtocolVersionId::Version0
+        | ProtocolVersionId::Version1
+        | ProtocolVersionId::Version2
+        | ProtocolVersionId::Version3
+        | ProtocolVersionId::Version4
+        | ProtocolVersionId::Version5
+        | ProtocolVersionId::Version6
+        | ProtocolVersionId::Version7
+        | ProtocolVersionId::Version8
+        | ProtocolVersionId::Version9
+        | ProtocolVersionId::Version10
+        | ProtocolVersionId::Version11
+        | ProtocolVersionId::Version12 => BaseSystemContracts::proved_batch_pre_virtual_blocks(),
+        ProtocolVersionId::Version13 => BaseSystemContracts::proved_batch_post_virtual_blocks(),
+        ProtocolVersionId::Version14 => {
+            BaseSystemContracts::proved_batch_post_virtual_blocks_finish_upgrade_fix()
+        }
+        // The bootloader for these versions is built from the workspace.
+        ProtocolVersionId::Version15 | ProtocolVersionId::Version16 => return None,
+    })
+}
+
+/// Schema of the genesis file loaded by [`GenesisParams::from_file()`]. The file must be JSON. Example:
+///
+/// ```json
+/// {
+///   "first_validator": "0xde03a0b5963f75f1c8485b355ff6d30f3093bde7",
+///   "protocol_version": 16,
+///   "first_verifier_address": "0x34782ee00206eab6478f2692caa800e4a581687b",
+///   "first_l1_verifier_config": {
+///     "params": {
+///       "recursion_node_level_vk_hash": "0x...",
+///       "recursion_leaf_level_vk_hash": "0x...",
+///       "recursion_circuits_set_vks_hash": "0x..."
+///     },
+///     "recursion_scheduler_level_vk_hash": "0x..."
+///   },
+///   "base_system_contracts": {
+///     "bootloader": { "path": "bootloader.yul.zbin" },
+///     "default_aa": { "hash": "0x..." }
+///   }
+/// }
+/// ```
+///
+/// Unknown fields are rejected. `base_system_contracts` and its fields may be omitted; see
+/// [`BaseSystemContractSource`] for how contracts are resolved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisParamsFile {
+    pub first_validator: Address,
+    /// Numeric protocol version ID.
+    pub protocol_version: u16,
+    pub first_verifier_address: Address,
+    pub first_l1_verifier_config: L1VerifierConfig,
+    #[serde(default)]
+    pub base_system_contracts: BaseSystemContractsSource,
+}
+
+/// Sources of the base system contracts in [`GenesisParamsFile`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BaseSystemContractsSource {
+    #[serde(default)]
+    pub bootloader: BaseSystemContractSource,
+    #[serde(default)]
+    pub default_aa: BaseSystemContractSource,
+}
+
+/// Source of a single base system contract.
+///
+/// If `path` is specified, raw contract bytecode is read from it; relative paths are resolved
+/// relative to the directory containing the genesis file. Otherwise, the contract is loaded from disk
+/// for the protocol version specified in the file (see [`base_system_contracts_for_version()`]). If `hash` is specified, it is checked
+/// against the hash of the loaded bytecode.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BaseSystemContractSource {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<H256>,
+}
+
+impl BaseSystemContractSource {
+    fn resolve(
+        &self,
+        field_name: &str,
+        base_dir: &Path,
+        default_code: &SystemContractCode,
+    ) -> anyhow::Result<SystemContractCode> {
+        let code = if let Some(path) = &self.path {
+            let path = base_dir.join(path);
+            let bytecode = fs::read(&path).with_context(|| {
+                format!(
+                    "`{field_name}.path`: cannot read bytecode from `{}`",
+                    path.display()
+                )
+            })?;
+            validate_bytecode(&bytecode).with_context(|| {
+                format!(
+                    "`{field_name}.path`: invalid bytecode in `{}`",
+                    path.display()
+                )
+            })?;
+            SystemContractCode {
+                hash: hash_bytecode(&bytecode),
+                code: bytes_to_be_words(bytecode),
+            }
+        } else {
+            default_code.clone()
+        };
+
+        if let Some(expected_hash) = self.hash {
+            anyhow::ensure!(
+                code.hash == expected_hash,
+                "`{field_name}.hash`: expected {expected_hash:?}, but the loaded bytecode has hash {:?}",
+                code.hash
+            );
+        }
+        Ok(code)
+    }
+}
+
+impl GenesisParamsFile {
+    /// Creates a file schema from the provided params. Base system contracts are specified by their hashes.
+    pub fn new(params: &GenesisParams) -> Self {
+        let hashes = params.base_system_contracts.hashes();
+        Self {
+            first_validator: params.first_validator,
+            protocol_version: params.protocol_version as u16,
+            first_verifier_address: params.first_verifier_address,
+            first_l1_verifier_config: params.first_l1_verifier_config,
+            base_system_contracts: BaseSystemContractsSource {
+                bootloader: BaseSystemContractSource {
+                    path: None,
+                    hash: Some(hashes.bootloader),
+                },
+                default_aa: BaseSystemContractSource {
+                    path: None,
+                    hash: Some(hashes.default_aa),
+                },
+            },
+        }
+    }
+
+    /// Resolves genesis params. Relative contract paths are resolved relative to `base_dir`.
+    pub fn resolve(&self, base_dir: &Path) -> anyhow::Result<GenesisParams> {
+        let protocol_version =
+            ProtocolVersionId::try_from(self.protocol_version).map_err(|_| {
+                anyhow::anyhow!(
+                    "`protocol_version`: unsupported protocol version {}",
+                    self.protocol_version
+                )
+            })?;
+
+        let default_contracts = base_system_contracts_for_version(protocol_version);
+        let sources = &self.base_system_contracts;
+        let bootloader = sources.bootloader.resolve(
+            "base_system_contracts.bootloader",
+            base_dir,
+            &default_contracts.bootloader,
+        )?;
+        let default_aa = sources.default_aa.resolve(
+            "base_system_contracts.default_aa",
+            base_dir,
+            &default_contracts.default_aa,
+        )?;
+
+        Ok(GenesisParams {
+            first_validator: self.first_validator,
+            protocol_version,
+            base_system_contracts: BaseSystemContracts {
+                bootloader,
+                default_aa,
+            },
+            system_contracts: get_system_smart_contracts(),
+            first_verifier_address: self.first_verifier_address,
+            first_l1_verifier_config: self.first_l1_verifier_config,
+            additional_storage_logs: vec![],
+            additional_factory_deps: vec![],
+        })
+    }
 }
 
 pub async fn ensure_genesis_state(
@@ -39,20 +366,28 @@ pub async fn ensure_genesis_state(
     zksync_chain_id: L2ChainId,
     genesis_params: &GenesisParams,
 ) -> anyhow::Result<H256> {
+    genesis_params
+        .validate_additional_state(zksync_chain_id)
+        .context("invalid additional genesis state")?;
     let mut transaction = storage.start_transaction().await.unwrap();
 
     // return if genesis block was already processed
     if !transaction.blocks_dal().is_genesis_needed().await.unwrap() {
         tracing::debug!("genesis is not needed!");
-        return transaction
+        let root_hash = transaction
             .blocks_dal()
             .get_l1_batch_state_root(L1BatchNumber(0))
             .await
             .unwrap()
-            .context("genesis block hash is empty");
+            .context("genesis block hash is empty")?;
+        check_genesis_fingerprint(&mut transaction, zksync_chain_id, genesis_params, root_hash)
+            .await?;
+        transaction.commit().await.unwrap();
+        return Ok(root_hash);
     }
 
     tracing::info!("running regenesis");
+    genesis_params.validate_base_system_contracts()?;
     let GenesisParams {
         first_validator,
         protocol_version,
@@ -60,6 +395,8 @@ pub async fn ensure_genesis_state(
         system_contracts,
         first_verifier_address,
         first_l1_verifier_config,
+        additional_storage_logs,
+        additional_factory_deps,
     } = genesis_params;
 
     let base_system_contracts_hashes = base_system_contracts.hashes();
@@ -73,12 +410,22 @@ pub async fn ensure_genesis_state(
         system_contracts,
         *first_l1_verifier_config,
         *first_verifier_address,
+        additional_storage_logs,
+        additional_factory_deps,
     )
     .await;
     tracing::info!("chain_schema_genesis is complete");
 
-    let storage_logs = L1BatchWithLogs::new(&mut transaction, L1BatchNumber(0)).await;
-    let storage_logs = storage_logs.unwrap().storage_logs;
+    let storage_logs = L1BatchWithLogs::new(
+        &mut transaction,
+        L1BatchNumber(0),
+        MerkleTreeMode::Full,
+        DalConsistencyPolicy::FailFast,
+    )
+    .await?;
+    let storage_logs = storage_logs
+        .context("genesis L1 batch is missing in Postgres")?
+        .storage_logs;
     let metadata = ZkSyncTree::process_genesis_batch(&storage_logs);
     let genesis_root_hash = metadata.root_hash;
     let rollup_last_leaf_index = metadata.leaf_count + 1;
@@ -102,7 +449,15 @@ pub async fn ensure_genesis_state(
     .await;
     tracing::info!("operations_schema_genesis is complete");
 
+    let fingerprint = GenesisFingerprint::new(genesis_params, zksync_chain_id, genesis_root_hash);
+    fingerprint.save(&mut transaction).await;
     transaction.commit().await.unwrap();
+    tracing::info!(
+        "Initialized genesis for chain {} with root hash {genesis_root_hash:?} \
+         and parameters fingerprint {:?}; both are persisted for later integrity checks",
+        zksync_chain_id.as_u64(),
+        fingerprint.hash()
+    );
 
     // We need to `println` this value because it will be used to initialize the smart contract.
     println!("CONTRACTS_GENESIS_ROOT={:?}", genesis_root_hash);
@@ -126,6 +481,279 @@ pub async fn ensure_genesis_state(
     Ok(genesis_root_hash)
 }
 
+/// Fingerprint of the genesis state: parameters it was created with and the resulting tree root hash.
+/// Persisted when the genesis is created, and checked against the expected one by [`ensure_genesis_state()`]
+/// on subsequent runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisFingerprint {
+    pub chain_id: u64,
+    /// Numeric protocol version ID.
+    pub protocol_version: u16,
+    pub first_validator: Address,
+    pub first_verifier_address: Address,
+    pub first_l1_verifier_config: L1VerifierConfig,
+    pub bootloader_hash: H256,
+    pub default_aa_hash: H256,
+    /// Hash of system contract addresses and bytecode hashes.
+    pub system_contracts_hash: H256,
+    /// Hash of additional storage logs and factory deps.
+    pub additional_state_hash: H256,
+    pub root_hash: H256,
+}
+
+impl GenesisFingerprint {
+    pub fn new(genesis_params: &GenesisParams, chain_id: L2ChainId, root_hash: H256) -> Self {
+        let base_system_contracts_hashes = genesis_params.base_system_contracts.hashes();
+
+        let mut system_contracts: Vec<_> = genesis_params
+            .system_contracts
+            .iter()
+            .map(|contract| {
+                (
+                    *contract.account_id.address(),
+                    hash_bytecode(&contract.bytecode),
+                )
+            })
+            .collect();
+        system_contracts.sort_unstable();
+        let system_contracts_preimage: Vec<u8> = system_contracts
+            .iter()
+            .flat_map(|(address, hash)| address.as_bytes().iter().chain(hash.as_bytes()))
+            .copied()
+            .collect();
+
+        let mut additional_logs: Vec<_> = genesis_params
+            .additional_storage_logs
+            .iter()
+            .map(|log| (log.key.hashed_key(), log.value))
+            .collect();
+        additional_logs.sort_unstable();
+        let mut additional_factory_deps: Vec<_> = genesis_params
+            .additional_factory_deps
+            .iter()
+            .map(|bytecode| hash_bytecode(bytecode))
+            .collect();
+        additional_factory_deps.sort_unstable();
+        let additional_state_preimage: Vec<u8> = additional_logs
+            .iter()
+            .flat_map(|(key, value)| [*key, *value])
+            .chain(additional_factory_deps)
+            .flat_map(|hash| hash.0)
+            .collect();
+
+        Self {
+            chain_id: chain_id.as_u64(),
+            protocol_version: genesis_params.protocol_version as u16,
+            first_validator: genesis_params.first_validator,
+            first_verifier_address: genesis_params.first_verifier_address,
+            first_l1_verifier_config: genesis_params.first_l1_verifier_config,
+            bootloader_hash: base_system_contracts_hashes.bootloader,
+            default_aa_hash: base_system_contracts_hashes.default_aa,
+            system_contracts_hash: H256(keccak256(&system_contracts_preimage)),
+            additional_state_hash: H256(keccak256(&additional_state_preimage)),
+            root_hash,
+        }
+    }
+
+    /// Returns the hash of this fingerprint.
+    pub fn hash(&self) -> H256 {
+        let serialized = serde_json::to_vec(self).expect("failed serializing genesis fingerprint");
+        H256(keccak256(&serialized))
+    }
+
+    async fn save(&self, storage: &mut StorageProcessor<'_>) {
+        let details = serde_json::to_value(self).expect("failed serializing genesis fingerprint");
+        storage
+            .blocks_dal()
+            .save_genesis_fingerprint(self.hash(), self.root_hash, &details)
+            .await
+            .unwrap();
+    }
+}
+
+/// Mismatch between the genesis fingerprint persisted in Postgres and the one expected
+/// for the provided genesis params.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenesisMismatch {
+    /// Differing fingerprint fields, ordered by the field name.
+    pub fields: Vec<GenesisFieldMismatch>,
+}
+
+/// Single field differing between the persisted and expected genesis fingerprints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenesisFieldMismatch {
+    pub field: String,
+    /// Persisted value, or `None` if the field is missing in the persisted fingerprint.
+    pub persisted: Option<serde_json::Value>,
+    /// Expected value, or `None` if the field is not expected.
+    pub expected: Option<serde_json::Value>,
+}
+
+impl GenesisMismatch {
+    fn new(persisted: &serde_json::Value, expected: &GenesisFingerprint) -> Self {
+        let expected =
+            serde_json::to_value(expected).expect("failed serializing genesis fingerprint");
+        let empty_map = serde_json::Map::new();
+        let persisted = persisted.as_object().unwrap_or(&empty_map);
+        let expected = expected.as_object().unwrap_or(&empty_map);
+
+        let field_names: BTreeSet<_> = persisted.keys().chain(expected.keys()).collect();
+        let fields = field_names
+            .into_iter()
+            .filter_map(|field| {
+                let persisted = persisted.get(field);
+                let expected = expected.get(field);
+                (persisted != expected).then(|| GenesisFieldMismatch {
+                    field: field.clone(),
+                    persisted: persisted.cloned(),
+                    expected: expected.cloned(),
+                })
+            })
+            .collect();
+        Self { fields }
+    }
+}
+
+impl fmt::Display for GenesisMismatch {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("genesis in Postgres was created with different parameters")?;
+        for (i, mismatch) in self.fields.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            let persisted = mismatch.persisted.as_ref();
+            let expected = mismatch.expected.as_ref();
+            write!(
+                formatter,
+                "{separator}`{}`: persisted {}, expected {}",
+                mismatch.field,
+                persisted.map_or_else(|| "nothing".to_owned(), ToString::to_string),
+                expected.map_or_else(|| "nothing".to_owned(), ToString::to_string)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GenesisMismatch {}
+
+/// Checks the genesis fingerprint persisted in Postgres against the one expected for `genesis_params`.
+/// If Postgres was initialized before fingerprints were introduced, only the root hash is checked,
+/// and the fingerprint is persisted.
+async fn check_genesis_fingerprint(
+    storage: &mut StorageProcessor<'_>,
+    chain_id: L2ChainId,
+    genesis_params: &GenesisParams,
+    root_hash: H256,
+) -> anyhow::Result<()> {
+    let write_logs = genesis_write_logs(
+        &genesis_params.system_contracts,
+        chain_id,
+        &genesis_params.additional_storage_logs,
+    );
+    let expected_root_hash = ZkSyncTree::process_genesis_batch(&write_logs).root_hash;
+    let expected = GenesisFingerprint::new(genesis_params, chain_id, expected_root_hash);
+
+    let persisted = storage
+        .blocks_dal()
+        .get_genesis_fingerprint()
+        .await
+        .context("cannot load genesis fingerprint")?;
+    let Some((persisted_hash, persisted_details)) = persisted else {
+        if root_hash != expected_root_hash {
+            let mut persisted = expected.clone();
+            persisted.root_hash = root_hash;
+            let persisted = serde_json::to_value(persisted)?;
+            return Err(GenesisMismatch::new(&persisted, &expected).into());
+        }
+        tracing::warn!(
+            "Genesis fingerprint is missing in Postgres; persisting fingerprint {:?} for genesis with matching root hash",
+            expected.hash()
+        );
+        expected.save(storage).await;
+        return Ok(());
+    };
+
+    if persisted_hash != expected.hash() {
+        let mismatch = GenesisMismatch::new(&persisted_details, &expected);
+        if mismatch.fields.is_empty() {
+            anyhow::bail!(
+                "genesis fingerprint hash in Postgres ({persisted_hash:?}) doesn't match its details"
+            );
+        }
+        return Err(mismatch.into());
+    }
+
+    let expected_root_hash = storage
+        .blocks_dal()
+        .get_expected_genesis_root_hash()
+        .await
+        .context("cannot load expected genesis root hash")?;
+    if expected_root_hash.is_none() {
+        tracing::info!(
+            "Expected genesis root hash is missing in Postgres; persisting {:?}",
+            expected.root_hash
+        );
+        expected.save(storage).await;
+    }
+    Ok(())
+}
+
+/// Checks the genesis L1 batch in Postgres against the genesis L1 batch hash stored in the L1 diamond proxy.
+/// `l1_batch_hash` is the future fetching the hash from L1; it is cancelled after `timeout`.
+///
+/// # Errors
+///
+/// Returns an error if the hashes do not match, or if the genesis L1 batch is missing in Postgres.
+/// If the hash cannot be fetched from L1, returns an error in the [`L1GenesisCheck::Error`] mode
+/// and logs a warning in the [`L1GenesisCheck::Warn`] mode.
+pub async fn check_genesis_on_l1(
+    storage: &mut StorageProcessor<'_>,
+    l1_batch_hash: impl Future<Output = anyhow::Result<H256>>,
+    timeout: Duration,
+    mode: L1GenesisCheck,
+) -> anyhow::Result<()> {
+    if mode == L1GenesisCheck::Disabled {
+        return Ok(());
+    }
+
+    let genesis_batch = storage
+        .blocks_dal()
+        .get_l1_batch_metadata(L1BatchNumber(0))
+        .await?
+        .context("genesis L1 batch is missing in Postgres")?;
+    let local_hash = H256(keccak256(&ethabi::encode(
+        &[genesis_batch.l1_header_data()],
+    )));
+
+    let l1_hash = match tokio::time::timeout(timeout, l1_batch_hash).await {
+        Ok(Ok(hash)) => hash,
+        Ok(Err(err)) if mode == L1GenesisCheck::Warn => {
+            tracing::warn!(
+                "Cannot fetch genesis L1 batch hash from L1, skipping the check: {err:#}"
+            );
+            return Ok(());
+        }
+        Ok(Err(err)) => return Err(err.context("cannot fetch genesis L1 batch hash from L1")),
+        Err(_) if mode == L1GenesisCheck::Warn => {
+            tracing::warn!(
+                "Timed out fetching genesis L1 batch hash from L1 after {timeout:?}, skipping the check"
+            );
+            return Ok(());
+        }
+        Err(_) => {
+            anyhow::bail!("timed out fetching genesis L1 batch hash from L1 after {timeout:?}")
+        }
+    };
+
+    anyhow::ensure!(
+        l1_hash == local_hash,
+        "genesis L1 batch hash mismatch: L1 contract stores {l1_hash:?}, while the local genesis \
+         L1 batch (root hash {:?}) has hash {local_hash:?}",
+        genesis_batch.metadata.root_hash
+    );
+    tracing::info!("Genesis L1 batch hash {local_hash:?} matches the hash stored on L1");
+    Ok(())
+}
+
 // Default account and bootloader are not a regular system contracts
 // they have never been actually deployed anywhere,
 // They are the initial code that is fed into the VM upon its start.
@@ -147,14 +775,18 @@ async fn insert_base_system_contracts_to_factory_deps(
         .await;
 }
 
-async fn insert_system_contracts(
-    storage: &mut StorageProcessor<'_>,
+/// Returns storage logs produced by the genesis L1 batch, grouped by transaction. Additional logs
+/// are placed after the standard ones.
+fn genesis_storage_logs(
     contracts: &[DeployedContract],
     chain_id: L2ChainId,
-) {
+    additional_logs: &[StorageLog],
+) -> Vec<(H256, Vec<StorageLog>)> {
+    let additional_logs =
+        (!additional_logs.is_empty()).then(|| (H256::default(), additional_logs.to_vec()));
     let system_context_init_logs = (H256::default(), get_system_context_init_logs(chain_id));
 
-    let storage_logs: Vec<(H256, Vec<StorageLog>)> = contracts
+    contracts
         .iter()
         .map(|contract| {
             let hash = hash_bytecode(&contract.bytecode);
@@ -166,15 +798,144 @@ async fn insert_system_contracts(
             )
         })
         .chain(Some(system_context_init_logs))
+        .chain(additional_logs)
+        .collect()
+}
+
+/// Returns deduplicated write logs produced by the genesis L1 batch, ordered by storage key.
+fn genesis_write_logs(
+    contracts: &[DeployedContract],
+    chain_id: L2ChainId,
+    additional_logs: &[StorageLog],
+) -> Vec<StorageLog> {
+    let storage_logs = genesis_storage_logs(contracts, chain_id, additional_logs);
+    // Mimic deduplication and ordering of logs performed when loading the L1 batch from Postgres.
+    let write_logs: BTreeMap<_, _> = storage_logs
+        .iter()
+        .flat_map(|(_, logs)| logs)
+        .filter(|log| log.kind == StorageLogKind::Write)
+        .map(|log| (log.key, *log))
         .collect();
+    write_logs.into_values().collect()
+}
+
+/// Computes the Merkle tree root hash after processing the standard genesis L1 batch (i.e., without
+/// additional storage logs). Only write logs influence the tree, so the hash doesn't depend on protective reads
+/// in the genesis batch.
+pub fn compute_genesis_root(contracts: &[DeployedContract], chain_id: L2ChainId) -> H256 {
+    let write_logs = genesis_write_logs(contracts, chain_id, &[]);
+    ZkSyncTree::process_genesis_batch(&write_logs).root_hash
+}
+
+/// Genesis state of a chain computed without accessing Postgres. Used to bootstrap L1 contracts
+/// for a new chain.
+///
+/// The artifact is deterministic for given [`GenesisParams`] and chain ID: storage logs are ordered
+/// by storage key, and the L1 batch header doesn't depend on the current time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisArtifact {
+    pub chain_id: u64,
+    pub l1_batch_header: L1BatchHeader,
+    pub root_hash: H256,
+    pub rollup_last_leaf_index: u64,
+    pub storage_logs: Vec<GenesisStorageLog>,
+}
+
+/// Storage write performed in the genesis L1 batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisStorageLog {
+    pub address: Address,
+    pub key: H256,
+    pub value: H256,
+    /// Enumeration index of the written slot in the Merkle tree.
+    pub leaf_index: u64,
+}
+
+impl GenesisArtifact {
+    /// Computes the genesis state in the same way as [`ensure_genesis_state()`], but without
+    /// persisting it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if additional storage logs or factory deps in `genesis_params` are invalid,
+    /// or if the base system contracts are inconsistent with the protocol version.
+    pub fn new(genesis_params: &GenesisParams, chain_id: L2ChainId) -> anyhow::Result<Self> {
+        genesis_params
+            .validate_additional_state(chain_id)
+            .context("invalid additional genesis state")?;
+        genesis_params.validate_base_system_contracts()?;
+        let l1_batch_header = genesis_l1_batch_header(
+            genesis_params.first_validator,
+            genesis_params.protocol_version,
+            &genesis_params.base_system_contracts,
+        );
+        let write_logs = genesis_write_logs(
+            &genesis_params.system_contracts,
+            chain_id,
+            &genesis_params.additional_storage_logs,
+        );
+        let output = ZkSyncTree::process_genesis_batch(&write_logs);
+
+        let storage_logs = write_logs
+            .iter()
+            .zip(&output.logs)
+            .map(|(log, tree_log)| {
+                let TreeLogEntry::Inserted { leaf_index } = *tree_log else {
+                    unreachable!("genesis L1 batch contains a non-insertion write: {tree_log:?}");
+                };
+                GenesisStorageLog {
+                    address: *log.key.address(),
+                    key: *log.key.key(),
+                    value: log.value,
+                    leaf_index,
+                }
+            })
+            .collect();
 
+        Ok(Self {
+            chain_id: chain_id.as_u64(),
+            l1_batch_header,
+            root_hash: output.root_hash,
+            rollup_last_leaf_index: output.leaf_count + 1,
+            storage_logs,
+        })
+    }
+}
+
+async fn insert_system_contracts(
+    storage: &mut StorageProcessor<'_>,
+    contracts: &[DeployedContract],
+    chain_id: L2ChainId,
+    additional_storage_logs: &[StorageLog],
+    additional_factory_deps: &[Vec<u8>],
+) {
+    let storage_logs = genesis_storage_logs(contracts, chain_id, additional_storage_logs);
     let mut transaction = storage.start_transaction().await.unwrap();
 
+    insert_genesis_storage_logs(&mut transaction, &storage_logs).await;
+
+    let factory_deps = contracts
+        .iter()
+        .map(|c| &c.bytecode)
+        .chain(additional_factory_deps)
+        .map(|bytecode| (hash_bytecode(bytecode), bytecode.clone()))
+        .collect();
     transaction
-        .storage_logs_dal()
-        .insert_storage_logs(MiniblockNumber(0), &storage_logs)
+        .storage_dal()
+        .insert_factory_deps(MiniblockNumber(0), &factory_deps)
         .await;
 
+    transaction.commit().await.unwrap();
+}
+
+/// Inserts storage logs for the genesis L1 batch, deduplicating them in the same way as for other L1 batches.
+/// In particular, read logs are only used to determine protective reads; they are not persisted as storage logs.
+async fn insert_genesis_storage_logs(
+    storage: &mut StorageProcessor<'_>,
+    storage_logs: &[(H256, Vec<StorageLog>)],
+) {
+    let mut transaction = storage.start_transaction().await.unwrap();
+
     // we don't produce proof for the genesis block,
     // but we still need to populate the table
     // to have the correct initial state of the merkle tree
@@ -186,6 +947,13 @@ async fn insert_system_contracts(
                 .iter()
                 .enumerate()
                 .map(move |(log_index, storage_log)| {
+                    let is_write = storage_log.kind == StorageLogKind::Write;
+                    // For reads, the read value coincides with the "written" one (i.e., the slot value).
+                    let read_value = if is_write {
+                        H256::zero()
+                    } else {
+                        storage_log.value
+                    };
                     LogQuery {
                         // Monotonically increasing Timestamp. Normally it's generated by the VM, but we don't have a VM in the genesis block.
                         timestamp: Timestamp(((tx_index << 16) + log_index) as u32),
@@ -194,9 +962,9 @@ async fn insert_system_contracts(
                         shard_id: 0,
                         address: *storage_log.key.address(),
                         key: h256_to_u256(*storage_log.key.key()),
-                        read_value: h256_to_u256(H256::zero()),
+                        read_value: h256_to_u256(read_value),
                         written_value: h256_to_u256(storage_log.value),
-                        rw_flag: storage_log.kind == StorageLogKind::Write,
+                        rw_flag: is_write,
                         rollback: false,
                         is_service: false,
                     }
@@ -224,18 +992,24 @@ async fn insert_system_contracts(
         .insert_initial_writes(L1BatchNumber(0), &written_storage_keys)
         .await;
 
-    transaction
-        .storage_dal()
-        .apply_storage_logs(&storage_logs)
-        .await;
-
-    let factory_deps = contracts
+    // Same as for other L1 batches, only write logs are persisted.
+    let write_logs: Vec<(H256, Vec<StorageLog>)> = storage_logs
         .iter()
-        .map(|c| (hash_bytecode(&c.bytecode), c.bytecode.clone()))
+        .map(|(tx_hash, logs)| {
+            let write_logs = logs
+                .iter()
+                .filter(|log| log.kind == StorageLogKind::Write)
+                .copied();
+            (*tx_hash, write_logs.collect())
+        })
         .collect();
+    transaction
+        .storage_logs_dal()
+        .insert_storage_logs(MiniblockNumber(0), &write_logs)
+        .await;
     transaction
         .storage_dal()
-        .insert_factory_deps(MiniblockNumber(0), &factory_deps)
+        .apply_storage_logs(&write_logs)
         .await;
 
     transaction.commit().await.unwrap();
@@ -251,6 +1025,59 @@ pub(crate) async fn create_genesis_l1_batch(
     system_contracts: &[DeployedContract],
     l1_verifier_config: L1VerifierConfig,
     verifier_address: Address,
+    additional_storage_logs: &[StorageLog],
+    additional_factory_deps: &[Vec<u8>],
+) {
+    let mut transaction = storage.start_transaction().await.unwrap();
+
+    insert_genesis_l1_batch_header(
+        &mut transaction,
+        first_validator_address,
+        protocol_version,
+        base_system_contracts,
+        l1_verifier_config,
+        verifier_address,
+    )
+    .await;
+    insert_base_system_contracts_to_factory_deps(&mut transaction, base_system_contracts).await;
+    insert_system_contracts(
+        &mut transaction,
+        system_contracts,
+        chain_id,
+        additional_storage_logs,
+        additional_factory_deps,
+    )
+    .await;
+
+    add_eth_token(&mut transaction).await;
+
+    transaction.commit().await.unwrap();
+}
+
+fn genesis_l1_batch_header(
+    first_validator_address: Address,
+    protocol_version: ProtocolVersionId,
+    base_system_contracts: &BaseSystemContracts,
+) -> L1BatchHeader {
+    let mut header = L1BatchHeader::new(
+        L1BatchNumber(0),
+        0,
+        first_validator_address,
+        base_system_contracts.hashes(),
+        protocol_version,
+    );
+    header.is_finished = true;
+    header
+}
+
+/// Inserts the protocol version, the genesis L1 batch header and the genesis miniblock header.
+async fn insert_genesis_l1_batch_header(
+    storage: &mut StorageProcessor<'_>,
+    first_validator_address: Address,
+    protocol_version: ProtocolVersionId,
+    base_system_contracts: &BaseSystemContracts,
+    l1_verifier_config: L1VerifierConfig,
+    verifier_address: Address,
 ) {
     let version = ProtocolVersion {
         id: protocol_version,
@@ -261,15 +1088,11 @@ pub(crate) async fn create_genesis_l1_batch(
         tx: None,
     };
 
-    let mut genesis_l1_batch_header = L1BatchHeader::new(
-        L1BatchNumber(0),
-        0,
+    let genesis_l1_batch_header = genesis_l1_batch_header(
         first_validator_address,
-        base_system_contracts.hashes(),
-        ProtocolVersionId::latest(),
+        protocol_version,
+        base_system_contracts,
     );
-    genesis_l1_batch_header.is_finished = true;
-
     let genesis_miniblock_header = MiniblockHeader {
         number: MiniblockNumber(0),
         timestamp: 0,
@@ -280,7 +1103,7 @@ pub(crate) async fn create_genesis_l1_batch(
         l1_gas_price: 0,
         l2_fair_gas_price: 0,
         base_system_contracts_hashes: base_system_contracts.hashes(),
-        protocol_version: Some(ProtocolVersionId::latest()),
+        protocol_version: Some(protocol_version),
         virtual_blocks: 0,
     };
 
@@ -306,11 +1129,6 @@ pub(crate) async fn create_genesis_l1_batch(
         .await
         .unwrap();
 
-    insert_base_system_contracts_to_factory_deps(&mut transaction, base_system_contracts).await;
-    insert_system_contracts(&mut transaction, system_contracts, chain_id).await;
-
-    add_eth_token(&mut transaction).await;
-
     transaction.commit().await.unwrap();
 }
 
@@ -371,8 +1189,11 @@ pub(crate) async fn save_genesis_l1_batch_metadata(
 #[cfg(test)]
 mod tests {
     use db_test_macro::db_test;
+    use tempfile::TempDir;
     use zksync_dal::ConnectionPool;
-    use zksync_types::system_contracts::get_system_smart_contracts;
+    use zksync_types::{utils::storage_key_for_eth_balance, U256};
+
+    use std::future;
 
     use super::*;
 
@@ -381,14 +1202,7 @@ mod tests {
         let mut conn = pool.access_storage().await.unwrap();
         conn.blocks_dal().delete_genesis().await.unwrap();
 
-        let params = GenesisParams {
-            protocol_version: ProtocolVersionId::latest(),
-            first_validator: Address::random(),
-            base_system_contracts: BaseSystemContracts::load_from_disk(),
-            system_contracts: get_system_smart_contracts(),
-            first_l1_verifier_config: L1VerifierConfig::default(),
-            first_verifier_address: Address::random(),
-        };
+        let params = GenesisParams::mock();
         ensure_genesis_state(&mut conn, L2ChainId::from(270), &params)
             .await
             .unwrap();
@@ -401,6 +1215,15 @@ mod tests {
             .unwrap();
         let root_hash = metadata.unwrap().metadata.root_hash;
         assert_ne!(root_hash, H256::zero());
+        let expected_root_hash =
+            compute_genesis_root(&params.system_contracts, L2ChainId::from(270));
+        assert_eq!(root_hash, expected_root_hash);
+        let persisted_root_hash = conn
+            .blocks_dal()
+            .get_expected_genesis_root_hash()
+            .await
+            .unwrap();
+        assert_eq!(persisted_root_hash, Some(expected_root_hash));
 
         // Check that `ensure_genesis_state()` doesn't panic on repeated runs.
         ensure_genesis_state(&mut conn, L2ChainId::from(270), &params)
@@ -409,18 +1232,470 @@ mod tests {
     }
 
     #[db_test]
-    async fn running_genesis_with_big_chain_id(pool: ConnectionPool) {
-        let mut conn: StorageProcessor<'_> = pool.access_storage().await.unwrap();
+    async fn mismatched_genesis_reruns_are_rejected(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
         conn.blocks_dal().delete_genesis().await.unwrap();
+        let chain_id = L2ChainId::from(270);
+        let params = GenesisParams::mock();
+        let root_hash = ensure_genesis_state(&mut conn, chain_id, &params)
+            .await
+            .unwrap();
+
+        let expected_fingerprint = GenesisFingerprint::new(&params, chain_id, root_hash);
+        let (fingerprint_hash, details) = conn
+            .blocks_dal()
+            .get_genesis_fingerprint()
+            .await
+            .unwrap()
+            .expect("genesis fingerprint is not persisted");
+        assert_eq!(fingerprint_hash, expected_fingerprint.hash());
+        let persisted_fingerprint: GenesisFingerprint = serde_json::from_value(details).unwrap();
+        assert_eq!(persisted_fingerprint, expected_fingerprint);
 
+        // Re-running genesis with the same params is fine.
+        let rerun_root_hash = ensure_genesis_state(&mut conn, chain_id, &params)
+            .await
+            .unwrap();
+        assert_eq!(rerun_root_hash, root_hash);
+
+        let mut changed_params = GenesisParams::mock();
+        changed_params.protocol_version = ProtocolVersionId::Version14;
+        changed_params.first_validator = Address::repeat_byte(0xff);
+        let err = ensure_genesis_state(&mut conn, chain_id, &changed_params)
+            .await
+            .unwrap_err();
+        let mismatch = err.downcast_ref::<GenesisMismatch>().unwrap();
+        let mismatched_fields: Vec<_> = mismatch.fields.iter().map(|m| m.field.as_str()).collect();
+        assert_eq!(mismatched_fields, ["first_validator", "protocol_version"]);
+        assert_eq!(
+            mismatch.fields[1].persisted,
+            Some(serde_json::json!(ProtocolVersionId::latest() as u16))
+        );
+        assert_eq!(mismatch.fields[1].expected, Some(serde_json::json!(14)));
+        let err = err.to_string();
+        assert!(err.contains("`first_validator`"), "{err}");
+        assert!(err.contains("`protocol_version`"), "{err}");
+
+        // Changing the chain ID changes the genesis state.
+        let err = ensure_genesis_state(&mut conn, L2ChainId::from(271), &params)
+            .await
+            .unwrap_err();
+        let mismatch = err.downcast_ref::<GenesisMismatch>().unwrap();
+        let mismatched_fields: Vec<_> = mismatch.fields.iter().map(|m| m.field.as_str()).collect();
+        assert_eq!(mismatched_fields, ["chain_id", "root_hash"]);
+
+        // The persisted fingerprint must not be changed by failed re-runs.
+        let (fingerprint_hash, _) = conn
+            .blocks_dal()
+            .get_genesis_fingerprint()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fingerprint_hash, expected_fingerprint.hash());
+    }
+
+    #[db_test]
+    async fn checking_genesis_on_l1(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal().delete_genesis().await.unwrap();
+        ensure_genesis_state(&mut conn, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+
+        let genesis_batch = conn
+            .blocks_dal()
+            .get_l1_batch_metadata(L1BatchNumber(0))
+            .await
+            .unwrap()
+            .unwrap();
+        let expected_hash = H256(keccak256(&ethabi::encode(
+            &[genesis_batch.l1_header_data()],
+        )));
+        let timeout = Duration::from_millis(50);
+        for mode in [L1GenesisCheck::Warn, L1GenesisCheck::Error] {
+            check_genesis_on_l1(&mut conn, async { Ok(expected_hash) }, timeout, mode)
+                .await
+                .unwrap();
+
+            let wrong_hash = H256::repeat_byte(0xfe);
+            let err = check_genesis_on_l1(&mut conn, async { Ok(wrong_hash) }, timeout, mode)
+                .await
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("hash mismatch"), "{err}");
+            assert!(err.contains(&format!("{wrong_hash:?}")), "{err}");
+            assert!(err.contains(&format!("{expected_hash:?}")), "{err}");
+        }
+
+        let unreachable_l1 = future::pending::<anyhow::Result<H256>>;
+        check_genesis_on_l1(&mut conn, unreachable_l1(), timeout, L1GenesisCheck::Warn)
+            .await
+            .unwrap();
+        let err = check_genesis_on_l1(&mut conn, unreachable_l1(), timeout, L1GenesisCheck::Error)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("timed out"), "{err}");
+        let failing_l1 = async { Err(anyhow::anyhow!("connection refused")) };
+        check_genesis_on_l1(&mut conn, failing_l1, timeout, L1GenesisCheck::Warn)
+            .await
+            .unwrap();
+        // The check must not access L1 at all if it's disabled.
+        check_genesis_on_l1(
+            &mut conn,
+            unreachable_l1(),
+            timeout,
+            L1GenesisCheck::Disabled,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[db_test]
+    async fn running_genesis_with_previous_protocol_version(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal().delete_genesis().await.unwrap();
+        let protocol_version = ProtocolVersionId::try_from(ProtocolVersionId::latest() as u16 - 1)
+            .expect("no previous protocol version");
         let params = GenesisParams {
-            protocol_version: ProtocolVersionId::latest(),
-            first_validator: Address::random(),
-            base_system_contracts: BaseSystemContracts::load_from_disk(),
-            system_contracts: get_system_smart_contracts(),
-            first_l1_verifier_config: L1VerifierConfig::default(),
-            first_verifier_address: Address::random(),
+            protocol_version,
+            base_system_contracts: base_system_contracts_for_version(protocol_version),
+            ..GenesisParams::mock()
         };
+        let expected_hashes = params.base_system_contracts.hashes();
+        ensure_genesis_state(&mut conn, L2ChainId::from(270), &params)
+            .await
+            .unwrap();
+
+        let header = conn
+            .blocks_dal()
+            .get_l1_batch_header(L1BatchNumber(0))
+            .await
+            .unwrap()
+            .expect("genesis L1 batch is not persisted");
+        assert_eq!(header.protocol_version, Some(protocol_version));
+        assert_eq!(header.base_system_contracts_hashes, expected_hashes);
+        let stored_version = conn
+            .protocol_versions_dal()
+            .get_protocol_version(protocol_version)
+            .await
+            .expect("genesis protocol version is not persisted");
+        assert_eq!(stored_version.base_system_contracts_hashes, expected_hashes);
+        assert_eq!(
+            stored_version.l1_verifier_config,
+            params.first_l1_verifier_config
+        );
+        assert_eq!(
+            conn.protocol_versions_dal().last_version_id().await,
+            Some(protocol_version)
+        );
+    }
+
+    #[test]
+    fn base_system_contracts_inconsistent_with_protocol_version_are_rejected() {
+        let params = GenesisParams {
+            protocol_version: ProtocolVersionId::Version14,
+            base_system_contracts: BaseSystemContracts::proved_batch_pre_virtual_blocks(),
+            ..GenesisParams::mock()
+        };
+        let err = GenesisArtifact::new(&params, L2ChainId::from(270))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`base_system_contracts.bootloader`"), "{err}");
+        assert!(err.contains("Version14"), "{err}");
+
+        let params = GenesisParams {
+            base_system_contracts: base_system_contracts_for_version(ProtocolVersionId::Version14),
+            ..params
+        };
+        GenesisArtifact::new(&params, L2ChainId::from(270)).unwrap();
+    }
+
+    fn assert_params_eq(actual: &GenesisParams, expected: &GenesisParams) {
+        assert_eq!(actual.first_validator, expected.first_validator);
+        assert_eq!(actual.protocol_version, expected.protocol_version);
+        assert_eq!(actual.base_system_contracts, expected.base_system_contracts);
+        assert_eq!(
+            actual.first_verifier_address,
+            expected.first_verifier_address
+        );
+        assert_eq!(
+            actual.first_l1_verifier_config,
+            expected.first_l1_verifier_config
+        );
+    }
+
+    #[test]
+    fn genesis_params_file_roundtrip() {
+        let params = GenesisParams::mock();
+        let file = GenesisParamsFile::new(&params);
+        let serialized = serde_json::to_string_pretty(&file).unwrap();
+        let deserialized: GenesisParamsFile = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, file);
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("genesis.json");
+        fs::write(&path, serialized).unwrap();
+        let loaded_params = GenesisParams::from_file(&path).unwrap();
+        assert_params_eq(&loaded_params, &params);
+    }
+
+    #[test]
+    fn yaml_genesis_files_are_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("genesis.yaml");
+        fs::write(
+            &path,
+            "first_validator: '0x0000000000000000000000000000000000000001'",
+        )
+        .unwrap();
+        let err = GenesisParams::from_file(&path).unwrap_err().to_string();
+        assert!(
+            err.contains("only JSON genesis files are supported"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn loading_genesis_params_with_bytecode_paths() {
+        let params = GenesisParams::mock();
+        let temp_dir = TempDir::new().unwrap();
+        let bootloader_code = &params.base_system_contracts.bootloader.code;
+        fs::write(
+            temp_dir.path().join("bootloader.zbin"),
+            be_words_to_bytes(bootloader_code),
+        )
+        .unwrap();
+
+        let mut file = GenesisParamsFile::new(&params);
+        file.base_system_contracts.bootloader.path = Some("bootloader.zbin".into());
+        let loaded_params = file.resolve(temp_dir.path()).unwrap();
+        assert_params_eq(&loaded_params, &params);
+
+        // Check that hashes are validated for contracts loaded from a path.
+        file.base_system_contracts.bootloader.hash = Some(H256::repeat_byte(0xff));
+        let err = file.resolve(temp_dir.path()).unwrap_err().to_string();
+        assert!(
+            err.contains("`base_system_contracts.bootloader.hash`"),
+            "{err}"
+        );
+
+        file.base_system_contracts.bootloader.path = Some("missing.zbin".into());
+        let err = file.resolve(temp_dir.path()).unwrap_err().to_string();
+        assert!(
+            err.contains("`base_system_contracts.bootloader.path`"),
+            "{err}"
+        );
+
+        // Bytecode length must be divisible by 32.
+        fs::write(temp_dir.path().join("invalid.zbin"), [0_u8; 33]).unwrap();
+        file.base_system_contracts.bootloader.path = Some("invalid.zbin".into());
+        let err = file.resolve(temp_dir.path()).unwrap_err().to_string();
+        assert!(
+            err.contains("`base_system_contracts.bootloader.path`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn genesis_params_file_errors_name_offending_fields() {
+        let params = GenesisParams::mock();
+        let mut file = GenesisParamsFile::new(&params);
+        file.protocol_version = u16::MAX;
+        let err = file.resolve(Path::new("")).unwrap_err().to_string();
+        assert!(err.contains("`protocol_version`"), "{err}");
+
+        file.protocol_version = params.protocol_version as u16;
+        file.base_system_contracts.default_aa.hash = Some(H256::zero());
+        let err = file.resolve(Path::new("")).unwrap_err().to_string();
+        assert!(
+            err.contains("`base_system_contracts.default_aa.hash`"),
+            "{err}"
+        );
+
+        let mut json = serde_json::to_value(GenesisParamsFile::new(&params)).unwrap();
+        json.as_object_mut().unwrap().remove("first_validator");
+        let err = serde_json::from_value::<GenesisParamsFile>(json.clone()).unwrap_err();
+        assert!(err.to_string().contains("`first_validator`"), "{err}");
+
+        json["first_validator"] = serde_json::json!(Address::zero());
+        json["base_system_contracts"]["bootloader"]["url"] = serde_json::json!("file:///");
+        let err = serde_json::from_value::<GenesisParamsFile>(json).unwrap_err();
+        assert!(err.to_string().contains("`url`"), "{err}");
+    }
+
+    #[test]
+    fn loading_genesis_params_fixture() {
+        let home = std::env::var("ZKSYNC_HOME").unwrap_or_else(|_| ".".into());
+        let path = Path::new(&home).join("etc/test_config/constant/genesis.json");
+        let params = GenesisParams::from_file(&path).unwrap();
+
+        assert_eq!(params.protocol_version, ProtocolVersionId::Version16);
+        assert_eq!(
+            params.first_validator,
+            "0xde03a0b5963f75f1c8485b355ff6d30f3093bde7"
+                .parse::<Address>()
+                .unwrap()
+        );
+        assert_eq!(
+            params.base_system_contracts,
+            BaseSystemContracts::load_from_disk()
+        );
+        assert_eq!(params.system_contracts, get_system_smart_contracts());
+    }
+
+    fn golden_genesis_params() -> GenesisParams {
+        let contract_code = |bytecode: Vec<u8>| SystemContractCode {
+            hash: hash_bytecode(&bytecode),
+            code: bytes_to_be_words(bytecode),
+        };
+        let deployed_contract = |address: u64, bytecode: Vec<u8>| {
+            DeployedContract::new(
+                AccountTreeId::new(Address::from_low_u64_be(address)),
+                bytecode,
+            )
+        };
+
+        GenesisParams {
+            protocol_version: ProtocolVersionId::Version15,
+            base_system_contracts: BaseSystemContracts {
+                bootloader: contract_code(vec![1; 32]),
+                default_aa: contract_code(vec![2; 32]),
+            },
+            system_contracts: vec![
+                deployed_contract(0x8002, vec![3; 32]),
+                deployed_contract(0x8003, vec![4; 96]),
+            ],
+            first_verifier_address: Address::repeat_byte(2),
+            first_l1_verifier_config: L1VerifierConfig::default(),
+            ..GenesisParams::mock()
+        }
+    }
+
+    #[test]
+    fn genesis_artifact_matches_golden_file() {
+        let chain_id = L2ChainId::from(270);
+        let params = golden_genesis_params();
+        let artifact = GenesisArtifact::new(&params, chain_id).unwrap();
+        assert_eq!(GenesisArtifact::new(&params, chain_id).unwrap(), artifact);
+        assert_eq!(
+            artifact.root_hash,
+            compute_genesis_root(&params.system_contracts, chain_id)
+        );
+
+        let serialized = serde_json::to_string_pretty(&artifact).unwrap();
+        let deserialized: GenesisArtifact = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, artifact);
+
+        let home = std::env::var("ZKSYNC_HOME").unwrap_or_else(|_| ".".into());
+        let path = Path::new(&home).join("etc/test_config/constant/genesis_artifact.json");
+        let golden_artifact = fs::read_to_string(&path).unwrap();
+        let golden_artifact: GenesisArtifact = serde_json::from_str(&golden_artifact).unwrap();
+        assert_eq!(artifact, golden_artifact);
+    }
+
+    #[db_test]
+    async fn running_genesis_with_additional_storage_logs(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal().delete_genesis().await.unwrap();
+
+        let chain_id = L2ChainId::from(270);
+        let account = Address::repeat_byte(0x23);
+        let balance_key = storage_key_for_eth_balance(&account);
+        let balance = u256_to_h256(U256::exp10(18));
+        let balance_log = StorageLog::new_write_log(balance_key, balance);
+        let custom_bytecode = vec![0x42; 32];
+        let mut params = GenesisParams::mock();
+        params.additional_storage_logs = vec![balance_log];
+        params.additional_factory_deps = vec![custom_bytecode.clone()];
+
+        let root_hash = ensure_genesis_state(&mut conn, chain_id, &params)
+            .await
+            .unwrap();
+        assert_ne!(
+            root_hash,
+            compute_genesis_root(&params.system_contracts, chain_id)
+        );
+
+        let stored_balance = conn.storage_dal().get_by_key(&balance_key).await;
+        assert_eq!(stored_balance, Some(balance));
+        let stored_bytecode = conn
+            .storage_dal()
+            .get_factory_dep(hash_bytecode(&custom_bytecode))
+            .await;
+        assert_eq!(stored_bytecode, Some(custom_bytecode));
+
+        let l1_batch = L1BatchWithLogs::new(
+            &mut conn,
+            L1BatchNumber(0),
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(l1_batch.storage_logs.contains(&balance_log));
+
+        let artifact = GenesisArtifact::new(&params, chain_id).unwrap();
+        assert_eq!(artifact.root_hash, root_hash);
+        let artifact_log = artifact
+            .storage_logs
+            .iter()
+            .find(|log| log.address == *balance_key.address() && log.key == *balance_key.key());
+        let artifact_log = artifact_log
+            .unwrap_or_else(|| panic!("balance of {account:?} is missing in genesis artifact"));
+        assert_eq!(artifact_log.value, balance);
+    }
+
+    #[test]
+    fn invalid_additional_storage_logs_are_rejected() {
+        let chain_id = L2ChainId::from(270);
+        let system_context_log = get_system_context_init_logs(chain_id)[0];
+        let balance_key = storage_key_for_eth_balance(&Address::repeat_byte(0x23));
+        let balance_log = StorageLog::new_write_log(balance_key, H256::repeat_byte(1));
+
+        let invalid_logs = [
+            (
+                vec![StorageLog::new_write_log(
+                    system_context_log.key,
+                    H256::repeat_byte(1),
+                )],
+                "`additional_storage_logs[0]`: slot",
+            ),
+            (
+                vec![balance_log, balance_log],
+                "`additional_storage_logs[1]`: slot",
+            ),
+            (
+                vec![StorageLog::new_write_log(balance_key, H256::zero())],
+                "must be non-zero",
+            ),
+            (
+                vec![StorageLog::new_read_log(balance_key, H256::repeat_byte(1))],
+                "only write logs",
+            ),
+        ];
+        for (logs, expected_message) in invalid_logs {
+            let mut params = GenesisParams::mock();
+            params.additional_storage_logs = logs;
+            let err = GenesisArtifact::new(&params, chain_id).unwrap_err();
+            let err = format!("{err:#}");
+            assert!(err.contains(expected_message), "{err}");
+        }
+
+        let mut params = GenesisParams::mock();
+        params.additional_factory_deps = vec![vec![0; 64]];
+        let err = GenesisArtifact::new(&params, chain_id).unwrap_err();
+        let err = format!("{err:#}");
+        assert!(err.contains("`additional_factory_deps[0]`"), "{err}");
+    }
+
+    #[db_test]
+    async fn running_genesis_with_big_chain_id(pool: ConnectionPool) {
+        let mut conn: StorageProcessor<'_> = pool.access_storage().await.unwrap();
+        conn.blocks_dal().delete_genesis().await.unwrap();
+
+        let params = GenesisParams::mock();
         ensure_genesis_state(&mut conn, L2ChainId::max(), &params)
             .await
             .unwrap();
@@ -433,4 +1708,70 @@ mod tests {
         let root_hash = metadata.unwrap().unwrap().metadata.root_hash;
         assert_ne!(root_hash, H256::zero());
     }
+
+    #[db_test]
+    async fn running_genesis_with_protective_reads(pool: ConnectionPool) {
+        let mut conn = pool.access_storage().await.unwrap();
+        conn.blocks_dal().delete_genesis().await.unwrap();
+
+        let chain_id = L2ChainId::from(270);
+        let base_system_contracts = BaseSystemContracts::load_from_disk();
+        let system_contracts = get_system_smart_contracts();
+        insert_genesis_l1_batch_header(
+            &mut conn,
+            Address::random(),
+            ProtocolVersionId::latest(),
+            &base_system_contracts,
+            L1VerifierConfig::default(),
+            Address::random(),
+        )
+        .await;
+
+        let mut storage_logs = genesis_storage_logs(&system_contracts, chain_id);
+        let account = AccountTreeId::new(Address::repeat_byte(0xaa));
+        let read_keys: Vec<_> = (0_u64..5)
+            .map(|i| StorageKey::new(account, H256::from_low_u64_be(i)))
+            .collect();
+        let mut read_logs: Vec<_> = read_keys
+            .iter()
+            .map(|&key| StorageLog::new_read_log(key, H256::zero()))
+            .collect();
+        // Reading a slot written to earlier in the batch must not produce a protective read.
+        let written_log = storage_logs[0].1[0];
+        read_logs.push(StorageLog::new_read_log(written_log.key, written_log.value));
+        storage_logs.push((H256::repeat_byte(1), read_logs));
+        insert_genesis_storage_logs(&mut conn, &storage_logs).await;
+
+        let protective_reads = conn
+            .storage_logs_dedup_dal()
+            .get_protective_reads_for_l1_batch(L1BatchNumber(0))
+            .await;
+        let expected_protective_reads: HashSet<_> = read_keys.iter().copied().collect();
+        assert_eq!(protective_reads, expected_protective_reads);
+
+        let l1_batch = L1BatchWithLogs::new(
+            &mut conn,
+            L1BatchNumber(0),
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let loaded_read_keys: HashSet<_> = l1_batch
+            .storage_logs
+            .iter()
+            .filter(|log| log.kind == StorageLogKind::Read)
+            .map(|log| log.key)
+            .collect();
+        assert_eq!(loaded_read_keys, expected_protective_reads);
+        let written_log_is_loaded = l1_batch
+            .storage_logs
+            .iter()
+            .any(|log| log.kind == StorageLogKind::Write && log.key == written_log.key);
+        assert!(written_log_is_loaded);
+
+        let root_hash = ZkSyncTree::process_genesis_batch(&l1_batch.storage_logs).root_hash;
+        assert_eq!(root_hash, compute_genesis_root(&system_contracts, chain_id));
+    }
 }
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use zksync_dal::ConnectionPool;
 use zksync_object_store::{Bucket, ObjectStore, ObjectStoreError, ObjectStoreFactory};
+use zksync_types::L1BatchNumber;
 
 use zksync_prover_utils::periodic_job::PeriodicJob;
 
@@ -98,15 +99,35 @@ impl GcsBlobCleaner {
             .get_merkle_tree_paths_blob_urls_to_be_cleaned(BATCH_CLEANUP_SIZE)
             .await
             .unwrap();
-        let l1_batch_numbers = self
-            .cleanup_blob_urls(Bucket::WitnessInput, blob_urls)
-            .await;
+        let l1_batch_numbers = self.cleanup_witness_input_urls(blob_urls).await;
         conn.blocks_dal()
             .mark_gcs_blobs_as_cleaned(&l1_batch_numbers)
             .await
             .unwrap();
     }
 
+    /// Unlike other blobs, witness inputs may be stored in the chunked format, in which case
+    /// the blob URL points to the manifest and chunks must be removed as well.
+    async fn cleanup_witness_input_urls(&self, blob_urls: Vec<(i64, String)>) -> Vec<i64> {
+        if !blob_urls.is_empty() {
+            tracing::info!(
+                "Found {} {} for cleaning blobs",
+                blob_urls.len(),
+                Bucket::WitnessInput
+            );
+        }
+
+        for (l1_batch_number, url) in &blob_urls {
+            let l1_batch_number = L1BatchNumber(*l1_batch_number as u32);
+            handle_remove_result(
+                self.object_store
+                    .remove_witness_input(l1_batch_number, url)
+                    .await,
+            );
+        }
+        blob_urls.into_iter().map(|(id, _)| id).collect()
+    }
+
     async fn cleanup_leaf_aggregation_witness_jobs_blobs(&self) {
         let mut conn = self.pool.access_storage().await.unwrap();
 
@@ -166,3 +187,48 @@ impl PeriodicJob for GcsBlobCleaner {
         self.cleaning_interval_ms
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use db_test_macro::db_test;
+
+    use zksync_object_store::{witness_input_chunk_key, StoredObject};
+    use zksync_types::proofs::PrepareBasicCircuitsJob;
+
+    use super::*;
+    use crate::metadata_calculator::tests::{create_mock_witness, store_chunked_witness};
+
+    #[db_test]
+    async fn cleaning_up_chunked_witness_inputs(pool: ConnectionPool) {
+        let store_factory = ObjectStoreFactory::mock();
+        let cleaner = GcsBlobCleaner::new(&store_factory, pool, 1_000).await;
+        let object_store = store_factory.create_store().await;
+        let job = create_mock_witness(5);
+        let manifest_key =
+            store_chunked_witness(object_store.as_ref(), L1BatchNumber(1), &job, 2).await;
+        let single_object_key = object_store.put(L1BatchNumber(2), &job).await.unwrap();
+
+        let blob_urls = vec![(1, manifest_key.clone()), (2, single_object_key)];
+        let cleaned = cleaner.cleanup_witness_input_urls(blob_urls.clone()).await;
+        assert_eq!(cleaned, [1, 2]);
+        for l1_batch_number in [L1BatchNumber(1), L1BatchNumber(2)] {
+            let err = object_store
+                .get_witness_input(l1_batch_number)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+        }
+        for chunk_index in 0..3 {
+            let key = witness_input_chunk_key(L1BatchNumber(1), chunk_index);
+            let err = object_store
+                .get_raw(PrepareBasicCircuitsJob::BUCKET, &key)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+        }
+
+        // Cleaning up must be idempotent, e.g., if marking blobs as cleaned in Postgres has failed.
+        let cleaned = cleaner.cleanup_witness_input_urls(blob_urls).await;
+        assert_eq!(cleaned, [1, 2]);
+    }
+}
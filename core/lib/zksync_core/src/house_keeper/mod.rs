@@ -10,6 +10,7 @@ pub mod gcs_blob_cleaner;
 pub mod gpu_prover_queue_monitor;
 pub mod prover_job_retry_manager;
 pub mod prover_queue_monitor;
+pub mod stale_witness_artifacts_cleaner;
 pub mod waiting_to_queued_fri_witness_job_mover;
 pub mod waiting_to_queued_witness_job_mover;
 pub mod witness_generator_queue_monitor;
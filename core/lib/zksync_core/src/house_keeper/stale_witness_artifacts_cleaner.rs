@@ -0,0 +1,211 @@
+use async_trait::async_trait;
+
+use zksync_dal::{ConnectionPool, StorageProcessor};
+use zksync_object_store::{ObjectStore, ObjectStoreError, ObjectStoreFactory};
+use zksync_prover_utils::periodic_job::PeriodicJob;
+
+/// Maximum number of stale witness artifacts removed in a single run of [`StaleWitnessArtifactsCleaner`].
+const BATCH_CLEANUP_SIZE: usize = 100;
+
+/// Outcome of [`remove_stale_witness_artifacts()`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StaleArtifactsRemoval {
+    /// Number of objects removed from the object store.
+    pub removed: usize,
+    /// Number of objects that failed to be removed; their removal will be retried.
+    pub failed: usize,
+}
+
+/// Removes up to `limit` witness input objects uploaded for reverted L1 batches from the object store.
+/// For witness inputs stored in the chunked format, chunks are removed together with the manifest.
+/// Removal failures are logged and recorded in Postgres, but do not result in an error; failed removals
+/// are retried by [`StaleWitnessArtifactsCleaner`].
+pub async fn remove_stale_witness_artifacts(
+    storage: &mut StorageProcessor<'_>,
+    object_store: &dyn ObjectStore,
+    limit: usize,
+) -> StaleArtifactsRemoval {
+    let artifacts = storage
+        .witness_artifacts_dal()
+        .get_stale_witness_artifacts(limit)
+        .await;
+
+    let mut removed_keys = vec![];
+    let mut failed_keys = vec![];
+    for artifact in artifacts {
+        let object_key = artifact.object_key;
+        match object_store
+            .remove_witness_input(artifact.l1_batch_number, &object_key)
+            .await
+        {
+            // The object may be already removed, e.g. if updating Postgres after a previous removal has failed.
+            Ok(()) | Err(ObjectStoreError::KeyNotFound(_)) => {
+                tracing::info!(
+                    "Removed stale witness input `{object_key}` for reverted L1 batch #{}",
+                    artifact.l1_batch_number
+                );
+                removed_keys.push(object_key);
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "Failed removing stale witness input `{object_key}` for reverted L1 batch #{} \
+                     (previous failed attempts: {}): {err}",
+                    artifact.l1_batch_number,
+                    artifact.removal_attempts
+                );
+                failed_keys.push(object_key);
+            }
+        }
+    }
+
+    let mut witness_artifacts_dal = storage.witness_artifacts_dal();
+    if !removed_keys.is_empty() {
+        witness_artifacts_dal
+            .remove_stale_witness_artifacts(&removed_keys)
+            .await;
+    }
+    if !failed_keys.is_empty() {
+        witness_artifacts_dal
+            .record_failed_stale_artifact_removals(&failed_keys)
+            .await;
+    }
+    StaleArtifactsRemoval {
+        removed: removed_keys.len(),
+        failed: failed_keys.len(),
+    }
+}
+
+/// Periodically retries removing witness input objects uploaded for reverted L1 batches.
+/// Such objects are normally removed by the block reverter, but their removal may fail.
+#[derive(Debug)]
+pub struct StaleWitnessArtifactsCleaner {
+    object_store: Box<dyn ObjectStore>,
+    cleaning_interval_ms: u64,
+    pool: ConnectionPool,
+}
+
+impl StaleWitnessArtifactsCleaner {
+    pub async fn new(
+        store_factory: &ObjectStoreFactory,
+        pool: ConnectionPool,
+        cleaning_interval_ms: u64,
+    ) -> Self {
+        Self {
+            object_store: store_factory.create_store().await,
+            cleaning_interval_ms,
+            pool,
+        }
+    }
+}
+
+#[async_trait]
+impl PeriodicJob for StaleWitnessArtifactsCleaner {
+    const SERVICE_NAME: &'static str = "StaleWitnessArtifactsCleaner";
+
+    async fn run_routine_task(&mut self) -> anyhow::Result<()> {
+        let mut storage = self.pool.access_storage().await?;
+        let removal =
+            remove_stale_witness_artifacts(&mut storage, &*self.object_store, BATCH_CLEANUP_SIZE)
+                .await;
+        if removal != StaleArtifactsRemoval::default() {
+            tracing::info!("Cleaned up stale witness inputs: {removal:?}");
+        }
+        Ok(())
+    }
+
+    fn polling_interval_ms(&self) -> u64 {
+        self.cleaning_interval_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use db_test_macro::db_test;
+
+    use zksync_object_store::{witness_input_chunk_key, Bucket};
+    use zksync_types::{block::L1BatchHeader, L1BatchNumber, H256};
+
+    use super::*;
+    use crate::metadata_calculator::tests::{create_mock_witness, store_chunked_witness};
+
+    #[db_test]
+    async fn removing_stale_chunked_witness_input(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        storage
+            .protocol_versions_dal()
+            .save_protocol_version_with_tx(Default::default())
+            .await;
+        let object_store = ObjectStoreFactory::mock().create_store().await;
+        let job = create_mock_witness(5);
+        let mut object_keys = vec![];
+        for number in 1..=2 {
+            let l1_batch_number = L1BatchNumber(number);
+            let header = L1BatchHeader::new(
+                l1_batch_number,
+                0,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            );
+            storage
+                .blocks_dal()
+                .insert_l1_batch(&header, &[], Default::default())
+                .await
+                .unwrap();
+            let object_key =
+                store_chunked_witness(object_store.as_ref(), l1_batch_number, &job, 2).await;
+            storage
+                .witness_artifacts_dal()
+                .save_witness_artifact(l1_batch_number, &object_key, 100, H256::zero())
+                .await;
+            object_keys.push(object_key);
+        }
+
+        storage
+            .witness_artifacts_dal()
+            .mark_witness_artifacts_as_stale(L1BatchNumber(1))
+            .await;
+        storage
+            .blocks_dal()
+            .delete_l1_batches(L1BatchNumber(1))
+            .await
+            .unwrap();
+
+        let removal = remove_stale_witness_artifacts(&mut storage, object_store.as_ref(), 10).await;
+        assert_eq!(
+            removal,
+            StaleArtifactsRemoval {
+                removed: 1,
+                failed: 0
+            }
+        );
+        let stale_artifacts = storage
+            .witness_artifacts_dal()
+            .get_stale_witness_artifacts(10)
+            .await;
+        assert!(stale_artifacts.is_empty(), "{stale_artifacts:?}");
+
+        // The witness input for the reverted L1 batch must be removed together with all its chunks,
+        // while the witness input for the retained L1 batch must be intact.
+        for chunk_index in 0..3 {
+            let key = witness_input_chunk_key(L1BatchNumber(2), chunk_index);
+            let err = object_store
+                .get_raw(Bucket::WitnessInput, &key)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+        }
+        let err = object_store
+            .get_raw(Bucket::WitnessInput, &object_keys[1])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ObjectStoreError::KeyNotFound(_)), "{err}");
+
+        let retained_job = object_store
+            .get_witness_input(L1BatchNumber(1))
+            .await
+            .unwrap();
+        let retained_paths: Vec<_> = retained_job.into_merkle_paths().collect();
+        assert_eq!(retained_paths, job.into_merkle_paths().collect::<Vec<_>>());
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(clippy::upper_case_acronyms, clippy::derive_partial_eq_without_eq)]
 
-use std::{str::FromStr, sync::Arc, time::Instant};
+use std::{fs, path::Path, str::FromStr, sync::Arc, time::Instant};
 
 use anyhow::Context as _;
 use futures::channel::oneshot;
@@ -27,12 +27,12 @@ use zksync_config::{
     ApiConfig, ContractsConfig, DBConfig, ETHClientConfig, ETHSenderConfig, FetcherConfig,
     ProverConfigs,
 };
-use zksync_contracts::BaseSystemContracts;
+use zksync_contracts::{zksync_contract, BaseSystemContracts};
 use zksync_dal::{
     connection::DbVariant, healthcheck::ConnectionPoolHealthCheck, ConnectionPool, StorageProcessor,
 };
 use zksync_eth_client::clients::http::QueryClient;
-use zksync_eth_client::{clients::http::PKSigningClient, BoundEthInterface};
+use zksync_eth_client::{clients::http::PKSigningClient, BoundEthInterface, EthInterface};
 use zksync_health_check::{CheckHealth, HealthStatus, ReactiveHealthCheck};
 use zksync_object_store::ObjectStoreFactory;
 use zksync_prover_utils::periodic_job::PeriodicJob;
@@ -42,7 +42,8 @@ use zksync_types::{
     proofs::AggregationRound,
     protocol_version::{L1VerifierConfig, VerifierParams},
     system_contracts::get_system_smart_contracts,
-    Address, PackedEthSignature, ProtocolVersionId,
+    web3::{contract::Options, ethabi::Token},
+    Address, PackedEthSignature, ProtocolVersionId, H256, U256,
 };
 use zksync_verification_key_server::get_cached_commitments;
 
@@ -62,6 +63,7 @@ use crate::house_keeper::gcs_blob_cleaner::GcsBlobCleaner;
 use crate::house_keeper::{
     blocks_state_reporter::L1BatchMetricsReporter, gpu_prover_queue_monitor::GpuProverQueueMonitor,
     prover_job_retry_manager::ProverJobRetryManager, prover_queue_monitor::ProverStatsReporter,
+    stale_witness_artifacts_cleaner::StaleWitnessArtifactsCleaner,
     waiting_to_queued_fri_witness_job_mover::WaitingToQueuedFriWitnessJobMover,
     waiting_to_queued_witness_job_mover::WaitingToQueuedWitnessJobMover,
     witness_generator_queue_monitor::WitnessGeneratorStatsReporter,
@@ -114,6 +116,78 @@ pub async fn genesis_init(
     let mut storage = StorageProcessor::establish_connection(true)
         .await
         .context("establish_connection")?;
+    let genesis_params = genesis_params(eth_sender, network_config, contracts_config)?;
+    genesis::ensure_genesis_state(
+        &mut storage,
+        network_config.zksync_network_id,
+        &genesis_params,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Computes the genesis state without accessing Postgres and writes it as a JSON artifact to `path`.
+pub fn export_genesis_artifact(
+    eth_sender: &ETHSenderConfig,
+    network_config: &NetworkConfig,
+    contracts_config: &ContractsConfig,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let genesis_params = genesis_params(eth_sender, network_config, contracts_config)?;
+    let artifact =
+        genesis::GenesisArtifact::new(&genesis_params, network_config.zksync_network_id)?;
+    let artifact = serde_json::to_string_pretty(&artifact).context("cannot serialize artifact")?;
+    fs::write(path, artifact)
+        .with_context(|| format!("cannot write genesis artifact to `{}`", path.display()))?;
+    tracing::info!("Exported genesis artifact to `{}`", path.display());
+    Ok(())
+}
+
+/// Checks the genesis L1 batch in Postgres against the genesis L1 batch hash stored in the L1 diamond proxy,
+/// according to [`NetworkConfig::l1_genesis_check`].
+pub async fn check_genesis_on_l1(
+    network_config: &NetworkConfig,
+    eth_client_config: &ETHClientConfig,
+    contracts_config: &ContractsConfig,
+) -> anyhow::Result<()> {
+    let mut storage = StorageProcessor::establish_connection(true)
+        .await
+        .context("establish_connection")?;
+    let eth_client = QueryClient::new(&eth_client_config.web3_url)
+        .context("cannot create L1 client for genesis check")?;
+    let l1_batch_hash = async {
+        eth_client
+            .call_contract_function::<H256, _, _, _>(
+                "storedBlockHash",
+                Token::Uint(U256::zero()), // genesis L1 batch number
+                None,
+                Options::default(),
+                None,
+                contracts_config.diamond_proxy_addr,
+                zksync_contract(),
+            )
+            .await
+            .context("storedBlockHash")
+    };
+    genesis::check_genesis_on_l1(
+        &mut storage,
+        l1_batch_hash,
+        network_config.l1_genesis_check_timeout(),
+        network_config.l1_genesis_check,
+    )
+    .await
+}
+
+fn genesis_params(
+    eth_sender: &ETHSenderConfig,
+    network_config: &NetworkConfig,
+    contracts_config: &ContractsConfig,
+) -> anyhow::Result<genesis::GenesisParams> {
+    if let Some(genesis_file) = &network_config.genesis_file {
+        tracing::info!("Loading genesis params from `{}`", genesis_file.display());
+        return genesis::GenesisParams::from_file(genesis_file);
+    }
+
     let operator_address = PackedEthSignature::address_from_private_key(
         &eth_sender
             .sender
@@ -121,31 +195,24 @@ pub async fn genesis_init(
             .context("Private key is required for genesis init")?,
     )
     .context("Failed to restore operator address from private key")?;
-
-    genesis::ensure_genesis_state(
-        &mut storage,
-        network_config.zksync_network_id,
-        &genesis::GenesisParams {
-            // We consider the operator to be the first validator for now.
-            first_validator: operator_address,
-            protocol_version: ProtocolVersionId::latest(),
-            base_system_contracts: BaseSystemContracts::load_from_disk(),
-            system_contracts: get_system_smart_contracts(),
-            first_verifier_address: contracts_config.verifier_addr,
-            first_l1_verifier_config: L1VerifierConfig {
-                params: VerifierParams {
-                    recursion_node_level_vk_hash: contracts_config.recursion_node_level_vk_hash,
-                    recursion_leaf_level_vk_hash: contracts_config.recursion_leaf_level_vk_hash,
-                    recursion_circuits_set_vks_hash: contracts_config
-                        .recursion_circuits_set_vks_hash,
-                },
-                recursion_scheduler_level_vk_hash: contracts_config
-                    .recursion_scheduler_level_vk_hash,
+    Ok(genesis::GenesisParams {
+        // We consider the operator to be the first validator for now.
+        first_validator: operator_address,
+        protocol_version: ProtocolVersionId::latest(),
+        base_system_contracts: BaseSystemContracts::load_from_disk(),
+        system_contracts: get_system_smart_contracts(),
+        first_verifier_address: contracts_config.verifier_addr,
+        first_l1_verifier_config: L1VerifierConfig {
+            params: VerifierParams {
+                recursion_node_level_vk_hash: contracts_config.recursion_node_level_vk_hash,
+                recursion_leaf_level_vk_hash: contracts_config.recursion_leaf_level_vk_hash,
+                recursion_circuits_set_vks_hash: contracts_config.recursion_circuits_set_vks_hash,
             },
+            recursion_scheduler_level_vk_hash: contracts_config.recursion_scheduler_level_vk_hash,
         },
-    )
-    .await?;
-    Ok(())
+        additional_storage_logs: vec![],
+        additional_factory_deps: vec![],
+    })
 }
 
 pub async fn is_genesis_needed() -> bool {
@@ -331,6 +398,9 @@ pub async fn initialize_components(
 
     // Prometheus exporter and circuit breaker checker should run for every component configuration.
     let prom_config = PrometheusConfig::from_env().context("PrometheusConfig::from_env()")?;
+    if let Some(buckets) = prom_config.merkle_tree_stage_latency_buckets.clone() {
+        metadata_calculator::set_stage_latency_buckets(buckets)?;
+    }
     let prom_config = if use_prometheus_push_gateway {
         PrometheusExporterConfig::push(prom_config.gateway_endpoint(), prom_config.push_interval())
     } else {
@@ -755,18 +825,38 @@ async fn run_tree(
     };
     tracing::info!("Initializing Merkle tree in {mode_str} mode");
 
+    let load_from_replica = config.merkle_tree.load_from_replica;
+    let replica_max_replication_lag = config.merkle_tree.replica_max_replication_lag();
+    let postgres_schema = config.merkle_tree.postgres_schema.clone();
     let config = MetadataCalculatorConfig::for_main_node(config, operation_manager, mode);
     let metadata_calculator = MetadataCalculator::new(&config).await;
     let tree_health_check = metadata_calculator.tree_health_check();
     let pool = ConnectionPool::singleton(DbVariant::Master)
+        .set_schema(postgres_schema.clone())
         .build()
         .await
         .context("failed to build connection pool")?;
+    let replica_pool = if load_from_replica {
+        let replica_pool = ConnectionPool::singleton(DbVariant::Replica)
+            .set_max_replication_lag(replica_max_replication_lag)
+            .set_schema(postgres_schema)
+            .build()
+            .await
+            .context("failed to build replica_pool")?;
+        Some(replica_pool)
+    } else {
+        None
+    };
     let prover_pool = ConnectionPool::singleton(DbVariant::Prover)
         .build()
         .await
         .context("failed to build prover_pool")?;
-    let future = tokio::spawn(metadata_calculator.run(pool, prover_pool, stop_receiver));
+    let future = tokio::spawn(metadata_calculator.run_with_replica(
+        pool,
+        replica_pool,
+        prover_pool,
+        stop_receiver,
+    ));
 
     tracing::info!("Initialized {mode_str} tree in {:?}", started_at.elapsed());
     metrics::gauge!(
@@ -930,8 +1020,20 @@ async fn add_house_keeper_to_task_futures(
         house_keeper_config.blob_cleaning_interval_ms,
     )
     .await;
+    // Unlike other jobs, the stale witness artifacts cleaner updates the main DB, so it needs a master connection.
+    let master_connection_pool = ConnectionPool::singleton(DbVariant::Master)
+        .build()
+        .await
+        .context("failed to build a master connection pool")?;
+    let stale_witness_artifacts_cleaner = StaleWitnessArtifactsCleaner::new(
+        store_factory,
+        master_connection_pool,
+        house_keeper_config.blob_cleaning_interval_ms,
+    )
+    .await;
 
     task_futures.push(tokio::spawn(gcs_blob_cleaner.run()));
+    task_futures.push(tokio::spawn(stale_witness_artifacts_cleaner.run()));
     task_futures.push(tokio::spawn(witness_generator_stats_reporter.run()));
     task_futures.push(tokio::spawn(gpu_prover_queue.run()));
     task_futures.push(tokio::spawn(l1_batch_metrics_reporter.run()));
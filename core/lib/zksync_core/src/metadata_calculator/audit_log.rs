@@ -0,0 +1,130 @@
+//! Append-only audit log of destructive operations on the Merkle tree (reverts, pruning
+//! and defragmentation).
+//!
+//! The log is stored as a sidecar file next to the tree RocksDB directory (`{db_path}.audit_log.jsonl`),
+//! so that it survives wiping or replacing the directory (e.g., when the tree is rebuilt after RocksDB
+//! corruption or is defragmented). Each entry is serialized as a JSON object on a separate line.
+//! Entries are synced to disk before the operation is considered complete, so the log provides
+//! a durable record for incident forensics.
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use zksync_types::{L1BatchNumber, H256};
+
+/// Destructive operation on the Merkle tree recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum AuditedOperation {
+    /// Tree versions after the specified L1 batch were reverted.
+    Revert {
+        last_l1_batch_to_keep: L1BatchNumber,
+    },
+    /// Tree versions before the specified L1 batch were pruned.
+    Prune {
+        first_l1_batch_to_keep: L1BatchNumber,
+        pruned_key_count: usize,
+    },
+    /// Tree RocksDB was defragmented, discarding all tree versions except for the latest one.
+    Defragment { copied_node_count: u64 },
+}
+
+/// Entry in the tree audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    /// Milliseconds since the Unix epoch when the operation was performed.
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub operation: AuditedOperation,
+    /// Tree root hash after the operation.
+    pub root_hash: H256,
+}
+
+impl AuditLogEntry {
+    pub(super) fn new(operation: AuditedOperation, root_hash: H256) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |timestamp| timestamp.as_millis() as u64);
+        Self {
+            timestamp_ms,
+            operation,
+            root_hash,
+        }
+    }
+}
+
+/// Audit log of a Merkle tree.
+#[derive(Debug)]
+pub(super) struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    const FILE_NAME: &'static str = "audit_log.jsonl";
+
+    /// Creates a log for the tree with the specified RocksDB directory. If the log was stored inside
+    /// the directory by a previous version of the software, it is moved next to the directory.
+    pub fn new(db_path: &Path) -> Self {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".");
+        path.push(Self::FILE_NAME);
+        let this = Self { path: path.into() };
+
+        let legacy_path = db_path.join(Self::FILE_NAME);
+        if legacy_path.exists() && !this.path.exists() {
+            if let Err(err) = fs::rename(&legacy_path, &this.path) {
+                tracing::warn!(
+                    "Failed moving audit log from `{}` to `{}`: {err}",
+                    legacy_path.display(),
+                    this.path.display()
+                );
+            }
+        }
+        this
+    }
+
+    /// Appends an entry to the log and syncs it to disk.
+    pub fn append(&self, entry: &AuditLogEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(entry).context("cannot serialize audit log entry")?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("cannot open audit log `{}`", self.path.display()))?;
+        file.write_all(&line)
+            .context("cannot write audit log entry")?;
+        file.sync_data().context("cannot sync audit log")?;
+        Ok(())
+    }
+
+    /// Reads all entries from the log in the order they were appended.
+    pub fn read(&self) -> anyhow::Result<Vec<AuditLogEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("cannot open audit log `{}`", self.path.display()))
+            }
+        };
+        let mut entries = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context("cannot read audit log")?;
+            if line.is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .with_context(|| format!("cannot parse audit log entry on line {}", i + 1))?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,310 @@
+//! Benchmark mode of the metadata calculator processing a synthetic workload.
+
+use anyhow::Context as _;
+use itertools::Itertools;
+
+use std::{
+    collections::BTreeMap,
+    ops,
+    time::{Duration, Instant},
+};
+
+use zksync_config::configs::database::DalConsistencyPolicy;
+use zksync_contracts::BaseSystemContracts;
+use zksync_dal::{ConnectionPool, StorageProcessor};
+use zksync_types::{
+    block::{miniblock_hash, BlockGasCount, L1BatchHeader, MiniblockHeader},
+    AccountTreeId, Address, L1BatchNumber, MiniblockNumber, StorageKey, StorageLog, H256,
+};
+use zksync_utils::u32_to_h256;
+
+use super::{helpers::L1BatchWithLogs, MetadataCalculator};
+
+/// Configuration of a [`MetadataCalculator::benchmark()`] run.
+#[derive(Debug, Clone, Copy)]
+pub struct BenchmarkConfig {
+    /// Number of synthetic L1 batches to process.
+    pub num_batches: usize,
+    /// Number of storage logs in each synthetic L1 batch.
+    pub logs_per_batch: usize,
+    /// Whether to persist synthetic L1 batches to Postgres and load them back as a part of the benchmark.
+    /// If not set, L1 batches are fed to the tree directly from memory, and Postgres is not touched.
+    pub include_load: bool,
+}
+
+/// Stage of L1 batch processing measured by the benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BenchmarkStage {
+    /// Loading an L1 batch from Postgres. Only measured if [`BenchmarkConfig::include_load`] is set.
+    Load,
+    /// Applying storage logs to the tree.
+    Compute,
+    /// Flushing tree changes to RocksDB.
+    SaveRocksDB,
+}
+
+/// Results of a [`MetadataCalculator::benchmark()`] run.
+#[derive(Debug, Clone)]
+pub struct BenchmarkReport {
+    /// Number of processed L1 batches.
+    pub l1_batch_count: usize,
+    /// Total number of processed storage logs.
+    pub log_count: usize,
+    /// Total processing time. Doesn't include generating synthetic L1 batches and persisting them to Postgres.
+    pub elapsed: Duration,
+    /// Processed L1 batches per second.
+    pub batches_per_sec: f64,
+    /// Processed storage logs per second.
+    pub logs_per_sec: f64,
+    /// 95th percentile of per-L1-batch latencies for each measured stage.
+    pub p95_latencies: BTreeMap<BenchmarkStage, Duration>,
+}
+
+impl MetadataCalculator {
+    /// Processes a synthetic workload of `config.num_batches` L1 batches through the tree
+    /// and reports the resulting throughput and per-stage latencies. Synthetic L1 batches are generated
+    /// deterministically, so that the benchmark results are comparable across hardware and configs.
+    ///
+    /// The tree must be empty. Since the benchmark modifies the tree (and, with [`BenchmarkConfig::include_load`],
+    /// Postgres), it should only be run with a scratch RocksDB directory and database. Witness inputs produced
+    /// by the tree for synthetic L1 batches are discarded rather than persisted to the object store.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config is invalid, the tree is not empty, or synthetic L1 batches
+    /// cannot be loaded from Postgres.
+    pub async fn benchmark(
+        self,
+        pool: &ConnectionPool,
+        config: BenchmarkConfig,
+    ) -> anyhow::Result<BenchmarkReport> {
+        anyhow::ensure!(
+            config.num_batches > 0 && config.logs_per_batch > 0,
+            "benchmark workload must be non-empty"
+        );
+        let mut updater = self.updater;
+        anyhow::ensure!(
+            updater.tree_mut().is_empty(),
+            "benchmark must be run on an empty Merkle tree"
+        );
+
+        let l1_batches = gen_synthetic_l1_batches(config.num_batches, config.logs_per_batch);
+        let mut storage = pool.access_storage().await?;
+        let first_l1_batch_number = if config.include_load {
+            let sealed_l1_batch_number = storage.blocks_dal().get_sealed_l1_batch_number().await?;
+            extend_db_state(&mut storage, l1_batches.clone()).await;
+            sealed_l1_batch_number + 1
+        } else {
+            L1BatchNumber(1)
+        };
+        tracing::info!(
+            "Starting tree benchmark with {} L1 batches x {} storage logs",
+            config.num_batches,
+            config.logs_per_batch
+        );
+
+        let mut latencies = BTreeMap::<_, Vec<_>>::new();
+        let mut record = |stage, started_at: Instant| {
+            latencies
+                .entry(stage)
+                .or_default()
+                .push(started_at.elapsed());
+        };
+        let mode = updater.mode();
+        let policy = DalConsistencyPolicy::FailFast;
+        let start = Instant::now();
+        for (l1_batch_number, storage_logs) in (first_l1_batch_number.0..).zip(l1_batches) {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            let storage_logs = if config.include_load {
+                let started_at = Instant::now();
+                let l1_batch = L1BatchWithLogs::new(&mut storage, l1_batch_number, mode, policy)
+                    .await?
+                    .with_context(|| format!("L1 batch #{l1_batch_number} is missing"))?;
+                record(BenchmarkStage::Load, started_at);
+                l1_batch.storage_logs
+            } else {
+                storage_logs
+            };
+
+            let started_at = Instant::now();
+            updater.tree_mut().process_l1_batch(storage_logs).await;
+            record(BenchmarkStage::Compute, started_at);
+
+            let started_at = Instant::now();
+            updater.tree_mut().save().await;
+            record(BenchmarkStage::SaveRocksDB, started_at);
+        }
+        let elapsed = start.elapsed();
+
+        let log_count = config.num_batches * config.logs_per_batch;
+        let report = BenchmarkReport {
+            l1_batch_count: config.num_batches,
+            log_count,
+            elapsed,
+            batches_per_sec: config.num_batches as f64 / elapsed.as_secs_f64(),
+            logs_per_sec: log_count as f64 / elapsed.as_secs_f64(),
+            p95_latencies: latencies
+                .into_iter()
+                .map(|(stage, latencies)| (stage, percentile(latencies, 0.95)))
+                .collect(),
+        };
+        tracing::info!("Finished tree benchmark: {report:?}");
+        Ok(report)
+    }
+}
+
+/// Generates `num_batches` L1 batches with `logs_per_batch` storage logs in each.
+fn gen_synthetic_l1_batches(num_batches: usize, logs_per_batch: usize) -> Vec<Vec<StorageLog>> {
+    let log_count = num_batches * logs_per_batch;
+    // `gen_storage_logs()` generates logs for 5 accounts at a time, so we round the log count up.
+    let index_end = u32::try_from((log_count + 4) / 5 * 5).expect("too many storage logs");
+    let mut logs = gen_storage_logs(0..index_end, 1).pop().unwrap();
+    logs.truncate(log_count);
+    logs.chunks(logs_per_batch).map(<[_]>::to_vec).collect()
+}
+
+fn percentile(mut latencies: Vec<Duration>, quantile: f64) -> Duration {
+    latencies.sort_unstable();
+    let len = latencies.len();
+    let idx = ((len as f64 * quantile).ceil() as usize).clamp(1, len) - 1;
+    latencies[idx]
+}
+
+/// Appends L1 batches with the specified storage logs to Postgres. Each L1 batch consists of a single miniblock.
+pub(super) async fn extend_db_state(
+    storage: &mut StorageProcessor<'_>,
+    new_logs: impl IntoIterator<Item = Vec<StorageLog>>,
+) {
+    let next_l1_batch = storage
+        .blocks_dal()
+        .get_sealed_l1_batch_number()
+        .await
+        .unwrap()
+        .0
+        + 1;
+
+    let base_system_contracts = BaseSystemContracts::load_from_disk();
+    for (idx, batch_logs) in (next_l1_batch..).zip(new_logs) {
+        let batch_number = L1BatchNumber(idx);
+        let mut header = L1BatchHeader::new(
+            batch_number,
+            0,
+            Address::default(),
+            base_system_contracts.hashes(),
+            Default::default(),
+        );
+        header.is_finished = true;
+
+        // Assumes that L1 batch consists of only one miniblock.
+        let miniblock_number = MiniblockNumber(idx);
+        let miniblock_header = MiniblockHeader {
+            number: miniblock_number,
+            timestamp: header.timestamp,
+            hash: miniblock_hash(
+                miniblock_number,
+                header.timestamp,
+                H256::zero(),
+                H256::zero(),
+            ),
+            l1_tx_count: header.l1_tx_count,
+            l2_tx_count: header.l2_tx_count,
+            base_fee_per_gas: header.base_fee_per_gas,
+            l1_gas_price: 0,
+            l2_fair_gas_price: 0,
+            base_system_contracts_hashes: base_system_contracts.hashes(),
+            protocol_version: Some(Default::default()),
+            virtual_blocks: 0,
+        };
+
+        storage
+            .blocks_dal()
+            .insert_l1_batch(&header, &[], BlockGasCount::default())
+            .await
+            .unwrap();
+        storage
+            .blocks_dal()
+            .insert_miniblock(&miniblock_header)
+            .await
+            .unwrap();
+        storage
+            .storage_logs_dal()
+            .insert_storage_logs(miniblock_number, &[(H256::zero(), batch_logs)])
+            .await;
+        storage
+            .blocks_dal()
+            .mark_miniblocks_as_executed_in_l1_batch(batch_number)
+            .await
+            .unwrap();
+        insert_initial_writes_for_batch(storage, batch_number).await;
+    }
+}
+
+async fn insert_initial_writes_for_batch(
+    connection: &mut StorageProcessor<'_>,
+    l1_batch_number: L1BatchNumber,
+) {
+    let written_non_zero_slots: Vec<_> = connection
+        .storage_logs_dal()
+        .get_touched_slots_for_l1_batch(l1_batch_number)
+        .await
+        .into_iter()
+        .filter_map(|(key, value)| (!value.is_zero()).then_some(key))
+        .collect();
+    let hashed_keys: Vec<_> = written_non_zero_slots
+        .iter()
+        .map(|key| key.hashed_key())
+        .collect();
+    let pre_written_slots = connection
+        .storage_logs_dedup_dal()
+        .filter_written_slots(&hashed_keys)
+        .await;
+
+    let keys_to_insert: Vec<_> = written_non_zero_slots
+        .into_iter()
+        .sorted()
+        .filter(|key| !pre_written_slots.contains(&key.hashed_key()))
+        .collect();
+    connection
+        .storage_logs_dedup_dal()
+        .insert_initial_writes(l1_batch_number, &keys_to_insert)
+        .await;
+}
+
+/// Generates storage logs with keys for 5 accounts and values corresponding to `indices`, split into
+/// `num_batches` chunks. Keys of the generated logs are sorted.
+pub(super) fn gen_storage_logs(
+    indices: ops::Range<u32>,
+    num_batches: usize,
+) -> Vec<Vec<StorageLog>> {
+    // Addresses and keys of storage logs must be sorted for the `multi_block_workflow` test.
+    let mut accounts = [
+        "4b3af74f66ab1f0da3f2e4ec7a3cb99baf1af7b2",
+        "ef4bb7b21c5fe7432a7d63876cc59ecc23b46636",
+        "89b8988a018f5348f52eeac77155a793adf03ecc",
+        "782806db027c08d36b2bed376b4271d1237626b3",
+        "b2b57b76717ee02ae1327cc3cf1f40e76f692311",
+    ]
+    .map(|s| AccountTreeId::new(s.parse::<Address>().unwrap()));
+    accounts.sort_unstable();
+
+    let account_keys = (indices.start / 5)..(indices.end / 5);
+    let proof_keys = accounts.iter().flat_map(|&account| {
+        account_keys
+            .clone()
+            .map(move |i| StorageKey::new(account, u32_to_h256(i)))
+    });
+    let proof_values = indices.map(u32_to_h256);
+
+    let logs: Vec<_> = proof_keys
+        .zip(proof_values)
+        .map(|(proof_key, proof_value)| StorageLog::new_write_log(proof_key, proof_value))
+        .collect();
+    for window in logs.windows(2) {
+        let [prev, next] = window else { unreachable!() };
+        assert!(prev.key < next.key);
+    }
+
+    logs.chunks(logs.len() / num_batches)
+        .map(<[_]>::to_vec)
+        .collect()
+}
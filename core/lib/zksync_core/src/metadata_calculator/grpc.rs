@@ -0,0 +1,215 @@
+//! Optional gRPC service providing read-only access to the Merkle tree maintained by the calculator.
+//! The service is intended for consumers that cannot use the Rust API directly.
+
+use anyhow::Context as _;
+use tokio::{net::TcpListener, sync::watch};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use std::net::SocketAddr;
+
+use zksync_merkle_tree::{domain::ZkSyncTreeReader, TreeEntry, TreeEntryWithProof};
+use zksync_types::{L1BatchNumber, H256, U256};
+
+use self::proto::{
+    tree_server::{Tree, TreeServer},
+    GetProofAtVersionRequest, GetProofRequest, GetRootRequest, GetRootResponse, GetValueRequest,
+    GetValueResponse, ProofResponse,
+};
+use super::{CommittedTreeState, MetadataCalculator};
+
+#[allow(clippy::all)] // Generated code
+pub(super) mod proto {
+    tonic::include_proto!("zksync.tree");
+}
+
+/// gRPC server backed by the in-process Merkle tree of a [`MetadataCalculator`]. The server doesn't
+/// reopen the tree RocksDB; instead, it reads the tree via a [`ZkSyncTreeReader`] and only serves
+/// tree versions saved by the calculator.
+#[derive(Debug, Clone)]
+pub struct TreeGrpcServer {
+    reader: ZkSyncTreeReader,
+    committed_state: watch::Receiver<Option<CommittedTreeState>>,
+}
+
+impl TreeGrpcServer {
+    /// Creates a server for the tree of the provided calculator. The server should be created
+    /// before the calculator is run.
+    pub fn new(calculator: &MetadataCalculator) -> Self {
+        Self {
+            reader: calculator.tree_reader(),
+            committed_state: calculator.subscribe_committed_state(),
+        }
+    }
+
+    /// Runs the server on the specified address until a stop signal is received.
+    pub async fn run(
+        self,
+        bind_address: SocketAddr,
+        stop_receiver: watch::Receiver<bool>,
+    ) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(bind_address)
+            .await
+            .with_context(|| format!("failed binding Merkle tree gRPC server to {bind_address}"))?;
+        self.run_with_listener(listener, stop_receiver).await
+    }
+
+    pub(super) async fn run_with_listener(
+        self,
+        listener: TcpListener,
+        mut stop_receiver: watch::Receiver<bool>,
+    ) -> anyhow::Result<()> {
+        let local_addr = listener
+            .local_addr()
+            .context("failed getting local address of Merkle tree gRPC server")?;
+        tracing::info!("Starting Merkle tree gRPC server on {local_addr}");
+
+        Server::builder()
+            .add_service(TreeServer::new(self))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+                if stop_receiver.changed().await.is_err() {
+                    tracing::warn!(
+                        "Stop signal sender for Merkle tree gRPC server was dropped without sending a signal"
+                    );
+                }
+                tracing::info!("Stop signal received, Merkle tree gRPC server is shutting down");
+            })
+            .await
+            .context("Merkle tree gRPC server failed")?;
+        tracing::info!("Merkle tree gRPC server shut down");
+        Ok(())
+    }
+
+    fn committed_state(&self) -> Result<CommittedTreeState, Status> {
+        let state = *self.committed_state.borrow();
+        state.ok_or_else(|| Status::unavailable("Merkle tree is not initialized yet"))
+    }
+
+    async fn entry(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        hashed_key: &[u8],
+    ) -> Result<TreeEntry, Status> {
+        let key = parse_hashed_key(hashed_key)?;
+        let reader = self.reader.clone();
+        let entries =
+            tokio::task::spawn_blocking(move || reader.entries(l1_batch_number, &[key])).await;
+        let entries = entries
+            .map_err(|err| Status::internal(format!("failed reading Merkle tree: {err}")))?
+            .map_err(|err| Status::not_found(err.to_string()))?;
+        Ok(entries[0])
+    }
+
+    async fn proof(
+        &self,
+        state: CommittedTreeState,
+        l1_batch_number: L1BatchNumber,
+        hashed_key: &[u8],
+    ) -> Result<ProofResponse, Status> {
+        if l1_batch_number > state.l1_batch_number {
+            return Err(Status::failed_precondition(format!(
+                "L1 batch #{l1_batch_number} is not processed by Merkle tree yet; \
+                 last processed L1 batch is #{}",
+                state.l1_batch_number
+            )));
+        }
+        let root_hash = if l1_batch_number == state.l1_batch_number {
+            state.root_hash
+        } else {
+            self.reader.root_hash_at(l1_batch_number).ok_or_else(|| {
+                Status::not_found(format!(
+                    "L1 batch #{l1_batch_number} is missing in Merkle tree"
+                ))
+            })?
+        };
+
+        let key = parse_hashed_key(hashed_key)?;
+        let reader = self.reader.clone();
+        let entries = tokio::task::spawn_blocking(move || {
+            reader.entries_with_proofs(l1_batch_number, &[key])
+        })
+        .await;
+        let mut entries = entries
+            .map_err(|err| Status::internal(format!("failed reading Merkle tree: {err}")))?
+            .map_err(|err| Status::not_found(err.to_string()))?;
+        let TreeEntryWithProof { base, merkle_path } = entries.pop().unwrap();
+
+        Ok(ProofResponse {
+            l1_batch_number: l1_batch_number.0,
+            root_hash: root_hash.as_bytes().to_vec(),
+            value_hash: base.value_hash.as_bytes().to_vec(),
+            leaf_index: base.leaf_index,
+            merkle_path: merkle_path
+                .iter()
+                .map(|hash| hash.as_bytes().to_vec())
+                .collect(),
+        })
+    }
+}
+
+/// Parses a hashed storage key encoded in the same way as `StorageKey::hashed_key()`.
+fn parse_hashed_key(bytes: &[u8]) -> Result<U256, Status> {
+    if bytes.len() != H256::len_bytes() {
+        return Err(Status::invalid_argument(format!(
+            "hashed key must have {} bytes, got {}",
+            H256::len_bytes(),
+            bytes.len()
+        )));
+    }
+    Ok(U256::from_little_endian(bytes))
+}
+
+#[tonic::async_trait]
+impl Tree for TreeGrpcServer {
+    async fn get_root(
+        &self,
+        _request: Request<GetRootRequest>,
+    ) -> Result<Response<GetRootResponse>, Status> {
+        let state = self.committed_state()?;
+        Ok(Response::new(GetRootResponse {
+            l1_batch_number: state.l1_batch_number.0,
+            root_hash: state.root_hash.as_bytes().to_vec(),
+        }))
+    }
+
+    async fn get_value(
+        &self,
+        request: Request<GetValueRequest>,
+    ) -> Result<Response<GetValueResponse>, Status> {
+        let state = self.committed_state()?;
+        let request = request.into_inner();
+        let entry = self
+            .entry(state.l1_batch_number, &request.hashed_key)
+            .await?;
+        Ok(Response::new(GetValueResponse {
+            l1_batch_number: state.l1_batch_number.0,
+            value_hash: entry.value_hash.as_bytes().to_vec(),
+            leaf_index: entry.leaf_index,
+        }))
+    }
+
+    async fn get_proof(
+        &self,
+        request: Request<GetProofRequest>,
+    ) -> Result<Response<ProofResponse>, Status> {
+        let state = self.committed_state()?;
+        let request = request.into_inner();
+        let response = self
+            .proof(state, state.l1_batch_number, &request.hashed_key)
+            .await?;
+        Ok(Response::new(response))
+    }
+
+    async fn get_proof_at_version(
+        &self,
+        request: Request<GetProofAtVersionRequest>,
+    ) -> Result<Response<ProofResponse>, Status> {
+        let state = self.committed_state()?;
+        let request = request.into_inner();
+        let l1_batch_number = L1BatchNumber(request.l1_batch_number);
+        let response = self
+            .proof(state, l1_batch_number, &request.hashed_key)
+            .await?;
+        Ok(Response::new(response))
+    }
+}
@@ -1,38 +1,183 @@
 //! Various helpers for the metadata calculator.
 
+use anyhow::Context as _;
+use async_trait::async_trait;
 use serde::Serialize;
 #[cfg(test)]
 use tokio::sync::mpsc;
+use tokio::sync::watch;
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs,
     future::Future,
+    io,
+    marker::PhantomData,
     mem,
     path::{Path, PathBuf},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use zksync_config::configs::database::MerkleTreeMode;
-use zksync_dal::StorageProcessor;
-use zksync_health_check::{Health, HealthStatus};
+use zksync_config::configs::database::{
+    DalConsistencyPolicy, MerkleTreeMode, TreeHealthDetailsFormat,
+};
+use zksync_dal::{ConnectionPool, StorageProcessor};
+use zksync_health_check::{Health, HealthStatus, HealthUpdater};
 use zksync_merkle_tree::{
-    domain::{TreeMetadata, ZkSyncTree},
-    MerkleTreeColumnFamily,
+    domain::{TreeMetadata, ZkSyncTree, ZkSyncTreeReader},
+    Key, MerkleTreeColumnFamily, NoVersionError, TreeEntryWithProof,
+};
+use zksync_storage::{rocksdb::ErrorKind, RocksDB, RocksDBOptions};
+use zksync_types::{
+    block::L1BatchHeader, L1BatchNumber, StorageKey, StorageLog, StorageLogKind, H256, U256,
+};
+
+use super::{
+    audit_log::{AuditLog, AuditLogEntry, AuditedOperation},
+    leaf_value::LeafValue,
+    metrics::{
+        LoadChangesStage, LoadedL1BatchStats, ReportStage, TreeGauges, TreeUpdateStage, METRICS,
+    },
+    save_latency_slo::SaveLatencyTracker,
+    upload_policy::UploadHealthDetails,
+    witness::{WitnessBuilder, WitnessInput},
+    ProtectiveReadsThresholds, TreeHealthThresholds,
 };
-use zksync_storage::RocksDB;
-use zksync_types::{block::L1BatchHeader, L1BatchNumber, StorageLog, H256};
 
-use super::metrics::{LoadChangesStage, ReportStage, TreeUpdateStage};
+/// Period after a tree revert during which the revert is reported in the tree health details.
+const REVERT_HEALTH_GRACE_PERIOD: Duration = Duration::from_secs(600);
 
 #[derive(Debug, Serialize)]
 pub(super) struct TreeHealthCheckDetails {
     pub mode: MerkleTreeMode,
     pub next_l1_batch_to_seal: L1BatchNumber,
+    /// Whether the tree generates witness inputs for provers.
+    pub generate_witness_inputs: bool,
+    /// Number of sealed L1 batches in Postgres not yet processed by the tree.
+    pub l1_batch_lag: u32,
+    /// Health of object store requests made when persisting witness inputs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_store: Option<UploadHealthDetails>,
+    /// Fraction of recent tree saves that met the save latency SLO.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slo_compliance: Option<f64>,
+    #[serde(skip)]
+    is_slo_violated: bool,
+    /// Genesis root hash persisted in Postgres, identifying the chain the tree belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genesis_root_hash: Option<H256>,
+    /// Last L1 batch kept by a recent tree revert, if the tree was reverted recently.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recently_reverted_to: Option<L1BatchNumber>,
+}
+
+impl TreeHealthCheckDetails {
+    pub fn new(
+        mode: MerkleTreeMode,
+        generate_witness_inputs: bool,
+        next_l1_batch_to_seal: L1BatchNumber,
+        sealed_l1_batch: L1BatchNumber,
+        object_store: Option<UploadHealthDetails>,
+    ) -> Self {
+        Self {
+            mode,
+            next_l1_batch_to_seal,
+            generate_witness_inputs,
+            l1_batch_lag: (sealed_l1_batch.0 + 1).saturating_sub(next_l1_batch_to_seal.0),
+            object_store,
+            slo_compliance: None,
+            is_slo_violated: false,
+            genesis_root_hash: None,
+            recently_reverted_to: None,
+        }
+    }
+
+    /// Adds compliance with the save latency SLO to these details.
+    pub fn with_save_latency_slo(mut self, tracker: Option<&SaveLatencyTracker>) -> Self {
+        if let Some(tracker) = tracker {
+            self.slo_compliance = tracker.compliance();
+            self.is_slo_violated = tracker.is_violated();
+        }
+        self
+    }
+
+    /// Adds the genesis root hash to these details.
+    pub fn with_genesis_root_hash(mut self, genesis_root_hash: Option<H256>) -> Self {
+        self.genesis_root_hash = genesis_root_hash;
+        self
+    }
+
+    /// Adds the most recent tree revert (the last kept L1 batch and the revert time) to these details
+    /// if it happened within the grace period.
+    pub fn with_recent_revert(mut self, revert: Option<(L1BatchNumber, Instant)>) -> Self {
+        self.recently_reverted_to = revert.and_then(|(reverted_to, reverted_at)| {
+            (reverted_at.elapsed() < REVERT_HEALTH_GRACE_PERIOD).then_some(reverted_to)
+        });
+        self
+    }
+
+    /// Converts these details into health with details serialized in the specified `format`. Failing object store
+    /// requests or a violated save latency SLO make an otherwise ready tree [`HealthStatus::Affected`].
+    pub fn into_health(
+        self,
+        thresholds: TreeHealthThresholds,
+        format: TreeHealthDetailsFormat,
+    ) -> Health {
+        let mut status = thresholds.status(self.l1_batch_lag);
+        let is_object_store_failing = self
+            .object_store
+            .as_ref()
+            .map_or(false, |health| health.is_failing);
+        if status == HealthStatus::Ready && (is_object_store_failing || self.is_slo_violated) {
+            status = HealthStatus::Affected;
+        }
+        let health = Health::from(status);
+        match format {
+            TreeHealthDetailsFormat::Json => health.with_details(self),
+            TreeHealthDetailsFormat::Flat => health.with_details(self.to_flat_map()),
+        }
+    }
+
+    /// Serializes these details as a flat map (see [`TreeHealthDetailsFormat::Flat`]).
+    fn to_flat_map(&self) -> serde_json::Map<String, serde_json::Value> {
+        let value = serde_json::to_value(self).expect("failed serializing tree health details");
+        let mut map = serde_json::Map::new();
+        flatten_json_value(String::new(), value, &mut map);
+        map
+    }
 }
 
-impl From<TreeHealthCheckDetails> for Health {
-    fn from(details: TreeHealthCheckDetails) -> Self {
-        Self::from(HealthStatus::Ready).with_details(details)
+fn flatten_json_value(
+    key: String,
+    value: serde_json::Value,
+    output: &mut serde_json::Map<String, serde_json::Value>,
+) {
+    let child_key = |child: &str| {
+        if key.is_empty() {
+            child.to_owned()
+        } else {
+            format!("{key}_{child}")
+        }
+    };
+
+    match value {
+        serde_json::Value::Null => { /* omitted */ }
+        serde_json::Value::Bool(flag) => {
+            output.insert(key, u8::from(flag).into());
+        }
+        serde_json::Value::Object(fields) => {
+            for (child, value) in fields {
+                flatten_json_value(child_key(&child), value, output);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (i, value) in items.into_iter().enumerate() {
+                flatten_json_value(child_key(&i.to_string()), value, output);
+            }
+        }
+        scalar => {
+            output.insert(key, scalar);
+        }
     }
 }
 
@@ -44,56 +189,213 @@ impl From<TreeHealthCheckDetails> for Health {
 /// at least not explicitly), all `MetadataCalculator` data including `ZkSyncTree` is discarded.
 /// In the unlikely case you get a "`ZkSyncTree` is in inconsistent state" panic,
 /// cancellation is most probably the reason.
-#[derive(Debug, Default)]
-pub(super) struct AsyncTree(Option<ZkSyncTree>);
+///
+/// The tree is parameterized by the type of values stored in its leaves; values are encoded and decoded
+/// with the [`LeafValue`] codec. The calculator itself uses `H256` values.
+#[derive(Debug)]
+pub(super) struct AsyncTree<V = H256> {
+    inner: Option<ZkSyncTree>,
+    mode: MerkleTreeMode,
+    /// Was the tree RocksDB wiped on initialization because it was corrupted?
+    was_wiped: bool,
+    root_hash_cache: RootHashCache,
+    /// Log of reverts, pruning and defragmentation; `None` only for a tree taken out for a blocking operation.
+    audit_log: Option<AuditLog>,
+    /// Parameters used to reopen the tree, e.g. after defragmentation.
+    reopen_params: TreeReopenParams,
+    _values: PhantomData<fn() -> V>,
+}
+
+// Cannot be derived since it would require `V: Default`.
+impl<V> Default for AsyncTree<V> {
+    fn default() -> Self {
+        Self {
+            inner: None,
+            mode: MerkleTreeMode::default(),
+            was_wiped: false,
+            root_hash_cache: RootHashCache::default(),
+            audit_log: None,
+            reopen_params: TreeReopenParams::default(),
+            _values: PhantomData,
+        }
+    }
+}
+
+/// Latest tree state saved to RocksDB, as published by the tree update loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommittedTreeState {
+    /// Number of the last L1 batch processed by the tree.
+    pub l1_batch_number: L1BatchNumber,
+    /// Root hash of the tree after processing this L1 batch.
+    pub root_hash: H256,
+}
+
+impl CommittedTreeState {
+    /// Returns the state of the provided tree, or `None` if the tree is empty. Must be called
+    /// after the tree is saved.
+    pub(super) fn of(tree: &AsyncTree) -> Option<Self> {
+        let next_l1_batch_number = tree.next_l1_batch_number();
+        (next_l1_batch_number.0 > 0).then(|| Self {
+            l1_batch_number: next_l1_batch_number - 1,
+            root_hash: tree.root_hash(),
+        })
+    }
+}
+
+/// Parameters necessary to reopen [`AsyncTree`] after its RocksDB directory is replaced.
+#[derive(Debug, Clone, Default)]
+struct TreeReopenParams {
+    db_path: PathBuf,
+    db_options: RocksDBOptions,
+    multi_get_chunk_size: usize,
+    generate_witness: bool,
+}
 
 impl AsyncTree {
+    pub async fn new(
+        db_path: PathBuf,
+        mode: MerkleTreeMode,
+        multi_get_chunk_size: usize,
+        block_cache_capacity: usize,
+        rocksdb_rate_limit_bytes_per_sec: Option<usize>,
+        auto_rebuild_on_corruption: bool,
+        root_hash_cache_size: usize,
+    ) -> Self {
+        Self::with_value_codec(
+            db_path,
+            mode,
+            multi_get_chunk_size,
+            block_cache_capacity,
+            rocksdb_rate_limit_bytes_per_sec,
+            auto_rebuild_on_corruption,
+            root_hash_cache_size,
+        )
+        .await
+    }
+}
+
+impl<V: LeafValue> AsyncTree<V> {
     const INCONSISTENT_MSG: &'static str =
         "`ZkSyncTree` is in inconsistent state, which could occur after one of its blocking futures was cancelled";
 
-    pub async fn new(
+    /// Creates a tree storing values of type `V` in its leaves.
+    pub async fn with_value_codec(
         db_path: PathBuf,
         mode: MerkleTreeMode,
         multi_get_chunk_size: usize,
         block_cache_capacity: usize,
+        rocksdb_rate_limit_bytes_per_sec: Option<usize>,
+        auto_rebuild_on_corruption: bool,
+        root_hash_cache_size: usize,
     ) -> Self {
         tracing::info!(
             "Initializing Merkle tree at `{db_path}` with {multi_get_chunk_size} multi-get chunk size, \
-             {block_cache_capacity}B block cache",
+             {block_cache_capacity}B block cache, {root_hash_cache_size} cached root hashes, \
+             background I/O rate limit: {rocksdb_rate_limit_bytes_per_sec:?} B/s",
             db_path = db_path.display()
         );
 
-        let mut tree = tokio::task::spawn_blocking(move || {
-            let db = Self::create_db(&db_path, block_cache_capacity);
-            match mode {
-                MerkleTreeMode::Full => ZkSyncTree::new(db),
-                MerkleTreeMode::Lightweight => ZkSyncTree::new_lightweight(db),
-            }
+        let audit_log = AuditLog::new(&db_path);
+        let reopen_params = TreeReopenParams {
+            db_path,
+            db_options: RocksDBOptions {
+                block_cache_capacity: Some(block_cache_capacity),
+                rate_limit_bytes_per_sec: rocksdb_rate_limit_bytes_per_sec,
+                ..RocksDBOptions::default()
+            },
+            multi_get_chunk_size,
+            generate_witness: true,
+        };
+        let params = reopen_params.clone();
+        let (mut tree, was_wiped) = tokio::task::spawn_blocking(move || {
+            let (db, was_wiped) = Self::create_db(
+                &params.db_path,
+                params.db_options,
+                auto_rebuild_on_corruption,
+            );
+            (Self::create_tree(db, mode), was_wiped)
         })
         .await
         .unwrap();
 
         tree.set_multi_get_chunk_size(multi_get_chunk_size);
-        Self(Some(tree))
+        Self {
+            inner: Some(tree),
+            mode,
+            was_wiped,
+            root_hash_cache: RootHashCache::new(root_hash_cache_size),
+            audit_log: Some(audit_log),
+            reopen_params,
+            _values: PhantomData,
+        }
+    }
+
+    fn create_tree(db: RocksDB<MerkleTreeColumnFamily>, mode: MerkleTreeMode) -> ZkSyncTree {
+        match mode {
+            MerkleTreeMode::Full => ZkSyncTree::new(db),
+            MerkleTreeMode::Lightweight => ZkSyncTree::new_lightweight(db),
+        }
     }
 
-    fn create_db(path: &Path, block_cache_capacity: usize) -> RocksDB<MerkleTreeColumnFamily> {
-        let db = RocksDB::with_cache(path, true, Some(block_cache_capacity));
-        if cfg!(test) {
+    /// Opens the tree RocksDB. If `auto_rebuild_on_corruption` is set and RocksDB is corrupted,
+    /// the DB directory is wiped, so that the tree is rebuilt from scratch; in this case, the second
+    /// returned value is `true`.
+    fn create_db(
+        path: &Path,
+        db_options: RocksDBOptions,
+        auto_rebuild_on_corruption: bool,
+    ) -> (RocksDB<MerkleTreeColumnFamily>, bool) {
+        let (db, was_wiped) = match RocksDB::try_with_options(path, true, db_options) {
+            Ok(db) => (db, false),
+            Err(err) if auto_rebuild_on_corruption && err.kind() == ErrorKind::Corruption => {
+                tracing::error!(
+                    "Merkle tree RocksDB at `{}` is corrupted: {err}. Since automatic rebuilding is enabled, \
+                     the DB will be wiped and the tree will be rebuilt from Postgres from scratch. \
+                     This may take a long time",
+                    path.display()
+                );
+                fs::remove_dir_all(path).unwrap_or_else(|err| {
+                    panic!(
+                        "Failed removing corrupted Merkle tree RocksDB at `{}`: {err}",
+                        path.display()
+                    );
+                });
+                let db = RocksDB::with_options(path, true, db_options);
+                (db, true)
+            }
+            Err(err) => panic!("failed to init rocksdb: {err}"),
+        };
+
+        let db = if cfg!(test) {
             // We need sync writes for the unit tests to execute reliably. With the default config,
             // some writes to RocksDB may occur, but not be visible to the test code.
             db.with_sync_writes()
         } else {
             db
-        }
+        };
+        (db, was_wiped)
     }
 
     fn as_ref(&self) -> &ZkSyncTree {
-        self.0.as_ref().expect(Self::INCONSISTENT_MSG)
+        self.inner.as_ref().expect(Self::INCONSISTENT_MSG)
     }
 
     fn as_mut(&mut self) -> &mut ZkSyncTree {
-        self.0.as_mut().expect(Self::INCONSISTENT_MSG)
+        self.inner.as_mut().expect(Self::INCONSISTENT_MSG)
+    }
+
+    /// Enables or disables generating witness inputs in the full tree mode.
+    pub fn set_witness_generation(&mut self, enabled: bool) {
+        self.as_mut().set_witness_generation(enabled);
+        self.reopen_params.generate_witness = enabled;
+    }
+
+    pub fn mode(&self) -> MerkleTreeMode {
+        self.mode
+    }
+
+    pub fn was_wiped(&self) -> bool {
+        self.was_wiped
     }
 
     pub fn is_empty(&self) -> bool {
@@ -108,6 +410,102 @@ impl AsyncTree {
         self.as_ref().root_hash()
     }
 
+    /// Returns a read-only handle to the tree that only observes changes saved to RocksDB.
+    /// The tree cannot be defragmented while such handles are alive; see [`Self::defragment()`].
+    pub fn reader(&self) -> ZkSyncTreeReader {
+        self.as_ref().reader()
+    }
+
+    /// Returns the root hash of the tree after processing the specified L1 batch. Recently saved
+    /// and requested root hashes are served from an in-memory cache.
+    pub fn root_hash_at(&mut self, l1_batch_number: L1BatchNumber) -> Option<H256> {
+        if let Some(root_hash) = self.root_hash_cache.get(l1_batch_number) {
+            return Some(root_hash);
+        }
+        let root_hash = self.as_ref().root_hash_at(l1_batch_number)?;
+        self.root_hash_cache.insert(l1_batch_number, root_hash);
+        Some(root_hash)
+    }
+
+    #[cfg(test)]
+    pub fn root_hash_cache_hits(&self) -> u64 {
+        self.root_hash_cache.hits
+    }
+
+    pub async fn entries_with_proofs(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+        keys: Vec<U256>,
+    ) -> Result<Vec<TreeEntryWithProof>, NoVersionError> {
+        let tree = mem::take(self);
+        let (tree, entries) = tokio::task::spawn_blocking(move || {
+            let entries = tree.as_ref().entries_with_proofs(l1_batch_number, &keys);
+            (tree, entries)
+        })
+        .await
+        .unwrap();
+
+        *self = tree;
+        entries
+    }
+
+    /// Reads values for the specified keys from the tree version after the specified L1 batch, decoding
+    /// them with the [`LeafValue`] codec. Missing entries and entries that cannot be decoded are returned
+    /// as `None`.
+    pub async fn values(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+        keys: Vec<U256>,
+    ) -> Result<Vec<Option<V>>, NoVersionError> {
+        let tree = mem::take(self);
+        let (tree, entries) = tokio::task::spawn_blocking(move || {
+            let entries = tree.as_ref().entries(l1_batch_number, &keys);
+            (tree, entries)
+        })
+        .await
+        .unwrap();
+
+        *self = tree;
+        let entries = entries?.into_iter().map(|entry| {
+            if entry.is_empty() {
+                None
+            } else {
+                V::decode(entry.value_hash)
+            }
+        });
+        Ok(entries.collect())
+    }
+
+    /// Computes a fingerprint of the latest tree state. Trees with equal fingerprints have the same entries
+    /// with the same leaf indices, even if the tree hashing logic is faulty.
+    pub async fn state_fingerprint(&mut self) -> H256 {
+        let tree = mem::take(self);
+        let (tree, fingerprint) = tokio::task::spawn_blocking(move || {
+            let fingerprint = tree.as_ref().state_fingerprint();
+            (tree, fingerprint)
+        })
+        .await
+        .unwrap();
+
+        *self = tree;
+        fingerprint
+    }
+
+    /// Estimates the number of new leaves that processing `storage_logs` would insert into the tree
+    /// without modifying the tree. Can be used to plan batching and memory usage.
+    pub async fn estimate_new_leaves(&mut self, storage_logs: Vec<StorageLog>) -> u64 {
+        let tree = mem::take(self);
+        let (tree, new_leaf_count) = tokio::task::spawn_blocking(move || {
+            let new_leaf_count = tree.as_ref().estimate_new_leaves(&storage_logs);
+            (tree, new_leaf_count)
+        })
+        .await
+        .unwrap();
+
+        *self = tree;
+        new_leaf_count
+    }
+
     pub async fn process_l1_batch(&mut self, storage_logs: Vec<StorageLog>) -> TreeMetadata {
         let mut tree = mem::take(self);
         let (tree, metadata) = tokio::task::spawn_blocking(move || {
@@ -121,6 +519,49 @@ impl AsyncTree {
         metadata
     }
 
+    /// Processes an L1 batch, pushing the produced witness input to `witness_builder` as it's being produced,
+    /// so that large witnesses can be spilled to disk without being held in memory as a whole.
+    pub async fn process_l1_batch_with_witness(
+        &mut self,
+        storage_logs: Vec<StorageLog>,
+        mut witness_builder: WitnessBuilder,
+    ) -> (TreeMetadata, anyhow::Result<WitnessInput>) {
+        let mut tree = mem::take(self);
+        let (tree, metadata, witness) = tokio::task::spawn_blocking(move || {
+            let metadata = tree
+                .as_mut()
+                .process_l1_batch_with_witness(&storage_logs, &mut witness_builder);
+            (tree, metadata, witness_builder.finish())
+        })
+        .await
+        .unwrap();
+
+        *self = tree;
+        (metadata, witness)
+    }
+
+    /// Computes metadata for `storage_logs` applied on top of the tree state after `base_l1_batch`
+    /// (which may be older than the latest tree state). The computed changes are discarded, so the tree
+    /// is not modified; hence, this should only be called when the tree has no unsaved changes.
+    pub async fn process_on_base(
+        &mut self,
+        base_l1_batch: L1BatchNumber,
+        storage_logs: Vec<StorageLog>,
+    ) -> Result<TreeMetadata, NoVersionError> {
+        let mut tree = mem::take(self);
+        let (tree, metadata) = tokio::task::spawn_blocking(move || {
+            let metadata = tree
+                .as_mut()
+                .process_l1_batch_on_base(base_l1_batch, &storage_logs);
+            (tree, metadata)
+        })
+        .await
+        .unwrap();
+
+        *self = tree;
+        metadata
+    }
+
     pub async fn save(&mut self) {
         let mut tree = mem::take(self);
         *self = tokio::task::spawn_blocking(|| {
@@ -129,10 +570,311 @@ impl AsyncTree {
         })
         .await
         .unwrap();
+        self.cache_latest_root_hash();
+    }
+
+    /// Saves the tree together with a cursor of a tree consumer atomically and fsyncs the RocksDB WAL,
+    /// so that the saved cursor is durable. See [`ZkSyncTree::save_with_cursor()`] for details.
+    pub async fn save_with_cursor(
+        &mut self,
+        cursor_cf: MerkleTreeColumnFamily,
+        cursor_key: Vec<u8>,
+        cursor_value: Vec<u8>,
+    ) {
+        let mut tree = mem::take(self);
+        *self = tokio::task::spawn_blocking(move || {
+            tree.as_mut()
+                .save_with_cursor(cursor_cf, &cursor_key, &cursor_value);
+            tree.as_ref().sync_wal();
+            tree
+        })
+        .await
+        .unwrap();
+        self.cache_latest_root_hash();
+    }
+
+    /// Fsyncs the RocksDB write-ahead log, making changes saved to RocksDB durable without flushing
+    /// memtables. See [`ZkSyncTree::sync_wal()`] for details.
+    pub async fn sync_wal(&mut self) {
+        let tree = mem::take(self);
+        *self = tokio::task::spawn_blocking(|| {
+            tree.as_ref().sync_wal();
+            tree
+        })
+        .await
+        .unwrap();
+    }
+
+    /// Reads a consumer cursor persisted via [`Self::save_with_cursor()`].
+    pub fn cursor(&self, cursor_cf: MerkleTreeColumnFamily, cursor_key: &[u8]) -> Option<Vec<u8>> {
+        self.as_ref().cursor(cursor_cf, cursor_key)
     }
 
-    pub fn revert_logs(&mut self, last_l1_batch_to_keep: L1BatchNumber) {
+    fn cache_latest_root_hash(&mut self) {
+        let next_l1_batch_number = self.next_l1_batch_number();
+        if next_l1_batch_number > L1BatchNumber(0) {
+            let root_hash = self.root_hash();
+            self.root_hash_cache
+                .insert(next_l1_batch_number - 1, root_hash);
+        }
+    }
+
+    /// Reverts the tree to the specified L1 batch, saves the reverted tree and records the revert
+    /// in the audit log. The log entry is only written after the revert is persisted.
+    pub async fn revert_and_save(&mut self, last_l1_batch_to_keep: L1BatchNumber) {
         self.as_mut().revert_logs(last_l1_batch_to_keep);
+        self.root_hash_cache.remove_after(last_l1_batch_to_keep);
+        self.save().await;
+        let operation = AuditedOperation::Revert {
+            last_l1_batch_to_keep,
+        };
+        self.audit(operation);
+    }
+
+    /// Prunes tree versions persisted in RocksDB before the specified L1 batch and records pruning
+    /// in the audit log. Returns the number of pruned node keys.
+    pub async fn prune_up_to(&mut self, first_l1_batch_to_keep: L1BatchNumber) -> usize {
+        let mut tree = mem::take(self);
+        let (tree, pruned_key_count) = tokio::task::spawn_blocking(move || {
+            let pruned_key_count = tree.as_mut().prune_up_to(first_l1_batch_to_keep);
+            (tree, pruned_key_count)
+        })
+        .await
+        .unwrap();
+        *self = tree;
+
+        tracing::info!(
+            "Pruned {pruned_key_count} node keys from Merkle tree up to L1 batch #{first_l1_batch_to_keep}"
+        );
+        let operation = AuditedOperation::Prune {
+            first_l1_batch_to_keep,
+            pruned_key_count,
+        };
+        self.audit(operation);
+        pruned_key_count
+    }
+
+    /// Defragments the tree RocksDB: copies the latest tree version to a fresh RocksDB instance
+    /// with nodes laid out in the key order (see [`ZkSyncTree::copy_defragmented()`]), checks
+    /// the copy against the current tree and swaps it in place of the current RocksDB directory.
+    /// Unsaved changes are saved beforehand. Previous tree versions become inaccessible, as if
+    /// the tree was pruned up to the latest L1 batch.
+    ///
+    /// This is an offline maintenance routine: the tree cannot be updated until it completes,
+    /// and it temporarily requires disk space for a full copy of the latest tree version.
+    /// Returns the number of copied tree nodes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are live readers obtained via [`Self::reader()`] (they would keep
+    /// observing the replaced RocksDB), if the copy doesn't match the current tree, or on I/O errors.
+    /// The original RocksDB directory is retained in this case.
+    pub async fn defragment(&mut self) -> anyhow::Result<u64> {
+        self.save().await;
+        let mut tree = mem::take(self);
+        let (tree, result) = tokio::task::spawn_blocking(move || {
+            let result = tree.defragment_blocking();
+            (tree, result)
+        })
+        .await
+        .unwrap();
+        *self = tree;
+        let copied_node_count = result?;
+
+        // Root hashes for previous L1 batches are no longer available in the tree.
+        self.root_hash_cache.clear();
+        self.cache_latest_root_hash();
+        self.audit(AuditedOperation::Defragment { copied_node_count });
+        Ok(copied_node_count)
+    }
+
+    fn defragment_blocking(&mut self) -> anyhow::Result<u64> {
+        let db_path = self.reopen_params.db_path.clone();
+        let reader_count = self.as_ref().reader_count();
+        anyhow::ensure!(
+            reader_count == 0,
+            "Cannot defragment Merkle tree at `{}`: it has {reader_count} live reader(s) that would \
+             observe the replaced RocksDB",
+            db_path.display()
+        );
+        let defrag_path = Self::sibling_path(&db_path, "defrag");
+        let old_path = Self::sibling_path(&db_path, "old");
+        for path in [&defrag_path, &old_path] {
+            if path.exists() {
+                tracing::warn!("Removing leftover directory `{}`", path.display());
+                fs::remove_dir_all(path)
+                    .with_context(|| format!("failed removing `{}`", path.display()))?;
+            }
+        }
+
+        let tree = self.as_ref();
+        let root_hash = tree.root_hash();
+        let next_l1_batch_number = tree.next_l1_batch_number();
+        tracing::info!(
+            "Defragmenting Merkle tree at `{}` with root hash {root_hash:?} (next L1 batch: #{next_l1_batch_number})",
+            db_path.display()
+        );
+        let started_at = Instant::now();
+        let (target_db, _) = Self::create_db(&defrag_path, self.reopen_params.db_options, false);
+        let copied_node_count = tree.copy_defragmented(target_db);
+        tracing::info!(
+            "Copied {copied_node_count} tree nodes to `{}` in {:?}",
+            defrag_path.display(),
+            started_at.elapsed()
+        );
+
+        let (copied_db, _) = Self::create_db(&defrag_path, self.reopen_params.db_options, false);
+        let copied_tree = ZkSyncTree::new_lightweight(copied_db);
+        anyhow::ensure!(
+            copied_tree.root_hash() == root_hash
+                && copied_tree.next_l1_batch_number() == next_l1_batch_number,
+            "Defragmented tree at `{}` doesn't match the original tree: root hash {:?} (expected {root_hash:?}), \
+             next L1 batch #{} (expected #{next_l1_batch_number})",
+            defrag_path.display(),
+            copied_tree.root_hash(),
+            copied_tree.next_l1_batch_number()
+        );
+        if let Some(last_l1_batch) = next_l1_batch_number.0.checked_sub(1) {
+            copied_tree.verify_consistency(L1BatchNumber(last_l1_batch));
+        }
+        drop(copied_tree);
+
+        // Close the original RocksDB before touching its directory.
+        self.inner = None;
+        let swap_result = Self::swap_dirs(&db_path, &defrag_path, &old_path);
+        // If swapping failed, the original RocksDB is still at `db_path`, so we reopen it in any case.
+        let params = &self.reopen_params;
+        let (db, _) = Self::create_db(&db_path, params.db_options, false);
+        let mut tree = Self::create_tree(db, self.mode);
+        tree.set_multi_get_chunk_size(params.multi_get_chunk_size);
+        tree.set_witness_generation(params.generate_witness);
+        self.inner = Some(tree);
+        swap_result?;
+
+        fs::remove_dir_all(&old_path)
+            .with_context(|| format!("failed removing `{}`", old_path.display()))?;
+        tracing::info!(
+            "Defragmented Merkle tree at `{}` in {:?}",
+            db_path.display(),
+            started_at.elapsed()
+        );
+        Ok(copied_node_count)
+    }
+
+    fn sibling_path(db_path: &Path, extension: &str) -> PathBuf {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".");
+        path.push(extension);
+        path.into()
+    }
+
+    /// Replaces the directory at `db_path` with the directory at `new_path`, moving the former to `old_path`.
+    fn swap_dirs(db_path: &Path, new_path: &Path, old_path: &Path) -> anyhow::Result<()> {
+        fs::rename(db_path, old_path).with_context(|| {
+            format!(
+                "failed moving `{}` to `{}`",
+                db_path.display(),
+                old_path.display()
+            )
+        })?;
+        if let Err(err) = fs::rename(new_path, db_path) {
+            // Attempt to restore the original directory.
+            fs::rename(old_path, db_path).with_context(|| {
+                format!(
+                    "failed restoring original Merkle tree directory `{}` from `{}`",
+                    db_path.display(),
+                    old_path.display()
+                )
+            })?;
+            return Err(err).with_context(|| {
+                format!(
+                    "failed moving `{}` to `{}`",
+                    new_path.display(),
+                    db_path.display()
+                )
+            });
+        }
+        Ok(())
+    }
+
+    fn audit(&self, operation: AuditedOperation) {
+        let audit_log = self.audit_log.as_ref().expect(Self::INCONSISTENT_MSG);
+        let entry = AuditLogEntry::new(operation, self.root_hash());
+        audit_log
+            .append(&entry)
+            .expect("failed recording operation in Merkle tree audit log");
+    }
+
+    /// Reads back the audit log of destructive operations on the tree.
+    pub fn audit_log(&self) -> anyhow::Result<Vec<AuditLogEntry>> {
+        self.audit_log
+            .as_ref()
+            .expect(Self::INCONSISTENT_MSG)
+            .read()
+    }
+}
+
+/// Small LRU cache of tree root hashes used by [`AsyncTree::root_hash_at()`].
+#[derive(Debug, Default)]
+struct RootHashCache {
+    capacity: usize,
+    /// Cached entries ordered from the least recently used to the most recently used one.
+    /// The cache is expected to be small, so linear lookups are fine.
+    entries: VecDeque<(L1BatchNumber, H256)>,
+    #[cfg(test)]
+    hits: u64,
+}
+
+impl RootHashCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            #[cfg(test)]
+            hits: 0,
+        }
+    }
+
+    fn get(&mut self, l1_batch_number: L1BatchNumber) -> Option<H256> {
+        let idx = self
+            .entries
+            .iter()
+            .position(|(number, _)| *number == l1_batch_number);
+        let Some(idx) = idx else {
+            METRICS.root_hash_cache_misses.inc();
+            return None;
+        };
+
+        let entry = self.entries.remove(idx).unwrap();
+        self.entries.push_back(entry);
+        METRICS.root_hash_cache_hits.inc();
+        #[cfg(test)]
+        {
+            self.hits += 1;
+        }
+        Some(entry.1)
+    }
+
+    fn insert(&mut self, l1_batch_number: L1BatchNumber, root_hash: H256) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries
+            .retain(|(number, _)| *number != l1_batch_number);
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((l1_batch_number, root_hash));
+    }
+
+    /// Removes entries for L1 batches after `last_l1_batch_to_keep`.
+    fn remove_after(&mut self, last_l1_batch_to_keep: L1BatchNumber) {
+        self.entries
+            .retain(|(number, _)| *number <= last_l1_batch_to_keep);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
     }
 }
 
@@ -167,229 +909,1359 @@ impl Delayer {
     }
 }
 
-#[derive(Debug)]
-#[cfg_attr(test, derive(PartialEq))]
-pub(crate) struct L1BatchWithLogs {
-    pub header: L1BatchHeader,
-    pub storage_logs: Vec<StorageLog>,
+/// Source of Postgres connections for the tree update loop. Abstracted so that connection failures
+/// can be emulated in tests.
+#[async_trait]
+pub(super) trait ConnectionSource: Send + Sync {
+    async fn access_storage(&self) -> anyhow::Result<StorageProcessor<'_>>;
 }
 
-impl L1BatchWithLogs {
-    pub async fn new(
-        storage: &mut StorageProcessor<'_>,
-        l1_batch_number: L1BatchNumber,
-    ) -> Option<Self> {
-        tracing::debug!("Loading storage logs data for L1 batch #{l1_batch_number}");
-        let load_changes_latency = TreeUpdateStage::LoadChanges.start();
+#[async_trait]
+impl ConnectionSource for ConnectionPool {
+    async fn access_storage(&self) -> anyhow::Result<StorageProcessor<'_>> {
+        self.access_storage_tagged("metadata_calculator").await
+    }
+}
 
-        let header_latency = LoadChangesStage::L1BatchHeader.start();
-        let header = storage
-            .blocks_dal()
-            .get_l1_batch_header(l1_batch_number)
-            .await
-            .unwrap()?;
-        header_latency.report();
+/// Health check details reported once the tree gives up acquiring a Postgres connection.
+#[derive(Debug, Serialize)]
+struct ConnectionFailureDetails {
+    failed_attempts: usize,
+    error: String,
+}
 
-        let protective_reads_latency = LoadChangesStage::ProtectiveReads.start();
-        let protective_reads = storage
-            .storage_logs_dedup_dal()
-            .get_protective_reads_for_l1_batch(l1_batch_number)
-            .await;
-        protective_reads_latency.report_with_count(protective_reads.len());
+/// Policy for acquiring Postgres connections in the tree update loop. Failed acquisitions are assumed
+/// to be caused by transient issues (e.g., an exhausted pool or a restarting DB) and are retried
+/// with exponential backoff. Once all attempts fail, the DB is considered permanently unavailable.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ConnectionRetryPolicy {
+    pub max_attempts: usize,
+    pub initial_backoff: Duration,
+}
+
+impl ConnectionRetryPolicy {
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+    /// Acquires a connection from `source`. If all attempts fail, marks the tree as not ready
+    /// and returns an error. Returns `Ok(None)` if a stop signal is received while backing off.
+    pub async fn access_storage<'a>(
+        &self,
+        source: &'a dyn ConnectionSource,
+        stop_receiver: &mut watch::Receiver<bool>,
+        health_updater: &HealthUpdater,
+    ) -> anyhow::Result<Option<StorageProcessor<'a>>> {
+        let mut backoff = self.initial_backoff;
+        let mut failed_attempts = 0;
+        loop {
+            let err = match source.access_storage().await {
+                Ok(storage) => return Ok(Some(storage)),
+                Err(err) => err,
+            };
+            failed_attempts += 1;
+            METRICS.db_connection_failures.inc();
+
+            if failed_attempts >= self.max_attempts {
+                let details = ConnectionFailureDetails {
+                    failed_attempts,
+                    error: format!("{err:#}"),
+                };
+                health_updater.update(Health::from(HealthStatus::NotReady).with_details(details));
+                return Err(err.context(format!(
+                    "failed acquiring Postgres connection after {failed_attempts} attempts"
+                )));
+            }
+            tracing::warn!(
+                "Failed acquiring Postgres connection for Merkle tree (attempt {failed_attempts}/{}), \
+                 retrying in {backoff:?}: {err:#}",
+                self.max_attempts
+            );
+            tokio::select! {
+                _ = stop_receiver.changed() => return Ok(None),
+                () = tokio::time::sleep(backoff) => { /* The backoff has passed */ }
+            }
+            backoff = (backoff * 2).min(Self::MAX_BACKOFF);
+        }
+    }
+}
+
+/// Component periodically refreshing tree gauges in [`MetadataCalculator`]. Without it, gauges
+/// are only updated when the tree processes L1 batches, so an idle tree would look the same
+/// as a stuck one on dashboards.
+#[derive(Debug, Clone)]
+pub(super) struct IdleMetricsEmitter {
+    db_path: PathBuf,
+    pub refresh_interval: Duration,
+    // Notifies the tests about each gauges refresh.
+    #[cfg(test)]
+    pub update_notifier: mpsc::UnboundedSender<TreeGauges>,
+}
+
+impl IdleMetricsEmitter {
+    pub fn new(db_path: PathBuf, refresh_interval: Duration) -> Self {
+        Self {
+            db_path,
+            refresh_interval,
+            #[cfg(test)]
+            update_notifier: mpsc::unbounded_channel().0,
+        }
+    }
+
+    /// Runs the emitter until a stop signal is received, or `next_l1_batch_receiver` is closed
+    /// (i.e., the tree updater has terminated). `None` values in `next_l1_batch_receiver` mean
+    /// that the tree is not initialized yet.
+    pub async fn run(
+        self,
+        pool: ConnectionPool,
+        mut next_l1_batch_receiver: watch::Receiver<Option<L1BatchNumber>>,
+        mut stop_receiver: watch::Receiver<bool>,
+    ) {
+        loop {
+            if *stop_receiver.borrow() || next_l1_batch_receiver.has_changed().is_err() {
+                break;
+            }
+            let next_l1_batch = *next_l1_batch_receiver.borrow_and_update();
+            if let Some(next_l1_batch) = next_l1_batch {
+                if let Err(err) = self.refresh(&pool, next_l1_batch).await {
+                    tracing::warn!("Failed refreshing Merkle tree gauges: {err:#}");
+                }
+            }
+
+            tokio::select! {
+                _ = stop_receiver.changed() => break,
+                () = tokio::time::sleep(self.refresh_interval) => { /* continue refreshing */ }
+            }
+        }
+    }
+
+    async fn refresh(
+        &self,
+        pool: &ConnectionPool,
+        next_l1_batch: L1BatchNumber,
+    ) -> anyhow::Result<()> {
+        let mut storage = pool.access_storage_tagged("metadata_calculator").await?;
+        let last_sealed_l1_batch = storage.blocks_dal().get_sealed_l1_batch_number().await?;
+        drop(storage);
+
+        let db_path = self.db_path.clone();
+        let db_size = tokio::task::spawn_blocking(move || dir_size(&db_path)).await??;
+        let gauges = TreeGauges {
+            next_l1_batch,
+            lag: (last_sealed_l1_batch.0 + 1).saturating_sub(next_l1_batch.0),
+            db_size,
+        };
+        tracing::trace!("Refreshed Merkle tree gauges: {gauges:?}");
+        gauges.report();
+
+        #[cfg(test)]
+        self.update_notifier.send(gauges).ok();
+        Ok(())
+    }
+}
+
+/// Recursively computes the total size of files in the specified directory.
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Handles an inconsistency in Postgres data according to the `policy`: either returns an error
+/// halting the tree, or logs a warning and reports the inconsistency via metrics.
+pub(super) fn handle_dal_inconsistency(
+    policy: DalConsistencyPolicy,
+    kind: &'static str,
+    message: String,
+) -> anyhow::Result<()> {
+    match policy {
+        DalConsistencyPolicy::FailFast => Err(anyhow::Error::msg(message)),
+        DalConsistencyPolicy::BestEffort => {
+            tracing::warn!("{message}; continuing per the best-effort DAL consistency policy");
+            METRICS.dal_inconsistencies[&kind].inc();
+            Ok(())
+        }
+    }
+}
+
+/// Converts a Merkle tree key to the hashed storage key (the inverse of `StorageKey::hashed_key_u256()`).
+pub(super) fn hashed_key(key: Key) -> H256 {
+    let mut bytes = [0_u8; 32];
+    key.to_little_endian(&mut bytes);
+    H256(bytes)
+}
+
+/// Converts a hashed storage key to the Merkle tree key (same as `StorageKey::hashed_key_u256()`).
+/// Tree keys are ordered differently from hashed keys, since the latter are compared bytewise.
+pub(super) fn tree_key(hashed_key: H256) -> Key {
+    Key::from_little_endian(hashed_key.as_bytes())
+}
+
+/// Protective reads for a single L1 batch.
+#[derive(Debug, Default)]
+pub(crate) struct ProtectiveReads {
+    /// Full storage keys. Not loaded (i.e., `None`) in the lightweight tree mode since the lightweight tree
+    /// doesn't use read logs.
+    keys: Option<Vec<StorageKey>>,
+    hashed_keys: Vec<H256>,
+}
+
+impl ProtectiveReads {
+    pub fn new(keys: Vec<StorageKey>, mode: MerkleTreeMode) -> Self {
+        let hashed_keys = keys.iter().map(StorageKey::hashed_key).collect();
+        let keys = match mode {
+            MerkleTreeMode::Full => Some(keys),
+            MerkleTreeMode::Lightweight => None,
+        };
+        Self { keys, hashed_keys }
+    }
+
+    async fn load(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        mode: MerkleTreeMode,
+    ) -> Self {
+        match mode {
+            MerkleTreeMode::Full => {
+                let keys = storage
+                    .storage_logs_dedup_dal()
+                    .get_protective_reads_for_l1_batch(l1_batch_number)
+                    .await;
+                Self::new(keys.into_iter().collect(), mode)
+            }
+            MerkleTreeMode::Lightweight => {
+                let hashed_keys = storage
+                    .storage_logs_dedup_dal()
+                    .get_protective_reads_hashed_keys_for_l1_batch(l1_batch_number)
+                    .await;
+                Self {
+                    keys: None,
+                    hashed_keys,
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashed_keys.len()
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct L1BatchWithLogs {
+    pub header: L1BatchHeader,
+    /// Storage logs (both protective reads and writes) for the L1 batch, strictly sorted by `StorageKey`.
+    /// The order is important for leaf index assignment in the tree, so it must not depend
+    /// on the way logs are loaded.
+    pub storage_logs: Vec<StorageLog>,
+}
+
+impl L1BatchWithLogs {
+    /// Maximum number of zero-value writes checked against the `initial_writes` table in a single query.
+    /// Bounds memory used for filtering out zero-value writes in L1 batches with many such writes.
+    const ZERO_VALUES_CHUNK_SIZE: usize = 10_000;
+
+    /// Loads an L1 batch with the specified number. Read logs are only loaded in the full tree `mode`
+    /// since the lightweight tree doesn't use them. Returns `Ok(None)` if the L1 batch is missing in Postgres.
+    ///
+    /// If the L1 batch header cannot be loaded (e.g., because the corresponding row is malformed),
+    /// this is handled according to the DAL consistency `policy`: the error is either returned,
+    /// or logged, in which case the L1 batch is treated as missing.
+    pub async fn new(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        mode: MerkleTreeMode,
+        policy: DalConsistencyPolicy,
+    ) -> anyhow::Result<Option<Self>> {
+        let header_latency = LoadChangesStage::L1BatchHeader.start();
+        let header = match storage
+            .blocks_dal()
+            .get_l1_batch_header(l1_batch_number)
+            .await
+        {
+            Ok(header) => header,
+            Err(err) => {
+                let message = format!("Cannot load header for L1 batch #{l1_batch_number}: {err}");
+                handle_dal_inconsistency(policy, "l1_batch_header", message)?;
+                None
+            }
+        };
+        let Some(header) = header else {
+            return Ok(None);
+        };
+        header_latency.report_with_count(1);
+        Ok(Some(Self::with_header(storage, header, mode).await))
+    }
+
+    /// Loads storage logs for an L1 batch with the specified (already loaded) header.
+    pub async fn with_header(
+        storage: &mut StorageProcessor<'_>,
+        header: L1BatchHeader,
+        mode: MerkleTreeMode,
+    ) -> Self {
+        let protective_reads_latency = LoadChangesStage::ProtectiveReads.start();
+        let protective_reads = ProtectiveReads::load(storage, header.number, mode).await;
+        protective_reads_latency.report_with_count(protective_reads.len());
+        Self::with_protective_reads(storage, header, protective_reads).await
+    }
+
+    /// Loads storage logs for an L1 batch with the specified (already loaded) header
+    /// and protective reads.
+    pub async fn with_protective_reads(
+        storage: &mut StorageProcessor<'_>,
+        header: L1BatchHeader,
+        protective_reads: ProtectiveReads,
+    ) -> Self {
+        Self::with_zero_values_chunk_size(
+            storage,
+            header,
+            protective_reads,
+            Self::ZERO_VALUES_CHUNK_SIZE,
+        )
+        .await
+    }
+
+    async fn with_zero_values_chunk_size(
+        storage: &mut StorageProcessor<'_>,
+        header: L1BatchHeader,
+        protective_reads: ProtectiveReads,
+        zero_values_chunk_size: usize,
+    ) -> Self {
+        assert!(zero_values_chunk_size > 0, "Chunk size must be positive");
+        let l1_batch_number = header.number;
+        tracing::debug!("Loading storage logs data for L1 batch #{l1_batch_number}");
+        let load_changes_latency = TreeUpdateStage::LoadChanges.start();
+        let ProtectiveReads {
+            keys: protective_reads,
+            hashed_keys: protective_reads_hashed_keys,
+        } = protective_reads;
+        // Protective reads are only converted to read logs in the full tree mode, so they are only reported there.
+        let protective_reads_count = protective_reads
+            .as_ref()
+            .map(|_| protective_reads_hashed_keys.len());
+
+        let touched_slots_latency = LoadChangesStage::TouchedSlots.start();
+        let touched_slots = storage
+            .storage_logs_dal()
+            .get_touched_slots_for_l1_batch(l1_batch_number)
+            .await;
+        touched_slots_latency.report_with_count(touched_slots.len());
+        let mut touched_slots: HashMap<_, _> = touched_slots
+            .into_iter()
+            .map(|(storage_key, value)| (storage_key.hashed_key(), (storage_key, value)))
+            .collect();
+
+        for hashed_key in &protective_reads_hashed_keys {
+            touched_slots.remove(hashed_key);
+            // ^ As per deduplication rules, all keys in `protective_reads` haven't *really* changed
+            // in the considered L1 batch. Thus, we can remove them from `touched_slots` in order to simplify
+            // their further processing.
+        }
+
+        // Logs are ordered by `StorageKey`s, which is important for leaf index assignment in the tree.
+        let mut storage_logs = BTreeMap::new();
+        for storage_key in protective_reads.into_iter().flatten() {
+            let log = StorageLog::new_read_log(storage_key, H256::zero());
+            // ^ The tree doesn't use the read value, so we set it to zero.
+            storage_logs.insert(storage_key, log);
+        }
+        tracing::debug!(
+            "Made touched slots disjoint with protective reads; remaining touched slots: {}",
+            touched_slots.len()
+        );
+
+        // We don't want to update the tree with zero values which were never written to per storage log
+        // deduplication rules. If we write such values to the tree, it'd result in bogus tree hashes because
+        // new (bogus) leaf indices would be allocated for them. To filter out those values, it's sufficient
+        // to check when a `storage_key` was first written per `initial_writes` table. If this never occurred
+        // or occurred after the considered `l1_batch_number`, this means that the write must be ignored.
+        //
+        // Note that this approach doesn't filter out no-op writes of the same value, but this is fine;
+        // since no new leaf indices are allocated in the tree for them, such writes are no-op on the tree side as well.
+        //
+        // Zero values are checked in chunks of bounded size, so that neither the checked keys
+        // nor the loaded initial write L1 batches are materialized for the entire L1 batch at once.
+        let mut zero_values_chunk =
+            Vec::with_capacity(zero_values_chunk_size.min(touched_slots.len()));
+        let mut zero_values_count = 0;
+        let mut filtered_zero_values_count = 0;
+        for (hashed_key, (storage_key, value)) in touched_slots {
+            if value.is_zero() {
+                zero_values_chunk.push((hashed_key, storage_key));
+                if zero_values_chunk.len() == zero_values_chunk_size {
+                    zero_values_count += zero_values_chunk.len();
+                    filtered_zero_values_count += Self::filter_zero_values(
+                        storage,
+                        l1_batch_number,
+                        &mut zero_values_chunk,
+                        &mut storage_logs,
+                    )
+                    .await;
+                }
+            } else {
+                // Non-zero values are always written per deduplication rules.
+                storage_logs.insert(storage_key, StorageLog::new_write_log(storage_key, value));
+            }
+        }
+        if !zero_values_chunk.is_empty() {
+            zero_values_count += zero_values_chunk.len();
+            filtered_zero_values_count += Self::filter_zero_values(
+                storage,
+                l1_batch_number,
+                &mut zero_values_chunk,
+                &mut storage_logs,
+            )
+            .await;
+        }
+        METRICS.load_changes_zero_values.observe(zero_values_count);
+
+        load_changes_latency.report();
+        let storage_logs: Vec<_> = storage_logs.into_values().collect();
+        debug_assert!(
+            storage_logs
+                .windows(2)
+                .all(|pair| pair[0].key < pair[1].key),
+            "Storage logs for L1 batch #{l1_batch_number} are not strictly sorted by key"
+        );
+        let write_count = storage_logs
+            .iter()
+            .filter(|log| log.kind == StorageLogKind::Write)
+            .count();
+        let stats = LoadedL1BatchStats {
+            l1_batch_number,
+            write_count,
+            read_count: protective_reads_count,
+            filtered_zero_write_count: filtered_zero_values_count,
+        };
+        stats.report();
+        tracing::debug!("Loaded storage logs for {stats}");
+        Self {
+            header,
+            storage_logs,
+        }
+    }
+
+    /// Reports the ratio of protective reads to writes in this L1 batch and warns if it exceeds `thresholds`.
+    /// Returns whether the ratio is excessive. Must only be called for L1 batches loaded in the full tree mode;
+    /// in the lightweight mode, protective reads are not loaded as read logs.
+    pub fn check_protective_reads_ratio(&self, thresholds: &ProtectiveReadsThresholds) -> bool {
+        let l1_batch_number = self.header.number;
+        let (mut read_count, mut write_count) = (0, 0);
+        for log in &self.storage_logs {
+            match log.kind {
+                StorageLogKind::Read => read_count += 1,
+                StorageLogKind::Write => write_count += 1,
+            }
+        }
+        // L1 batches without writes are treated as having a single write, so that the ratio is finite.
+        let ratio = read_count as f64 / write_count.max(1) as f64;
+        METRICS.protective_reads_ratio.observe(ratio);
+        let is_excessive = read_count >= thresholds.min_reads && ratio > thresholds.max_ratio;
+        if is_excessive {
+            tracing::warn!(
+                "L1 batch #{l1_batch_number} has {read_count} protective reads and {write_count} writes \
+                 (ratio: {ratio:.1}), which exceeds the warning threshold {}; this may indicate misconfigured \
+                 storage log deduplication",
+                thresholds.max_ratio
+            );
+            METRICS.excessive_protective_reads.inc();
+        }
+        is_excessive
+    }
+
+    /// Inserts zero-value writes from `chunk` that were initially written no later than `l1_batch_number`
+    /// into `storage_logs`. Drains `chunk`. Returns the number of filtered out writes.
+    async fn filter_zero_values(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        chunk: &mut Vec<(H256, StorageKey)>,
+        storage_logs: &mut BTreeMap<StorageKey, StorageLog>,
+    ) -> usize {
+        let hashed_keys: Vec<_> = chunk.iter().map(|(hashed_key, _)| *hashed_key).collect();
+        let latency = LoadChangesStage::InitialWritesForZeroValues.start();
+        let l1_batches_for_initial_writes = storage
+            .storage_logs_dal()
+            .get_l1_batches_for_initial_writes(&hashed_keys)
+            .await;
+        latency.report_with_count(hashed_keys.len());
+
+        let mut filtered_count = 0;
+        for (hashed_key, storage_key) in chunk.drain(..) {
+            let initial_write_batch_for_key = l1_batches_for_initial_writes.get(&hashed_key);
+            if initial_write_batch_for_key.map_or(false, |&number| number <= l1_batch_number) {
+                let log = StorageLog::new_write_log(storage_key, H256::zero());
+                storage_logs.insert(storage_key, log);
+            } else {
+                filtered_count += 1;
+            }
+        }
+        filtered_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use tempfile::TempDir;
+
+    use db_test_macro::db_test;
+
+    use std::collections::HashSet;
+
+    use zksync_dal::ConnectionPool;
+    use zksync_types::{block::BlockGasCount, proofs::PrepareBasicCircuitsJob, L2ChainId};
+
+    use super::*;
+    use crate::{
+        genesis::{ensure_genesis_state, GenesisParams},
+        metadata_calculator::{
+            benchmark::{extend_db_state, gen_storage_logs},
+            logs_verifier::{LogsDivergence, LogsVerifier, PreviousValuesCache, ReferenceLogs},
+            tests::reset_db_state,
+        },
+    };
+
+    impl L1BatchWithLogs {
+        /// Old, slower method of loading storage logs. We want to test its equivalence to the new implementation.
+        async fn slow(
+            storage: &mut StorageProcessor<'_>,
+            l1_batch_number: L1BatchNumber,
+        ) -> Option<Self> {
+            let mut cache = PreviousValuesCache::new(0);
+            let reference = ReferenceLogs::load(storage, l1_batch_number, &mut cache).await?;
+            // Sanity check: value must not change for slots that require protective reads.
+            assert!(
+                reference.changed_protective_reads.is_empty(),
+                "Value was changed for slot that requires protective read"
+            );
+            Some(reference.l1_batch)
+        }
+    }
+
+    #[db_test]
+    async fn loaded_logs_equivalence_basics(pool: ConnectionPool) {
+        ensure_genesis_state(
+            &mut pool.access_storage().await.unwrap(),
+            L2ChainId::from(270),
+            &GenesisParams::mock(),
+        )
+        .await
+        .unwrap();
+        reset_db_state(&pool, 5).await;
+
+        let mut storage = pool.access_storage().await.unwrap();
+        for l1_batch_number in 0..=5 {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            let batch_with_logs = L1BatchWithLogs::new(
+                &mut storage,
+                l1_batch_number,
+                MerkleTreeMode::Full,
+                DalConsistencyPolicy::FailFast,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            let slow_batch_with_logs = L1BatchWithLogs::slow(&mut storage, l1_batch_number)
+                .await
+                .unwrap();
+            assert_eq!(batch_with_logs, slow_batch_with_logs);
+        }
+    }
+
+    #[db_test]
+    async fn l1_batch_header_errors_are_handled_according_to_policy(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+        let header = storage
+            .blocks_dal()
+            .get_l1_batch_header(L1BatchNumber(0))
+            .await
+            .unwrap()
+            .unwrap();
+
+        for policy in [
+            DalConsistencyPolicy::FailFast,
+            DalConsistencyPolicy::BestEffort,
+        ] {
+            let mut transaction = storage.start_transaction().await.unwrap();
+            // Inserting a duplicate L1 batch aborts the transaction, so that loading the header fails.
+            transaction
+                .blocks_dal()
+                .insert_l1_batch(&header, &[], BlockGasCount::default())
+                .await
+                .unwrap_err();
+
+            let result = L1BatchWithLogs::new(
+                &mut transaction,
+                L1BatchNumber(0),
+                MerkleTreeMode::Lightweight,
+                policy,
+            )
+            .await;
+            match policy {
+                DalConsistencyPolicy::FailFast => {
+                    let err = result.unwrap_err().to_string();
+                    assert!(err.contains("Cannot load header for L1 batch #0"), "{err}");
+                }
+                DalConsistencyPolicy::BestEffort => {
+                    assert!(result.unwrap().is_none());
+                }
+            }
+        }
+    }
+
+    #[db_test]
+    async fn logs_verification_is_sampled_by_l1_batch_number(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+        drop(storage);
+        reset_db_state(&pool, 7).await;
+
+        let mut storage = pool.access_storage().await.unwrap();
+        for mode in [MerkleTreeMode::Full, MerkleTreeMode::Lightweight] {
+            let mut verifier = LogsVerifier::new(Some(3), mode, 0);
+            let mut verified_l1_batches = vec![];
+            for l1_batch_number in 0..=7 {
+                let l1_batch_number = L1BatchNumber(l1_batch_number);
+                let l1_batch = L1BatchWithLogs::new(
+                    &mut storage,
+                    l1_batch_number,
+                    mode,
+                    DalConsistencyPolicy::FailFast,
+                )
+                .await
+                .unwrap()
+                .unwrap();
+                assert_eq!(
+                    verifier.is_sampled(l1_batch_number),
+                    l1_batch_number.0 % 3 == 0
+                );
+                if let Some(divergences) = verifier.verify_if_sampled(&mut storage, &l1_batch).await
+                {
+                    assert!(divergences.is_empty(), "{divergences:?}");
+                    verified_l1_batches.push(l1_batch_number);
+                }
+            }
+            assert_eq!(
+                verified_l1_batches,
+                [L1BatchNumber(0), L1BatchNumber(3), L1BatchNumber(6)]
+            );
+        }
+
+        // Divergences must be detected for sampled L1 batches.
+        let mut verifier = LogsVerifier::new(Some(3), MerkleTreeMode::Full, 0);
+        let mut l1_batch = L1BatchWithLogs::new(
+            &mut storage,
+            L1BatchNumber(3),
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let write_idx = l1_batch
+            .storage_logs
+            .iter()
+            .position(|log| log.kind == StorageLogKind::Write)
+            .unwrap();
+        let removed_log = l1_batch.storage_logs.remove(write_idx);
+        let divergences = verifier
+            .verify_if_sampled(&mut storage, &l1_batch)
+            .await
+            .unwrap();
+        assert_eq!(
+            divergences,
+            [LogsDivergence::MissingWrite {
+                key: removed_log.key,
+                expected: removed_log.value,
+            }]
+        );
+
+        let mut disabled_verifier = LogsVerifier::new(None, MerkleTreeMode::Full, 0);
+        assert!(disabled_verifier
+            .verify_if_sampled(&mut storage, &l1_batch)
+            .await
+            .is_none());
+    }
+
+    #[db_test]
+    async fn logs_verification_caches_previous_values(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+        // Adjacent L1 batches overwrite the same slots.
+        let logs = gen_storage_logs(100..200, 1).pop().unwrap();
+        let batches = (1..=4_u8).map(|i| {
+            logs.iter()
+                .map(|log| StorageLog::new_write_log(log.key, H256::repeat_byte(i)))
+                .collect()
+        });
+        extend_db_state(&mut storage, batches).await;
+
+        let mut verifier = LogsVerifier::new(Some(1), MerkleTreeMode::Full, 1_000);
+        for l1_batch_number in 1..=4 {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            let l1_batch = L1BatchWithLogs::new(
+                &mut storage,
+                l1_batch_number,
+                MerkleTreeMode::Full,
+                DalConsistencyPolicy::FailFast,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            let divergences = verifier
+                .verify_if_sampled(&mut storage, &l1_batch)
+                .await
+                .unwrap();
+            assert!(divergences.is_empty(), "{divergences:?}");
+        }
+
+        let cache = verifier.cache();
+        assert!(cache.hits > 0, "{cache:?}");
+        // Only the first L1 batch needs to load previous values from Postgres.
+        assert_eq!(cache.misses, logs.len() as u64);
+        assert_eq!(cache.hits, 3 * logs.len() as u64);
+
+        // Reference logs loaded using the cache must be equivalent to ones loaded from Postgres.
+        let mut cache = PreviousValuesCache::new(1_000);
+        for l1_batch_number in 1..=4 {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            let cached = ReferenceLogs::load(&mut storage, l1_batch_number, &mut cache)
+                .await
+                .unwrap();
+            let mut no_cache = PreviousValuesCache::new(0);
+            let uncached = ReferenceLogs::load(&mut storage, l1_batch_number, &mut no_cache)
+                .await
+                .unwrap();
+            assert_eq!(cached.l1_batch, uncached.l1_batch);
+        }
+    }
+
+    #[db_test]
+    async fn recent_root_hashes_are_cached(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+        extend_db_state(&mut storage, gen_storage_logs(100..200, 3)).await;
+
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        // The tree is reverted below, so its audit log is created next to the tree directory.
+        let mut tree = AsyncTree::new(
+            temp_dir.path().join("tree"),
+            MerkleTreeMode::Lightweight,
+            500,
+            0,
+            None,
+            false,
+            2,
+        )
+        .await;
+        let mut root_hashes = vec![];
+        for number in 0..=3 {
+            let l1_batch = L1BatchWithLogs::new(
+                &mut storage,
+                L1BatchNumber(number),
+                MerkleTreeMode::Lightweight,
+                DalConsistencyPolicy::FailFast,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            let metadata = tree.process_l1_batch(l1_batch.storage_logs).await;
+            root_hashes.push(metadata.root_hash);
+            tree.save().await;
+        }
+
+        // Root hashes for L1 batches #2 and #3 are cached on saving the tree.
+        assert_eq!(tree.root_hash_at(L1BatchNumber(3)), Some(root_hashes[3]));
+        assert_eq!(tree.root_hash_cache_hits(), 1);
+
+        // The root hash for L1 batch #1 was evicted from the cache, so it's loaded from RocksDB first.
+        assert_eq!(tree.root_hash_at(L1BatchNumber(1)), Some(root_hashes[1]));
+        assert_eq!(tree.root_hash_cache_hits(), 1);
+        assert_eq!(tree.root_hash_at(L1BatchNumber(1)), Some(root_hashes[1]));
+        assert_eq!(tree.root_hash_cache_hits(), 2);
+
+        // The root hash for L1 batch #2 was evicted when caching the root hash for L1 batch #1.
+        assert_eq!(tree.root_hash_at(L1BatchNumber(2)), Some(root_hashes[2]));
+        assert_eq!(tree.root_hash_cache_hits(), 2);
+        assert_eq!(tree.root_hash_at(L1BatchNumber(4)), None);
+
+        // Reverting the tree must invalidate cached root hashes for the reverted L1 batches.
+        tree.revert_and_save(L1BatchNumber(1)).await;
+        assert_eq!(tree.root_hash_at(L1BatchNumber(1)), Some(root_hashes[1]));
+        assert_eq!(tree.root_hash_cache_hits(), 3);
+        tree.root_hash_at(L1BatchNumber(2));
+        assert_eq!(tree.root_hash_cache_hits(), 3);
+    }
+
+    async fn create_tree_with_logs(db_path: &Path, logs: Vec<Vec<StorageLog>>) -> AsyncTree {
+        let mut tree = AsyncTree::new(
+            db_path.to_owned(),
+            MerkleTreeMode::Lightweight,
+            500,
+            0,
+            None,
+            false,
+            0,
+        )
+        .await;
+        for batch_logs in logs {
+            tree.process_l1_batch(batch_logs).await;
+        }
+        tree.save().await;
+        tree
+    }
+
+    #[test]
+    fn failing_object_store_requests_affect_tree_health() {
+        let thresholds = TreeHealthThresholds {
+            affected_lag: None,
+            not_ready_lag: Some(10),
+        };
+        let object_store = UploadHealthDetails {
+            consecutive_failures: 3,
+            is_failing: true,
+            last_error: None,
+        };
+        let details = TreeHealthCheckDetails::new(
+            MerkleTreeMode::Full,
+            true,
+            L1BatchNumber(5),
+            L1BatchNumber(4),
+            Some(object_store.clone()),
+        );
+        let health = details.into_health(thresholds, TreeHealthDetailsFormat::Json);
+        assert_eq!(health.status(), HealthStatus::Affected);
+
+        // The lag-based status takes precedence if it's worse.
+        let details = TreeHealthCheckDetails::new(
+            MerkleTreeMode::Full,
+            true,
+            L1BatchNumber(5),
+            L1BatchNumber(20),
+            Some(object_store),
+        );
+        let health = details.into_health(thresholds, TreeHealthDetailsFormat::Json);
+        assert_eq!(health.status(), HealthStatus::NotReady);
+
+        let details = TreeHealthCheckDetails::new(
+            MerkleTreeMode::Full,
+            true,
+            L1BatchNumber(5),
+            L1BatchNumber(4),
+            Some(UploadHealthDetails::default()),
+        );
+        let health = details.into_health(thresholds, TreeHealthDetailsFormat::Json);
+        assert_eq!(health.status(), HealthStatus::Ready);
+    }
+
+    #[test]
+    fn serializing_tree_health_details() {
+        let details = || {
+            let object_store = UploadHealthDetails {
+                consecutive_failures: 3,
+                is_failing: true,
+                last_error: None,
+            };
+            TreeHealthCheckDetails::new(
+                MerkleTreeMode::Full,
+                true,
+                L1BatchNumber(5),
+                L1BatchNumber(6),
+                Some(object_store),
+            )
+            .with_genesis_root_hash(Some(H256::repeat_byte(1)))
+            .with_recent_revert(Some((L1BatchNumber(3), Instant::now())))
+        };
+        let thresholds = TreeHealthThresholds::default();
+
+        let health = details().into_health(thresholds, TreeHealthDetailsFormat::Json);
+        assert_eq!(health.status(), HealthStatus::Affected);
+        let health = serde_json::to_value(health).unwrap();
+        let json_details = &health["details"];
+        assert_eq!(json_details["mode"], "full");
+        assert_eq!(json_details["next_l1_batch_to_seal"], 5);
+        assert_eq!(json_details["generate_witness_inputs"], true);
+        assert_eq!(json_details["l1_batch_lag"], 2);
+        assert_eq!(json_details["object_store"]["consecutive_failures"], 3);
+        assert_eq!(json_details["object_store"]["is_failing"], true);
+        assert!(json_details["object_store"]["last_error"].is_null());
+        assert_eq!(
+            json_details["genesis_root_hash"],
+            serde_json::to_value(H256::repeat_byte(1)).unwrap()
+        );
+        assert_eq!(json_details["recently_reverted_to"], 3);
+
+        let health = details().into_health(thresholds, TreeHealthDetailsFormat::Flat);
+        assert_eq!(health.status(), HealthStatus::Affected);
+        let health = serde_json::to_value(health).unwrap();
+        let flat_details = health["details"].as_object().unwrap();
+        assert_eq!(flat_details["mode"], "full");
+        assert_eq!(flat_details["next_l1_batch_to_seal"], 5);
+        assert_eq!(flat_details["generate_witness_inputs"], 1);
+        assert_eq!(flat_details["l1_batch_lag"], 2);
+        assert_eq!(flat_details["object_store_consecutive_failures"], 3);
+        assert_eq!(flat_details["object_store_is_failing"], 1);
+        assert_eq!(
+            flat_details["genesis_root_hash"],
+            json_details["genesis_root_hash"]
+        );
+        assert_eq!(flat_details["recently_reverted_to"], 3);
+        assert!(!flat_details.contains_key("object_store"));
+        assert!(!flat_details.contains_key("object_store_last_error"));
+        assert!(flat_details
+            .values()
+            .all(|value| !value.is_object() && !value.is_array() && !value.is_boolean()));
+    }
 
-        let touched_slots_latency = LoadChangesStage::TouchedSlots.start();
-        let mut touched_slots = storage
-            .storage_logs_dal()
-            .get_touched_slots_for_l1_batch(l1_batch_number)
-            .await;
-        touched_slots_latency.report_with_count(touched_slots.len());
+    #[test]
+    fn recent_revert_is_reported_only_within_grace_period() {
+        let details = |reverted_at| {
+            TreeHealthCheckDetails::new(
+                MerkleTreeMode::Lightweight,
+                false,
+                L1BatchNumber(3),
+                L1BatchNumber(2),
+                None,
+            )
+            .with_recent_revert(Some((L1BatchNumber(2), reverted_at)))
+        };
+
+        let recent = details(Instant::now());
+        assert_eq!(recent.recently_reverted_to, Some(L1BatchNumber(2)));
+        let Some(long_ago) = Instant::now().checked_sub(REVERT_HEALTH_GRACE_PERIOD * 2) else {
+            return; // The monotonic clock started too recently
+        };
+        assert_eq!(details(long_ago).recently_reverted_to, None);
+    }
 
-        let mut storage_logs = BTreeMap::new();
-        for storage_key in protective_reads {
-            touched_slots.remove(&storage_key);
-            // ^ As per deduplication rules, all keys in `protective_reads` haven't *really* changed
-            // in the considered L1 batch. Thus, we can remove them from `touched_slots` in order to simplify
-            // their further processing.
+    #[tokio::test]
+    async fn audit_log_records_reverts_and_pruning() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let db_path = temp_dir.path().join("tree");
+        let logs = gen_storage_logs(100..200, 5);
+        let mut tree = create_tree_with_logs(&db_path, logs).await;
+        assert!(tree.audit_log().unwrap().is_empty());
+        let root_hashes: Vec<_> = (0..5)
+            .map(|number| tree.root_hash_at(L1BatchNumber(number)).unwrap())
+            .collect();
 
-            let log = StorageLog::new_read_log(storage_key, H256::zero());
-            // ^ The tree doesn't use the read value, so we set it to zero.
-            storage_logs.insert(storage_key, log);
-        }
-        tracing::debug!(
-            "Made touched slots disjoint with protective reads; remaining touched slots: {}",
-            touched_slots.len()
+        tree.revert_and_save(L1BatchNumber(3)).await;
+        let pruned_key_count = tree.prune_up_to(L1BatchNumber(2)).await;
+        assert!(pruned_key_count > 0);
+        drop(tree);
+
+        // The audit log must persist across tree restarts, and must be stored outside the RocksDB directory,
+        // so that it's not lost if the directory is wiped (e.g., when the tree is rebuilt after corruption).
+        fs::remove_dir_all(&db_path).unwrap();
+        let tree = create_tree_with_logs(&db_path, vec![]).await;
+        let audit_log = tree.audit_log().unwrap();
+        assert_eq!(audit_log.len(), 2, "{audit_log:?}");
+        assert_eq!(
+            audit_log[0].operation,
+            AuditedOperation::Revert {
+                last_l1_batch_to_keep: L1BatchNumber(3)
+            }
         );
+        assert_eq!(audit_log[0].root_hash, root_hashes[3]);
+        assert_eq!(
+            audit_log[1].operation,
+            AuditedOperation::Prune {
+                first_l1_batch_to_keep: L1BatchNumber(2),
+                pruned_key_count,
+            }
+        );
+        assert_eq!(audit_log[1].root_hash, root_hashes[3]);
+        assert!(audit_log[0].timestamp_ms <= audit_log[1].timestamp_ms);
+    }
 
-        // We don't want to update the tree with zero values which were never written to per storage log
-        // deduplication rules. If we write such values to the tree, it'd result in bogus tree hashes because
-        // new (bogus) leaf indices would be allocated for them. To filter out those values, it's sufficient
-        // to check when a `storage_key` was first written per `initial_writes` table. If this never occurred
-        // or occurred after the considered `l1_batch_number`, this means that the write must be ignored.
-        //
-        // Note that this approach doesn't filter out no-op writes of the same value, but this is fine;
-        // since no new leaf indices are allocated in the tree for them, such writes are no-op on the tree side as well.
-        let hashed_keys_for_zero_values: Vec<_> = touched_slots
-            .iter()
-            .filter_map(|(key, value)| {
-                // Only zero values are worth checking for initial writes; non-zero values are always
-                // written per deduplication rules.
-                value.is_zero().then(|| key.hashed_key())
-            })
+    #[tokio::test]
+    async fn defragmenting_fragmented_tree() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let db_path = temp_dir.path().join("tree");
+        let keys: Vec<_> = gen_storage_logs(0..1_000, 1)
+            .pop()
+            .unwrap()
+            .into_iter()
+            .map(|log| log.key)
             .collect();
-        metrics::histogram!(
-            "server.metadata_calculator.load_changes.zero_values",
-            hashed_keys_for_zero_values.len() as f64
+        // Fragment the tree by overwriting random keys in many L1 batches.
+        let mut rng = StdRng::seed_from_u64(123);
+        let logs = (0..30).map(|_| {
+            (0..100)
+                .map(|_| {
+                    let key = keys[rng.gen_range(0..keys.len())];
+                    StorageLog::new_write_log(key, H256::from_low_u64_be(rng.gen()))
+                })
+                .collect()
+        });
+        let mut tree = create_tree_with_logs(&db_path, logs.collect()).await;
+        let root_hash = tree.root_hash();
+        let next_l1_batch_number = tree.next_l1_batch_number();
+        let fingerprint = tree.state_fingerprint().await;
+        let size_before = dir_size(&db_path).unwrap();
+
+        let copied_node_count = tree.defragment().await.unwrap();
+        assert!(copied_node_count > 0);
+        assert_eq!(tree.root_hash(), root_hash);
+        assert_eq!(tree.next_l1_batch_number(), next_l1_batch_number);
+        assert_eq!(tree.state_fingerprint().await, fingerprint);
+        assert_eq!(tree.root_hash_at(L1BatchNumber(20)), None);
+        let size_after = dir_size(&db_path).unwrap();
+        assert!(
+            size_after < size_before,
+            "size_before={size_before}, size_after={size_after}"
         );
+        assert!(!AsyncTree::<H256>::sibling_path(&db_path, "defrag").exists());
+        assert!(!AsyncTree::<H256>::sibling_path(&db_path, "old").exists());
 
-        let latency = LoadChangesStage::InitialWritesForZeroValues.start();
-        let l1_batches_for_initial_writes = storage
-            .storage_logs_dal()
-            .get_l1_batches_for_initial_writes(&hashed_keys_for_zero_values)
-            .await;
-        latency.report_with_count(hashed_keys_for_zero_values.len());
+        let audit_log = tree.audit_log().unwrap();
+        assert_eq!(
+            audit_log.last().unwrap().operation,
+            AuditedOperation::Defragment { copied_node_count }
+        );
 
-        for (storage_key, value) in touched_slots {
-            let write_matters = if value.is_zero() {
-                let initial_write_batch_for_key =
-                    l1_batches_for_initial_writes.get(&storage_key.hashed_key());
-                initial_write_batch_for_key.map_or(false, |&number| number <= l1_batch_number)
-            } else {
-                true
-            };
+        // The defragmented tree must be usable for further updates.
+        let new_logs = vec![StorageLog::new_write_log(keys[0], H256::repeat_byte(1))];
+        tree.process_l1_batch(new_logs).await;
+        tree.save().await;
+        assert_eq!(tree.next_l1_batch_number(), next_l1_batch_number + 1);
+        drop(tree);
 
-            if write_matters {
-                storage_logs.insert(storage_key, StorageLog::new_write_log(storage_key, value));
-            }
+        let tree = create_tree_with_logs(&db_path, vec![]).await;
+        assert_eq!(tree.next_l1_batch_number(), next_l1_batch_number + 1);
+        assert_eq!(tree.audit_log().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn defragmentation_is_refused_with_live_readers() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let db_path = temp_dir.path().join("tree");
+        let logs = gen_storage_logs(100..200, 3);
+        let mut tree = create_tree_with_logs(&db_path, logs).await;
+        let root_hash = tree.root_hash();
+        let reader = tree.reader();
+        let reader_clone = reader.clone();
+
+        let err = tree.defragment().await.unwrap_err().to_string();
+        assert!(err.contains("2 live reader(s)"), "{err}");
+        assert!(tree.audit_log().unwrap().is_empty());
+        assert!(!AsyncTree::<H256>::sibling_path(&db_path, "defrag").exists());
+        assert_eq!(reader.next_l1_batch_number(), L1BatchNumber(3));
+
+        drop((reader, reader_clone));
+        tree.defragment().await.unwrap();
+        assert_eq!(tree.root_hash(), root_hash);
+        let reader = tree.reader();
+        assert_eq!(reader.next_l1_batch_number(), L1BatchNumber(3));
+    }
+
+    #[tokio::test]
+    async fn tree_reports_its_mode() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        for mode in [MerkleTreeMode::Full, MerkleTreeMode::Lightweight] {
+            let db_path = temp_dir.path().join(format!("{mode:?}"));
+            let tree = AsyncTree::new(db_path, mode, 500, 0, None, false, 0).await;
+            assert_eq!(tree.mode(), mode);
         }
+    }
 
-        load_changes_latency.report();
-        Some(Self {
-            header,
-            storage_logs: storage_logs.into_values().collect(),
-        })
+    #[tokio::test]
+    async fn tree_with_rate_limited_background_io() {
+        const RATE_LIMIT: usize = 16 << 20; // 16 MiB/s
+
+        let logs = gen_storage_logs(0..5_000, 20);
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let db_path = temp_dir.path().join("limited");
+        let mut tree = AsyncTree::new(
+            db_path.clone(),
+            MerkleTreeMode::Full,
+            500,
+            0,
+            Some(RATE_LIMIT),
+            false,
+            0,
+        )
+        .await;
+        for batch_logs in logs.clone() {
+            tree.process_l1_batch(batch_logs).await;
+            tree.save().await;
+        }
+        let root_hash = tree.root_hash();
+        drop(tree);
+
+        let reference_tree = create_tree_with_logs(&temp_dir.path().join("reference"), logs).await;
+        assert_eq!(reference_tree.root_hash(), root_hash);
+
+        let tree = AsyncTree::new(
+            db_path,
+            MerkleTreeMode::Full,
+            500,
+            0,
+            Some(RATE_LIMIT),
+            false,
+            0,
+        )
+        .await;
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(20));
+        assert_eq!(tree.root_hash(), root_hash);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use tempfile::TempDir;
+    #[tokio::test]
+    async fn saving_tree_with_cursor() {
+        const CURSOR_KEY: &[u8] = b"consumer";
 
-    use db_test_macro::db_test;
-    use zksync_contracts::BaseSystemContracts;
-    use zksync_dal::ConnectionPool;
-    use zksync_types::{
-        proofs::PrepareBasicCircuitsJob, protocol_version::L1VerifierConfig,
-        system_contracts::get_system_smart_contracts, Address, L2ChainId, ProtocolVersionId,
-        StorageKey, StorageLogKind,
-    };
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let mut logs = gen_storage_logs(100..200, 2).into_iter();
+        let mut tree = create_tree_with_logs(temp_dir.path(), vec![logs.next().unwrap()]).await;
+        assert_eq!(
+            tree.cursor(MerkleTreeColumnFamily::Cursors, CURSOR_KEY),
+            None
+        );
 
-    use super::*;
-    use crate::{
-        genesis::{ensure_genesis_state, GenesisParams},
-        metadata_calculator::tests::{extend_db_state, gen_storage_logs, reset_db_state},
-    };
+        tree.process_l1_batch(logs.next().unwrap()).await;
+        let root_hash = tree.root_hash();
+        tree.save_with_cursor(
+            MerkleTreeColumnFamily::Cursors,
+            CURSOR_KEY.to_vec(),
+            1_u32.to_be_bytes().to_vec(),
+        )
+        .await;
+        drop(tree);
+
+        let tree = AsyncTree::new(
+            temp_dir.path().to_owned(),
+            MerkleTreeMode::Lightweight,
+            500,
+            0,
+            None,
+            false,
+            0,
+        )
+        .await;
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(2));
+        assert_eq!(tree.root_hash(), root_hash);
+        let cursor = tree.cursor(MerkleTreeColumnFamily::Cursors, CURSOR_KEY);
+        assert_eq!(cursor, Some(1_u32.to_be_bytes().to_vec()));
+    }
 
-    impl L1BatchWithLogs {
-        /// Old, slower method of loading storage logs. We want to test its equivalence to the new implementation.
-        async fn slow(
-            storage: &mut StorageProcessor<'_>,
-            l1_batch_number: L1BatchNumber,
-        ) -> Option<Self> {
-            let header = storage
-                .blocks_dal()
-                .get_l1_batch_header(l1_batch_number)
-                .await
-                .unwrap()?;
-            let protective_reads = storage
-                .storage_logs_dedup_dal()
-                .get_protective_reads_for_l1_batch(l1_batch_number)
-                .await;
-            let touched_slots = storage
-                .storage_logs_dal()
-                .get_touched_slots_for_l1_batch(l1_batch_number)
-                .await;
+    #[tokio::test]
+    async fn state_fingerprints_for_trees() {
+        let logs = gen_storage_logs(100..200, 3);
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let mut tree = create_tree_with_logs(&temp_dir.path().join("tree"), logs.clone()).await;
+        let mut same_tree =
+            create_tree_with_logs(&temp_dir.path().join("same_tree"), logs.clone()).await;
+        let fingerprint = tree.state_fingerprint().await;
+        assert_eq!(same_tree.state_fingerprint().await, fingerprint);
+
+        let mut other_logs = logs;
+        other_logs[1][0].value = H256::repeat_byte(0xff);
+        let mut other_tree =
+            create_tree_with_logs(&temp_dir.path().join("other_tree"), other_logs).await;
+        assert_ne!(other_tree.state_fingerprint().await, fingerprint);
+    }
 
-            let mut storage_logs = BTreeMap::new();
+    #[tokio::test]
+    async fn estimating_new_leaves() {
+        let logs = gen_storage_logs(100..200, 2);
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let mut tree = create_tree_with_logs(temp_dir.path(), logs[..1].to_vec()).await;
+        let root_hash = tree.root_hash();
 
-            let hashed_keys: Vec<_> = protective_reads
-                .iter()
-                .chain(touched_slots.keys())
-                .map(StorageKey::hashed_key)
-                .collect();
-            let previous_values = storage
-                .storage_logs_dal()
-                .get_previous_storage_values(&hashed_keys, l1_batch_number)
-                .await;
+        let new_leaf_count = tree.estimate_new_leaves(logs[1].clone()).await;
+        assert_eq!(new_leaf_count, logs[1].len() as u64);
+        let repeated_logs: Vec<_> = logs[0]
+            .iter()
+            .map(|log| StorageLog::new_write_log(log.key, H256::repeat_byte(0xff)))
+            .collect();
+        let new_leaf_count = tree.estimate_new_leaves(repeated_logs).await;
+        assert_eq!(new_leaf_count, 0);
 
-            for storage_key in protective_reads {
-                let previous_value = previous_values[&storage_key.hashed_key()].unwrap_or_default();
-                // Sanity check: value must not change for slots that require protective reads.
-                if let Some(value) = touched_slots.get(&storage_key) {
-                    assert_eq!(
-                        previous_value, *value,
-                        "Value was changed for slot that requires protective read"
-                    );
-                }
+        // Check that the tree is not modified.
+        assert_eq!(tree.root_hash(), root_hash);
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(1));
+    }
 
-                storage_logs.insert(
-                    storage_key,
-                    StorageLog::new_read_log(storage_key, previous_value),
-                );
-            }
+    /// Trivial alternative codec encoding `u64` values into 8 bytes.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct U64Value(u64);
 
-            for (storage_key, value) in touched_slots {
-                let previous_value = previous_values[&storage_key.hashed_key()].unwrap_or_default();
-                if previous_value != value {
-                    storage_logs.insert(storage_key, StorageLog::new_write_log(storage_key, value));
-                }
-            }
+    impl LeafValue for U64Value {
+        fn encode(&self) -> Vec<u8> {
+            self.0.to_be_bytes().to_vec()
+        }
 
-            Some(Self {
-                header,
-                storage_logs: storage_logs.into_values().collect(),
-            })
+        fn decode(value_hash: H256) -> Option<Self> {
+            let (high_bytes, low_bytes) = value_hash.as_bytes().split_at(24);
+            if high_bytes.iter().any(|&byte| byte != 0) {
+                return None;
+            }
+            Some(Self(u64::from_be_bytes(low_bytes.try_into().unwrap())))
         }
     }
 
-    fn mock_genesis_params() -> GenesisParams {
-        GenesisParams {
-            first_validator: Address::repeat_byte(0x01),
-            protocol_version: ProtocolVersionId::latest(),
-            base_system_contracts: BaseSystemContracts::load_from_disk(),
-            system_contracts: get_system_smart_contracts(),
-            first_l1_verifier_config: L1VerifierConfig::default(),
-            first_verifier_address: Address::zero(),
+    /// Codec with encodings longer than value hashes, so that values cannot be read back from the tree.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct LongValue(u64);
+
+    impl LeafValue for LongValue {
+        fn encode(&self) -> Vec<u8> {
+            [self.0.to_be_bytes(); 5].concat()
+        }
+
+        fn decode(_value_hash: H256) -> Option<Self> {
+            None
         }
     }
 
-    #[db_test]
-    async fn loaded_logs_equivalence_basics(pool: ConnectionPool) {
-        ensure_genesis_state(
-            &mut pool.access_storage().await.unwrap(),
-            L2ChainId::from(270),
-            &mock_genesis_params(),
+    async fn test_leaf_value_codec<V>(db_path: &Path, value: impl Fn(u64) -> V) -> H256
+    where
+        V: LeafValue + PartialEq,
+    {
+        let keys: Vec<_> = gen_storage_logs(100..120, 1)[0]
+            .iter()
+            .map(|log| log.key)
+            .collect();
+        let writes = keys
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| StorageLog::new_write_log(key, value(i as u64 + 1).value_hash()))
+            .collect();
+        let mut tree = AsyncTree::<V>::with_value_codec(
+            db_path.to_owned(),
+            MerkleTreeMode::Lightweight,
+            500,
+            0,
+            None,
+            false,
+            0,
         )
-        .await
-        .unwrap();
-        reset_db_state(&pool, 5).await;
+        .await;
+        let metadata = tree.process_l1_batch(writes).await;
+        assert_eq!(metadata.initial_writes.len(), keys.len());
+
+        let hashed_keys = keys.iter().map(StorageKey::hashed_key_u256).collect();
+        let values = tree.values(L1BatchNumber(0), hashed_keys).await.unwrap();
+        let expected_values: Vec<_> = (1..=keys.len() as u64)
+            .map(|i| V::decode(value(i).value_hash()))
+            .collect();
+        assert_eq!(values, expected_values);
+
+        let missing_key = U256::from(u64::MAX);
+        let values = tree.values(L1BatchNumber(0), vec![missing_key]).await;
+        assert_eq!(values.unwrap(), [None]);
+        tree.root_hash()
+    }
+
+    #[tokio::test]
+    async fn processing_values_with_codecs() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let root_hash =
+            test_leaf_value_codec(&temp_dir.path().join("h256"), H256::from_low_u64_be).await;
+        let other_root_hash = test_leaf_value_codec(&temp_dir.path().join("u64"), U64Value).await;
+        // Both codecs produce the same value hashes, so the resulting trees must match.
+        assert_eq!(root_hash, other_root_hash);
+        assert_eq!(U64Value::decode(H256::repeat_byte(0xff)), None);
+
+        let long_root_hash = test_leaf_value_codec(&temp_dir.path().join("long"), LongValue).await;
+        assert_ne!(long_root_hash, root_hash);
+    }
+
+    #[test]
+    fn long_leaf_value_encodings_are_hashed() {
+        use zksync_crypto::hasher::{blake2::Blake2Hasher, Hasher};
+
+        let value = LongValue(1);
+        assert_eq!(value.encode().len(), 40);
+        assert_eq!(value.value_hash(), Blake2Hasher.hash_bytes(&value.encode()));
+        assert_eq!(U64Value(1).value_hash(), H256::from_low_u64_be(1));
+    }
 
+    #[db_test]
+    async fn processing_logs_on_base_version(pool: ConnectionPool) {
         let mut storage = pool.access_storage().await.unwrap();
-        for l1_batch_number in 0..=5 {
-            let l1_batch_number = L1BatchNumber(l1_batch_number);
-            let batch_with_logs = L1BatchWithLogs::new(&mut storage, l1_batch_number)
-                .await
-                .unwrap();
-            let slow_batch_with_logs = L1BatchWithLogs::slow(&mut storage, l1_batch_number)
-                .await
-                .unwrap();
-            assert_eq!(batch_with_logs, slow_batch_with_logs);
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+        extend_db_state(&mut storage, gen_storage_logs(100..200, 3)).await;
+
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let mut tree = AsyncTree::new(
+            temp_dir.path().to_owned(),
+            MerkleTreeMode::Full,
+            500,
+            0,
+            None,
+            false,
+            0,
+        )
+        .await;
+        let mut batches = vec![];
+        let mut root_hashes = vec![];
+        for number in 0..=3 {
+            let l1_batch = L1BatchWithLogs::new(
+                &mut storage,
+                L1BatchNumber(number),
+                MerkleTreeMode::Full,
+                DalConsistencyPolicy::FailFast,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            let metadata = tree.process_l1_batch(l1_batch.storage_logs.clone()).await;
+            root_hashes.push(metadata.root_hash);
+            batches.push(l1_batch);
+            tree.save().await;
         }
+
+        let logs = batches[3].storage_logs.clone();
+        let metadata_on_2 = tree
+            .process_on_base(L1BatchNumber(2), logs.clone())
+            .await
+            .unwrap();
+        // Processing logs on top of their actual base must reproduce the original root hash.
+        assert_eq!(metadata_on_2.root_hash, root_hashes[3]);
+
+        let metadata_on_1 = tree
+            .process_on_base(L1BatchNumber(1), logs.clone())
+            .await
+            .unwrap();
+        assert_ne!(metadata_on_1.root_hash, metadata_on_2.root_hash);
+        // L1 batch #2 inserts new leaves, so the tree after L1 batch #1 has fewer leaves.
+        assert!(metadata_on_1.rollup_last_leaf_index < metadata_on_2.rollup_last_leaf_index);
+
+        // The live tree must not be affected.
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(4));
+        assert_eq!(tree.root_hash(), root_hashes[3]);
+        assert_eq!(tree.root_hash_at(L1BatchNumber(2)), Some(root_hashes[2]));
+
+        let err = tree
+            .process_on_base(L1BatchNumber(4), logs)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains('4'), "{err}");
     }
 
     #[db_test]
     async fn loaded_logs_equivalence_with_zero_no_op_logs(pool: ConnectionPool) {
         let mut storage = pool.access_storage().await.unwrap();
-        ensure_genesis_state(&mut storage, L2ChainId::from(270), &mock_genesis_params())
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
             .await
             .unwrap();
 
@@ -403,21 +2275,98 @@ mod tests {
         extend_db_state(&mut storage, logs).await;
 
         let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
-        let mut tree =
-            AsyncTree::new(temp_dir.path().to_owned(), MerkleTreeMode::Full, 500, 0).await;
+        let mut tree = AsyncTree::new(
+            temp_dir.path().to_owned(),
+            MerkleTreeMode::Full,
+            500,
+            0,
+            None,
+            false,
+            0,
+        )
+        .await;
         for number in 0..3 {
             assert_log_equivalence(&mut storage, &mut tree, L1BatchNumber(number)).await;
         }
     }
 
+    #[db_test]
+    async fn chunked_zero_value_filtering(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+
+        let mut logs = gen_storage_logs(100..500, 3);
+        // Zero writes to new slots in the first L1 batch are never written; ones in later batches
+        // alternate between zeroing previously written slots and no-op writes to new slots.
+        for log in logs[0].iter_mut().step_by(2) {
+            log.value = H256::zero();
+        }
+        let zeroed_keys: Vec<_> = logs[0]
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|log| log.key)
+            .collect();
+        for (log, key) in logs[1].iter_mut().zip(zeroed_keys) {
+            *log = StorageLog::new_write_log(key, H256::zero());
+        }
+        for log in logs[2].iter_mut().step_by(3) {
+            log.value = H256::zero();
+        }
+        extend_db_state(&mut storage, logs).await;
+
+        for number in 1..4 {
+            let header = storage
+                .blocks_dal()
+                .get_l1_batch_header(L1BatchNumber(number))
+                .await
+                .unwrap()
+                .unwrap();
+            let unchunked = L1BatchWithLogs::with_zero_values_chunk_size(
+                &mut storage,
+                header.clone(),
+                ProtectiveReads::default(),
+                usize::MAX,
+            )
+            .await;
+            assert!(!unchunked.storage_logs.is_empty());
+            if number == 2 {
+                // Sanity check: zeroed slots written in the previous L1 batch must be retained.
+                assert!(unchunked.storage_logs.iter().any(|log| log.value.is_zero()));
+            }
+
+            for chunk_size in [1, 7, 64] {
+                let chunked = L1BatchWithLogs::with_zero_values_chunk_size(
+                    &mut storage,
+                    header.clone(),
+                    ProtectiveReads::default(),
+                    chunk_size,
+                )
+                .await;
+                assert_eq!(
+                    chunked.storage_logs, unchunked.storage_logs,
+                    "L1 batch #{number}, chunk size {chunk_size}"
+                );
+            }
+        }
+    }
+
     async fn assert_log_equivalence(
         storage: &mut StorageProcessor<'_>,
         tree: &mut AsyncTree,
         l1_batch_number: L1BatchNumber,
     ) {
-        let l1_batch_with_logs = L1BatchWithLogs::new(storage, l1_batch_number)
-            .await
-            .unwrap();
+        let l1_batch_with_logs = L1BatchWithLogs::new(
+            storage,
+            l1_batch_number,
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap()
+        .unwrap();
         let slow_l1_batch_with_logs = L1BatchWithLogs::slow(storage, l1_batch_number)
             .await
             .unwrap();
@@ -467,7 +2416,7 @@ mod tests {
     #[db_test]
     async fn loaded_logs_equivalence_with_non_zero_no_op_logs(pool: ConnectionPool) {
         let mut storage = pool.access_storage().await.unwrap();
-        ensure_genesis_state(&mut storage, L2ChainId::from(270), &mock_genesis_params())
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
             .await
             .unwrap();
 
@@ -504,17 +2453,130 @@ mod tests {
         extend_db_state(&mut storage, logs).await;
 
         let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
-        let mut tree =
-            AsyncTree::new(temp_dir.path().to_owned(), MerkleTreeMode::Full, 500, 0).await;
+        let mut tree = AsyncTree::new(
+            temp_dir.path().to_owned(),
+            MerkleTreeMode::Full,
+            500,
+            0,
+            None,
+            false,
+            0,
+        )
+        .await;
         for batch_number in 0..5 {
             assert_log_equivalence(&mut storage, &mut tree, L1BatchNumber(batch_number)).await;
         }
     }
 
+    #[db_test]
+    async fn loaded_logs_are_sorted_by_key(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+
+        // Insert logs in the order not matching the `StorageKey` order.
+        let mut logs = gen_storage_logs(100..140, 1);
+        logs[0].reverse();
+        let read_logs: Vec<_> = logs[0]
+            .iter()
+            .skip(1)
+            .step_by(4)
+            .map(|log| StorageLog::new_read_log(log.key, log.value))
+            .collect();
+        let read_keys: HashSet<_> = read_logs.iter().map(|log| log.key).collect();
+        logs[0].retain(|log| !read_keys.contains(&log.key));
+        extend_db_state(&mut storage, logs).await;
+        let read_logs: Vec<_> = read_logs
+            .iter()
+            .map(StorageLog::to_test_log_query)
+            .collect();
+        storage
+            .storage_logs_dedup_dal()
+            .insert_protective_reads(L1BatchNumber(1), &read_logs)
+            .await;
+
+        let l1_batch_with_logs = L1BatchWithLogs::new(
+            &mut storage,
+            L1BatchNumber(1),
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let logs = &l1_batch_with_logs.storage_logs;
+        let read_logs_count = logs
+            .iter()
+            .filter(|log| log.kind == StorageLogKind::Read)
+            .count();
+        assert_eq!(read_logs_count, read_keys.len());
+        assert!(logs.iter().any(|log| log.kind == StorageLogKind::Write));
+        assert!(
+            logs.windows(2).all(|pair| pair[0].key < pair[1].key),
+            "{logs:#?}"
+        );
+    }
+
+    #[db_test]
+    async fn excessive_protective_reads_are_reported(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+
+        let logs = gen_storage_logs(100..300, 1).pop().unwrap();
+        let write_log = StorageLog::new_write_log(logs[0].key, H256::repeat_byte(0xff));
+        let read_logs: Vec<_> = logs[1..]
+            .iter()
+            .map(StorageLog::to_test_log_query)
+            .collect();
+        extend_db_state(&mut storage, [logs, vec![write_log]]).await;
+        storage
+            .storage_logs_dedup_dal()
+            .insert_protective_reads(L1BatchNumber(2), &read_logs)
+            .await;
+
+        let warnings_before = METRICS.excessive_protective_reads.get();
+        let l1_batch_with_logs = L1BatchWithLogs::new(
+            &mut storage,
+            L1BatchNumber(2),
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(l1_batch_with_logs.storage_logs.len(), 200);
+
+        let thresholds = ProtectiveReadsThresholds {
+            max_ratio: 100.0,
+            min_reads: 100,
+        };
+        assert!(l1_batch_with_logs.check_protective_reads_ratio(&thresholds));
+        // Other tests may report excessive protective reads concurrently, so we don't check the exact value.
+        assert!(METRICS.excessive_protective_reads.get() > warnings_before);
+
+        // 199 protective reads to 1 write don't exceed relaxed thresholds.
+        let relaxed_thresholds = [
+            ProtectiveReadsThresholds {
+                max_ratio: 200.0,
+                ..thresholds
+            },
+            ProtectiveReadsThresholds {
+                min_reads: 200,
+                ..thresholds
+            },
+        ];
+        for thresholds in relaxed_thresholds {
+            assert!(!l1_batch_with_logs.check_protective_reads_ratio(&thresholds));
+        }
+    }
+
     #[db_test]
     async fn loaded_logs_equivalence_with_protective_reads(pool: ConnectionPool) {
         let mut storage = pool.access_storage().await.unwrap();
-        ensure_genesis_state(&mut storage, L2ChainId::from(270), &mock_genesis_params())
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
             .await
             .unwrap();
 
@@ -532,9 +2594,15 @@ mod tests {
             .insert_protective_reads(L1BatchNumber(2), &read_logs)
             .await;
 
-        let l1_batch_with_logs = L1BatchWithLogs::new(&mut storage, L1BatchNumber(2))
-            .await
-            .unwrap();
+        let l1_batch_with_logs = L1BatchWithLogs::new(
+            &mut storage,
+            L1BatchNumber(2),
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap()
+        .unwrap();
         // Check that we have protective reads transformed into read logs
         let read_logs_count = l1_batch_with_logs
             .storage_logs
@@ -543,9 +2611,59 @@ mod tests {
             .count();
         assert_eq!(read_logs_count, 7);
 
+        // In the lightweight mode, read logs are skipped, but the order of write logs must be retained.
+        let lightweight_l1_batch_with_logs = L1BatchWithLogs::new(
+            &mut storage,
+            L1BatchNumber(2),
+            MerkleTreeMode::Lightweight,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let write_logs: Vec<_> = l1_batch_with_logs
+            .storage_logs
+            .iter()
+            .filter(|log| log.kind == StorageLogKind::Write)
+            .copied()
+            .collect();
+        assert_eq!(lightweight_l1_batch_with_logs.storage_logs, write_logs);
+
+        // Loading with protective reads fetched for a range of L1 batches must yield the same result.
+        let protective_reads = storage
+            .storage_logs_dedup_dal()
+            .get_protective_reads_for_l1_batch_range(L1BatchNumber(1)..=L1BatchNumber(2))
+            .await;
+        assert!(!protective_reads.contains_key(&L1BatchNumber(1)));
+        let protective_reads = &protective_reads[&L1BatchNumber(2)];
+        for (mode, expected) in [
+            (MerkleTreeMode::Full, &l1_batch_with_logs),
+            (MerkleTreeMode::Lightweight, &lightweight_l1_batch_with_logs),
+        ] {
+            let header = storage
+                .blocks_dal()
+                .get_l1_batch_header(L1BatchNumber(2))
+                .await
+                .unwrap()
+                .unwrap();
+            let protective_reads = ProtectiveReads::new(protective_reads.clone(), mode);
+            let loaded =
+                L1BatchWithLogs::with_protective_reads(&mut storage, header, protective_reads)
+                    .await;
+            assert_eq!(loaded, *expected);
+        }
+
         let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
-        let mut tree =
-            AsyncTree::new(temp_dir.path().to_owned(), MerkleTreeMode::Full, 500, 0).await;
+        let mut tree = AsyncTree::new(
+            temp_dir.path().to_owned(),
+            MerkleTreeMode::Full,
+            500,
+            0,
+            None,
+            false,
+            0,
+        )
+        .await;
         for batch_number in 0..3 {
             assert_log_equivalence(&mut storage, &mut tree, L1BatchNumber(batch_number)).await;
         }
@@ -0,0 +1,243 @@
+//! Optional tracking of the most frequently written storage keys, which is useful for performance analysis
+//! (e.g., tuning caches) and for detecting contract hotspots.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+use zksync_types::{L1BatchNumber, StorageLog, StorageLogKind, H256};
+
+/// Number of rows in the count-min sketch. Each row uses a separate 8-byte chunk of the hashed key
+/// as the hash value, so this must not exceed 4.
+const SKETCH_DEPTH: usize = 4;
+/// Number of counters in each count-min sketch row.
+const SKETCH_WIDTH: usize = 1 << 16;
+
+/// Count-min sketch estimating the number of writes for hashed keys using a fixed amount of memory.
+/// Estimates never undercount, and overcount by a small fraction of the total number of writes
+/// with high probability.
+#[derive(Debug)]
+struct CountMinSketch {
+    counters: Vec<u32>,
+}
+
+impl CountMinSketch {
+    fn new() -> Self {
+        Self {
+            counters: vec![0; SKETCH_DEPTH * SKETCH_WIDTH],
+        }
+    }
+
+    /// Hashed keys are outputs of a cryptographic hash function, so their chunks are already
+    /// uniformly distributed and can be used as independent hashes.
+    fn indices(key: &H256) -> impl Iterator<Item = usize> + '_ {
+        key.as_bytes()
+            .chunks_exact(8)
+            .enumerate()
+            .map(|(row, chunk)| {
+                let hash = u64::from_le_bytes(chunk.try_into().unwrap());
+                row * SKETCH_WIDTH + (hash % SKETCH_WIDTH as u64) as usize
+            })
+    }
+
+    /// Increments the counters for `key` and returns the updated estimate.
+    fn increment(&mut self, key: &H256) -> u64 {
+        let mut estimate = u32::MAX;
+        for idx in Self::indices(key) {
+            let counter = &mut self.counters[idx];
+            *counter = counter.saturating_add(1);
+            estimate = estimate.min(*counter);
+        }
+        estimate.into()
+    }
+
+    fn reset(&mut self) {
+        self.counters.fill(0);
+    }
+}
+
+/// Report on the most frequently written keys in a window of L1 batches.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct HotKeysReport {
+    pub l1_batch_numbers: (L1BatchNumber, L1BatchNumber),
+    /// Total number of writes in the window.
+    pub total_writes: u64,
+    /// Hashed keys together with the estimated number of writes, ordered by the decreasing number of writes.
+    pub keys: Vec<(H256, u64)>,
+}
+
+impl fmt::Display for HotKeysReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (first, last) = self.l1_batch_numbers;
+        write!(
+            formatter,
+            "top {} written keys for L1 batches #{first}..=#{last} ({} writes in total):",
+            self.keys.len(),
+            self.total_writes
+        )?;
+        for (key, count) in &self.keys {
+            write!(formatter, " {key:?} ({count})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Tracker of the `top_n` most frequently written hashed keys in tumbling windows of L1 batches.
+/// Memory usage is bounded regardless of the number of distinct keys: write counts are estimated
+/// using a count-min sketch, and only the current top keys are stored exactly.
+#[derive(Debug)]
+pub(super) struct HotKeysTracker {
+    top_n: usize,
+    window_len: u32,
+    sketch: CountMinSketch,
+    top_counts: HashMap<H256, u64>,
+    /// Top keys ordered by their counts; the first entry is evicted when a more frequent key is found.
+    top_ordered: BTreeSet<(u64, H256)>,
+    window_start: Option<L1BatchNumber>,
+    total_writes: u64,
+}
+
+impl HotKeysTracker {
+    pub fn new(top_n: usize, window_len: u32) -> Self {
+        assert!(top_n > 0, "Number of reported hot keys must be positive");
+        assert!(window_len > 0, "Hot keys window must be positive");
+        Self {
+            top_n,
+            window_len,
+            sketch: CountMinSketch::new(),
+            top_counts: HashMap::with_capacity(top_n),
+            top_ordered: BTreeSet::new(),
+            window_start: None,
+            total_writes: 0,
+        }
+    }
+
+    /// Records writes in `storage_logs` for the specified L1 batch. L1 batches must be observed in order.
+    /// Returns a report once the current window is complete.
+    pub fn observe(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+        storage_logs: &[StorageLog],
+    ) -> Option<HotKeysReport> {
+        let window_start = *self.window_start.get_or_insert(l1_batch_number);
+        let writes = storage_logs
+            .iter()
+            .filter(|log| log.kind == StorageLogKind::Write);
+        for log in writes {
+            self.record_write(log.key.hashed_key());
+        }
+
+        if l1_batch_number.0 + 1 - window_start.0 < self.window_len {
+            return None;
+        }
+        let report = HotKeysReport {
+            l1_batch_numbers: (window_start, l1_batch_number),
+            total_writes: self.total_writes,
+            keys: self
+                .top_ordered
+                .iter()
+                .rev()
+                .map(|&(count, key)| (key, count))
+                .collect(),
+        };
+        self.reset();
+        Some(report)
+    }
+
+    fn record_write(&mut self, key: H256) {
+        self.total_writes += 1;
+        let estimate = self.sketch.increment(&key);
+        if let Some(count) = self.top_counts.get_mut(&key) {
+            self.top_ordered.remove(&(*count, key));
+            *count = estimate;
+            self.top_ordered.insert((estimate, key));
+            return;
+        }
+
+        if self.top_counts.len() < self.top_n {
+            self.top_counts.insert(key, estimate);
+            self.top_ordered.insert((estimate, key));
+        } else {
+            let &(min_count, min_key) = self.top_ordered.first().unwrap();
+            if estimate > min_count {
+                self.top_ordered.pop_first();
+                self.top_counts.remove(&min_key);
+                self.top_counts.insert(key, estimate);
+                self.top_ordered.insert((estimate, key));
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sketch.reset();
+        self.top_counts.clear();
+        self.top_ordered.clear();
+        self.window_start = None;
+        self.total_writes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zksync_types::{AccountTreeId, Address, StorageKey};
+
+    use super::*;
+
+    fn write_log(address: u8, slot: u64) -> StorageLog {
+        let key = StorageKey::new(
+            AccountTreeId::new(Address::repeat_byte(address)),
+            H256::from_low_u64_be(slot),
+        );
+        StorageLog::new_write_log(key, H256::repeat_byte(1))
+    }
+
+    #[test]
+    fn frequently_written_key_is_reported() {
+        let hot_log = write_log(0xff, 0);
+        let hot_key = hot_log.key.hashed_key();
+        let mut tracker = HotKeysTracker::new(3, 5);
+
+        for number in 1..5 {
+            let mut logs: Vec<_> = (0..100).map(|slot| write_log(number as u8, slot)).collect();
+            logs.push(hot_log);
+            // Read logs must not be counted.
+            logs.push(StorageLog::new_read_log(
+                write_log(0xee, 0).key,
+                H256::zero(),
+            ));
+            assert_eq!(tracker.observe(L1BatchNumber(number), &logs), None);
+        }
+        let logs = [hot_log, write_log(0xaa, 0)];
+        let report = tracker.observe(L1BatchNumber(5), &logs).unwrap();
+
+        assert_eq!(
+            report.l1_batch_numbers,
+            (L1BatchNumber(1), L1BatchNumber(5))
+        );
+        assert_eq!(report.total_writes, 4 * 101 + 2);
+        assert_eq!(report.keys.len(), 3);
+        assert_eq!(report.keys[0], (hot_key, 5));
+        assert!(report.keys[1..].iter().all(|&(_, count)| count < 5));
+    }
+
+    #[test]
+    fn tracker_is_reset_after_report() {
+        let hot_log = write_log(0xff, 0);
+        let mut tracker = HotKeysTracker::new(1, 2);
+        assert_eq!(tracker.observe(L1BatchNumber(1), &[hot_log]), None);
+        let report = tracker.observe(L1BatchNumber(2), &[hot_log]).unwrap();
+        assert_eq!(report.keys, [(hot_log.key.hashed_key(), 2)]);
+
+        let other_log = write_log(0xaa, 0);
+        assert_eq!(tracker.observe(L1BatchNumber(3), &[other_log]), None);
+        let report = tracker.observe(L1BatchNumber(4), &[hot_log]).unwrap();
+        assert_eq!(
+            report.l1_batch_numbers,
+            (L1BatchNumber(3), L1BatchNumber(4))
+        );
+        assert_eq!(report.total_writes, 2);
+        assert_eq!(report.keys.len(), 1);
+        assert_eq!(report.keys[0].1, 1);
+    }
+}
@@ -0,0 +1,159 @@
+//! Reconciling enumeration indices in the `initial_writes` Postgres table with the Merkle tree.
+
+use anyhow::Context as _;
+
+use std::collections::HashMap;
+
+use zksync_dal::StorageProcessor;
+use zksync_merkle_tree::domain::ZkSyncTree;
+use zksync_types::{L1BatchNumber, H256};
+
+use super::helpers::hashed_key;
+
+/// Number of initial writes loaded from Postgres in a single query.
+const CHUNK_SIZE: usize = 10_000;
+/// Maximum number of unmatched keys mentioned in the error message.
+const MAX_REPORTED_KEYS: usize = 10;
+
+/// Initial write with the enumeration index overwritten by [`reconcile_initial_writes()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepairedInitialWrite {
+    /// Hashed storage key of the write.
+    pub hashed_key: H256,
+    /// L1 batch the write belongs to.
+    pub l1_batch_number: L1BatchNumber,
+    /// Enumeration index previously recorded in Postgres.
+    pub old_index: u64,
+    /// Enumeration index of the key in the Merkle tree.
+    pub new_index: u64,
+}
+
+/// Report produced by [`reconcile_initial_writes()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InitialWritesReconciliation {
+    /// Number of tree leaves checked against initial writes.
+    pub checked_count: u64,
+    /// Repaired initial writes ordered by the new enumeration index.
+    pub repaired: Vec<RepairedInitialWrite>,
+}
+
+/// Reconciles enumeration indices in the `initial_writes` table with leaf indices in the Merkle tree.
+/// Tree leaves are walked in the enumeration index order, and initial writes with indices differing
+/// from the tree are rewritten to match it.
+///
+/// Reconciliation must run while the node is stopped. The `tree` should be opened directly from
+/// the tree directory (RocksDB locking then ensures that the metadata calculator doesn't update the tree
+/// concurrently) and must have processed all sealed L1 batches. If the tree lags behind Postgres,
+/// or an L1 batch is sealed during reconciliation, an error is returned and Postgres is not modified.
+/// Likewise, an error is returned if the set of keys in `initial_writes` differs from the tree,
+/// since such a divergence cannot be repaired by rewriting indices.
+///
+/// # Performance
+///
+/// All tree leaves are loaded into RAM, so this method should only be used for repairs.
+pub async fn reconcile_initial_writes(
+    storage: &mut StorageProcessor<'_>,
+    tree: &ZkSyncTree,
+) -> anyhow::Result<InitialWritesReconciliation> {
+    let sealed_l1_batch_number = storage.blocks_dal().get_sealed_l1_batch_number().await?;
+    let next_l1_batch_number = tree.next_l1_batch_number();
+    anyhow::ensure!(
+        next_l1_batch_number == sealed_l1_batch_number + 1,
+        "Cannot reconcile initial writes: Merkle tree is not in sync with Postgres (next L1 batch \
+         of the tree: #{next_l1_batch_number}, last sealed L1 batch: #{sealed_l1_batch_number}). \
+         Reconciliation requires the node to be stopped after the tree has processed all sealed L1 batches"
+    );
+
+    tracing::info!(
+        "Loading Merkle tree leaves to reconcile initial writes up to L1 batch #{sealed_l1_batch_number}"
+    );
+    let leaves = tree.leaves();
+    let checked_count = leaves.len() as u64;
+    let mut tree_indices: HashMap<_, _> = leaves
+        .into_iter()
+        .map(|(key, index)| (hashed_key(key), index))
+        .collect();
+
+    let mut transaction = storage
+        .start_transaction()
+        .await
+        .context("failed starting Postgres transaction")?;
+    let mut repaired = vec![];
+    let mut unknown_keys = vec![];
+    let mut start_index = 0;
+    loop {
+        let chunk = transaction
+            .storage_logs_dedup_dal()
+            .initial_writes_chunk(start_index, CHUNK_SIZE)
+            .await;
+        let Some(&(_, last_index, _)) = chunk.last() else {
+            break;
+        };
+        start_index = last_index + 1;
+
+        for (hashed_key, old_index, l1_batch_number) in chunk {
+            match tree_indices.remove(&hashed_key) {
+                Some(new_index) if new_index != old_index => {
+                    repaired.push(RepairedInitialWrite {
+                        hashed_key,
+                        l1_batch_number,
+                        old_index,
+                        new_index,
+                    });
+                }
+                Some(_) => { /* Index is correct */ }
+                None => unknown_keys.push(hashed_key),
+            }
+        }
+    }
+
+    let mut missing_keys: Vec<_> = tree_indices.into_iter().collect();
+    missing_keys.sort_unstable_by_key(|&(_, index)| index);
+    anyhow::ensure!(
+        unknown_keys.is_empty() && missing_keys.is_empty(),
+        "Cannot reconcile initial writes: {} initial writes are missing in Merkle tree (e.g., {:?}), \
+         and {} tree keys are missing in `initial_writes` (e.g., {:?}). Postgres was not modified",
+        unknown_keys.len(),
+        &unknown_keys[..unknown_keys.len().min(MAX_REPORTED_KEYS)],
+        missing_keys.len(),
+        &missing_keys[..missing_keys.len().min(MAX_REPORTED_KEYS)]
+    );
+
+    repaired.sort_unstable_by_key(|write| write.new_index);
+    if !repaired.is_empty() {
+        for write in &repaired {
+            tracing::debug!("Repairing initial write: {write:?}");
+        }
+        let new_indices: Vec<_> = repaired
+            .iter()
+            .map(|write| (write.hashed_key, write.new_index))
+            .collect();
+        transaction
+            .storage_logs_dedup_dal()
+            .update_initial_writes_indices(&new_indices)
+            .await;
+    }
+
+    let new_sealed_l1_batch_number = transaction
+        .blocks_dal()
+        .get_sealed_l1_batch_number()
+        .await?;
+    anyhow::ensure!(
+        new_sealed_l1_batch_number == sealed_l1_batch_number,
+        "L1 batch #{new_sealed_l1_batch_number} was sealed during reconciliation of initial writes; \
+         the node must be stopped. Postgres was not modified"
+    );
+    transaction
+        .commit()
+        .await
+        .context("failed committing Postgres transaction")?;
+
+    tracing::info!(
+        "Reconciled initial writes with Merkle tree: checked {checked_count} keys, repaired {} initial writes",
+        repaired.len()
+    );
+    Ok(InitialWritesReconciliation {
+        checked_count,
+        repaired,
+    })
+}
@@ -0,0 +1,94 @@
+//! Test-only injection of artificial delays into the tree update loop and the witness stage. Allows tests to deterministically
+//! emulate a slow disk or a slow Postgres instance without real I/O stalls.
+
+use std::{collections::HashMap, time::Duration};
+
+use zksync_types::L1BatchNumber;
+
+/// Stage of the tree update loop into which a delay can be injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum DelayedStage {
+    /// Loading data for an L1 batch from Postgres.
+    Load,
+    /// Processing an L1 batch by the tree.
+    ProcessL1Batch,
+    /// Saving the tree to RocksDB. The delay is keyed by the last L1 batch included in the save.
+    Save,
+    /// Persisting a witness input by the witness stage worker.
+    PersistWitness,
+}
+
+/// Artificial delays injected into the tree update loop, keyed by the stage and the L1 batch number.
+#[derive(Debug, Clone, Default)]
+pub(super) struct InjectedDelays {
+    /// `None` L1 batch number means that the delay applies to all L1 batches.
+    delays: HashMap<(DelayedStage, Option<L1BatchNumber>), Duration>,
+}
+
+impl InjectedDelays {
+    /// Injects `delay` into `stage` for the specified L1 batch, or for all L1 batches
+    /// if `l1_batch_number` is `None`. A delay for a specific L1 batch takes precedence.
+    pub fn set(
+        &mut self,
+        stage: DelayedStage,
+        l1_batch_number: Option<L1BatchNumber>,
+        delay: Duration,
+    ) -> &mut Self {
+        self.delays.insert((stage, l1_batch_number), delay);
+        self
+    }
+
+    fn get(&self, stage: DelayedStage, l1_batch_number: L1BatchNumber) -> Option<Duration> {
+        let delay = self.delays.get(&(stage, Some(l1_batch_number)));
+        delay.or_else(|| self.delays.get(&(stage, None))).copied()
+    }
+
+    /// Sleeps for the delay injected into `stage` for the specified L1 batch, if any.
+    pub async fn inject(&self, stage: DelayedStage, l1_batch_number: L1BatchNumber) {
+        if let Some(delay) = self.get(stage, l1_batch_number) {
+            tracing::debug!(
+                "Injecting {delay:?} delay into {stage:?} stage for L1 batch #{l1_batch_number}"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specific_delays_take_precedence() {
+        let mut delays = InjectedDelays::default();
+        delays
+            .set(DelayedStage::Save, None, Duration::from_millis(10))
+            .set(
+                DelayedStage::Save,
+                Some(L1BatchNumber(3)),
+                Duration::from_millis(100),
+            )
+            .set(
+                DelayedStage::Load,
+                Some(L1BatchNumber(1)),
+                Duration::from_millis(5),
+            );
+
+        assert_eq!(
+            delays.get(DelayedStage::Save, L1BatchNumber(1)),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            delays.get(DelayedStage::Save, L1BatchNumber(3)),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            delays.get(DelayedStage::Load, L1BatchNumber(1)),
+            Some(Duration::from_millis(5))
+        );
+        assert_eq!(delays.get(DelayedStage::Load, L1BatchNumber(2)), None);
+        assert_eq!(
+            delays.get(DelayedStage::ProcessL1Batch, L1BatchNumber(1)),
+            None
+        );
+    }
+}
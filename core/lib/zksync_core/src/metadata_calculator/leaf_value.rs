@@ -0,0 +1,51 @@
+//! Codecs for values stored in Merkle tree leaves.
+
+use std::fmt;
+
+use zksync_crypto::hasher::{blake2::Blake2Hasher, Hasher};
+use zksync_types::H256;
+
+/// Value stored in a Merkle tree leaf. Values are encoded into bytes of an arbitrary (but deterministic)
+/// length; the tree stores a 32-byte value hash derived from the encoding (see [`Self::value_hash()`]).
+/// [`AsyncTree`] is parameterized by the value type, so that it can be used with values other than `H256`.
+///
+/// Decoding must invert encoding: `V::decode(value.value_hash()) == Some(value)` for values encoded
+/// into at most 32 bytes. Longer encodings are hashed, so such values cannot be read back from the tree;
+/// for them, [`Self::decode()`] should always return `None`.
+///
+/// [`AsyncTree`]: super::helpers::AsyncTree
+pub trait LeafValue: fmt::Debug + Sized + Send + 'static {
+    /// Encodes this value into bytes.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decodes a value from the value hash stored in the tree. Returns `None` if the hash
+    /// cannot be produced by [`Self::value_hash()`].
+    fn decode(value_hash: H256) -> Option<Self>;
+
+    /// Returns the value hash stored in the tree for this value. Encodings of up to 32 bytes are stored as is,
+    /// left-padded with zeros; longer encodings are hashed with Blake2s.
+    fn value_hash(&self) -> H256 {
+        let encoded = self.encode();
+        if encoded.len() > H256::len_bytes() {
+            return Blake2Hasher.hash_bytes(&encoded);
+        }
+        let mut value_hash = H256::zero();
+        value_hash.as_bytes_mut()[H256::len_bytes() - encoded.len()..].copy_from_slice(&encoded);
+        value_hash
+    }
+}
+
+/// Default codec used by the tree: values are stored as is.
+impl LeafValue for H256 {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(value_hash: H256) -> Option<Self> {
+        Some(value_hash)
+    }
+
+    fn value_hash(&self) -> H256 {
+        *self
+    }
+}
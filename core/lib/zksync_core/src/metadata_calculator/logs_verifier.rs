@@ -0,0 +1,380 @@
+//! Sampled verification of storage logs loaded for the Merkle tree.
+//!
+//! [`L1BatchWithLogs::new()`] relies on the `initial_writes` table to filter out storage writes that
+//! don't matter for the tree. To catch inconsistencies in this logic or in the underlying data, logs
+//! for a deterministic sample of L1 batches can be cross-checked against logs loaded using the reference
+//! method based on previous storage values, which is much slower and thus cannot be used for each L1 batch.
+//! Previous values are read through a bounded [`PreviousValuesCache`], so that slots touched in adjacent
+//! verified L1 batches are not repeatedly loaded from Postgres.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    time::Instant,
+};
+
+use zksync_config::configs::database::MerkleTreeMode;
+use zksync_dal::StorageProcessor;
+use zksync_types::{L1BatchNumber, StorageKey, StorageLog, StorageLogKind, H256};
+
+use super::{helpers::L1BatchWithLogs, metrics::METRICS};
+
+/// Storage logs for an L1 batch loaded using the reference method.
+#[derive(Debug)]
+pub(super) struct ReferenceLogs {
+    /// L1 batch with reference logs. Unlike logs loaded by [`L1BatchWithLogs::new()`], read logs
+    /// contain actual read values, and no-op writes are omitted.
+    pub l1_batch: L1BatchWithLogs,
+    /// Storage values before the L1 batch for all read or touched slots, keyed by hashed storage keys.
+    previous_values: HashMap<H256, H256>,
+    /// Protective reads for slots which have their values changed in the L1 batch.
+    pub changed_protective_reads: Vec<StorageKey>,
+}
+
+impl ReferenceLogs {
+    /// Loads reference logs for the specified L1 batch. Returns `None` if the L1 batch doesn't exist.
+    pub async fn load(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        cache: &mut PreviousValuesCache,
+    ) -> Option<Self> {
+        let header = storage
+            .blocks_dal()
+            .get_l1_batch_header(l1_batch_number)
+            .await
+            .unwrap()?;
+        let protective_reads = storage
+            .storage_logs_dedup_dal()
+            .get_protective_reads_for_l1_batch(l1_batch_number)
+            .await;
+        let touched_slots = storage
+            .storage_logs_dal()
+            .get_touched_slots_for_l1_batch(l1_batch_number)
+            .await;
+
+        let hashed_keys: Vec<_> = protective_reads
+            .iter()
+            .chain(touched_slots.keys())
+            .map(StorageKey::hashed_key)
+            .collect();
+        let previous_values = cache
+            .get_previous_values(storage, &hashed_keys, l1_batch_number)
+            .await;
+        // Values after this L1 batch are previous values for the next L1 batch; values of touched slots
+        // take precedence over the previous values for protective reads.
+        let values_after_l1_batch = protective_reads
+            .iter()
+            .map(|key| {
+                let hashed_key = key.hashed_key();
+                (hashed_key, previous_values[&hashed_key])
+            })
+            .chain(
+                touched_slots
+                    .iter()
+                    .map(|(key, &value)| (key.hashed_key(), value)),
+            );
+        cache.insert_values(l1_batch_number + 1, values_after_l1_batch);
+
+        let mut storage_logs = BTreeMap::new();
+        let mut changed_protective_reads = vec![];
+        for storage_key in protective_reads {
+            let previous_value = previous_values[&storage_key.hashed_key()];
+            // Value must not change for slots that require protective reads.
+            if touched_slots
+                .get(&storage_key)
+                .map_or(false, |value| *value != previous_value)
+            {
+                changed_protective_reads.push(storage_key);
+            }
+            storage_logs.insert(
+                storage_key,
+                StorageLog::new_read_log(storage_key, previous_value),
+            );
+        }
+
+        for (storage_key, value) in touched_slots {
+            let previous_value = previous_values[&storage_key.hashed_key()];
+            if previous_value != value {
+                storage_logs.insert(storage_key, StorageLog::new_write_log(storage_key, value));
+            }
+        }
+
+        Some(Self {
+            l1_batch: L1BatchWithLogs {
+                header,
+                storage_logs: storage_logs.into_values().collect(),
+            },
+            previous_values,
+            changed_protective_reads,
+        })
+    }
+
+    /// Compares these reference logs with the logs loaded for the tree. Read logs are only compared
+    /// in the full tree `mode` since the lightweight tree doesn't load them.
+    fn compare(&self, loaded: &L1BatchWithLogs, mode: MerkleTreeMode) -> Vec<LogsDivergence> {
+        let mut divergences: Vec<_> = self
+            .changed_protective_reads
+            .iter()
+            .map(|&key| LogsDivergence::ChangedProtectiveRead { key })
+            .collect();
+
+        let (reference_reads, reference_writes) = split_logs(&self.l1_batch.storage_logs);
+        let (loaded_reads, loaded_writes) = split_logs(&loaded.storage_logs);
+        if mode == MerkleTreeMode::Full {
+            divergences.extend(
+                reference_reads
+                    .difference(&loaded_reads)
+                    .map(|&key| LogsDivergence::MissingRead { key }),
+            );
+            divergences.extend(
+                loaded_reads
+                    .difference(&reference_reads)
+                    .map(|&key| LogsDivergence::ExtraRead { key }),
+            );
+        }
+
+        for (&key, &expected) in &reference_writes {
+            match loaded_writes.get(&key) {
+                None => divergences.push(LogsDivergence::MissingWrite { key, expected }),
+                Some(&actual) if actual != expected => {
+                    divergences.push(LogsDivergence::WriteValueMismatch {
+                        key,
+                        expected,
+                        actual,
+                    });
+                }
+                Some(_) => { /* Writes match */ }
+            }
+        }
+        // No-op writes (i.e., ones not changing the slot value) are omitted from the reference logs,
+        // but may be loaded for the tree; this is fine since they don't change the tree.
+        for (&key, &value) in &loaded_writes {
+            if reference_writes.contains_key(&key) {
+                continue;
+            }
+            let previous_value = self.previous_values.get(&key.hashed_key()).copied();
+            if previous_value != Some(value) {
+                divergences.push(LogsDivergence::ExtraWrite { key, value });
+            }
+        }
+        divergences
+    }
+}
+
+/// Bounded read-through cache of storage values before an L1 batch, keyed by the hashed storage key
+/// and the L1 batch number. Besides values loaded from Postgres, the cache is populated with values
+/// after each verified L1 batch, which serve lookups for the next L1 batch. Once the cache is full,
+/// the oldest entries are evicted first.
+#[derive(Debug, Default)]
+pub(super) struct PreviousValuesCache {
+    capacity: usize,
+    values: HashMap<(H256, L1BatchNumber), H256>,
+    /// Cached keys in the insertion order.
+    insertion_order: VecDeque<(H256, L1BatchNumber)>,
+    #[cfg(test)]
+    pub hits: u64,
+    #[cfg(test)]
+    pub misses: u64,
+}
+
+impl PreviousValuesCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    /// Returns storage values before `l1_batch_number` for the specified hashed keys, loading missing values
+    /// from Postgres. Values for slots that were never written are set to zero.
+    async fn get_previous_values(
+        &mut self,
+        storage: &mut StorageProcessor<'_>,
+        hashed_keys: &[H256],
+        l1_batch_number: L1BatchNumber,
+    ) -> HashMap<H256, H256> {
+        let mut previous_values = HashMap::with_capacity(hashed_keys.len());
+        let mut missing_keys = vec![];
+        for &hashed_key in hashed_keys {
+            if let Some(&value) = self.values.get(&(hashed_key, l1_batch_number)) {
+                previous_values.insert(hashed_key, value);
+            } else {
+                missing_keys.push(hashed_key);
+            }
+        }
+
+        let hits = (hashed_keys.len() - missing_keys.len()) as u64;
+        METRICS.logs_verification_cache_hits.inc_by(hits);
+        METRICS
+            .logs_verification_cache_misses
+            .inc_by(missing_keys.len() as u64);
+        #[cfg(test)]
+        {
+            self.hits += hits;
+            self.misses += missing_keys.len() as u64;
+        }
+
+        if !missing_keys.is_empty() {
+            let loaded_values = storage
+                .storage_logs_dal()
+                .get_previous_storage_values(&missing_keys, l1_batch_number)
+                .await;
+            let loaded_values = loaded_values
+                .into_iter()
+                .map(|(hashed_key, value)| (hashed_key, value.unwrap_or_default()));
+            let loaded_values: Vec<_> = loaded_values.collect();
+            self.insert_values(l1_batch_number, loaded_values.iter().copied());
+            previous_values.extend(loaded_values);
+        }
+        previous_values
+    }
+
+    /// Caches storage values before `l1_batch_number`.
+    fn insert_values(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+        values: impl IntoIterator<Item = (H256, H256)>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        for (hashed_key, value) in values {
+            let key = (hashed_key, l1_batch_number);
+            if self.values.insert(key, value).is_none() {
+                self.insertion_order.push_back(key);
+            }
+        }
+        while self.insertion_order.len() > self.capacity {
+            let evicted_key = self.insertion_order.pop_front().unwrap();
+            self.values.remove(&evicted_key);
+        }
+    }
+
+    /// Removes all cached values. Should be called if L1 batches are reverted in Postgres.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.insertion_order.clear();
+    }
+}
+
+fn split_logs(logs: &[StorageLog]) -> (BTreeSet<StorageKey>, BTreeMap<StorageKey, H256>) {
+    let mut reads = BTreeSet::new();
+    let mut writes = BTreeMap::new();
+    for log in logs {
+        match log.kind {
+            StorageLogKind::Read => {
+                reads.insert(log.key);
+            }
+            StorageLogKind::Write => {
+                writes.insert(log.key, log.value);
+            }
+        }
+    }
+    (reads, writes)
+}
+
+/// Divergence between storage logs loaded for the tree and the reference logs.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum LogsDivergence {
+    /// Value of a slot with a protective read is changed in the L1 batch.
+    ChangedProtectiveRead { key: StorageKey },
+    /// Protective read is missing in the loaded logs.
+    MissingRead { key: StorageKey },
+    /// Loaded logs contain a read not present among protective reads.
+    ExtraRead { key: StorageKey },
+    /// Write changing the slot value is missing in the loaded logs.
+    MissingWrite { key: StorageKey, expected: H256 },
+    /// Loaded write has a value different from the reference one.
+    WriteValueMismatch {
+        key: StorageKey,
+        expected: H256,
+        actual: H256,
+    },
+    /// Loaded logs contain a write changing the slot value that is not present among reference writes.
+    ExtraWrite { key: StorageKey, value: H256 },
+}
+
+/// Logs and reports divergences found for an L1 batch. Divergences don't halt the tree since
+/// the reference method is used for diagnostics only.
+fn report_divergences(l1_batch_number: L1BatchNumber, divergences: &[LogsDivergence]) {
+    if divergences.is_empty() {
+        return;
+    }
+    for divergence in divergences {
+        tracing::error!(
+            "Storage logs loaded for L1 batch #{l1_batch_number} diverge from reference logs: {divergence:?}"
+        );
+    }
+    METRICS
+        .logs_verification_divergences
+        .inc_by(divergences.len() as u64);
+}
+
+/// Verifies storage logs for a deterministic sample of L1 batches: an L1 batch is verified
+/// if its number is divisible by the configured modulus.
+#[derive(Debug)]
+pub(super) struct LogsVerifier {
+    modulus: Option<u32>,
+    mode: MerkleTreeMode,
+    cache: PreviousValuesCache,
+}
+
+impl LogsVerifier {
+    pub fn new(modulus: Option<u32>, mode: MerkleTreeMode, cache_capacity: usize) -> Self {
+        assert!(
+            modulus != Some(0),
+            "Modulus for storage logs verification must be positive"
+        );
+        Self {
+            modulus,
+            mode,
+            cache: PreviousValuesCache::new(cache_capacity),
+        }
+    }
+
+    /// Drops cached previous storage values, e.g. after L1 batches are reverted.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    #[cfg(test)]
+    pub fn cache(&self) -> &PreviousValuesCache {
+        &self.cache
+    }
+
+    /// Checks whether the specified L1 batch is sampled for verification.
+    pub fn is_sampled(&self, l1_batch_number: L1BatchNumber) -> bool {
+        self.modulus
+            .map_or(false, |modulus| l1_batch_number.0 % modulus == 0)
+    }
+
+    /// Verifies logs loaded for the tree if the L1 batch is sampled, and reports found divergences.
+    /// Returns `None` if the L1 batch is not sampled, or the found divergences otherwise.
+    pub async fn verify_if_sampled(
+        &mut self,
+        storage: &mut StorageProcessor<'_>,
+        l1_batch: &L1BatchWithLogs,
+    ) -> Option<Vec<LogsDivergence>> {
+        let l1_batch_number = l1_batch.header.number;
+        if !self.is_sampled(l1_batch_number) {
+            return None;
+        }
+
+        let started_at = Instant::now();
+        let reference = ReferenceLogs::load(storage, l1_batch_number, &mut self.cache)
+            .await
+            .unwrap_or_else(|| panic!("L1 batch #{l1_batch_number} disappeared from Postgres"));
+        let divergences = reference.compare(l1_batch, self.mode);
+        METRICS
+            .logs_verification_latency
+            .observe(started_at.elapsed());
+        METRICS.logs_verification_l1_batches.inc();
+        tracing::info!(
+            "Verified storage logs for L1 batch #{l1_batch_number} against reference logs in {:?}; \
+             found {} divergence(s)",
+            started_at.elapsed(),
+            divergences.len()
+        );
+        report_divergences(l1_batch_number, &divergences);
+        Some(divergences)
+    }
+}
@@ -1,20 +1,47 @@
-//! Metrics for `MetadataCalculator`.
+//! Metrics for `MetadataCalculator`. All calculator-specific metrics are defined in [`MetadataCalculatorMetrics`].
+//!
+//! # Metric names
+//!
+//! The metrics were previously reported via the `metrics` facade. Their exported names map to the current ones
+//! as follows:
+//!
+//! - Dots in names are replaced with underscores, e.g. `server.metadata_calculator.lag` is exported
+//!   as `server_metadata_calculator_lag` (the same as with the legacy exporter).
+//! - Counters get the `_total` suffix, e.g. `server.metadata_calculator.stalls` is exported
+//!   as `server_metadata_calculator_stalls_total`. `server.metadata_calculator.tree_reverts_total`
+//!   retains its name.
+//! - Latencies and other durations get the `_seconds` suffix, e.g. `server.metadata_calculator.update_tree.latency.stage`
+//!   is exported as `server_metadata_calculator_update_tree_latency_stage_seconds`.
+//!   `server.metadata_calculator.gauges_refreshed_at` (a UNIX timestamp) also gets this suffix.
+//! - Sizes get the `_bytes` suffix, e.g. `server.metadata_calculator.witness.size` is exported
+//!   as `server_metadata_calculator_witness_size_bytes`.
+//!
+//! Labels and label values are unchanged. Server-wide metrics shared with other components (`server.processed_txs`,
+//! `server.processed_l1_txs`, `server.block_number` and `server.block_latency`) are still reported
+//! via the `metrics` facade so that they remain in a single metric family.
 
-use std::time::Instant;
+use anyhow::Context as _;
+use once_cell::sync::OnceCell;
+use vise::{
+    Buckets, Counter, EncodeLabelSet, EncodeLabelValue, Family, Gauge, Global, Histogram,
+    LabeledFamily, Metrics, Unit,
+};
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
 
 use zksync_config::configs::database::MerkleTreeMode;
-use zksync_types::block::L1BatchHeader;
+use zksync_types::{block::L1BatchHeader, L1BatchNumber};
 use zksync_utils::time::seconds_since_epoch;
 
-use super::MetadataCalculator;
+use super::{witness::WitnessSummary, MetadataCalculator};
 
 /// Stage of [`MetadataCalculator`] update reported via metric and logged.
-pub(super) trait ReportStage: Copy {
-    /// Name of the histogram using which the stage latency is reported.
-    const HISTOGRAM_NAME: &'static str;
-
-    /// Returns the stage tag for the histogram.
-    fn as_tag(self) -> &'static str;
+pub(super) trait ReportStage: Copy + fmt::Debug {
+    /// Records the stage latency into the corresponding metric family.
+    fn observe_latency(self, latency: Duration);
 
     /// Starts the stage.
     fn start(self) -> UpdateTreeLatency<Self> {
@@ -25,59 +52,116 @@ pub(super) trait ReportStage: Copy {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue, EncodeLabelSet)]
+#[metrics(label = "stage", rename_all = "snake_case")]
 pub(super) enum TreeUpdateStage {
     LoadChanges,
     Compute,
     PrepareResults,
+    #[metrics(name = "reestimate_block_commit_gas_cost")]
     ReestimateGasCost,
     SavePostgres,
+    #[metrics(name = "save_rocksdb")]
     SaveRocksDB,
+    #[metrics(name = "save_gcs")]
     SaveWitnesses,
+    /// Serializing and compressing a witness input. Only reported in the full tree mode.
+    BuildWitness,
+    /// Uploading a witness input to the object store. Only reported in the full tree mode.
+    UploadWitness,
+    #[metrics(name = "backup_tree")]
     _Backup,
 }
 
 impl ReportStage for TreeUpdateStage {
-    const HISTOGRAM_NAME: &'static str = "server.metadata_calculator.update_tree.latency.stage";
-
-    fn as_tag(self) -> &'static str {
-        match self {
-            Self::LoadChanges => "load_changes",
-            Self::Compute => "compute",
-            Self::PrepareResults => "prepare_results",
-            Self::ReestimateGasCost => "reestimate_block_commit_gas_cost",
-            Self::SavePostgres => "save_postgres",
-            Self::SaveRocksDB => "save_rocksdb",
-            Self::SaveWitnesses => "save_gcs",
-            Self::_Backup => "backup_tree",
-        }
+    fn observe_latency(self, latency: Duration) {
+        METRICS.update_tree_latency_stage[&self].observe(latency);
     }
 }
 
 /// Sub-stages of [`TreeUpdateStage::LoadChanges`].
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue, EncodeLabelSet)]
+#[metrics(label = "stage", rename_all = "snake_case")]
 pub(super) enum LoadChangesStage {
+    #[metrics(name = "load_l1_batch_header")]
     L1BatchHeader,
+    #[metrics(name = "load_protective_reads")]
     ProtectiveReads,
+    #[metrics(name = "load_touched_slots")]
     TouchedSlots,
+    #[metrics(name = "load_initial_writes_for_zero_values")]
     InitialWritesForZeroValues,
 }
 
 impl LoadChangesStage {
-    const COUNT_HISTOGRAM_NAME: &'static str = "server.metadata_calculator.load_changes.count";
+    /// Returns the approximate size of a single row fetched from Postgres on this stage, in bytes.
+    /// Used to estimate the amount of data transferred since `sqlx` doesn't provide statement statistics.
+    const fn approx_row_size(self) -> usize {
+        match self {
+            // Headers contain a bunch of variable-length fields (e.g., L2-to-L1 logs and the bloom filter),
+            // so this is a rough estimate.
+            Self::L1BatchHeader => 1_024,
+            // `address` (20 bytes) + `key` (32 bytes)
+            Self::ProtectiveReads => 52,
+            // `address` (20 bytes) + `key` (32 bytes) + `value` (32 bytes)
+            Self::TouchedSlots => 84,
+            // `hashed_key` (32 bytes) + `l1_batch_number` (8 bytes)
+            Self::InitialWritesForZeroValues => 40,
+        }
+    }
 }
 
 impl ReportStage for LoadChangesStage {
-    const HISTOGRAM_NAME: &'static str = "server.metadata_calculator.load_changes.latency";
+    fn observe_latency(self, latency: Duration) {
+        METRICS.load_changes_latency[&self].observe(latency);
+    }
+}
 
-    fn as_tag(self) -> &'static str {
-        match self {
-            Self::L1BatchHeader => "load_l1_batch_header",
-            Self::ProtectiveReads => "load_protective_reads",
-            Self::TouchedSlots => "load_touched_slots",
-            Self::InitialWritesForZeroValues => "load_initial_writes_for_zero_values",
-        }
+/// Default buckets for latencies of [`TreeUpdateStage`]s and [`LoadChangesStage`]s in seconds, spanning
+/// 1ms to 10min. Unlike [`Buckets::LATENCIES`], these cover saving the tree during catch-up and hashing
+/// large L1 batches, which may take minutes.
+pub(super) const DEFAULT_STAGE_LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0,
+    120.0, 300.0, 600.0,
+];
+
+static STAGE_LATENCY_BUCKETS: OnceCell<&'static [f64]> = OnceCell::new();
+
+/// Overrides buckets for tree stage latency histograms. Buckets are specified in seconds and must be
+/// finite, positive and strictly increasing.
+///
+/// The override must be set before the metadata calculator metrics are first accessed (i.e., before
+/// the calculator is created) and can only be set once per process.
+pub fn set_stage_latency_buckets(buckets: Vec<f64>) -> anyhow::Result<()> {
+    validate_buckets(&buckets).context("invalid tree stage latency buckets")?;
+    let buckets: &'static [f64] = Box::leak(buckets.into_boxed_slice());
+    STAGE_LATENCY_BUCKETS
+        .set(buckets)
+        .map_err(|_| anyhow::anyhow!("tree stage latency buckets are already set"))
+}
+
+fn validate_buckets(buckets: &[f64]) -> anyhow::Result<()> {
+    anyhow::ensure!(!buckets.is_empty(), "no buckets specified");
+    for &bucket in buckets {
+        anyhow::ensure!(
+            bucket.is_finite() && bucket > 0.0,
+            "bucket {bucket} is not a finite positive value"
+        );
+    }
+    for window in buckets.windows(2) {
+        anyhow::ensure!(
+            window[0] < window[1],
+            "buckets are not strictly increasing: {} >= {}",
+            window[0],
+            window[1]
+        );
     }
+    Ok(())
+}
+
+fn stage_latency_buckets() -> Buckets {
+    let buckets = STAGE_LATENCY_BUCKETS.get().copied();
+    Buckets::values(buckets.unwrap_or(DEFAULT_STAGE_LATENCY_BUCKETS))
 }
 
 /// Latency metric for a certain stage of the tree update.
@@ -95,24 +179,365 @@ impl<S: ReportStage> UpdateTreeLatency<S> {
 
     fn report_inner(self, record_count: Option<usize>) {
         let elapsed = self.start.elapsed();
-        let stage = self.stage.as_tag();
-        metrics::histogram!(S::HISTOGRAM_NAME, elapsed, "stage" => stage);
+        let stage = self.stage;
+        stage.observe_latency(elapsed);
 
         if let Some(record_count) = record_count {
             tracing::debug!(
-                "Metadata calculator stage `{stage}` with {record_count} records completed in {elapsed:?}"
+                "Metadata calculator stage {stage:?} with {record_count} records completed in {elapsed:?}"
             );
         } else {
-            tracing::debug!("Metadata calculator stage `{stage}` completed in {elapsed:?}");
+            tracing::debug!("Metadata calculator stage {stage:?} completed in {elapsed:?}");
         }
     }
 }
 
 impl UpdateTreeLatency<LoadChangesStage> {
+    /// Reports the stage latency together with the number of rows fetched from Postgres
+    /// and the approximate number of transferred bytes. The latter two metrics allow to distinguish
+    /// between increased data volume and slower Postgres.
     pub fn report_with_count(self, count: usize) {
-        let stage = self.stage.as_tag();
+        let stage = self.stage;
         self.report_inner(Some(count));
-        metrics::histogram!(LoadChangesStage::COUNT_HISTOGRAM_NAME, count as f64, "stage" => stage);
+        METRICS.load_changes_count[&stage].observe(count);
+        METRICS.load_changes_bytes[&stage].observe(count * stage.approx_row_size());
+    }
+}
+
+/// Kind of Merkle paths in a witness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue, EncodeLabelSet)]
+#[metrics(label = "kind", rename_all = "snake_case")]
+pub(super) enum WitnessPathKind {
+    Read,
+    Write,
+}
+
+/// Kind of a witness size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue, EncodeLabelSet)]
+#[metrics(label = "kind", rename_all = "snake_case")]
+pub(super) enum WitnessSizeKind {
+    Raw,
+    Compressed,
+}
+
+const COUNT_BUCKETS: Buckets = Buckets::exponential(1.0..=1_000_000.0, 4.0);
+const BYTE_SIZE_BUCKETS: Buckets =
+    Buckets::exponential(1_024.0..=4.0 * 1_024.0 * 1_024.0 * 1_024.0, 4.0);
+const L1_BATCH_COUNT_BUCKETS: Buckets = Buckets::linear(1.0..=10.0, 1.0);
+const ATTEMPT_BUCKETS: Buckets = Buckets::linear(1.0..=10.0, 1.0);
+const PROTECTIVE_READS_RATIO_BUCKETS: Buckets =
+    Buckets::values(&[0.01, 0.1, 0.5, 1.0, 2.0, 5.0, 10.0, 100.0, 1_000.0]);
+
+/// Metrics for the metadata calculator.
+#[derive(Debug, Metrics)]
+#[metrics(prefix = "server_metadata_calculator")]
+pub(super) struct MetadataCalculatorMetrics {
+    // Tree update metrics
+    /// Latency of a single tree update iteration, which may process multiple L1 batches.
+    #[metrics(buckets = Buckets::LATENCIES)]
+    pub update_tree_latency: Histogram<Duration>,
+    /// Latency of a tree update iteration divided by the number of processed storage logs.
+    #[metrics(buckets = Buckets::LATENCIES)]
+    pub update_tree_per_log_latency: Histogram<Duration>,
+    /// Latency of tree update stages.
+    #[metrics(buckets = stage_latency_buckets())]
+    pub update_tree_latency_stage: Family<TreeUpdateStage, Histogram<Duration>>,
+    /// Number of storage logs processed in a single tree update iteration.
+    #[metrics(buckets = COUNT_BUCKETS)]
+    pub log_batch: Histogram<usize>,
+    /// Number of L1 batches processed in a single tree update iteration.
+    #[metrics(buckets = L1_BATCH_COUNT_BUCKETS)]
+    pub blocks_batch: Histogram<usize>,
+
+    // Loading changes from Postgres
+    /// Latency of sub-stages of loading changes from Postgres.
+    #[metrics(buckets = stage_latency_buckets())]
+    pub load_changes_latency: Family<LoadChangesStage, Histogram<Duration>>,
+    /// Number of rows fetched from Postgres on a loading sub-stage.
+    #[metrics(buckets = COUNT_BUCKETS)]
+    pub load_changes_count: Family<LoadChangesStage, Histogram<usize>>,
+    /// Approximate number of bytes fetched from Postgres on a loading sub-stage.
+    #[metrics(buckets = BYTE_SIZE_BUCKETS, unit = Unit::Bytes)]
+    pub load_changes_bytes: Family<LoadChangesStage, Histogram<usize>>,
+    /// Number of storage logs with zero values in an L1 batch.
+    #[metrics(buckets = COUNT_BUCKETS)]
+    pub load_changes_zero_values: Histogram<usize>,
+    /// Ratio of protective reads to writes in an L1 batch.
+    #[metrics(buckets = PROTECTIVE_READS_RATIO_BUCKETS)]
+    pub protective_reads_ratio: Histogram<f64>,
+    /// Number of L1 batches with an excessive ratio of protective reads to writes.
+    pub excessive_protective_reads: Counter,
+    /// Number of failed attempts to acquire a Postgres connection.
+    pub db_connection_failures: Counter,
+    /// Number of retries of loading an L1 batch without storage logs.
+    pub empty_l1_batch_retries: Counter,
+
+    // Witnesses
+    /// Total number of Merkle paths in a witness.
+    #[metrics(buckets = COUNT_BUCKETS)]
+    pub witness_path_count: Histogram<usize>,
+    /// Number of Merkle paths in a witness, grouped by the path kind.
+    #[metrics(buckets = COUNT_BUCKETS)]
+    pub witness_paths: Family<WitnessPathKind, Histogram<usize>>,
+    /// Number of leaves inserted into the tree by an L1 batch.
+    #[metrics(buckets = COUNT_BUCKETS)]
+    pub witness_enumeration_index_delta: Histogram<u64>,
+    /// Size of a witness.
+    #[metrics(buckets = BYTE_SIZE_BUCKETS, unit = Unit::Bytes)]
+    pub witness_size: Family<WitnessSizeKind, Histogram<u64>>,
+    /// Number of witnesses spilled to disk.
+    pub witness_spilled: Counter,
+    /// Latency of compressing a witness.
+    #[metrics(buckets = Buckets::LATENCIES)]
+    pub witness_compression_time: Histogram<Duration>,
+    /// Size of a persisted witness artifact.
+    #[metrics(buckets = BYTE_SIZE_BUCKETS, unit = Unit::Bytes)]
+    pub witness_artifact_size: Histogram<u64>,
+    /// Number of times the tree waited for the witness stage to catch up.
+    pub witness_stage_backpressure: Counter,
+    /// Number of witnesses pending in the witness stage.
+    pub witness_stage_pending: Gauge<u64>,
+    /// Number of witnesses lost on the previous run and re-produced on startup.
+    pub restored_witnesses: Counter,
+    /// Number of L1 batches for which witness generation was skipped because they are already proven.
+    pub skipped_witnesses_for_proven_l1_batches: Counter,
+    /// Number of witnesses in the upload queue.
+    pub witness_upload_queue_depth: Gauge<u64>,
+    /// Total size of witnesses in the upload queue.
+    #[metrics(unit = Unit::Bytes)]
+    pub witness_upload_queue_size: Gauge<u64>,
+    /// Age of the oldest witness in the upload queue.
+    #[metrics(unit = Unit::Seconds)]
+    pub witness_upload_queue_oldest_pending_age: Gauge<Duration>,
+    /// Number of failed witness uploads from the upload queue.
+    pub witness_upload_queue_failed_uploads: Counter,
+
+    // Object store
+    /// Latency of a single object store request attempt.
+    #[metrics(buckets = Buckets::LATENCIES, labels = ["operation"])]
+    pub object_store_attempt_latency: LabeledFamily<&'static str, Histogram<Duration>>,
+    /// Latency of an object store request including retries.
+    #[metrics(buckets = Buckets::LATENCIES, labels = ["operation"])]
+    pub object_store_latency: LabeledFamily<&'static str, Histogram<Duration>>,
+    /// Number of attempts made for an object store request.
+    #[metrics(buckets = ATTEMPT_BUCKETS, labels = ["operation"])]
+    pub object_store_attempts: LabeledFamily<&'static str, Histogram<usize>>,
+    /// Number of failed object store request attempts.
+    #[metrics(labels = ["operation"])]
+    pub object_store_failed_attempts: LabeledFamily<&'static str, Counter>,
+
+    // Last loaded L1 batch
+    /// Number of the L1 batch most recently loaded from Postgres; allows annotating other `last_loaded_*` gauges.
+    pub last_loaded_l1_batch: Gauge<u64>,
+    /// Total number of storage logs (writes and protective reads) in the most recently loaded L1 batch.
+    pub last_loaded_logs: Gauge<u64>,
+    /// Number of writes in the most recently loaded L1 batch.
+    pub last_loaded_writes: Gauge<u64>,
+    /// Number of protective reads in the most recently loaded L1 batch. Only reported for the full tree
+    /// since the lightweight tree doesn't load protective reads as read logs.
+    pub last_loaded_reads: Gauge<u64>,
+    /// Number of zero-value writes filtered out from the most recently loaded L1 batch
+    /// because they don't correspond to initial writes.
+    pub last_loaded_filtered_zero_writes: Gauge<u64>,
+
+    // Tree state
+    /// Next L1 batch to be processed by the tree.
+    pub next_l1_batch: Gauge<u64>,
+    /// Number of sealed L1 batches in Postgres not yet processed by the tree.
+    pub lag: Gauge<u64>,
+    /// Total size of the tree RocksDB directory.
+    #[metrics(unit = Unit::Bytes)]
+    pub db_size: Gauge<u64>,
+    /// UNIX timestamp of the last refresh of tree gauges.
+    #[metrics(unit = Unit::Seconds)]
+    pub gauges_refreshed_at: Gauge<u64>,
+    /// Number of L1 batches with metadata in Postgres not covered by the tree on startup.
+    pub backup_lag: Gauge<u64>,
+    /// Number of hits in the root hash cache.
+    pub root_hash_cache_hits: Counter,
+    /// Number of misses in the root hash cache.
+    pub root_hash_cache_misses: Counter,
+    /// Number of detected tree stalls.
+    pub stalls: Counter,
+    /// Number of times the tree was halted because of a reorg.
+    pub reorg_halts: Counter,
+    /// Number of tree reverts, grouped by the revert depth bucket.
+    #[metrics(labels = ["depth"])]
+    pub tree_reverts: LabeledFamily<&'static str, Counter>,
+    /// Number of L1 batch notifications dropped for lagging subscribers.
+    pub l1_batch_notifications_dropped: Counter,
+    /// Number of violations of the save latency SLO.
+    pub save_latency_slo_violations: Counter,
+    /// Share of saves meeting the save latency SLO in the sliding window.
+    pub save_latency_slo_compliance: Gauge<f64>,
+
+    // Consistency checks
+    /// Number of Postgres inconsistencies tolerated per the best-effort DAL consistency policy.
+    #[metrics(labels = ["kind"])]
+    pub dal_inconsistencies: LabeledFamily<&'static str, Counter>,
+    /// Number of enumeration index mismatches between the tree and Postgres.
+    pub enumeration_index_mismatches: Counter,
+    /// Number of times the tree was found to be ahead of the last sealed L1 batch in Postgres on startup.
+    pub progress_mismatches: Counter,
+    /// Number of repeated writes inconsistent with initial writes.
+    pub repeated_write_mismatches: Counter,
+
+    // Storage logs verification
+    /// Latency of verifying storage logs for an L1 batch.
+    #[metrics(buckets = Buckets::LATENCIES)]
+    pub logs_verification_latency: Histogram<Duration>,
+    /// Number of L1 batches with verified storage logs.
+    pub logs_verification_l1_batches: Counter,
+    /// Number of previous values taken from the verification cache.
+    pub logs_verification_cache_hits: Counter,
+    /// Number of previous values missing from the verification cache.
+    pub logs_verification_cache_misses: Counter,
+    /// Number of divergences between loaded and reference storage logs.
+    pub logs_verification_divergences: Counter,
+
+    // Root hash webhook
+    /// Number of webhook payloads dropped because the queue is full or the webhook worker was stopped.
+    pub webhook_dropped: Counter,
+    /// Number of successfully sent webhook payloads.
+    pub webhook_sent: Counter,
+    /// Number of webhook payloads not sent after all retries.
+    pub webhook_failed: Counter,
+}
+
+#[vise::register]
+pub(super) static METRICS: Global<MetadataCalculatorMetrics> = Global::new();
+
+/// Statistics of the witness input produced for a single L1 batch by the tree in the full mode.
+/// Witness sizes directly drive prover costs, so we track their growth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct WitnessStats {
+    /// Total number of Merkle paths in the witness.
+    pub path_count: usize,
+    /// Number of paths corresponding to reads.
+    pub read_count: usize,
+    /// Number of paths corresponding to writes.
+    pub write_count: usize,
+    /// Difference between the next enumeration index after and before the L1 batch,
+    /// i.e., the number of leaves inserted into the tree.
+    pub enumeration_index_delta: u64,
+    /// Size of the uncompressed `bincode` serialization of the witness in bytes.
+    pub raw_size: u64,
+    /// Size of the witness as persisted in the object store in bytes.
+    pub compressed_size: u64,
+}
+
+impl WitnessStats {
+    /// Collects path statistics from the witness `summary`. `next_enumeration_index` is the next leaf index
+    /// after processing the L1 batch. Sizes are filled in once the witness is serialized.
+    pub fn new(summary: &WitnessSummary, next_enumeration_index: u64) -> Self {
+        let path_count = summary.merkle_path_count;
+        let write_count = summary.write_count;
+        Self {
+            path_count,
+            read_count: path_count - write_count,
+            write_count,
+            enumeration_index_delta: next_enumeration_index
+                .saturating_sub(summary.next_enumeration_index),
+            raw_size: 0,
+            compressed_size: 0,
+        }
+    }
+
+    pub fn report(&self) {
+        METRICS.witness_path_count.observe(self.path_count);
+        METRICS.witness_paths[&WitnessPathKind::Read].observe(self.read_count);
+        METRICS.witness_paths[&WitnessPathKind::Write].observe(self.write_count);
+        METRICS
+            .witness_enumeration_index_delta
+            .observe(self.enumeration_index_delta);
+        METRICS.witness_size[&WitnessSizeKind::Raw].observe(self.raw_size);
+        METRICS.witness_size[&WitnessSizeKind::Compressed].observe(self.compressed_size);
+    }
+}
+
+impl fmt::Display for WitnessStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{} Merkle paths ({} reads, {} writes), enumeration index delta {}, \
+             {}B raw, {}B compressed",
+            self.path_count,
+            self.read_count,
+            self.write_count,
+            self.enumeration_index_delta,
+            self.raw_size,
+            self.compressed_size
+        )
+    }
+}
+
+/// Tree gauges refreshed periodically regardless of whether the tree processes L1 batches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct TreeGauges {
+    /// Next L1 batch to be processed by the tree.
+    pub next_l1_batch: L1BatchNumber,
+    /// Number of sealed L1 batches in Postgres not yet processed by the tree.
+    pub lag: u32,
+    /// Total size of the tree RocksDB directory in bytes.
+    pub db_size: u64,
+}
+
+impl TreeGauges {
+    pub fn report(&self) {
+        METRICS.next_l1_batch.set(self.next_l1_batch.0.into());
+        METRICS.lag.set(self.lag.into());
+        METRICS.db_size.set(self.db_size);
+        METRICS.gauges_refreshed_at.set(seconds_since_epoch());
+    }
+}
+
+/// Composition of storage logs in an L1 batch loaded from Postgres.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct LoadedL1BatchStats {
+    pub l1_batch_number: L1BatchNumber,
+    pub write_count: usize,
+    /// Number of protective reads in the L1 batch as loaded from Postgres; `None` for the lightweight tree.
+    pub read_count: Option<usize>,
+    pub filtered_zero_write_count: usize,
+}
+
+impl LoadedL1BatchStats {
+    pub fn log_count(&self) -> usize {
+        self.write_count + self.read_count.unwrap_or(0)
+    }
+
+    pub fn report(&self) {
+        METRICS
+            .last_loaded_l1_batch
+            .set(self.l1_batch_number.0.into());
+        METRICS.last_loaded_logs.set(self.log_count() as u64);
+        METRICS.last_loaded_writes.set(self.write_count as u64);
+        if let Some(read_count) = self.read_count {
+            METRICS.last_loaded_reads.set(read_count as u64);
+        }
+        METRICS
+            .last_loaded_filtered_zero_writes
+            .set(self.filtered_zero_write_count as u64);
+    }
+}
+
+impl fmt::Display for LoadedL1BatchStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "L1 batch #{}: {} storage logs ({} writes",
+            self.l1_batch_number,
+            self.log_count(),
+            self.write_count
+        )?;
+        if let Some(read_count) = self.read_count {
+            write!(formatter, ", {read_count} protective reads")?;
+        }
+        write!(
+            formatter,
+            "), {} filtered zero-value writes",
+            self.filtered_zero_write_count
+        )
     }
 }
 
@@ -128,15 +553,12 @@ impl MetadataCalculator {
             MerkleTreeMode::Lightweight => "lightweight",
         };
 
-        metrics::histogram!(
-            "server.metadata_calculator.update_tree.latency",
-            start.elapsed()
-        );
+        let elapsed = start.elapsed();
+        METRICS.update_tree_latency.observe(elapsed);
         if total_logs > 0 {
-            metrics::histogram!(
-                "server.metadata_calculator.update_tree.per_log.latency",
-                start.elapsed().div_f32(total_logs as f32)
-            );
+            METRICS
+                .update_tree_per_log_latency
+                .observe(elapsed.div_f32(total_logs as f32));
         }
 
         let total_tx: usize = batch_headers.iter().map(L1BatchHeader::tx_count).sum();
@@ -146,11 +568,8 @@ impl MetadataCalculator {
             .sum();
         metrics::counter!("server.processed_txs", total_tx as u64, "stage" => "tree");
         metrics::counter!("server.processed_l1_txs", total_l1_tx_count, "stage" => "tree");
-        metrics::histogram!("server.metadata_calculator.log_batch", total_logs as f64);
-        metrics::histogram!(
-            "server.metadata_calculator.blocks_batch",
-            batch_headers.len() as f64
-        );
+        METRICS.log_batch.observe(total_logs);
+        METRICS.blocks_batch.observe(batch_headers.len());
 
         let first_batch_number = batch_headers.first().unwrap().number.0;
         let last_batch_number = batch_headers.last().unwrap().number.0;
@@ -173,3 +592,55 @@ impl MetadataCalculator {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_stage_latency_buckets() {
+        assert_eq!(
+            DEFAULT_STAGE_LATENCY_BUCKETS,
+            [
+                0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0,
+                30.0, 60.0, 120.0, 300.0, 600.0
+            ]
+        );
+        validate_buckets(DEFAULT_STAGE_LATENCY_BUCKETS).unwrap();
+    }
+
+    #[test]
+    fn validating_stage_latency_buckets() {
+        validate_buckets(&[0.5, 1.0, 60.0]).unwrap();
+        validate_buckets(&[]).unwrap_err();
+        validate_buckets(&[0.0, 1.0]).unwrap_err();
+        validate_buckets(&[1.0, f64::INFINITY]).unwrap_err();
+        validate_buckets(&[1.0, 1.0]).unwrap_err();
+        validate_buckets(&[10.0, 1.0]).unwrap_err();
+    }
+
+    #[test]
+    fn loaded_l1_batch_stats_summary() {
+        let stats = LoadedL1BatchStats {
+            l1_batch_number: L1BatchNumber(5),
+            write_count: 10,
+            read_count: Some(3),
+            filtered_zero_write_count: 2,
+        };
+        assert_eq!(stats.log_count(), 13);
+        assert_eq!(
+            stats.to_string(),
+            "L1 batch #5: 13 storage logs (10 writes, 3 protective reads), 2 filtered zero-value writes"
+        );
+
+        let lightweight_stats = LoadedL1BatchStats {
+            read_count: None,
+            ..stats
+        };
+        assert_eq!(lightweight_stats.log_count(), 10);
+        assert_eq!(
+            lightweight_stats.to_string(),
+            "L1 batch #5: 10 storage logs (10 writes), 2 filtered zero-value writes"
+        );
+    }
+}
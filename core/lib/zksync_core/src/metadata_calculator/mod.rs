@@ -1,34 +1,107 @@
 //! This module applies updates to the ZkSyncTree, calculates metadata for sealed blocks, and
 //! stores them in the DB.
 
+use anyhow::Context as _;
+use futures::future;
+use serde::{Deserialize, Serialize};
 use tokio::sync::watch;
 
-use std::time::Duration;
+use std::{io, ops, time::Duration};
 
 use zksync_config::configs::{
     chain::OperationsManagerConfig,
-    database::{DBConfig, MerkleTreeMode},
+    database::{
+        DBConfig, DalConsistencyPolicy, EmptyL1BatchPolicy, EnumerationIndexCheck, MerkleTreeMode,
+        TreeHealthDetailsFormat,
+    },
 };
 use zksync_dal::{ConnectionPool, StorageProcessor};
-use zksync_health_check::{HealthUpdater, ReactiveHealthCheck};
-use zksync_merkle_tree::domain::TreeMetadata;
+use zksync_health_check::{HealthStatus, HealthUpdater, ReactiveHealthCheck};
+use zksync_merkle_tree::{
+    domain::{TreeMetadata, ZkSyncTreeReader},
+    MerkleTreeColumnFamily,
+};
 use zksync_object_store::ObjectStoreFactory;
 use zksync_types::{
     block::L1BatchHeader,
     commitment::{L1BatchCommitment, L1BatchMetadata},
+    L1BatchNumber, ProtocolVersionId, StorageKey, StorageLog, H256,
 };
 
+mod audit_log;
+#[cfg(any(test, feature = "benchmark"))]
+mod benchmark;
+#[cfg(feature = "grpc")]
+mod grpc;
 mod helpers;
+mod hot_keys;
+mod initial_writes_repair;
+#[cfg(test)]
+mod injected_delays;
+mod leaf_value;
+mod logs_verifier;
 mod metrics;
+mod notifications;
+mod reorg_halt;
+mod save_latency_slo;
+mod snapshot_diff;
+mod snapshot_export;
+#[cfg(feature = "sse")]
+mod sse;
+mod stall_watchdog;
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
+mod tree_versions;
 mod updater;
+mod upload_policy;
+mod upload_queue;
+mod watched_keys;
+#[cfg(feature = "webhook")]
+mod webhook;
+mod witness;
+mod witness_artifacts;
+mod witness_export;
+mod witness_regenerator;
+mod witness_stage;
+mod witness_verifier;
 
+#[cfg(any(test, feature = "benchmark"))]
+pub use self::audit_log::{AuditLogEntry, AuditedOperation};
+pub use self::benchmark::{BenchmarkConfig, BenchmarkReport, BenchmarkStage};
+#[cfg(feature = "grpc")]
+pub use self::grpc::TreeGrpcServer;
+pub use self::helpers::CommittedTreeState;
 pub(crate) use self::helpers::L1BatchWithLogs;
+pub use self::initial_writes_repair::{
+    reconcile_initial_writes, InitialWritesReconciliation, RepairedInitialWrite,
+};
+pub use self::leaf_value::LeafValue;
+pub use self::metrics::set_stage_latency_buckets;
+pub use self::notifications::{
+    L1BatchNotification, ProcessedL1Batch, ProcessedL1BatchesReceiver, TreeRevert,
+};
+pub use self::save_latency_slo::SaveLatencySlo;
+pub use self::snapshot_diff::{diff_snapshots, SnapshotChange, SnapshotDiff, SnapshotEntry};
+pub use self::snapshot_export::{SnapshotExport, SnapshotExporter};
+#[cfg(feature = "sse")]
+pub use self::sse::{RootEvent, TreeRootsSseServer};
+pub use self::tree_versions::{check_tree_versions, TreeVersionsReport};
+pub use self::upload_policy::ObjectStoreUploadConfig;
+pub use self::watched_keys::{WatchedKeyProof, WatchedKeyProofs};
+#[cfg(feature = "webhook")]
+pub use self::webhook::RootWebhookPayload;
+pub use self::witness_artifacts::{verify_witness_artifact, ArtifactVerification};
+pub use self::witness_export::{export_witnesses, read_exported_witness, ExportedWitness};
+pub use self::witness_regenerator::RegeneratedWitness;
+pub use self::witness_verifier::{verify_witness_input, WitnessMismatch, WitnessVerification};
 use self::{
-    helpers::Delayer,
+    helpers::{ConnectionSource, Delayer, IdleMetricsEmitter},
     metrics::{ReportStage, TreeUpdateStage},
+    stall_watchdog::StallWatchdog,
     updater::TreeUpdater,
+    upload_policy::UploadPolicy,
+    upload_queue::{WitnessUploadQueue, WitnessUploader},
+    witness_stage::{WitnessPersister, WitnessStage, WitnessStageWorker},
 };
 use crate::gas_tracker::commit_gas_count_for_l1_batch;
 
@@ -55,6 +128,57 @@ impl MetadataCalculatorModeConfig<'_> {
     }
 }
 
+/// Thresholds on the Merkle tree lag (i.e., the number of sealed L1 batches not yet processed by the tree)
+/// determining the tree health status.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TreeHealthThresholds {
+    /// Lag starting from which the tree is reported as [`HealthStatus::Affected`]. If not set,
+    /// the lag doesn't affect the tree health.
+    pub affected_lag: Option<u32>,
+    /// Lag starting from which the tree is reported as [`HealthStatus::NotReady`]. If not set,
+    /// the lag doesn't affect the tree readiness.
+    pub not_ready_lag: Option<u32>,
+}
+
+impl TreeHealthThresholds {
+    fn status(self, lag: u32) -> HealthStatus {
+        if self
+            .not_ready_lag
+            .map_or(false, |threshold| lag >= threshold)
+        {
+            HealthStatus::NotReady
+        } else if self
+            .affected_lag
+            .map_or(false, |threshold| lag >= threshold)
+        {
+            HealthStatus::Affected
+        } else {
+            HealthStatus::Ready
+        }
+    }
+}
+
+/// Retries of loading data for L1 batches that have a header in Postgres, but neither storage logs
+/// nor protective reads. Such an L1 batch may be partially written to Postgres.
+#[derive(Debug, Clone, Copy)]
+pub struct EmptyL1BatchRetries {
+    /// Maximum number of retries. If the data doesn't appear after all retries, the tree halts with an error.
+    pub max_retries: usize,
+    /// Interval between retries.
+    pub interval: Duration,
+}
+
+/// Thresholds for warning about L1 batches with protective reads vastly outnumbering writes, which may indicate
+/// misconfigured storage log deduplication in the state keeper. Only checked for the full tree.
+#[derive(Debug, Clone, Copy)]
+pub struct ProtectiveReadsThresholds {
+    /// Ratio of protective reads to writes in an L1 batch above which a warning is logged.
+    pub max_ratio: f64,
+    /// Minimum number of protective reads in an L1 batch for it to be checked, so that small L1 batches
+    /// don't produce noise.
+    pub min_reads: usize,
+}
+
 /// Configuration of [`MetadataCalculator`].
 #[derive(Debug)]
 pub struct MetadataCalculatorConfig<'a> {
@@ -71,10 +195,110 @@ pub struct MetadataCalculatorConfig<'a> {
     pub multi_get_chunk_size: usize,
     /// Capacity of RocksDB block cache in bytes. Reasonable values range from ~100 MB to several GB.
     pub block_cache_capacity: usize,
+    /// Limit on the background I/O (flushes and compactions) of RocksDB in bytes per second.
+    /// If not set, background I/O is not limited.
+    pub rocksdb_rate_limit_bytes_per_sec: Option<usize>,
+    /// Interval between refreshing tree gauges (e.g., the tree lag and RocksDB size) if the tree is idle.
+    pub metrics_refresh_interval: Duration,
+    /// Whether to validate consistency of the `initial_writes` table with storage logs for each processed
+    /// range of L1 batches. This is slow and should only be used for diagnostics.
+    pub validate_initial_writes: bool,
+    /// Whether to cross-check leaf indices of repeated writes produced by the tree against the `initial_writes`
+    /// table. Mismatches are logged and reported via metrics.
+    pub validate_repeated_writes: bool,
+    /// Whether to wipe the tree RocksDB and rebuild the tree from Postgres if RocksDB is corrupted.
+    pub auto_rebuild_on_corruption: bool,
+    /// Number of recent tree root hashes cached in memory. If set to 0, caching is disabled.
+    pub root_hash_cache_size: usize,
+    /// URL of the HTTP webhook notified about root hashes of processed L1 batches. If not set,
+    /// the webhook is disabled. Ignored unless the `webhook` crate feature is enabled.
+    pub root_webhook_url: Option<&'a str>,
+    /// Maximum serialized size of a witness input (in bytes) kept in memory in the full mode.
+    /// Larger witnesses are spilled to a temporary file.
+    pub max_in_memory_witness_size: usize,
+    /// Maximum number of touched storage slots in a single L1 batch loaded into memory. If an L1 batch
+    /// touches more slots, tree processing fails with an error.
+    pub max_in_memory_slots: usize,
+    /// `zstd` compression level for witness inputs persisted to the object store.
+    pub witness_compression_level: i32,
+    /// `zstd` compression level for witness inputs spilled to disk. If not set, spilled witnesses
+    /// are not compressed.
+    pub witness_spill_compression_level: Option<i32>,
+    /// Lag thresholds determining the tree health status.
+    pub health_thresholds: TreeHealthThresholds,
+    /// Format of the details reported in the tree health check.
+    pub health_details_format: TreeHealthDetailsFormat,
+    /// Whether to generate witness inputs and persist them to the object store in the full mode.
+    /// If disabled, the tree still operates in the full mode otherwise.
+    pub generate_witness_inputs: bool,
+    /// Maximum protocol version of L1 batches processed by the tree. Encountering an L1 batch
+    /// with a newer protocol version halts the tree with an error.
+    pub max_supported_protocol_version: ProtocolVersionId,
+    /// Path to the directory used as a durable queue of witness inputs awaiting upload to the object store.
+    /// If not set, witness inputs are uploaded by the tree directly.
+    pub witness_upload_queue_path: Option<&'a str>,
+    /// Maximum total size of queued witness inputs in bytes. If the queue is full, the tree waits
+    /// until queued witnesses are uploaded.
+    pub witness_upload_queue_max_size: usize,
+    /// Number of the most frequently written storage keys periodically logged by the tree.
+    /// If not set, write frequencies are not tracked.
+    pub hot_keys_report_top_n: Option<usize>,
+    /// Number of L1 batches over which write frequencies are tracked for each hot keys report.
+    pub hot_keys_report_window: u32,
+    /// Maximum number of L1 batches processed by the tree with witness inputs not yet persisted.
+    /// If this number is reached, the tree waits until witness inputs are persisted.
+    pub max_pending_witnesses: usize,
+    /// Number of witness inputs serialized and persisted concurrently.
+    pub witness_persistence_concurrency: usize,
+    /// Serialized size of a witness input in bytes starting from which it's uploaded to the object store
+    /// in the chunked format.
+    pub witness_chunking_threshold: usize,
+    /// Number of Merkle paths in a single chunk of a witness input uploaded in the chunked format.
+    pub witness_chunk_size: usize,
+    /// Whether to persist witness inputs with deduplicated Merkle path hashes.
+    pub deduplicate_witness_paths: bool,
+    /// Whether to record the protocol version of L1 batches in the header of persisted witness inputs.
+    pub record_witness_protocol_version: bool,
+    /// Maximum number of attempts to acquire a Postgres connection in the tree update loop.
+    pub db_connection_max_attempts: usize,
+    /// Initial backoff between attempts to acquire a Postgres connection in the tree update loop.
+    pub db_connection_backoff: Duration,
+    /// Retries, timeouts and the health threshold for object store requests made when persisting witness inputs.
+    pub object_store: ObjectStoreUploadConfig,
+    /// If set, storage logs loaded for L1 batches with numbers divisible by this value are cross-checked
+    /// against reference logs. Divergences are logged and reported via metrics.
+    pub logs_verification_modulus: Option<u32>,
+    /// Maximum number of previous storage values cached between verified L1 batches.
+    pub logs_verification_cache_capacity: usize,
+    /// Service-level objective on the latency of saving the tree. If set, compliance with the objective
+    /// is reported in the tree health details.
+    pub save_latency_slo: Option<SaveLatencySlo>,
+    /// Handling of mismatches between the next enumeration index produced by the tree and the one
+    /// derived from the `initial_writes` table.
+    pub enumeration_index_check: EnumerationIndexCheck,
+    /// Handling of the tree being ahead of the last sealed L1 batch in Postgres by more than one L1 batch on startup.
+    pub progress_check: EnumerationIndexCheck,
+    /// Handling of inconsistencies in Postgres data detected by the tree, e.g. between initial writes
+    /// produced by the tree and the `initial_writes` table.
+    pub dal_consistency_policy: DalConsistencyPolicy,
+    /// Retries of loading data for L1 batches with a header, but without storage logs or protective reads.
+    /// If not set, such L1 batches are processed as valid empty L1 batches.
+    pub empty_l1_batch_retries: Option<EmptyL1BatchRetries>,
+    /// Thresholds for warning about excessive protective reads in L1 batches processed by the full tree.
+    pub protective_reads_thresholds: ProtectiveReadsThresholds,
+    /// Threshold after which the tree update loop is considered stalled if it doesn't complete an iteration.
+    /// A stalled tree is reported as not ready. If not set, stalls are not detected.
+    pub stall_threshold: Option<Duration>,
+    /// Capacity of the channel notifying subscribers about processed L1 batches. Subscribers falling behind
+    /// by more than this number of notifications miss notifications and need to resync.
+    pub l1_batch_notifications_capacity: usize,
+    /// Hashed storage keys for which Merkle proofs are precomputed and cached after each tree save.
+    /// A cached proof is recomputed only when the corresponding key is written.
+    pub watched_keys: &'a [H256],
 }
 
 impl<'a> MetadataCalculatorConfig<'a> {
-    pub(crate) fn for_main_node(
+    pub fn for_main_node(
         db_config: &'a DBConfig,
         operation_config: &'a OperationsManagerConfig,
         mode: MetadataCalculatorModeConfig<'a>,
@@ -86,14 +310,111 @@ impl<'a> MetadataCalculatorConfig<'a> {
             max_l1_batches_per_iter: db_config.merkle_tree.max_l1_batches_per_iter,
             multi_get_chunk_size: db_config.merkle_tree.multi_get_chunk_size,
             block_cache_capacity: db_config.merkle_tree.block_cache_size(),
+            rocksdb_rate_limit_bytes_per_sec: db_config
+                .merkle_tree
+                .rocksdb_rate_limit_bytes_per_sec,
+            metrics_refresh_interval: db_config.merkle_tree.metrics_refresh_interval(),
+            validate_initial_writes: db_config.merkle_tree.validate_initial_writes,
+            validate_repeated_writes: db_config.merkle_tree.validate_repeated_writes,
+            auto_rebuild_on_corruption: db_config.merkle_tree.auto_rebuild_on_corruption,
+            root_hash_cache_size: db_config.merkle_tree.root_hash_cache_size,
+            root_webhook_url: db_config.merkle_tree.root_webhook_url.as_deref(),
+            max_in_memory_witness_size: db_config.merkle_tree.max_in_memory_witness_size(),
+            max_in_memory_slots: db_config.merkle_tree.max_in_memory_slots,
+            witness_compression_level: db_config.merkle_tree.witness_compression_level,
+            witness_spill_compression_level: db_config.merkle_tree.witness_spill_compression_level,
+            health_thresholds: TreeHealthThresholds {
+                affected_lag: db_config.merkle_tree.health_affected_lag,
+                not_ready_lag: db_config.merkle_tree.health_not_ready_lag,
+            },
+            health_details_format: db_config.merkle_tree.health_details_format,
+            generate_witness_inputs: db_config.merkle_tree.generate_witness_inputs,
+            max_supported_protocol_version: db_config
+                .merkle_tree
+                .max_supported_protocol_version
+                .map_or_else(ProtocolVersionId::next, |version| {
+                    ProtocolVersionId::try_from(version).unwrap_or_else(|_| {
+                        panic!("Unknown max supported protocol version for Merkle tree: {version}")
+                    })
+                }),
+            witness_upload_queue_path: db_config.merkle_tree.witness_upload_queue_path.as_deref(),
+            witness_upload_queue_max_size: db_config.merkle_tree.witness_upload_queue_max_size(),
+            hot_keys_report_top_n: db_config.merkle_tree.hot_keys_report_top_n,
+            hot_keys_report_window: db_config.merkle_tree.hot_keys_report_window,
+            max_pending_witnesses: db_config.merkle_tree.max_pending_witnesses,
+            witness_persistence_concurrency: db_config.merkle_tree.witness_persistence_concurrency,
+            witness_chunking_threshold: db_config.merkle_tree.witness_chunking_threshold(),
+            witness_chunk_size: db_config.merkle_tree.witness_chunk_size,
+            deduplicate_witness_paths: db_config.merkle_tree.deduplicate_witness_paths,
+            record_witness_protocol_version: db_config.merkle_tree.record_witness_protocol_version,
+            db_connection_max_attempts: db_config.merkle_tree.db_connection_max_attempts,
+            db_connection_backoff: db_config.merkle_tree.db_connection_backoff(),
+            object_store: ObjectStoreUploadConfig {
+                max_attempts: db_config.merkle_tree.object_store_max_attempts,
+                initial_backoff: db_config.merkle_tree.object_store_backoff(),
+                request_timeout: db_config.merkle_tree.object_store_request_timeout(),
+                failure_threshold: db_config.merkle_tree.object_store_failure_threshold,
+            },
+            logs_verification_modulus: db_config.merkle_tree.logs_verification_modulus,
+            logs_verification_cache_capacity: db_config
+                .merkle_tree
+                .logs_verification_cache_capacity,
+            save_latency_slo: db_config.merkle_tree.save_latency_slo_ms.map(|target_ms| {
+                SaveLatencySlo {
+                    target: Duration::from_millis(target_ms),
+                    window: db_config.merkle_tree.save_latency_slo_window,
+                    min_compliance: db_config.merkle_tree.save_latency_slo_min_compliance,
+                }
+            }),
+            enumeration_index_check: db_config.merkle_tree.enumeration_index_check,
+            progress_check: db_config.merkle_tree.progress_check,
+            dal_consistency_policy: db_config.merkle_tree.dal_consistency_policy,
+            empty_l1_batch_retries: match db_config.merkle_tree.empty_l1_batch_policy {
+                EmptyL1BatchPolicy::Accept => None,
+                EmptyL1BatchPolicy::Retry => Some(EmptyL1BatchRetries {
+                    max_retries: db_config.merkle_tree.empty_l1_batch_max_retries,
+                    interval: db_config.merkle_tree.empty_l1_batch_retry_interval(),
+                }),
+            },
+            protective_reads_thresholds: ProtectiveReadsThresholds {
+                max_ratio: db_config.merkle_tree.excessive_protective_reads_ratio,
+                min_reads: db_config.merkle_tree.min_checked_protective_reads,
+            },
+            stall_threshold: db_config.merkle_tree.stall_threshold(),
+            l1_batch_notifications_capacity: db_config.merkle_tree.l1_batch_notifications_capacity,
+            watched_keys: &db_config.merkle_tree.watched_keys,
         }
     }
 }
 
+/// Merkle proof for a single storage key at a certain L1 batch produced by
+/// [`MetadataCalculator::archive_proofs()`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedProof {
+    /// L1 batch number corresponding to the tree version the proof is obtained for.
+    pub l1_batch_number: L1BatchNumber,
+    /// Tree root hash after processing the L1 batch.
+    pub root_hash: H256,
+    /// Hashed storage key.
+    pub hashed_key: H256,
+    /// Value hash for the key; zero if the key is missing from the tree.
+    pub value_hash: H256,
+    /// Enumeration index of the key; zero if the key is missing from the tree.
+    pub leaf_index: u64,
+    /// Merkle path for the key (see `TreeEntryWithProof` in the Merkle tree crate for details).
+    pub merkle_path: Vec<H256>,
+}
+
 #[derive(Debug)]
 pub struct MetadataCalculator {
     updater: TreeUpdater,
     delayer: Delayer,
+    idle_metrics: IdleMetricsEmitter,
+    stall_watchdog: Option<StallWatchdog>,
+    #[cfg(feature = "webhook")]
+    root_webhook_worker: Option<webhook::RootWebhookWorker>,
+    witness_stage_worker: Option<WitnessStageWorker>,
+    witness_uploader: Option<WitnessUploader>,
     health_updater: HealthUpdater,
 }
 
@@ -104,16 +425,83 @@ impl MetadataCalculator {
 
         let mode = config.mode.to_mode();
         let object_store = match config.mode {
-            MetadataCalculatorModeConfig::Full { store_factory } => {
+            MetadataCalculatorModeConfig::Full { store_factory }
+                if config.generate_witness_inputs =>
+            {
                 Some(store_factory.create_store().await)
             }
-            MetadataCalculatorModeConfig::Lightweight => None,
+            MetadataCalculatorModeConfig::Full { .. }
+            | MetadataCalculatorModeConfig::Lightweight => None,
+        };
+        let upload_policy = UploadPolicy::new(config.object_store);
+        let (upload_queue, witness_uploader) = match (config.mode, config.witness_upload_queue_path)
+        {
+            (MetadataCalculatorModeConfig::Full { store_factory }, Some(path))
+                if object_store.is_some() =>
+            {
+                let queue =
+                    WitnessUploadQueue::new(path.into(), config.witness_upload_queue_max_size)
+                        .expect("failed initializing witness upload queue");
+                let uploader = WitnessUploader::new(
+                    queue.clone(),
+                    store_factory.create_store().await,
+                    upload_policy.clone(),
+                );
+                (Some(queue), Some(uploader))
+            }
+            _ => (None, None),
+        };
+        let (witness_stage, witness_stage_worker) = match object_store {
+            Some(object_store) => {
+                let persister = WitnessPersister::new(
+                    object_store,
+                    upload_queue,
+                    config.max_in_memory_witness_size,
+                    config.witness_compression_level,
+                    config.witness_spill_compression_level,
+                    config.witness_chunking_threshold,
+                    config.witness_chunk_size,
+                    config.deduplicate_witness_paths,
+                    config.record_witness_protocol_version,
+                    upload_policy,
+                );
+                let (stage, worker) = WitnessStage::new(
+                    persister,
+                    config.max_pending_witnesses,
+                    config.witness_persistence_concurrency,
+                );
+                (Some(stage), Some(worker))
+            }
+            None => (None, None),
         };
-        let updater = TreeUpdater::new(mode, config, object_store).await;
+        let updater = TreeUpdater::new(mode, config, witness_stage).await;
+        #[cfg(feature = "webhook")]
+        let (updater, root_webhook_worker) = match config.root_webhook_url {
+            Some(url) => {
+                let (sender, worker) = webhook::RootWebhookWorker::new(url.to_owned());
+                (updater.with_root_webhook(sender), Some(worker))
+            }
+            None => (updater, None),
+        };
+        #[cfg(not(feature = "webhook"))]
+        if config.root_webhook_url.is_some() {
+            tracing::warn!(
+                "Root hash webhook URL is configured, but the `webhook` feature is disabled; the webhook will not be called"
+            );
+        }
         let (_, health_updater) = ReactiveHealthCheck::new("tree");
         Self {
             updater,
             delayer: Delayer::new(config.delay_interval),
+            idle_metrics: IdleMetricsEmitter::new(
+                config.db_path.into(),
+                config.metrics_refresh_interval,
+            ),
+            stall_watchdog: config.stall_threshold.map(StallWatchdog::new),
+            #[cfg(feature = "webhook")]
+            root_webhook_worker,
+            witness_stage_worker,
+            witness_uploader,
             health_updater,
         }
     }
@@ -123,23 +511,289 @@ impl MetadataCalculator {
         self.health_updater.subscribe()
     }
 
+    /// Subscribes this calculator to reorg signals, e.g. from [`ReorgDetector`](crate::reorg_detector::ReorgDetector).
+    /// Each signal carries the last L1 batch consistent with the main node. Once a signal is received,
+    /// the calculator stops after the L1 batch it is currently processing and doesn't process L1 batches
+    /// after the last correct one; its health is set to not ready. After these L1 batches are reverted
+    /// in Postgres, the calculator truncates the tree if necessary and resumes automatically.
+    pub fn with_reorg_receiver(mut self, receiver: watch::Receiver<Option<L1BatchNumber>>) -> Self {
+        self.updater.set_reorg_receiver(receiver);
+        self
+    }
+
+    /// Subscribes to notifications about L1 batches processed by the tree and tree reverts. Notifications are sent
+    /// after the processed L1 batches (or the revert) are saved to RocksDB. Sending notifications never blocks the calculator;
+    /// a subscriber falling behind by more than the configured capacity misses notifications
+    /// and receives [`L1BatchNotification::Lagged`] instead, which reports the latest missed revert (if any).
+    pub fn subscribe_processed_l1_batches(&self) -> ProcessedL1BatchesReceiver {
+        self.updater.subscribe_processed_l1_batches()
+    }
+
+    /// Returns a read-only handle to the Merkle tree that can be used concurrently with the running
+    /// calculator. The handle only observes tree versions saved to RocksDB; use it together with
+    /// [`Self::subscribe_committed_state()`] to get a consistent view of the tree.
+    pub fn tree_reader(&self) -> ZkSyncTreeReader {
+        self.updater.tree_reader()
+    }
+
+    /// Subscribes to the latest tree state saved to RocksDB. The state is `None` until the tree
+    /// is initialized; the receiver is closed once the calculator terminates.
+    pub fn subscribe_committed_state(&self) -> watch::Receiver<Option<CommittedTreeState>> {
+        self.updater.subscribe_committed_state()
+    }
+
+    /// Returns a handle to Merkle proofs for watched keys cached by the tree. Proofs are computed
+    /// after the tree is saved; a proof is recomputed only when the corresponding key is written,
+    /// so it may correspond to an earlier L1 batch than the latest one. If no keys are watched,
+    /// the returned handle is always empty.
+    pub fn watched_key_proofs(&self) -> WatchedKeyProofs {
+        self.updater.watched_key_proofs()
+    }
+
+    /// Archives Merkle proofs for the specified `keys` for each L1 batch in the specified range,
+    /// iterating from the newest L1 batch to the oldest one. Proofs are written to `writer`
+    /// as JSON lines, one [`ArchivedProof`] per line. Returns the number of written proofs.
+    ///
+    /// This method is intended for one-off jobs (e.g., building a historical proof archive)
+    /// and cannot be used while the calculator is running.
+    pub async fn archive_proofs(
+        &mut self,
+        l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+        keys: &[StorageKey],
+        writer: &mut impl io::Write,
+    ) -> anyhow::Result<usize> {
+        let tree = self.updater.tree_mut();
+        let tree_keys: Vec<_> = keys.iter().map(StorageKey::hashed_key_u256).collect();
+        let mut proof_count = 0;
+        for number in (l1_batch_numbers.start().0..=l1_batch_numbers.end().0).rev() {
+            let l1_batch_number = L1BatchNumber(number);
+            let root_hash = tree.root_hash_at(l1_batch_number).with_context(|| {
+                format!("L1 batch #{l1_batch_number} is not processed by the tree")
+            })?;
+            let entries = tree
+                .entries_with_proofs(l1_batch_number, tree_keys.clone())
+                .await
+                .with_context(|| {
+                    format!("failed getting proofs for L1 batch #{l1_batch_number}")
+                })?;
+
+            for (key, entry) in keys.iter().zip(entries) {
+                let proof = ArchivedProof {
+                    l1_batch_number,
+                    root_hash,
+                    hashed_key: key.hashed_key(),
+                    value_hash: entry.base.value_hash,
+                    leaf_index: entry.base.leaf_index,
+                    merkle_path: entry.merkle_path,
+                };
+                serde_json::to_writer(&mut *writer, &proof)?;
+                writer.write_all(b"\n")?;
+                proof_count += 1;
+            }
+            tracing::info!(
+                "Archived proofs for {} keys at L1 batch #{l1_batch_number}",
+                keys.len()
+            );
+        }
+        writer.flush()?;
+        Ok(proof_count)
+    }
+
+    /// Computes a fingerprint of the latest Merkle tree state, which can be used to compare tree states
+    /// across nodes; see `ZkSyncTree::state_fingerprint()` for details. Returns the number of the last
+    /// L1 batch processed by the tree together with the fingerprint.
+    ///
+    /// This method visits the entire tree, so it is slow. It cannot be used while the calculator is running.
+    pub async fn tree_state_fingerprint(&mut self) -> (Option<L1BatchNumber>, H256) {
+        let tree = self.updater.tree_mut();
+        let next_l1_batch_number = tree.next_l1_batch_number();
+        let last_l1_batch_number =
+            (next_l1_batch_number > L1BatchNumber(0)).then(|| next_l1_batch_number - 1);
+        (last_l1_batch_number, tree.state_fingerprint().await)
+    }
+
+    /// Reads values for the specified `keys` from the Merkle tree version after the specified L1 batch.
+    /// Keys not present in the tree version are returned as `None`. This method cannot be used while
+    /// the calculator is running.
+    pub async fn tree_values(
+        &mut self,
+        l1_batch_number: L1BatchNumber,
+        keys: &[StorageKey],
+    ) -> anyhow::Result<Vec<Option<H256>>> {
+        let tree_keys = keys.iter().map(StorageKey::hashed_key_u256).collect();
+        self.updater
+            .tree_mut()
+            .values(l1_batch_number, tree_keys)
+            .await
+            .with_context(|| format!("failed reading tree values for L1 batch #{l1_batch_number}"))
+    }
+
+    /// Prunes Merkle tree versions before the specified L1 batch, so that they no longer take up space
+    /// in RocksDB. Pruned versions can no longer be used to serve proofs or regenerate witness inputs.
+    /// Pruning is recorded in the tree audit log. Returns the number of pruned node keys.
+    ///
+    /// This method is intended for maintenance and cannot be used while the calculator is running.
+    pub async fn prune_tree(&mut self, first_l1_batch_to_keep: L1BatchNumber) -> usize {
+        self.updater
+            .tree_mut()
+            .prune_up_to(first_l1_batch_to_keep)
+            .await
+    }
+
+    /// Defragments the Merkle tree RocksDB: copies the latest tree version to a fresh RocksDB instance with nodes
+    /// laid out in the key order and swaps it in place of the current instance. Previous tree versions are discarded,
+    /// as if the tree was pruned up to the latest L1 batch. Defragmentation is recorded in the tree audit log.
+    /// Returns the number of copied tree nodes.
+    ///
+    /// This method is intended for maintenance and cannot be used while the calculator is running.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if handles obtained via [`Self::tree_reader()`] are alive, since they would keep observing
+    /// the replaced RocksDB instance. Also returns an error if the defragmented copy doesn't match the tree,
+    /// or on I/O errors; the tree is not modified in these cases.
+    pub async fn defragment_tree(&mut self) -> anyhow::Result<u64> {
+        self.updater.tree_mut().defragment().await
+    }
+
+    /// Saves the Merkle tree together with a cursor of a tree consumer in a single RocksDB write batch
+    /// and fsyncs the RocksDB WAL. This allows consumers to track side effects performed for the L1 batches
+    /// processed by the tree (e.g., "the tree is saved up to L1 batch N, and its outputs for L1 batch N
+    /// are exported") in a crash-consistent way. The cursor can be read back using [`Self::tree_cursor()`].
+    ///
+    /// This method cannot be used while the calculator is running.
+    pub async fn save_tree_with_cursor(&mut self, cursor_key: Vec<u8>, cursor_value: Vec<u8>) {
+        self.updater
+            .tree_mut()
+            .save_with_cursor(MerkleTreeColumnFamily::Cursors, cursor_key, cursor_value)
+            .await;
+    }
+
+    /// Reads a cursor of a tree consumer persisted via [`Self::save_tree_with_cursor()`].
+    pub fn tree_cursor(&self, cursor_key: &[u8]) -> Option<Vec<u8>> {
+        self.updater
+            .tree()
+            .cursor(MerkleTreeColumnFamily::Cursors, cursor_key)
+    }
+
+    /// Reads the audit log of destructive operations on the Merkle tree (reverts, pruning and defragmentation)
+    /// in the order the operations were performed.
+    pub fn tree_audit_log(&self) -> anyhow::Result<Vec<AuditLogEntry>> {
+        self.updater.tree().audit_log()
+    }
+
+    /// Estimates the number of new leaves (i.e., initial writes) that an L1 batch with the specified
+    /// `storage_logs` would insert into the tree, without processing the L1 batch. This can be used
+    /// to plan batching and memory usage. It cannot be used while the calculator is running.
+    pub async fn estimate_new_leaves(&mut self, storage_logs: Vec<StorageLog>) -> u64 {
+        self.updater
+            .tree_mut()
+            .estimate_new_leaves(storage_logs)
+            .await
+    }
+
+    /// Runs this calculator, loading L1 batch data from and persisting tree outputs to `pool`.
+    /// Multiple calculators (e.g., for a shadow chain) can run in a single process as long as they use
+    /// distinct tree directories and pools; a pool can be pointed at a secondary Postgres schema
+    /// using [`ConnectionPoolBuilder::set_schema()`](zksync_dal::connection::ConnectionPoolBuilder::set_schema()).
+    /// When the calculator is run as a server component, the schema is set via the `postgres_schema` option
+    /// of the Merkle tree config.
     pub async fn run(
         self,
         pool: ConnectionPool,
         prover_pool: ConnectionPool,
         stop_receiver: watch::Receiver<bool>,
     ) -> anyhow::Result<()> {
-        self.updater
-            .loop_updating_tree(
-                self.delayer,
-                &pool,
-                &prover_pool,
-                stop_receiver,
-                self.health_updater,
-            )
+        self.run_with_replica(pool, None, prover_pool, stop_receiver)
             .await
     }
 
+    /// Runs this calculator loading L1 batch data processed by the tree from `replica_pool` (if specified)
+    /// rather than from `pool`. Tree outputs are always written using `pool`.
+    pub async fn run_with_replica(
+        self,
+        pool: ConnectionPool,
+        replica_pool: Option<ConnectionPool>,
+        prover_pool: ConnectionPool,
+        stop_receiver: watch::Receiver<bool>,
+    ) -> anyhow::Result<()> {
+        let (next_l1_batch_sender, next_l1_batch_receiver) = watch::channel(None);
+        // The witness uploader is stopped once the updater terminates (either because of the stop signal,
+        // or because of an error) and all pending witnesses are handed over to the upload queue.
+        let (uploader_stop_sender, uploader_stop_receiver) = watch::channel(false);
+        let health_updater = self.health_updater;
+        let heartbeat_receiver = self.updater.subscribe_heartbeat();
+        let update_task = self.updater.loop_updating_tree(
+            self.delayer,
+            &pool,
+            replica_pool
+                .as_ref()
+                .map(|pool| pool as &dyn ConnectionSource),
+            Some(&prover_pool),
+            stop_receiver.clone(),
+            &health_updater,
+            next_l1_batch_sender,
+        );
+        // The watchdog terminates after the updater is dropped.
+        let watchdog_stop_receiver = stop_receiver.clone();
+        let watchdog_task = async {
+            if let Some(watchdog) = self.stall_watchdog {
+                watchdog
+                    .run(heartbeat_receiver, &health_updater, watchdog_stop_receiver)
+                    .await;
+            }
+        };
+        // The witness stage worker terminates after the updater is dropped and all pending witnesses
+        // are persisted.
+        let witness_stage_task = async {
+            if let Some(worker) = self.witness_stage_worker {
+                worker.run(&pool, &prover_pool).await
+            } else {
+                Ok(())
+            }
+        };
+        let update_task = async {
+            let (results, ()) =
+                future::join(future::join(update_task, witness_stage_task), watchdog_task).await;
+            uploader_stop_sender.send_replace(true);
+            results
+        };
+        #[cfg(feature = "webhook")]
+        let webhook_stop_receiver = stop_receiver.clone();
+        let idle_metrics_task =
+            self.idle_metrics
+                .run(pool.clone(), next_l1_batch_receiver, stop_receiver);
+        // The webhook worker terminates after the updater is dropped and all queued notifications are sent,
+        // or once the stop signal is received.
+        #[cfg(feature = "webhook")]
+        let root_webhook_task = async {
+            if let Some(worker) = self.root_webhook_worker {
+                worker.run(webhook_stop_receiver).await;
+            }
+        };
+        #[cfg(not(feature = "webhook"))]
+        let root_webhook_task = future::ready(());
+        let witness_uploader_task = async {
+            if let Some(uploader) = self.witness_uploader {
+                uploader
+                    .run(&pool, &prover_pool, uploader_stop_receiver)
+                    .await
+            } else {
+                Ok(())
+            }
+        };
+        let ((update_result, stage_result), (), (), uploader_result) = future::join4(
+            update_task,
+            idle_metrics_task,
+            root_webhook_task,
+            witness_uploader_task,
+        )
+        .await;
+        update_result?;
+        stage_result.context("witness stage failed")?;
+        uploader_result.context("witness uploader failed")
+    }
+
     /// This is used to improve L1 gas estimation for the commit operation. The estimations are computed
     /// in the State Keeper, where storage writes aren't yet deduplicated, whereas L1 batch metadata
     /// contains deduplicated storage writes.
@@ -0,0 +1,350 @@
+//! Notifications about L1 batches processed by the tree and tree reverts for downstream consumers.
+
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    watch,
+};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zksync_types::{L1BatchNumber, H256};
+
+use super::metrics::METRICS;
+
+/// Information about an L1 batch processed by the tree and saved to RocksDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessedL1Batch {
+    /// Number of the processed L1 batch.
+    pub l1_batch_number: L1BatchNumber,
+    /// Tree root hash after processing the L1 batch.
+    pub root_hash: H256,
+    /// Enumeration index to be assigned to the next key inserted into the tree.
+    pub next_enumeration_index: u64,
+}
+
+/// Information about a revert of the tree saved to RocksDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeRevert {
+    /// Last L1 batch kept in the tree after the revert.
+    pub reverted_to: L1BatchNumber,
+    /// Number of L1 batches removed from the tree.
+    pub reverted_l1_batch_count: u32,
+    /// Tree root hash before the revert.
+    pub old_root_hash: H256,
+    /// Tree root hash after the revert, i.e. the root hash of the `reverted_to` L1 batch.
+    pub new_root_hash: H256,
+    /// Milliseconds since the Unix epoch when the revert was performed.
+    pub timestamp_ms: u64,
+}
+
+impl TreeRevert {
+    pub(super) fn new(
+        reverted_to: L1BatchNumber,
+        reverted_l1_batch_count: u32,
+        old_root_hash: H256,
+        new_root_hash: H256,
+    ) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |timestamp| timestamp.as_millis() as u64);
+        Self {
+            reverted_to,
+            reverted_l1_batch_count,
+            old_root_hash,
+            new_root_hash,
+            timestamp_ms,
+        }
+    }
+
+    /// Returns the label of the revert depth bucket used in metrics.
+    pub(super) fn depth_bucket(&self) -> &'static str {
+        match self.reverted_l1_batch_count {
+            0 | 1 => "1",
+            2..=10 => "2-10",
+            11..=100 => "11-100",
+            _ => "100+",
+        }
+    }
+}
+
+/// Notification received by a [`ProcessedL1BatchesReceiver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L1BatchNotification {
+    /// An L1 batch was processed by the tree.
+    Processed(ProcessedL1Batch),
+    /// The tree was reverted, e.g. after a reorg or a Postgres revert. Notifications about L1 batches
+    /// processed after the revert start from the L1 batch following [`TreeRevert::reverted_to`].
+    Reverted(TreeRevert),
+    /// The receiver has fallen behind, and the specified number of notifications was dropped for it.
+    /// All notifications buffered for the receiver are dropped as well, so that receiving continues
+    /// from the next notification sent after this one. The subscriber should resync its state with the tree,
+    /// e.g. using [`MetadataCalculator::subscribe_committed_state()`].
+    ///
+    /// [`MetadataCalculator::subscribe_committed_state()`]: super::MetadataCalculator::subscribe_committed_state()
+    Lagged {
+        /// Number of dropped notifications.
+        missed: u64,
+        /// Latest tree revert among the dropped notifications, if any. Unlike other notifications,
+        /// reverts are never lost for a lagging subscriber.
+        last_revert: Option<TreeRevert>,
+    },
+}
+
+/// Latest tree revert retained by [`L1BatchNotifier`] for lagging subscribers.
+#[derive(Debug, Clone, Copy, Default)]
+struct LastRevert {
+    /// Total number of reverts sent by the notifier.
+    count: u64,
+    revert: Option<TreeRevert>,
+}
+
+/// Sending part of the notification channel. The channel has a fixed capacity (rounded up to a power of two);
+/// sending never blocks tree processing. If a subscriber falls behind by more than the capacity, notifications
+/// are dropped for it, and it receives [`L1BatchNotification::Lagged`] instead. The latest revert is additionally
+/// kept in a `watch` channel, so that it can be reported to lagging subscribers.
+#[derive(Debug)]
+pub(super) struct L1BatchNotifier {
+    sender: broadcast::Sender<L1BatchNotification>,
+    last_revert: watch::Sender<LastRevert>,
+}
+
+impl L1BatchNotifier {
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "L1 batch notifications capacity is misconfigured to be 0; please update it to positive value"
+        );
+        Self {
+            sender: broadcast::channel(capacity).0,
+            last_revert: watch::channel(LastRevert::default()).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> ProcessedL1BatchesReceiver {
+        let last_revert = self.last_revert.subscribe();
+        let observed_revert_count = last_revert.borrow().count;
+        ProcessedL1BatchesReceiver {
+            receiver: self.sender.subscribe(),
+            last_revert,
+            observed_revert_count,
+        }
+    }
+
+    pub fn notify(&self, l1_batch: ProcessedL1Batch) {
+        // An error means that there are no subscribers, which is fine.
+        self.sender
+            .send(L1BatchNotification::Processed(l1_batch))
+            .ok();
+    }
+
+    pub fn notify_revert(&self, revert: TreeRevert) {
+        // The revert must be recorded before it's broadcast, so that a subscriber lagging
+        // on the broadcast notification is guaranteed to observe it.
+        self.last_revert.send_modify(|last_revert| {
+            last_revert.count += 1;
+            last_revert.revert = Some(revert);
+        });
+        self.sender.send(L1BatchNotification::Reverted(revert)).ok();
+    }
+}
+
+/// Receiver of notifications about L1 batches processed by the tree and tree reverts, obtained via
+/// [`MetadataCalculator::subscribe_processed_l1_batches()`](super::MetadataCalculator::subscribe_processed_l1_batches()).
+#[derive(Debug)]
+pub struct ProcessedL1BatchesReceiver {
+    receiver: broadcast::Receiver<L1BatchNotification>,
+    last_revert: watch::Receiver<LastRevert>,
+    /// Number of reverts sent by the notifier that were observed by this receiver.
+    observed_revert_count: u64,
+}
+
+impl ProcessedL1BatchesReceiver {
+    /// Receives the next notification. Returns `None` once the calculator has terminated
+    /// and all retained notifications are received.
+    pub async fn recv(&mut self) -> Option<L1BatchNotification> {
+        match self.receiver.recv().await {
+            Ok(notification) => {
+                if matches!(notification, L1BatchNotification::Reverted(_)) {
+                    self.observed_revert_count += 1;
+                }
+                Some(notification)
+            }
+            Err(RecvError::Lagged(missed)) => {
+                // Buffered notifications are dropped as well since the subscriber needs to resync anyway,
+                // and the dropped revert (if any) would be reported out of order otherwise.
+                let missed = missed + self.receiver.len() as u64;
+                self.receiver = self.receiver.resubscribe();
+                let last_revert = *self.last_revert.borrow();
+                let last_revert = if last_revert.count > self.observed_revert_count {
+                    self.observed_revert_count = last_revert.count;
+                    last_revert.revert
+                } else {
+                    None
+                };
+
+                tracing::warn!(
+                    "Subscriber to processed L1 batches has fallen behind; {missed} notifications were dropped, \
+                     last dropped revert: {last_revert:?}"
+                );
+                METRICS.l1_batch_notifications_dropped.inc_by(missed);
+                Some(L1BatchNotification::Lagged {
+                    missed,
+                    last_revert,
+                })
+            }
+            Err(RecvError::Closed) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processed_l1_batch(number: u32) -> ProcessedL1Batch {
+        ProcessedL1Batch {
+            l1_batch_number: L1BatchNumber(number),
+            root_hash: H256::from_low_u64_be(number.into()),
+            next_enumeration_index: u64::from(number) * 10 + 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_subscriber_observes_lag() {
+        let notifier = L1BatchNotifier::new(4);
+        let mut receiver = notifier.subscribe();
+        // Sending must not block even though the subscriber doesn't receive notifications.
+        for number in 0..10 {
+            notifier.notify(processed_l1_batch(number));
+        }
+        drop(notifier);
+
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Lagged {
+                missed: 10,
+                last_revert: None,
+            })
+        );
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn lagging_subscriber_resumes_from_new_notifications() {
+        let notifier = L1BatchNotifier::new(2);
+        let mut receiver = notifier.subscribe();
+        for number in 0..3 {
+            notifier.notify(processed_l1_batch(number));
+        }
+
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Lagged {
+                missed: 3,
+                last_revert: None,
+            })
+        );
+        notifier.notify(processed_l1_batch(3));
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Processed(processed_l1_batch(3)))
+        );
+    }
+
+    #[tokio::test]
+    async fn lagging_subscriber_observes_revert() {
+        let notifier = L1BatchNotifier::new(2);
+        let mut receiver = notifier.subscribe();
+        let mut lagging_receiver = notifier.subscribe();
+        notifier.notify(processed_l1_batch(3));
+        let revert = TreeRevert::new(
+            L1BatchNumber(1),
+            2,
+            H256::repeat_byte(3),
+            H256::repeat_byte(1),
+        );
+        notifier.notify_revert(revert);
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Processed(processed_l1_batch(3)))
+        );
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Reverted(revert))
+        );
+
+        // The revert notification is pushed out of the channel buffer by these notifications.
+        for number in 2..5 {
+            notifier.notify(processed_l1_batch(number));
+        }
+        drop(notifier);
+
+        assert_eq!(
+            lagging_receiver.recv().await,
+            Some(L1BatchNotification::Lagged {
+                missed: 5,
+                last_revert: Some(revert),
+            })
+        );
+        assert_eq!(lagging_receiver.recv().await, None);
+        // The revert must not be reported again to a receiver that has already observed it.
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Lagged {
+                missed: 3,
+                last_revert: None,
+            })
+        );
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[test]
+    fn notifying_without_subscribers() {
+        let notifier = L1BatchNotifier::new(1);
+        notifier.notify(processed_l1_batch(1));
+        let mut receiver = notifier.subscribe();
+        notifier.notify(processed_l1_batch(2));
+        assert_eq!(
+            receiver.receiver.try_recv().unwrap(),
+            L1BatchNotification::Processed(processed_l1_batch(2))
+        );
+    }
+
+    #[tokio::test]
+    async fn revert_notifications_are_ordered_with_processed_l1_batches() {
+        let notifier = L1BatchNotifier::new(4);
+        let mut receiver = notifier.subscribe();
+        notifier.notify(processed_l1_batch(3));
+        let revert = TreeRevert::new(
+            L1BatchNumber(1),
+            2,
+            H256::repeat_byte(3),
+            H256::repeat_byte(1),
+        );
+        notifier.notify_revert(revert);
+        notifier.notify(processed_l1_batch(2));
+        drop(notifier);
+
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Processed(processed_l1_batch(3)))
+        );
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Reverted(revert))
+        );
+        assert_eq!(
+            receiver.recv().await,
+            Some(L1BatchNotification::Processed(processed_l1_batch(2)))
+        );
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[test]
+    fn revert_depth_buckets() {
+        let buckets = [1, 2, 10, 11, 100, 101].map(|count| {
+            TreeRevert::new(L1BatchNumber(0), count, H256::zero(), H256::zero()).depth_bucket()
+        });
+        assert_eq!(buckets, ["1", "2-10", "2-10", "11-100", "11-100", "100+"]);
+    }
+}
@@ -0,0 +1,81 @@
+//! Halting the tree update loop on a reorg signalled by the reorg detector.
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+use zksync_health_check::{Health, HealthStatus};
+use zksync_types::L1BatchNumber;
+
+use super::metrics::METRICS;
+
+/// Health check details reported while the tree update loop is halted because of a reorg.
+#[derive(Debug, Serialize)]
+struct ReorgHaltDetails {
+    halted_on_reorg: bool,
+    last_correct_l1_batch: L1BatchNumber,
+    next_l1_batch: L1BatchNumber,
+}
+
+/// Tracks signals from the reorg detector. Each signal carries the last L1 batch that is consistent
+/// with the main node; once a signal is received, the tree must not process L1 batches after it
+/// until the revert is executed. Without halting, the tree would keep processing L1 batches that
+/// are about to be reverted, creating more work to undo.
+#[derive(Debug)]
+pub(super) struct ReorgHalt {
+    receiver: watch::Receiver<Option<L1BatchNumber>>,
+    last_signal: Option<L1BatchNumber>,
+    /// Last correct L1 batch if the tree is currently halted.
+    halted_at: Option<L1BatchNumber>,
+}
+
+impl ReorgHalt {
+    pub fn new(receiver: watch::Receiver<Option<L1BatchNumber>>) -> Self {
+        Self {
+            receiver,
+            last_signal: None,
+            halted_at: None,
+        }
+    }
+
+    /// Checks for a new reorg signal. Returns the last correct L1 batch if the tree is halted.
+    ///
+    /// A signal that was already handled (i.e., the tree has resumed after the corresponding revert)
+    /// does not halt the tree again. The receiver is not required to be open; the reorg detector
+    /// may terminate after sending a signal.
+    pub fn poll(&mut self) -> Option<L1BatchNumber> {
+        let signal = *self.receiver.borrow_and_update();
+        if signal != self.last_signal {
+            self.last_signal = signal;
+            if let Some(last_correct_l1_batch) = signal {
+                tracing::warn!(
+                    "Reorg detected; Merkle tree will not process L1 batches after the last correct \
+                     L1 batch #{last_correct_l1_batch} until they are reverted"
+                );
+                METRICS.reorg_halts.inc();
+                self.halted_at = Some(last_correct_l1_batch);
+            }
+        }
+        self.halted_at
+    }
+
+    /// Resumes the tree after the revert is executed.
+    pub fn resume(&mut self) {
+        if let Some(last_correct_l1_batch) = self.halted_at.take() {
+            tracing::info!(
+                "L1 batches after the last correct L1 batch #{last_correct_l1_batch} were reverted; \
+                 resuming Merkle tree updates"
+            );
+        }
+    }
+
+    /// Returns health of the tree if it is halted.
+    pub fn health(&self, next_l1_batch: L1BatchNumber) -> Option<Health> {
+        let last_correct_l1_batch = self.halted_at?;
+        let details = ReorgHaltDetails {
+            halted_on_reorg: true,
+            last_correct_l1_batch,
+            next_l1_batch,
+        };
+        Some(Health::from(HealthStatus::NotReady).with_details(details))
+    }
+}
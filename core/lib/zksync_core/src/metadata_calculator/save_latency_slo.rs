@@ -0,0 +1,150 @@
+//! Service-level objective (SLO) on the latency of saving the tree.
+
+use std::{collections::VecDeque, time::Duration};
+
+use super::metrics::METRICS;
+
+/// Service-level objective on the latency of saving processed L1 batches to RocksDB.
+#[derive(Debug, Clone, Copy)]
+pub struct SaveLatencySlo {
+    /// Target latency of a single save.
+    pub target: Duration,
+    /// Number of most recent saves used to compute the SLO compliance.
+    pub window: usize,
+    /// Minimum fraction of recent saves meeting the target. If the compliance drops below this value,
+    /// an otherwise ready tree is reported as [`HealthStatus::Affected`].
+    ///
+    /// [`HealthStatus::Affected`]: zksync_health_check::HealthStatus::Affected
+    pub min_compliance: f64,
+}
+
+/// Tracks the fraction of recent saves that met the [`SaveLatencySlo`] target.
+#[derive(Debug)]
+pub(super) struct SaveLatencyTracker {
+    slo: SaveLatencySlo,
+    /// Whether each of the recent saves met the target, from the oldest to the newest one.
+    recent_saves: VecDeque<bool>,
+    met_count: usize,
+}
+
+impl SaveLatencyTracker {
+    pub fn new(slo: SaveLatencySlo) -> Self {
+        assert!(slo.window > 0, "Save latency SLO window must be positive");
+        assert!(
+            (0.0..=1.0).contains(&slo.min_compliance),
+            "Minimum save latency SLO compliance must be in [0, 1]"
+        );
+        Self {
+            slo,
+            recent_saves: VecDeque::with_capacity(slo.window),
+            met_count: 0,
+        }
+    }
+
+    /// Records latency of a single save.
+    pub fn observe(&mut self, latency: Duration) {
+        let met = latency <= self.slo.target;
+        if !met {
+            tracing::info!(
+                "Saving Merkle tree took {latency:?}, exceeding the target latency {:?}",
+                self.slo.target
+            );
+            METRICS.save_latency_slo_violations.inc();
+        }
+
+        self.recent_saves.push_back(met);
+        self.met_count += usize::from(met);
+        if self.recent_saves.len() > self.slo.window {
+            let evicted_met = self.recent_saves.pop_front().unwrap();
+            self.met_count -= usize::from(evicted_met);
+        }
+        if let Some(compliance) = self.compliance() {
+            METRICS.save_latency_slo_compliance.set(compliance);
+        }
+    }
+
+    /// Returns the fraction of recent saves that met the target, or `None` if no saves were recorded yet.
+    pub fn compliance(&self) -> Option<f64> {
+        if self.recent_saves.is_empty() {
+            None
+        } else {
+            Some(self.met_count as f64 / self.recent_saves.len() as f64)
+        }
+    }
+
+    /// Checks whether the compliance has dropped below the configured minimum.
+    pub fn is_violated(&self) -> bool {
+        self.compliance()
+            .map_or(false, |compliance| compliance < self.slo.min_compliance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zksync_config::configs::database::{MerkleTreeMode, TreeHealthDetailsFormat};
+    use zksync_health_check::HealthStatus;
+    use zksync_types::L1BatchNumber;
+
+    use super::*;
+    use crate::metadata_calculator::{helpers::TreeHealthCheckDetails, TreeHealthThresholds};
+
+    fn health_status(tracker: &SaveLatencyTracker) -> HealthStatus {
+        let details = TreeHealthCheckDetails::new(
+            MerkleTreeMode::Full,
+            true,
+            L1BatchNumber(5),
+            L1BatchNumber(4),
+            None,
+        );
+        let health = details.with_save_latency_slo(Some(tracker)).into_health(
+            TreeHealthThresholds::default(),
+            TreeHealthDetailsFormat::Json,
+        );
+        health.status()
+    }
+
+    #[test]
+    fn tracking_save_latency_slo_compliance() {
+        let mut tracker = SaveLatencyTracker::new(SaveLatencySlo {
+            target: Duration::from_millis(100),
+            window: 4,
+            min_compliance: 0.5,
+        });
+        assert_eq!(tracker.compliance(), None);
+        assert!(!tracker.is_violated());
+        assert_eq!(health_status(&tracker), HealthStatus::Ready);
+
+        tracker.observe(Duration::from_millis(50));
+        tracker.observe(Duration::from_millis(100)); // the target is inclusive
+        tracker.observe(Duration::from_millis(150));
+        assert_eq!(tracker.compliance(), Some(2.0 / 3.0));
+        assert!(!tracker.is_violated());
+        assert_eq!(health_status(&tracker), HealthStatus::Ready);
+
+        tracker.observe(Duration::from_millis(200));
+        assert_eq!(tracker.compliance(), Some(0.5));
+        assert!(!tracker.is_violated());
+
+        // The oldest save meeting the target is evicted from the window.
+        tracker.observe(Duration::from_secs(1));
+        assert_eq!(tracker.compliance(), Some(0.25));
+        assert!(tracker.is_violated());
+        assert_eq!(health_status(&tracker), HealthStatus::Affected);
+
+        let details = TreeHealthCheckDetails::new(
+            MerkleTreeMode::Full,
+            true,
+            L1BatchNumber(5),
+            L1BatchNumber(4),
+            None,
+        );
+        let details = serde_json::to_value(details.with_save_latency_slo(Some(&tracker))).unwrap();
+        assert_eq!(details["slo_compliance"], 0.25);
+
+        for _ in 0..3 {
+            tracker.observe(Duration::from_millis(10));
+        }
+        assert_eq!(tracker.compliance(), Some(0.75));
+        assert_eq!(health_status(&tracker), HealthStatus::Ready);
+    }
+}
@@ -0,0 +1,262 @@
+//! Diffing Merkle tree snapshots for offline reconciliation.
+//!
+//! A snapshot is an NDJSON stream of [`SnapshotEntry`]s (one JSON object per line) sorted in the ascending order
+//! of Merkle tree keys, i.e. hashed keys interpreted as little-endian 256-bit integers. This is the order
+//! in which the tree stores its leaves, so snapshots can be exported without sorting all keys in RAM.
+//! Since both inputs are sorted, snapshots are diffed in a single streaming pass
+//! without loading either of them into memory; only the differences are retained. Snapshots may be
+//! [compressed](zksync_object_store::compression); compression is detected and handled transparently.
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use std::{cmp::Ordering, io};
+
+use zksync_object_store::compression;
+use zksync_types::H256;
+
+use super::helpers::tree_key;
+
+/// Tree leaf in a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Hashed storage key of the leaf.
+    pub key: H256,
+    /// Value hash of the leaf.
+    pub value: H256,
+    /// Enumeration index of the leaf.
+    pub leaf_index: u64,
+}
+
+/// Difference between two snapshots for a single key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotChange {
+    /// Entry is present only in the second snapshot.
+    Added(SnapshotEntry),
+    /// Entry is present only in the first snapshot.
+    Removed(SnapshotEntry),
+    /// Entry is present in both snapshots, but has a different value or leaf index.
+    Changed {
+        old: SnapshotEntry,
+        new: SnapshotEntry,
+    },
+}
+
+impl SnapshotChange {
+    /// Returns the hashed key affected by this change.
+    pub fn key(&self) -> H256 {
+        match self {
+            Self::Added(entry) | Self::Removed(entry) => entry.key,
+            Self::Changed { new, .. } => new.key,
+        }
+    }
+}
+
+/// Difference between two snapshots returned by [`diff_snapshots()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    /// Changes ordered by the tree key.
+    pub changes: Vec<SnapshotChange>,
+    /// Number of entries equal in both snapshots.
+    pub unchanged_count: u64,
+}
+
+impl SnapshotDiff {
+    /// Checks whether the compared snapshots are equal.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Streaming reader of snapshot entries checking that entries are sorted by key.
+#[derive(Debug)]
+struct SnapshotReader<R> {
+    name: &'static str,
+    lines: io::Lines<R>,
+    line_number: usize,
+    last_key: Option<H256>,
+}
+
+impl<R: io::BufRead> SnapshotReader<R> {
+    fn new(name: &'static str, reader: R) -> Self {
+        Self {
+            name,
+            lines: reader.lines(),
+            line_number: 0,
+            last_key: None,
+        }
+    }
+
+    fn next_entry(&mut self) -> anyhow::Result<Option<SnapshotEntry>> {
+        let name = self.name;
+        for line in self.lines.by_ref() {
+            self.line_number += 1;
+            let line_number = self.line_number;
+            let line =
+                line.with_context(|| format!("cannot read {name} snapshot, line {line_number}"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry: SnapshotEntry = serde_json::from_str(&line)
+                .with_context(|| format!("cannot parse {name} snapshot, line {line_number}"))?;
+            if let Some(last_key) = self.last_key {
+                anyhow::ensure!(
+                    tree_key(entry.key) > tree_key(last_key),
+                    "{name} snapshot is not sorted by key: key {:?} on line {line_number} \
+                     does not exceed the previous key {last_key:?}",
+                    entry.key
+                );
+            }
+            self.last_key = Some(entry.key);
+            return Ok(Some(entry));
+        }
+        Ok(None)
+    }
+}
+
+/// Computes the difference between two NDJSON snapshots `old` and `new`. Both snapshots must be
+/// sorted by the tree key (see the module docs). Compressed snapshots are decompressed on the fly.
+///
+/// # Errors
+///
+/// Returns an error if any of the snapshots cannot be read, decompressed or parsed, or is not sorted by key.
+pub fn diff_snapshots(old: impl io::Read, new: impl io::Read) -> anyhow::Result<SnapshotDiff> {
+    let old = compression::decompressing_stream(old).context("cannot read old snapshot")?;
+    let new = compression::decompressing_stream(new).context("cannot read new snapshot")?;
+    let mut old = SnapshotReader::new("old", io::BufReader::new(old));
+    let mut new = SnapshotReader::new("new", io::BufReader::new(new));
+    let mut diff = SnapshotDiff::default();
+
+    let mut old_entry = old.next_entry()?;
+    let mut new_entry = new.next_entry()?;
+    loop {
+        match (old_entry, new_entry) {
+            (None, None) => break,
+            (Some(entry), None) => {
+                diff.changes.push(SnapshotChange::Removed(entry));
+                old_entry = old.next_entry()?;
+            }
+            (None, Some(entry)) => {
+                diff.changes.push(SnapshotChange::Added(entry));
+                new_entry = new.next_entry()?;
+            }
+            (Some(old_value), Some(new_value)) => {
+                match tree_key(old_value.key).cmp(&tree_key(new_value.key)) {
+                    Ordering::Less => {
+                        diff.changes.push(SnapshotChange::Removed(old_value));
+                        old_entry = old.next_entry()?;
+                    }
+                    Ordering::Greater => {
+                        diff.changes.push(SnapshotChange::Added(new_value));
+                        new_entry = new.next_entry()?;
+                    }
+                    Ordering::Equal => {
+                        if old_value == new_value {
+                            diff.unchanged_count += 1;
+                        } else {
+                            diff.changes.push(SnapshotChange::Changed {
+                                old: old_value,
+                                new: new_value,
+                            });
+                        }
+                        old_entry = old.next_entry()?;
+                        new_entry = new.next_entry()?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata_calculator::helpers::hashed_key;
+
+    fn entry(key: u64, value: u64, leaf_index: u64) -> SnapshotEntry {
+        SnapshotEntry {
+            key: hashed_key(key.into()),
+            value: H256::from_low_u64_be(value),
+            leaf_index,
+        }
+    }
+
+    fn to_ndjson(entries: &[SnapshotEntry]) -> String {
+        entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+            .collect()
+    }
+
+    #[test]
+    fn diffing_snapshots() {
+        let old_entries: Vec<_> = (0..100).map(|i| entry(i * 2, i, i + 1)).collect();
+        let mut new_entries = old_entries.clone();
+        let removed = new_entries.remove(10);
+        new_entries[50].value = H256::repeat_byte(0xff);
+        new_entries[70].leaf_index = 1_000;
+        let added = entry(77, 1, 101);
+        new_entries.insert(38, added);
+        new_entries.push(entry(1_000, 2, 102));
+
+        let old = to_ndjson(&old_entries);
+        let new = to_ndjson(&new_entries);
+        let diff = diff_snapshots(old.as_bytes(), new.as_bytes()).unwrap();
+
+        assert_eq!(
+            diff.changes,
+            [
+                SnapshotChange::Removed(removed),
+                SnapshotChange::Added(added),
+                SnapshotChange::Changed {
+                    old: old_entries[51],
+                    new: new_entries[51],
+                },
+                SnapshotChange::Changed {
+                    old: old_entries[71],
+                    new: new_entries[71],
+                },
+                SnapshotChange::Added(entry(1_000, 2, 102)),
+            ]
+        );
+        assert_eq!(diff.unchanged_count, 97);
+
+        let reverse_diff = diff_snapshots(new.as_bytes(), old.as_bytes()).unwrap();
+        assert_eq!(reverse_diff.changes.len(), diff.changes.len());
+        assert_eq!(reverse_diff.unchanged_count, diff.unchanged_count);
+        assert!(matches!(
+            reverse_diff.changes[0],
+            SnapshotChange::Added(entry) if entry == removed
+        ));
+
+        let self_diff = diff_snapshots(old.as_bytes(), old.as_bytes()).unwrap();
+        assert!(self_diff.is_empty());
+        assert_eq!(self_diff.unchanged_count, 100);
+    }
+
+    #[test]
+    fn diffing_empty_snapshots() {
+        let entries = [entry(1, 1, 1), entry(2, 2, 2)];
+        let snapshot = to_ndjson(&entries);
+        let diff = diff_snapshots(snapshot.as_bytes(), io::empty()).unwrap();
+        assert_eq!(diff.changes, entries.map(SnapshotChange::Removed).to_vec());
+
+        let diff = diff_snapshots(io::empty(), snapshot.as_bytes()).unwrap();
+        assert_eq!(diff.changes, entries.map(SnapshotChange::Added).to_vec());
+    }
+
+    #[test]
+    fn unsorted_snapshot_is_rejected() {
+        let snapshot = to_ndjson(&[entry(1, 1, 1), entry(3, 3, 3), entry(2, 2, 2)]);
+        let err = diff_snapshots(snapshot.as_bytes(), io::empty()).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("old snapshot is not sorted"), "{err}");
+        assert!(err.contains("line 3"), "{err}");
+
+        let err = diff_snapshots(io::empty(), "{}\n".as_bytes()).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("cannot parse new snapshot, line 1"), "{err}");
+    }
+}
@@ -0,0 +1,551 @@
+//! Exporting Merkle tree snapshots in the NDJSON format consumed by [`diff_snapshots()`](super::diff_snapshots).
+//!
+//! Entries are streamed from the tree in the order of tree keys (which is the order required by the snapshot format),
+//! so only entries between consecutive checkpoints are kept in RAM.
+//!
+//! A full export of a large tree may take hours. To avoid restarting it from scratch after an interruption,
+//! the exporter periodically records the last exported key together with the length of the exported data
+//! to a checkpoint file. A resumed export truncates the output to the recorded length (discarding entries
+//! that may be partially written) and continues streaming entries after the recorded key.
+//!
+//! Exports can be compressed with `zstd`. In this case, entries between consecutive checkpoints are written
+//! as separate `zstd` frames, so that a resumed export can be continued at a frame boundary.
+//! [`diff_snapshots()`](super::diff_snapshots) decompresses snapshots transparently.
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fs,
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use zksync_merkle_tree::{domain::ZkSyncTreeReader, Key, TreeEntry};
+use zksync_object_store::compression::CompressingWriter;
+use zksync_types::{L1BatchNumber, H256};
+
+use super::{
+    helpers::{hashed_key, tree_key},
+    SnapshotEntry,
+};
+
+/// Default number of exported entries between checkpoints.
+const DEFAULT_CHECKPOINT_INTERVAL: usize = 100_000;
+
+/// Export progress recorded in the checkpoint file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct ExportCheckpoint {
+    /// L1 batch the exported tree version corresponds to.
+    l1_batch_number: u32,
+    /// Last key written to the output.
+    last_key: H256,
+    /// Number of entries written to the output.
+    entry_count: u64,
+    /// Length of the output in bytes after writing `last_key`.
+    output_len: u64,
+    /// Whether the output is compressed.
+    #[serde(default)]
+    compressed: bool,
+}
+
+/// Summary of an export returned by [`SnapshotExporter::export_entries()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotExport {
+    /// Total number of entries in the output.
+    pub entry_count: u64,
+    /// Number of entries recovered from the checkpoint, i.e. not exported in this run.
+    pub resumed_entry_count: u64,
+}
+
+/// Exporter of the Merkle tree leaves at a certain L1 batch to an NDJSON snapshot sorted by the tree key.
+#[derive(Debug)]
+pub struct SnapshotExporter<'a> {
+    tree: &'a ZkSyncTreeReader,
+    l1_batch_number: L1BatchNumber,
+    checkpoint_interval: usize,
+    compression_level: Option<i32>,
+    /// Number of entries after which the export is aborted with an error, emulating an interruption.
+    #[cfg(test)]
+    pub interrupt_after: Option<u64>,
+}
+
+impl<'a> SnapshotExporter<'a> {
+    /// Creates an exporter for the tree version corresponding to `l1_batch_number`.
+    pub fn new(tree: &'a ZkSyncTreeReader, l1_batch_number: L1BatchNumber) -> Self {
+        Self {
+            tree,
+            l1_batch_number,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            compression_level: None,
+            #[cfg(test)]
+            interrupt_after: None,
+        }
+    }
+
+    /// Sets the number of exported entries between checkpoints. Entries between checkpoints are loaded
+    /// from the tree at once, so the interval bounds RAM usage of the export.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the provided interval is zero.
+    pub fn set_checkpoint_interval(&mut self, interval: usize) {
+        assert!(interval > 0, "checkpoint interval must be positive");
+        self.checkpoint_interval = interval;
+    }
+
+    /// Sets the `zstd` compression level for the output. If set to `None` (the default), the output
+    /// is not compressed.
+    pub fn set_compression_level(&mut self, level: Option<i32>) {
+        self.compression_level = level;
+    }
+
+    /// Returns the path to the checkpoint file used for the export to `output_path`.
+    pub fn checkpoint_path(output_path: &Path) -> PathBuf {
+        let mut path = output_path.as_os_str().to_owned();
+        path.push(".checkpoint");
+        path.into()
+    }
+
+    /// Exports tree entries to `output_path`, recording progress to [`Self::checkpoint_path()`].
+    /// If `resume` is set and the checkpoint file exists, the export continues after the key recorded
+    /// in the checkpoint; otherwise, the output is overwritten. The checkpoint file is removed once
+    /// the export completes.
+    ///
+    /// This is a blocking operation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version is missing, on I/O errors, or if the checkpoint is invalid
+    /// (e.g., was recorded for another L1 batch, or the output is shorter than recorded).
+    pub fn export_entries(
+        &self,
+        output_path: &Path,
+        resume: bool,
+    ) -> anyhow::Result<SnapshotExport> {
+        let l1_batch_number = self.l1_batch_number;
+        let checkpoint_path = Self::checkpoint_path(output_path);
+        let checkpoint = if resume {
+            self.read_checkpoint(&checkpoint_path)?
+        } else {
+            None
+        };
+
+        let (output, mut start_after, mut entry_count) = if let Some(checkpoint) = checkpoint {
+            anyhow::ensure!(
+                checkpoint.compressed == self.compression_level.is_some(),
+                "checkpoint at `{}` was recorded for {} output, while the export is configured \
+                 to produce {} output",
+                checkpoint_path.display(),
+                Self::describe_compression(checkpoint.compressed),
+                Self::describe_compression(self.compression_level.is_some())
+            );
+            let last_key = tree_key(checkpoint.last_key);
+            let last_entry = self
+                .tree
+                .entries(l1_batch_number, &[last_key])
+                .with_context(|| {
+                    format!("cannot load Merkle tree entries for L1 batch #{l1_batch_number}")
+                })?;
+            anyhow::ensure!(
+                !last_entry[0].is_empty(),
+                "checkpoint at `{}` is inconsistent with Merkle tree: recorded last key {:?} \
+                 is missing from the tree",
+                checkpoint_path.display(),
+                checkpoint.last_key
+            );
+            let output = Self::truncate_output(output_path, checkpoint.output_len)?;
+            tracing::info!(
+                "Resuming export of Merkle tree for L1 batch #{l1_batch_number} to `{}` after key {:?} \
+                 ({} entries already exported)",
+                output_path.display(),
+                checkpoint.last_key,
+                checkpoint.entry_count
+            );
+            (output, Some(last_key), checkpoint.entry_count)
+        } else {
+            let mut output = fs::File::create(output_path)
+                .with_context(|| format!("cannot create `{}`", output_path.display()))?;
+            if let Some(level) = self.compression_level {
+                // Start the compressed output with an empty frame, so that it's valid even if the tree is empty.
+                CompressingWriter::new(&mut output, level)
+                    .and_then(CompressingWriter::finish)
+                    .with_context(|| format!("cannot write to `{}`", output_path.display()))?;
+            }
+            tracing::info!(
+                "Exporting Merkle tree for L1 batch #{l1_batch_number} to `{}`",
+                output_path.display()
+            );
+            (output, None, 0)
+        };
+        let resumed_entry_count = entry_count;
+
+        let mut output = io::BufWriter::new(output);
+        loop {
+            let chunk = self.load_entries_after(start_after, self.checkpoint_interval)?;
+            let Some(&(last_key, _)) = chunk.last() else {
+                break;
+            };
+            let is_last_chunk = chunk.len() < self.checkpoint_interval;
+            let entries = chunk.into_iter().map(|(key, entry)| SnapshotEntry {
+                key: hashed_key(key),
+                value: entry.value_hash,
+                leaf_index: entry.leaf_index,
+            });
+            if let Some(level) = self.compression_level {
+                let frame = CompressingWriter::continuation(&mut output, level)?;
+                // `serde_json` performs many small writes, so we buffer them before compression.
+                let mut frame = io::BufWriter::new(frame);
+                self.write_entries(&mut frame, entries, &mut entry_count)?;
+                frame
+                    .into_inner()
+                    .map_err(io::IntoInnerError::into_error)?
+                    .finish()?;
+            } else {
+                self.write_entries(&mut output, entries, &mut entry_count)?;
+            }
+
+            output.flush()?;
+            let file = output.get_mut();
+            file.sync_data()?;
+            let checkpoint = ExportCheckpoint {
+                l1_batch_number: l1_batch_number.0,
+                last_key: hashed_key(last_key),
+                entry_count,
+                output_len: file.stream_position()?,
+                compressed: self.compression_level.is_some(),
+            };
+            Self::write_checkpoint(&checkpoint_path, &checkpoint)?;
+            tracing::debug!("Recorded export checkpoint: {checkpoint:?}");
+            if is_last_chunk {
+                break;
+            }
+            start_after = Some(last_key);
+        }
+
+        if checkpoint_path.exists() {
+            fs::remove_file(&checkpoint_path).with_context(|| {
+                format!("cannot remove checkpoint `{}`", checkpoint_path.display())
+            })?;
+        }
+        tracing::info!(
+            "Exported {entry_count} entries of Merkle tree for L1 batch #{l1_batch_number} to `{}`",
+            output_path.display()
+        );
+        Ok(SnapshotExport {
+            entry_count,
+            resumed_entry_count,
+        })
+    }
+
+    fn load_entries_after(
+        &self,
+        start_after: Option<Key>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(Key, TreeEntry)>> {
+        let l1_batch_number = self.l1_batch_number;
+        self.tree
+            .entries_in_key_order(l1_batch_number, start_after, limit)
+            .with_context(|| {
+                format!("cannot load Merkle tree entries for L1 batch #{l1_batch_number}")
+            })
+    }
+
+    fn write_entries(
+        &self,
+        writer: &mut dyn Write,
+        entries: impl Iterator<Item = SnapshotEntry>,
+        entry_count: &mut u64,
+    ) -> anyhow::Result<()> {
+        for entry in entries {
+            #[cfg(test)]
+            anyhow::ensure!(
+                self.interrupt_after != Some(*entry_count),
+                "export interrupted after {entry_count} entries"
+            );
+
+            serde_json::to_writer(&mut *writer, &entry)?;
+            writer.write_all(b"\n")?;
+            *entry_count += 1;
+        }
+        Ok(())
+    }
+
+    fn describe_compression(compressed: bool) -> &'static str {
+        if compressed {
+            "compressed"
+        } else {
+            "uncompressed"
+        }
+    }
+
+    fn read_checkpoint(&self, checkpoint_path: &Path) -> anyhow::Result<Option<ExportCheckpoint>> {
+        let raw_checkpoint = match fs::read(checkpoint_path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                tracing::info!(
+                    "Export checkpoint `{}` not found; starting export from scratch",
+                    checkpoint_path.display()
+                );
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("cannot read `{}`", checkpoint_path.display()));
+            }
+        };
+        let checkpoint: ExportCheckpoint = serde_json::from_slice(&raw_checkpoint)
+            .with_context(|| format!("cannot parse `{}`", checkpoint_path.display()))?;
+        anyhow::ensure!(
+            checkpoint.l1_batch_number == self.l1_batch_number.0,
+            "checkpoint at `{}` was recorded for L1 batch #{}, while exporting L1 batch #{}",
+            checkpoint_path.display(),
+            checkpoint.l1_batch_number,
+            self.l1_batch_number
+        );
+        Ok(Some(checkpoint))
+    }
+
+    /// Writes the checkpoint atomically, so that an interruption cannot leave a partially written file.
+    fn write_checkpoint(
+        checkpoint_path: &Path,
+        checkpoint: &ExportCheckpoint,
+    ) -> anyhow::Result<()> {
+        let mut tmp_path = checkpoint_path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        fs::write(&tmp_path, serde_json::to_vec(checkpoint)?)
+            .with_context(|| format!("cannot write `{}`", tmp_path.display()))?;
+        fs::rename(&tmp_path, checkpoint_path)
+            .with_context(|| format!("cannot write `{}`", checkpoint_path.display()))
+    }
+
+    /// Opens the output of an interrupted export and discards data written after the checkpoint.
+    fn truncate_output(output_path: &Path, len: u64) -> anyhow::Result<fs::File> {
+        let mut output = fs::OpenOptions::new()
+            .write(true)
+            .open(output_path)
+            .with_context(|| format!("cannot open `{}` to resume export", output_path.display()))?;
+        let actual_len = output.metadata()?.len();
+        anyhow::ensure!(
+            actual_len >= len,
+            "export output `{}` is shorter ({actual_len} bytes) than recorded in the checkpoint \
+             ({len} bytes)",
+            output_path.display()
+        );
+        output.set_len(len)?;
+        output.seek(io::SeekFrom::End(0))?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use std::io::Read;
+
+    use zksync_merkle_tree::{domain::ZkSyncTree, MerkleTreeColumnFamily};
+    use zksync_object_store::compression;
+    use zksync_storage::RocksDB;
+    use zksync_types::{AccountTreeId, Address, StorageKey, StorageLog};
+
+    use super::*;
+    use crate::metadata_calculator::diff_snapshots;
+
+    fn create_tree(db_path: &Path) -> ZkSyncTree {
+        let db = RocksDB::<MerkleTreeColumnFamily>::new(db_path, true);
+        let mut tree = ZkSyncTree::new_lightweight(db);
+        for l1_batch in 0_u64..3 {
+            let logs: Vec<_> = (0_u64..50)
+                .map(|i| {
+                    let key = H256::from_low_u64_be(l1_batch * 30 + i);
+                    let key = StorageKey::new(AccountTreeId::new(Address::repeat_byte(1)), key);
+                    StorageLog::new_write_log(key, H256::from_low_u64_be(l1_batch + i + 1))
+                })
+                .collect();
+            tree.process_l1_batch(&logs);
+        }
+        tree.save();
+        tree
+    }
+
+    #[test]
+    fn resuming_interrupted_export() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let tree = create_tree(&temp_dir.path().join("tree"));
+        let reader = tree.reader();
+        let l1_batch_number = L1BatchNumber(2);
+
+        let full_path = temp_dir.path().join("full.ndjson");
+        let mut exporter = SnapshotExporter::new(&reader, l1_batch_number);
+        exporter.set_checkpoint_interval(16);
+        let export = exporter.export_entries(&full_path, false).unwrap();
+        assert_eq!(export.entry_count, 110);
+        assert_eq!(export.resumed_entry_count, 0);
+        assert!(!SnapshotExporter::checkpoint_path(&full_path).exists());
+
+        let resumed_path = temp_dir.path().join("resumed.ndjson");
+        exporter.interrupt_after = Some(40);
+        let err = exporter.export_entries(&resumed_path, false).unwrap_err();
+        assert!(err.to_string().contains("interrupted"), "{err}");
+        assert!(SnapshotExporter::checkpoint_path(&resumed_path).exists());
+        // Emulate a partially written entry after the checkpoint.
+        let mut output = fs::OpenOptions::new()
+            .append(true)
+            .open(&resumed_path)
+            .unwrap();
+        output.write_all(b"{\"key\":\"0x").unwrap();
+        drop(output);
+
+        exporter.interrupt_after = None;
+        let export = exporter.export_entries(&resumed_path, true).unwrap();
+        assert_eq!(export.entry_count, 110);
+        assert_eq!(export.resumed_entry_count, 32);
+        assert!(!SnapshotExporter::checkpoint_path(&resumed_path).exists());
+
+        let full_output = fs::read(&full_path).unwrap();
+        assert_eq!(fs::read(&resumed_path).unwrap(), full_output);
+        let diff = diff_snapshots(full_output.as_slice(), full_output.as_slice()).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged_count, 110);
+    }
+
+    fn read_decompressed(path: &Path) -> Vec<u8> {
+        let mut decompressed = vec![];
+        compression::decompressing_stream(fs::File::open(path).unwrap())
+            .unwrap()
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        decompressed
+    }
+
+    #[test]
+    fn exporting_compressed_snapshot() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let tree = create_tree(&temp_dir.path().join("tree"));
+        let reader = tree.reader();
+        let l1_batch_number = L1BatchNumber(2);
+
+        let raw_path = temp_dir.path().join("raw.ndjson");
+        SnapshotExporter::new(&reader, l1_batch_number)
+            .export_entries(&raw_path, false)
+            .unwrap();
+        let raw_output = fs::read(&raw_path).unwrap();
+
+        let compressed_path = temp_dir.path().join("compressed.ndjson.zst");
+        let mut exporter = SnapshotExporter::new(&reader, l1_batch_number);
+        exporter.set_compression_level(Some(3));
+        let export = exporter.export_entries(&compressed_path, false).unwrap();
+        assert_eq!(export.entry_count, 110);
+
+        let compressed_output = fs::read(&compressed_path).unwrap();
+        assert!(compression::is_compressed(&compressed_output));
+        assert!(
+            compressed_output.len() * 2 < raw_output.len(),
+            "{} >= {} / 2",
+            compressed_output.len(),
+            raw_output.len()
+        );
+        assert_eq!(read_decompressed(&compressed_path), raw_output);
+
+        let diff = diff_snapshots(raw_output.as_slice(), compressed_output.as_slice()).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged_count, 110);
+    }
+
+    #[test]
+    fn exporting_compressed_snapshot_for_empty_tree() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let db = RocksDB::<MerkleTreeColumnFamily>::new(&temp_dir.path().join("tree"), true);
+        let mut tree = ZkSyncTree::new_lightweight(db);
+        tree.process_l1_batch(&[]);
+        tree.save();
+        let reader = tree.reader();
+
+        let output_path = temp_dir.path().join("snapshot.ndjson.zst");
+        let mut exporter = SnapshotExporter::new(&reader, L1BatchNumber(0));
+        exporter.set_compression_level(Some(3));
+        let export = exporter.export_entries(&output_path, false).unwrap();
+        assert_eq!(export.entry_count, 0);
+        assert!(read_decompressed(&output_path).is_empty());
+    }
+
+    #[test]
+    fn resuming_interrupted_compressed_export() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let tree = create_tree(&temp_dir.path().join("tree"));
+        let reader = tree.reader();
+        let l1_batch_number = L1BatchNumber(2);
+
+        let raw_path = temp_dir.path().join("raw.ndjson");
+        SnapshotExporter::new(&reader, l1_batch_number)
+            .export_entries(&raw_path, false)
+            .unwrap();
+
+        let output_path = temp_dir.path().join("snapshot.ndjson.zst");
+        let mut exporter = SnapshotExporter::new(&reader, l1_batch_number);
+        exporter.set_checkpoint_interval(16);
+        exporter.set_compression_level(Some(3));
+        exporter.interrupt_after = Some(40);
+        exporter.export_entries(&output_path, false).unwrap_err();
+
+        // Resuming with another compression setting is not allowed.
+        let mut uncompressed_exporter = SnapshotExporter::new(&reader, l1_batch_number);
+        uncompressed_exporter.set_checkpoint_interval(16);
+        let err = uncompressed_exporter
+            .export_entries(&output_path, true)
+            .unwrap_err();
+        assert!(err.to_string().contains("compressed output"), "{err}");
+
+        exporter.interrupt_after = None;
+        let export = exporter.export_entries(&output_path, true).unwrap();
+        assert_eq!(export.entry_count, 110);
+        assert_eq!(export.resumed_entry_count, 32);
+        assert_eq!(
+            read_decompressed(&output_path),
+            fs::read(&raw_path).unwrap()
+        );
+    }
+
+    #[test]
+    fn resuming_with_checkpoint_for_missing_key() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let tree = create_tree(&temp_dir.path().join("tree"));
+        let reader = tree.reader();
+
+        let output_path = temp_dir.path().join("snapshot.ndjson");
+        let mut exporter = SnapshotExporter::new(&reader, L1BatchNumber(2));
+        exporter.set_checkpoint_interval(16);
+        exporter.interrupt_after = Some(20);
+        exporter.export_entries(&output_path, false).unwrap_err();
+
+        let checkpoint_path = SnapshotExporter::checkpoint_path(&output_path);
+        let mut checkpoint: ExportCheckpoint =
+            serde_json::from_slice(&fs::read(&checkpoint_path).unwrap()).unwrap();
+        assert_eq!(checkpoint.entry_count, 16);
+        checkpoint.last_key = H256::repeat_byte(0xff);
+        SnapshotExporter::write_checkpoint(&checkpoint_path, &checkpoint).unwrap();
+
+        exporter.interrupt_after = None;
+        let err = exporter.export_entries(&output_path, true).unwrap_err();
+        assert!(err.to_string().contains("missing from the tree"), "{err}");
+    }
+
+    #[test]
+    fn resuming_with_checkpoint_for_other_l1_batch() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let tree = create_tree(&temp_dir.path().join("tree"));
+        let reader = tree.reader();
+
+        let output_path = temp_dir.path().join("snapshot.ndjson");
+        let mut exporter = SnapshotExporter::new(&reader, L1BatchNumber(1));
+        exporter.set_checkpoint_interval(16);
+        exporter.interrupt_after = Some(20);
+        exporter.export_entries(&output_path, false).unwrap_err();
+
+        let exporter = SnapshotExporter::new(&reader, L1BatchNumber(2));
+        let err = exporter.export_entries(&output_path, true).unwrap_err();
+        assert!(err.to_string().contains("L1 batch #1"), "{err}");
+    }
+}
@@ -0,0 +1,210 @@
+//! Optional Server-Sent Events (SSE) endpoint streaming Merkle tree roots as the calculator seals L1 batches.
+//! SSE is intended for simple consumers such as browser-based dashboards.
+
+use anyhow::Context as _;
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use futures::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use std::{collections::VecDeque, convert::Infallible, net::SocketAddr};
+
+use zksync_merkle_tree::domain::ZkSyncTreeReader;
+use zksync_types::{L1BatchNumber, H256};
+
+use super::{CommittedTreeState, MetadataCalculator};
+
+/// Maximum number of L1 batches for which events are backfilled if a stream falls behind the tree
+/// (e.g., if several L1 batches are saved at once). Older L1 batches are skipped.
+const MAX_BACKFILLED_L1_BATCHES: u32 = 1_000;
+
+/// Event sent by [`TreeRootsSseServer`] for each L1 batch sealed by the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootEvent {
+    /// Number of the sealed L1 batch.
+    pub batch: L1BatchNumber,
+    /// Root hash of the tree after processing the L1 batch.
+    pub root: H256,
+}
+
+impl RootEvent {
+    fn to_sse_event(self) -> Event {
+        Event::default()
+            .event("root")
+            .json_data(self)
+            .expect("failed serializing root event")
+    }
+}
+
+/// HTTP server streaming [`RootEvent`]s via Server-Sent Events on the `/roots` path. Like the gRPC server,
+/// the server reads the in-process tree of a [`MetadataCalculator`] and only observes tree versions
+/// saved by the calculator.
+#[derive(Debug, Clone)]
+pub struct TreeRootsSseServer {
+    reader: ZkSyncTreeReader,
+    committed_state: watch::Receiver<Option<CommittedTreeState>>,
+}
+
+impl TreeRootsSseServer {
+    /// Creates a server for the tree of the provided calculator. The server should be created
+    /// before the calculator is run.
+    pub fn new(calculator: &MetadataCalculator) -> Self {
+        Self {
+            reader: calculator.tree_reader(),
+            committed_state: calculator.subscribe_committed_state(),
+        }
+    }
+
+    /// Runs the server on the specified address until a stop signal is received.
+    pub async fn run(
+        self,
+        bind_address: SocketAddr,
+        stop_receiver: watch::Receiver<bool>,
+    ) -> anyhow::Result<()> {
+        let listener = std::net::TcpListener::bind(bind_address)
+            .with_context(|| format!("failed binding Merkle tree SSE server to {bind_address}"))?;
+        self.run_with_listener(listener, stop_receiver).await
+    }
+
+    pub(super) async fn run_with_listener(
+        self,
+        listener: std::net::TcpListener,
+        mut stop_receiver: watch::Receiver<bool>,
+    ) -> anyhow::Result<()> {
+        let local_addr = listener
+            .local_addr()
+            .context("failed getting local address of Merkle tree SSE server")?;
+        tracing::info!("Starting Merkle tree SSE server on {local_addr}");
+
+        let app = Router::new()
+            .route("/roots", get(Self::stream_roots))
+            .with_state((self, stop_receiver.clone()));
+        axum::Server::from_tcp(listener)
+            .context("failed creating Merkle tree SSE server")?
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(async move {
+                if stop_receiver.changed().await.is_err() {
+                    tracing::warn!(
+                        "Stop signal sender for Merkle tree SSE server was dropped without sending a signal"
+                    );
+                }
+                tracing::info!("Stop signal received, Merkle tree SSE server is shutting down");
+            })
+            .await
+            .context("Merkle tree SSE server failed")?;
+        tracing::info!("Merkle tree SSE server shut down");
+        Ok(())
+    }
+
+    async fn stream_roots(
+        State((this, stop_receiver)): State<(Self, watch::Receiver<bool>)>,
+    ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let events = RootEvents::new(this.reader, this.committed_state, stop_receiver);
+        Sse::new(events.into_stream()).keep_alive(KeepAlive::default())
+    }
+}
+
+/// State of a single root event stream.
+#[derive(Debug)]
+struct RootEvents {
+    reader: ZkSyncTreeReader,
+    committed_state: watch::Receiver<Option<CommittedTreeState>>,
+    stop_receiver: watch::Receiver<bool>,
+    last_state: Option<CommittedTreeState>,
+    pending: VecDeque<RootEvent>,
+}
+
+impl RootEvents {
+    fn new(
+        reader: ZkSyncTreeReader,
+        mut committed_state: watch::Receiver<Option<CommittedTreeState>>,
+        stop_receiver: watch::Receiver<bool>,
+    ) -> Self {
+        // The current state is captured eagerly, so that the stream doesn't miss L1 batches
+        // sealed between establishing the connection and polling the stream.
+        let current_state = *committed_state.borrow_and_update();
+        let mut this = Self {
+            reader,
+            committed_state,
+            stop_receiver,
+            last_state: None,
+            pending: VecDeque::new(),
+        };
+        if let Some(state) = current_state {
+            this.pending.push_back(RootEvent {
+                batch: state.l1_batch_number,
+                root: state.root_hash,
+            });
+            this.last_state = Some(state);
+        }
+        this
+    }
+
+    fn into_stream(self) -> impl Stream<Item = Result<Event, Infallible>> {
+        stream::unfold(self, |mut this| async move {
+            let event = this.next_event().await?;
+            Some((Ok(event.to_sse_event()), this))
+        })
+    }
+
+    async fn next_event(&mut self) -> Option<RootEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if *self.stop_receiver.borrow() {
+                return None;
+            }
+            tokio::select! {
+                res = self.committed_state.changed() => res.ok()?, // The calculator has terminated
+                _ = self.stop_receiver.changed() => return None,
+            }
+            let current_state = *self.committed_state.borrow_and_update();
+            if let Some(state) = current_state {
+                self.enqueue_events(state);
+            }
+        }
+    }
+
+    /// Enqueues events for all L1 batches sealed since the last observed state, up to and including `state`.
+    fn enqueue_events(&mut self, state: CommittedTreeState) {
+        if self.last_state == Some(state) {
+            return; // The state was re-sent without changes, e.g. after defragmenting the tree
+        }
+        let first_new_l1_batch = match self.last_state {
+            Some(last_state) if last_state.l1_batch_number < state.l1_batch_number => {
+                last_state.l1_batch_number.0 + 1
+            }
+            // The tree was reverted; there are no intermediate L1 batches to backfill.
+            Some(_) => state.l1_batch_number.0,
+            None => 0,
+        };
+        let first_new_l1_batch = first_new_l1_batch.max(
+            state
+                .l1_batch_number
+                .0
+                .saturating_sub(MAX_BACKFILLED_L1_BATCHES),
+        );
+
+        for l1_batch_number in first_new_l1_batch..state.l1_batch_number.0 {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            // The root may be missing if the corresponding tree version is pruned.
+            if let Some(root) = self.reader.root_hash_at(l1_batch_number) {
+                self.pending.push_back(RootEvent {
+                    batch: l1_batch_number,
+                    root,
+                });
+            }
+        }
+        self.pending.push_back(RootEvent {
+            batch: state.l1_batch_number,
+            root: state.root_hash,
+        });
+        self.last_state = Some(state);
+    }
+}
@@ -0,0 +1,130 @@
+//! Watchdog detecting stalls of the tree update loop.
+
+use futures::{future, FutureExt};
+use serde::Serialize;
+use tokio::{
+    sync::watch,
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+use tokio::sync::mpsc;
+
+use zksync_health_check::{Health, HealthStatus, HealthUpdater};
+use zksync_types::L1BatchNumber;
+
+use super::metrics::METRICS;
+
+/// Heartbeat sent by the tree update loop on each iteration, regardless of whether the tree
+/// made progress.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct TreeHeartbeat {
+    pub at: Instant,
+    pub next_l1_batch: L1BatchNumber,
+}
+
+impl TreeHeartbeat {
+    pub fn new(next_l1_batch: L1BatchNumber) -> Self {
+        Self {
+            at: Instant::now(),
+            next_l1_batch,
+        }
+    }
+}
+
+/// Health check details reported once the tree update loop is considered stalled.
+#[derive(Debug, Serialize)]
+struct StallDetails {
+    stalled: bool,
+    next_l1_batch: L1BatchNumber,
+    stall_threshold_ms: u64,
+}
+
+/// Component marking the tree as not ready if the update loop doesn't send a [`TreeHeartbeat`]
+/// for longer than the configured threshold. Without it, a hung loop (e.g., one deadlocked on
+/// loading data from Postgres) would keep reporting the last health it has set, and would look
+/// the same as an idle one.
+#[derive(Debug)]
+pub(super) struct StallWatchdog {
+    threshold: Duration,
+    // Notifies the tests about each detected stall.
+    #[cfg(test)]
+    pub stall_notifier: mpsc::UnboundedSender<L1BatchNumber>,
+}
+
+impl StallWatchdog {
+    pub fn new(threshold: Duration) -> Self {
+        assert!(
+            threshold > Duration::ZERO,
+            "Merkle tree stall threshold must be positive"
+        );
+        Self {
+            threshold,
+            #[cfg(test)]
+            stall_notifier: mpsc::unbounded_channel().0,
+        }
+    }
+
+    /// Runs the watchdog until a stop signal is received, or `heartbeat_receiver` is closed
+    /// (i.e., the tree updater has terminated). `None` values in `heartbeat_receiver` mean
+    /// that the tree is not initialized yet; stalls are not detected during initialization.
+    pub async fn run(
+        self,
+        mut heartbeat_receiver: watch::Receiver<Option<TreeHeartbeat>>,
+        health_updater: &HealthUpdater,
+        mut stop_receiver: watch::Receiver<bool>,
+    ) {
+        let mut is_stalled = false;
+        loop {
+            if *stop_receiver.borrow() {
+                break;
+            }
+            let heartbeat = *heartbeat_receiver.borrow_and_update();
+            let stall_deadline = match heartbeat {
+                Some(heartbeat) if !is_stalled => {
+                    tokio::time::sleep_until(heartbeat.at + self.threshold).left_future()
+                }
+                _ => future::pending().right_future(),
+            };
+
+            tokio::select! {
+                _ = stop_receiver.changed() => break,
+                changed = heartbeat_receiver.changed() => {
+                    if changed.is_err() {
+                        break; // The updater has terminated
+                    }
+                    if is_stalled {
+                        tracing::info!("Merkle tree update loop has resumed after a stall");
+                        is_stalled = false;
+                    }
+                }
+                () = stall_deadline => {
+                    // `heartbeat` is always set if the deadline is reached.
+                    self.report_stall(heartbeat.unwrap(), health_updater);
+                    is_stalled = true;
+                }
+            }
+        }
+    }
+
+    fn report_stall(&self, heartbeat: TreeHeartbeat, health_updater: &HealthUpdater) {
+        tracing::warn!(
+            "Merkle tree update loop has stalled: no iterations completed in {:?} (threshold: {:?}); \
+             next L1 batch to process: #{}",
+            heartbeat.at.elapsed(),
+            self.threshold,
+            heartbeat.next_l1_batch
+        );
+        METRICS.stalls.inc();
+
+        let details = StallDetails {
+            stalled: true,
+            next_l1_batch: heartbeat.next_l1_batch,
+            stall_threshold_ms: self.threshold.as_millis() as u64,
+        };
+        health_updater.update(Health::from(HealthStatus::NotReady).with_details(details));
+
+        #[cfg(test)]
+        self.stall_notifier.send(heartbeat.next_l1_batch).ok();
+    }
+}
@@ -1,29 +1,62 @@
 use assert_matches::assert_matches;
+use async_trait::async_trait;
 use db_test_macro::db_test;
-use itertools::Itertools;
+use futures::future;
 use tempfile::TempDir;
 use tokio::sync::{mpsc, watch};
 
-use std::{future::Future, ops, panic, path::Path, time::Duration};
+use std::{
+    collections::HashMap,
+    future::Future,
+    panic,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
-use zksync_config::{configs::chain::OperationsManagerConfig, DBConfig};
+use zksync_config::{
+    configs::{
+        chain::OperationsManagerConfig,
+        database::{DalConsistencyPolicy, EnumerationIndexCheck, MerkleTreeMode},
+    },
+    DBConfig,
+};
 use zksync_contracts::BaseSystemContracts;
-use zksync_dal::{ConnectionPool, StorageProcessor};
+use zksync_crypto::hasher::blake2::Blake2Hasher;
+use zksync_dal::{
+    connection::{ReplicationLagError, TestPool},
+    ConnectionPool, StorageProcessor,
+};
 use zksync_health_check::{CheckHealth, HealthStatus};
-use zksync_merkle_tree::domain::ZkSyncTree;
-use zksync_object_store::{ObjectStore, ObjectStoreFactory};
+use zksync_merkle_tree::{domain::ZkSyncTree, TreeEntry, TreeEntryWithProof};
+use zksync_object_store::{
+    compression, witness_input_chunk_key, Bucket, ObjectStore, ObjectStoreError,
+    ObjectStoreFactory, StoredObject, WitnessInputFormat, WitnessInputHeader,
+};
+use zksync_storage::RocksDB;
 use zksync_types::{
-    block::{miniblock_hash, BlockGasCount, L1BatchHeader, MiniblockHeader},
-    proofs::PrepareBasicCircuitsJob,
-    protocol_version::L1VerifierConfig,
-    system_contracts::get_system_smart_contracts,
+    block::{BlockGasCount, L1BatchHeader},
+    proofs::{
+        AggregationRound, PrepareBasicCircuitsJob, PrepareBasicCircuitsJobManifest,
+        StorageLogMetadata, WitnessChunkMetadata,
+    },
+    protocol_version::{FriProtocolVersionId, L1VerifierConfig},
     AccountTreeId, Address, L1BatchNumber, L2ChainId, MiniblockNumber, ProtocolVersionId,
-    StorageKey, StorageLog, H256,
+    StorageKey, StorageLog, H256, U256,
 };
-use zksync_utils::u32_to_h256;
 
 use super::{
-    L1BatchWithLogs, MetadataCalculator, MetadataCalculatorConfig, MetadataCalculatorModeConfig,
+    benchmark::{extend_db_state, gen_storage_logs},
+    check_tree_versions,
+    helpers::{hashed_key, ConnectionSource},
+    injected_delays::DelayedStage,
+    metrics::METRICS,
+    reconcile_initial_writes,
+    updater::{RepeatedWriteMismatch, TreeUpdater},
+    verify_witness_artifact, verify_witness_input, ArchivedProof, AuditedOperation,
+    BenchmarkConfig, BenchmarkStage, EmptyL1BatchRetries, L1BatchNotification, L1BatchWithLogs,
+    MetadataCalculator, MetadataCalculatorConfig, MetadataCalculatorModeConfig,
+    TreeHealthThresholds,
 };
 use crate::genesis::{ensure_genesis_state, GenesisParams};
 
@@ -53,6 +86,127 @@ async fn genesis_creation(pool: ConnectionPool, prover_pool: ConnectionPool) {
     );
 }
 
+#[db_test]
+async fn tree_genesis_is_checked_against_postgres(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    let mut storage = pool.access_storage().await.unwrap();
+    let (genesis_root_hash, _) = storage
+        .blocks_dal()
+        .get_l1_batch_tree_data(L1BatchNumber(0))
+        .await
+        .unwrap()
+        .expect("no tree data for genesis L1 batch");
+    storage
+        .blocks_dal()
+        .set_l1_batch_hash(L1BatchNumber(0), H256::repeat_byte(0xff))
+        .await
+        .unwrap();
+    drop(storage);
+
+    let (_stop_sender, stop_receiver) = watch::channel(false);
+    let err = run_with_timeout(
+        RUN_TIMEOUT,
+        calculator.run(pool.clone(), prover_pool.clone(), stop_receiver),
+    )
+    .await
+    .unwrap_err();
+    let err = format!("{err:#}");
+    assert!(err.contains("diverges from the genesis L1 batch"), "{err}");
+    assert!(
+        err.contains(&format!("{genesis_root_hash:?} in tree")),
+        "{err}"
+    );
+    assert!(!err.contains("last leaf index"), "{err}");
+
+    // The tree must not be persisted if the genesis check fails.
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    assert!(calculator.updater.tree().is_empty());
+
+    let mut storage = pool.access_storage().await.unwrap();
+    storage
+        .blocks_dal()
+        .set_l1_batch_hash(L1BatchNumber(0), genesis_root_hash)
+        .await
+        .unwrap();
+    drop(storage);
+    let root_hash = run_calculator(calculator, pool, prover_pool).await;
+    assert_eq!(root_hash, genesis_root_hash);
+}
+
+#[db_test]
+async fn tree_genesis_is_checked_against_expected_root_hash(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    let mut storage = pool.access_storage().await.unwrap();
+    let genesis_root_hash = storage
+        .blocks_dal()
+        .get_expected_genesis_root_hash()
+        .await
+        .unwrap()
+        .expect("expected genesis root hash is not persisted");
+    let (fingerprint, details) = storage
+        .blocks_dal()
+        .get_genesis_fingerprint()
+        .await
+        .unwrap()
+        .unwrap();
+    let corrupted_root_hash = H256::repeat_byte(0xfe);
+    storage
+        .blocks_dal()
+        .save_genesis_fingerprint(fingerprint, corrupted_root_hash, &details)
+        .await
+        .unwrap();
+    drop(storage);
+
+    let (_stop_sender, stop_receiver) = watch::channel(false);
+    let err = run_with_timeout(
+        RUN_TIMEOUT,
+        calculator.run(pool.clone(), prover_pool.clone(), stop_receiver),
+    )
+    .await
+    .unwrap_err();
+    let err = format!("{err:#}");
+    assert!(err.contains("expected genesis root hash"), "{err}");
+    assert!(err.contains(&format!("{corrupted_root_hash:?}")), "{err}");
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    assert!(calculator.updater.tree().is_empty());
+
+    let mut storage = pool.access_storage().await.unwrap();
+    storage
+        .blocks_dal()
+        .save_genesis_fingerprint(fingerprint, genesis_root_hash, &details)
+        .await
+        .unwrap();
+    drop(storage);
+
+    // The genesis root hash is reported in the tree health details.
+    let (mut calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    let tree_health_check = calculator.tree_health_check();
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let (delay_sender, mut delay_receiver) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sender;
+    let calculator_handle = tokio::spawn(calculator.run(pool, prover_pool, stop_receiver));
+    delay_receiver.recv().await.unwrap();
+    let health = serde_json::to_value(tree_health_check.check_health().await).unwrap();
+    assert_eq!(
+        health["details"]["genesis_root_hash"],
+        serde_json::json!(genesis_root_hash)
+    );
+
+    stop_sender.send_replace(true);
+    run_with_timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .expect("metadata calculator panicked")
+        .unwrap();
+}
+
 // TODO (SMA-1726): Restore tests for tree backup mode
 
 #[db_test]
@@ -81,125 +235,2008 @@ async fn basic_workflow(pool: ConnectionPool, prover_pool: ConnectionPool) {
     );
 }
 
-async fn expected_tree_hash(pool: &ConnectionPool) -> H256 {
-    let mut storage = pool.access_storage().await.unwrap();
-    let sealed_l1_batch_number = storage
-        .blocks_dal()
-        .get_sealed_l1_batch_number()
+#[db_test]
+async fn basic_workflow_without_witness_generation(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let store_factory = &ObjectStoreFactory::mock();
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.generate_witness_inputs = false;
+    let mode = MetadataCalculatorModeConfig::Full { store_factory };
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 1).await;
+    let merkle_tree_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let expected_tree_hash = expected_tree_hash(&pool).await;
+    assert_eq!(merkle_tree_hash, expected_tree_hash);
+
+    let object_store = store_factory.create_store().await;
+    let err = object_store
+        .get::<PrepareBasicCircuitsJob>(L1BatchNumber(1))
         .await
-        .unwrap()
-        .0;
-    let mut all_logs = vec![];
-    for i in 0..=sealed_l1_batch_number {
-        let logs = L1BatchWithLogs::new(&mut storage, L1BatchNumber(i)).await;
-        let logs = logs.unwrap().storage_logs;
-        all_logs.extend(logs);
-    }
-    ZkSyncTree::process_genesis_batch(&all_logs).root_hash
+        .unwrap_err();
+    assert_matches!(err, ObjectStoreError::KeyNotFound(_));
 }
 
 #[db_test]
-async fn status_receiver_has_correct_states(pool: ConnectionPool, prover_pool: ConnectionPool) {
+async fn basic_workflow_with_initial_writes_validation(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
     let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.validate_initial_writes = true;
+    let mode = MetadataCalculatorModeConfig::Lightweight;
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 5).await;
+    let merkle_tree_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
 
-    let (mut calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
-    let tree_health_check = calculator.tree_health_check();
-    assert_eq!(tree_health_check.name(), "tree");
-    let health = tree_health_check.check_health().await;
-    assert_matches!(health.status(), HealthStatus::NotReady);
-
-    let other_tree_health_check = calculator.tree_health_check();
-    assert_eq!(other_tree_health_check.name(), "tree");
-    let health = other_tree_health_check.check_health().await;
-    assert_matches!(health.status(), HealthStatus::NotReady);
+    let expected_tree_hash = expected_tree_hash(&pool).await;
+    assert_eq!(merkle_tree_hash, expected_tree_hash);
+}
 
+#[db_test]
+async fn calculator_fails_on_l1_batch_exceeding_slots_limit(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.max_in_memory_slots = 50;
+    // Idle metrics are refreshed until the tree updater terminates, so we want them to be refreshed often.
+    db_config.merkle_tree.metrics_refresh_interval_ms = 50;
+    let mode = MetadataCalculatorModeConfig::Lightweight;
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
     reset_db_state(&pool, 1).await;
-    let (stop_sx, stop_rx) = watch::channel(false);
-    let (delay_sx, mut delay_rx) = mpsc::unbounded_channel();
-    calculator.delayer.delay_notifier = delay_sx;
+    let touched_slots_count = pool
+        .access_storage()
+        .await
+        .unwrap()
+        .storage_logs_dal()
+        .estimate_touched_slots_count_for_l1_batch(L1BatchNumber(1))
+        .await;
+    assert!(touched_slots_count > 50, "{touched_slots_count}");
 
-    let calculator_handle = tokio::spawn(calculator.run(pool, prover_pool, stop_rx));
-    delay_rx.recv().await.unwrap();
-    assert_eq!(
-        tree_health_check.check_health().await.status(),
-        HealthStatus::Ready
-    );
+    let (_stop_sender, stop_receiver) = watch::channel(false);
+    let err = run_with_timeout(
+        RUN_TIMEOUT,
+        calculator.run(pool.clone(), prover_pool, stop_receiver),
+    )
+    .await
+    .unwrap_err();
+    let err = format!("{err:#}");
+    assert!(err.contains("L1 batch #1 touches up to"), "{err}");
+
+    // The tree must not process the offending L1 batch.
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
     assert_eq!(
-        other_tree_health_check.check_health().await.status(),
-        HealthStatus::Ready
+        calculator.updater.tree().next_l1_batch_number(),
+        L1BatchNumber(1)
     );
+}
 
-    stop_sx.send(true).unwrap();
-    tokio::time::timeout(RUN_TIMEOUT, calculator_handle)
+#[db_test]
+async fn calculator_fails_on_unsupported_protocol_version(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.max_supported_protocol_version = Some(ProtocolVersionId::latest() as u16);
+    let mode = MetadataCalculatorModeConfig::Lightweight;
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 1).await;
+
+    let unsupported_version = ProtocolVersionId::next();
+    let mut storage = pool.access_storage().await.unwrap();
+    storage
+        .protocol_versions_dal()
+        .save_protocol_version(
+            unsupported_version,
+            0,
+            L1VerifierConfig::default(),
+            BaseSystemContracts::load_from_disk().hashes(),
+            Address::zero(),
+            None,
+        )
+        .await;
+    let mut header = L1BatchHeader::new(
+        L1BatchNumber(2),
+        0,
+        Address::default(),
+        BaseSystemContracts::load_from_disk().hashes(),
+        unsupported_version,
+    );
+    header.is_finished = true;
+    storage
+        .blocks_dal()
+        .insert_l1_batch(&header, &[], BlockGasCount::default())
         .await
-        .expect("timed out waiting for calculator")
-        .unwrap()
         .unwrap();
-    assert_eq!(
-        tree_health_check.check_health().await.status(),
-        HealthStatus::ShutDown
+    drop(storage);
+
+    let (_stop_sender, stop_receiver) = watch::channel(false);
+    let err = run_with_timeout(
+        RUN_TIMEOUT,
+        calculator.run(pool.clone(), prover_pool, stop_receiver),
+    )
+    .await
+    .unwrap_err();
+    let err = format!("{err:#}");
+    assert!(
+        err.contains("L1 batch #2 has protocol version") && err.contains("exceeds"),
+        "{err}"
     );
+
+    // The tree must not process any L1 batches from the iteration containing the offending L1 batch.
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
     assert_eq!(
-        other_tree_health_check.check_health().await.status(),
-        HealthStatus::ShutDown
+        calculator.updater.tree().next_l1_batch_number(),
+        L1BatchNumber(1)
     );
 }
 
 #[db_test]
-async fn multi_l1_batch_workflow(pool: ConnectionPool, prover_pool: ConnectionPool) {
-    // Collect all storage logs in a single L1 batch
+async fn tree_is_rebuilt_after_rocksdb_corruption(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
     let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
-    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
-    reset_db_state(&pool, 1).await;
-    let root_hash = run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
+    run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+
+    // Corrupt RocksDB by overwriting its `CURRENT` file (RocksDB expects it to end with a newline).
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    let current_file_path = Path::new(&db_config.merkle_tree.path).join("CURRENT");
+    std::fs::write(current_file_path, "garbage").unwrap();
+
+    db_config.merkle_tree.auto_rebuild_on_corruption = true;
+    let mode = MetadataCalculatorModeConfig::Lightweight;
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    assert!(calculator.updater.tree().was_wiped());
+    assert!(calculator.updater.tree().is_empty());
+    let merkle_tree_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let expected_tree_hash = expected_tree_hash(&pool).await;
+    assert_eq!(merkle_tree_hash, expected_tree_hash);
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    assert_eq!(
+        calculator.updater.tree().next_l1_batch_number(),
+        L1BatchNumber(6)
+    );
+}
+
+#[cfg(feature = "grpc")]
+#[db_test]
+async fn grpc_server_serves_roots_and_proofs(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    use super::grpc::proto::{tree_client::TreeClient, GetProofRequest, GetRootRequest};
+    use super::TreeGrpcServer;
 
-    // Collect the same logs in multiple L1 batches
     let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
-    let (calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
-    reset_db_state(&pool, 10).await;
-    let multi_block_root_hash = run_calculator(calculator, pool, prover_pool).await;
-    assert_eq!(multi_block_root_hash, root_hash);
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
 
-    let mut prev_index = None;
-    for l1_batch_number in 1..=10 {
-        let l1_batch_number = L1BatchNumber(l1_batch_number);
-        let job: PrepareBasicCircuitsJob = object_store.get(l1_batch_number).await.unwrap();
-        let next_enumeration_index = job.next_enumeration_index();
-        let merkle_paths: Vec<_> = job.into_merkle_paths().collect();
-        assert!(!merkle_paths.is_empty() && merkle_paths.len() <= 10);
+    let server = TreeGrpcServer::new(&calculator);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let server_task = tokio::spawn(server.run_with_listener(listener, stop_receiver));
 
-        if let Some(prev_index) = prev_index {
-            assert_eq!(next_enumeration_index, prev_index + 1);
-        }
-        let max_leaf_index_in_block = merkle_paths
+    let root_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let mut client = TreeClient::connect(format!("http://{server_addr}"))
+        .await
+        .unwrap();
+    let root = client
+        .get_root(GetRootRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(root.l1_batch_number, 5);
+    assert_eq!(H256::from_slice(&root.root_hash), root_hash);
+
+    let logs = gen_storage_logs(0..100, 5);
+    let hashed_key = logs[4][0].key.hashed_key();
+    let request = GetProofRequest {
+        hashed_key: hashed_key.as_bytes().to_vec(),
+    };
+    let proof = client.get_proof(request).await.unwrap().into_inner();
+    assert_eq!(proof.l1_batch_number, 5);
+    assert_eq!(H256::from_slice(&proof.root_hash), root_hash);
+    assert_ne!(proof.leaf_index, 0);
+    let entry = TreeEntryWithProof {
+        base: TreeEntry {
+            value_hash: H256::from_slice(&proof.value_hash),
+            leaf_index: proof.leaf_index,
+        },
+        merkle_path: proof
+            .merkle_path
             .iter()
-            .filter_map(|log| log.first_write.then_some(log.leaf_enumeration_index))
-            .max();
-        prev_index = max_leaf_index_in_block.or(prev_index);
-    }
+            .map(|hash| H256::from_slice(hash))
+            .collect(),
+    };
+    let key = U256::from_little_endian(hashed_key.as_bytes());
+    entry.verify(&Blake2Hasher, key, root_hash);
+
+    let invalid_request = GetProofRequest {
+        hashed_key: vec![0; 10],
+    };
+    let err = client.get_proof(invalid_request).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+    stop_sender.send_replace(true);
+    server_task.await.unwrap().unwrap();
 }
 
 #[db_test]
-async fn running_metadata_calculator_with_additional_blocks(
+async fn archiving_proofs_for_multiple_l1_batches(
     pool: ConnectionPool,
     prover_pool: ConnectionPool,
 ) {
     let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
     let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
     reset_db_state(&pool, 5).await;
-    run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
 
+    let logs = gen_storage_logs(0..100, 5);
+    // The first key is written in L1 batch #1, the second one in L1 batch #5.
+    // (The very first generated log has a zero value, so it's skipped.)
+    let keys = [logs[0][1].key, logs[4][0].key];
     let mut calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
-    let (stop_sx, stop_rx) = watch::channel(false);
-    let (delay_sx, mut delay_rx) = mpsc::unbounded_channel();
-    calculator.delayer.delay_notifier = delay_sx;
-
-    let calculator_handle =
-        tokio::spawn(calculator.run(pool.clone(), prover_pool.clone(), stop_rx));
-    // Wait until the calculator has processed initial blocks.
-    let (next_l1_batch, _) = tokio::time::timeout(RUN_TIMEOUT, delay_rx.recv())
+    let mut buffer = vec![];
+    let proof_count = calculator
+        .archive_proofs(L1BatchNumber(1)..=L1BatchNumber(5), &keys, &mut buffer)
         .await
-        .expect("metadata calculator timed out processing initial blocks")
+        .unwrap();
+    assert_eq!(proof_count, 10);
+
+    let proofs: Vec<ArchivedProof> = buffer
+        .split(|&byte| byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert_eq!(proofs.len(), proof_count);
+
+    let mut storage = pool.access_storage().await.unwrap();
+    for (i, proof) in proofs.iter().enumerate() {
+        // Proofs must be ordered from the newest L1 batch to the oldest one.
+        let expected_l1_batch_number = L1BatchNumber(5 - (i / 2) as u32);
+        assert_eq!(proof.l1_batch_number, expected_l1_batch_number);
+        assert_eq!(proof.hashed_key, keys[i % 2].hashed_key());
+        let l1_batch = storage
+            .blocks_dal()
+            .get_l1_batch_metadata(expected_l1_batch_number)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(proof.root_hash, l1_batch.metadata.root_hash);
+
+        let entry = TreeEntryWithProof {
+            base: TreeEntry {
+                value_hash: proof.value_hash,
+                leaf_index: proof.leaf_index,
+            },
+            merkle_path: proof.merkle_path.clone(),
+        };
+        let key = U256::from_little_endian(proof.hashed_key.as_bytes());
+        entry.verify(&Blake2Hasher, key, proof.root_hash);
+
+        let is_written = i % 2 == 0 || expected_l1_batch_number == L1BatchNumber(5);
+        assert_eq!(entry.base.is_empty(), !is_written);
+    }
+}
+
+#[db_test]
+async fn reading_tree_values(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let logs = gen_storage_logs(0..100, 5);
+    // The first key is written in L1 batch #1, the second one in L1 batch #5.
+    let keys = [logs[0][1].key, logs[4][0].key];
+    let mut calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    let values = calculator
+        .tree_values(L1BatchNumber(1), &keys)
+        .await
+        .unwrap();
+    assert_eq!(values, [Some(logs[0][1].value), None]);
+    let values = calculator
+        .tree_values(L1BatchNumber(5), &keys)
+        .await
+        .unwrap();
+    assert_eq!(values, [Some(logs[0][1].value), Some(logs[4][0].value)]);
+
+    let err = calculator
+        .tree_values(L1BatchNumber(6), &keys)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("L1 batch #6"), "{err:#}");
+}
+
+#[cfg(feature = "sse")]
+#[db_test]
+async fn sse_server_streams_root_for_each_l1_batch(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    use super::{RootEvent, TreeRootsSseServer};
+
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
+
+    let server = TreeRootsSseServer::new(&calculator);
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let server_task = tokio::spawn(server.run_with_listener(listener, stop_receiver));
+
+    // Wait until the stream is established, so that it observes all L1 batches.
+    let response = reqwest::get(format!("http://{server_addr}/roots"))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let content_type = &response.headers()[reqwest::header::CONTENT_TYPE];
+    assert_eq!(content_type, "text/event-stream");
+
+    let root_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+    // The stream ends once the calculator terminates.
+    let body = response.text().await.unwrap();
+
+    let messages: Vec<_> = body.split("\n\n").filter(|msg| !msg.is_empty()).collect();
+    let events: Vec<RootEvent> = messages
+        .iter()
+        .map(|message| {
+            let lines: Vec<_> = message.lines().collect();
+            assert_eq!(lines.len(), 2, "{message}");
+            assert_eq!(lines[0], "event: root");
+            let data = lines[1].strip_prefix("data: ").unwrap();
+            serde_json::from_str(data).unwrap()
+        })
+        .collect();
+    let l1_batch_numbers: Vec<_> = events.iter().map(|event| event.batch).collect();
+    assert_eq!(
+        l1_batch_numbers,
+        (0..=5).map(L1BatchNumber).collect::<Vec<_>>()
+    );
+    assert_eq!(events.last().unwrap().root, root_hash);
+
+    let mut storage = pool.access_storage().await.unwrap();
+    for event in &events[1..] {
+        let l1_batch = storage
+            .blocks_dal()
+            .get_l1_batch_metadata(event.batch)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.root, l1_batch.metadata.root_hash);
+    }
+
+    stop_sender.send_replace(true);
+    server_task.await.unwrap().unwrap();
+}
+
+#[cfg(feature = "webhook")]
+#[db_test]
+async fn root_webhook_is_called_for_each_l1_batch(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    use super::RootWebhookPayload;
+
+    let (payloads_sender, mut payloads_receiver) = mpsc::unbounded_channel();
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::post(move |axum::Json(payload): axum::Json<RootWebhookPayload>| {
+            payloads_sender.send(payload).ok();
+            async {}
+        }),
+    );
+    let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+    let webhook_addr = server.local_addr();
+    tokio::spawn(server);
+
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.root_webhook_url = Some(format!("http://{webhook_addr}/"));
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 5).await;
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let calculator_handle = tokio::spawn(calculator.run(pool.clone(), prover_pool, stop_receiver));
+
+    // Queued notifications are dropped once the calculator is stopped, so we wait for all of them
+    // to be delivered before stopping it.
+    let mut payloads = vec![];
+    while payloads.len() < 5 {
+        let payload = run_with_timeout(RUN_TIMEOUT, payloads_receiver.recv()).await;
+        payloads.push(payload.unwrap());
+    }
+    stop_sender.send_replace(true);
+    run_with_timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let l1_batch_numbers: Vec<_> = payloads.iter().map(|payload| payload.batch).collect();
+    assert_eq!(
+        l1_batch_numbers,
+        (1..=5).map(L1BatchNumber).collect::<Vec<_>>()
+    );
+
+    let mut storage = pool.access_storage().await.unwrap();
+    for payload in payloads {
+        let l1_batch = storage
+            .blocks_dal()
+            .get_l1_batch_metadata(payload.batch)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payload.root, l1_batch.metadata.root_hash);
+        assert_eq!(
+            payload.next_enumeration_index,
+            l1_batch.metadata.rollup_last_leaf_index
+        );
+    }
+}
+
+#[cfg(feature = "webhook")]
+#[tokio::test]
+async fn root_webhook_worker_drops_queued_payloads_on_stop() {
+    use super::{webhook::RootWebhookWorker, RootWebhookPayload};
+
+    // Nothing listens on this address, so all requests fail and are retried.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let dead_addr = listener.local_addr().unwrap();
+    drop(listener);
+    let (sender, worker) = RootWebhookWorker::new(format!("http://{dead_addr}/"));
+    for number in 1..=10 {
+        sender.send(RootWebhookPayload {
+            batch: L1BatchNumber(number),
+            root: H256::zero(),
+            next_enumeration_index: 1,
+        });
+    }
+
+    let dropped_before = METRICS.webhook_dropped.get();
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let worker_handle = tokio::spawn(worker.run(stop_receiver));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    stop_sender.send_replace(true);
+    // Without handling the stop signal, the worker would retry each payload for several seconds.
+    run_with_timeout(Duration::from_secs(1), worker_handle)
+        .await
+        .unwrap();
+    // At most one payload is being sent when the worker is stopped.
+    assert!(METRICS.webhook_dropped.get() >= dropped_before + 9);
+    drop(sender);
+}
+
+#[db_test]
+async fn watched_key_proofs_are_updated_when_key_is_written(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let watched_key = StorageKey::new(AccountTreeId::new(Address::repeat_byte(0xaa)), H256::zero());
+    let hashed_key = watched_key.hashed_key();
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.max_l1_batches_per_iter = 1;
+    db_config.merkle_tree.watched_keys = vec![hashed_key];
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 1).await;
+
+    // The watched key is written in L1 batches #2 and #4.
+    let written_values = [
+        Some(H256::repeat_byte(1)),
+        None,
+        Some(H256::repeat_byte(2)),
+        None,
+    ];
+    let new_logs = gen_storage_logs(100..120, 4)
+        .into_iter()
+        .zip(written_values)
+        .map(|(mut logs, value)| {
+            if let Some(value) = value {
+                logs.push(StorageLog::new_write_log(watched_key, value));
+            }
+            logs
+        });
+    let mut storage = pool.access_storage().await.unwrap();
+    extend_db_state(&mut storage, new_logs).await;
+    let genesis_root_hash = storage
+        .blocks_dal()
+        .get_l1_batch_state_root(L1BatchNumber(0))
+        .await
+        .unwrap()
+        .unwrap();
+    drop(storage);
+
+    let proofs = calculator.watched_key_proofs();
+    assert!(proofs.get(&hashed_key).is_none());
+    let mut notifications = calculator.subscribe_processed_l1_batches();
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let calculator_handle = tokio::spawn(calculator.run(pool, prover_pool, stop_receiver));
+
+    let mut root_hashes = HashMap::from([(L1BatchNumber(0), genesis_root_hash)]);
+    let mut proof_l1_batches = vec![];
+    while proof_l1_batches.len() < 5 {
+        let notification = tokio::time::timeout(RUN_TIMEOUT, notifications.recv())
+            .await
+            .expect("timed out waiting for notification")
+            .unwrap();
+        let L1BatchNotification::Processed(l1_batch) = notification else {
+            panic!("unexpected notification: {notification:?}");
+        };
+        root_hashes.insert(l1_batch.l1_batch_number, l1_batch.root_hash);
+
+        // Proofs are refreshed before notifications are sent.
+        let proof = proofs.get(&hashed_key).unwrap();
+        assert_eq!(proof.root_hash, root_hashes[&proof.l1_batch_number]);
+        let key = U256::from_little_endian(hashed_key.as_bytes());
+        proof.entry.verify(&Blake2Hasher, key, proof.root_hash);
+        let expected_value = match proof.l1_batch_number.0 {
+            0 | 1 => H256::zero(),
+            2 | 3 => H256::repeat_byte(1),
+            _ => H256::repeat_byte(2),
+        };
+        assert_eq!(proof.entry.base.value_hash, expected_value);
+        proof_l1_batches.push((l1_batch.l1_batch_number, proof.l1_batch_number));
+    }
+    stop_sender.send_replace(true);
+    run_with_timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The proof is initially computed for the genesis L1 batch, and then is only recomputed
+    // when the watched key is written.
+    let expected_proof_l1_batches = [(1, 0), (2, 2), (3, 2), (4, 4), (5, 4)]
+        .map(|(processed, proven)| (L1BatchNumber(processed), L1BatchNumber(proven)));
+    assert_eq!(proof_l1_batches, expected_proof_l1_batches);
+}
+
+async fn expected_tree_hash(pool: &ConnectionPool) -> H256 {
+    let mut storage = pool.access_storage().await.unwrap();
+    let sealed_l1_batch_number = storage
+        .blocks_dal()
+        .get_sealed_l1_batch_number()
+        .await
+        .unwrap()
+        .0;
+    let mut all_logs = vec![];
+    for i in 0..=sealed_l1_batch_number {
+        let logs = L1BatchWithLogs::new(
+            &mut storage,
+            L1BatchNumber(i),
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap();
+        let logs = logs.unwrap().storage_logs;
+        all_logs.extend(logs);
+    }
+    ZkSyncTree::process_genesis_batch(&all_logs).root_hash
+}
+
+#[db_test]
+async fn repeated_write_indices_match_initial_writes(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    // Only used to create the genesis L1 batch.
+    setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 1).await;
+    let mut storage = pool.access_storage().await.unwrap();
+    // Skip the first log since it writes a zero value, i.e., doesn't produce an initial write in Postgres.
+    let updated_logs = gen_storage_logs(0..100, 1)[0]
+        .iter()
+        .skip(1)
+        .step_by(3)
+        .enumerate()
+        .map(|(i, log)| StorageLog::new_write_log(log.key, H256::from_low_u64_be(1_000 + i as u64)))
+        .collect();
+    extend_db_state(&mut storage, [updated_logs]).await;
+
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let mut tree = ZkSyncTree::new_lightweight(RocksDB::new(temp_dir.path(), false));
+    let mut metadata = None;
+    for l1_batch_number in 0..=2 {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let mode = MerkleTreeMode::Lightweight;
+        let logs = L1BatchWithLogs::new(
+            &mut storage,
+            l1_batch_number,
+            mode,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap();
+        metadata = Some(tree.process_l1_batch(&logs.unwrap().storage_logs));
+    }
+    let mut repeated_writes = metadata.unwrap().repeated_writes;
+    assert_eq!(repeated_writes.len(), 33);
+
+    let l1_batch_number = L1BatchNumber(2);
+    let mismatches = TreeUpdater::check_repeated_writes_consistency(
+        &mut storage,
+        l1_batch_number,
+        &repeated_writes,
+    )
+    .await;
+    assert!(mismatches.is_empty(), "{mismatches:#?}");
+
+    // Introduce synthetic index divergences.
+    let (first_index, second_index) = (repeated_writes[0].index, repeated_writes[1].index);
+    repeated_writes[0].index = second_index;
+    repeated_writes[1].index = first_index;
+    repeated_writes[2].index = u64::MAX >> 1;
+    let mismatches = TreeUpdater::check_repeated_writes_consistency(
+        &mut storage,
+        l1_batch_number,
+        &repeated_writes,
+    )
+    .await;
+    assert_eq!(mismatches.len(), 3, "{mismatches:#?}");
+    assert_matches!(
+        &mismatches[0],
+        RepeatedWriteMismatch::ValueMismatch { index, batch_value: Some(_), .. }
+            if *index == second_index
+    );
+    assert_matches!(
+        &mismatches[1],
+        RepeatedWriteMismatch::ValueMismatch { index, batch_value: Some(_), .. }
+            if *index == first_index
+    );
+    assert_matches!(
+        mismatches[2],
+        RepeatedWriteMismatch::MissingInitialWrite { index } if index == u64::MAX >> 1
+    );
+}
+
+#[db_test]
+async fn enumeration_index_is_checked_against_initial_writes(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    // Only used to create the genesis L1 batch.
+    setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 2).await;
+    let mut storage = pool.access_storage().await.unwrap();
+
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let mut tree = ZkSyncTree::new_lightweight(RocksDB::new(temp_dir.path(), false));
+    let mut metadata = None;
+    for l1_batch_number in 0..=2 {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let mode = MerkleTreeMode::Lightweight;
+        let logs = L1BatchWithLogs::new(
+            &mut storage,
+            l1_batch_number,
+            mode,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap();
+        metadata = Some(tree.process_l1_batch(&logs.unwrap().storage_logs));
+    }
+    let metadata = metadata.unwrap();
+    let l1_batch_number = L1BatchNumber(2);
+    TreeUpdater::check_enumeration_index(
+        &mut storage,
+        l1_batch_number,
+        &metadata,
+        None,
+        EnumerationIndexCheck::Error,
+    )
+    .await
+    .unwrap();
+
+    // Corrupt Postgres by allocating an enumeration index not known to the tree.
+    let extra_key = gen_storage_logs(1_000..1_001, 1)[0][0].key;
+    storage
+        .storage_logs_dedup_dal()
+        .insert_initial_writes(l1_batch_number, &[extra_key])
+        .await;
+
+    let err = TreeUpdater::check_enumeration_index(
+        &mut storage,
+        l1_batch_number,
+        &metadata,
+        None,
+        EnumerationIndexCheck::Error,
+    )
+    .await
+    .unwrap_err();
+    let err = err.to_string();
+    let tree_index = metadata.rollup_last_leaf_index;
+    assert!(err.contains(&format!("({tree_index})")), "{err}");
+    assert!(err.contains(&format!("({})", tree_index + 1)), "{err}");
+
+    TreeUpdater::check_enumeration_index(
+        &mut storage,
+        l1_batch_number,
+        &metadata,
+        None,
+        EnumerationIndexCheck::Warn,
+    )
+    .await
+    .unwrap();
+}
+
+#[db_test]
+async fn dal_inconsistencies_are_handled_according_to_policy(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    // Only used to create the genesis L1 batch.
+    setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 2).await;
+    let mut storage = pool.access_storage().await.unwrap();
+
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let mut tree = ZkSyncTree::new_lightweight(RocksDB::new(temp_dir.path(), false));
+    let mut metadata = None;
+    for l1_batch_number in 0..=2 {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let mode = MerkleTreeMode::Lightweight;
+        let logs = L1BatchWithLogs::new(
+            &mut storage,
+            l1_batch_number,
+            mode,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await
+        .unwrap();
+        metadata = Some(tree.process_l1_batch(&logs.unwrap().storage_logs));
+    }
+    let initial_writes = metadata.unwrap().initial_writes;
+    let l1_batch_number = L1BatchNumber(2);
+    for policy in [
+        DalConsistencyPolicy::FailFast,
+        DalConsistencyPolicy::BestEffort,
+    ] {
+        TreeUpdater::validate_initial_writes(
+            &mut storage,
+            L1BatchNumber(1)..=l1_batch_number,
+            policy,
+        )
+        .await
+        .unwrap();
+        TreeUpdater::check_initial_writes_consistency(
+            &mut storage,
+            l1_batch_number,
+            &initial_writes,
+            policy,
+        )
+        .await
+        .unwrap();
+    }
+
+    // Corrupt Postgres by adding an initial write without a corresponding storage log.
+    let extra_key = gen_storage_logs(1_000..1_001, 1)[0][0].key;
+    storage
+        .storage_logs_dedup_dal()
+        .insert_initial_writes(l1_batch_number, &[extra_key])
+        .await;
+
+    let err = TreeUpdater::validate_initial_writes(
+        &mut storage,
+        L1BatchNumber(1)..=l1_batch_number,
+        DalConsistencyPolicy::FailFast,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("1 inconsistencies"), "{err}");
+    let err = TreeUpdater::check_initial_writes_consistency(
+        &mut storage,
+        l1_batch_number,
+        &initial_writes,
+        DalConsistencyPolicy::FailFast,
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("Leaf indices are not consistent"),
+        "{err}"
+    );
+
+    // With the best-effort policy, inconsistencies are only logged.
+    TreeUpdater::validate_initial_writes(
+        &mut storage,
+        L1BatchNumber(1)..=l1_batch_number,
+        DalConsistencyPolicy::BestEffort,
+    )
+    .await
+    .unwrap();
+    TreeUpdater::check_initial_writes_consistency(
+        &mut storage,
+        l1_batch_number,
+        &initial_writes,
+        DalConsistencyPolicy::BestEffort,
+    )
+    .await
+    .unwrap();
+}
+
+#[db_test]
+async fn calculator_handles_dal_inconsistencies_according_to_policy(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 2).await;
+    let expected_root_hash = expected_tree_hash(&pool).await;
+
+    // Corrupt Postgres by adding an initial write without a corresponding storage log.
+    let extra_key = gen_storage_logs(1_000..1_001, 1)[0][0].key;
+    let mut storage = pool.access_storage().await.unwrap();
+    storage
+        .storage_logs_dedup_dal()
+        .insert_initial_writes(L1BatchNumber(2), &[extra_key])
+        .await;
+    drop(storage);
+
+    assert_eq!(
+        db_config.merkle_tree.dal_consistency_policy,
+        DalConsistencyPolicy::FailFast
+    );
+    let (_stop_sender, stop_receiver) = watch::channel(false);
+    let err = run_with_timeout(
+        RUN_TIMEOUT,
+        calculator.run(pool.clone(), prover_pool.clone(), stop_receiver),
+    )
+    .await
+    .unwrap_err();
+    let err = format!("{err:#}");
+    assert!(
+        err.contains("Leaf indices are not consistent for L1 batch 2"),
+        "{err}"
+    );
+
+    // With the best-effort policy, the calculator should process all L1 batches. The extra initial write
+    // also shifts the enumeration index in Postgres, so the corresponding check is relaxed as well.
+    let inconsistencies_before = METRICS.dal_inconsistencies[&"initial_writes_mismatch"].get();
+    db_config.merkle_tree.dal_consistency_policy = DalConsistencyPolicy::BestEffort;
+    db_config.merkle_tree.enumeration_index_check = EnumerationIndexCheck::Warn;
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    let root_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+    assert_eq!(root_hash, expected_root_hash);
+    assert!(METRICS.dal_inconsistencies[&"initial_writes_mismatch"].get() > inconsistencies_before);
+}
+
+#[db_test]
+async fn tree_progress_is_checked_against_sealed_l1_batches(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 2).await;
+    let mut storage = pool.access_storage().await.unwrap();
+    // Add a trailing L1 batch without initial writes.
+    extend_db_state(&mut storage, [vec![]]).await;
+    let last_l1_batch_with_writes = storage
+        .storage_logs_dedup_dal()
+        .max_l1_batch_with_initial_writes()
+        .await;
+    assert_eq!(last_l1_batch_with_writes, Some(L1BatchNumber(2)));
+    let last_sealed_l1_batch = storage
+        .blocks_dal()
+        .get_sealed_l1_batch_number()
+        .await
+        .unwrap();
+    assert_eq!(last_sealed_l1_batch, L1BatchNumber(3));
+
+    // The tree may lead the last sealed L1 batch by at most one L1 batch, regardless of initial writes.
+    for next_l1_batch_number in [2, 3, 4] {
+        TreeUpdater::check_tree_progress(
+            &mut storage,
+            L1BatchNumber(next_l1_batch_number),
+            EnumerationIndexCheck::Error,
+        )
+        .await
+        .unwrap();
+    }
+
+    let err = TreeUpdater::check_tree_progress(
+        &mut storage,
+        L1BatchNumber(5),
+        EnumerationIndexCheck::Error,
+    )
+    .await
+    .unwrap_err();
+    let err = err.to_string();
+    assert!(err.contains("#5"), "{err}");
+    assert!(err.contains("#3"), "{err}");
+
+    TreeUpdater::check_tree_progress(&mut storage, L1BatchNumber(5), EnumerationIndexCheck::Warn)
+        .await
+        .unwrap();
+}
+
+#[db_test]
+async fn checking_tree_versions_for_sealed_l1_batches(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 3).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+    // Seal L1 batches #4 and #5 without processing them by the tree.
+    let mut storage = pool.access_storage().await.unwrap();
+    extend_db_state(&mut storage, gen_storage_logs(100..200, 2)).await;
+
+    let (db_config, _) = create_config(temp_dir.path());
+    let tree_path = Path::new(&db_config.merkle_tree.path);
+    let tree = ZkSyncTree::new_lightweight(RocksDB::new(tree_path, false));
+    let all_l1_batches = L1BatchNumber(0)..=L1BatchNumber(5);
+    let report = check_tree_versions(&mut storage, &tree.reader(), all_l1_batches.clone())
+        .await
+        .unwrap();
+    assert_eq!(report.sealed_count, 6);
+    assert_eq!(report.missing, [L1BatchNumber(4), L1BatchNumber(5)]);
+    let err = report.ensure_complete().unwrap_err().to_string();
+    assert!(err.contains("missing versions for 2 of 6"), "{err}");
+
+    let processed_l1_batches = L1BatchNumber(0)..=L1BatchNumber(3);
+    let report = check_tree_versions(&mut storage, &tree.reader(), processed_l1_batches)
+        .await
+        .unwrap();
+    assert_eq!(report.sealed_count, 4);
+    report.ensure_complete().unwrap();
+}
+
+#[db_test]
+async fn initial_writes_are_reconciled_with_tree(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let (db_config, _) = create_config(temp_dir.path());
+    let tree_path = Path::new(&db_config.merkle_tree.path);
+    let tree = ZkSyncTree::new_lightweight(RocksDB::new(tree_path, false));
+    let mut storage = pool.access_storage().await.unwrap();
+    let reconciliation = reconcile_initial_writes(&mut storage, &tree).await.unwrap();
+    assert!(reconciliation.checked_count > 0);
+    assert!(reconciliation.repaired.is_empty());
+
+    // Corrupt `initial_writes` by swapping indices of 2 initial writes.
+    let initial_writes = storage
+        .storage_logs_dedup_dal()
+        .initial_writes_for_batch(L1BatchNumber(3))
+        .await;
+    let (first_key, first_index) = initial_writes[0];
+    let (second_key, second_index) = initial_writes[1];
+    storage
+        .storage_logs_dedup_dal()
+        .update_initial_writes_indices(&[(first_key, second_index), (second_key, first_index)])
+        .await;
+
+    let reconciliation = reconcile_initial_writes(&mut storage, &tree).await.unwrap();
+    let repaired: Vec<_> = reconciliation
+        .repaired
+        .iter()
+        .map(|write| (write.hashed_key, write.old_index, write.new_index))
+        .collect();
+    assert_eq!(
+        repaired,
+        [
+            (first_key, second_index, first_index),
+            (second_key, first_index, second_index)
+        ]
+    );
+
+    let expected_initial_writes: Vec<_> = tree
+        .leaves()
+        .into_iter()
+        .map(|(key, index)| (hashed_key(key), index))
+        .collect();
+    let initial_writes: Vec<_> = storage
+        .storage_logs_dedup_dal()
+        .initial_writes_chunk(0, expected_initial_writes.len() + 1)
+        .await
+        .into_iter()
+        .map(|(hashed_key, index, _)| (hashed_key, index))
+        .collect();
+    assert_eq!(initial_writes, expected_initial_writes);
+
+    // Reconciliation must be refused if the tree lags behind Postgres (e.g., because the node is running).
+    extend_db_state(&mut storage, gen_storage_logs(100..200, 1)).await;
+    let err = reconcile_initial_writes(&mut storage, &tree)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("not in sync"), "{err}");
+}
+
+#[db_test]
+async fn empty_l1_batches_are_handled_according_to_policy(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 1).await;
+    // Emulate an L1 batch partially written to Postgres: its header is present, but its miniblocks,
+    // storage logs and protective reads are not.
+    insert_l1_batch_header(&pool, L1BatchNumber(2), true).await;
+
+    let retries = EmptyL1BatchRetries {
+        max_retries: 2,
+        interval: Duration::from_millis(10),
+    };
+    let mut storage = pool.access_storage().await.unwrap();
+    for retries in [None, Some(retries)] {
+        TreeUpdater::check_empty_l1_batch(&mut storage, L1BatchNumber(1), retries)
+            .await
+            .unwrap();
+    }
+
+    TreeUpdater::check_empty_l1_batch(&mut storage, L1BatchNumber(2), None)
+        .await
+        .unwrap();
+    let err = TreeUpdater::check_empty_l1_batch(&mut storage, L1BatchNumber(2), Some(retries))
+        .await
+        .unwrap_err();
+    let err = err.to_string();
+    assert!(err.contains("#2"), "{err}");
+    assert!(err.contains("after 2 retries"), "{err}");
+
+    // An L1 batch with only protective reads is not considered empty.
+    let read_log = &gen_storage_logs(1_000..1_001, 1)[0][0];
+    let read_log = StorageLog::new_read_log(read_log.key, read_log.value);
+    storage
+        .storage_logs_dedup_dal()
+        .insert_protective_reads(L1BatchNumber(2), &[read_log.to_test_log_query()])
+        .await;
+    TreeUpdater::check_empty_l1_batch(&mut storage, L1BatchNumber(2), Some(retries))
+        .await
+        .unwrap();
+}
+
+#[db_test]
+async fn witness_artifacts_are_recorded(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 2).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let mut storage = pool.access_storage().await.unwrap();
+    let artifacts = storage
+        .witness_artifacts_dal()
+        .get_witness_artifacts(L1BatchNumber(0)..=L1BatchNumber(2))
+        .await;
+    let l1_batch_numbers: Vec<_> = artifacts.iter().map(|art| art.l1_batch_number).collect();
+    assert_eq!(l1_batch_numbers, [L1BatchNumber(1), L1BatchNumber(2)]);
+    for artifact in &artifacts {
+        let bytes = object_store
+            .get_raw(PrepareBasicCircuitsJob::BUCKET, &artifact.object_key)
+            .await
+            .unwrap();
+        assert_eq!(artifact.size, bytes.len() as u64);
+    }
+
+    let verification =
+        verify_witness_artifact(&mut storage, object_store.as_ref(), L1BatchNumber(1))
+            .await
+            .unwrap()
+            .expect("no artifact for L1 batch #1");
+    assert!(verification.is_ok(), "{verification:?}");
+    let verification =
+        verify_witness_artifact(&mut storage, object_store.as_ref(), L1BatchNumber(3))
+            .await
+            .unwrap();
+    assert!(verification.is_none());
+
+    // Emulate truncating the object after upload.
+    let object_key = &artifacts[0].object_key;
+    let mut bytes = object_store
+        .get_raw(PrepareBasicCircuitsJob::BUCKET, object_key)
+        .await
+        .unwrap();
+    bytes.truncate(bytes.len() / 2);
+    object_store
+        .put_raw(PrepareBasicCircuitsJob::BUCKET, object_key, bytes)
+        .await
+        .unwrap();
+    let verification =
+        verify_witness_artifact(&mut storage, object_store.as_ref(), L1BatchNumber(1))
+            .await
+            .unwrap()
+            .expect("no artifact for L1 batch #1");
+    assert!(!verification.is_ok());
+    assert_eq!(verification.actual_size, artifacts[0].size / 2);
+}
+
+#[db_test]
+async fn chunks_of_witness_artifacts_are_verified(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let store_factory = &ObjectStoreFactory::mock();
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.witness_chunking_threshold_mb = 0;
+    db_config.merkle_tree.witness_chunk_size = 2;
+    let mode = MetadataCalculatorModeConfig::Full { store_factory };
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 2).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let object_store = store_factory.create_store().await;
+    let mut storage = pool.access_storage().await.unwrap();
+    let verification =
+        verify_witness_artifact(&mut storage, object_store.as_ref(), L1BatchNumber(1))
+            .await
+            .unwrap()
+            .expect("no artifact for L1 batch #1");
+    assert_eq!(
+        verification.object_key,
+        PrepareBasicCircuitsJobManifest::encode_key(L1BatchNumber(1))
+    );
+    assert!(verification.is_ok(), "{verification:?}");
+
+    // Corrupt a chunk; the manifest itself stays intact.
+    let chunk_key = witness_input_chunk_key(L1BatchNumber(1), 1);
+    let mut chunk = object_store
+        .get_raw(PrepareBasicCircuitsJob::BUCKET, &chunk_key)
+        .await
+        .unwrap();
+    chunk[0] ^= 1;
+    object_store
+        .put_raw(PrepareBasicCircuitsJob::BUCKET, &chunk_key, chunk)
+        .await
+        .unwrap();
+    // Remove another chunk.
+    let chunk_key = witness_input_chunk_key(L1BatchNumber(1), 0);
+    object_store
+        .remove_raw(PrepareBasicCircuitsJob::BUCKET, &chunk_key)
+        .await
+        .unwrap();
+
+    let verification =
+        verify_witness_artifact(&mut storage, object_store.as_ref(), L1BatchNumber(1))
+            .await
+            .unwrap()
+            .expect("no artifact for L1 batch #1");
+    assert!(!verification.is_ok());
+    assert_eq!(verification.actual_size, verification.recorded_size);
+    assert_eq!(verification.actual_hash, verification.recorded_hash);
+    assert_eq!(verification.chunk_errors.len(), 2, "{verification:?}");
+    assert!(
+        verification.chunk_errors[0].contains("chunk #0")
+            && verification.chunk_errors[0].contains("missing"),
+        "{verification:?}"
+    );
+    assert!(
+        verification.chunk_errors[1].contains("chunk #1 has unexpected hash"),
+        "{verification:?}"
+    );
+}
+
+#[db_test]
+async fn witness_is_not_produced_for_proven_l1_batches(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 2).await;
+
+    let mut storage = pool.access_storage().await.unwrap();
+    let mut prover_storage = prover_pool.access_storage().await.unwrap();
+    let protocol_version_id = ProtocolVersionId::latest();
+    if !prover_storage
+        .protocol_versions_dal()
+        .prover_protocol_version_exists(protocol_version_id)
+        .await
+    {
+        let protocol_version = storage
+            .protocol_versions_dal()
+            .get_protocol_version(protocol_version_id)
+            .await
+            .unwrap();
+        prover_storage
+            .protocol_versions_dal()
+            .save_prover_protocol_version(protocol_version)
+            .await;
+    }
+    // Emulate a final proof for L1 batch #1.
+    prover_storage
+        .prover_dal()
+        .insert_prover_jobs(
+            L1BatchNumber(1),
+            vec![("Scheduler", "scheduler_input".to_owned())],
+            AggregationRound::Scheduler,
+            protocol_version_id as i32,
+        )
+        .await;
+    let job = prover_storage
+        .prover_dal()
+        .get_next_prover_job(&[protocol_version_id])
+        .await
+        .unwrap();
+    prover_storage
+        .prover_dal()
+        .save_proof(job.id, Duration::ZERO, vec![], "test")
+        .await
+        .unwrap();
+    drop((storage, prover_storage));
+
+    assert_witness_is_not_produced_for_first_l1_batch(calculator, object_store, pool, prover_pool)
+        .await;
+}
+
+#[db_test]
+async fn witness_is_not_produced_for_l1_batches_proven_by_fri_prover(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 2).await;
+
+    let mut prover_storage = prover_pool.access_storage().await.unwrap();
+    let protocol_version_id = FriProtocolVersionId::latest();
+    prover_storage
+        .fri_protocol_versions_dal()
+        .save_prover_protocol_version(protocol_version_id, L1VerifierConfig::default())
+        .await;
+    // Emulate a final FRI proof for L1 batch #1 that was already sent to the server.
+    prover_storage
+        .fri_prover_jobs_dal()
+        .insert_prover_job(
+            L1BatchNumber(1),
+            1,
+            0,
+            0,
+            AggregationRound::Scheduler,
+            "scheduler_input",
+            false,
+            protocol_version_id,
+        )
+        .await;
+    prover_storage
+        .fri_prover_jobs_dal()
+        .save_successful_sent_proof(L1BatchNumber(1))
+        .await;
+    drop(prover_storage);
+
+    assert_witness_is_not_produced_for_first_l1_batch(calculator, object_store, pool, prover_pool)
+        .await;
+}
+
+async fn assert_witness_is_not_produced_for_first_l1_batch(
+    calculator: MetadataCalculator,
+    object_store: Box<dyn ObjectStore>,
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let merkle_tree_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+    assert_eq!(merkle_tree_hash, expected_tree_hash(&pool).await);
+
+    let err = object_store
+        .get::<PrepareBasicCircuitsJob>(L1BatchNumber(1))
+        .await
+        .unwrap_err();
+    assert_matches!(err, ObjectStoreError::KeyNotFound(_));
+    let job: PrepareBasicCircuitsJob = object_store.get(L1BatchNumber(2)).await.unwrap();
+    assert!(job.next_enumeration_index() > 0);
+
+    // Tree metadata must be computed for both L1 batches regardless.
+    let mut storage = pool.access_storage().await.unwrap();
+    for l1_batch_number in [L1BatchNumber(1), L1BatchNumber(2)] {
+        let metadata = storage
+            .blocks_dal()
+            .get_l1_batch_metadata(l1_batch_number)
+            .await
+            .unwrap();
+        assert!(metadata.is_some(), "{l1_batch_number}");
+    }
+}
+
+#[db_test]
+async fn status_receiver_has_correct_states(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+
+    let (mut calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    let tree_health_check = calculator.tree_health_check();
+    assert_eq!(tree_health_check.name(), "tree");
+    let health = tree_health_check.check_health().await;
+    assert_matches!(health.status(), HealthStatus::NotReady);
+
+    let other_tree_health_check = calculator.tree_health_check();
+    assert_eq!(other_tree_health_check.name(), "tree");
+    let health = other_tree_health_check.check_health().await;
+    assert_matches!(health.status(), HealthStatus::NotReady);
+
+    reset_db_state(&pool, 1).await;
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let (delay_sx, mut delay_rx) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sx;
+
+    let calculator_handle = tokio::spawn(calculator.run(pool, prover_pool, stop_rx));
+    delay_rx.recv().await.unwrap();
+    assert_eq!(
+        tree_health_check.check_health().await.status(),
+        HealthStatus::Ready
+    );
+    assert_eq!(
+        other_tree_health_check.check_health().await.status(),
+        HealthStatus::Ready
+    );
+
+    stop_sx.send(true).unwrap();
+    tokio::time::timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .expect("timed out waiting for calculator")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        tree_health_check.check_health().await.status(),
+        HealthStatus::ShutDown
+    );
+    assert_eq!(
+        other_tree_health_check.check_health().await.status(),
+        HealthStatus::ShutDown
+    );
+}
+
+#[test]
+fn health_status_thresholds() {
+    let thresholds = TreeHealthThresholds {
+        affected_lag: Some(3),
+        not_ready_lag: Some(5),
+    };
+    assert_matches!(thresholds.status(0), HealthStatus::Ready);
+    assert_matches!(thresholds.status(2), HealthStatus::Ready);
+    assert_matches!(thresholds.status(3), HealthStatus::Affected);
+    assert_matches!(thresholds.status(4), HealthStatus::Affected);
+    assert_matches!(thresholds.status(5), HealthStatus::NotReady);
+    assert_matches!(thresholds.status(100), HealthStatus::NotReady);
+
+    let no_thresholds = TreeHealthThresholds::default();
+    assert_matches!(no_thresholds.status(u32::MAX), HealthStatus::Ready);
+}
+
+async fn insert_l1_batch_header(pool: &ConnectionPool, number: L1BatchNumber, is_finished: bool) {
+    let mut header = L1BatchHeader::new(
+        number,
+        0,
+        Address::default(),
+        BaseSystemContracts::load_from_disk().hashes(),
+        Default::default(),
+    );
+    header.is_finished = is_finished;
+    let mut storage = pool.access_storage().await.unwrap();
+    storage
+        .blocks_dal()
+        .insert_l1_batch(&header, &[], BlockGasCount::default())
+        .await
+        .unwrap();
+}
+
+#[db_test]
+async fn tree_health_depends_on_lag(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.health_affected_lag = Some(3);
+    db_config.merkle_tree.health_not_ready_lag = Some(4);
+    let mode = MetadataCalculatorModeConfig::Lightweight;
+    let mut calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    let tree_health_check = calculator.tree_health_check();
+
+    reset_db_state(&pool, 1).await;
+    // The tree cannot progress past an unsealed L1 batch, so each L1 batch sealed after it
+    // increases the tree lag.
+    insert_l1_batch_header(&pool, L1BatchNumber(2), false).await;
+
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let (delay_sx, mut delay_rx) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sx;
+    let calculator_handle = tokio::spawn(calculator.run(pool.clone(), prover_pool, stop_rx));
+    delay_rx.recv().await.unwrap();
+    assert_eq!(
+        tree_health_check.check_health().await.status(),
+        HealthStatus::Ready
+    );
+
+    let expected_statuses = [
+        (L1BatchNumber(3), HealthStatus::Ready),    // lag = 2
+        (L1BatchNumber(4), HealthStatus::Affected), // lag = 3
+        (L1BatchNumber(5), HealthStatus::NotReady), // lag = 4
+    ];
+    for (l1_batch_number, expected_status) in expected_statuses {
+        insert_l1_batch_header(&pool, l1_batch_number, true).await;
+        // The first delay after draining may be reported by an iteration that has started before
+        // the L1 batch was inserted; the second one is guaranteed to observe the L1 batch.
+        while delay_rx.try_recv().is_ok() {}
+        for _ in 0..2 {
+            run_with_timeout(RUN_TIMEOUT, delay_rx.recv())
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            tree_health_check.check_health().await.status(),
+            expected_status,
+            "{l1_batch_number}"
+        );
+    }
+
+    stop_sx.send(true).unwrap();
+    run_with_timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[db_test]
+async fn idle_metrics_are_refreshed(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 1).await;
+    run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+
+    let mut calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    calculator.idle_metrics.refresh_interval = Duration::from_millis(10);
+    let (gauges_sx, mut gauges_rx) = mpsc::unbounded_channel();
+    calculator.idle_metrics.update_notifier = gauges_sx;
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let calculator_handle = tokio::spawn(calculator.run(pool, prover_pool, stop_rx));
+
+    // The tree has no new L1 batches to process, but gauges should still be refreshed.
+    for _ in 0..3 {
+        let gauges = tokio::time::timeout(RUN_TIMEOUT, gauges_rx.recv())
+            .await
+            .expect("timed out waiting for tree gauges")
+            .expect("metadata calculator shut down prematurely");
+        assert_eq!(gauges.next_l1_batch, L1BatchNumber(2));
+        assert_eq!(gauges.lag, 0);
+        assert!(gauges.db_size > 0);
+    }
+
+    stop_sx.send(true).unwrap();
+    tokio::time::timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .expect("timed out waiting for calculator")
+        .unwrap()
+        .unwrap();
+}
+
+#[db_test]
+async fn multi_l1_batch_workflow(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    // Collect all storage logs in a single L1 batch
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 1).await;
+    let root_hash = run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+
+    // Collect the same logs in multiple L1 batches
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 10).await;
+    let multi_block_root_hash = run_calculator(calculator, pool, prover_pool).await;
+    assert_eq!(multi_block_root_hash, root_hash);
+
+    let mut prev_index = None;
+    for l1_batch_number in 1..=10 {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let job: PrepareBasicCircuitsJob = object_store.get(l1_batch_number).await.unwrap();
+        let next_enumeration_index = job.next_enumeration_index();
+        let merkle_paths: Vec<_> = job.into_merkle_paths().collect();
+        assert!(!merkle_paths.is_empty() && merkle_paths.len() <= 10);
+
+        if let Some(prev_index) = prev_index {
+            assert_eq!(next_enumeration_index, prev_index + 1);
+        }
+        let max_leaf_index_in_block = merkle_paths
+            .iter()
+            .filter_map(|log| log.first_write.then_some(log.leaf_enumeration_index))
+            .max();
+        prev_index = max_leaf_index_in_block.or(prev_index);
+    }
+}
+
+#[db_test]
+async fn lost_witnesses_are_restored_on_restart(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let store_factory = &ObjectStoreFactory::mock();
+    let (db_config, operation_config) = create_config(temp_dir.path());
+    let mode = MetadataCalculatorModeConfig::Full { store_factory };
+    let mut calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 3).await;
+
+    // Witnesses for L1 batches after #1 are never persisted, so they are lost once the calculator is terminated.
+    calculator
+        .witness_stage_worker
+        .as_mut()
+        .unwrap()
+        .injected_delays
+        .set(
+            DelayedStage::PersistWitness,
+            None,
+            Duration::from_secs(3_600),
+        )
+        .set(
+            DelayedStage::PersistWitness,
+            Some(L1BatchNumber(1)),
+            Duration::ZERO,
+        );
+    let (_stop_sender, stop_receiver) = watch::channel(false);
+    let (delay_sender, mut delay_receiver) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sender;
+    let calculator_handle =
+        tokio::spawn(calculator.run(pool.clone(), prover_pool.clone(), stop_receiver));
+    wait_for_next_l1_batch(&mut delay_receiver, L1BatchNumber(4)).await;
+    let mut prover_storage = prover_pool.access_storage().await.unwrap();
+    let l1_batch_numbers = L1BatchNumber(1)..=L1BatchNumber(3);
+    loop {
+        let ready_l1_batches = prover_storage
+            .witness_generator_dal()
+            .get_l1_batches_with_witness_inputs(l1_batch_numbers.clone())
+            .await;
+        if ready_l1_batches == [L1BatchNumber(1)] {
+            break;
+        }
+        assert!(ready_l1_batches.is_empty(), "{ready_l1_batches:?}");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    // Emulate the process being terminated.
+    calculator_handle.abort();
+    assert!(calculator_handle.await.unwrap_err().is_cancelled());
+
+    let restored_witnesses = METRICS.restored_witnesses.get();
+    let mode = MetadataCalculatorModeConfig::Full { store_factory };
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    let root_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+    assert_eq!(root_hash, expected_tree_hash(&pool).await);
+    assert!(METRICS.restored_witnesses.get() >= restored_witnesses + 2);
+
+    let ready_l1_batches = prover_storage
+        .witness_generator_dal()
+        .get_l1_batches_with_witness_inputs(l1_batch_numbers)
+        .await;
+    assert_eq!(
+        ready_l1_batches,
+        [L1BatchNumber(1), L1BatchNumber(2), L1BatchNumber(3)]
+    );
+    let object_store = store_factory.create_store().await;
+    for l1_batch_number in 1..=3 {
+        let job: PrepareBasicCircuitsJob = object_store
+            .get(L1BatchNumber(l1_batch_number))
+            .await
+            .unwrap();
+        assert!(job.next_enumeration_index() > 0);
+    }
+}
+
+#[db_test]
+async fn benchmark_reports_throughput(pool: ConnectionPool) {
+    for include_load in [false, true] {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+        reset_db_state(&pool, 1).await;
+        let config = BenchmarkConfig {
+            num_batches: 3,
+            logs_per_batch: 7,
+            include_load,
+        };
+        let report = calculator.benchmark(&pool, config).await.unwrap();
+
+        assert_eq!(report.l1_batch_count, 3);
+        assert_eq!(report.log_count, 21);
+        assert!(report.elapsed > Duration::ZERO);
+        assert!(report.batches_per_sec.is_finite() && report.batches_per_sec > 0.0);
+        assert!(report.logs_per_sec.is_finite() && report.logs_per_sec > report.batches_per_sec);
+
+        let mut expected_stages = vec![BenchmarkStage::Compute, BenchmarkStage::SaveRocksDB];
+        if include_load {
+            expected_stages.insert(0, BenchmarkStage::Load);
+        }
+        let stages: Vec<_> = report.p95_latencies.keys().copied().collect();
+        assert_eq!(stages, expected_stages);
+        assert!(report
+            .p95_latencies
+            .values()
+            .all(|&latency| latency > Duration::ZERO));
+    }
+}
+
+#[db_test]
+async fn benchmark_requires_empty_tree(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 1).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    let config = BenchmarkConfig {
+        num_batches: 1,
+        logs_per_batch: 1,
+        include_load: false,
+    };
+    let err = calculator.benchmark(&pool, config).await.unwrap_err();
+    assert!(err.to_string().contains("empty Merkle tree"), "{err}");
+}
+
+#[db_test]
+async fn witness_inputs_are_uploaded_via_queue(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let store_factory = &ObjectStoreFactory::mock();
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    let queue_path = temp_dir.path().join("witness_queue");
+    db_config.merkle_tree.witness_upload_queue_path = Some(path_to_string(&queue_path));
+    let mode = MetadataCalculatorModeConfig::Full { store_factory };
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 3).await;
+
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let calculator_handle = tokio::spawn(calculator.run(pool.clone(), prover_pool, stop_rx));
+    let object_store = store_factory.create_store().await;
+    // Witnesses are uploaded in the L1 batch order, so it's sufficient to wait for the last one.
+    run_with_timeout(RUN_TIMEOUT, async {
+        while object_store
+            .get::<PrepareBasicCircuitsJob>(L1BatchNumber(3))
+            .await
+            .is_err()
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    stop_sx.send_replace(true);
+    run_with_timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .unwrap()
+        .unwrap();
+
+    for l1_batch_number in 1..=3 {
+        let job: PrepareBasicCircuitsJob = object_store
+            .get(L1BatchNumber(l1_batch_number))
+            .await
+            .unwrap();
+        assert!(job.next_enumeration_index() > 0);
+    }
+    // Uploaded witnesses must be removed from the queue.
+    let queued_files: Vec<_> = std::fs::read_dir(&queue_path)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(queued_files.is_empty(), "{queued_files:?}");
+}
+
+#[db_test]
+async fn witness_inputs_pass_verification(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 3).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let mut storage = pool.access_storage().await.unwrap();
+    // Start from L1 batch #2 so that both compared root hashes are persisted by the calculator.
+    for l1_batch_number in 2..=3 {
+        let verification =
+            verify_witness_input(&mut storage, &*object_store, L1BatchNumber(l1_batch_number))
+                .await
+                .unwrap();
+        assert!(verification.is_ok(), "{verification:?}");
+        assert!(verification.verified_path_count > 0);
+    }
+}
+
+#[db_test]
+async fn witness_inputs_are_uploaded_in_chunks(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let store_factory = &ObjectStoreFactory::mock();
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    // Upload all witness inputs in chunks.
+    db_config.merkle_tree.witness_chunking_threshold_mb = 0;
+    db_config.merkle_tree.witness_chunk_size = 2;
+    let mode = MetadataCalculatorModeConfig::Full { store_factory };
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 3).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let object_store = store_factory.create_store().await;
+    let mut storage = pool.access_storage().await.unwrap();
+    for l1_batch_number in 1..=3 {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let manifest: PrepareBasicCircuitsJobManifest =
+            object_store.get(l1_batch_number).await.unwrap();
+        assert!(manifest.chunks.len() > 1, "{manifest:?}");
+        assert!(object_store
+            .get::<PrepareBasicCircuitsJob>(l1_batch_number)
+            .await
+            .is_err());
+
+        let job = object_store
+            .get_witness_input(l1_batch_number)
+            .await
+            .unwrap();
+        assert_eq!(job.merkle_path_count(), manifest.merkle_path_count());
+        assert_eq!(
+            job.next_enumeration_index(),
+            manifest.next_enumeration_index
+        );
+        if l1_batch_number > L1BatchNumber(1) {
+            let verification = verify_witness_input(&mut storage, &*object_store, l1_batch_number)
+                .await
+                .unwrap();
+            assert!(verification.is_ok(), "{verification:?}");
+        }
+    }
+}
+
+#[db_test]
+async fn witness_inputs_with_deduplicated_paths(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    test_witness_inputs_with_deduplicated_paths(pool, prover_pool, false).await;
+}
+
+#[db_test]
+async fn witness_inputs_with_deduplicated_paths_and_protocol_version(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    test_witness_inputs_with_deduplicated_paths(pool, prover_pool, true).await;
+}
+
+async fn test_witness_inputs_with_deduplicated_paths(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+    record_protocol_version: bool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let store_factory = &ObjectStoreFactory::mock();
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.deduplicate_witness_paths = true;
+    db_config.merkle_tree.record_witness_protocol_version = record_protocol_version;
+    let mode = MetadataCalculatorModeConfig::Full { store_factory };
+    let calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 3).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let object_store = store_factory.create_store().await;
+    let mut storage = pool.access_storage().await.unwrap();
+    for l1_batch_number in 1..=3 {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let object_key = PrepareBasicCircuitsJob::encode_key(l1_batch_number);
+        let blob = object_store
+            .get_raw(PrepareBasicCircuitsJob::BUCKET, &object_key)
+            .await
+            .unwrap();
+        let serialized = compression::decompress_if_needed(blob).unwrap();
+        // L1 batches created by `reset_db_state()` have the latest protocol version, which is only recorded
+        // if explicitly enabled.
+        let expected_protocol_version =
+            Some(ProtocolVersionId::latest()).filter(|_| record_protocol_version);
+        let expected_header =
+            WitnessInputHeader::new(WitnessInputFormat::Deduplicated, expected_protocol_version);
+        assert_eq!(
+            WitnessInputHeader::parse(&serialized).unwrap(),
+            expected_header
+        );
+
+        let job: PrepareBasicCircuitsJob = object_store.get(l1_batch_number).await.unwrap();
+        assert!(job.next_enumeration_index() > 0);
+        if l1_batch_number > L1BatchNumber(1) {
+            let verification = verify_witness_input(&mut storage, &*object_store, l1_batch_number)
+                .await
+                .unwrap();
+            assert!(verification.is_ok(), "{verification:?}");
+        }
+    }
+}
+
+#[db_test]
+async fn regenerating_witness_input(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 3).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    // The new calculator uses a fresh object store, so the verified witness input can only be
+    // the regenerated one.
+    let (mut calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
+    let regenerated = calculator
+        .regenerate_witness(&pool, L1BatchNumber(2), None)
+        .await
+        .unwrap();
+    assert!(!regenerated.used_checkpoint);
+    assert!(regenerated.path_count > 0);
+
+    let mut storage = pool.access_storage().await.unwrap();
+    let verification = verify_witness_input(&mut storage, &*object_store, L1BatchNumber(2))
+        .await
+        .unwrap();
+    assert!(verification.is_ok(), "{verification:?}");
+    assert_eq!(verification.verified_path_count, regenerated.path_count);
+
+    let err = calculator
+        .regenerate_witness(&pool, L1BatchNumber(4), None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not processed"), "{err}");
+}
+
+#[db_test]
+async fn defragmenting_tree_via_calculator(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    const CURSOR_KEY: &[u8] = b"consumer";
+
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
+    let root_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let mut calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    calculator
+        .save_tree_with_cursor(CURSOR_KEY.to_vec(), b"5".to_vec())
+        .await;
+    let reader = calculator.tree_reader();
+    let err = calculator.defragment_tree().await.unwrap_err().to_string();
+    assert!(err.contains("live reader"), "{err}");
+    drop(reader);
+
+    let copied_node_count = calculator.defragment_tree().await.unwrap();
+    assert!(copied_node_count > 0);
+    let reader = calculator.tree_reader();
+    assert_eq!(reader.next_l1_batch_number(), L1BatchNumber(6));
+    assert_eq!(reader.root_hash_at(L1BatchNumber(5)), Some(root_hash));
+    assert_eq!(reader.root_hash_at(L1BatchNumber(4)), None);
+    assert_eq!(calculator.tree_cursor(CURSOR_KEY), Some(b"5".to_vec()));
+    let audit_log = calculator.tree_audit_log().unwrap();
+    assert_matches!(
+        audit_log.last().unwrap().operation,
+        AuditedOperation::Defragment { copied_node_count: count } if count == copied_node_count
+    );
+}
+
+#[db_test]
+async fn regenerating_witness_input_using_checkpoint(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 3).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    // Copy the tree before pruning, so that the copy can serve as a checkpoint.
+    let checkpoint_path = temp_dir.path().join("checkpoint");
+    copy_dir(&temp_dir.path().join("new"), &checkpoint_path);
+
+    let (mut calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
+    let pruned_key_count = calculator.prune_tree(L1BatchNumber(3)).await;
+    assert!(pruned_key_count > 0);
+
+    let err = calculator
+        .regenerate_witness(&pool, L1BatchNumber(2), None)
+        .await
+        .unwrap_err();
+    let err = err.to_string();
+    assert!(err.contains("pruned"), "{err}");
+    assert!(err.contains("no checkpoint is provided"), "{err}");
+
+    let regenerated = calculator
+        .regenerate_witness(&pool, L1BatchNumber(2), Some(&checkpoint_path))
+        .await
+        .unwrap();
+    assert!(regenerated.used_checkpoint);
+    assert!(regenerated.path_count > 0);
+
+    let mut storage = pool.access_storage().await.unwrap();
+    let verification = verify_witness_input(&mut storage, &*object_store, L1BatchNumber(2))
+        .await
+        .unwrap();
+    assert!(verification.is_ok(), "{verification:?}");
+    assert_eq!(verification.verified_path_count, regenerated.path_count);
+}
+
+fn copy_dir(source: &Path, target: &Path) {
+    std::fs::create_dir_all(target).unwrap();
+    for entry in std::fs::read_dir(source).unwrap() {
+        let entry = entry.unwrap();
+        let target_path = target.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target_path);
+        } else {
+            std::fs::copy(entry.path(), target_path).unwrap();
+        }
+    }
+}
+
+#[db_test]
+async fn regenerated_witness_input_is_not_uploaded_on_root_hash_mismatch(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 3).await;
+    run_calculator(calculator, pool.clone(), prover_pool).await;
+
+    let mut storage = pool.access_storage().await.unwrap();
+    storage
+        .blocks_dal()
+        .set_l1_batch_hash(L1BatchNumber(2), H256::repeat_byte(0xff))
+        .await
+        .unwrap();
+    drop(storage);
+
+    let (mut calculator, object_store) = setup_calculator(temp_dir.path(), &pool).await;
+    let err = calculator
+        .regenerate_witness(&pool, L1BatchNumber(2), None)
+        .await
+        .unwrap_err();
+    let err = err.to_string();
+    assert!(err.contains("disagrees with Postgres"), "{err}");
+    assert!(err.contains("refusing to upload"), "{err}");
+
+    let missing_witness = object_store
+        .get::<PrepareBasicCircuitsJob>(L1BatchNumber(2))
+        .await
+        .unwrap_err();
+    assert_matches!(missing_witness, ObjectStoreError::KeyNotFound(_));
+}
+
+#[db_test]
+async fn running_metadata_calculator_with_additional_blocks(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
+    run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+
+    let mut calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let (delay_sx, mut delay_rx) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sx;
+
+    let calculator_handle =
+        tokio::spawn(calculator.run(pool.clone(), prover_pool.clone(), stop_rx));
+    // Wait until the calculator has processed initial blocks.
+    let (next_l1_batch, _) = tokio::time::timeout(RUN_TIMEOUT, delay_rx.recv())
+        .await
+        .expect("metadata calculator timed out processing initial blocks")
         .unwrap();
     assert_eq!(next_l1_batch, L1BatchNumber(6));
 
@@ -207,54 +2244,512 @@ async fn running_metadata_calculator_with_additional_blocks(
     let new_logs = gen_storage_logs(100..200, 10);
     extend_db_state(&mut pool.access_storage().await.unwrap(), new_logs).await;
 
-    // Wait until these blocks are processed. The calculator may have spurious delays,
-    // thus we wait in a loop.
-    let updated_root_hash = loop {
+    // Wait until these blocks are processed. The calculator may have spurious delays,
+    // thus we wait in a loop.
+    let updated_root_hash = loop {
+        let (next_l1_batch, root_hash) = tokio::time::timeout(RUN_TIMEOUT, delay_rx.recv())
+            .await
+            .expect("metadata calculator shut down prematurely")
+            .unwrap();
+        if next_l1_batch == L1BatchNumber(16) {
+            stop_sx.send(true).unwrap(); // Shut down the calculator.
+            break root_hash;
+        }
+    };
+    tokio::time::timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .expect("timed out waiting for calculator")
+        .unwrap()
+        .unwrap();
+
+    // Switch to the full tree. It should pick up from the same spot and result in the same tree root hash.
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    let root_hash_for_full_tree = run_calculator(calculator, pool, prover_pool).await;
+    assert_eq!(root_hash_for_full_tree, updated_root_hash);
+}
+
+#[db_test]
+async fn shutting_down_calculator(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (db_config, mut operation_config) = create_config(temp_dir.path());
+    operation_config.delay_interval = 30_000; // ms; chosen to be larger than `RUN_TIMEOUT`
+
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+
+    reset_db_state(&pool, 5).await;
+
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let calculator_task = tokio::spawn(calculator.run(pool, prover_pool, stop_rx));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    stop_sx.send_replace(true);
+    run_with_timeout(RUN_TIMEOUT, calculator_task)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+/// Connection source failing the specified number of first acquisitions.
+#[derive(Debug)]
+struct FlakyConnectionSource {
+    pool: ConnectionPool,
+    remaining_failures: AtomicUsize,
+    /// If set, failures are reported as [`ReplicationLagError`]s with the specified lag.
+    replication_lag: Option<Duration>,
+}
+
+impl FlakyConnectionSource {
+    fn new(pool: ConnectionPool, failures: usize) -> Self {
+        Self {
+            pool,
+            remaining_failures: AtomicUsize::new(failures),
+            replication_lag: None,
+        }
+    }
+
+    /// Emulates a read replica lagging behind the primary for the specified number of first acquisitions.
+    fn lagging_replica(pool: ConnectionPool, failures: usize) -> Self {
+        Self {
+            replication_lag: Some(Duration::from_secs(10)),
+            ..Self::new(pool, failures)
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectionSource for FlakyConnectionSource {
+    async fn access_storage(&self) -> anyhow::Result<StorageProcessor<'_>> {
+        let should_fail = self
+            .remaining_failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok();
+        if should_fail {
+            if let Some(lag) = self.replication_lag {
+                let max_lag = Duration::from_secs(1);
+                return Err(ReplicationLagError { lag, max_lag }.into());
+            }
+            anyhow::bail!("emulated connection failure");
+        }
+        self.pool.access_storage().await
+    }
+}
+
+async fn run_updater_with_source(
+    calculator: MetadataCalculator,
+    source: &dyn ConnectionSource,
+    replica_source: Option<&dyn ConnectionSource>,
+    expected_next_l1_batch: L1BatchNumber,
+) -> anyhow::Result<()> {
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let (next_l1_batch_sender, mut next_l1_batch_receiver) = watch::channel(None);
+    let update_task = calculator.updater.loop_updating_tree(
+        calculator.delayer,
+        source,
+        replica_source,
+        None,
+        stop_receiver,
+        &calculator.health_updater,
+        next_l1_batch_sender,
+    );
+    let wait_task = async {
+        while *next_l1_batch_receiver.borrow_and_update() != Some(expected_next_l1_batch) {
+            if next_l1_batch_receiver.changed().await.is_err() {
+                return; // The updater has terminated
+            }
+        }
+        stop_sender.send_replace(true);
+    };
+    let (update_result, ()) =
+        run_with_timeout(RUN_TIMEOUT, future::join(update_task, wait_task)).await;
+    update_result
+}
+
+#[db_test]
+async fn tree_update_recovers_from_connection_failures(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.db_connection_max_attempts = 5;
+    db_config.merkle_tree.db_connection_backoff_ms = 10;
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 1).await;
+
+    let source = FlakyConnectionSource::new(pool.clone(), 3);
+    run_updater_with_source(calculator, &source, None, L1BatchNumber(2))
+        .await
+        .unwrap();
+    assert_eq!(source.remaining_failures.load(Ordering::SeqCst), 0);
+
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    assert_eq!(
+        calculator.updater.tree().next_l1_batch_number(),
+        L1BatchNumber(2)
+    );
+}
+
+#[db_test]
+async fn tree_update_fails_after_exhausting_connection_attempts(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.db_connection_max_attempts = 3;
+    db_config.merkle_tree.db_connection_backoff_ms = 10;
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 1).await;
+
+    let source = FlakyConnectionSource::new(pool.clone(), usize::MAX);
+    let err = run_updater_with_source(calculator, &source, None, L1BatchNumber(2))
+        .await
+        .unwrap_err();
+    let err = format!("{err:#}");
+    assert!(err.contains("after 3 attempts"), "{err}");
+    assert!(err.contains("emulated connection failure"), "{err}");
+}
+
+#[db_test]
+async fn tree_update_waits_for_lagging_replica(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.db_connection_max_attempts = 5;
+    db_config.merkle_tree.db_connection_backoff_ms = 10;
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 1).await;
+
+    let replica_source = FlakyConnectionSource::lagging_replica(pool.clone(), 3);
+    run_updater_with_source(calculator, &pool, Some(&replica_source), L1BatchNumber(2))
+        .await
+        .unwrap();
+    assert_eq!(replica_source.remaining_failures.load(Ordering::SeqCst), 0);
+
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    assert_eq!(
+        calculator.updater.tree().next_l1_batch_number(),
+        L1BatchNumber(2)
+    );
+}
+
+#[db_test]
+async fn tree_update_reports_replication_lag_after_exhausting_attempts(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.db_connection_max_attempts = 3;
+    db_config.merkle_tree.db_connection_backoff_ms = 10;
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 1).await;
+
+    let replica_source = FlakyConnectionSource::lagging_replica(pool.clone(), usize::MAX);
+    let err = run_updater_with_source(calculator, &pool, Some(&replica_source), L1BatchNumber(2))
+        .await
+        .unwrap_err();
+    let err = format!("{err:#}");
+    assert!(err.contains("after 3 attempts"), "{err}");
+    assert!(err.contains("replication lag"), "{err}");
+}
+
+/// Connection source blocking acquisitions while it's stalled.
+#[derive(Debug)]
+struct StallingConnectionSource {
+    pool: ConnectionPool,
+    stalled_receiver: watch::Receiver<bool>,
+}
+
+#[async_trait]
+impl ConnectionSource for StallingConnectionSource {
+    async fn access_storage(&self) -> anyhow::Result<StorageProcessor<'_>> {
+        let mut stalled_receiver = self.stalled_receiver.clone();
+        while *stalled_receiver.borrow_and_update() {
+            stalled_receiver.changed().await?;
+        }
+        self.pool.access_storage().await
+    }
+}
+
+#[db_test]
+async fn slow_subscriber_to_processed_l1_batches_does_not_block_calculator(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.l1_batch_notifications_capacity = 2;
+    let calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 5).await;
+    let mut fast_receiver = calculator.subscribe_processed_l1_batches();
+    let mut slow_receiver = calculator.subscribe_processed_l1_batches();
+    let tree_reader = calculator.tree_reader();
+
+    let receiver_task = tokio::spawn(async move {
+        let mut l1_batch_numbers = vec![];
+        while let Some(notification) = fast_receiver.recv().await {
+            match notification {
+                L1BatchNotification::Processed(l1_batch) => {
+                    l1_batch_numbers.push(l1_batch.l1_batch_number);
+                }
+                L1BatchNotification::Lagged { .. } => { /* may happen if the task is slow to start */
+                }
+                L1BatchNotification::Reverted(revert) => panic!("unexpected revert: {revert:?}"),
+            }
+        }
+        l1_batch_numbers
+    });
+    // The calculator must process all L1 batches even though the slow subscriber doesn't receive notifications.
+    let root_hash = run_calculator(calculator, pool.clone(), prover_pool).await;
+    let fast_l1_batch_numbers = receiver_task.await.unwrap();
+    assert_eq!(fast_l1_batch_numbers.last(), Some(&L1BatchNumber(5)));
+
+    let notification = slow_receiver.recv().await.unwrap();
+    assert_eq!(
+        notification,
+        L1BatchNotification::Lagged {
+            missed: 5,
+            last_revert: None,
+        }
+    );
+    assert_eq!(slow_receiver.recv().await, None);
+    // The subscriber should resync using the tree instead.
+    assert_eq!(tree_reader.root_hash_at(L1BatchNumber(5)), Some(root_hash));
+}
+
+#[db_test]
+async fn stalled_tree_update_loop_is_detected_by_watchdog(pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.stall_threshold_ms = Some(200);
+    let mut calculator = setup_calculator_with_options(
+        &db_config,
+        &operation_config,
+        &pool,
+        MetadataCalculatorModeConfig::Lightweight,
+    )
+    .await;
+    reset_db_state(&pool, 1).await;
+
+    let mut watchdog = calculator.stall_watchdog.take().unwrap();
+    let (stall_sender, mut stall_receiver) = mpsc::unbounded_channel();
+    watchdog.stall_notifier = stall_sender;
+    let health_check = calculator.tree_health_check();
+    let (stalled_sender, stalled_receiver) = watch::channel(false);
+    let source = StallingConnectionSource {
+        pool: pool.clone(),
+        stalled_receiver,
+    };
+
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let (next_l1_batch_sender, mut next_l1_batch_receiver) = watch::channel(None);
+    let heartbeat_receiver = calculator.updater.subscribe_heartbeat();
+    let update_task = calculator.updater.loop_updating_tree(
+        calculator.delayer,
+        &source,
+        None,
+        None,
+        stop_receiver.clone(),
+        &calculator.health_updater,
+        next_l1_batch_sender,
+    );
+    let watchdog_task = watchdog.run(
+        heartbeat_receiver,
+        &calculator.health_updater,
+        stop_receiver,
+    );
+    let test_task = async {
+        while *next_l1_batch_receiver.borrow_and_update() != Some(L1BatchNumber(2)) {
+            next_l1_batch_receiver.changed().await.unwrap();
+        }
+        // An idle loop must not be reported as stalled.
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(stall_receiver.try_recv().is_err());
+        assert_eq!(
+            health_check.check_health().await.status(),
+            HealthStatus::Ready
+        );
+
+        stalled_sender.send_replace(true);
+        let stalled_l1_batch = stall_receiver.recv().await.unwrap();
+        assert_eq!(stalled_l1_batch, L1BatchNumber(2));
+        let health = health_check.check_health().await;
+        assert_eq!(health.status(), HealthStatus::NotReady);
+        let health = serde_json::to_value(health).unwrap();
+        assert_eq!(health["details"]["stalled"], true);
+        assert_eq!(health["details"]["next_l1_batch"], 2);
+
+        stalled_sender.send_replace(false);
+        stop_sender.send_replace(true);
+    };
+    let (update_result, (), ()) = run_with_timeout(
+        RUN_TIMEOUT,
+        future::join3(update_task, watchdog_task, test_task),
+    )
+    .await;
+    update_result.unwrap();
+}
+
+#[db_test]
+async fn l1_batches_are_loaded_from_read_replica(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    // The primary Postgres (`pool`) and the read replica are emulated with separate Postgres schemas
+    // filled with the same data. Initially, the replica lags behind the primary by one L1 batch.
+    let replica_pool = ConnectionPool::Test(TestPool::with_schema("read_replica").await);
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let mut calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 2).await;
+
+    let mut replica_storage = replica_pool.access_storage().await.unwrap();
+    ensure_genesis_state(
+        &mut replica_storage,
+        L2ChainId::from(270),
+        &GenesisParams::mock(),
+    )
+    .await
+    .unwrap();
+    let mut logs = gen_storage_logs(0..100, 2);
+    let lagging_logs = logs.pop().unwrap();
+    extend_db_state(&mut replica_storage, logs).await;
+    drop(replica_storage);
+
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let (delay_sx, mut delay_rx) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sx;
+    let calculator_handle = tokio::spawn(calculator.run_with_replica(
+        pool.clone(),
+        Some(replica_pool.clone()),
+        prover_pool,
+        stop_rx,
+    ));
+
+    // The L1 batch not yet replicated must be treated as not sealed, i.e., the calculator should wait for it
+    // rather than fail or load it from the primary.
+    for _ in 0..2 {
+        let (next_l1_batch, _) = tokio::time::timeout(RUN_TIMEOUT, delay_rx.recv())
+            .await
+            .expect("metadata calculator timed out processing replicated L1 batches")
+            .unwrap();
+        assert_eq!(next_l1_batch, L1BatchNumber(2));
+    }
+
+    // Replicate the lagging L1 batch and wait until it's processed.
+    extend_db_state(
+        &mut replica_pool.access_storage().await.unwrap(),
+        [lagging_logs],
+    )
+    .await;
+    let root_hash = loop {
         let (next_l1_batch, root_hash) = tokio::time::timeout(RUN_TIMEOUT, delay_rx.recv())
             .await
             .expect("metadata calculator shut down prematurely")
             .unwrap();
-        if next_l1_batch == L1BatchNumber(16) {
-            stop_sx.send(true).unwrap(); // Shut down the calculator.
+        if next_l1_batch == L1BatchNumber(3) {
+            stop_sx.send(true).unwrap();
             break root_hash;
         }
     };
-    tokio::time::timeout(RUN_TIMEOUT, calculator_handle)
+    run_with_timeout(RUN_TIMEOUT, calculator_handle)
         .await
-        .expect("timed out waiting for calculator")
         .unwrap()
         .unwrap();
+    assert_eq!(root_hash, expected_tree_hash(&pool).await);
 
-    // Switch to the full tree. It should pick up from the same spot and result in the same tree root hash.
-    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
-    let root_hash_for_full_tree = run_calculator(calculator, pool, prover_pool).await;
-    assert_eq!(root_hash_for_full_tree, updated_root_hash);
+    // Tree outputs must be written to the primary only.
+    let mut storage = pool.access_storage().await.unwrap();
+    let mut replica_storage = replica_pool.access_storage().await.unwrap();
+    for l1_batch_number in [L1BatchNumber(1), L1BatchNumber(2)] {
+        let metadata = storage
+            .blocks_dal()
+            .get_l1_batch_metadata(l1_batch_number)
+            .await
+            .unwrap();
+        assert!(metadata.is_some(), "{l1_batch_number}");
+        let replica_metadata = replica_storage
+            .blocks_dal()
+            .get_l1_batch_metadata(l1_batch_number)
+            .await
+            .unwrap();
+        assert!(replica_metadata.is_none(), "{l1_batch_number}");
+    }
 }
 
 #[db_test]
-async fn shutting_down_calculator(pool: ConnectionPool, prover_pool: ConnectionPool) {
+async fn l1_batches_are_processed_from_separate_schemas(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    // The shadow chain lives in a separate Postgres schema and has its own prover DB and tree directory.
+    let shadow_pool = ConnectionPool::Test(TestPool::with_schema("shadow_chain").await);
+    let shadow_prover_pool = ConnectionPool::Test(TestPool::new().await);
     let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
-    let (db_config, mut operation_config) = create_config(temp_dir.path());
-    operation_config.delay_interval = 30_000; // ms; chosen to be larger than `RUN_TIMEOUT`
+    let shadow_temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
 
-    let calculator = setup_calculator_with_options(
-        &db_config,
-        &operation_config,
-        &pool,
-        MetadataCalculatorModeConfig::Lightweight,
+    let (calculator, _) = setup_calculator(temp_dir.path(), &pool).await;
+    let (shadow_calculator, _) = setup_calculator(shadow_temp_dir.path(), &shadow_pool).await;
+    reset_db_state(&pool, 2).await;
+    let mut shadow_storage = shadow_pool.access_storage().await.unwrap();
+    extend_db_state(&mut shadow_storage, gen_storage_logs(100..200, 3)).await;
+    drop(shadow_storage);
+
+    let (root_hash, shadow_root_hash) = future::join(
+        run_calculator(calculator, pool.clone(), prover_pool),
+        run_calculator(shadow_calculator, shadow_pool.clone(), shadow_prover_pool),
     )
     .await;
-
-    reset_db_state(&pool, 5).await;
-
-    let (stop_sx, stop_rx) = watch::channel(false);
-    let calculator_task = tokio::spawn(calculator.run(pool, prover_pool, stop_rx));
-    tokio::time::sleep(Duration::from_millis(100)).await;
-    stop_sx.send_replace(true);
-    run_with_timeout(RUN_TIMEOUT, calculator_task)
-        .await
-        .unwrap()
-        .unwrap();
+    assert_ne!(root_hash, shadow_root_hash);
+    assert_eq!(root_hash, expected_tree_hash(&pool).await);
+    assert_eq!(shadow_root_hash, expected_tree_hash(&shadow_pool).await);
+
+    for (pool, last_l1_batch_number, expected_root_hash) in [
+        (&pool, L1BatchNumber(2), root_hash),
+        (&shadow_pool, L1BatchNumber(3), shadow_root_hash),
+    ] {
+        let mut storage = pool.access_storage().await.unwrap();
+        let sealed_l1_batch_number = storage
+            .blocks_dal()
+            .get_sealed_l1_batch_number()
+            .await
+            .unwrap();
+        assert_eq!(sealed_l1_batch_number, last_l1_batch_number);
+        let (persisted_root_hash, _) = storage
+            .blocks_dal()
+            .get_l1_batch_tree_data(last_l1_batch_number)
+            .await
+            .unwrap()
+            .expect("no tree data for the last L1 batch");
+        assert_eq!(persisted_root_hash, expected_root_hash);
+    }
 }
 
 async fn test_postgres_backup_recovery(
@@ -356,7 +2851,235 @@ async fn postgres_backup_recovery_with_excluded_metadata(
     test_postgres_backup_recovery(pool, prover_pool, false, true).await;
 }
 
-async fn setup_calculator(
+#[db_test]
+async fn tree_is_not_truncated_if_diverged_from_postgres(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
+    run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+
+    // Simulate reverting Postgres to a diverged chain.
+    let mut storage = pool.access_storage().await.unwrap();
+    remove_l1_batches(&mut storage, L1BatchNumber(3)).await;
+    storage
+        .blocks_dal()
+        .set_l1_batch_hash(L1BatchNumber(3), H256::repeat_byte(0xff))
+        .await
+        .unwrap();
+    drop(storage);
+
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    let (_stop_sender, stop_receiver) = watch::channel(false);
+    let err = run_with_timeout(
+        RUN_TIMEOUT,
+        calculator.run(pool.clone(), prover_pool, stop_receiver),
+    )
+    .await
+    .unwrap_err();
+    let err = format!("{err:#}");
+    assert!(err.contains("Cannot truncate Merkle tree"), "{err}");
+    assert!(err.contains("histories have diverged"), "{err}");
+
+    // The tree must not be truncated.
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    assert_eq!(
+        calculator.updater.tree().next_l1_batch_number(),
+        L1BatchNumber(6)
+    );
+}
+
+#[db_test]
+async fn injected_save_delay_violates_save_latency_slo(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (mut db_config, operation_config) = create_config(temp_dir.path());
+    db_config.merkle_tree.save_latency_slo_ms = Some(100);
+    db_config.merkle_tree.save_latency_slo_window = 1;
+    db_config.merkle_tree.save_latency_slo_min_compliance = 1.0;
+    let mode = MetadataCalculatorModeConfig::Lightweight;
+    let mut calculator =
+        setup_calculator_with_options(&db_config, &operation_config, &pool, mode).await;
+    reset_db_state(&pool, 2).await;
+    // Emulate a slow disk when saving the tree after L1 batch #2.
+    calculator.updater.injected_delays.set(
+        DelayedStage::Save,
+        Some(L1BatchNumber(2)),
+        Duration::from_millis(500),
+    );
+
+    let tree_health_check = calculator.tree_health_check();
+    let (stop_sender, stop_receiver) = watch::channel(false);
+    let (delay_sender, mut delay_receiver) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sender;
+    let calculator_handle = tokio::spawn(calculator.run(pool.clone(), prover_pool, stop_receiver));
+    wait_for_next_l1_batch(&mut delay_receiver, L1BatchNumber(3)).await;
+
+    let health = tree_health_check.check_health().await;
+    assert_matches!(health.status(), HealthStatus::Affected);
+    let health = serde_json::to_value(health).unwrap();
+    assert_eq!(health["details"]["slo_compliance"], 0.0);
+
+    // The next save isn't delayed, so the SLO should recover.
+    let mut storage = pool.access_storage().await.unwrap();
+    extend_db_state(&mut storage, gen_storage_logs(100..200, 1)).await;
+    drop(storage);
+    wait_for_next_l1_batch(&mut delay_receiver, L1BatchNumber(4)).await;
+
+    let health = tree_health_check.check_health().await;
+    assert_matches!(health.status(), HealthStatus::Ready);
+    let health = serde_json::to_value(health).unwrap();
+    assert_eq!(health["details"]["slo_compliance"], 1.0);
+
+    stop_sender.send_replace(true);
+    run_with_timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .expect("metadata calculator panicked")
+        .unwrap();
+}
+
+async fn wait_for_next_l1_batch(
+    delay_rx: &mut mpsc::UnboundedReceiver<(L1BatchNumber, H256)>,
+    expected_next_l1_batch: L1BatchNumber,
+) {
+    loop {
+        let (next_l1_batch, _) = tokio::time::timeout(RUN_TIMEOUT, delay_rx.recv())
+            .await
+            .expect("metadata calculator timed out")
+            .expect("metadata calculator shut down prematurely");
+        if next_l1_batch == expected_next_l1_batch {
+            break;
+        }
+    }
+}
+
+#[db_test]
+async fn calculator_halts_on_reorg_and_resumes_after_revert(
+    pool: ConnectionPool,
+    prover_pool: ConnectionPool,
+) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let (reorg_sender, reorg_receiver) = watch::channel(None);
+    reorg_sender.send_replace(Some(L1BatchNumber(2)));
+    let mut calculator = setup_lightweight_calculator(temp_dir.path(), &pool)
+        .await
+        .with_reorg_receiver(reorg_receiver);
+    reset_db_state(&pool, 5).await;
+
+    let tree_health_check = calculator.tree_health_check();
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let (delay_sx, mut delay_rx) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sx;
+    let calculator_handle = tokio::spawn(calculator.run(pool.clone(), prover_pool, stop_rx));
+
+    // The calculator must not process L1 batches after the last correct one.
+    let (next_l1_batch, _) = tokio::time::timeout(RUN_TIMEOUT, delay_rx.recv())
+        .await
+        .expect("metadata calculator timed out")
+        .unwrap();
+    assert_eq!(next_l1_batch, L1BatchNumber(3));
+    let health = tree_health_check.check_health().await;
+    assert_matches!(health.status(), HealthStatus::NotReady);
+    let health = serde_json::to_value(health).unwrap();
+    assert_eq!(health["details"]["halted_on_reorg"], true);
+    assert_eq!(health["details"]["last_correct_l1_batch"], 2);
+
+    // Emulate executing the revert and re-sealing L1 batches afterwards.
+    let mut storage = pool.access_storage().await.unwrap();
+    let removed_batches = remove_l1_batches(&mut storage, L1BatchNumber(2)).await;
+    // Wait until the calculator notices the revert and resumes.
+    loop {
+        wait_for_next_l1_batch(&mut delay_rx, L1BatchNumber(3)).await;
+        let health = serde_json::to_value(tree_health_check.check_health().await).unwrap();
+        if health["details"]["halted_on_reorg"].is_null() {
+            break;
+        }
+    }
+    for batch_header in &removed_batches {
+        storage
+            .blocks_dal()
+            .insert_l1_batch(batch_header, &[], BlockGasCount::default())
+            .await
+            .unwrap();
+        insert_initial_writes_for_batch(&mut storage, batch_header.number).await;
+    }
+    drop(storage);
+
+    wait_for_next_l1_batch(&mut delay_rx, L1BatchNumber(6)).await;
+    let health = tree_health_check.check_health().await;
+    assert_matches!(health.status(), HealthStatus::Ready);
+
+    stop_sx.send_replace(true);
+    tokio::time::timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .expect("timed out waiting for calculator")
+        .unwrap()
+        .unwrap();
+}
+
+#[db_test]
+async fn tree_is_truncated_after_reorg_revert(pool: ConnectionPool, prover_pool: ConnectionPool) {
+    let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+    let calculator = setup_lightweight_calculator(temp_dir.path(), &pool).await;
+    reset_db_state(&pool, 5).await;
+    let old_root_hash = run_calculator(calculator, pool.clone(), prover_pool.clone()).await;
+
+    let (_reorg_sender, reorg_receiver) = watch::channel(Some(L1BatchNumber(2)));
+    let mut calculator = setup_lightweight_calculator(temp_dir.path(), &pool)
+        .await
+        .with_reorg_receiver(reorg_receiver);
+    let mut committed_state = calculator.subscribe_committed_state();
+    let mut notifications = calculator.subscribe_processed_l1_batches();
+    let tree_health_check = calculator.tree_health_check();
+    let (stop_sx, stop_rx) = watch::channel(false);
+    let (delay_sx, mut delay_rx) = mpsc::unbounded_channel();
+    calculator.delayer.delay_notifier = delay_sx;
+    let calculator_handle = tokio::spawn(calculator.run(pool.clone(), prover_pool, stop_rx));
+    wait_for_next_l1_batch(&mut delay_rx, L1BatchNumber(6)).await;
+
+    let mut storage = pool.access_storage().await.unwrap();
+    remove_l1_batches(&mut storage, L1BatchNumber(2)).await;
+    let expected_root_hash = storage
+        .blocks_dal()
+        .get_l1_batch_state_root(L1BatchNumber(2))
+        .await
+        .unwrap()
+        .unwrap();
+    drop(storage);
+
+    wait_for_next_l1_batch(&mut delay_rx, L1BatchNumber(3)).await;
+    let state = committed_state.borrow_and_update().unwrap();
+    assert_eq!(state.l1_batch_number, L1BatchNumber(2));
+    assert_eq!(state.root_hash, expected_root_hash);
+    let health = tree_health_check.check_health().await;
+    let health = serde_json::to_value(health).unwrap();
+    assert_eq!(health["details"]["recently_reverted_to"], 2);
+
+    stop_sx.send_replace(true);
+    tokio::time::timeout(RUN_TIMEOUT, calculator_handle)
+        .await
+        .expect("timed out waiting for calculator")
+        .unwrap()
+        .unwrap();
+
+    let mut reverts = vec![];
+    while let Some(notification) = notifications.recv().await {
+        if let L1BatchNotification::Reverted(revert) = notification {
+            reverts.push(revert);
+        }
+    }
+    assert_eq!(reverts.len(), 1, "{reverts:?}");
+    assert_eq!(reverts[0].reverted_to, L1BatchNumber(2));
+    assert_eq!(reverts[0].reverted_l1_batch_count, 3);
+    assert_eq!(reverts[0].old_root_hash, old_root_hash);
+    assert_eq!(reverts[0].new_root_hash, expected_root_hash);
+}
+
+pub(super) async fn setup_calculator(
     db_path: &Path,
     pool: &ConnectionPool,
 ) -> (MetadataCalculator, Box<dyn ObjectStore>) {
@@ -368,7 +3091,10 @@ async fn setup_calculator(
     (calculator, store_factory.create_store().await)
 }
 
-async fn setup_lightweight_calculator(db_path: &Path, pool: &ConnectionPool) -> MetadataCalculator {
+pub(crate) async fn setup_lightweight_calculator(
+    db_path: &Path,
+    pool: &ConnectionPool,
+) -> MetadataCalculator {
     let mode = MetadataCalculatorModeConfig::Lightweight;
     let (db_config, operation_config) = create_config(db_path);
     setup_calculator_with_options(&db_config, &operation_config, pool, mode).await
@@ -397,27 +3123,9 @@ async fn setup_calculator_with_options(
 
     let mut storage = pool.access_storage().await.unwrap();
     if storage.blocks_dal().is_genesis_needed().await.unwrap() {
-        let chain_id = L2ChainId::from(270);
-        let protocol_version = ProtocolVersionId::latest();
-        let base_system_contracts = BaseSystemContracts::load_from_disk();
-        let system_contracts = get_system_smart_contracts();
-        let first_validator = Address::repeat_byte(0x01);
-        let first_l1_verifier_config = L1VerifierConfig::default();
-        let first_verifier_address = Address::zero();
-        ensure_genesis_state(
-            &mut storage,
-            chain_id,
-            &GenesisParams {
-                first_validator,
-                protocol_version,
-                base_system_contracts,
-                system_contracts,
-                first_l1_verifier_config,
-                first_verifier_address,
-            },
-        )
-        .await
-        .unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
     }
     metadata_calculator
 }
@@ -426,7 +3134,7 @@ fn path_to_string(path: &Path) -> String {
     path.to_str().unwrap().to_owned()
 }
 
-async fn run_calculator(
+pub(crate) async fn run_calculator(
     mut calculator: MetadataCalculator,
     pool: ConnectionPool,
     prover_pool: ConnectionPool,
@@ -451,7 +3159,7 @@ async fn run_calculator(
     delayer_handle.await.unwrap()
 }
 
-pub(super) async fn reset_db_state(pool: &ConnectionPool, num_batches: usize) {
+pub(crate) async fn reset_db_state(pool: &ConnectionPool, num_batches: usize) {
     let mut storage = pool.access_storage().await.unwrap();
     // Drops all L1 batches (except the L1 batch with number 0) and their storage logs.
     storage
@@ -473,140 +3181,46 @@ pub(super) async fn reset_db_state(pool: &ConnectionPool, num_batches: usize) {
     extend_db_state(&mut storage, logs).await;
 }
 
-pub(super) async fn extend_db_state(
-    storage: &mut StorageProcessor<'_>,
-    new_logs: impl IntoIterator<Item = Vec<StorageLog>>,
-) {
-    let next_l1_batch = storage
-        .blocks_dal()
-        .get_sealed_l1_batch_number()
-        .await
-        .unwrap()
-        .0
-        + 1;
-
-    let base_system_contracts = BaseSystemContracts::load_from_disk();
-    for (idx, batch_logs) in (next_l1_batch..).zip(new_logs) {
-        let batch_number = L1BatchNumber(idx);
-        let mut header = L1BatchHeader::new(
-            batch_number,
-            0,
-            Address::default(),
-            base_system_contracts.hashes(),
-            Default::default(),
-        );
-        header.is_finished = true;
-
-        // Assumes that L1 batch consists of only one miniblock.
-        let miniblock_number = MiniblockNumber(idx);
-        let miniblock_header = MiniblockHeader {
-            number: miniblock_number,
-            timestamp: header.timestamp,
-            hash: miniblock_hash(
-                miniblock_number,
-                header.timestamp,
-                H256::zero(),
-                H256::zero(),
-            ),
-            l1_tx_count: header.l1_tx_count,
-            l2_tx_count: header.l2_tx_count,
-            base_fee_per_gas: header.base_fee_per_gas,
-            l1_gas_price: 0,
-            l2_fair_gas_price: 0,
-            base_system_contracts_hashes: base_system_contracts.hashes(),
-            protocol_version: Some(Default::default()),
-            virtual_blocks: 0,
-        };
-
-        storage
-            .blocks_dal()
-            .insert_l1_batch(&header, &[], BlockGasCount::default())
-            .await
-            .unwrap();
-        storage
-            .blocks_dal()
-            .insert_miniblock(&miniblock_header)
-            .await
-            .unwrap();
-        storage
-            .storage_logs_dal()
-            .insert_storage_logs(miniblock_number, &[(H256::zero(), batch_logs)])
-            .await;
-        storage
-            .blocks_dal()
-            .mark_miniblocks_as_executed_in_l1_batch(batch_number)
-            .await
-            .unwrap();
-        insert_initial_writes_for_batch(storage, batch_number).await;
+/// Creates a witness input with `path_count` synthetic Merkle paths. Paths are highly compressible,
+/// and their hashes partially coincide, so the witness is compacted.
+pub(crate) fn create_mock_witness(path_count: usize) -> PrepareBasicCircuitsJob {
+    let mut job = PrepareBasicCircuitsJob::new(1);
+    for i in 0..path_count {
+        job.push_merkle_path(StorageLogMetadata {
+            root_hash: [i as u8; 32],
+            is_write: i % 2 == 0,
+            first_write: i % 3 == 0,
+            merkle_paths: (0..256).map(|j| [(i + j) as u8; 32]).collect(),
+            leaf_hashed_key: (i as u64).into(),
+            leaf_enumeration_index: i as u64 + 1,
+            value_written: [1; 32],
+            value_read: [2; 32],
+        });
     }
+    job
 }
 
-async fn insert_initial_writes_for_batch(
-    connection: &mut StorageProcessor<'_>,
+/// Stores `job` in the chunked format with up to `chunk_size` Merkle paths per chunk.
+/// Returns the key of the stored manifest.
+pub(crate) async fn store_chunked_witness(
+    object_store: &dyn ObjectStore,
     l1_batch_number: L1BatchNumber,
-) {
-    let written_non_zero_slots: Vec<_> = connection
-        .storage_logs_dal()
-        .get_touched_slots_for_l1_batch(l1_batch_number)
-        .await
-        .into_iter()
-        .filter_map(|(key, value)| (!value.is_zero()).then_some(key))
-        .collect();
-    let hashed_keys: Vec<_> = written_non_zero_slots
-        .iter()
-        .map(|key| key.hashed_key())
-        .collect();
-    let pre_written_slots = connection
-        .storage_logs_dedup_dal()
-        .filter_written_slots(&hashed_keys)
-        .await;
-
-    let keys_to_insert: Vec<_> = written_non_zero_slots
-        .into_iter()
-        .sorted()
-        .filter(|key| !pre_written_slots.contains(&key.hashed_key()))
-        .collect();
-    connection
-        .storage_logs_dedup_dal()
-        .insert_initial_writes(l1_batch_number, &keys_to_insert)
-        .await;
-}
-
-pub(super) fn gen_storage_logs(
-    indices: ops::Range<u32>,
-    num_batches: usize,
-) -> Vec<Vec<StorageLog>> {
-    // Addresses and keys of storage logs must be sorted for the `multi_block_workflow` test.
-    let mut accounts = [
-        "4b3af74f66ab1f0da3f2e4ec7a3cb99baf1af7b2",
-        "ef4bb7b21c5fe7432a7d63876cc59ecc23b46636",
-        "89b8988a018f5348f52eeac77155a793adf03ecc",
-        "782806db027c08d36b2bed376b4271d1237626b3",
-        "b2b57b76717ee02ae1327cc3cf1f40e76f692311",
-    ]
-    .map(|s| AccountTreeId::new(s.parse::<Address>().unwrap()));
-    accounts.sort_unstable();
-
-    let account_keys = (indices.start / 5)..(indices.end / 5);
-    let proof_keys = accounts.iter().flat_map(|&account| {
-        account_keys
-            .clone()
-            .map(move |i| StorageKey::new(account, u32_to_h256(i)))
-    });
-    let proof_values = indices.map(u32_to_h256);
-
-    let logs: Vec<_> = proof_keys
-        .zip(proof_values)
-        .map(|(proof_key, proof_value)| StorageLog::new_write_log(proof_key, proof_value))
-        .collect();
-    for window in logs.windows(2) {
-        let [prev, next] = window else { unreachable!() };
-        assert!(prev.key < next.key);
+    job: &PrepareBasicCircuitsJob,
+    chunk_size: usize,
+) -> String {
+    let mut manifest = PrepareBasicCircuitsJobManifest::new(job.next_enumeration_index());
+    for (chunk_index, chunk) in job.clone().into_chunks(chunk_size).enumerate() {
+        let blob = chunk.serialize().unwrap();
+        manifest
+            .chunks
+            .push(WitnessChunkMetadata::new(chunk.merkle_path_count(), &blob));
+        let key = witness_input_chunk_key(l1_batch_number, chunk_index);
+        object_store
+            .put_raw(Bucket::WitnessInput, &key, blob)
+            .await
+            .unwrap();
     }
-
-    logs.chunks(logs.len() / num_batches)
-        .map(<[_]>::to_vec)
-        .collect()
+    object_store.put(l1_batch_number, &manifest).await.unwrap()
 }
 
 async fn remove_l1_batches(
@@ -642,26 +3256,9 @@ async fn remove_l1_batches(
 async fn deduplication_works_as_expected(pool: ConnectionPool) {
     let mut storage = pool.access_storage().await.unwrap();
 
-    let first_validator = Address::repeat_byte(0x01);
-    let protocol_version = ProtocolVersionId::latest();
-    let base_system_contracts = BaseSystemContracts::load_from_disk();
-    let system_contracts = get_system_smart_contracts();
-    let first_l1_verifier_config = L1VerifierConfig::default();
-    let first_verifier_address = Address::zero();
-    ensure_genesis_state(
-        &mut storage,
-        L2ChainId::from(270),
-        &GenesisParams {
-            protocol_version,
-            first_validator,
-            base_system_contracts,
-            system_contracts,
-            first_l1_verifier_config,
-            first_verifier_address,
-        },
-    )
-    .await
-    .unwrap();
+    ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+        .await
+        .unwrap();
 
     let logs = gen_storage_logs(100..120, 1).pop().unwrap();
     let hashed_keys: Vec<_> = logs.iter().map(|log| log.key.hashed_key()).collect();
@@ -0,0 +1,81 @@
+//! Checking that the Merkle tree has stored versions for all sealed L1 batches.
+
+use anyhow::Context as _;
+
+use std::{collections::HashSet, ops};
+
+use zksync_dal::StorageProcessor;
+use zksync_merkle_tree::domain::ZkSyncTreeReader;
+use zksync_types::L1BatchNumber;
+
+/// Maximum number of missing L1 batches mentioned in the error message.
+const MAX_REPORTED_L1_BATCHES: usize = 10;
+
+/// Report produced by [`check_tree_versions()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeVersionsReport {
+    /// Range of L1 batches that was checked.
+    pub l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+    /// Number of sealed L1 batches in the checked range.
+    pub sealed_count: usize,
+    /// Sealed L1 batches without a stored tree version, in the ascending order.
+    pub missing: Vec<L1BatchNumber>,
+}
+
+impl TreeVersionsReport {
+    /// Checks that the tree has stored versions for all sealed L1 batches in the checked range.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing (some of) the missing L1 batches otherwise.
+    pub fn ensure_complete(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.missing.is_empty(),
+            "Merkle tree is missing versions for {} of {} sealed L1 batches in #{}..=#{} (e.g., {:?})",
+            self.missing.len(),
+            self.sealed_count,
+            self.l1_batch_numbers.start(),
+            self.l1_batch_numbers.end(),
+            &self.missing[..self.missing.len().min(MAX_REPORTED_L1_BATCHES)]
+        );
+        Ok(())
+    }
+}
+
+/// Checks that the tree has a stored version for each L1 batch in the specified range sealed in Postgres.
+/// Unlike the tree update loop (which only compares the next L1 batch of the tree with Postgres),
+/// this check surfaces gaps in processing, e.g. tree versions lost because of manual intervention.
+///
+/// L1 batches pruned from the tree are reported as missing, so the range should start
+/// at or after the first L1 batch retained by the tree.
+pub async fn check_tree_versions(
+    storage: &mut StorageProcessor<'_>,
+    tree: &ZkSyncTreeReader,
+    l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+) -> anyhow::Result<TreeVersionsReport> {
+    let sealed_l1_batches = storage
+        .blocks_dal()
+        .get_sealed_l1_batch_numbers(l1_batch_numbers.clone())
+        .await
+        .context("failed loading sealed L1 batches")?;
+    let stored_versions: HashSet<_> = tree
+        .stored_versions(l1_batch_numbers.clone())
+        .into_iter()
+        .collect();
+    let missing: Vec<_> = sealed_l1_batches
+        .iter()
+        .copied()
+        .filter(|l1_batch_number| !stored_versions.contains(l1_batch_number))
+        .collect();
+
+    if !missing.is_empty() {
+        tracing::warn!(
+            "Merkle tree is missing versions for sealed L1 batches: {missing:?} (checked range: {l1_batch_numbers:?})"
+        );
+    }
+    Ok(TreeVersionsReport {
+        l1_batch_numbers,
+        sealed_count: sealed_l1_batches.len(),
+        missing,
+    })
+}
@@ -3,256 +3,843 @@ use anyhow::Context as _;
 use futures::{future, FutureExt};
 use tokio::sync::watch;
 
-use std::{ops, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    ops,
+    time::Instant,
+};
 
-use zksync_config::configs::database::MerkleTreeMode;
+use zksync_config::configs::database::{
+    DalConsistencyPolicy, EnumerationIndexCheck, MerkleTreeMode, TreeHealthDetailsFormat,
+};
 use zksync_dal::{ConnectionPool, StorageProcessor};
 use zksync_health_check::HealthUpdater;
-use zksync_merkle_tree::domain::TreeMetadata;
-use zksync_object_store::ObjectStore;
-use zksync_types::{block::L1BatchHeader, writes::InitialStorageWrite, L1BatchNumber, U256};
+use zksync_merkle_tree::domain::{TreeMetadata, ZkSyncTreeReader};
+use zksync_object_store::MULTIPART_UPLOAD_PART_ALIGNMENT;
+use zksync_types::{
+    block::L1BatchHeader,
+    commitment::TreeData,
+    writes::{InitialStorageWrite, RepeatedStorageWrite},
+    L1BatchNumber, ProtocolVersionId, H256, U256,
+};
 
+#[cfg(test)]
+use super::injected_delays::{DelayedStage, InjectedDelays};
+#[cfg(feature = "webhook")]
+use super::webhook::{RootWebhookPayload, RootWebhookSender};
 use super::{
-    helpers::{AsyncTree, Delayer, L1BatchWithLogs, TreeHealthCheckDetails},
-    metrics::{ReportStage, TreeUpdateStage},
-    MetadataCalculator, MetadataCalculatorConfig,
+    helpers::{
+        handle_dal_inconsistency, AsyncTree, CommittedTreeState, ConnectionRetryPolicy,
+        ConnectionSource, Delayer, L1BatchWithLogs, ProtectiveReads, TreeHealthCheckDetails,
+    },
+    hot_keys::HotKeysTracker,
+    logs_verifier::LogsVerifier,
+    metrics::{LoadChangesStage, ReportStage, TreeUpdateStage, METRICS},
+    notifications::{L1BatchNotifier, ProcessedL1Batch, ProcessedL1BatchesReceiver, TreeRevert},
+    reorg_halt::ReorgHalt,
+    save_latency_slo::SaveLatencyTracker,
+    stall_watchdog::TreeHeartbeat,
+    watched_keys::{WatchedKeyProofs, WatchedKeys},
+    witness::{WitnessBuilder, WitnessInput},
+    witness_stage::{PendingWitness, SavedWitness, WitnessStage},
+    EmptyL1BatchRetries, MetadataCalculator, MetadataCalculatorConfig, ProtectiveReadsThresholds,
+    TreeHealthThresholds,
 };
 
+/// Mismatch between a repeated write produced by the tree and the `initial_writes` table in Postgres.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum RepeatedWriteMismatch {
+    /// No initial write with the leaf index of the repeated write.
+    MissingInitialWrite { index: u64 },
+    /// The leaf index was allocated in the same or a later L1 batch.
+    LateInitialWrite {
+        index: u64,
+        hashed_key: H256,
+        l1_batch_number: L1BatchNumber,
+    },
+    /// The key the leaf index was allocated to is not written in the L1 batch with the value
+    /// reported by the tree.
+    ValueMismatch {
+        index: u64,
+        hashed_key: H256,
+        tree_value: H256,
+        batch_value: Option<H256>,
+    },
+}
+
+/// Size of parts in which witness inputs are uploaded to the object store (8 MiB).
+pub(super) const WITNESS_UPLOAD_PART_SIZE: usize = 32 * MULTIPART_UPLOAD_PART_ALIGNMENT;
+
 #[derive(Debug)]
 pub(super) struct TreeUpdater {
     mode: MerkleTreeMode,
     tree: AsyncTree,
     max_l1_batches_per_iter: usize,
-    object_store: Option<Box<dyn ObjectStore>>,
+    validate_initial_writes: bool,
+    validate_repeated_writes: bool,
+    witness_stage: Option<WitnessStage>,
+    max_in_memory_slots: usize,
+    health_thresholds: TreeHealthThresholds,
+    health_details_format: TreeHealthDetailsFormat,
+    max_supported_protocol_version: ProtocolVersionId,
+    hot_keys: Option<HotKeysTracker>,
+    /// Cached proofs for watched keys; `None` if no keys are watched.
+    watched_keys: Option<WatchedKeys>,
+    #[cfg(feature = "webhook")]
+    root_webhook: Option<RootWebhookSender>,
+    connection_retry_policy: ConnectionRetryPolicy,
+    logs_verifier: LogsVerifier,
+    enumeration_index_check: EnumerationIndexCheck,
+    progress_check: EnumerationIndexCheck,
+    dal_consistency_policy: DalConsistencyPolicy,
+    save_latency: Option<SaveLatencyTracker>,
+    empty_l1_batch_retries: Option<EmptyL1BatchRetries>,
+    /// Thresholds for warning about excessive protective reads; `None` in the lightweight mode,
+    /// in which protective reads are not loaded.
+    protective_reads_thresholds: Option<ProtectiveReadsThresholds>,
+    /// Genesis root hash persisted in Postgres; loaded when the tree update loop starts.
+    genesis_root_hash: Option<H256>,
+    /// Sends a heartbeat on each iteration of the tree update loop; used to detect stalls.
+    heartbeat_sender: watch::Sender<Option<TreeHeartbeat>>,
+    /// Publishes the tree state after each save to RocksDB; `None` until the tree is initialized.
+    committed_state_sender: watch::Sender<Option<CommittedTreeState>>,
+    /// Halts the tree on reorgs signalled by the reorg detector, if configured.
+    reorg_halt: Option<ReorgHalt>,
+    l1_batch_notifier: L1BatchNotifier,
+    /// Last L1 batch kept by the most recent tree revert together with the revert time.
+    recent_revert: Option<(L1BatchNumber, Instant)>,
+    /// Artificial delays injected into tree processing, saving and loading L1 batch data.
+    #[cfg(test)]
+    pub injected_delays: InjectedDelays,
 }
 
 impl TreeUpdater {
     pub async fn new(
         mode: MerkleTreeMode,
         config: &MetadataCalculatorConfig<'_>,
-        object_store: Option<Box<dyn ObjectStore>>,
+        witness_stage: Option<WitnessStage>,
     ) -> Self {
         assert!(
             config.max_l1_batches_per_iter > 0,
             "Maximum L1 batches per iteration is misconfigured to be 0; please update it to positive value"
         );
+        assert!(
+            config.db_connection_max_attempts > 0,
+            "Maximum number of Postgres connection attempts is misconfigured to be 0; please update it to positive value"
+        );
 
         let db_path = config.db_path.into();
-        let tree = AsyncTree::new(
+        let mut tree = AsyncTree::new(
             db_path,
             mode,
             config.multi_get_chunk_size,
             config.block_cache_capacity,
+            config.rocksdb_rate_limit_bytes_per_sec,
+            config.auto_rebuild_on_corruption,
+            config.root_hash_cache_size,
         )
         .await;
+        if mode == MerkleTreeMode::Full && witness_stage.is_none() {
+            tracing::warn!(
+                "Witness input generation is disabled for the full-mode Merkle tree; witness inputs \
+                 will not be persisted to the object store, so provers will not find them"
+            );
+            tree.set_witness_generation(false);
+        }
         Self {
             mode,
             tree,
             max_l1_batches_per_iter: config.max_l1_batches_per_iter,
-            object_store,
+            validate_initial_writes: config.validate_initial_writes,
+            validate_repeated_writes: config.validate_repeated_writes,
+            witness_stage,
+            max_in_memory_slots: config.max_in_memory_slots,
+            health_thresholds: config.health_thresholds,
+            health_details_format: config.health_details_format,
+            max_supported_protocol_version: config.max_supported_protocol_version,
+            hot_keys: config
+                .hot_keys_report_top_n
+                .map(|top_n| HotKeysTracker::new(top_n, config.hot_keys_report_window)),
+            watched_keys: (!config.watched_keys.is_empty())
+                .then(|| WatchedKeys::new(config.watched_keys.iter().copied())),
+            #[cfg(feature = "webhook")]
+            root_webhook: None,
+            connection_retry_policy: ConnectionRetryPolicy {
+                max_attempts: config.db_connection_max_attempts,
+                initial_backoff: config.db_connection_backoff,
+            },
+            logs_verifier: LogsVerifier::new(
+                config.logs_verification_modulus,
+                mode,
+                config.logs_verification_cache_capacity,
+            ),
+            enumeration_index_check: config.enumeration_index_check,
+            progress_check: config.progress_check,
+            dal_consistency_policy: config.dal_consistency_policy,
+            save_latency: config.save_latency_slo.map(SaveLatencyTracker::new),
+            empty_l1_batch_retries: config.empty_l1_batch_retries,
+            protective_reads_thresholds: (mode == MerkleTreeMode::Full)
+                .then_some(config.protective_reads_thresholds),
+            genesis_root_hash: None,
+            heartbeat_sender: watch::channel(None).0,
+            committed_state_sender: watch::channel(None).0,
+            reorg_halt: None,
+            l1_batch_notifier: L1BatchNotifier::new(config.l1_batch_notifications_capacity),
+            recent_revert: None,
+            #[cfg(test)]
+            injected_delays: InjectedDelays::default(),
         }
     }
 
+    pub fn mode(&self) -> MerkleTreeMode {
+        self.mode
+    }
+
+    /// Checks whether this updater produces witness inputs and persists them to the object store.
+    pub fn produces_witnesses(&self) -> bool {
+        self.witness_stage.is_some()
+    }
+
+    /// Sets the webhook notified about root hashes of processed L1 batches.
+    #[cfg(feature = "webhook")]
+    pub fn with_root_webhook(mut self, root_webhook: RootWebhookSender) -> Self {
+        self.root_webhook = Some(root_webhook);
+        self
+    }
+
     #[cfg(test)]
     pub fn tree(&self) -> &AsyncTree {
         &self.tree
     }
 
+    /// Returns a read-only handle to the tree observing versions saved to RocksDB.
+    pub fn tree_reader(&self) -> ZkSyncTreeReader {
+        self.tree.reader()
+    }
+
+    /// Returns the number of the next L1 batch to be processed by the tree.
+    pub fn next_l1_batch_number(&self) -> L1BatchNumber {
+        self.tree.next_l1_batch_number()
+    }
+
+    pub fn tree_mut(&mut self) -> &mut AsyncTree {
+        &mut self.tree
+    }
+
+    /// Subscribes to heartbeats of the tree update loop. The returned receiver is closed
+    /// once the loop terminates.
+    pub fn subscribe_heartbeat(&self) -> watch::Receiver<Option<TreeHeartbeat>> {
+        self.heartbeat_sender.subscribe()
+    }
+
+    /// Subscribes to the tree state saved to RocksDB. The state is updated each time the tree
+    /// is saved, i.e., it is consistent with [`ZkSyncTreeReader`] obtained from the tree.
+    pub fn subscribe_committed_state(&self) -> watch::Receiver<Option<CommittedTreeState>> {
+        self.committed_state_sender.subscribe()
+    }
+
+    /// Returns a handle to cached proofs for watched keys.
+    pub fn watched_key_proofs(&self) -> WatchedKeyProofs {
+        self.watched_keys
+            .as_ref()
+            .map(WatchedKeys::proofs)
+            .unwrap_or_default()
+    }
+
+    /// Subscribes to notifications about L1 batches processed by the tree.
+    pub fn subscribe_processed_l1_batches(&self) -> ProcessedL1BatchesReceiver {
+        self.l1_batch_notifier.subscribe()
+    }
+
+    /// Subscribes to reorg signals carrying the last correct L1 batch; see [`ReorgHalt`].
+    pub fn set_reorg_receiver(&mut self, receiver: watch::Receiver<Option<L1BatchNumber>>) {
+        self.reorg_halt = Some(ReorgHalt::new(receiver));
+    }
+
+    /// Processes an L1 batch. If `witness_builder` is provided, the witness input is streamed to it
+    /// while the L1 batch is processed.
     async fn process_l1_batch(
         &mut self,
         l1_batch: L1BatchWithLogs,
-    ) -> (L1BatchHeader, TreeMetadata, Option<String>) {
+        witness_builder: Option<WitnessBuilder>,
+    ) -> (
+        L1BatchHeader,
+        TreeMetadata,
+        Option<anyhow::Result<WitnessInput>>,
+    ) {
+        #[cfg(test)]
+        self.injected_delays
+            .inject(DelayedStage::ProcessL1Batch, l1_batch.header.number)
+            .await;
         let compute_latency = TreeUpdateStage::Compute.start();
-        let mut metadata = self.tree.process_l1_batch(l1_batch.storage_logs).await;
-        compute_latency.report();
-
-        let witness_input = metadata.witness.take();
-        let l1_batch_number = l1_batch.header.number;
-        let object_key = if let Some(object_store) = &self.object_store {
-            let witness_input =
-                witness_input.expect("No witness input provided by tree; this is a bug");
-            let save_witnesses_latency = TreeUpdateStage::SaveWitnesses.start();
-            let object_key = object_store
-                .put(l1_batch_number, &witness_input)
-                .await
-                .unwrap();
-            save_witnesses_latency.report();
-
-            tracing::info!(
-                "Saved witnesses for L1 batch #{l1_batch_number} to object storage at `{object_key}`"
-            );
-            Some(object_key)
+        let (metadata, witness) = if let Some(witness_builder) = witness_builder {
+            let (metadata, witness) = self
+                .tree
+                .process_l1_batch_with_witness(l1_batch.storage_logs, witness_builder)
+                .await;
+            (metadata, Some(witness))
         } else {
-            None
+            let metadata = self.tree.process_l1_batch(l1_batch.storage_logs).await;
+            (metadata, None)
         };
+        compute_latency.report();
+        (l1_batch.header, metadata, witness)
+    }
 
-        (l1_batch.header, metadata, object_key)
+    /// Takes the witness input produced by the tree as a whole from `metadata`.
+    async fn take_witness(
+        witness_stage: &WitnessStage,
+        l1_batch_number: L1BatchNumber,
+        protocol_version: Option<ProtocolVersionId>,
+        metadata: &mut TreeMetadata,
+    ) -> anyhow::Result<PendingWitness> {
+        let job = metadata
+            .witness
+            .take()
+            .expect("No witness input provided by tree; this is a bug");
+        let input = witness_stage
+            .persister()
+            .wrap_witness(job, protocol_version)
+            .await?;
+        Ok(PendingWitness {
+            l1_batch_number,
+            protocol_version,
+            input,
+            next_enumeration_index: metadata.rollup_last_leaf_index,
+        })
+    }
+
+    /// Persists the witness input taken from `metadata` to the object store, if the updater has one.
+    /// Unlike witnesses for L1 batches processed in the main loop, the witness is uploaded right away.
+    pub(super) async fn save_witness_input(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        protocol_version: Option<ProtocolVersionId>,
+        metadata: &mut TreeMetadata,
+    ) -> Option<SavedWitness> {
+        let witness_stage = self.witness_stage.as_ref()?;
+        let witness =
+            Self::take_witness(witness_stage, l1_batch_number, protocol_version, metadata)
+                .await
+                .expect("Failed wrapping witness input");
+        let saved_witness = witness_stage
+            .persister()
+            .upload(witness)
+            .await
+            .expect("Failed saving witness input");
+        Some(saved_witness)
     }
 
     /// Processes a range of L1 batches with a single flushing of the tree updates to RocksDB at the end.
     /// This allows to save on RocksDB I/O ops.
     ///
+    /// `headers` must be non-empty and correspond to a contiguous range of L1 batches. If `replica_storage`
+    /// is specified, L1 batch data is loaded from it; all other queries are made using `storage`.
     /// Returns the number of the next L1 batch to be processed by the tree.
     ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the L1 batches touches too many storage slots to be loaded into memory,
+    /// or has a protocol version not supported by the tree.
+    ///
     /// # Implementation details
     ///
     /// We load L1 batch data from Postgres in parallel with updating the tree. (Naturally, we need to load
     /// the first L1 batch data beforehand.) This allows saving some time if we actually process
     /// multiple L1 batches at once (e.g., during the initial tree syncing), and if loading data from Postgres
     /// is slow for whatever reason.
-    async fn process_multiple_batches(
+    async fn process_multiple_batches<'a>(
         &mut self,
-        storage: &mut StorageProcessor<'_>,
-        prover_storage: &mut StorageProcessor<'_>,
-        l1_batch_numbers: ops::RangeInclusive<u32>,
-    ) -> L1BatchNumber {
+        storage: &mut StorageProcessor<'a>,
+        mut replica_storage: Option<&mut StorageProcessor<'a>>,
+        headers: Vec<L1BatchHeader>,
+        finalized_l1_batches: &HashSet<L1BatchNumber>,
+    ) -> anyhow::Result<L1BatchNumber> {
         let start = Instant::now();
+        let first_l1_batch_number = headers.first().expect("no L1 batches to process").number;
+        let last_l1_batch_number = headers.last().unwrap().number;
+        let l1_batch_numbers = first_l1_batch_number..=last_l1_batch_number;
         tracing::info!("Processing L1 batches #{l1_batch_numbers:?}");
-        let first_l1_batch_number = L1BatchNumber(*l1_batch_numbers.start());
-        let last_l1_batch_number = L1BatchNumber(*l1_batch_numbers.end());
-        let mut l1_batch_data = L1BatchWithLogs::new(storage, first_l1_batch_number).await;
+        if self.validate_initial_writes {
+            Self::validate_initial_writes(
+                storage,
+                l1_batch_numbers.clone(),
+                self.dal_consistency_policy,
+            )
+            .await?;
+        }
+        for header in &headers {
+            Self::check_protocol_version(header, self.max_supported_protocol_version)?;
+            let load_storage = replica_storage.as_deref_mut().unwrap_or(&mut *storage);
+            Self::check_touched_slots_count(load_storage, header.number, self.max_in_memory_slots)
+                .await?;
+            Self::check_empty_l1_batch(load_storage, header.number, self.empty_l1_batch_retries)
+                .await?;
+        }
+
+        let mode = self.mode;
+        #[cfg(test)]
+        let injected_delays = self.injected_delays.clone();
+        let protective_reads_latency = LoadChangesStage::ProtectiveReads.start();
+        let load_storage = replica_storage.as_deref_mut().unwrap_or(&mut *storage);
+        let mut protective_reads = load_storage
+            .storage_logs_dedup_dal()
+            .get_protective_reads_for_l1_batch_range(l1_batch_numbers)
+            .await;
+        let protective_reads_count = protective_reads.values().map(Vec::len).sum::<usize>();
+        protective_reads_latency.report_with_count(protective_reads_count);
+        let mut take_protective_reads = |l1_batch_number: L1BatchNumber| {
+            let keys = protective_reads
+                .remove(&l1_batch_number)
+                .unwrap_or_default();
+            ProtectiveReads::new(keys, mode)
+        };
+
+        let mut headers = headers.into_iter();
+        let first_header = headers.next().unwrap();
+        let first_protective_reads = take_protective_reads(first_header.number);
+        #[cfg(test)]
+        injected_delays
+            .inject(DelayedStage::Load, first_header.number)
+            .await;
+        let mut l1_batch_data = L1BatchWithLogs::with_protective_reads(
+            load_storage,
+            first_header,
+            first_protective_reads,
+        )
+        .await;
 
         let mut previous_root_hash = self.tree.root_hash();
         let mut total_logs = 0;
         let mut updated_headers = vec![];
-        for l1_batch_number in l1_batch_numbers {
-            let l1_batch_number = L1BatchNumber(l1_batch_number);
-            let Some(current_l1_batch_data) = l1_batch_data else {
-                return l1_batch_number;
-            };
-            total_logs += current_l1_batch_data.storage_logs.len();
+        let mut processed_l1_batches = vec![];
+        loop {
+            let l1_batch_number = l1_batch_data.header.number;
+            let protocol_version = l1_batch_data.header.protocol_version;
+            total_logs += l1_batch_data.storage_logs.len();
+            self.logs_verifier
+                .verify_if_sampled(storage, &l1_batch_data)
+                .await;
+            if let Some(thresholds) = &self.protective_reads_thresholds {
+                l1_batch_data.check_protective_reads_ratio(thresholds);
+            }
+            if let Some(hot_keys) = &mut self.hot_keys {
+                if let Some(report) = hot_keys.observe(l1_batch_number, &l1_batch_data.storage_logs)
+                {
+                    tracing::info!("Hot storage keys report: {report}");
+                }
+            }
+            if let Some(watched_keys) = &mut self.watched_keys {
+                watched_keys.observe(&l1_batch_data.storage_logs);
+            }
+
+            let produces_witness =
+                self.witness_stage.is_some() && !finalized_l1_batches.contains(&l1_batch_number);
+            if self.witness_stage.is_some() {
+                if produces_witness {
+                    tracing::debug!("Producing witness input for L1 batch #{l1_batch_number}");
+                } else {
+                    tracing::info!(
+                        "L1 batch #{l1_batch_number} already has a final proof; skipping witness input production"
+                    );
+                    METRICS.skipped_witnesses_for_proven_l1_batches.inc();
+                }
+                self.tree.set_witness_generation(produces_witness);
+            }
 
-            let process_l1_batch_task = self.process_l1_batch(current_l1_batch_data);
+            let witness_builder = self
+                .witness_stage
+                .as_ref()
+                .filter(|_| produces_witness)
+                .map(|stage| stage.persister().witness_builder(protocol_version));
+            let process_l1_batch_task = self.process_l1_batch(l1_batch_data, witness_builder);
+            let next_l1_batch = headers.next().map(|header| {
+                let protective_reads = take_protective_reads(header.number);
+                (header, protective_reads)
+            });
+            let load_storage = replica_storage.as_deref_mut().unwrap_or(&mut *storage);
             let load_next_l1_batch_task = async {
-                if l1_batch_number < last_l1_batch_number {
-                    L1BatchWithLogs::new(storage, l1_batch_number + 1).await
+                if let Some((header, protective_reads)) = next_l1_batch {
+                    #[cfg(test)]
+                    injected_delays
+                        .inject(DelayedStage::Load, header.number)
+                        .await;
+                    Some(
+                        L1BatchWithLogs::with_protective_reads(
+                            load_storage,
+                            header,
+                            protective_reads,
+                        )
+                        .await,
+                    )
                 } else {
                     None // Don't need to load the next L1 batch after the last one we're processing.
                 }
             };
-            let ((header, metadata, object_key), next_l1_batch_data) =
+            let ((header, metadata, witness_input), next_l1_batch_data) =
                 future::join(process_l1_batch_task, load_next_l1_batch_task).await;
+            let pending_witness = witness_input
+                .map(|input| {
+                    let input = input.with_context(|| {
+                        format!("failed building witness input for L1 batch #{l1_batch_number}")
+                    })?;
+                    anyhow::Ok(PendingWitness {
+                        l1_batch_number,
+                        protocol_version,
+                        input,
+                        next_enumeration_index: metadata.rollup_last_leaf_index,
+                    })
+                })
+                .transpose()?;
 
             let prepare_results_latency = TreeUpdateStage::PrepareResults.start();
             Self::check_initial_writes_consistency(
                 storage,
                 header.number,
                 &metadata.initial_writes,
+                self.dal_consistency_policy,
             )
-            .await;
+            .await?;
+            Self::check_enumeration_index(
+                storage,
+                header.number,
+                &metadata,
+                pending_witness.as_ref(),
+                self.enumeration_index_check,
+            )
+            .await?;
+            if self.validate_repeated_writes {
+                Self::validate_repeated_writes(storage, header.number, &metadata.repeated_writes)
+                    .await;
+            }
             let metadata = MetadataCalculator::build_l1_batch_metadata(metadata, &header);
             prepare_results_latency.report();
 
             MetadataCalculator::reestimate_l1_batch_commit_gas(storage, &header, &metadata).await;
 
             let save_postgres_latency = TreeUpdateStage::SavePostgres.start();
+            let tree_data = TreeData {
+                metadata,
+                parent_hash: previous_root_hash,
+            };
             storage
                 .blocks_dal()
-                .save_l1_batch_metadata(l1_batch_number, &metadata, previous_root_hash)
+                .save_l1_batch_tree_data(l1_batch_number, &tree_data)
                 .await
                 .unwrap();
-            // ^ Note that `save_l1_batch_metadata()` will not blindly overwrite changes if L1 batch
+            // ^ Note that `save_l1_batch_tree_data()` will not blindly overwrite changes if L1 batch
             // metadata already exists; instead, it'll check that the old an new metadata match.
             // That is, if we run multiple tree instances, we'll get metadata correspondence
             // right away without having to implement dedicated code.
 
-            if let Some(object_key) = &object_key {
-                let protocol_version_id = storage
-                    .blocks_dal()
-                    .get_batch_protocol_version_id(l1_batch_number)
-                    .await
-                    .unwrap();
-                if let Some(id) = protocol_version_id {
-                    if !prover_storage
-                        .protocol_versions_dal()
-                        .prover_protocol_version_exists(id)
-                        .await
-                    {
-                        let protocol_version = storage
-                            .protocol_versions_dal()
-                            .get_protocol_version(id)
-                            .await
-                            .unwrap();
-                        prover_storage
-                            .protocol_versions_dal()
-                            .save_prover_protocol_version(protocol_version)
-                            .await;
-                    }
-                }
-                prover_storage
-                    .witness_generator_dal()
-                    .save_witness_inputs(l1_batch_number, object_key, protocol_version_id)
-                    .await;
-                storage
-                    .proof_generation_dal()
-                    .insert_proof_generation_details(l1_batch_number, object_key)
-                    .await;
-            }
             save_postgres_latency.report();
             tracing::info!("Updated metadata for L1 batch #{l1_batch_number} in Postgres");
 
-            previous_root_hash = metadata.merkle_root_hash;
+            // The witness is handed over only after the L1 batch metadata is persisted, so that
+            // the witness stage never marks a witness as ready for an L1 batch without metadata.
+            if let (Some(witness_stage), Some(witness)) = (&self.witness_stage, pending_witness) {
+                witness_stage.send(witness).await?;
+            }
+
+            processed_l1_batches.push(ProcessedL1Batch {
+                l1_batch_number,
+                root_hash: tree_data.metadata.root_hash,
+                next_enumeration_index: tree_data.metadata.rollup_last_leaf_index,
+            });
+            previous_root_hash = tree_data.metadata.merkle_root_hash;
             updated_headers.push(header);
+            let last_correct_l1_batch = self.reorg_halt.as_mut().and_then(ReorgHalt::poll);
+            if last_correct_l1_batch.map_or(false, |last_correct| l1_batch_number >= last_correct) {
+                tracing::info!(
+                    "Stopping Merkle tree update after L1 batch #{l1_batch_number} because of a reorg"
+                );
+                break;
+            }
+            let Some(next_l1_batch_data) = next_l1_batch_data else {
+                break;
+            };
             l1_batch_data = next_l1_batch_data;
         }
 
         let save_rocksdb_latency = TreeUpdateStage::SaveRocksDB.start();
+        let save_started_at = Instant::now();
+        #[cfg(test)]
+        self.injected_delays
+            .inject(DelayedStage::Save, updated_headers.last().unwrap().number)
+            .await;
         self.tree.save().await;
         save_rocksdb_latency.report();
+        self.committed_state_sender
+            .send_replace(CommittedTreeState::of(&self.tree));
+        if let Some(save_latency) = &mut self.save_latency {
+            save_latency.observe(save_started_at.elapsed());
+        }
+        self.refresh_watched_key_proofs().await?;
+        // Notifications are sent only after the tree is saved, so that subscribers can immediately
+        // read the processed L1 batches from the tree.
+        for l1_batch in processed_l1_batches {
+            self.l1_batch_notifier.notify(l1_batch);
+            #[cfg(feature = "webhook")]
+            if let Some(root_webhook) = &self.root_webhook {
+                root_webhook.send(RootWebhookPayload {
+                    batch: l1_batch.l1_batch_number,
+                    root: l1_batch.root_hash,
+                    next_enumeration_index: l1_batch.next_enumeration_index,
+                });
+            }
+        }
         MetadataCalculator::update_metrics(self.mode, &updated_headers, total_logs, start);
 
-        last_l1_batch_number + 1
+        // Not all loaded L1 batches are processed if processing was stopped because of a reorg.
+        let last_processed_l1_batch_number = updated_headers.last().unwrap().number;
+        Ok(last_processed_l1_batch_number + 1)
     }
 
-    async fn step(
-        &mut self,
-        mut storage: StorageProcessor<'_>,
-        mut prover_storage: StorageProcessor<'_>,
-        next_l1_batch_to_seal: &mut L1BatchNumber,
+    /// Marks the witness input uploaded to the object store under `object_key` as ready
+    /// for the prover pipeline. This operation is idempotent.
+    pub(super) async fn mark_witness_ready(
+        storage: &mut StorageProcessor<'_>,
+        prover_storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        object_key: &str,
     ) {
-        let last_sealed_l1_batch = storage
+        let protocol_version_id = storage
             .blocks_dal()
-            .get_sealed_l1_batch_number()
+            .get_batch_protocol_version_id(l1_batch_number)
             .await
             .unwrap();
-        let last_requested_l1_batch =
-            next_l1_batch_to_seal.0 + self.max_l1_batches_per_iter as u32 - 1;
-        let last_requested_l1_batch = last_requested_l1_batch.min(last_sealed_l1_batch.0);
-        let l1_batch_numbers = next_l1_batch_to_seal.0..=last_requested_l1_batch;
-        if l1_batch_numbers.is_empty() {
+        if let Some(id) = protocol_version_id {
+            if !prover_storage
+                .protocol_versions_dal()
+                .prover_protocol_version_exists(id)
+                .await
+            {
+                let protocol_version = storage
+                    .protocol_versions_dal()
+                    .get_protocol_version(id)
+                    .await
+                    .unwrap();
+                prover_storage
+                    .protocol_versions_dal()
+                    .save_prover_protocol_version(protocol_version)
+                    .await;
+            }
+        }
+        prover_storage
+            .witness_generator_dal()
+            .save_witness_inputs(l1_batch_number, object_key, protocol_version_id)
+            .await;
+        storage
+            .proof_generation_dal()
+            .insert_proof_generation_details(l1_batch_number, object_key)
+            .await;
+    }
+
+    /// Checks that the (estimated) number of storage slots touched in an L1 batch doesn't exceed
+    /// `max_in_memory_slots`. Storage logs for an L1 batch are loaded into memory all at once,
+    /// so without this check, processing an extremely large L1 batch could exhaust memory.
+    async fn check_touched_slots_count(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        max_in_memory_slots: usize,
+    ) -> anyhow::Result<()> {
+        let slots_count = storage
+            .storage_logs_dal()
+            .estimate_touched_slots_count_for_l1_batch(l1_batch_number)
+            .await;
+        anyhow::ensure!(
+            slots_count <= max_in_memory_slots,
+            "L1 batch #{l1_batch_number} touches up to {slots_count} storage slots, which exceeds \
+             the maximum number of slots that can be loaded into memory ({max_in_memory_slots}). \
+             Increase the limit if the Merkle tree has enough memory to process this L1 batch"
+        );
+        Ok(())
+    }
+
+    /// Checks whether an L1 batch with a sealed header has storage logs or protective reads. If it has neither,
+    /// it may be partially written to Postgres, so the check is retried according to `retries`, and an error
+    /// is returned if the data doesn't appear. If `retries` are not set, such an L1 batch is considered
+    /// a valid empty L1 batch.
+    pub(super) async fn check_empty_l1_batch(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        retries: Option<EmptyL1BatchRetries>,
+    ) -> anyhow::Result<()> {
+        let Some(retries) = retries else {
+            return Ok(());
+        };
+        for retry in 0..=retries.max_retries {
+            if retry > 0 {
+                tokio::time::sleep(retries.interval).await;
+            }
+            let slots_count = storage
+                .storage_logs_dal()
+                .estimate_touched_slots_count_for_l1_batch(l1_batch_number)
+                .await;
+            if slots_count > 0 {
+                return Ok(());
+            }
+            let protective_reads = storage
+                .storage_logs_dedup_dal()
+                .get_protective_reads_hashed_keys_for_l1_batch(l1_batch_number)
+                .await;
+            if !protective_reads.is_empty() {
+                return Ok(());
+            }
+            tracing::info!(
+                "L1 batch #{l1_batch_number} has neither storage logs nor protective reads \
+                 (check {}/{}); it may be partially written to Postgres",
+                retry + 1,
+                retries.max_retries + 1
+            );
+            METRICS.empty_l1_batch_retries.inc();
+        }
+        anyhow::bail!(
+            "L1 batch #{l1_batch_number} has neither storage logs nor protective reads after {} retries. \
+             If the L1 batch is legitimately empty, set the empty L1 batch policy to `accept`",
+            retries.max_retries
+        )
+    }
+
+    /// Checks that the protocol version of an L1 batch doesn't exceed `max_supported_protocol_version`.
+    /// Processing an L1 batch with an unsupported protocol version could silently produce wrong root hashes
+    /// if the new protocol version changes tree semantics.
+    fn check_protocol_version(
+        header: &L1BatchHeader,
+        max_supported_protocol_version: ProtocolVersionId,
+    ) -> anyhow::Result<()> {
+        let Some(protocol_version) = header.protocol_version else {
+            return Ok(()); // L1 batches sealed before protocol versioning are always supported
+        };
+        anyhow::ensure!(
+            protocol_version <= max_supported_protocol_version,
+            "L1 batch #{} has protocol version {protocol_version:?}, which exceeds the maximum protocol version \
+             supported by the Merkle tree ({max_supported_protocol_version:?}). Upgrade the server \
+             to process this L1 batch",
+            header.number
+        );
+        Ok(())
+    }
+
+    /// Returns L1 batches among `headers` that already have final proofs, so that witness inputs
+    /// don't need to be produced for them (e.g., when the tree is replaying L1 batches after being
+    /// restored from an old checkpoint).
+    async fn load_finalized_l1_batches(
+        &self,
+        prover_pool: Option<&ConnectionPool>,
+        headers: &[L1BatchHeader],
+    ) -> anyhow::Result<HashSet<L1BatchNumber>> {
+        let (Some(prover_pool), Some(_)) = (prover_pool, &self.witness_stage) else {
+            return Ok(HashSet::new());
+        };
+        let first_l1_batch_number = headers.first().expect("no L1 batches to process").number;
+        let last_l1_batch_number = headers.last().unwrap().number;
+        let mut prover_storage = prover_pool
+            .access_storage_tagged("metadata_calculator")
+            .await?;
+        let finalized_l1_batches = prover_storage
+            .prover_dal()
+            .get_l1_batches_with_final_proofs(first_l1_batch_number..=last_l1_batch_number)
+            .await;
+        Ok(finalized_l1_batches.into_iter().collect())
+    }
+
+    /// Loads headers for the contiguous range of sealed L1 batches at the start of `requested_l1_batches`.
+    async fn load_sealed_headers(
+        storage: &mut StorageProcessor<'_>,
+        requested_l1_batches: ops::RangeInclusive<L1BatchNumber>,
+    ) -> Vec<L1BatchHeader> {
+        let headers_latency = LoadChangesStage::L1BatchHeader.start();
+        let headers = storage
+            .blocks_dal()
+            .get_l1_batch_headers(requested_l1_batches)
+            .await
+            .unwrap();
+        headers_latency.report_with_count(headers.iter().flatten().count());
+        headers
+            .into_iter()
+            .map_while(|header| header.filter(|header| header.is_finished))
+            .collect()
+    }
+
+    async fn step<'a>(
+        &mut self,
+        mut storage: StorageProcessor<'a>,
+        mut replica_storage: Option<StorageProcessor<'a>>,
+        prover_pool: Option<&ConnectionPool>,
+        next_l1_batch_to_seal: &mut L1BatchNumber,
+        last_correct_l1_batch: Option<L1BatchNumber>,
+    ) -> anyhow::Result<()> {
+        let mut last_requested_l1_batch =
+            *next_l1_batch_to_seal + self.max_l1_batches_per_iter as u32 - 1;
+        if let Some(last_correct_l1_batch) = last_correct_l1_batch {
+            last_requested_l1_batch = last_requested_l1_batch.min(last_correct_l1_batch);
+        }
+        let requested_l1_batches = *next_l1_batch_to_seal..=last_requested_l1_batch;
+        if requested_l1_batches.is_empty() {
+            tracing::trace!(
+                "No L1 batches to seal: Merkle tree is halted after the last correct L1 batch \
+                 #{last_requested_l1_batch} because of a reorg"
+            );
+            return Ok(());
+        }
+        // If the read replica is used, L1 batches not yet replicated are not returned here,
+        // i.e., they are treated in the same way as not yet sealed L1 batches.
+        let load_storage = replica_storage.as_mut().unwrap_or(&mut storage);
+        let headers = Self::load_sealed_headers(load_storage, requested_l1_batches.clone()).await;
+        if headers.is_empty() {
             tracing::trace!(
-                "No L1 batches to seal: batch numbers range to be loaded {l1_batch_numbers:?} is empty"
+                "No L1 batches to seal: no sealed L1 batches in the requested range {requested_l1_batches:?}"
             );
         } else {
-            tracing::info!("Updating Merkle tree with L1 batches #{l1_batch_numbers:?}");
+            tracing::info!(
+                "Updating Merkle tree with L1 batches #{:?}",
+                headers[0].number..=headers[headers.len() - 1].number
+            );
+            let finalized_l1_batches = self
+                .load_finalized_l1_batches(prover_pool, &headers)
+                .await?;
             *next_l1_batch_to_seal = self
-                .process_multiple_batches(&mut storage, &mut prover_storage, l1_batch_numbers)
-                .await;
+                .process_multiple_batches(
+                    &mut storage,
+                    replica_storage.as_mut(),
+                    headers,
+                    &finalized_l1_batches,
+                )
+                .await?;
         }
+        Ok(())
     }
 
     /// The processing loop for this updater.
     pub async fn loop_updating_tree(
         mut self,
         delayer: Delayer,
-        pool: &ConnectionPool,
-        prover_pool: &ConnectionPool,
+        pool: &dyn ConnectionSource,
+        replica_pool: Option<&dyn ConnectionSource>,
+        prover_pool: Option<&ConnectionPool>,
         mut stop_receiver: watch::Receiver<bool>,
-        health_updater: HealthUpdater,
+        health_updater: &HealthUpdater,
+        next_l1_batch_sender: watch::Sender<Option<L1BatchNumber>>,
     ) -> anyhow::Result<()> {
-        let mut storage = pool
-            .access_storage_tagged("metadata_calculator")
-            .await
-            .unwrap();
+        let retry_policy = self.connection_retry_policy;
+        let storage = retry_policy
+            .access_storage(pool, &mut stop_receiver, health_updater)
+            .await?;
+        let Some(mut storage) = storage else {
+            tracing::info!("Stop signal received, metadata_calculator is shutting down");
+            return Ok(());
+        };
 
-        // Ensure genesis creation
+        self.genesis_root_hash = storage
+            .blocks_dal()
+            .get_expected_genesis_root_hash()
+            .await
+            .context("failed loading expected genesis root hash")?;
+        // Ensure genesis creation. This is also the first iteration of rebuilding a wiped tree.
         let tree = &mut self.tree;
         if tree.is_empty() {
-            let logs = L1BatchWithLogs::new(&mut storage, L1BatchNumber(0))
-                .await
-                .context("Missing storage logs for the genesis L1 batch")?;
-            tree.process_l1_batch(logs.storage_logs).await;
-            tree.save().await;
+            Self::initialize_tree_genesis(tree, &mut storage, self.mode).await?;
         }
         let mut next_l1_batch_to_seal = tree.next_l1_batch_number();
 
@@ -261,28 +848,50 @@ impl TreeUpdater {
             .get_sealed_l1_batch_number()
             .await
             .unwrap();
-        let last_l1_batch_with_metadata = storage
+        let (last_l1_batch_with_metadata, last_root_hash, last_leaf_index) = storage
             .blocks_dal()
-            .get_last_l1_batch_number_with_metadata()
+            .get_last_l1_batch_with_tree_data()
             .await
-            .unwrap();
+            .unwrap()
+            .context("no L1 batches with tree data in Postgres; was genesis performed?")?;
         drop(storage);
 
         tracing::info!(
             "Initialized metadata calculator with {max_batches_per_iter} max L1 batches per iteration. \
              Next L1 batch for Merkle tree: {next_l1_batch_to_seal}, current Postgres L1 batch: {current_db_batch}, \
-             last L1 batch with metadata: {last_l1_batch_with_metadata}",
+             last L1 batch with metadata: {last_l1_batch_with_metadata} (root hash: {last_root_hash:?}, \
+             last leaf index: {last_leaf_index})",
             max_batches_per_iter = self.max_l1_batches_per_iter
         );
+        let mut rebuild_target = None;
+        if tree.was_wiped() {
+            tracing::warn!(
+                "Merkle tree was wiped because of RocksDB corruption; it will be rebuilt \
+                 up to L1 batch #{current_db_batch}"
+            );
+            rebuild_target = Some(current_db_batch);
+        }
         let backup_lag =
             (last_l1_batch_with_metadata.0 + 1).saturating_sub(next_l1_batch_to_seal.0);
-        metrics::gauge!("server.metadata_calculator.backup_lag", backup_lag as f64);
+        METRICS.backup_lag.set(backup_lag.into());
 
-        let health = TreeHealthCheckDetails {
-            mode: self.mode,
+        let health = TreeHealthCheckDetails::new(
+            self.mode,
+            self.witness_stage.is_some(),
             next_l1_batch_to_seal,
-        };
-        health_updater.update(health.into());
+            current_db_batch,
+            self.witness_stage.as_ref().map(WitnessStage::upload_health),
+        )
+        .with_save_latency_slo(self.save_latency.as_ref())
+        .with_genesis_root_hash(self.genesis_root_hash)
+        .with_recent_revert(self.recent_revert);
+        health_updater
+            .update(health.into_health(self.health_thresholds, self.health_details_format));
+        self.heartbeat_sender
+            .send_replace(Some(TreeHeartbeat::new(next_l1_batch_to_seal)));
+        next_l1_batch_sender.send_replace(Some(next_l1_batch_to_seal));
+        self.committed_state_sender
+            .send_replace(CommittedTreeState::of(tree));
 
         if next_l1_batch_to_seal > last_l1_batch_with_metadata + 1 {
             // Check stop signal before proceeding with a potentially time-consuming operation.
@@ -291,40 +900,161 @@ impl TreeUpdater {
                 return Ok(());
             }
 
+            // Truncation is only safe if the tree and Postgres agree on the state at the truncation point;
+            // otherwise, their histories have diverged, and truncating the tree would mask the divergence.
+            let tree_root_hash = tree.root_hash_at(last_l1_batch_with_metadata);
+            anyhow::ensure!(
+                tree_root_hash == Some(last_root_hash),
+                "Cannot truncate Merkle tree (next L1 batch: {next_l1_batch_to_seal}) to the last L1 batch with metadata \
+                 in Postgres (#{last_l1_batch_with_metadata}, sealed L1 batch: #{current_db_batch}): root hash for \
+                 L1 batch #{last_l1_batch_with_metadata} in the tree ({tree_root_hash:?}) differs from the one \
+                 in Postgres ({last_root_hash:?}). Tree and Postgres histories have diverged; restore the tree \
+                 from a backup consistent with Postgres or remove it to rebuild it from scratch"
+            );
+
             tracing::warn!(
                 "Next L1 batch of the tree ({next_l1_batch_to_seal}) is greater than last L1 batch with metadata in Postgres \
-                 ({last_l1_batch_with_metadata}); this may be a result of restoring Postgres from a snapshot. \
-                 Truncating Merkle tree versions so that this mismatch is fixed..."
+                 ({last_l1_batch_with_metadata}); this may be a result of reverting Postgres or restoring it from a snapshot. \
+                 Root hashes for L1 batch #{last_l1_batch_with_metadata} match ({last_root_hash:?}); \
+                 truncating Merkle tree versions so that this mismatch is fixed..."
             );
-            tree.revert_logs(last_l1_batch_with_metadata);
-            tree.save().await;
-            next_l1_batch_to_seal = tree.next_l1_batch_number();
-            tracing::info!("Truncated Merkle tree to L1 batch #{next_l1_batch_to_seal}");
+            let old_root_hash = tree.root_hash();
+            tree.revert_and_save(last_l1_batch_with_metadata).await;
+            self.committed_state_sender
+                .send_replace(CommittedTreeState::of(tree));
+            self.logs_verifier.clear_cache();
+            self.report_revert(
+                last_l1_batch_with_metadata,
+                next_l1_batch_to_seal,
+                old_root_hash,
+            );
+            tracing::warn!(
+                "Truncated Merkle tree to L1 batch #{last_l1_batch_with_metadata}; next L1 batch for the tree \
+                 changed from #{next_l1_batch_to_seal} to #{}",
+                self.tree.next_l1_batch_number()
+            );
+            next_l1_batch_to_seal = self.tree.next_l1_batch_number();
 
-            let health = TreeHealthCheckDetails {
-                mode: self.mode,
+            let health = TreeHealthCheckDetails::new(
+                self.mode,
+                self.witness_stage.is_some(),
                 next_l1_batch_to_seal,
-            };
-            health_updater.update(health.into());
+                current_db_batch,
+                self.witness_stage.as_ref().map(WitnessStage::upload_health),
+            )
+            .with_save_latency_slo(self.save_latency.as_ref())
+            .with_genesis_root_hash(self.genesis_root_hash)
+            .with_recent_revert(self.recent_revert);
+            health_updater
+                .update(health.into_health(self.health_thresholds, self.health_details_format));
+            self.heartbeat_sender
+                .send_replace(Some(TreeHeartbeat::new(next_l1_batch_to_seal)));
+            next_l1_batch_sender.send_replace(Some(next_l1_batch_to_seal));
         }
 
+        // Cross-check the tree state with Postgres. If the tree has processed the last L1 batch
+        // with metadata in Postgres, root hashes must match; otherwise, either the tree or Postgres
+        // contains data from a diverged chain.
+        if let Some(tree_root_hash) = self.tree.root_hash_at(last_l1_batch_with_metadata) {
+            anyhow::ensure!(
+                tree_root_hash == last_root_hash,
+                "Root hash for L1 batch #{last_l1_batch_with_metadata} in Merkle tree ({tree_root_hash:?}) \
+                 differs from the one in Postgres ({last_root_hash:?})"
+            );
+        }
+        self.refresh_watched_key_proofs().await?;
+
+        let mut storage = retry_policy
+            .access_storage(pool, &mut stop_receiver, health_updater)
+            .await?
+            .context("stop signal received during startup checks")?;
+        Self::check_tree_progress(
+            &mut storage,
+            self.tree.next_l1_batch_number(),
+            self.progress_check,
+        )
+        .await?;
+        self.restore_lost_witnesses(&mut storage, prover_pool)
+            .await?;
+        drop(storage);
+
         loop {
             if *stop_receiver.borrow_and_update() {
                 tracing::info!("Stop signal received, metadata_calculator is shutting down");
                 break;
             }
-            let storage = pool
-                .access_storage_tagged("metadata_calculator")
-                .await
-                .unwrap();
-            let prover_storage = prover_pool
-                .access_storage_tagged("metadata_calculator")
+            let storage = retry_policy
+                .access_storage(pool, &mut stop_receiver, health_updater)
+                .await?;
+            let Some(mut storage) = storage else {
+                tracing::info!("Stop signal received, metadata_calculator is shutting down");
+                break;
+            };
+            let sealed_l1_batch = storage
+                .blocks_dal()
+                .get_sealed_l1_batch_number()
                 .await
                 .unwrap();
+            let replica_storage = if let Some(replica_pool) = replica_pool {
+                let replica_storage = retry_policy
+                    .access_storage(replica_pool, &mut stop_receiver, health_updater)
+                    .await?;
+                let Some(replica_storage) = replica_storage else {
+                    tracing::info!("Stop signal received, metadata_calculator is shutting down");
+                    break;
+                };
+                Some(replica_storage)
+            } else {
+                None
+            };
+
+            let mut last_correct_l1_batch = self.reorg_halt.as_mut().and_then(ReorgHalt::poll);
+            if let Some(last_correct) = last_correct_l1_batch {
+                if sealed_l1_batch <= last_correct {
+                    // The revert was executed; L1 batches processed by the tree after the last correct one
+                    // (if any) must be reverted as well.
+                    self.truncate_after_reorg(&mut storage, last_correct)
+                        .await?;
+                    next_l1_batch_to_seal = self.tree.next_l1_batch_number();
+                    if let Some(reorg_halt) = &mut self.reorg_halt {
+                        reorg_halt.resume();
+                    }
+                    last_correct_l1_batch = None;
+                }
+            }
 
             let snapshot = *next_l1_batch_to_seal;
-            self.step(storage, prover_storage, &mut next_l1_batch_to_seal)
-                .await;
+            self.step(
+                storage,
+                replica_storage,
+                prover_pool,
+                &mut next_l1_batch_to_seal,
+                last_correct_l1_batch,
+            )
+            .await?;
+            // The health is updated on each iteration since the tree lag may change even if the tree
+            // doesn't make progress.
+            let halt_health = self
+                .reorg_halt
+                .as_ref()
+                .and_then(|reorg_halt| reorg_halt.health(next_l1_batch_to_seal));
+            let health = halt_health.unwrap_or_else(|| {
+                TreeHealthCheckDetails::new(
+                    self.mode,
+                    self.witness_stage.is_some(),
+                    next_l1_batch_to_seal,
+                    sealed_l1_batch,
+                    self.witness_stage.as_ref().map(WitnessStage::upload_health),
+                )
+                .with_save_latency_slo(self.save_latency.as_ref())
+                .with_genesis_root_hash(self.genesis_root_hash)
+                .with_recent_revert(self.recent_revert)
+                .into_health(self.health_thresholds, self.health_details_format)
+            });
+            health_updater.update(health);
+            self.heartbeat_sender
+                .send_replace(Some(TreeHeartbeat::new(next_l1_batch_to_seal)));
+
             let delay = if snapshot == *next_l1_batch_to_seal {
                 tracing::trace!(
                     "Metadata calculator (next L1 batch: #{next_l1_batch_to_seal}) \
@@ -332,11 +1062,18 @@ impl TreeUpdater {
                 );
                 delayer.wait(&self.tree).left_future()
             } else {
-                let health = TreeHealthCheckDetails {
-                    mode: self.mode,
-                    next_l1_batch_to_seal,
-                };
-                health_updater.update(health.into());
+                next_l1_batch_sender.send_replace(Some(next_l1_batch_to_seal));
+                if let Some(target) = rebuild_target {
+                    if next_l1_batch_to_seal > target {
+                        tracing::warn!("Finished rebuilding Merkle tree up to L1 batch #{target}");
+                        rebuild_target = None;
+                    } else {
+                        tracing::warn!(
+                            "Rebuilding Merkle tree: processed L1 batches up to #{} out of #{target}",
+                            next_l1_batch_to_seal - 1
+                        );
+                    }
+                }
 
                 tracing::trace!(
                     "Metadata calculator (next L1 batch: #{next_l1_batch_to_seal}) made progress from #{snapshot}"
@@ -354,15 +1091,258 @@ impl TreeUpdater {
                 () = delay => { /* The delay has passed */ }
             }
         }
-        drop(health_updater); // Explicitly mark where the updater should be dropped
+        // Saved changes may reside only in the WAL buffer since the tree doesn't use sync writes.
+        self.tree.sync_wal().await;
+        Ok(())
+    }
+
+    /// Re-produces witness inputs lost on the previous run. Witnesses are handed over to the witness stage
+    /// before they are persisted, and the tree may be saved to RocksDB before the witness stage catches up,
+    /// so if the process is terminated in between, recently processed L1 batches are left without witness inputs.
+    /// Such L1 batches are found among the last L1 batches processed by the tree, and their witness inputs
+    /// are produced on top of the preceding tree versions and handed over to the witness stage again.
+    async fn restore_lost_witnesses(
+        &mut self,
+        storage: &mut StorageProcessor<'_>,
+        prover_pool: Option<&ConnectionPool>,
+    ) -> anyhow::Result<()> {
+        let (Some(witness_stage), Some(prover_pool)) = (&self.witness_stage, prover_pool) else {
+            return Ok(());
+        };
+        let next_l1_batch_number = self.tree.next_l1_batch_number();
+        let window = witness_stage.max_unpersisted_witnesses() as u32;
+        // Witness inputs are not produced for the genesis L1 batch.
+        let first_l1_batch_number = next_l1_batch_number.0.saturating_sub(window).max(1);
+        if first_l1_batch_number >= next_l1_batch_number.0 {
+            return Ok(());
+        }
+        let l1_batch_numbers = L1BatchNumber(first_l1_batch_number)..=next_l1_batch_number - 1;
+
+        let mut prover_storage = prover_pool
+            .access_storage_tagged("metadata_calculator")
+            .await?;
+        let mut persisted_l1_batches: HashSet<_> = prover_storage
+            .witness_generator_dal()
+            .get_l1_batches_with_witness_inputs(l1_batch_numbers.clone())
+            .await
+            .into_iter()
+            .collect();
+        // Witness inputs are not produced for L1 batches with final proofs.
+        persisted_l1_batches.extend(
+            prover_storage
+                .prover_dal()
+                .get_l1_batches_with_final_proofs(l1_batch_numbers.clone())
+                .await,
+        );
+        drop(prover_storage);
+        persisted_l1_batches.extend(witness_stage.persister().queued_l1_batches()?);
+
+        let lost_l1_batches: Vec<_> = (l1_batch_numbers.start().0..=l1_batch_numbers.end().0)
+            .map(L1BatchNumber)
+            .filter(|number| !persisted_l1_batches.contains(number))
+            .collect();
+        if lost_l1_batches.is_empty() {
+            return Ok(());
+        }
+        tracing::warn!(
+            "L1 batches {lost_l1_batches:?} are processed by the tree, but have no witness inputs; \
+             this may be caused by the previous run terminating before witnesses were persisted. \
+             Re-producing witness inputs for these L1 batches"
+        );
+
+        self.tree.set_witness_generation(true);
+        for l1_batch_number in lost_l1_batches {
+            let l1_batch = L1BatchWithLogs::new(
+                storage,
+                l1_batch_number,
+                self.mode,
+                self.dal_consistency_policy,
+            )
+            .await?
+            .with_context(|| format!("L1 batch #{l1_batch_number} is missing in Postgres"))?;
+            let protocol_version = l1_batch.header.protocol_version;
+            let metadata = self
+                .tree
+                .process_on_base(l1_batch_number - 1, l1_batch.storage_logs)
+                .await;
+            let mut metadata = match metadata {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    tracing::error!(
+                        "Cannot re-produce witness input for L1 batch #{l1_batch_number} ({err}); \
+                         it needs to be regenerated manually"
+                    );
+                    continue;
+                }
+            };
+
+            let root_hash = storage
+                .blocks_dal()
+                .get_l1_batch_state_root(l1_batch_number)
+                .await
+                .unwrap();
+            anyhow::ensure!(
+                root_hash == Some(metadata.root_hash),
+                "Re-produced root hash for L1 batch #{l1_batch_number} ({:?}) differs from the one \
+                 in Postgres ({root_hash:?})",
+                metadata.root_hash
+            );
+            let witness = Self::take_witness(
+                witness_stage,
+                l1_batch_number,
+                protocol_version,
+                &mut metadata,
+            )
+            .await?;
+            witness_stage.send(witness).await?;
+            METRICS.restored_witnesses.inc();
+        }
+        Ok(())
+    }
+
+    /// Truncates the tree after a revert caused by a reorg if the tree has processed L1 batches
+    /// after the last correct one. Similar to truncation on startup, the tree and Postgres must agree
+    /// on the root hash of the last correct L1 batch.
+    async fn truncate_after_reorg(
+        &mut self,
+        storage: &mut StorageProcessor<'_>,
+        last_correct_l1_batch: L1BatchNumber,
+    ) -> anyhow::Result<()> {
+        let next_l1_batch_number = self.tree.next_l1_batch_number();
+        if next_l1_batch_number <= last_correct_l1_batch + 1 {
+            return Ok(());
+        }
+
+        let postgres_root_hash = storage
+            .blocks_dal()
+            .get_l1_batch_state_root(last_correct_l1_batch)
+            .await
+            .unwrap()
+            .with_context(|| {
+                format!("root hash for the last correct L1 batch #{last_correct_l1_batch} is missing in Postgres")
+            })?;
+        let tree_root_hash = self.tree.root_hash_at(last_correct_l1_batch);
+        anyhow::ensure!(
+            tree_root_hash == Some(postgres_root_hash),
+            "Cannot truncate Merkle tree (next L1 batch: {next_l1_batch_number}) to the last correct L1 batch \
+             #{last_correct_l1_batch} after a reorg: root hash in the tree ({tree_root_hash:?}) differs \
+             from the one in Postgres ({postgres_root_hash:?})"
+        );
+
+        let old_root_hash = self.tree.root_hash();
+        self.tree.revert_and_save(last_correct_l1_batch).await;
+        self.logs_verifier.clear_cache();
+        self.committed_state_sender
+            .send_replace(CommittedTreeState::of(&self.tree));
+        self.report_revert(last_correct_l1_batch, next_l1_batch_number, old_root_hash);
+        tracing::warn!(
+            "Truncated Merkle tree to the last correct L1 batch #{last_correct_l1_batch} after a reorg; \
+             next L1 batch for the tree changed from #{next_l1_batch_number} to #{}",
+            self.tree.next_l1_batch_number()
+        );
+        Ok(())
+    }
+
+    /// Notifies subscribers about a tree revert saved to RocksDB, and records the revert
+    /// for health checks and metrics.
+    fn report_revert(
+        &mut self,
+        reverted_to: L1BatchNumber,
+        old_next_l1_batch: L1BatchNumber,
+        old_root_hash: H256,
+    ) {
+        let revert = TreeRevert::new(
+            reverted_to,
+            old_next_l1_batch.0 - reverted_to.0 - 1,
+            old_root_hash,
+            self.tree.root_hash(),
+        );
+        METRICS.tree_reverts[&revert.depth_bucket()].inc();
+        self.l1_batch_notifier.notify_revert(revert);
+        self.recent_revert = Some((reverted_to, Instant::now()));
+        if let Some(watched_keys) = &mut self.watched_keys {
+            watched_keys.invalidate_all();
+        }
+    }
+
+    /// Recomputes stale proofs for watched keys against the latest saved tree version.
+    async fn refresh_watched_key_proofs(&mut self) -> anyhow::Result<()> {
+        let Some(watched_keys) = &mut self.watched_keys else {
+            return Ok(());
+        };
+        let Some(state) = CommittedTreeState::of(&self.tree) else {
+            return Ok(()); // The tree is empty; there is nothing to prove
+        };
+        let keys = watched_keys.take_stale_keys();
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let tree_keys = keys
+            .iter()
+            .map(|key| U256::from_little_endian(key.as_bytes()))
+            .collect();
+        let entries = self
+            .tree
+            .entries_with_proofs(state.l1_batch_number, tree_keys)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed computing proofs for watched keys at L1 batch #{}",
+                    state.l1_batch_number
+                )
+            })?;
+        tracing::debug!(
+            "Computed proofs for {} watched keys at L1 batch #{}",
+            keys.len(),
+            state.l1_batch_number
+        );
+        if let Some(watched_keys) = &self.watched_keys {
+            watched_keys.update(state, keys.into_iter().zip(entries));
+        }
         Ok(())
     }
 
-    async fn check_initial_writes_consistency(
+    /// Validates the `initial_writes` table against storage logs. The tree relies on `initial_writes`
+    /// to be consistent (e.g., when filtering out zero-value writes), so found inconsistencies halt the tree
+    /// unless the best-effort DAL consistency `policy` is used.
+    pub(super) async fn validate_initial_writes(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+        policy: DalConsistencyPolicy,
+    ) -> anyhow::Result<()> {
+        let started_at = Instant::now();
+        let inconsistencies = storage
+            .storage_logs_dedup_dal()
+            .validate_initial_writes_consistency(l1_batch_numbers.clone())
+            .await
+            .unwrap();
+        tracing::info!(
+            "Validated initial writes for L1 batches #{l1_batch_numbers:?} in {:?}",
+            started_at.elapsed()
+        );
+
+        if !inconsistencies.is_empty() {
+            for inconsistency in &inconsistencies {
+                tracing::error!("Inconsistency in initial writes: {inconsistency:?}");
+            }
+            let message = format!(
+                "Found {} inconsistencies in initial writes for L1 batches #{l1_batch_numbers:?}",
+                inconsistencies.len()
+            );
+            handle_dal_inconsistency(policy, "initial_writes_validation", message)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that initial writes produced by the tree for an L1 batch (keys and assigned leaf indices)
+    /// match the `initial_writes` table.
+    pub(super) async fn check_initial_writes_consistency(
         connection: &mut StorageProcessor<'_>,
         l1_batch_number: L1BatchNumber,
         tree_initial_writes: &[InitialStorageWrite],
-    ) {
+        policy: DalConsistencyPolicy,
+    ) -> anyhow::Result<()> {
         let pg_initial_writes = connection
             .storage_logs_dedup_dal()
             .initial_writes_for_batch(l1_batch_number)
@@ -380,9 +1360,248 @@ impl TreeUpdater {
             .iter()
             .map(|write| (write.key, write.index))
             .collect();
-        assert_eq!(
-            pg_initial_writes, tree_initial_writes,
-            "Leaf indices are not consistent for L1 batch {l1_batch_number}"
+        if pg_initial_writes == tree_initial_writes {
+            return Ok(());
+        }
+        let first_mismatch = pg_initial_writes
+            .iter()
+            .zip(&tree_initial_writes)
+            .position(|(pg_write, tree_write)| pg_write != tree_write)
+            .unwrap_or_else(|| pg_initial_writes.len().min(tree_initial_writes.len()));
+        let message = format!(
+            "Leaf indices are not consistent for L1 batch {l1_batch_number}: {} initial writes in Postgres, \
+             {} produced by the tree; first mismatch at position {first_mismatch}: {:?} in Postgres, {:?} in the tree",
+            pg_initial_writes.len(),
+            tree_initial_writes.len(),
+            pg_initial_writes.get(first_mismatch),
+            tree_initial_writes.get(first_mismatch)
+        );
+        handle_dal_inconsistency(policy, "initial_writes_mismatch", message)
+    }
+
+    /// Cross-checks the next enumeration index produced by the tree for an L1 batch (and the one recorded
+    /// in the witness input, if any) against the maximum index allocated in the `initial_writes` table.
+    /// A mismatch means that L1 batch commitments and proofs will disagree.
+    pub(super) async fn check_enumeration_index(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        metadata: &TreeMetadata,
+        witness: Option<&PendingWitness>,
+        check: EnumerationIndexCheck,
+    ) -> anyhow::Result<()> {
+        let max_index = storage
+            .storage_logs_dedup_dal()
+            .max_enumeration_index_for_l1_batch(l1_batch_number)
+            .await;
+        let expected_index = max_index.unwrap_or(0) + 1;
+        // The witness records the next enumeration index *before* the L1 batch. Initial writes
+        // produced by the tree are checked against Postgres beforehand, so we can use them here.
+        let expected_witness_index =
+            expected_index.saturating_sub(metadata.initial_writes.len() as u64);
+        let witness_index = witness.map(|witness| witness.input.summary().next_enumeration_index);
+        let is_witness_index_valid =
+            witness_index.map_or(true, |index| index == expected_witness_index);
+
+        let tree_index = metadata.rollup_last_leaf_index;
+        if tree_index == expected_index && is_witness_index_valid {
+            return Ok(());
+        }
+        let message = format!(
+            "Next enumeration index for L1 batch #{l1_batch_number} produced by the tree ({tree_index}) \
+             disagrees with the one derived from Postgres ({expected_index}); witness input index: \
+             {witness_index:?}, expected {expected_witness_index}"
+        );
+        match check {
+            EnumerationIndexCheck::Error => anyhow::bail!(message),
+            EnumerationIndexCheck::Warn => {
+                tracing::warn!("{message}");
+                METRICS.enumeration_index_mismatches.inc();
+                Ok(())
+            }
+        }
+    }
+
+    /// Initializes an empty tree from the genesis L1 batch in Postgres. The resulting root hash and leaf index
+    /// are cross-checked against the ones stored for the genesis L1 batch, and the root hash is additionally checked
+    /// against the genesis root hash persisted at the first initialization. On a mismatch, the tree is not saved
+    /// and an error listing all differences is returned.
+    pub(super) async fn initialize_tree_genesis(
+        tree: &mut AsyncTree,
+        storage: &mut StorageProcessor<'_>,
+        mode: MerkleTreeMode,
+    ) -> anyhow::Result<()> {
+        tracing::info!(
+            "Merkle tree is empty; initializing it from the genesis L1 batch in Postgres"
+        );
+        // The genesis L1 batch is required to initialize the tree, so errors loading it are never tolerated.
+        let logs = L1BatchWithLogs::new(
+            storage,
+            L1BatchNumber(0),
+            mode,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await?
+        .context("Missing storage logs for the genesis L1 batch")?;
+        let metadata = tree.process_l1_batch(logs.storage_logs).await;
+
+        let stored_tree_data = storage
+            .blocks_dal()
+            .get_l1_batch_tree_data(L1BatchNumber(0))
+            .await
+            .context("failed loading tree data for the genesis L1 batch")?;
+        if let Some((stored_root_hash, stored_leaf_index)) = stored_tree_data {
+            let mut diff = vec![];
+            if metadata.root_hash != stored_root_hash {
+                diff.push(format!(
+                    "root hash: {:?} in tree, {stored_root_hash:?} in Postgres",
+                    metadata.root_hash
+                ));
+            }
+            if metadata.rollup_last_leaf_index != stored_leaf_index {
+                diff.push(format!(
+                    "last leaf index: {} in tree, {stored_leaf_index} in Postgres",
+                    metadata.rollup_last_leaf_index
+                ));
+            }
+            anyhow::ensure!(
+                diff.is_empty(),
+                "Merkle tree initialized from genesis storage logs diverges from the genesis L1 batch \
+                 in Postgres: {}",
+                diff.join("; ")
+            );
+        } else {
+            tracing::warn!(
+                "No tree data is stored for the genesis L1 batch in Postgres; tree genesis cannot be cross-checked"
+            );
+        }
+
+        let expected_root_hash = storage
+            .blocks_dal()
+            .get_expected_genesis_root_hash()
+            .await
+            .context("failed loading expected genesis root hash")?;
+        if let Some(expected_root_hash) = expected_root_hash {
+            anyhow::ensure!(
+                metadata.root_hash == expected_root_hash,
+                "Merkle tree initialized from genesis storage logs has root hash {:?}, which differs \
+                 from the expected genesis root hash {expected_root_hash:?} persisted at genesis",
+                metadata.root_hash
+            );
+        } else {
+            tracing::warn!(
+                "Expected genesis root hash is not persisted in Postgres; tree genesis cannot be checked against it"
+            );
+        }
+
+        tree.save().await;
+        tracing::info!(
+            "Initialized Merkle tree from the genesis L1 batch (root hash: {:?}, last leaf index: {})",
+            metadata.root_hash,
+            metadata.rollup_last_leaf_index
         );
+        Ok(())
+    }
+
+    /// Checks on startup that the tree hasn't progressed further than Postgres. The tree only processes
+    /// sealed L1 batches, so its next L1 batch may exceed the last sealed L1 batch by at most one. A larger lead
+    /// means that Postgres was reverted or restored without the tree, and subsequent L1 batches could be processed
+    /// incorrectly. The `initial_writes` table cannot be used for this check, since the latest L1 batches
+    /// may legitimately have no initial writes.
+    pub(super) async fn check_tree_progress(
+        storage: &mut StorageProcessor<'_>,
+        next_l1_batch_number: L1BatchNumber,
+        check: EnumerationIndexCheck,
+    ) -> anyhow::Result<()> {
+        let last_sealed_l1_batch = storage.blocks_dal().get_sealed_l1_batch_number().await?;
+        if next_l1_batch_number <= last_sealed_l1_batch + 1 {
+            return Ok(());
+        }
+
+        let message = format!(
+            "Next L1 batch of the Merkle tree (#{next_l1_batch_number}) is ahead of the last sealed L1 batch \
+             in Postgres (#{last_sealed_l1_batch}) by more than one L1 batch; the tree and Postgres may be out of sync"
+        );
+        match check {
+            EnumerationIndexCheck::Error => anyhow::bail!(message),
+            EnumerationIndexCheck::Warn => {
+                tracing::warn!("{message}");
+                METRICS.progress_mismatches.inc();
+                Ok(())
+            }
+        }
+    }
+
+    async fn validate_repeated_writes(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        tree_repeated_writes: &[RepeatedStorageWrite],
+    ) {
+        let mismatches =
+            Self::check_repeated_writes_consistency(storage, l1_batch_number, tree_repeated_writes)
+                .await;
+        if mismatches.is_empty() {
+            return;
+        }
+        for mismatch in &mismatches {
+            tracing::error!(
+                "Repeated write in L1 batch #{l1_batch_number} is inconsistent with initial writes: {mismatch:?}"
+            );
+        }
+        METRICS
+            .repeated_write_mismatches
+            .inc_by(mismatches.len() as u64);
+    }
+
+    /// Cross-checks leaf indices of repeated writes produced by the tree for an L1 batch against
+    /// the `initial_writes` table. For each repeated write, the leaf index must be allocated
+    /// in an earlier L1 batch to a key that is written in this L1 batch with the same value
+    /// as reported by the tree.
+    pub(super) async fn check_repeated_writes_consistency(
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        tree_repeated_writes: &[RepeatedStorageWrite],
+    ) -> Vec<RepeatedWriteMismatch> {
+        if tree_repeated_writes.is_empty() {
+            return vec![];
+        }
+
+        let indices: Vec<_> = tree_repeated_writes
+            .iter()
+            .map(|write| write.index)
+            .collect();
+        let initial_writes = storage
+            .storage_logs_dedup_dal()
+            .initial_writes_for_indices(&indices)
+            .await;
+        let touched_slots = storage
+            .storage_logs_dal()
+            .get_touched_slots_for_l1_batch(l1_batch_number)
+            .await;
+        let touched_slots: HashMap<_, _> = touched_slots
+            .into_iter()
+            .map(|(key, value)| (key.hashed_key(), value))
+            .collect();
+
+        let mismatches = tree_repeated_writes.iter().filter_map(|write| {
+            let index = write.index;
+            let Some(&(hashed_key, initial_write_batch)) = initial_writes.get(&index) else {
+                return Some(RepeatedWriteMismatch::MissingInitialWrite { index });
+            };
+            if initial_write_batch >= l1_batch_number {
+                return Some(RepeatedWriteMismatch::LateInitialWrite {
+                    index,
+                    hashed_key,
+                    l1_batch_number: initial_write_batch,
+                });
+            }
+            let batch_value = touched_slots.get(&hashed_key).copied();
+            (batch_value != Some(write.value)).then_some(RepeatedWriteMismatch::ValueMismatch {
+                index,
+                hashed_key,
+                tree_value: write.value,
+                batch_value,
+            })
+        });
+        mismatches.collect()
     }
 }
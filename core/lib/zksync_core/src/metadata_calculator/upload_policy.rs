@@ -0,0 +1,323 @@
+//! Retries, timeouts and health tracking for object store requests made by the tree when persisting
+//! witness inputs.
+
+use serde::Serialize;
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use zksync_object_store::ObjectStoreError;
+use zksync_types::L1BatchNumber;
+
+use super::metrics::METRICS;
+
+/// Configuration of object store requests made by [`MetadataCalculator`](super::MetadataCalculator).
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectStoreUploadConfig {
+    /// Maximum number of attempts for each request.
+    pub max_attempts: usize,
+    /// Initial backoff between attempts of a request. The backoff is doubled after each failed attempt.
+    pub initial_backoff: Duration,
+    /// Timeout for a single attempt of a request.
+    pub request_timeout: Duration,
+    /// Number of consecutive failed attempts after which the tree is reported as affected
+    /// in its health check.
+    pub failure_threshold: usize,
+}
+
+impl ObjectStoreUploadConfig {
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+}
+
+/// Object store error reported in the tree health check.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct UploadError {
+    pub l1_batch_number: L1BatchNumber,
+    pub operation: &'static str,
+    pub message: String,
+}
+
+/// Health of object store requests included into the tree health check details. Allows to distinguish
+/// object store outages from issues with the tree itself.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub(super) struct UploadHealthDetails {
+    /// Number of consecutive failed request attempts. Reset after a successful request.
+    pub consecutive_failures: usize,
+    /// Whether the number of consecutive failures has reached the configured threshold.
+    pub is_failing: bool,
+    /// Last encountered error. Retained after successful requests.
+    pub last_error: Option<UploadError>,
+}
+
+/// Instrumented wrapper for object store requests. Retries requests failed with transient errors
+/// (see [`is_transient()`]) with exponential backoff, records attempt counts and latencies, and acts as a circuit breaker for the tree health:
+/// once requests keep failing, the tree is reported as affected until a request succeeds.
+///
+/// The policy is cheaply cloneable; all clones share the health state.
+#[derive(Debug, Clone)]
+pub(super) struct UploadPolicy {
+    config: ObjectStoreUploadConfig,
+    health: Arc<Mutex<UploadHealthDetails>>,
+}
+
+impl UploadPolicy {
+    pub fn new(config: ObjectStoreUploadConfig) -> Self {
+        assert!(
+            config.max_attempts > 0,
+            "Maximum number of object store request attempts must be positive"
+        );
+        assert!(
+            config.failure_threshold > 0,
+            "Object store failure threshold must be positive"
+        );
+        Self {
+            config,
+            health: Arc::default(),
+        }
+    }
+
+    /// Returns a snapshot of the request health.
+    pub fn health_details(&self) -> UploadHealthDetails {
+        self.health
+            .lock()
+            .expect("upload health is poisoned")
+            .clone()
+    }
+
+    /// Executes an object store request for the specified L1 batch. `request_fn` is called for each attempt
+    /// and must produce a future performing the request from scratch. `operation` is used to label metrics
+    /// and logs.
+    pub async fn execute<T, F, Fut>(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        operation: &'static str,
+        mut request_fn: F,
+    ) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let started_at = Instant::now();
+        let mut backoff = self.config.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let attempt_started_at = Instant::now();
+            let result = tokio::time::timeout(self.config.request_timeout, request_fn()).await;
+            let err = match result {
+                Ok(Ok(output)) => {
+                    METRICS.object_store_attempt_latency[&operation]
+                        .observe(attempt_started_at.elapsed());
+                    self.report_success(l1_batch_number, operation, attempt, started_at);
+                    return Ok(output);
+                }
+                Ok(Err(err)) => err,
+                Err(_) => {
+                    anyhow::anyhow!("request timed out after {:?}", self.config.request_timeout)
+                }
+            };
+            self.report_failure(l1_batch_number, operation, &err);
+
+            if !is_transient(&err) {
+                METRICS.object_store_attempts[&operation].observe(attempt);
+                return Err(err.context(format!(
+                    "object store request `{operation}` for L1 batch #{l1_batch_number} failed \
+                     with a permanent error"
+                )));
+            }
+            if attempt >= self.config.max_attempts {
+                METRICS.object_store_attempts[&operation].observe(attempt);
+                return Err(err.context(format!(
+                    "object store request `{operation}` for L1 batch #{l1_batch_number} failed \
+                     after {attempt} attempts"
+                )));
+            }
+            tracing::warn!(
+                "Object store request `{operation}` for L1 batch #{l1_batch_number} failed \
+                 (attempt {attempt}/{}), retrying in {backoff:?}: {err:#}",
+                self.config.max_attempts
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(ObjectStoreUploadConfig::MAX_BACKOFF);
+            attempt += 1;
+        }
+    }
+
+    fn report_success(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        operation: &'static str,
+        attempts: usize,
+        started_at: Instant,
+    ) {
+        let elapsed = started_at.elapsed();
+        METRICS.object_store_latency[&operation].observe(elapsed);
+        METRICS.object_store_attempts[&operation].observe(attempts);
+        tracing::debug!(
+            "Object store request `{operation}` for L1 batch #{l1_batch_number} succeeded \
+             in {elapsed:?} after {attempts} attempt(s)"
+        );
+
+        let mut health = self.health.lock().expect("upload health is poisoned");
+        if health.is_failing {
+            tracing::info!(
+                "Object store requests have recovered after {} consecutive failures",
+                health.consecutive_failures
+            );
+        }
+        health.consecutive_failures = 0;
+        health.is_failing = false;
+    }
+
+    fn report_failure(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        operation: &'static str,
+        err: &anyhow::Error,
+    ) {
+        METRICS.object_store_failed_attempts[&operation].inc();
+
+        let mut health = self.health.lock().expect("upload health is poisoned");
+        health.consecutive_failures += 1;
+        health.last_error = Some(UploadError {
+            l1_batch_number,
+            operation,
+            message: format!("{err:#}"),
+        });
+        let is_failing = health.consecutive_failures >= self.config.failure_threshold;
+        if is_failing && !health.is_failing {
+            tracing::error!(
+                "Object store requests have failed {} times in a row; marking tree as affected",
+                health.consecutive_failures
+            );
+        }
+        health.is_failing = is_failing;
+    }
+}
+
+/// Checks whether a failed request may succeed if retried. Object store errors are classified
+/// by [`ObjectStoreError::is_transient()`]; other errors (e.g., timeouts) are considered transient.
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain()
+        .find_map(|err| err.downcast_ref::<ObjectStoreError>())
+        .map_or(true, ObjectStoreError::is_transient)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn test_config() -> ObjectStoreUploadConfig {
+        ObjectStoreUploadConfig {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            request_timeout: Duration::from_millis(100),
+            failure_threshold: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_request_is_retried() {
+        let policy = UploadPolicy::new(test_config());
+        let calls = &AtomicUsize::new(0);
+        let output = policy
+            .execute(L1BatchNumber(1), "test", move || async move {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    anyhow::bail!("emulated failure");
+                }
+                Ok(42)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(output, 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let health = policy.health_details();
+        assert_eq!(health.consecutive_failures, 0);
+        assert!(!health.is_failing);
+        let last_error = health.last_error.unwrap();
+        assert_eq!(last_error.l1_batch_number, L1BatchNumber(1));
+        assert!(last_error.message.contains("emulated failure"));
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_opens_after_consecutive_failures() {
+        let policy = UploadPolicy::new(test_config());
+        let err = policy
+            .execute(L1BatchNumber(1), "test", || async {
+                Err::<(), _>(anyhow::anyhow!("emulated failure"))
+            })
+            .await
+            .unwrap_err();
+        let err = format!("{err:#}");
+        assert!(err.contains("failed after 3 attempts"), "{err}");
+
+        let health = policy.health_details();
+        assert_eq!(health.consecutive_failures, 3);
+        assert!(health.is_failing);
+
+        policy
+            .execute(L1BatchNumber(2), "test", || async { Ok(()) })
+            .await
+            .unwrap();
+        let health = policy.health_details();
+        assert_eq!(health.consecutive_failures, 0);
+        assert!(!health.is_failing);
+        assert_eq!(health.last_error.unwrap().l1_batch_number, L1BatchNumber(1));
+    }
+
+    #[tokio::test]
+    async fn timed_out_requests_are_retried() {
+        let policy = UploadPolicy::new(test_config());
+        let calls = &AtomicUsize::new(0);
+        policy
+            .execute(L1BatchNumber(1), "test", move || async move {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let last_error = policy.health_details().last_error.unwrap();
+        assert!(last_error.message.contains("timed out"), "{last_error:?}");
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_not_retried() {
+        let policy = UploadPolicy::new(test_config());
+        let calls = &AtomicUsize::new(0);
+        let err = policy
+            .execute(L1BatchNumber(1), "test", move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let err = ObjectStoreError::Serialization("emulated failure".into());
+                Err::<(), _>(anyhow::Error::from(err).context("cannot upload witness"))
+            })
+            .await
+            .unwrap_err();
+        let err = format!("{err:#}");
+        assert!(err.contains("permanent error"), "{err}");
+        assert!(err.contains("emulated failure"), "{err}");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Other object store errors are transient unless they are client-side HTTP errors.
+        let calls = &AtomicUsize::new(0);
+        policy
+            .execute(L1BatchNumber(1), "test", move || async move {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    let err = ObjectStoreError::Other("connection reset".into());
+                    return Err(anyhow::Error::from(err));
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,440 @@
+//! Durable queue of witness inputs awaiting upload to the object store.
+//!
+//! If the queue is enabled, the tree writes compressed witness inputs to a local directory instead
+//! of uploading them directly, and a background [`WitnessUploader`] drains the directory to the object store
+//! with retries. Thus, object store outages do not block tree processing (until the queue size limit
+//! is reached), and queued witnesses survive restarts.
+
+use anyhow::Context as _;
+use tokio::sync::{watch, Notify};
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use zksync_dal::ConnectionPool;
+use zksync_object_store::{streaming::upload_streaming, ObjectStore, StoredObject};
+use zksync_types::{proofs::PrepareBasicCircuitsJob, L1BatchNumber};
+
+use super::{
+    metrics::{ReportStage, TreeUpdateStage, METRICS},
+    updater::{TreeUpdater, WITNESS_UPLOAD_PART_SIZE},
+    upload_policy::UploadPolicy,
+    witness_artifacts::{ArtifactDigest, DigestWriter},
+};
+
+/// Extension of fully written queued witness files.
+const QUEUED_EXTENSION: &str = "bin";
+/// Extension of queued witness files being written.
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Witness input queued for upload.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct QueuedWitness {
+    pub l1_batch_number: L1BatchNumber,
+    pub path: PathBuf,
+    /// Size of the compressed witness in bytes.
+    pub size: u64,
+    pub queued_at: SystemTime,
+}
+
+/// Directory-backed queue of witness inputs. Each witness is stored in a separate file named
+/// after its L1 batch number, in the format used by the object store.
+#[derive(Debug, Clone)]
+pub(super) struct WitnessUploadQueue {
+    dir: PathBuf,
+    max_size: u64,
+    new_witness_notifier: Arc<Notify>,
+}
+
+impl WitnessUploadQueue {
+    /// Interval between checks whether the queue has free capacity.
+    const CAPACITY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Opens the queue in the specified directory, creating the directory if necessary.
+    /// Witnesses that were partially written before a restart are removed.
+    pub fn new(dir: PathBuf, max_size: usize) -> anyhow::Result<Self> {
+        fs::create_dir_all(&dir).with_context(|| {
+            format!(
+                "cannot create witness upload queue directory `{}`",
+                dir.display()
+            )
+        })?;
+        for entry in fs::read_dir(&dir).context("cannot read witness upload queue directory")? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == TEMP_EXTENSION) {
+                tracing::info!(
+                    "Removing partially written witness `{}` from upload queue",
+                    path.display()
+                );
+                fs::remove_file(&path).with_context(|| {
+                    format!(
+                        "cannot remove partially written witness `{}`",
+                        path.display()
+                    )
+                })?;
+            }
+        }
+
+        let this = Self {
+            dir,
+            max_size: max_size as u64,
+            new_witness_notifier: Arc::new(Notify::new()),
+        };
+        let pending = this.pending()?;
+        if !pending.is_empty() {
+            tracing::info!(
+                "Witness upload queue at `{}` contains {} witnesses for L1 batches #{}..=#{}",
+                this.dir.display(),
+                pending.len(),
+                pending[0].l1_batch_number,
+                pending[pending.len() - 1].l1_batch_number
+            );
+        }
+        Ok(this)
+    }
+
+    fn witness_path(&self, l1_batch_number: L1BatchNumber, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{extension}", l1_batch_number.0))
+    }
+
+    /// Lists queued witnesses ordered by the L1 batch number.
+    pub fn pending(&self) -> anyhow::Result<Vec<QueuedWitness>> {
+        let mut witnesses = vec![];
+        for entry in
+            fs::read_dir(&self.dir).context("cannot read witness upload queue directory")?
+        {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != QUEUED_EXTENSION) {
+                continue;
+            }
+            let Some(l1_batch_number) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            else {
+                tracing::warn!(
+                    "Unexpected file `{}` in witness upload queue",
+                    path.display()
+                );
+                continue;
+            };
+            let file_metadata = fs::metadata(&path)
+                .with_context(|| format!("cannot get metadata for `{}`", path.display()))?;
+            witnesses.push(QueuedWitness {
+                l1_batch_number: L1BatchNumber(l1_batch_number),
+                path,
+                size: file_metadata.len(),
+                queued_at: file_metadata
+                    .modified()
+                    .unwrap_or_else(|_| SystemTime::now()),
+            });
+        }
+        witnesses.sort_unstable_by_key(|witness| witness.l1_batch_number);
+        Ok(witnesses)
+    }
+
+    /// Waits until the total size of queued witnesses drops below the configured limit.
+    async fn wait_for_capacity(&self) -> anyhow::Result<()> {
+        let mut logged = false;
+        loop {
+            let size: u64 = self.pending()?.iter().map(|witness| witness.size).sum();
+            if size < self.max_size {
+                return Ok(());
+            }
+            if !logged {
+                tracing::warn!(
+                    "Witness upload queue size ({size}B) has reached the limit ({}B); pausing tree \
+                     until queued witnesses are uploaded",
+                    self.max_size
+                );
+                logged = true;
+            }
+            tokio::time::sleep(Self::CAPACITY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Adds the witness for the specified L1 batch to the queue, waiting for free capacity if necessary.
+    /// `write_fn` is executed on a blocking thread and must write the witness in the format
+    /// used by the object store. The witness file is synced to disk before it becomes visible
+    /// to the uploader.
+    pub async fn push<T, F>(&self, l1_batch_number: L1BatchNumber, write_fn: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Write) -> anyhow::Result<T> + Send + 'static,
+    {
+        self.wait_for_capacity().await?;
+
+        let temp_path = self.witness_path(l1_batch_number, TEMP_EXTENSION);
+        let path = self.witness_path(l1_batch_number, QUEUED_EXTENSION);
+        let output = tokio::task::spawn_blocking(move || {
+            let file = File::create(&temp_path).with_context(|| {
+                format!("cannot create queued witness `{}`", temp_path.display())
+            })?;
+            let mut writer = BufWriter::new(file);
+            let output = write_fn(&mut writer)?;
+            let file = writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)
+                .context("cannot flush queued witness")?;
+            file.sync_all().context("cannot sync queued witness")?;
+            fs::rename(&temp_path, &path).context("cannot finalize queued witness")?;
+            anyhow::Ok(output)
+        })
+        .await
+        .context("queueing witness panicked")??;
+
+        self.new_witness_notifier.notify_one();
+        Ok(output)
+    }
+
+    fn report_metrics(pending: &[QueuedWitness]) {
+        let size: u64 = pending.iter().map(|witness| witness.size).sum();
+        let oldest_age = pending
+            .iter()
+            .filter_map(|witness| witness.queued_at.elapsed().ok())
+            .max()
+            .unwrap_or_default();
+        METRICS.witness_upload_queue_depth.set(pending.len() as u64);
+        METRICS.witness_upload_queue_size.set(size);
+        METRICS
+            .witness_upload_queue_oldest_pending_age
+            .set(oldest_age);
+    }
+}
+
+/// Background task draining [`WitnessUploadQueue`] to the object store. Witnesses are uploaded
+/// in the L1 batch order; a witness is marked as ready for provers in Postgres only after it is uploaded.
+#[derive(Debug)]
+pub(super) struct WitnessUploader {
+    queue: WitnessUploadQueue,
+    object_store: Box<dyn ObjectStore>,
+    upload_policy: UploadPolicy,
+}
+
+impl WitnessUploader {
+    /// Interval between polling the queue if it's empty or has no witnesses eligible for upload.
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+    const INITIAL_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+    const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(
+        queue: WitnessUploadQueue,
+        object_store: Box<dyn ObjectStore>,
+        upload_policy: UploadPolicy,
+    ) -> Self {
+        Self {
+            queue,
+            object_store,
+            upload_policy,
+        }
+    }
+
+    /// Runs the uploader until a stop signal is received. Witnesses remaining in the queue
+    /// are uploaded after a restart.
+    pub async fn run(
+        self,
+        pool: &ConnectionPool,
+        prover_pool: &ConnectionPool,
+        mut stop_receiver: watch::Receiver<bool>,
+    ) -> anyhow::Result<()> {
+        let mut retry_interval = Self::INITIAL_RETRY_INTERVAL;
+        while !*stop_receiver.borrow_and_update() {
+            let pending = self.queue.pending()?;
+            WitnessUploadQueue::report_metrics(&pending);
+
+            // Only upload witnesses for L1 batches with tree data persisted in Postgres, so that
+            // provers never observe a witness for an L1 batch without metadata.
+            let mut storage = pool.access_storage_tagged("metadata_calculator").await?;
+            let last_l1_batch_with_tree_data = storage
+                .blocks_dal()
+                .get_last_l1_batch_with_tree_data()
+                .await?
+                .map(|(number, ..)| number);
+            drop(storage);
+            let next_witness = pending.into_iter().next().filter(|witness| {
+                last_l1_batch_with_tree_data
+                    .map_or(false, |number| witness.l1_batch_number <= number)
+            });
+
+            let Some(witness) = next_witness else {
+                tokio::select! {
+                    () = self.queue.new_witness_notifier.notified() => {}
+                    () = tokio::time::sleep(Self::POLL_INTERVAL) => {}
+                    _ = stop_receiver.changed() => {}
+                }
+                continue;
+            };
+
+            match self.upload(&witness).await {
+                Ok((object_key, artifact)) => {
+                    retry_interval = Self::INITIAL_RETRY_INTERVAL;
+                    Self::mark_uploaded(pool, prover_pool, &witness, &object_key, &artifact)
+                        .await?;
+                }
+                Err(err) => {
+                    METRICS.witness_upload_queue_failed_uploads.inc();
+                    tracing::warn!(
+                        "Failed uploading witness input for L1 batch #{}: {err:#}; retrying in {retry_interval:?}",
+                        witness.l1_batch_number
+                    );
+                    tokio::select! {
+                        () = tokio::time::sleep(retry_interval) => {}
+                        _ = stop_receiver.changed() => {}
+                    }
+                    retry_interval = (retry_interval * 2).min(Self::MAX_RETRY_INTERVAL);
+                }
+            }
+        }
+        tracing::info!("Stop signal received, witness uploader is shutting down");
+        Ok(())
+    }
+
+    async fn upload(&self, witness: &QueuedWitness) -> anyhow::Result<(String, ArtifactDigest)> {
+        let upload_latency = TreeUpdateStage::UploadWitness.start();
+        let object_store = self.object_store.as_ref();
+        let object_key = PrepareBasicCircuitsJob::encode_key(witness.l1_batch_number);
+        let object_key_ref = &object_key;
+        let artifact = self
+            .upload_policy
+            .execute(witness.l1_batch_number, "put_queued_witness", move || {
+                let path = witness.path.clone();
+                async move {
+                    let (artifact, _) = upload_streaming(
+                        object_store,
+                        PrepareBasicCircuitsJob::BUCKET,
+                        object_key_ref,
+                        WITNESS_UPLOAD_PART_SIZE,
+                        move |writer| {
+                            let mut file = BufReader::new(File::open(&path)?);
+                            let mut writer = DigestWriter::new(writer);
+                            io::copy(&mut file, &mut writer)?;
+                            Ok(writer.finish())
+                        },
+                    )
+                    .await?;
+                    anyhow::Ok(artifact)
+                }
+            })
+            .await?;
+        upload_latency.report();
+        Ok((object_key, artifact))
+    }
+
+    async fn mark_uploaded(
+        pool: &ConnectionPool,
+        prover_pool: &ConnectionPool,
+        witness: &QueuedWitness,
+        object_key: &str,
+        artifact: &ArtifactDigest,
+    ) -> anyhow::Result<()> {
+        let l1_batch_number = witness.l1_batch_number;
+        let mut storage = pool.access_storage_tagged("metadata_calculator").await?;
+        let mut prover_storage = prover_pool
+            .access_storage_tagged("metadata_calculator")
+            .await?;
+        artifact
+            .record(&mut storage, l1_batch_number, object_key)
+            .await;
+        TreeUpdater::mark_witness_ready(
+            &mut storage,
+            &mut prover_storage,
+            l1_batch_number,
+            object_key,
+        )
+        .await;
+        // Marking a witness as ready is idempotent, so the witness can be safely re-uploaded
+        // if the process is terminated before the file is removed.
+        remove_file(&witness.path)?;
+        tracing::info!(
+            "Uploaded queued witness input for L1 batch #{l1_batch_number} to `{object_key}`"
+        );
+        Ok(())
+    }
+}
+
+fn remove_file(path: &Path) -> anyhow::Result<()> {
+    fs::remove_file(path)
+        .with_context(|| format!("cannot remove queued witness `{}`", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn queue_lists_witnesses_in_l1_batch_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = WitnessUploadQueue::new(temp_dir.path().join("queue"), 1 << 20).unwrap();
+        for number in [3, 1, 2] {
+            queue
+                .push(L1BatchNumber(number), move |writer| {
+                    writer.write_all(&[number as u8; 10])?;
+                    Ok(())
+                })
+                .await
+                .unwrap();
+        }
+
+        let pending = queue.pending().unwrap();
+        let numbers: Vec<_> = pending
+            .iter()
+            .map(|witness| witness.l1_batch_number.0)
+            .collect();
+        assert_eq!(numbers, [1, 2, 3]);
+        assert!(pending.iter().all(|witness| witness.size == 10));
+        assert_eq!(fs::read(&pending[1].path).unwrap(), [2; 10]);
+    }
+
+    #[tokio::test]
+    async fn partially_written_witnesses_are_removed_on_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_owned();
+        let queue = WitnessUploadQueue::new(dir.clone(), 1 << 20).unwrap();
+        queue
+            .push(L1BatchNumber(1), |writer| Ok(writer.write_all(b"witness")?))
+            .await
+            .unwrap();
+        fs::write(dir.join("2.tmp"), b"partial").unwrap();
+
+        let queue = WitnessUploadQueue::new(dir.clone(), 1 << 20).unwrap();
+        let pending = queue.pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].l1_batch_number, L1BatchNumber(1));
+        assert!(!dir.join("2.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn pushing_to_full_queue_waits_for_capacity() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = WitnessUploadQueue::new(temp_dir.path().to_owned(), 16).unwrap();
+        queue
+            .push(L1BatchNumber(1), |writer| Ok(writer.write_all(&[0; 16])?))
+            .await
+            .unwrap();
+
+        let push_task = tokio::spawn({
+            let queue = queue.clone();
+            async move {
+                queue
+                    .push(L1BatchNumber(2), |writer| Ok(writer.write_all(&[0; 16])?))
+                    .await
+            }
+        });
+        tokio::time::sleep(WitnessUploadQueue::CAPACITY_POLL_INTERVAL * 2).await;
+        assert!(!push_task.is_finished());
+
+        let pending = queue.pending().unwrap();
+        remove_file(&pending[0].path).unwrap();
+        push_task.await.unwrap().unwrap();
+        let pending = queue.pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].l1_batch_number, L1BatchNumber(2));
+    }
+}
@@ -0,0 +1,144 @@
+//! Precomputed Merkle proofs for a configured set of "watched" storage keys, e.g. for monitoring systems
+//! that continuously prove the same keys.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock, RwLockWriteGuard},
+};
+
+use zksync_merkle_tree::TreeEntryWithProof;
+use zksync_types::{L1BatchNumber, StorageLog, StorageLogKind, H256};
+
+use super::CommittedTreeState;
+
+/// Cached Merkle proof for a watched storage key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchedKeyProof {
+    /// L1 batch corresponding to the tree version the proof is obtained for. This is the last L1 batch
+    /// writing the key, or the latest L1 batch at the time the proof cache was (re)initialized.
+    /// The value proven for this L1 batch remains current until the key is written again.
+    pub l1_batch_number: L1BatchNumber,
+    /// Tree root hash after processing the L1 batch.
+    pub root_hash: H256,
+    /// Tree entry for the key together with its Merkle path.
+    pub entry: TreeEntryWithProof,
+}
+
+/// Read-only handle to proofs for watched keys cached by the tree. The handle can be used concurrently
+/// with the running calculator.
+#[derive(Debug, Clone, Default)]
+pub struct WatchedKeyProofs {
+    proofs: Arc<RwLock<HashMap<H256, WatchedKeyProof>>>,
+}
+
+impl WatchedKeyProofs {
+    /// Returns the cached proof for the specified hashed key, or `None` if the key is not watched
+    /// or its proof is not computed yet.
+    pub fn get(&self, hashed_key: &H256) -> Option<WatchedKeyProof> {
+        let proofs = self.proofs.read().expect("watched key proofs are poisoned");
+        proofs.get(hashed_key).cloned()
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<H256, WatchedKeyProof>> {
+        self.proofs
+            .write()
+            .expect("watched key proofs are poisoned")
+    }
+}
+
+/// Tracks writes to watched keys and maintains their cached proofs.
+#[derive(Debug)]
+pub(super) struct WatchedKeys {
+    keys: HashSet<H256>,
+    /// Keys that need their proofs (re)computed.
+    stale_keys: HashSet<H256>,
+    proofs: WatchedKeyProofs,
+}
+
+impl WatchedKeys {
+    pub fn new(keys: impl IntoIterator<Item = H256>) -> Self {
+        let keys: HashSet<_> = keys.into_iter().collect();
+        Self {
+            stale_keys: keys.clone(),
+            keys,
+            proofs: WatchedKeyProofs::default(),
+        }
+    }
+
+    pub fn proofs(&self) -> WatchedKeyProofs {
+        self.proofs.clone()
+    }
+
+    /// Marks proofs for watched keys written in an L1 batch as stale.
+    pub fn observe(&mut self, storage_logs: &[StorageLog]) {
+        let written_keys = storage_logs
+            .iter()
+            .filter(|log| log.kind == StorageLogKind::Write)
+            .map(|log| log.key.hashed_key())
+            .filter(|key| self.keys.contains(key));
+        self.stale_keys.extend(written_keys);
+    }
+
+    /// Drops all cached proofs, e.g. after the tree is reverted. Proofs for all keys will be recomputed
+    /// on the next refresh.
+    pub fn invalidate_all(&mut self) {
+        self.stale_keys = self.keys.clone();
+        self.proofs.write().clear();
+    }
+
+    /// Takes keys with stale proofs, in no particular order.
+    pub fn take_stale_keys(&mut self) -> Vec<H256> {
+        self.stale_keys.drain().collect()
+    }
+
+    /// Updates proofs for the provided keys computed for the specified tree state.
+    pub fn update(
+        &self,
+        state: CommittedTreeState,
+        entries: impl Iterator<Item = (H256, TreeEntryWithProof)>,
+    ) {
+        let mut proofs = self.proofs.write();
+        for (key, entry) in entries {
+            let proof = WatchedKeyProof {
+                l1_batch_number: state.l1_batch_number,
+                root_hash: state.root_hash,
+                entry,
+            };
+            proofs.insert(key, proof);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zksync_types::{AccountTreeId, Address, StorageKey};
+
+    use super::*;
+
+    #[test]
+    fn only_written_watched_keys_become_stale() {
+        let storage_key = |i| {
+            StorageKey::new(
+                AccountTreeId::new(Address::zero()),
+                H256::from_low_u64_be(i),
+            )
+        };
+        let watched_key = storage_key(1);
+        let mut watched_keys = WatchedKeys::new([watched_key.hashed_key()]);
+        assert_eq!(watched_keys.take_stale_keys(), [watched_key.hashed_key()]);
+
+        let logs = [
+            StorageLog::new_read_log(watched_key, H256::zero()),
+            StorageLog::new_write_log(storage_key(2), H256::repeat_byte(1)),
+        ];
+        watched_keys.observe(&logs);
+        assert!(watched_keys.take_stale_keys().is_empty());
+
+        watched_keys.observe(&[StorageLog::new_write_log(watched_key, H256::repeat_byte(1))]);
+        assert_eq!(watched_keys.take_stale_keys(), [watched_key.hashed_key()]);
+        assert!(watched_keys.take_stale_keys().is_empty());
+
+        watched_keys.invalidate_all();
+        assert_eq!(watched_keys.take_stale_keys(), [watched_key.hashed_key()]);
+    }
+}
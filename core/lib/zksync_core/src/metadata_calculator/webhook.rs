@@ -0,0 +1,142 @@
+//! Optional HTTP webhook notifying about tree root hashes computed for L1 batches.
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    watch,
+};
+
+use std::time::Duration;
+
+use zksync_types::{L1BatchNumber, H256};
+
+use super::metrics::METRICS;
+
+/// Payload POSTed (as JSON) to the webhook URL for each L1 batch processed by the tree.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RootWebhookPayload {
+    /// Number of the processed L1 batch.
+    pub batch: L1BatchNumber,
+    /// Tree root hash after processing the L1 batch.
+    pub root: H256,
+    /// Enumeration index to be assigned to the next key inserted into the tree.
+    pub next_enumeration_index: u64,
+}
+
+/// Sending part of the webhook queue. Sending never blocks tree processing: if the queue is full
+/// (e.g., because the webhook endpoint is slow), the payload is dropped.
+#[derive(Debug, Clone)]
+pub(super) struct RootWebhookSender {
+    sender: mpsc::Sender<RootWebhookPayload>,
+}
+
+impl RootWebhookSender {
+    pub fn send(&self, payload: RootWebhookPayload) {
+        match self.sender.try_send(payload) {
+            Ok(()) => {}
+            Err(TrySendError::Full(payload)) => {
+                tracing::warn!(
+                    "Root hash webhook queue is full; dropping notification for L1 batch #{}",
+                    payload.batch
+                );
+                METRICS.webhook_dropped.inc();
+            }
+            Err(TrySendError::Closed(_)) => {
+                tracing::warn!("Root hash webhook worker has terminated");
+            }
+        }
+    }
+}
+
+/// Worker sending queued [`RootWebhookPayload`]s to the configured URL with retries.
+#[derive(Debug)]
+pub(super) struct RootWebhookWorker {
+    url: String,
+    client: reqwest::Client,
+    receiver: mpsc::Receiver<RootWebhookPayload>,
+}
+
+impl RootWebhookWorker {
+    const QUEUE_CAPACITY: usize = 1_024;
+    const MAX_ATTEMPTS: usize = 3;
+    const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new(url: String) -> (RootWebhookSender, Self) {
+        let (sender, receiver) = mpsc::channel(Self::QUEUE_CAPACITY);
+        let client = reqwest::Client::builder()
+            .timeout(Self::REQUEST_TIMEOUT)
+            .build()
+            .expect("failed building HTTP client for root hash webhook");
+        let this = Self {
+            url,
+            client,
+            receiver,
+        };
+        (RootWebhookSender { sender }, this)
+    }
+
+    /// Runs the worker until all [`RootWebhookSender`]s are dropped and all queued payloads are sent,
+    /// or until a stop signal is received. In the latter case, queued payloads are dropped.
+    pub async fn run(mut self, mut stop_receiver: watch::Receiver<bool>) {
+        loop {
+            if *stop_receiver.borrow() {
+                break;
+            }
+            let payload = tokio::select! {
+                payload = self.receiver.recv() => payload,
+                _ = stop_receiver.changed() => break,
+            };
+            let Some(payload) = payload else {
+                tracing::info!("Root hash webhook worker has stopped");
+                return;
+            };
+            // Sending with retries may take a long time if the endpoint is down, so it's interrupted on stop.
+            tokio::select! {
+                () = self.send_with_retries(&payload) => {}
+                _ = stop_receiver.changed() => break,
+            }
+        }
+
+        let mut dropped_count = 0;
+        while self.receiver.try_recv().is_ok() {
+            dropped_count += 1;
+        }
+        METRICS.webhook_dropped.inc_by(dropped_count);
+        tracing::info!(
+            "Stop signal received, root hash webhook worker is shutting down; dropped {dropped_count} queued notifications"
+        );
+    }
+
+    async fn send_with_retries(&self, payload: &RootWebhookPayload) {
+        for attempt in 1..=Self::MAX_ATTEMPTS {
+            match self.send(payload).await {
+                Ok(()) => {
+                    METRICS.webhook_sent.inc();
+                    return;
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed sending root hash webhook for L1 batch #{} (attempt {attempt}/{}): {err}",
+                        payload.batch,
+                        Self::MAX_ATTEMPTS
+                    );
+                }
+            }
+            if attempt < Self::MAX_ATTEMPTS {
+                tokio::time::sleep(Self::RETRY_INTERVAL).await;
+            }
+        }
+        METRICS.webhook_failed.inc();
+    }
+
+    async fn send(&self, payload: &RootWebhookPayload) -> reqwest::Result<()> {
+        self.client
+            .post(&self.url)
+            .json(payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
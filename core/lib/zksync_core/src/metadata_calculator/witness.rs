@@ -0,0 +1,1065 @@
+//! Witness inputs produced by the Merkle tree in the full mode, with spilling of large witnesses to disk
+//! (optionally compressed) and optional deduplication of Merkle path hashes.
+
+use anyhow::Context as _;
+use serde::Serialize;
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    mem,
+    time::Instant,
+};
+
+use zksync_merkle_tree::domain::WitnessSink;
+use zksync_object_store::{
+    bincode,
+    compression::{self, CompressingWriter},
+    WitnessInputFormat, WitnessInputHeader,
+};
+use zksync_types::{
+    proofs::{DeduplicatedBasicCircuitsJob, PrepareBasicCircuitsJob, StorageLogMetadata},
+    ProtocolVersionId,
+};
+
+use super::metrics::METRICS;
+
+/// Witness input for a single L1 batch. Witnesses not exceeding the configured size are kept
+/// in memory; larger ones are spilled to a temporary file and are read from it on demand.
+/// Witnesses with deduplicated Merkle path hashes are always kept in memory.
+///
+/// If the protocol version of the L1 batch is known, it's recorded in the [serialization header](WitnessInputHeader),
+/// so that provers can select the appropriate decoder.
+#[derive(Debug)]
+pub(crate) struct WitnessInput {
+    protocol_version: Option<ProtocolVersionId>,
+    summary: WitnessSummary,
+    repr: WitnessRepr,
+}
+
+/// Summary of a [`WitnessInput`] that is available without reading the witness.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct WitnessSummary {
+    /// Next leaf index at the beginning of the L1 batch.
+    pub next_enumeration_index: u64,
+    pub merkle_path_count: usize,
+    pub write_count: usize,
+    /// Size of the `bincode` serialization of the witness in the compact format (i.e., not including
+    /// the header and without deduplication) in bytes.
+    pub serialized_size: u64,
+}
+
+impl WitnessSummary {
+    /// Size of the serialized path count and next enumeration index.
+    const FIXED_SERIALIZED_SIZE: u64 = 16;
+
+    fn new(job: &PrepareBasicCircuitsJob) -> Self {
+        Self {
+            next_enumeration_index: job.next_enumeration_index(),
+            merkle_path_count: job.merkle_path_count(),
+            write_count: job.write_count(),
+            // `bincode` can only fail to estimate size for sequences with unknown length,
+            // which are not used in the witness.
+            serialized_size: bincode::serialized_size(job).expect("cannot estimate witness size"),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum WitnessRepr {
+    InMemory(PrepareBasicCircuitsJob),
+    Spilled(SpilledWitness),
+    Deduplicated(DeduplicatedBasicCircuitsJob),
+}
+
+impl WitnessInput {
+    /// Wraps the provided witness, spilling it to disk if its serialized size exceeds `max_in_memory_size`
+    /// (in bytes). If `spill_compression_level` is specified, spilled witnesses are compressed with `zstd`
+    /// using this level.
+    ///
+    /// Unlike [`WitnessBuilder`], this requires the entire witness to be in memory; thus, it should only be used
+    /// for witnesses not produced by the tree incrementally.
+    pub fn new(
+        job: PrepareBasicCircuitsJob,
+        protocol_version: Option<ProtocolVersionId>,
+        max_in_memory_size: usize,
+        spill_compression_level: Option<i32>,
+    ) -> anyhow::Result<Self> {
+        let summary = WitnessSummary::new(&job);
+        if summary.serialized_size <= max_in_memory_size as u64 {
+            return Ok(Self::in_memory(job, protocol_version));
+        }
+
+        let mut builder = WitnessBuilder::new(
+            protocol_version,
+            max_in_memory_size,
+            spill_compression_level,
+            false,
+        );
+        builder.start(summary.next_enumeration_index, summary.merkle_path_count);
+        for path in job.into_merkle_paths() {
+            builder.push_merkle_path(path);
+        }
+        builder.finish()
+    }
+
+    /// Wraps the provided witness, keeping it in memory regardless of its size.
+    pub fn in_memory(
+        job: PrepareBasicCircuitsJob,
+        protocol_version: Option<ProtocolVersionId>,
+    ) -> Self {
+        Self {
+            protocol_version,
+            summary: WitnessSummary::new(&job),
+            repr: WitnessRepr::InMemory(job),
+        }
+    }
+
+    /// Wraps the provided witness, deduplicating hashes in its Merkle paths. The witness is serialized
+    /// in the [`WitnessInputFormat::Deduplicated`] format.
+    pub fn deduplicated(
+        job: PrepareBasicCircuitsJob,
+        protocol_version: Option<ProtocolVersionId>,
+    ) -> Self {
+        Self {
+            protocol_version,
+            summary: WitnessSummary::new(&job),
+            repr: WitnessRepr::Deduplicated(job.into()),
+        }
+    }
+
+    pub fn summary(&self) -> &WitnessSummary {
+        &self.summary
+    }
+
+    /// Splits the witness into chunks with the specified number of Merkle paths, in the same way as
+    /// [`PrepareBasicCircuitsJob::into_chunks()`] does. Spilled witnesses are read from disk lazily,
+    /// one chunk at a time.
+    pub fn into_chunks(self, chunk_size: usize) -> anyhow::Result<WitnessChunks> {
+        anyhow::ensure!(chunk_size > 0, "chunk size must be positive");
+        let chunks: WitnessChunks = match self.repr {
+            WitnessRepr::InMemory(job) => Box::new(job.into_chunks(chunk_size).map(Ok)),
+            WitnessRepr::Deduplicated(job) => {
+                let job = job.into_job().context("cannot decode witness")?;
+                Box::new(job.into_chunks(chunk_size).map(Ok))
+            }
+            WitnessRepr::Spilled(spilled) => {
+                let header =
+                    WitnessInputHeader::new(WitnessInputFormat::Legacy, self.protocol_version);
+                let chunks = SpilledChunks::new(
+                    &spilled,
+                    header.to_bytes().len() as u64,
+                    self.summary.next_enumeration_index,
+                    chunk_size,
+                )?;
+                Box::new(chunks)
+            }
+        };
+        Ok(chunks)
+    }
+
+    #[cfg(test)]
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.repr, WitnessRepr::Spilled(_))
+    }
+
+    /// Converts this input into the witness, reading it from disk if necessary.
+    #[cfg(test)]
+    pub fn into_job(self) -> anyhow::Result<PrepareBasicCircuitsJob> {
+        match self.repr {
+            WitnessRepr::InMemory(job) => Ok(job),
+            WitnessRepr::Spilled(spilled) => {
+                // The spilled serialization may be prefixed with a header, so we cannot use
+                // `bincode` directly.
+                let mut serialized = Vec::with_capacity(spilled.len as usize);
+                spilled
+                    .reader()?
+                    .read_to_end(&mut serialized)
+                    .context("cannot read spilled witness")?;
+                PrepareBasicCircuitsJob::deserialize(serialized)
+                    .map_err(|err| anyhow::anyhow!(err))
+                    .context("cannot deserialize witness")
+            }
+            WitnessRepr::Deduplicated(job) => job.into_job().context("cannot decode witness"),
+        }
+    }
+
+    /// Writes the compressed `bincode` serialization of the witness (i.e., the format used by the object store)
+    /// to `writer`. Returns the sizes of the uncompressed and compressed serializations. The serialization
+    /// is streamed path by path, so it's never held in memory as a whole; for spilled witnesses,
+    /// it is streamed from disk without deserializing the witness.
+    pub fn write_compressed(
+        self,
+        level: i32,
+        writer: &mut dyn Write,
+    ) -> anyhow::Result<(u64, u64)> {
+        let started_at = Instant::now();
+        let mut writer = CountingWriter::new(writer);
+        let protocol_version = self.protocol_version;
+        let raw_size = match self.repr {
+            WitnessRepr::InMemory(job) => {
+                let header = WitnessInputHeader::new(WitnessInputFormat::Legacy, protocol_version);
+                compress_serialized(&mut writer, level, header, &job)?
+            }
+            WitnessRepr::Deduplicated(job) => {
+                let header =
+                    WitnessInputHeader::new(WitnessInputFormat::Deduplicated, protocol_version);
+                compress_serialized(&mut writer, level, header, &job)?
+            }
+            WitnessRepr::Spilled(spilled) if spilled.compression_level == Some(level) => {
+                // The spilled witness is already in the required format.
+                io::copy(&mut spilled.raw_reader()?, &mut writer)
+                    .context("cannot copy spilled witness")?;
+                spilled.len
+            }
+            WitnessRepr::Spilled(spilled) => {
+                let mut reader = spilled.reader()?;
+                compression::compress_into(&mut writer, level, |encoder| {
+                    io::copy(&mut reader, encoder).map(drop)
+                })
+                .context("cannot compress spilled witness")?;
+                spilled.len
+            }
+        };
+        let compressed_size = writer.count;
+
+        METRICS
+            .witness_compression_time
+            .observe(started_at.elapsed());
+        Ok((raw_size, compressed_size))
+    }
+
+    /// Returns the compressed `bincode` serialization of the witness together with the size
+    /// of the uncompressed serialization.
+    #[cfg(test)]
+    pub fn into_compressed(self, level: i32) -> anyhow::Result<(Vec<u8>, u64)> {
+        let mut compressed = vec![];
+        let (raw_size, _) = self.write_compressed(level, &mut compressed)?;
+        Ok((compressed, raw_size))
+    }
+
+    /// Returns the uncompressed serialization of the witness.
+    #[cfg(test)]
+    pub fn into_serialized(self) -> anyhow::Result<Vec<u8>> {
+        let protocol_version = self.protocol_version;
+        match self.repr {
+            WitnessRepr::InMemory(job) => {
+                let header = WitnessInputHeader::new(WitnessInputFormat::Legacy, protocol_version);
+                let mut buffer = header.to_bytes();
+                bincode::serialize_into(&mut buffer, &job).context("cannot serialize witness")?;
+                Ok(buffer)
+            }
+            WitnessRepr::Deduplicated(job) => {
+                let header =
+                    WitnessInputHeader::new(WitnessInputFormat::Deduplicated, protocol_version);
+                let mut buffer = header.to_bytes();
+                bincode::serialize_into(&mut buffer, &job).context("cannot serialize witness")?;
+                Ok(buffer)
+            }
+            WitnessRepr::Spilled(spilled) => {
+                let mut buffer = Vec::with_capacity(spilled.len as usize);
+                spilled
+                    .reader()?
+                    .read_to_end(&mut buffer)
+                    .context("cannot read spilled witness")?;
+                Ok(buffer)
+            }
+        }
+    }
+}
+
+/// Writes the compressed serialization of `value` with the specified header to `writer`. Returns the size
+/// of the uncompressed serialization (including the header).
+fn compress_serialized(
+    writer: &mut dyn Write,
+    level: i32,
+    header: WitnessInputHeader,
+    value: &impl Serialize,
+) -> anyhow::Result<u64> {
+    let header = header.to_bytes();
+    let raw_size = bincode::serialized_size(value).context("cannot estimate witness size")?;
+    compression::compress_into(writer, level, |encoder| {
+        // `bincode` performs many small writes, so we buffer them before compression.
+        let mut encoder = BufWriter::new(encoder);
+        encoder.write_all(&header)?;
+        bincode::serialize_into(&mut encoder, value)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        encoder.flush()
+    })
+    .context("cannot compress witness")?;
+    Ok(header.len() as u64 + raw_size)
+}
+
+/// Writer counting the number of bytes written to the wrapped writer.
+#[derive(Debug)]
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Handle to a witness spilled to an anonymous temporary file. The file is removed once the handle
+/// is dropped.
+#[derive(Debug)]
+pub(crate) struct SpilledWitness {
+    file: File,
+    /// Size of the file in bytes.
+    file_len: u64,
+    /// Size of the uncompressed witness serialization (including the header) in bytes.
+    len: u64,
+    compression_level: Option<i32>,
+}
+
+impl SpilledWitness {
+    /// Returns a reader streaming the file contents from its start. Since all readers share the file cursor,
+    /// only one reader should be used at a time.
+    fn raw_reader(&self) -> anyhow::Result<impl Read + Send> {
+        let mut file = self.file.try_clone().context("cannot clone file handle")?;
+        file.seek(SeekFrom::Start(0))
+            .context("cannot rewind spilled witness")?;
+        Ok(BufReader::new(file).take(self.file_len))
+    }
+
+    /// Returns a reader streaming the uncompressed serialized witness. Compression is detected
+    /// from the file contents. The same caveats as for [`Self::raw_reader()`] apply.
+    pub fn reader(&self) -> anyhow::Result<impl Read + Send> {
+        compression::decompressing_stream(self.raw_reader()?)
+            .context("cannot decompress spilled witness")
+    }
+}
+
+/// Iterator over witness chunks returned by [`WitnessInput::into_chunks()`].
+pub(crate) type WitnessChunks =
+    Box<dyn Iterator<Item = anyhow::Result<PrepareBasicCircuitsJob>> + Send>;
+
+/// Iterator over chunks of a spilled witness reading Merkle paths from the spilled file.
+struct SpilledChunks {
+    reader: Box<dyn Read + Send>,
+    first_path: Option<Vec<[u8; 32]>>,
+    remaining_paths: u64,
+    next_enumeration_index: u64,
+    chunk_size: usize,
+}
+
+impl SpilledChunks {
+    fn new(
+        spilled: &SpilledWitness,
+        header_len: u64,
+        next_enumeration_index: u64,
+        chunk_size: usize,
+    ) -> anyhow::Result<Self> {
+        let mut reader: Box<dyn Read + Send> = Box::new(spilled.reader()?);
+        io::copy(&mut (&mut reader).take(header_len), &mut io::sink())
+            .context("cannot read spilled witness header")?;
+        let remaining_paths: u64 = bincode::deserialize_from(&mut reader)
+            .context("cannot read number of Merkle paths in spilled witness")?;
+        Ok(Self {
+            reader,
+            first_path: None,
+            remaining_paths,
+            next_enumeration_index,
+            chunk_size,
+        })
+    }
+
+    fn read_chunk(&mut self) -> anyhow::Result<PrepareBasicCircuitsJob> {
+        let chunk_len = self.remaining_paths.min(self.chunk_size as u64);
+        let mut chunk = PrepareBasicCircuitsJob::new(self.next_enumeration_index);
+        chunk.reserve(chunk_len as usize);
+        for _ in 0..chunk_len {
+            let mut path: StorageLogMetadata = bincode::deserialize_from(&mut self.reader)
+                .context("cannot read Merkle path from spilled witness")?;
+            // Restore the full path; it will be compacted relative to the first path in the chunk.
+            if let Some(first_path) = &self.first_path {
+                path.restore_merkle_path(first_path);
+            } else {
+                self.first_path = Some(path.merkle_paths.clone());
+            }
+            chunk.push_merkle_path(path);
+        }
+        self.remaining_paths -= chunk_len;
+        Ok(chunk)
+    }
+}
+
+impl Iterator for SpilledChunks {
+    type Item = anyhow::Result<PrepareBasicCircuitsJob>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining_paths == 0 {
+            return None;
+        }
+        let chunk = self.read_chunk();
+        if chunk.is_err() {
+            self.remaining_paths = 0; // The spilled file cannot be read further
+        }
+        Some(chunk)
+    }
+}
+
+/// Builder of [`WitnessInput`]s consuming Merkle paths as they are produced by the tree. Unlike
+/// [`WitnessInput::new()`], the builder spills the witness to disk as soon as its serialized size
+/// exceeds the in-memory limit, so that large witnesses are never held in memory as a whole.
+/// Witnesses with deduplicated Merkle path hashes are never spilled.
+#[derive(Debug)]
+pub(crate) struct WitnessBuilder {
+    protocol_version: Option<ProtocolVersionId>,
+    max_in_memory_size: usize,
+    spill_compression_level: Option<i32>,
+    deduplicate_paths: bool,
+    expected_path_count: usize,
+    summary: WitnessSummary,
+    state: BuilderState,
+}
+
+#[derive(Debug)]
+enum BuilderState {
+    InMemory(PrepareBasicCircuitsJob),
+    Spilled(SpillingWriter),
+    /// Errors cannot be returned from [`WitnessSink`] methods, so they are stored and returned
+    /// from [`WitnessBuilder::finish()`].
+    Failed(anyhow::Error),
+}
+
+impl WitnessBuilder {
+    pub fn new(
+        protocol_version: Option<ProtocolVersionId>,
+        max_in_memory_size: usize,
+        spill_compression_level: Option<i32>,
+        deduplicate_paths: bool,
+    ) -> Self {
+        Self {
+            protocol_version,
+            max_in_memory_size,
+            spill_compression_level,
+            deduplicate_paths,
+            expected_path_count: 0,
+            summary: WitnessSummary::default(),
+            state: BuilderState::InMemory(PrepareBasicCircuitsJob::new(0)),
+        }
+    }
+
+    fn try_push(&mut self, path: StorageLogMetadata) -> anyhow::Result<()> {
+        self.summary.merkle_path_count += 1;
+        self.summary.write_count += usize::from(path.is_write);
+        match &mut self.state {
+            BuilderState::InMemory(job) => {
+                job.push_merkle_path(path);
+                let path = job.last_merkle_path().expect("no Merkle path after push");
+                self.summary.serialized_size +=
+                    bincode::serialized_size(path).context("cannot estimate Merkle path size")?;
+                if !self.deduplicate_paths
+                    && self.summary.serialized_size > self.max_in_memory_size as u64
+                {
+                    let job = mem::replace(job, PrepareBasicCircuitsJob::new(0));
+                    self.state = BuilderState::Spilled(self.spill(job)?);
+                }
+            }
+            BuilderState::Spilled(writer) => {
+                self.summary.serialized_size += writer.push(path)?;
+            }
+            BuilderState::Failed(_) => { /* the error will be returned from `finish()` */ }
+        }
+        Ok(())
+    }
+
+    fn spill(&self, job: PrepareBasicCircuitsJob) -> anyhow::Result<SpillingWriter> {
+        tracing::info!(
+            "Witness input with {} Merkle paths exceeds the in-memory limit {}B after {} paths; \
+             spilling it to disk",
+            self.expected_path_count,
+            self.max_in_memory_size,
+            job.merkle_path_count()
+        );
+        METRICS.witness_spilled.inc();
+
+        let header = WitnessInputHeader::new(WitnessInputFormat::Legacy, self.protocol_version);
+        let mut writer = SpillingWriter::new(header, self.spill_compression_level)?;
+        bincode::serialize_into(&mut writer.writer, &(self.expected_path_count as u64))
+            .context("cannot write witness to disk")?;
+        for path in job.into_merkle_paths() {
+            writer.push(path)?;
+        }
+        Ok(writer)
+    }
+
+    /// Finalizes the witness input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if spilling the witness to disk has failed, or if the number of pushed Merkle paths
+    /// differs from the one specified in [`WitnessSink::start()`].
+    pub fn finish(self) -> anyhow::Result<WitnessInput> {
+        let summary = self.summary;
+        if let BuilderState::Failed(err) = self.state {
+            return Err(err);
+        }
+        anyhow::ensure!(
+            summary.merkle_path_count == self.expected_path_count,
+            "unexpected number of Merkle paths in witness: expected {}, got {}",
+            self.expected_path_count,
+            summary.merkle_path_count
+        );
+        let repr = match self.state {
+            BuilderState::InMemory(job) if self.deduplicate_paths => {
+                WitnessRepr::Deduplicated(job.into())
+            }
+            BuilderState::InMemory(job) => WitnessRepr::InMemory(job),
+            BuilderState::Spilled(writer) => WitnessRepr::Spilled(
+                writer.finish(summary.next_enumeration_index, self.spill_compression_level)?,
+            ),
+            BuilderState::Failed(_) => unreachable!("checked above"),
+        };
+        Ok(WitnessInput {
+            protocol_version: self.protocol_version,
+            summary,
+            repr,
+        })
+    }
+}
+
+impl WitnessSink for WitnessBuilder {
+    fn start(&mut self, next_enumeration_index: u64, merkle_path_count: usize) {
+        self.expected_path_count = merkle_path_count;
+        self.summary = WitnessSummary {
+            next_enumeration_index,
+            merkle_path_count: 0,
+            write_count: 0,
+            serialized_size: WitnessSummary::FIXED_SERIALIZED_SIZE,
+        };
+        let mut job = PrepareBasicCircuitsJob::new(next_enumeration_index);
+        job.reserve(merkle_path_count);
+        self.state = BuilderState::InMemory(job);
+    }
+
+    fn push_merkle_path(&mut self, path: StorageLogMetadata) {
+        if let Err(err) = self.try_push(path) {
+            self.state = BuilderState::Failed(err);
+        }
+    }
+}
+
+/// Writer of a witness being spilled to an anonymous temporary file. Writes the `bincode` serialization
+/// of a compact [`PrepareBasicCircuitsJob`] path by path.
+#[derive(Debug)]
+struct SpillingWriter {
+    writer: CountingWriter<BufWriter<SpillFile>>,
+    first_path: Option<Vec<[u8; 32]>>,
+}
+
+#[derive(Debug)]
+enum SpillFile {
+    Raw(File),
+    Compressed(CompressingWriter<File>),
+}
+
+impl Write for SpillFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Raw(file) => file.write(buf),
+            Self::Compressed(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Raw(file) => file.flush(),
+            Self::Compressed(writer) => writer.flush(),
+        }
+    }
+}
+
+impl SpillingWriter {
+    fn new(header: WitnessInputHeader, compression_level: Option<i32>) -> anyhow::Result<Self> {
+        let file = tempfile::tempfile().context("cannot create temporary file for witness")?;
+        let file = if let Some(level) = compression_level {
+            let writer = CompressingWriter::new(file, level)
+                .context("cannot initialize witness compression")?;
+            SpillFile::Compressed(writer)
+        } else {
+            SpillFile::Raw(file)
+        };
+        // `bincode` performs many small writes, so we buffer them.
+        let mut writer = CountingWriter::new(BufWriter::new(file));
+        writer
+            .write_all(&header.to_bytes())
+            .context("cannot write witness header to disk")?;
+        Ok(Self {
+            writer,
+            first_path: None,
+        })
+    }
+
+    /// Writes the next Merkle path, compacting it relative to the first path. Returns the size
+    /// of the path serialization.
+    fn push(&mut self, mut path: StorageLogMetadata) -> anyhow::Result<u64> {
+        if let Some(first_path) = &self.first_path {
+            path.compact_merkle_path(first_path);
+        } else {
+            self.first_path = Some(path.merkle_paths.clone());
+        }
+        let count_before = self.writer.count;
+        bincode::serialize_into(&mut self.writer, &path).context("cannot write witness to disk")?;
+        Ok(self.writer.count - count_before)
+    }
+
+    fn finish(
+        mut self,
+        next_enumeration_index: u64,
+        compression_level: Option<i32>,
+    ) -> anyhow::Result<SpilledWitness> {
+        bincode::serialize_into(&mut self.writer, &next_enumeration_index)
+            .context("cannot write witness to disk")?;
+        let len = self.writer.count;
+        let file = self
+            .writer
+            .inner
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)
+            .context("cannot flush witness to disk")?;
+        let mut file = match file {
+            SpillFile::Raw(file) => file,
+            SpillFile::Compressed(writer) => writer
+                .finish()
+                .context("cannot write compressed witness to disk")?,
+        };
+        let file_len = file
+            .stream_position()
+            .context("cannot get spilled witness size")?;
+        Ok(SpilledWitness {
+            file,
+            file_len,
+            len,
+            compression_level,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use tempfile::TempDir;
+
+    use zksync_config::configs::database::MerkleTreeMode;
+    use zksync_object_store::{
+        streaming::upload_streaming, ObjectStoreFactory, StoredObject,
+        MULTIPART_UPLOAD_PART_ALIGNMENT,
+    };
+    use zksync_types::{AccountTreeId, Address, L1BatchNumber, StorageKey, StorageLog, H256};
+
+    use super::*;
+    use crate::metadata_calculator::{helpers::AsyncTree, tests::create_mock_witness};
+
+    fn assert_equivalent(lhs: PrepareBasicCircuitsJob, rhs: PrepareBasicCircuitsJob) {
+        assert_eq!(lhs.next_enumeration_index(), rhs.next_enumeration_index());
+        let lhs_paths: Vec<_> = lhs.into_merkle_paths().collect();
+        let rhs_paths: Vec<_> = rhs.into_merkle_paths().collect();
+        assert_eq!(lhs_paths, rhs_paths);
+    }
+
+    #[test]
+    fn small_witness_is_kept_in_memory() {
+        const MAX_IN_MEMORY_SIZE: usize = 1 << 20;
+
+        let job = create_mock_witness(10);
+        let input = WitnessInput::new(job.clone(), None, MAX_IN_MEMORY_SIZE, None).unwrap();
+        assert!(!input.is_spilled());
+        assert_equivalent(input.into_job().unwrap(), job.clone());
+
+        let input = WitnessInput::new(job.clone(), None, MAX_IN_MEMORY_SIZE, None).unwrap();
+        let serialized = input.into_serialized().unwrap();
+        assert_eq!(serialized, bincode::serialize(&job).unwrap());
+    }
+
+    #[test]
+    fn large_witness_is_spilled_to_disk() {
+        const MAX_IN_MEMORY_SIZE: usize = 1 << 10;
+
+        let job = create_mock_witness(1_000);
+        let input = WitnessInput::new(job.clone(), None, MAX_IN_MEMORY_SIZE, None).unwrap();
+        assert!(input.is_spilled());
+        let WitnessRepr::Spilled(spilled) = &input.repr else {
+            unreachable!();
+        };
+        let expected_serialized = bincode::serialize(&job).unwrap();
+        assert_eq!(spilled.len, expected_serialized.len() as u64);
+
+        // The witness can be streamed from disk multiple times.
+        for _ in 0..2 {
+            let mut streamed = vec![];
+            spilled
+                .reader()
+                .unwrap()
+                .read_to_end(&mut streamed)
+                .unwrap();
+            assert_eq!(streamed, expected_serialized);
+        }
+        assert_equivalent(input.into_job().unwrap(), job.clone());
+
+        let input = WitnessInput::new(job, None, MAX_IN_MEMORY_SIZE, None).unwrap();
+        assert_eq!(input.into_serialized().unwrap(), expected_serialized);
+    }
+
+    #[test]
+    fn large_witness_is_spilled_to_disk_with_compression() {
+        const MAX_IN_MEMORY_SIZE: usize = 1 << 10;
+
+        let job = create_mock_witness(1_000);
+        let expected_serialized = bincode::serialize(&job).unwrap();
+        let input = WitnessInput::new(job.clone(), None, MAX_IN_MEMORY_SIZE, Some(3)).unwrap();
+        let WitnessRepr::Spilled(spilled) = &input.repr else {
+            panic!("witness is not spilled");
+        };
+        assert_eq!(spilled.len, expected_serialized.len() as u64);
+        // Merkle paths in the generated witness are highly compressible.
+        assert!(
+            spilled.file_len * 4 < spilled.len,
+            "{} >= {} / 4",
+            spilled.file_len,
+            spilled.len
+        );
+
+        let mut streamed = vec![];
+        spilled
+            .reader()
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+        assert_eq!(streamed, expected_serialized);
+        assert_equivalent(input.into_job().unwrap(), job.clone());
+
+        let input = WitnessInput::new(job, None, MAX_IN_MEMORY_SIZE, Some(3)).unwrap();
+        assert_eq!(input.into_serialized().unwrap(), expected_serialized);
+    }
+
+    fn build_witness(
+        job: &PrepareBasicCircuitsJob,
+        max_in_memory_size: usize,
+        spill_compression_level: Option<i32>,
+    ) -> WitnessBuilder {
+        let mut builder =
+            WitnessBuilder::new(None, max_in_memory_size, spill_compression_level, false);
+        builder.start(job.next_enumeration_index(), job.merkle_path_count());
+        for path in job.clone().into_merkle_paths() {
+            builder.push_merkle_path(path);
+        }
+        builder
+    }
+
+    #[test]
+    fn witness_is_spilled_while_being_built() {
+        const MAX_IN_MEMORY_SIZE: usize = 1 << 10;
+
+        let job = create_mock_witness(1_000);
+        let expected_serialized = bincode::serialize(&job).unwrap();
+        for spill_compression_level in [None, Some(3)] {
+            let mut builder =
+                WitnessBuilder::new(None, MAX_IN_MEMORY_SIZE, spill_compression_level, false);
+            builder.start(job.next_enumeration_index(), job.merkle_path_count());
+            let mut paths = job.clone().into_merkle_paths();
+            for path in paths.by_ref().take(100) {
+                builder.push_merkle_path(path);
+            }
+            // The witness must be spilled long before all paths are pushed.
+            assert_matches!(builder.state, BuilderState::Spilled(_));
+            for path in paths {
+                builder.push_merkle_path(path);
+            }
+
+            let input = builder.finish().unwrap();
+            assert!(input.is_spilled());
+            let expected_summary = WitnessSummary::new(&job);
+            assert_eq!(input.summary.merkle_path_count, 1_000);
+            assert_eq!(input.summary.write_count, expected_summary.write_count);
+            assert_eq!(
+                input.summary.serialized_size,
+                expected_serialized.len() as u64
+            );
+            assert_eq!(input.into_serialized().unwrap(), expected_serialized);
+        }
+    }
+
+    #[test]
+    fn small_witness_is_built_in_memory() {
+        let job = create_mock_witness(10);
+        let input = build_witness(&job, 1 << 20, None).finish().unwrap();
+        assert!(!input.is_spilled());
+        assert_eq!(
+            input.summary.serialized_size,
+            bincode::serialized_size(&job).unwrap()
+        );
+        assert_equivalent(input.into_job().unwrap(), job);
+    }
+
+    #[test]
+    fn witness_builder_checks_path_count() {
+        let job = create_mock_witness(10);
+        let mut builder = WitnessBuilder::new(None, 1 << 20, None, false);
+        builder.start(job.next_enumeration_index(), 11);
+        for path in job.into_merkle_paths() {
+            builder.push_merkle_path(path);
+        }
+        let err = builder.finish().unwrap_err().to_string();
+        assert!(err.contains("expected 11, got 10"), "{err}");
+    }
+
+    #[test]
+    fn spilled_witness_is_split_into_chunks() {
+        let job = create_mock_witness(1_000);
+        for spill_compression_level in [None, Some(3)] {
+            let input = build_witness(&job, 1 << 10, spill_compression_level)
+                .finish()
+                .unwrap();
+            assert!(input.is_spilled());
+            let chunks: Vec<_> = input
+                .into_chunks(300)
+                .unwrap()
+                .collect::<anyhow::Result<_>>()
+                .unwrap();
+            let expected_chunks: Vec<_> = job.clone().into_chunks(300).collect();
+            assert_eq!(chunks.len(), expected_chunks.len());
+            for (chunk, expected) in chunks.iter().zip(&expected_chunks) {
+                assert_eq!(
+                    bincode::serialize(chunk).unwrap(),
+                    bincode::serialize(expected).unwrap()
+                );
+            }
+        }
+    }
+
+    fn assert_compression_roundtrip(
+        job: PrepareBasicCircuitsJob,
+        max_in_memory_size: usize,
+        spill_compression_level: Option<i32>,
+    ) {
+        let expected_serialized = bincode::serialize(&job).unwrap();
+        let input = WitnessInput::new(
+            job.clone(),
+            None,
+            max_in_memory_size,
+            spill_compression_level,
+        )
+        .unwrap();
+        let (compressed, raw_size) = input.into_compressed(3).unwrap();
+        assert_eq!(raw_size, expected_serialized.len() as u64);
+        assert!(compression::is_compressed(&compressed));
+
+        let restored = PrepareBasicCircuitsJob::deserialize(compressed).unwrap();
+        assert_equivalent(restored, job);
+    }
+
+    #[test]
+    fn compressing_empty_witness() {
+        assert_compression_roundtrip(create_mock_witness(0), 1 << 20, None);
+    }
+
+    #[test]
+    fn compressing_witness_with_multiple_paths() {
+        let job = create_mock_witness(100);
+        assert_compression_roundtrip(job.clone(), 1 << 20, None);
+        // Spilled witnesses are compressed directly from disk.
+        assert_compression_roundtrip(job.clone(), 1 << 10, None);
+        // Witnesses spilled with the same compression level are copied as is; with another level,
+        // they are recompressed.
+        assert_compression_roundtrip(job.clone(), 1 << 10, Some(3));
+        assert_compression_roundtrip(job, 1 << 10, Some(1));
+    }
+
+    #[tokio::test]
+    async fn streamed_witness_can_be_read_from_object_store() {
+        let store = ObjectStoreFactory::mock().create_store().await;
+        let job = create_mock_witness(1_000);
+        for (l1_batch_number, max_in_memory_size) in [(1, 1 << 30), (2, 1 << 10)] {
+            let l1_batch_number = L1BatchNumber(l1_batch_number);
+            let object_key = PrepareBasicCircuitsJob::encode_key(l1_batch_number);
+            let input = WitnessInput::new(job.clone(), None, max_in_memory_size, None).unwrap();
+            let part_size = MULTIPART_UPLOAD_PART_ALIGNMENT;
+            let ((raw_size, compressed_size), uploaded_size) = upload_streaming(
+                store.as_ref(),
+                PrepareBasicCircuitsJob::BUCKET,
+                &object_key,
+                part_size,
+                move |writer| input.write_compressed(1, writer),
+            )
+            .await
+            .unwrap();
+            assert_eq!(raw_size, bincode::serialized_size(&job).unwrap());
+            assert_eq!(compressed_size, uploaded_size);
+
+            let restored: PrepareBasicCircuitsJob = store.get(l1_batch_number).await.unwrap();
+            assert_equivalent(restored, job.clone());
+        }
+    }
+
+    #[test]
+    fn legacy_uncompressed_witness_can_be_read() {
+        let job = create_mock_witness(10);
+        let serialized = bincode::serialize(&job).unwrap();
+        assert!(!compression::is_compressed(&serialized));
+        let restored = PrepareBasicCircuitsJob::deserialize(serialized).unwrap();
+        assert_equivalent(restored, job);
+    }
+
+    #[test]
+    fn deduplicated_witness_roundtrip() {
+        let job = create_mock_witness(100);
+        let input = WitnessInput::deduplicated(job.clone(), None);
+        assert!(!input.is_spilled());
+        assert_equivalent(input.into_job().unwrap(), job.clone());
+
+        let input = WitnessInput::deduplicated(job.clone(), None);
+        let serialized = input.into_serialized().unwrap();
+        assert_eq!(
+            WitnessInputHeader::parse(&serialized).unwrap(),
+            WitnessInputHeader::new(WitnessInputFormat::Deduplicated, None)
+        );
+        let restored = PrepareBasicCircuitsJob::deserialize(serialized.clone()).unwrap();
+        assert_equivalent(restored, job.clone());
+
+        let input = WitnessInput::deduplicated(job.clone(), None);
+        let (compressed, raw_size) = input.into_compressed(3).unwrap();
+        assert_eq!(raw_size, serialized.len() as u64);
+        let restored = PrepareBasicCircuitsJob::deserialize(compressed).unwrap();
+        assert_equivalent(restored, job);
+    }
+
+    fn assert_versioned_roundtrip(
+        job: &PrepareBasicCircuitsJob,
+        expected_header: WitnessInputHeader,
+        create_input: impl Fn() -> WitnessInput,
+    ) {
+        let serialized = create_input().into_serialized().unwrap();
+        assert_eq!(
+            WitnessInputHeader::parse(&serialized).unwrap(),
+            expected_header
+        );
+        assert_equivalent(create_input().into_job().unwrap(), job.clone());
+
+        let (compressed, raw_size) = create_input().into_compressed(3).unwrap();
+        assert_eq!(raw_size, serialized.len() as u64);
+        let restored = PrepareBasicCircuitsJob::deserialize(compressed).unwrap();
+        assert_equivalent(restored, job.clone());
+    }
+
+    #[test]
+    fn witness_with_protocol_version_roundtrip() {
+        let job = create_mock_witness(100);
+        for protocol_version in [ProtocolVersionId::latest(), ProtocolVersionId::next()] {
+            let protocol_version = Some(protocol_version);
+            let header = WitnessInputHeader::new(WitnessInputFormat::Legacy, protocol_version);
+            // In-memory witness, spilled witness, and spilled witness with compression.
+            for (max_in_memory_size, spill_level) in
+                [(1 << 20, None), (1 << 10, None), (1 << 10, Some(3))]
+            {
+                assert_versioned_roundtrip(&job, header, || {
+                    WitnessInput::new(
+                        job.clone(),
+                        protocol_version,
+                        max_in_memory_size,
+                        spill_level,
+                    )
+                    .unwrap()
+                });
+            }
+
+            let header =
+                WitnessInputHeader::new(WitnessInputFormat::Deduplicated, protocol_version);
+            assert_versioned_roundtrip(&job, header, || {
+                WitnessInput::deduplicated(job.clone(), protocol_version)
+            });
+        }
+    }
+
+    /// Generates a witness for a synthetic L1 batch with a mainnet-like access pattern: each transaction
+    /// reads and updates the sender nonce and the fee account balance.
+    async fn generate_synthetic_witness() -> PrepareBasicCircuitsJob {
+        const KEY_COUNT: u64 = 10_000;
+        const SENDER_COUNT: u64 = 250;
+        const TX_COUNT: u64 = 2_500;
+
+        let storage_key = |i: u64| {
+            let address = Address::from_low_u64_be(i + 1);
+            StorageKey::new(AccountTreeId::new(address), H256::zero())
+        };
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let mut tree = AsyncTree::new(
+            temp_dir.path().to_owned(),
+            MerkleTreeMode::Full,
+            500,
+            0,
+            None,
+            false,
+            0,
+        )
+        .await;
+        let initial_logs = (0..KEY_COUNT)
+            .map(|i| StorageLog::new_write_log(storage_key(i), H256::repeat_byte(0xff)))
+            .collect();
+        tree.process_l1_batch(initial_logs).await;
+
+        let fee_key = storage_key(KEY_COUNT - 1);
+        let mut logs = Vec::with_capacity(TX_COUNT as usize * 4);
+        for tx_index in 0..TX_COUNT {
+            let nonce_key = storage_key(tx_index % SENDER_COUNT);
+            let value = H256::from_low_u64_be(tx_index + 1);
+            logs.extend([
+                StorageLog::new_read_log(nonce_key, H256::zero()),
+                StorageLog::new_write_log(nonce_key, value),
+                StorageLog::new_read_log(fee_key, H256::zero()),
+                StorageLog::new_write_log(fee_key, value),
+            ]);
+        }
+        let metadata = tree.process_l1_batch(logs).await;
+        metadata.witness.unwrap()
+    }
+
+    #[tokio::test]
+    async fn deduplication_savings_for_synthetic_l1_batch() {
+        let job = generate_synthetic_witness().await;
+        assert_eq!(job.merkle_path_count(), 10_000);
+
+        let compact = WitnessInput::in_memory(job.clone(), None);
+        let compact_size = compact.into_serialized().unwrap().len();
+        let compact = WitnessInput::in_memory(job.clone(), None);
+        let compact_compressed_size = compact.into_compressed(3).unwrap().0.len();
+        let deduplicated = WitnessInput::deduplicated(job.clone(), None);
+        let deduplicated_size = deduplicated.into_serialized().unwrap().len();
+        let deduplicated = WitnessInput::deduplicated(job.clone(), None);
+        let deduplicated_compressed_size = deduplicated.into_compressed(3).unwrap().0.len();
+
+        assert!(
+            deduplicated_size * 3 < compact_size * 2,
+            "{deduplicated_size} >= 2/3 * {compact_size}"
+        );
+        // Compression should provide additional savings for both formats.
+        assert!(
+            compact_compressed_size < compact_size,
+            "{compact_compressed_size} >= {compact_size}"
+        );
+        assert!(
+            deduplicated_compressed_size < deduplicated_size,
+            "{deduplicated_compressed_size} >= {deduplicated_size}"
+        );
+        let restored = WitnessInput::deduplicated(job.clone(), None)
+            .into_job()
+            .unwrap();
+        assert_equivalent(restored, job);
+    }
+}
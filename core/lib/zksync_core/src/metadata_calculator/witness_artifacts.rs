@@ -0,0 +1,199 @@
+//! Metadata of witness input artifacts uploaded to the object store.
+//!
+//! For each uploaded witness input, the tree records the size and the Blake2s-256 hash of the stored object
+//! in Postgres. This allows to distinguish objects corrupted or truncated after the upload from witnesses
+//! that were produced incorrectly, and to track witness sizes without listing the object store bucket.
+
+use anyhow::Context as _;
+use blake2::{Blake2s256, Digest};
+
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use zksync_dal::StorageProcessor;
+use zksync_object_store::{witness_input_chunk_key, ObjectStore, ObjectStoreError, StoredObject};
+use zksync_types::{
+    proofs::{PrepareBasicCircuitsJob, PrepareBasicCircuitsJobManifest},
+    L1BatchNumber, H256,
+};
+
+use super::metrics::METRICS;
+
+/// Size and content hash of an object uploaded to the object store.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct ArtifactDigest {
+    pub size: u64,
+    pub hash: H256,
+}
+
+impl ArtifactDigest {
+    pub fn new(bytes: &[u8]) -> Self {
+        Self {
+            size: bytes.len() as u64,
+            hash: H256(Blake2s256::digest(bytes).into()),
+        }
+    }
+
+    /// Records this digest for the witness input of the specified L1 batch stored under `object_key`.
+    pub async fn record(
+        &self,
+        storage: &mut StorageProcessor<'_>,
+        l1_batch_number: L1BatchNumber,
+        object_key: &str,
+    ) {
+        storage
+            .witness_artifacts_dal()
+            .save_witness_artifact(l1_batch_number, object_key, self.size, self.hash)
+            .await;
+        METRICS.witness_artifact_size.observe(self.size);
+    }
+}
+
+/// Writer computing [`ArtifactDigest`] of the data written to the wrapped writer.
+pub(super) struct DigestWriter<W> {
+    inner: W,
+    hasher: Blake2s256,
+    size: u64,
+}
+
+impl<W> fmt::Debug for DigestWriter<W> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("DigestWriter")
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W: Write> DigestWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Blake2s256::new(),
+            size: 0,
+        }
+    }
+
+    pub fn finish(self) -> ArtifactDigest {
+        ArtifactDigest {
+            size: self.size,
+            hash: H256(self.hasher.finalize().into()),
+        }
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Result of verifying a witness input object against the metadata recorded in Postgres.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactVerification {
+    pub object_key: String,
+    pub recorded_size: u64,
+    pub recorded_hash: H256,
+    pub actual_size: u64,
+    pub actual_hash: H256,
+    /// For witness inputs stored in the chunked format, problems with chunks found when checking them
+    /// against the manifest (the object recorded in Postgres). Empty for witness inputs stored as a single object.
+    pub chunk_errors: Vec<String>,
+}
+
+impl ArtifactVerification {
+    /// Checks whether the stored object matches the recorded metadata, and (for witness inputs stored
+    /// in the chunked format) whether all chunks match the manifest.
+    pub fn is_ok(&self) -> bool {
+        self.recorded_size == self.actual_size
+            && self.recorded_hash == self.actual_hash
+            && self.chunk_errors.is_empty()
+    }
+}
+
+/// Verifies the witness input object for the specified L1 batch against the size and hash recorded
+/// when the object was uploaded. If the witness input is stored in the chunked format, the recorded object
+/// is the manifest; in this case, each chunk is additionally checked against the manifest.
+/// Returns `None` if no metadata is recorded for the L1 batch.
+///
+/// # Errors
+///
+/// Returns an error if the object or any of its chunks cannot be fetched from the object store
+/// for a reason other than the object missing.
+pub async fn verify_witness_artifact(
+    storage: &mut StorageProcessor<'_>,
+    object_store: &dyn ObjectStore,
+    l1_batch_number: L1BatchNumber,
+) -> anyhow::Result<Option<ArtifactVerification>> {
+    let artifacts = storage
+        .witness_artifacts_dal()
+        .get_witness_artifacts(l1_batch_number..=l1_batch_number)
+        .await;
+    let Some(artifact) = artifacts.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let bytes = object_store
+        .get_raw(PrepareBasicCircuitsJob::BUCKET, &artifact.object_key)
+        .await
+        .with_context(|| format!("cannot fetch witness input `{}`", artifact.object_key))?;
+    let actual = ArtifactDigest::new(&bytes);
+    let manifest_key = PrepareBasicCircuitsJobManifest::encode_key(l1_batch_number);
+    let chunk_errors = if artifact.object_key == manifest_key {
+        verify_chunks(object_store, l1_batch_number, bytes).await?
+    } else {
+        vec![]
+    };
+    Ok(Some(ArtifactVerification {
+        object_key: artifact.object_key,
+        recorded_size: artifact.size,
+        recorded_hash: artifact.hash,
+        actual_size: actual.size,
+        actual_hash: actual.hash,
+        chunk_errors,
+    }))
+}
+
+/// Checks all chunks of a witness input stored in the chunked format against the serialized `manifest`.
+async fn verify_chunks(
+    object_store: &dyn ObjectStore,
+    l1_batch_number: L1BatchNumber,
+    manifest: Vec<u8>,
+) -> anyhow::Result<Vec<String>> {
+    let manifest = match PrepareBasicCircuitsJobManifest::deserialize(manifest) {
+        Ok(manifest) => manifest,
+        Err(err) => return Ok(vec![format!("manifest cannot be deserialized: {err}")]),
+    };
+
+    let mut chunk_errors = vec![];
+    for chunk_index in 0..manifest.chunks.len() {
+        let chunk_key = witness_input_chunk_key(l1_batch_number, chunk_index);
+        let blob = match object_store
+            .get_raw(PrepareBasicCircuitsJob::BUCKET, &chunk_key)
+            .await
+        {
+            Ok(blob) => blob,
+            Err(ObjectStoreError::KeyNotFound(_)) => {
+                chunk_errors.push(format!("chunk #{chunk_index} (`{chunk_key}`) is missing"));
+                continue;
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("cannot fetch witness chunk `{chunk_key}`"));
+            }
+        };
+        if let Err(err) = manifest.verify_chunk(chunk_index, &blob) {
+            chunk_errors.push(err.to_string());
+        }
+    }
+    Ok(chunk_errors)
+}
@@ -0,0 +1,200 @@
+//! Exporting witness inputs from the object store to local files, e.g., to reproduce prover failures
+//! or to move witnesses between environments.
+//!
+//! Exported witnesses can be compressed with `zstd`; [`read_exported_witness()`] detects compression
+//! and decompresses witnesses transparently.
+
+use anyhow::Context as _;
+
+use std::{
+    fs,
+    io::{self, Write},
+    ops,
+    path::{Path, PathBuf},
+};
+
+use zksync_object_store::{bincode, ObjectStore, StoredObject};
+use zksync_types::{proofs::PrepareBasicCircuitsJob, L1BatchNumber};
+
+use super::witness::WitnessInput;
+
+/// Witness input exported by [`export_witnesses()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedWitness {
+    pub l1_batch_number: L1BatchNumber,
+    /// Path to the exported file.
+    pub path: PathBuf,
+    /// Size of the uncompressed witness serialization in bytes.
+    pub raw_size: u64,
+    /// Size of the exported file in bytes.
+    pub file_size: u64,
+}
+
+/// Exports witness inputs for the specified range of L1 batches from the object store to `output_dir`,
+/// one file per L1 batch named in the same way as the object store key. Witness inputs stored
+/// in the chunked format are reassembled. If `compression_level` is specified, exported witnesses
+/// are compressed with `zstd` using this level.
+///
+/// # Errors
+///
+/// Returns an error if any of the witness inputs cannot be fetched from the object store,
+/// or on I/O errors.
+pub async fn export_witnesses(
+    object_store: &dyn ObjectStore,
+    l1_batch_numbers: ops::RangeInclusive<L1BatchNumber>,
+    output_dir: &Path,
+    compression_level: Option<i32>,
+) -> anyhow::Result<Vec<ExportedWitness>> {
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("cannot create `{}`", output_dir.display()))?;
+
+    let mut exported = vec![];
+    for l1_batch_number in l1_batch_numbers.start().0..=l1_batch_numbers.end().0 {
+        let l1_batch_number = L1BatchNumber(l1_batch_number);
+        let job = object_store
+            .get_witness_input(l1_batch_number)
+            .await
+            .with_context(|| {
+                format!("cannot fetch witness input for L1 batch #{l1_batch_number}")
+            })?;
+        let path = output_dir.join(PrepareBasicCircuitsJob::encode_key(l1_batch_number));
+        let output_path = path.clone();
+        let (raw_size, file_size) = tokio::task::spawn_blocking(move || {
+            write_witness(&output_path, job, compression_level)
+        })
+        .await
+        .context("exporting witness panicked")??;
+
+        tracing::info!(
+            "Exported witness input for L1 batch #{l1_batch_number} to `{}` ({raw_size}B raw, \
+             {file_size}B on disk)",
+            path.display()
+        );
+        exported.push(ExportedWitness {
+            l1_batch_number,
+            path,
+            raw_size,
+            file_size,
+        });
+    }
+    Ok(exported)
+}
+
+fn write_witness(
+    path: &Path,
+    job: PrepareBasicCircuitsJob,
+    compression_level: Option<i32>,
+) -> anyhow::Result<(u64, u64)> {
+    let file =
+        fs::File::create(path).with_context(|| format!("cannot create `{}`", path.display()))?;
+    let mut writer = io::BufWriter::new(file);
+    let raw_size = if let Some(level) = compression_level {
+        let (raw_size, _) =
+            WitnessInput::in_memory(job, None).write_compressed(level, &mut writer)?;
+        raw_size
+    } else {
+        bincode::serialize_into(&mut writer, &job).context("cannot serialize witness")?;
+        bincode::serialized_size(&job).context("cannot estimate witness size")?
+    };
+    writer
+        .flush()
+        .with_context(|| format!("cannot write `{}`", path.display()))?;
+    let file_size = writer.get_ref().metadata()?.len();
+    Ok((raw_size, file_size))
+}
+
+/// Reads a witness input exported by [`export_witnesses()`], decompressing it if necessary.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or does not contain a valid witness input.
+pub fn read_exported_witness(path: &Path) -> anyhow::Result<PrepareBasicCircuitsJob> {
+    let bytes = fs::read(path).with_context(|| format!("cannot read `{}`", path.display()))?;
+    PrepareBasicCircuitsJob::deserialize(bytes)
+        .map_err(|err| anyhow::anyhow!(err))
+        .with_context(|| format!("cannot deserialize witness input from `{}`", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use zksync_object_store::ObjectStoreFactory;
+
+    use super::*;
+    use crate::metadata_calculator::tests::create_mock_witness;
+
+    #[tokio::test]
+    async fn exporting_witnesses_with_compression() {
+        let object_store = ObjectStoreFactory::mock().create_store().await;
+        let jobs: Vec<_> = [10, 100].into_iter().map(create_mock_witness).collect();
+        for (i, job) in jobs.iter().enumerate() {
+            let l1_batch_number = L1BatchNumber(i as u32 + 1);
+            object_store.put(l1_batch_number, job).await.unwrap();
+        }
+        let l1_batch_numbers = L1BatchNumber(1)..=L1BatchNumber(2);
+
+        let temp_dir = TempDir::new().expect("failed get temporary directory");
+        let raw_dir = temp_dir.path().join("raw");
+        let raw_exports = export_witnesses(
+            object_store.as_ref(),
+            l1_batch_numbers.clone(),
+            &raw_dir,
+            None,
+        )
+        .await
+        .unwrap();
+        let compressed_dir = temp_dir.path().join("compressed");
+        let compressed_exports = export_witnesses(
+            object_store.as_ref(),
+            l1_batch_numbers,
+            &compressed_dir,
+            Some(3),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(raw_exports.len(), 2);
+        assert_eq!(compressed_exports.len(), 2);
+        let exports = raw_exports.iter().zip(&compressed_exports);
+        for ((raw, compressed), job) in exports.zip(&jobs) {
+            assert_eq!(raw.l1_batch_number, compressed.l1_batch_number);
+            assert_eq!(raw.raw_size, compressed.raw_size);
+            assert_eq!(raw.file_size, raw.raw_size);
+            // Merkle paths in the generated witnesses are highly compressible.
+            assert!(
+                compressed.file_size * 4 < raw.file_size,
+                "{} >= {} / 4",
+                compressed.file_size,
+                raw.file_size
+            );
+
+            let expected_paths: Vec<_> = job.clone().into_merkle_paths().collect();
+            for export in [raw, compressed] {
+                let restored = read_exported_witness(&export.path).unwrap();
+                assert_eq!(
+                    restored.next_enumeration_index(),
+                    job.next_enumeration_index()
+                );
+                let restored_paths: Vec<_> = restored.into_merkle_paths().collect();
+                assert_eq!(restored_paths, expected_paths);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn exporting_missing_witness() {
+        let object_store = ObjectStoreFactory::mock().create_store().await;
+        let temp_dir = TempDir::new().expect("failed get temporary directory");
+        let l1_batch_numbers = L1BatchNumber(1)..=L1BatchNumber(1);
+        let err = export_witnesses(
+            object_store.as_ref(),
+            l1_batch_numbers,
+            temp_dir.path(),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("L1 batch #1"), "{err}");
+    }
+}
@@ -0,0 +1,241 @@
+//! Regeneration of witness inputs for historical L1 batches, e.g., if a witness input blob was lost
+//! or corrupted in the object store.
+
+use anyhow::Context as _;
+
+use std::path::Path;
+
+use zksync_config::configs::database::{DalConsistencyPolicy, MerkleTreeMode};
+use zksync_dal::{ConnectionPool, StorageProcessor};
+use zksync_merkle_tree::domain::TreeMetadata;
+use zksync_object_store::bincode;
+use zksync_types::L1BatchNumber;
+
+use super::{
+    helpers::{AsyncTree, L1BatchWithLogs},
+    witness_verifier::{l1_batch_root_hash, verify_merkle_paths},
+    MetadataCalculator,
+};
+
+/// Multi-get chunk size used by checkpoint trees.
+const CHECKPOINT_MULTI_GET_CHUNK_SIZE: usize = 500;
+/// Block cache capacity used by checkpoint trees (128 MiB).
+const CHECKPOINT_BLOCK_CACHE_CAPACITY: usize = 128 << 20;
+
+/// Result of [`MetadataCalculator::regenerate_witness()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegeneratedWitness {
+    /// Key of the witness input in the object store.
+    pub object_key: String,
+    /// Number of Merkle paths in the regenerated witness input.
+    pub path_count: usize,
+    /// Whether the witness input was regenerated using a checkpoint tree rather than the main tree.
+    pub used_checkpoint: bool,
+}
+
+impl MetadataCalculator {
+    /// Regenerates the witness input for the specified L1 batch, validates it against the root hashes
+    /// stored in Postgres and re-uploads it to the object store under the expected key.
+    ///
+    /// The witness input is produced by the main tree if it retains the tree version preceding
+    /// the L1 batch. Otherwise, if `checkpoint_path` is specified, it is produced by a temporary tree
+    /// opened at this path (e.g., a tree RocksDB restored from a backup); L1 batches not processed
+    /// by the checkpoint tree are reprocessed from Postgres. Changes in both trees are discarded,
+    /// so neither of them is modified.
+    ///
+    /// This method is intended for maintenance and cannot be used while the calculator is running.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the calculator doesn't produce witness inputs (i.e., it's not in the full mode,
+    /// or witness input generation is disabled), if the L1 batch is not processed by the tree yet,
+    /// if neither tree can produce the witness input, or if the regenerated root hash or witness input
+    /// disagrees with Postgres. In the latter case, nothing is uploaded.
+    pub async fn regenerate_witness(
+        &mut self,
+        pool: &ConnectionPool,
+        l1_batch_number: L1BatchNumber,
+        checkpoint_path: Option<&Path>,
+    ) -> anyhow::Result<RegeneratedWitness> {
+        anyhow::ensure!(
+            self.updater.mode() == MerkleTreeMode::Full && self.updater.produces_witnesses(),
+            "witness inputs can only be regenerated by a full-mode tree with witness input generation enabled"
+        );
+        anyhow::ensure!(
+            l1_batch_number > L1BatchNumber(0),
+            "witness inputs are not produced for the genesis L1 batch"
+        );
+        let next_l1_batch_number = self.updater.next_l1_batch_number();
+        anyhow::ensure!(
+            l1_batch_number < next_l1_batch_number,
+            "L1 batch #{l1_batch_number} is not processed by the tree yet (next L1 batch: #{next_l1_batch_number})"
+        );
+
+        let mut storage = pool.access_storage_tagged("metadata_calculator").await?;
+        let previous_root_hash = l1_batch_root_hash(&mut storage, l1_batch_number - 1).await?;
+        let root_hash = l1_batch_root_hash(&mut storage, l1_batch_number).await?;
+        let l1_batch = L1BatchWithLogs::new(
+            &mut storage,
+            l1_batch_number,
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await?
+        .with_context(|| format!("L1 batch #{l1_batch_number} is missing in Postgres"))?;
+
+        let protocol_version = l1_batch.header.protocol_version;
+        let base_l1_batch = l1_batch_number - 1;
+        let tree = self.updater.tree_mut();
+        let (mut metadata, used_checkpoint) = match tree
+            .process_on_base(base_l1_batch, l1_batch.storage_logs)
+            .await
+        {
+            Ok(metadata) => (metadata, false),
+            Err(err) => {
+                let checkpoint_path = checkpoint_path.with_context(|| {
+                    format!(
+                        "cannot regenerate witness input for L1 batch #{l1_batch_number} \
+                         using the main tree ({err}), and no checkpoint is provided"
+                    )
+                })?;
+                tracing::info!(
+                    "Main tree cannot regenerate witness input for L1 batch #{l1_batch_number} ({err}); \
+                     using checkpoint at `{}`",
+                    checkpoint_path.display()
+                );
+                let metadata =
+                    process_on_checkpoint(&mut storage, checkpoint_path, l1_batch_number).await?;
+                (metadata, true)
+            }
+        };
+        drop(storage);
+
+        anyhow::ensure!(
+            metadata.root_hash == root_hash,
+            "regenerated root hash for L1 batch #{l1_batch_number} ({:?}) disagrees with Postgres ({root_hash:?}); \
+             refusing to upload witness input",
+            metadata.root_hash
+        );
+
+        let witness = metadata
+            .witness
+            .take()
+            .context("tree has not produced witness input")?;
+        let path_count = witness.merkle_path_count();
+        let (witness, verification) = tokio::task::spawn_blocking(move || {
+            let serialized = bincode::serialize(&witness).context("cannot serialize witness")?;
+            let verification =
+                verify_merkle_paths(serialized.as_slice(), previous_root_hash, root_hash)?;
+            anyhow::Ok((witness, verification))
+        })
+        .await
+        .context("witness verification panicked")??;
+        if let Some(mismatch) = verification.mismatch {
+            anyhow::bail!(
+                "regenerated witness input for L1 batch #{l1_batch_number} is invalid: {mismatch:?}; \
+                 refusing to upload it"
+            );
+        }
+
+        metadata.witness = Some(witness);
+        let saved_witness = self
+            .updater
+            .save_witness_input(l1_batch_number, protocol_version, &mut metadata)
+            .await
+            .context("object store is not configured")?;
+        let object_key = saved_witness.object_key;
+        if let Some(artifact) = &saved_witness.artifact {
+            let mut storage = pool.access_storage_tagged("metadata_calculator").await?;
+            artifact
+                .record(&mut storage, l1_batch_number, &object_key)
+                .await;
+        }
+        tracing::info!(
+            "Regenerated witness input for L1 batch #{l1_batch_number} with {path_count} Merkle paths \
+             and uploaded it to `{object_key}`"
+        );
+        Ok(RegeneratedWitness {
+            object_key,
+            path_count,
+            used_checkpoint,
+        })
+    }
+}
+
+/// Produces metadata for the specified L1 batch using a temporary tree opened at `checkpoint_path`.
+/// If the checkpoint tree hasn't processed the preceding L1 batches, they are reprocessed from Postgres
+/// (the changes are kept in memory and are never persisted).
+async fn process_on_checkpoint(
+    storage: &mut StorageProcessor<'_>,
+    checkpoint_path: &Path,
+    l1_batch_number: L1BatchNumber,
+) -> anyhow::Result<TreeMetadata> {
+    anyhow::ensure!(
+        checkpoint_path.is_dir(),
+        "checkpoint tree directory `{}` doesn't exist",
+        checkpoint_path.display()
+    );
+    let mut tree = AsyncTree::new(
+        checkpoint_path.to_owned(),
+        MerkleTreeMode::Full,
+        CHECKPOINT_MULTI_GET_CHUNK_SIZE,
+        CHECKPOINT_BLOCK_CACHE_CAPACITY,
+        None,
+        false,
+        0,
+    )
+    .await;
+    let next_l1_batch_number = tree.next_l1_batch_number();
+    anyhow::ensure!(
+        next_l1_batch_number > L1BatchNumber(0),
+        "checkpoint tree at `{}` is empty",
+        checkpoint_path.display()
+    );
+
+    if next_l1_batch_number > l1_batch_number {
+        let l1_batch = L1BatchWithLogs::new(
+            storage,
+            l1_batch_number,
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await?
+        .with_context(|| format!("L1 batch #{l1_batch_number} is missing in Postgres"))?;
+        return tree
+            .process_on_base(l1_batch_number - 1, l1_batch.storage_logs)
+            .await
+            .with_context(|| {
+                format!(
+                    "checkpoint tree cannot regenerate witness input for L1 batch #{l1_batch_number}"
+                )
+            });
+    }
+
+    tracing::info!(
+        "Reprocessing L1 batches #{next_l1_batch_number}..=#{l1_batch_number} in checkpoint tree"
+    );
+    let mut metadata = None;
+    for number in next_l1_batch_number.0..=l1_batch_number.0 {
+        let number = L1BatchNumber(number);
+        let l1_batch = L1BatchWithLogs::new(
+            storage,
+            number,
+            MerkleTreeMode::Full,
+            DalConsistencyPolicy::FailFast,
+        )
+        .await?
+        .with_context(|| format!("L1 batch #{number} is missing in Postgres"))?;
+        let batch_metadata = tree.process_l1_batch(l1_batch.storage_logs).await;
+        if number < l1_batch_number {
+            let expected_root_hash = l1_batch_root_hash(storage, number).await?;
+            anyhow::ensure!(
+                batch_metadata.root_hash == expected_root_hash,
+                "root hash for L1 batch #{number} reprocessed in checkpoint tree ({:?}) disagrees \
+                 with Postgres ({expected_root_hash:?})",
+                batch_metadata.root_hash
+            );
+        }
+        metadata = Some(batch_metadata);
+    }
+    Ok(metadata.unwrap())
+}
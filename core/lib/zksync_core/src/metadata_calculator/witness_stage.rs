@@ -0,0 +1,756 @@
+//! Witness finalization stage of the Merkle tree pipeline.
+//!
+//! Serializing witness inputs and uploading them to the object store can take a significant time,
+//! while processing the next L1 batch by the tree doesn't depend on it. Thus, the tree only hands over
+//! raw witnesses to a [`WitnessStageWorker`] running on a separate task, which persists them
+//! with bounded concurrency and marks them as ready for provers in the L1 batch order.
+//! Witnesses that weren't persisted before the process was terminated are re-produced by the tree on startup.
+
+use anyhow::Context as _;
+use futures::{stream, StreamExt};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use zksync_dal::ConnectionPool;
+use zksync_object_store::{
+    streaming::upload_streaming, witness_input_chunk_key, ObjectStore, StoredObject,
+};
+use zksync_types::{
+    proofs::{PrepareBasicCircuitsJob, PrepareBasicCircuitsJobManifest, WitnessChunkMetadata},
+    L1BatchNumber, ProtocolVersionId,
+};
+
+#[cfg(test)]
+use super::injected_delays::{DelayedStage, InjectedDelays};
+use super::{
+    metrics::{ReportStage, TreeUpdateStage, WitnessStats, METRICS},
+    updater::{TreeUpdater, WITNESS_UPLOAD_PART_SIZE},
+    upload_policy::{UploadHealthDetails, UploadPolicy},
+    upload_queue::WitnessUploadQueue,
+    witness::{WitnessBuilder, WitnessInput},
+    witness_artifacts::{ArtifactDigest, DigestWriter},
+};
+
+/// Raw witness input produced by the tree for an L1 batch.
+#[derive(Debug)]
+pub(super) struct PendingWitness {
+    pub l1_batch_number: L1BatchNumber,
+    /// Protocol version of the L1 batch, recorded in the witness serialization if known.
+    pub protocol_version: Option<ProtocolVersionId>,
+    pub input: WitnessInput,
+    /// Next leaf index after processing the L1 batch.
+    pub next_enumeration_index: u64,
+}
+
+/// Witness input persisted for an L1 batch.
+#[derive(Debug)]
+pub(super) struct SavedWitness {
+    pub l1_batch_number: L1BatchNumber,
+    pub object_key: String,
+    pub stats: WitnessStats,
+    /// `false` if the witness was put into the upload queue and will be uploaded (and marked as ready
+    /// for provers) by the background uploader.
+    pub is_uploaded: bool,
+    /// Digest of the uploaded object. `None` if the witness was put into the upload queue; in this case,
+    /// the digest is recorded by the background uploader.
+    pub artifact: Option<ArtifactDigest>,
+}
+
+/// Persists witness inputs to the object store, either directly or via the upload queue.
+#[derive(Debug)]
+pub(super) struct WitnessPersister {
+    object_store: Box<dyn ObjectStore>,
+    upload_queue: Option<WitnessUploadQueue>,
+    max_in_memory_witness_size: usize,
+    compression_level: i32,
+    spill_compression_level: Option<i32>,
+    chunking_threshold: usize,
+    chunk_size: usize,
+    deduplicate_paths: bool,
+    record_protocol_version: bool,
+    upload_policy: UploadPolicy,
+}
+
+impl WitnessPersister {
+    pub fn new(
+        object_store: Box<dyn ObjectStore>,
+        upload_queue: Option<WitnessUploadQueue>,
+        max_in_memory_witness_size: usize,
+        compression_level: i32,
+        spill_compression_level: Option<i32>,
+        chunking_threshold: usize,
+        chunk_size: usize,
+        deduplicate_paths: bool,
+        record_protocol_version: bool,
+        upload_policy: UploadPolicy,
+    ) -> Self {
+        assert!(chunk_size > 0, "Witness chunk size must be positive");
+        Self {
+            object_store,
+            upload_queue,
+            max_in_memory_witness_size,
+            compression_level,
+            spill_compression_level,
+            chunking_threshold,
+            chunk_size,
+            deduplicate_paths,
+            record_protocol_version,
+            upload_policy,
+        }
+    }
+
+    /// Returns the protocol version to be recorded in the header of persisted witness inputs.
+    /// The version is only recorded if explicitly enabled, since older witness generators cannot read it.
+    fn header_protocol_version(
+        &self,
+        protocol_version: Option<ProtocolVersionId>,
+    ) -> Option<ProtocolVersionId> {
+        protocol_version.filter(|_| self.record_protocol_version)
+    }
+
+    /// Creates a builder for the witness input produced by the tree for an L1 batch. Large witnesses
+    /// are spilled to disk while they are being built. Witnesses with deduplicated Merkle paths are much smaller,
+    /// so they are never spilled to disk.
+    pub fn witness_builder(&self, protocol_version: Option<ProtocolVersionId>) -> WitnessBuilder {
+        WitnessBuilder::new(
+            self.header_protocol_version(protocol_version),
+            self.max_in_memory_witness_size,
+            self.spill_compression_level,
+            self.deduplicate_paths,
+        )
+    }
+
+    /// Wraps the witness produced by the tree as a whole (e.g., when re-producing lost witnesses)
+    /// for serialization. Large witnesses are spilled to disk.
+    pub async fn wrap_witness(
+        &self,
+        job: PrepareBasicCircuitsJob,
+        protocol_version: Option<ProtocolVersionId>,
+    ) -> anyhow::Result<WitnessInput> {
+        let protocol_version = self.header_protocol_version(protocol_version);
+        if self.deduplicate_paths {
+            return Ok(WitnessInput::deduplicated(job, protocol_version));
+        }
+        let max_in_memory_witness_size = self.max_in_memory_witness_size;
+        let spill_level = self.spill_compression_level;
+        tokio::task::spawn_blocking(move || {
+            WitnessInput::new(
+                job,
+                protocol_version,
+                max_in_memory_witness_size,
+                spill_level,
+            )
+        })
+        .await
+        .context("wrapping witness panicked")?
+    }
+
+    /// Returns L1 batches with witnesses waiting in the upload queue. Such witnesses are persisted,
+    /// but are not marked as ready for provers yet.
+    pub fn queued_l1_batches(&self) -> anyhow::Result<Vec<L1BatchNumber>> {
+        let Some(upload_queue) = &self.upload_queue else {
+            return Ok(vec![]);
+        };
+        let pending = upload_queue.pending()?;
+        Ok(pending
+            .into_iter()
+            .map(|witness| witness.l1_batch_number)
+            .collect())
+    }
+
+    /// Persists the witness using the upload queue if it is configured, or uploads it directly otherwise.
+    pub async fn save(&self, witness: PendingWitness) -> anyhow::Result<SavedWitness> {
+        if let Some(upload_queue) = &self.upload_queue {
+            self.queue(upload_queue, witness).await
+        } else {
+            self.upload(witness).await
+        }
+    }
+
+    async fn queue(
+        &self,
+        upload_queue: &WitnessUploadQueue,
+        witness: PendingWitness,
+    ) -> anyhow::Result<SavedWitness> {
+        let PendingWitness {
+            l1_batch_number,
+            input,
+            next_enumeration_index,
+            ..
+        } = witness;
+        let mut stats = WitnessStats::new(input.summary(), next_enumeration_index);
+        let save_witnesses_latency = TreeUpdateStage::SaveWitnesses.start();
+        // The witness is only written to a local file here; it's uploaded by the upload queue worker.
+        let build_witness_latency = TreeUpdateStage::BuildWitness.start();
+        let level = self.compression_level;
+        let (raw_size, compressed_size) = upload_queue
+            .push(l1_batch_number, move |writer| {
+                input.write_compressed(level, writer)
+            })
+            .await?;
+        build_witness_latency.report();
+        save_witnesses_latency.report();
+        stats.raw_size = raw_size;
+        stats.compressed_size = compressed_size;
+        stats.report();
+
+        tracing::info!(
+            "Queued witnesses for L1 batch #{l1_batch_number} for upload \
+             ({raw_size}B raw, {compressed_size}B compressed)"
+        );
+        Ok(SavedWitness {
+            l1_batch_number,
+            object_key: PrepareBasicCircuitsJob::encode_key(l1_batch_number),
+            stats,
+            is_uploaded: false,
+            artifact: None,
+        })
+    }
+
+    /// Uploads the witness directly to the object store. Witnesses with the serialized size exceeding
+    /// the chunking threshold are uploaded in the chunked format.
+    pub async fn upload(&self, witness: PendingWitness) -> anyhow::Result<SavedWitness> {
+        let serialized_size = witness.input.summary().serialized_size;
+        if serialized_size > self.chunking_threshold as u64 {
+            tracing::info!(
+                "Witness input for L1 batch #{} has serialized size {serialized_size}B, which exceeds \
+                 the chunking threshold {}B; uploading it in chunks",
+                witness.l1_batch_number,
+                self.chunking_threshold
+            );
+            return self.upload_chunked(witness).await;
+        }
+
+        let PendingWitness {
+            l1_batch_number,
+            input,
+            next_enumeration_index,
+            ..
+        } = witness;
+        let mut stats = WitnessStats::new(input.summary(), next_enumeration_index);
+        let save_witnesses_latency = TreeUpdateStage::SaveWitnesses.start();
+        // The compressed witness is written to a temporary file once, and upload attempts stream it
+        // from the file, so that retries don't need to serialize the witness again. Large witnesses are
+        // spilled to disk while they are built by the tree, so that we don't keep both the witness
+        // and its serialization in memory at the same time.
+        let build_witness_latency = TreeUpdateStage::BuildWitness.start();
+        let level = self.compression_level;
+        let (file, (raw_size, compressed_size), artifact) =
+            tokio::task::spawn_blocking(move || {
+                let mut file = tempfile::NamedTempFile::new()
+                    .context("cannot create temporary file for witness")?;
+                let mut writer = DigestWriter::new(BufWriter::new(file.as_file_mut()));
+                let sizes = input.write_compressed(level, &mut writer)?;
+                writer.flush().context("cannot flush witness to disk")?;
+                let artifact = writer.finish();
+                anyhow::Ok((file, sizes, artifact))
+            })
+            .await
+            .context("building witness panicked")??;
+        build_witness_latency.report();
+
+        let upload_witness_latency = TreeUpdateStage::UploadWitness.start();
+        let object_store = self.object_store.as_ref();
+        let object_key = PrepareBasicCircuitsJob::encode_key(l1_batch_number);
+        let object_key_ref = &object_key;
+        let path = file.path();
+        self.upload_policy
+            .execute(l1_batch_number, "put_witness", move || {
+                // Each attempt opens the file anew, so that attempts don't share the file cursor.
+                let path = path.to_owned();
+                async move {
+                    upload_streaming(
+                        object_store,
+                        PrepareBasicCircuitsJob::BUCKET,
+                        object_key_ref,
+                        WITNESS_UPLOAD_PART_SIZE,
+                        move |writer| {
+                            let mut file = BufReader::new(File::open(&path)?);
+                            io::copy(&mut file, writer)?;
+                            Ok(())
+                        },
+                    )
+                    .await?;
+                    anyhow::Ok(())
+                }
+            })
+            .await?;
+        upload_witness_latency.report();
+        drop(file);
+        save_witnesses_latency.report();
+        stats.raw_size = raw_size;
+        stats.compressed_size = compressed_size;
+        stats.report();
+
+        tracing::info!(
+            "Saved witnesses for L1 batch #{l1_batch_number} to object storage at `{object_key}` \
+             ({raw_size}B raw, {compressed_size}B compressed)"
+        );
+        Ok(SavedWitness {
+            l1_batch_number,
+            object_key,
+            stats,
+            is_uploaded: true,
+            artifact: Some(artifact),
+        })
+    }
+
+    /// Uploads the witness in the chunked format: Merkle paths are split into chunks with a fixed number
+    /// of paths, which are compressed and uploaded one by one, and are followed by the manifest.
+    /// Since the manifest is uploaded last, readers never observe a partially uploaded witness.
+    /// Chunks of spilled witnesses are read from disk one at a time.
+    async fn upload_chunked(&self, witness: PendingWitness) -> anyhow::Result<SavedWitness> {
+        let PendingWitness {
+            l1_batch_number,
+            protocol_version,
+            input,
+            next_enumeration_index,
+        } = witness;
+        let mut stats = WitnessStats::new(input.summary(), next_enumeration_index);
+        let save_witnesses_latency = TreeUpdateStage::SaveWitnesses.start();
+        let level = self.compression_level;
+        let deduplicate_paths = self.deduplicate_paths;
+        let protocol_version = self.header_protocol_version(protocol_version);
+        let object_store = self.object_store.as_ref();
+        let mut manifest =
+            PrepareBasicCircuitsJobManifest::new(input.summary().next_enumeration_index);
+        let (mut raw_size, mut compressed_size) = (0, 0);
+        let (mut build_time, mut upload_time) = (Duration::ZERO, Duration::ZERO);
+        let mut chunks = input.into_chunks(self.chunk_size)?;
+        for chunk_index in 0.. {
+            let started_at = Instant::now();
+            // The chunk iterator may perform blocking I/O, so it's moved to the blocking task and back.
+            let (returned_chunks, compressed_chunk) = tokio::task::spawn_blocking(move || {
+                let compressed_chunk = chunks.next().map(|chunk| {
+                    let chunk = chunk?;
+                    let merkle_path_count = chunk.merkle_path_count();
+                    let mut blob = vec![];
+                    let input = if deduplicate_paths {
+                        WitnessInput::deduplicated(chunk, protocol_version)
+                    } else {
+                        WitnessInput::in_memory(chunk, protocol_version)
+                    };
+                    let (raw_size, _) = input.write_compressed(level, &mut blob)?;
+                    anyhow::Ok((merkle_path_count, blob, raw_size))
+                });
+                (chunks, compressed_chunk)
+            })
+            .await
+            .context("compressing witness chunk panicked")?;
+            chunks = returned_chunks;
+            let Some(compressed_chunk) = compressed_chunk else {
+                break;
+            };
+            let (merkle_path_count, blob, chunk_raw_size) = compressed_chunk?;
+            build_time += started_at.elapsed();
+            raw_size += chunk_raw_size;
+            compressed_size += blob.len() as u64;
+            manifest
+                .chunks
+                .push(WitnessChunkMetadata::new(merkle_path_count, &blob));
+
+            let chunk_key = &witness_input_chunk_key(l1_batch_number, chunk_index);
+            let blob = &blob;
+            let started_at = Instant::now();
+            self.upload_policy
+                .execute(l1_batch_number, "put_witness_chunk", move || async move {
+                    object_store
+                        .put_raw(PrepareBasicCircuitsJob::BUCKET, chunk_key, blob.clone())
+                        .await
+                        .with_context(|| format!("cannot upload witness chunk `{chunk_key}`"))
+                })
+                .await?;
+            upload_time += started_at.elapsed();
+        }
+        // Chunks are covered by hashes in the manifest, so it's sufficient to record the manifest digest.
+        let manifest_bytes = manifest
+            .serialize()
+            .map_err(|err| anyhow::anyhow!("cannot serialize witness manifest: {err}"))?;
+        let artifact = ArtifactDigest::new(&manifest_bytes);
+        let manifest = &manifest;
+        let started_at = Instant::now();
+        let object_key = self
+            .upload_policy
+            .execute(
+                l1_batch_number,
+                "put_witness_manifest",
+                move || async move {
+                    object_store
+                        .put(l1_batch_number, manifest)
+                        .await
+                        .context("cannot upload witness manifest")
+                },
+            )
+            .await?;
+        upload_time += started_at.elapsed();
+        TreeUpdateStage::BuildWitness.observe_latency(build_time);
+        TreeUpdateStage::UploadWitness.observe_latency(upload_time);
+        save_witnesses_latency.report();
+        stats.raw_size = raw_size;
+        stats.compressed_size = compressed_size;
+        stats.report();
+
+        tracing::info!(
+            "Saved witnesses for L1 batch #{l1_batch_number} to object storage in {} chunks with manifest \
+             at `{object_key}` ({raw_size}B raw, {compressed_size}B compressed)",
+            manifest.chunks.len()
+        );
+        Ok(SavedWitness {
+            l1_batch_number,
+            object_key,
+            stats,
+            is_uploaded: true,
+            artifact: Some(artifact),
+        })
+    }
+}
+
+/// Handle used by the tree updater to persist witnesses. Witnesses are handed over to
+/// the [`WitnessStageWorker`]; if the worker lags behind by the configured number of L1 batches,
+/// handing over blocks the tree.
+#[derive(Debug)]
+pub(super) struct WitnessStage {
+    persister: Arc<WitnessPersister>,
+    sender: mpsc::Sender<PendingWitness>,
+    concurrency: usize,
+}
+
+impl WitnessStage {
+    /// Creates a stage with the specified max number of pending witnesses (i.e., L1 batches
+    /// processed by the tree, but with witnesses not yet persisted) and the number of witnesses
+    /// persisted concurrently.
+    pub fn new(
+        persister: WitnessPersister,
+        max_pending_witnesses: usize,
+        concurrency: usize,
+    ) -> (Self, WitnessStageWorker) {
+        assert!(
+            max_pending_witnesses > 0,
+            "Max number of pending witnesses must be positive"
+        );
+        assert!(
+            concurrency > 0,
+            "Witness stage concurrency must be positive"
+        );
+
+        let persister = Arc::new(persister);
+        let (sender, receiver) = mpsc::channel(max_pending_witnesses);
+        let worker = WitnessStageWorker {
+            persister: persister.clone(),
+            receiver,
+            concurrency,
+            #[cfg(test)]
+            injected_delays: InjectedDelays::default(),
+        };
+        let this = Self {
+            persister,
+            sender,
+            concurrency,
+        };
+        (this, worker)
+    }
+
+    pub fn persister(&self) -> &WitnessPersister {
+        &self.persister
+    }
+
+    /// Returns the max number of L1 batches that may be processed by the tree, but not have persisted
+    /// witnesses: pending witnesses and witnesses being persisted by the worker. These witnesses are lost
+    /// if the process is terminated.
+    pub fn max_unpersisted_witnesses(&self) -> usize {
+        self.sender.max_capacity() + self.concurrency
+    }
+
+    pub fn upload_health(&self) -> UploadHealthDetails {
+        self.persister.upload_policy.health_details()
+    }
+
+    /// Hands over the witness to the worker, waiting if the worker lags behind.
+    pub async fn send(&self, witness: PendingWitness) -> anyhow::Result<()> {
+        let witness = match self.sender.try_send(witness) {
+            Ok(()) => None,
+            Err(TrySendError::Full(witness)) => Some(witness),
+            Err(TrySendError::Closed(_)) => anyhow::bail!("witness stage worker has terminated"),
+        };
+        if let Some(witness) = witness {
+            tracing::info!(
+                "Witness stage lags behind the tree by {} L1 batches; waiting until it catches up",
+                self.sender.max_capacity()
+            );
+            METRICS.witness_stage_backpressure.inc();
+            self.sender
+                .send(witness)
+                .await
+                .map_err(|_| anyhow::anyhow!("witness stage worker has terminated"))?;
+        }
+
+        let pending_count = self.sender.max_capacity() - self.sender.capacity();
+        METRICS.witness_stage_pending.set(pending_count as u64);
+        Ok(())
+    }
+}
+
+/// Worker persisting witnesses handed over by [`WitnessStage`].
+#[derive(Debug)]
+pub(super) struct WitnessStageWorker {
+    persister: Arc<WitnessPersister>,
+    receiver: mpsc::Receiver<PendingWitness>,
+    concurrency: usize,
+    /// Artificial delays injected into persisting witnesses.
+    #[cfg(test)]
+    pub injected_delays: InjectedDelays,
+}
+
+impl WitnessStageWorker {
+    /// Runs the worker until the [`WitnessStage`] is dropped and all pending witnesses are persisted.
+    /// Up to `concurrency` witnesses are persisted at the same time, but they are marked as ready
+    /// for provers strictly in the L1 batch order.
+    pub async fn run(
+        self,
+        pool: &ConnectionPool,
+        prover_pool: &ConnectionPool,
+    ) -> anyhow::Result<()> {
+        let persister = &self.persister;
+        #[cfg(test)]
+        let injected_delays = &self.injected_delays;
+        let witnesses = stream::unfold(self.receiver, |mut receiver| async move {
+            let witness = receiver.recv().await?;
+            Some((witness, receiver))
+        });
+        let mut saved_witnesses = witnesses
+            .map(|witness| async move {
+                #[cfg(test)]
+                injected_delays
+                    .inject(DelayedStage::PersistWitness, witness.l1_batch_number)
+                    .await;
+                persister.save(witness).await
+            })
+            .buffered(self.concurrency);
+
+        while let Some(saved_witness) = saved_witnesses.next().await {
+            let saved_witness = saved_witness?;
+            let l1_batch_number = saved_witness.l1_batch_number;
+            if saved_witness.is_uploaded {
+                let mut storage = pool.access_storage_tagged("metadata_calculator").await?;
+                let mut prover_storage = prover_pool
+                    .access_storage_tagged("metadata_calculator")
+                    .await?;
+                if let Some(artifact) = &saved_witness.artifact {
+                    artifact
+                        .record(&mut storage, l1_batch_number, &saved_witness.object_key)
+                        .await;
+                }
+                TreeUpdater::mark_witness_ready(
+                    &mut storage,
+                    &mut prover_storage,
+                    l1_batch_number,
+                    &saved_witness.object_key,
+                )
+                .await;
+            }
+            tracing::debug!(
+                "Witness input for L1 batch #{l1_batch_number} at `{}`: {}",
+                saved_witness.object_key,
+                saved_witness.stats
+            );
+        }
+        tracing::info!("Witness stage worker has stopped");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use db_test_macro::db_test;
+    use tempfile::TempDir;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use zksync_object_store::{Bucket, ObjectStoreError, ObjectStoreFactory};
+
+    use super::*;
+    use crate::metadata_calculator::{
+        tests::{create_mock_witness, reset_db_state, setup_calculator},
+        upload_policy::ObjectStoreUploadConfig,
+    };
+
+    fn mock_persister(object_store: Box<dyn ObjectStore>) -> WitnessPersister {
+        let upload_policy = UploadPolicy::new(ObjectStoreUploadConfig {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            request_timeout: Duration::from_secs(10),
+            failure_threshold: 1,
+        });
+        WitnessPersister::new(
+            object_store,
+            None,
+            usize::MAX,
+            1,
+            None,
+            usize::MAX,
+            1,
+            false,
+            false,
+            upload_policy,
+        )
+    }
+
+    fn mock_witness(number: u32) -> PendingWitness {
+        PendingWitness {
+            l1_batch_number: L1BatchNumber(number),
+            protocol_version: None,
+            input: WitnessInput::in_memory(PrepareBasicCircuitsJob::new(1), None),
+            next_enumeration_index: 1,
+        }
+    }
+
+    /// Object store failing the first [`ObjectStore::put_raw()`] call.
+    #[derive(Debug)]
+    struct FlakyObjectStore {
+        inner: Box<dyn ObjectStore>,
+        failed: AtomicBool,
+    }
+
+    #[async_trait]
+    impl ObjectStore for FlakyObjectStore {
+        async fn get_raw(&self, bucket: Bucket, key: &str) -> Result<Vec<u8>, ObjectStoreError> {
+            self.inner.get_raw(bucket, key).await
+        }
+
+        async fn put_raw(
+            &self,
+            bucket: Bucket,
+            key: &str,
+            value: Vec<u8>,
+        ) -> Result<(), ObjectStoreError> {
+            if !self.failed.swap(true, Ordering::SeqCst) {
+                return Err(ObjectStoreError::Other("emulated failure".into()));
+            }
+            self.inner.put_raw(bucket, key, value).await
+        }
+
+        async fn remove_raw(&self, bucket: Bucket, key: &str) -> Result<(), ObjectStoreError> {
+            self.inner.remove_raw(bucket, key).await
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_witness_upload_is_retried() {
+        let store_factory = ObjectStoreFactory::mock();
+        let object_store = FlakyObjectStore {
+            inner: store_factory.create_store().await,
+            failed: AtomicBool::new(false),
+        };
+        let persister = mock_persister(Box::new(object_store));
+        let mut witness = mock_witness(1);
+        let job = create_mock_witness(3);
+        let expected_next_index = job.next_enumeration_index();
+        let expected_paths: Vec<_> = job.clone().into_merkle_paths().collect();
+        witness.input = WitnessInput::in_memory(job, None);
+
+        let saved_witness = persister.upload(witness).await.unwrap();
+        assert!(saved_witness.is_uploaded);
+        let object_store = store_factory.create_store().await;
+        let job = object_store
+            .get_witness_input(L1BatchNumber(1))
+            .await
+            .unwrap();
+        assert_eq!(job.next_enumeration_index(), expected_next_index);
+        let paths: Vec<_> = job.into_merkle_paths().collect();
+        assert_eq!(paths, expected_paths);
+    }
+
+    #[tokio::test]
+    async fn tree_is_blocked_if_witness_stage_lags_behind() {
+        let object_store = ObjectStoreFactory::mock().create_store().await;
+        let (stage, mut worker) = WitnessStage::new(mock_persister(object_store), 2, 1);
+        for number in 1..=2 {
+            stage.send(mock_witness(number)).await.unwrap();
+        }
+
+        // The worker doesn't take witnesses from the queue, so the next witness cannot be handed over.
+        let backpressure_count = METRICS.witness_stage_backpressure.get();
+        let mut send_future = Box::pin(stage.send(mock_witness(3)));
+        let send_result = tokio::time::timeout(Duration::from_millis(100), &mut send_future).await;
+        assert!(
+            send_result.is_err(),
+            "witness was handed over to a full queue"
+        );
+        assert!(METRICS.witness_stage_backpressure.get() > backpressure_count);
+
+        let witness = worker.receiver.recv().await.unwrap();
+        assert_eq!(witness.l1_batch_number, L1BatchNumber(1));
+        send_future.await.unwrap();
+
+        drop(worker);
+        let err = stage.send(mock_witness(4)).await.unwrap_err();
+        assert!(err.to_string().contains("terminated"), "{err}");
+    }
+
+    #[db_test]
+    async fn witnesses_are_marked_ready_in_l1_batch_order(
+        pool: ConnectionPool,
+        prover_pool: ConnectionPool,
+    ) {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        // Creates genesis, so that L1 batches can be inserted.
+        setup_calculator(temp_dir.path(), &pool).await;
+        reset_db_state(&pool, 3).await;
+
+        let store_factory = ObjectStoreFactory::mock();
+        let persister = mock_persister(store_factory.create_store().await);
+        let (stage, mut worker) = WitnessStage::new(persister, 3, 3);
+        // Persisting the first witness is slow, so the following witnesses are persisted before it.
+        worker.injected_delays.set(
+            DelayedStage::PersistWitness,
+            Some(L1BatchNumber(1)),
+            Duration::from_millis(500),
+        );
+        let worker_pool = pool.clone();
+        let worker_prover_pool = prover_pool.clone();
+        let worker_handle =
+            tokio::spawn(async move { worker.run(&worker_pool, &worker_prover_pool).await });
+        for number in 1..=3 {
+            stage.send(mock_witness(number)).await.unwrap();
+        }
+
+        let object_store = store_factory.create_store().await;
+        let started_at = Instant::now();
+        while object_store
+            .get::<PrepareBasicCircuitsJob>(L1BatchNumber(3))
+            .await
+            .is_err()
+        {
+            assert!(
+                started_at.elapsed() < Duration::from_secs(5),
+                "witness for L1 batch #3 was not persisted"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let l1_batch_numbers = L1BatchNumber(1)..=L1BatchNumber(3);
+        let mut prover_storage = prover_pool.access_storage().await.unwrap();
+        let ready_l1_batches = prover_storage
+            .witness_generator_dal()
+            .get_l1_batches_with_witness_inputs(l1_batch_numbers.clone())
+            .await;
+        assert!(ready_l1_batches.is_empty(), "{ready_l1_batches:?}");
+
+        drop(stage);
+        worker_handle.await.unwrap().unwrap();
+        let ready_l1_batches = prover_storage
+            .witness_generator_dal()
+            .get_l1_batches_with_witness_inputs(l1_batch_numbers)
+            .await;
+        assert_eq!(
+            ready_l1_batches,
+            [L1BatchNumber(1), L1BatchNumber(2), L1BatchNumber(3)]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Independent verification of witness inputs produced by the Merkle tree in the full mode.
+//!
+//! The verifier folds Merkle paths from a `PrepareBasicCircuitsJob` in the same way as the circuits do,
+//! and checks that the resulting root hash transitions lead from the root hash of the previous L1 batch
+//! to the root hash of the verified L1 batch (both taken from Postgres).
+
+use anyhow::Context as _;
+
+use std::io::Read;
+
+use zksync_crypto::hasher::blake2::Blake2Hasher;
+use zksync_dal::StorageProcessor;
+use zksync_merkle_tree::HashTree;
+use zksync_object_store::{
+    bincode, compression, ObjectStore, ObjectStoreError, StoredObject, WitnessInputHeader,
+};
+use zksync_types::{
+    proofs::{PrepareBasicCircuitsJob, PrepareBasicCircuitsJobManifest, StorageLogMetadata},
+    L1BatchNumber, H256, U256,
+};
+
+/// Outcome of verifying a witness input for a single L1 batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WitnessVerification {
+    /// Number of Merkle paths successfully verified before the first mismatch (if any).
+    pub verified_path_count: usize,
+    /// First detected mismatch, or `None` if verification has passed.
+    pub mismatch: Option<WitnessMismatch>,
+}
+
+impl WitnessVerification {
+    /// Checks whether verification has passed.
+    pub fn is_ok(&self) -> bool {
+        self.mismatch.is_none()
+    }
+}
+
+/// Mismatch detected during witness input verification.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WitnessMismatch {
+    /// The Merkle path folded with the leaf before the operation doesn't lead to the root hash
+    /// after the previous operation.
+    RootBeforeOperation {
+        path_index: usize,
+        expected: H256,
+        actual: H256,
+    },
+    /// The Merkle path folded with the leaf after the operation doesn't lead to the root hash
+    /// recorded in the path.
+    RootAfterOperation {
+        path_index: usize,
+        expected: H256,
+        actual: H256,
+    },
+    /// The root hash after the last operation differs from the root hash of the L1 batch in Postgres.
+    FinalRootHash { expected: H256, actual: H256 },
+}
+
+/// Verifies the witness input persisted in `object_store` for the specified L1 batch against
+/// the root hashes of this and the previous L1 batch in Postgres.
+///
+/// The witness input blob is loaded into memory as is (i.e., potentially compressed), but Merkle paths
+/// are deserialized and verified one by one, without loading the entire witness into memory.
+/// Witness inputs stored in the chunked format are reassembled in memory before verification; ones stored
+/// in a versioned format (e.g., with deduplicated Merkle paths) are decoded in memory.
+///
+/// # Errors
+///
+/// Returns an error if the root hashes or the witness input are missing, or if the witness input
+/// cannot be deserialized. Mismatches in the witness input are reported in the returned value instead.
+pub async fn verify_witness_input(
+    storage: &mut StorageProcessor<'_>,
+    object_store: &dyn ObjectStore,
+    l1_batch_number: L1BatchNumber,
+) -> anyhow::Result<WitnessVerification> {
+    anyhow::ensure!(
+        l1_batch_number > L1BatchNumber(0),
+        "witness inputs are not produced for the genesis L1 batch"
+    );
+    let previous_root_hash = l1_batch_root_hash(storage, l1_batch_number - 1).await?;
+    let root_hash = l1_batch_root_hash(storage, l1_batch_number).await?;
+
+    let blob = load_witness_input_blob(object_store, l1_batch_number).await?;
+    tokio::task::spawn_blocking(move || {
+        let reader =
+            compression::decompressing_reader(&blob).context("cannot decompress witness input")?;
+        verify_merkle_paths(reader, previous_root_hash, root_hash)
+    })
+    .await
+    .context("witness verification panicked")?
+}
+
+/// Loads the serialized witness input for the specified L1 batch, reassembling it if it's stored
+/// in the chunked format. The returned blob may be compressed, but is always in the legacy format.
+async fn load_witness_input_blob(
+    object_store: &dyn ObjectStore,
+    l1_batch_number: L1BatchNumber,
+) -> anyhow::Result<Vec<u8>> {
+    let manifest_result = object_store
+        .get::<PrepareBasicCircuitsJobManifest>(l1_batch_number)
+        .await;
+    match manifest_result {
+        Ok(_) => {
+            let job = object_store
+                .get_witness_input(l1_batch_number)
+                .await
+                .context("cannot load chunked witness input")?;
+            bincode::serialize(&job).context("cannot serialize chunked witness input")
+        }
+        Err(ObjectStoreError::KeyNotFound(_)) => {
+            let object_key = PrepareBasicCircuitsJob::encode_key(l1_batch_number);
+            let blob = object_store
+                .get_raw(PrepareBasicCircuitsJob::BUCKET, &object_key)
+                .await
+                .with_context(|| format!("cannot load witness input `{object_key}`"))?;
+            into_legacy_blob(blob)
+        }
+        Err(err) => Err(err).context("cannot load witness input manifest"),
+    }
+}
+
+/// Converts a witness input blob stored in a versioned format to the legacy format. Blobs
+/// in the legacy format are returned as is.
+fn into_legacy_blob(blob: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(WitnessInputHeader::MAX_LEN);
+    compression::decompressing_reader(&blob)
+        .context("cannot decompress witness input")?
+        .take(WitnessInputHeader::MAX_LEN as u64)
+        .read_to_end(&mut header)
+        .context("cannot read witness input header")?;
+    let header = WitnessInputHeader::parse(&header).map_err(|err| anyhow::anyhow!(err))?;
+    if header == WitnessInputHeader::default() {
+        return Ok(blob);
+    }
+
+    let job = PrepareBasicCircuitsJob::deserialize(blob)
+        .map_err(|err| anyhow::anyhow!(err))
+        .context("cannot decode witness input")?;
+    bincode::serialize(&job).context("cannot serialize decoded witness input")
+}
+
+pub(super) async fn l1_batch_root_hash(
+    storage: &mut StorageProcessor<'_>,
+    l1_batch_number: L1BatchNumber,
+) -> anyhow::Result<H256> {
+    storage
+        .blocks_dal()
+        .get_l1_batch_state_root(l1_batch_number)
+        .await?
+        .with_context(|| format!("root hash for L1 batch #{l1_batch_number} is missing"))
+}
+
+/// Verifies Merkle paths streamed from the `bincode` serialization of a `PrepareBasicCircuitsJob`.
+pub(super) fn verify_merkle_paths(
+    mut reader: impl Read,
+    previous_root_hash: H256,
+    root_hash: H256,
+) -> anyhow::Result<WitnessVerification> {
+    // `PrepareBasicCircuitsJob` is serialized as the length-prefixed sequence of Merkle paths
+    // followed by the next enumeration index, which we don't need.
+    let path_count: u64 =
+        bincode::deserialize_from(&mut reader).context("cannot read Merkle path count")?;
+    let mut current_root_hash = previous_root_hash;
+    // Paths after the first one have their starting hashes shared with the first path omitted;
+    // we restore them in the same way as `PrepareBasicCircuitsJob::into_merkle_paths()` does.
+    let mut first_path = None::<Vec<[u8; 32]>>;
+    for path_index in 0..path_count as usize {
+        let mut log: StorageLogMetadata = bincode::deserialize_from(&mut reader)
+            .with_context(|| format!("cannot read Merkle path #{path_index}"))?;
+        if let Some(first_path) = &first_path {
+            let spliced_len = first_path.len().checked_sub(log.merkle_paths.len());
+            let spliced_len = spliced_len.with_context(|| {
+                format!("Merkle path #{path_index} is longer than the first path")
+            })?;
+            log.merkle_paths
+                .splice(0..0, first_path[..spliced_len].iter().copied());
+        } else {
+            first_path = Some(log.merkle_paths.clone());
+        }
+        anyhow::ensure!(
+            log.merkle_paths.len() == 256,
+            "Merkle path #{path_index} has unexpected length {}",
+            log.merkle_paths.len()
+        );
+
+        let (old_value, old_leaf_index) = if log.first_write {
+            (H256::zero(), 0) // the leaf was empty before the operation
+        } else {
+            (H256(log.value_read), log.leaf_enumeration_index)
+        };
+        let actual = fold_merkle_path(&log, old_value, old_leaf_index);
+        if actual != current_root_hash {
+            let mismatch = WitnessMismatch::RootBeforeOperation {
+                path_index,
+                expected: current_root_hash,
+                actual,
+            };
+            return Ok(WitnessVerification {
+                verified_path_count: path_index,
+                mismatch: Some(mismatch),
+            });
+        }
+
+        let new_root_hash = H256(log.root_hash);
+        let actual = if log.is_write {
+            fold_merkle_path(&log, H256(log.value_written), log.leaf_enumeration_index)
+        } else {
+            actual
+        };
+        if actual != new_root_hash {
+            let mismatch = WitnessMismatch::RootAfterOperation {
+                path_index,
+                expected: new_root_hash,
+                actual,
+            };
+            return Ok(WitnessVerification {
+                verified_path_count: path_index,
+                mismatch: Some(mismatch),
+            });
+        }
+        current_root_hash = new_root_hash;
+    }
+
+    let mismatch = (current_root_hash != root_hash).then_some(WitnessMismatch::FinalRootHash {
+        expected: root_hash,
+        actual: current_root_hash,
+    });
+    Ok(WitnessVerification {
+        verified_path_count: path_count as usize,
+        mismatch,
+    })
+}
+
+/// Folds the Merkle path from `log` for the leaf with the specified value and index. Paths are ordered
+/// starting from the leaf level; the direction on each level is determined by the corresponding bit
+/// of the hashed key, starting from the least significant one.
+fn fold_merkle_path(log: &StorageLogMetadata, value: H256, leaf_index: u64) -> H256 {
+    let key: U256 = log.leaf_hashed_key;
+    let mut hash = Blake2Hasher.hash_leaf(&value, leaf_index);
+    for (depth, adjacent_hash) in log.merkle_paths.iter().enumerate() {
+        let adjacent_hash = H256(*adjacent_hash);
+        hash = if key.bit(depth) {
+            Blake2Hasher.hash_branch(&adjacent_hash, &hash)
+        } else {
+            Blake2Hasher.hash_branch(&hash, &adjacent_hash)
+        };
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use tempfile::TempDir;
+
+    use zksync_merkle_tree::domain::ZkSyncTree;
+    use zksync_storage::RocksDB;
+    use zksync_types::StorageLog;
+
+    use super::*;
+    use crate::metadata_calculator::benchmark::gen_storage_logs;
+
+    fn create_witness(
+        temp_dir: &TempDir,
+        logs: &[Vec<StorageLog>],
+    ) -> (PrepareBasicCircuitsJob, H256, H256) {
+        let db = RocksDB::new(temp_dir.path(), false);
+        let mut tree = ZkSyncTree::new(db);
+        let (last_logs, previous_logs) = logs.split_last().unwrap();
+        for batch_logs in previous_logs {
+            tree.process_l1_batch(batch_logs);
+        }
+        let previous_root_hash = tree.root_hash();
+        let metadata = tree.process_l1_batch(last_logs);
+        (
+            metadata.witness.unwrap(),
+            previous_root_hash,
+            metadata.root_hash,
+        )
+    }
+
+    fn logs_with_reads_and_updates() -> Vec<Vec<StorageLog>> {
+        let mut logs = gen_storage_logs(100..200, 2);
+        let reads = logs[0]
+            .iter()
+            .take(10)
+            .map(|log| StorageLog::new_read_log(log.key, log.value));
+        let updates = logs[0].iter().skip(10).take(10).map(|log| {
+            let mut log = *log;
+            log.value = H256::repeat_byte(0xff);
+            log
+        });
+        let mut new_logs: Vec<_> = reads.chain(updates).collect();
+        new_logs.append(&mut logs[1]);
+        logs[1] = new_logs;
+        logs
+    }
+
+    #[test]
+    fn verifying_valid_witness() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let (job, previous_root_hash, root_hash) =
+            create_witness(&temp_dir, &logs_with_reads_and_updates());
+        let path_count = job.clone().into_merkle_paths().count();
+        assert!(path_count > 20);
+
+        let serialized = bincode::serialize(&job).unwrap();
+        let verification =
+            verify_merkle_paths(serialized.as_slice(), previous_root_hash, root_hash).unwrap();
+        assert!(verification.is_ok(), "{verification:?}");
+        assert_eq!(verification.verified_path_count, path_count);
+
+        let compressed = compression::compress(serialized.as_slice(), 3).unwrap();
+        let reader = compression::decompressing_reader(&compressed).unwrap();
+        let verification = verify_merkle_paths(reader, previous_root_hash, root_hash).unwrap();
+        assert!(verification.is_ok(), "{verification:?}");
+    }
+
+    #[test]
+    fn verifying_empty_witness() {
+        let job = PrepareBasicCircuitsJob::new(1);
+        let serialized = bincode::serialize(&job).unwrap();
+        let root_hash = H256::repeat_byte(1);
+        let verification =
+            verify_merkle_paths(serialized.as_slice(), root_hash, root_hash).unwrap();
+        assert!(verification.is_ok(), "{verification:?}");
+        assert_eq!(verification.verified_path_count, 0);
+    }
+
+    #[test]
+    fn detecting_tampered_merkle_path() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let (job, previous_root_hash, root_hash) =
+            create_witness(&temp_dir, &logs_with_reads_and_updates());
+        let mut paths: Vec<_> = job.clone().into_merkle_paths().collect();
+        paths[5].merkle_paths[100] = [0xaa; 32];
+        let mut tampered_job = PrepareBasicCircuitsJob::new(job.next_enumeration_index());
+        for path in paths {
+            tampered_job.push_merkle_path(path);
+        }
+
+        let serialized = bincode::serialize(&tampered_job).unwrap();
+        let verification =
+            verify_merkle_paths(serialized.as_slice(), previous_root_hash, root_hash).unwrap();
+        assert_eq!(verification.verified_path_count, 5);
+        assert_matches!(
+            verification.mismatch,
+            Some(WitnessMismatch::RootBeforeOperation { path_index: 5, .. })
+        );
+    }
+
+    #[test]
+    fn detecting_final_root_hash_mismatch() {
+        let temp_dir = TempDir::new().expect("failed get temporary directory for RocksDB");
+        let (job, previous_root_hash, root_hash) =
+            create_witness(&temp_dir, &gen_storage_logs(100..200, 2));
+
+        let serialized = bincode::serialize(&job).unwrap();
+        let wrong_root_hash = H256::repeat_byte(0xfe);
+        let verification =
+            verify_merkle_paths(serialized.as_slice(), previous_root_hash, wrong_root_hash)
+                .unwrap();
+        assert_eq!(
+            verification.mismatch,
+            Some(WitnessMismatch::FinalRootHash {
+                expected: wrong_root_hash,
+                actual: root_hash,
+            })
+        );
+    }
+}
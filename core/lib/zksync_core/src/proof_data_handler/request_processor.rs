@@ -95,9 +95,10 @@ impl RequestProcessor {
             .await
             .ok_or(RequestProcessorError::NoPendingBatches)?;
 
+        // Large witness inputs may be stored in the chunked format.
         let blob = self
             .blob_store
-            .get(l1_batch_number)
+            .get_witness_input(l1_batch_number)
             .await
             .map_err(RequestProcessorError::ObjectStore)?;
 
@@ -162,3 +163,79 @@ impl RequestProcessor {
         Ok(Json(SubmitProofResponse::Success))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use db_test_macro::db_test;
+
+    use zksync_contracts::BaseSystemContracts;
+    use zksync_object_store::ObjectStoreFactory;
+    use zksync_types::{
+        block::{BlockGasCount, L1BatchHeader},
+        Address, L2ChainId, ProtocolVersionId,
+    };
+
+    use super::*;
+    use crate::{
+        genesis::{ensure_genesis_state, GenesisParams},
+        metadata_calculator::tests::{create_mock_witness, store_chunked_witness},
+    };
+
+    #[db_test]
+    async fn serving_chunked_witness_input(pool: ConnectionPool) {
+        let mut storage = pool.access_storage().await.unwrap();
+        ensure_genesis_state(&mut storage, L2ChainId::from(270), &GenesisParams::mock())
+            .await
+            .unwrap();
+        let l1_batch_number = L1BatchNumber(1);
+        let header = L1BatchHeader::new(
+            l1_batch_number,
+            0,
+            Address::default(),
+            BaseSystemContracts::load_from_disk().hashes(),
+            ProtocolVersionId::latest(),
+        );
+        storage
+            .blocks_dal()
+            .insert_l1_batch(&header, &[], BlockGasCount::default())
+            .await
+            .unwrap();
+
+        let store_factory = ObjectStoreFactory::mock();
+        let store = store_factory.create_store().await;
+        let job = create_mock_witness(5);
+        let object_key = store_chunked_witness(store.as_ref(), l1_batch_number, &job, 2).await;
+        storage
+            .proof_generation_dal()
+            .insert_proof_generation_details(l1_batch_number, &object_key)
+            .await;
+        drop(storage);
+
+        let config = ProofDataHandlerConfig {
+            http_port: 3320,
+            proof_generation_timeout_in_secs: 18_000,
+            protocol_version_loading_mode: ProtocolVersionLoadingMode::FromEnvVar,
+            fri_protocol_version_id: 1,
+        };
+        let processor = RequestProcessor::new(
+            store_factory.create_store().await,
+            pool,
+            config,
+            Some(L1VerifierConfig::default()),
+        );
+        let response = processor
+            .get_proof_generation_data(Json(ProofGenerationDataRequest {}))
+            .await;
+        let Ok(Json(ProofGenerationDataResponse::Success(data))) = response else {
+            panic!("unexpected response for chunked witness input");
+        };
+
+        assert_eq!(data.l1_batch_number, l1_batch_number);
+        assert_eq!(
+            data.data.next_enumeration_index(),
+            job.next_enumeration_index()
+        );
+        let merkle_paths: Vec<_> = data.data.into_merkle_paths().collect();
+        assert_eq!(merkle_paths, job.into_merkle_paths().collect::<Vec<_>>());
+    }
+}
@@ -1,3 +1,5 @@
+use tokio::sync::watch;
+
 use zksync_dal::ConnectionPool;
 use zksync_types::L1BatchNumber;
 use zksync_web3_decl::{
@@ -29,6 +31,7 @@ const SLEEP_INTERVAL: Duration = Duration::from_secs(5);
 pub struct ReorgDetector {
     client: HttpClient,
     pool: ConnectionPool,
+    last_correct_l1_batch_sender: watch::Sender<Option<L1BatchNumber>>,
 }
 
 impl ReorgDetector {
@@ -36,7 +39,17 @@ impl ReorgDetector {
         let client = HttpClientBuilder::default()
             .build(url)
             .expect("Failed to create HTTP client");
-        Self { client, pool }
+        Self {
+            client,
+            pool,
+            last_correct_l1_batch_sender: watch::channel(None).0,
+        }
+    }
+
+    /// Subscribes to the last correct L1 batch, which is sent once a reorg is localized. This allows
+    /// other components (e.g., the metadata calculator) to halt before the revert is executed.
+    pub fn subscribe(&self) -> watch::Receiver<Option<L1BatchNumber>> {
+        self.last_correct_l1_batch_sender.subscribe()
     }
 
     /// Compares root hashes of the latest local batch and of the same batch from the main node.
@@ -171,6 +184,8 @@ impl ReorgDetector {
                 tracing::info!(
                     "Reorg localized: last correct L1 batch is #{last_correct_l1_batch}"
                 );
+                self.last_correct_l1_batch_sender
+                    .send_replace(Some(last_correct_l1_batch));
                 return Ok(last_correct_l1_batch);
             }
         }
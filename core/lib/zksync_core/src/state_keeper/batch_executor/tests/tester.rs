@@ -150,6 +150,8 @@ impl Tester {
                 &get_system_smart_contracts(),
                 Default::default(),
                 Default::default(),
+                &[],
+                &[],
             )
             .await;
         }
@@ -9,8 +9,8 @@ use zksync_contracts::BaseSystemContractsHashes;
 use zksync_dal::ConnectionPool;
 use zksync_mempool::L2TxFilter;
 use zksync_types::{
-    block::BlockGasCount, tx::ExecutionMetrics, AccountTreeId, Address, L1BatchNumber,
-    MiniblockNumber, ProtocolVersionId, StorageKey, VmEvent, H256, U256,
+    block::BlockGasCount, commitment::TreeData, tx::ExecutionMetrics, AccountTreeId, Address,
+    L1BatchNumber, MiniblockNumber, ProtocolVersionId, StorageKey, VmEvent, H256, U256,
 };
 use zksync_utils::time::seconds_since_epoch;
 
@@ -346,9 +346,12 @@ async fn test_miniblock_and_l1_batch_processing(
     tester.genesis(&pool).await;
     let mut conn = pool.access_storage_tagged("state_keeper").await.unwrap();
     // Save metadata for the genesis L1 batch so that we don't hang in `seal_l1_batch`.
-    let metadata = create_l1_batch_metadata(0);
+    let tree_data = TreeData {
+        metadata: create_l1_batch_metadata(0),
+        parent_hash: H256::zero(),
+    };
     conn.blocks_dal()
-        .save_l1_batch_metadata(L1BatchNumber(0), &metadata, H256::zero())
+        .save_l1_batch_tree_data(L1BatchNumber(0), &tree_data)
         .await
         .unwrap();
     drop(conn);
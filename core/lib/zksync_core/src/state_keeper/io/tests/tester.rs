@@ -114,6 +114,8 @@ impl Tester {
                 &get_system_smart_contracts(),
                 L1VerifierConfig::default(),
                 Address::zero(),
+                &[],
+                &[],
             )
             .await;
         }
@@ -123,6 +123,8 @@ async fn create_genesis_params(main_node_url: &str) -> anyhow::Result<GenesisPar
         first_validator,
         first_l1_verifier_config,
         first_verifier_address,
+        additional_storage_logs: vec![],
+        additional_factory_deps: vec![],
     })
 }
 
@@ -321,7 +321,7 @@ async fn get_artifacts(
     block_number: L1BatchNumber,
     object_store: &dyn ObjectStore,
 ) -> BasicWitnessGeneratorJob {
-    let job = object_store.get(block_number).await.unwrap();
+    let job = object_store.get_witness_input(block_number).await.unwrap();
     BasicWitnessGeneratorJob { block_number, job }
 }
 